@@ -0,0 +1,5 @@
+-- Key-value pairs written by the sqlite_set tool
+CREATE TABLE IF NOT EXISTS kv_store (
+    key TEXT PRIMARY KEY,
+    value TEXT NOT NULL
+);
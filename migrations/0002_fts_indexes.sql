@@ -0,0 +1,6 @@
+-- Tables indexed by sqlite_fts_index. Each has an FTS5 table named
+-- "<table_name>_fts" kept in sync by triggers.
+CREATE TABLE IF NOT EXISTS fts_indexes (
+    table_name TEXT PRIMARY KEY,
+    columns TEXT NOT NULL -- JSON array of the indexed column names
+);
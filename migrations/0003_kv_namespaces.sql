@@ -0,0 +1,13 @@
+-- Namespaces and expiry for the key-value store. Existing pairs move to the
+-- "default" namespace and never expire.
+CREATE TABLE kv_store_new (
+    namespace TEXT NOT NULL DEFAULT 'default',
+    key TEXT NOT NULL,
+    value TEXT NOT NULL,
+    expires_at INTEGER, -- Unix time in seconds; NULL never expires
+    PRIMARY KEY (namespace, key)
+);
+INSERT INTO kv_store_new (key, value) SELECT key, value FROM kv_store;
+DROP TABLE kv_store;
+ALTER TABLE kv_store_new RENAME TO kv_store;
+CREATE INDEX kv_store_expires_at ON kv_store (expires_at) WHERE expires_at IS NOT NULL;
@@ -0,0 +1,232 @@
+//! Agent Module: The tool-calling loop of a chat turn
+//!
+//! This module provides:
+//! - `AgentConfig`, read from the `agent` section of `config.yaml`: how
+//!   many tool calls a turn may make and how many tokens it may use
+//! - `parse_tool_calls`: the tool calls an LLM response asks for, a single
+//!   call or an array of independent calls
+//! - `AgentTrace`: the tool calls and the answer of a turn, shown by
+//!   `/trace`
+//!
+//! In a turn the chat asks the LLM, runs the tool it calls, appends the
+//! call and the tool's result to the conversation and asks again, until the
+//! LLM answers without calling a tool. The calls of one response run
+//! concurrently, at most `max_parallel_tools` at a time, and their results
+//! are appended in the order of the calls. Once a limit is reached the LLM
+//! is asked to answer with the results it has.
+//!
+//! Author: arkSong <arksong2018@gmail.com>
+//! Version: 1.0.0
+//! License: MIT
+
+use chrono::{DateTime, Local};
+use log::warn;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::config::Configuration;
+use crate::mcp_server::ToolResult;
+
+/// Sent to the LLM once a limit is reached, instead of running more tools
+pub const FINAL_ANSWER_PROMPT: &str = "No more tools can be called in this turn. Answer the user's request now in natural language, using the tool results above, and say what is still missing if they were not enough.";
+
+/// Limits of the tool-calling loop, read from the `agent` section of
+/// `config.yaml`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct AgentConfig {
+    /// Most tool calls in one turn
+    pub max_iterations: usize,
+    /// Most tool calls of one response that run at the same time
+    pub max_parallel_tools: usize,
+    /// Most prompt and completion tokens one turn may use, if limited
+    pub token_budget: Option<u64>,
+}
+
+impl Default for AgentConfig {
+    fn default() -> Self {
+        Self {
+            max_iterations: 5,
+            max_parallel_tools: 4,
+            token_budget: None,
+        }
+    }
+}
+
+impl AgentConfig {
+    /// The `agent` section of the configuration file `config_path`, or the
+    /// defaults if it cannot be read
+    pub fn from_config_file(config_path: &str) -> Self {
+        Configuration::new()
+            .load_agent_config(config_path)
+            .unwrap_or_else(|e| {
+                warn!(
+                    "Failed to load the agent configuration, using defaults: {}",
+                    e
+                );
+                Self::default()
+            })
+    }
+
+    /// Why a turn that made `tool_calls` calls and used `tokens` tokens may
+    /// not call more tools, if it may not
+    pub fn limit_reached(&self, tool_calls: usize, tokens: u64) -> Option<String> {
+        if tool_calls >= self.max_iterations {
+            return Some(format!(
+                "reached the limit of {} tool calls",
+                self.max_iterations
+            ));
+        }
+        match self.token_budget {
+            Some(budget) if tokens >= budget => {
+                Some(format!("used {} tokens of a budget of {}", tokens, budget))
+            }
+            _ => None,
+        }
+    }
+}
+
+/// The tool names and arguments of `response` if it is a tool call, a JSON
+/// object like `{"tool": "memory_get", "arguments": {"key": "name"}}`, or a
+/// non-empty array of such objects, optionally in a code fence
+pub fn parse_tool_calls(response: &str) -> Option<Vec<(String, Value)>> {
+    let text = response.trim();
+    let text = text
+        .strip_prefix("```json")
+        .or_else(|| text.strip_prefix("```"))
+        .and_then(|fenced| fenced.strip_suffix("```"))
+        .unwrap_or(text);
+    match serde_json::from_str(text.trim()).ok()? {
+        Value::Array(calls) if !calls.is_empty() => calls.iter().map(parse_call).collect(),
+        call => Some(vec![parse_call(&call)?]),
+    }
+}
+
+/// The tool name and arguments of one call
+fn parse_call(call: &Value) -> Option<(String, Value)> {
+    let tool = call.get("tool")?.as_str()?.to_string();
+    let arguments = call.get("arguments")?.clone();
+    Some((tool, arguments))
+}
+
+/// One step of a turn
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum TraceStep {
+    /// A tool the LLM called and what it returned
+    ToolCall {
+        tool: String,
+        arguments: Value,
+        result: ToolResult,
+    },
+    /// Why no more tools were called
+    Stopped { reason: String },
+    /// The answer the turn ended with
+    Answer { text: String },
+}
+
+/// What happened in a turn, for `/trace`
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct AgentTrace {
+    /// The user's message
+    pub input: String,
+    pub started: DateTime<Local>,
+    pub steps: Vec<TraceStep>,
+    /// Requests sent to the LLM
+    pub llm_requests: u32,
+    /// Prompt and completion tokens the requests used, as far as reported
+    pub tokens: u64,
+}
+
+impl AgentTrace {
+    pub fn new(input: &str) -> Self {
+        Self {
+            input: input.to_string(),
+            started: Local::now(),
+            steps: Vec::new(),
+            llm_requests: 0,
+            tokens: 0,
+        }
+    }
+
+    /// Number of tools called so far
+    pub fn tool_calls(&self) -> usize {
+        self.steps
+            .iter()
+            .filter(|step| matches!(step, TraceStep::ToolCall { .. }))
+            .count()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+    use std::time::Instant;
+
+    #[test]
+    fn test_parse_tool_calls() {
+        assert_eq!(
+            parse_tool_calls(r#"{"tool": "memory_get", "arguments": {"key": "name"}}"#),
+            Some(vec![("memory_get".to_string(), json!({ "key": "name" }))])
+        );
+        assert_eq!(
+            parse_tool_calls("```json\n{\"tool\": \"file_read\", \"arguments\": {}}\n```"),
+            Some(vec![("file_read".to_string(), json!({}))])
+        );
+        assert_eq!(parse_tool_calls("Your name is Ada."), None);
+        assert_eq!(parse_tool_calls(r#"{"tool": "memory_get"}"#), None);
+        assert_eq!(parse_tool_calls(r#"{"tool": 3, "arguments": {}}"#), None);
+
+        // Independent calls come as an array, kept in order
+        assert_eq!(
+            parse_tool_calls(
+                r#"[{"tool": "file_read", "arguments": {"path": "a.txt"}},
+                    {"tool": "memory_get", "arguments": {"key": "name"}}]"#
+            ),
+            Some(vec![
+                ("file_read".to_string(), json!({ "path": "a.txt" })),
+                ("memory_get".to_string(), json!({ "key": "name" })),
+            ])
+        );
+        assert_eq!(parse_tool_calls("[]"), None);
+        assert_eq!(
+            parse_tool_calls(r#"[{"tool": "file_read", "arguments": {}}, "and more"]"#),
+            None
+        );
+    }
+
+    #[test]
+    fn test_limits() {
+        let config = AgentConfig {
+            max_iterations: 2,
+            token_budget: Some(1000),
+            ..AgentConfig::default()
+        };
+        assert_eq!(config.limit_reached(1, 500), None);
+        assert!(config
+            .limit_reached(2, 500)
+            .unwrap()
+            .contains("2 tool calls"));
+        assert!(config
+            .limit_reached(1, 1200)
+            .unwrap()
+            .contains("budget of 1000"));
+        assert_eq!(AgentConfig::default().limit_reached(4, u64::MAX), None);
+
+        let mut trace = AgentTrace::new("What is my name?");
+        trace.steps.push(TraceStep::ToolCall {
+            tool: "memory_get".to_string(),
+            arguments: json!({ "key": "name" }),
+            result: ToolResult::new(Ok(json!({ "value": "Ada" })), Instant::now()),
+        });
+        trace.steps.push(TraceStep::Answer {
+            text: "Ada".to_string(),
+        });
+        assert_eq!(trace.tool_calls(), 1);
+        assert_eq!(
+            serde_json::to_value(&trace.steps[1]).unwrap(),
+            json!({ "type": "answer", "text": "Ada" })
+        );
+    }
+}
@@ -0,0 +1,540 @@
+//! Audit Log Module: Append-only record of every tool call
+//!
+//! This module provides:
+//! - `AuditConfig`, read from the `audit` section of `config.yaml`
+//! - `AuditLog`: one JSON line per tool call, appended to a file
+//! - `AuditQuery`: the recorded calls of a tool, session, status or period
+//! - Redaction of secrets in the recorded arguments
+//!
+//! Calls are recorded by an `after_tool_call` hook of each server, so calls
+//! from the chat and from MCP clients of `serve --stdio` alike are kept,
+//! including failed and refused ones. An entry holds the time the call
+//! finished, the id of the process's session, the tool, its arguments,
+//! whether it succeeded and how long it took; results are not recorded.
+//! Arguments named like secrets (`password`, `token`, `api_key`, ...) are
+//! written as `[REDACTED]`, at any depth, and strings longer than
+//! `MAX_RECORDED_CHARS` are cut, so file contents do not fill the log.
+//!
+//! Auditing is off unless the `audit` section enables it. Entries are
+//! written by a thread of their own, so tool calls do not wait for the
+//! disk; queries see every entry recorded before them.
+//!
+//! Author: arkSong <arksong2018@gmail.com>
+//! Version: 1.0.0
+//! License: MIT
+
+use anyhow::{Error, Result};
+use chrono::{DateTime, NaiveDate, Utc};
+use log::warn;
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use std::fs::{self, File, OpenOptions};
+use std::io::{BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{mpsc, Arc};
+use std::thread;
+use std::time::Duration;
+
+use crate::config::Configuration;
+use crate::mcp_server::{McpServer, ToolId};
+
+/// Replaces the value of a secret argument
+pub const REDACTED: &str = "[REDACTED]";
+
+/// Characters of a string argument that are recorded; the rest is replaced
+/// by its length
+pub const MAX_RECORDED_CHARS: usize = 256;
+
+/// Parts of argument names whose values are not recorded
+const SECRET_NAMES: [&str; 10] = [
+    "password",
+    "passwd",
+    "secret",
+    "token",
+    "api_key",
+    "apikey",
+    "access_key",
+    "private_key",
+    "authorization",
+    "credential",
+];
+
+/// Settings of the audit log, read from the `audit` section of `config.yaml`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct AuditConfig {
+    /// Whether tool calls are recorded
+    pub enabled: bool,
+    /// The JSONL file the calls are appended to
+    pub path: String,
+}
+
+impl Default for AuditConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            path: "logs/audit.jsonl".to_string(),
+        }
+    }
+}
+
+/// How a recorded call ended
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum AuditStatus {
+    Success,
+    Error,
+}
+
+/// One recorded tool call
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AuditEntry {
+    /// When the call finished
+    pub timestamp: DateTime<Utc>,
+    /// Session of the process that made the call
+    pub session_id: String,
+    /// The tool as `server/tool`
+    pub tool: String,
+    /// Arguments of the call, secrets redacted
+    pub arguments: Value,
+    pub status: AuditStatus,
+    /// Why the call failed
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    pub duration_ms: u64,
+}
+
+impl AuditEntry {
+    /// The entry of a call of `tool` with `arguments` that ended with
+    /// `outcome` after `elapsed`
+    pub fn new(
+        session_id: &str,
+        tool: &ToolId,
+        arguments: &Value,
+        outcome: &Result<Value>,
+        elapsed: Duration,
+    ) -> Self {
+        Self {
+            timestamp: Utc::now(),
+            session_id: session_id.to_string(),
+            tool: tool.to_string(),
+            arguments: shorten(&redact(arguments)),
+            status: match outcome {
+                Ok(_) => AuditStatus::Success,
+                Err(_) => AuditStatus::Error,
+            },
+            error: outcome.as_ref().err().map(|e| e.to_string()),
+            duration_ms: elapsed.as_millis() as u64,
+        }
+    }
+}
+
+/// Which recorded calls to return; unset fields match every call
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct AuditQuery {
+    /// The tool, bare or as `server/tool`
+    pub tool: Option<String>,
+    pub session_id: Option<String>,
+    pub status: Option<AuditStatus>,
+    /// Calls finished at or after this time
+    pub since: Option<DateTime<Utc>>,
+    /// At most this many of the latest matching calls
+    pub limit: Option<usize>,
+}
+
+impl AuditQuery {
+    /// Reads `key=value` pairs such as `tool=file_read status=error
+    /// since=2024-05-01 limit=10`; `since` is a date or an RFC 3339 time
+    pub fn parse(args: &str) -> Result<Self> {
+        let mut query = Self::default();
+        for pair in args.split_whitespace() {
+            let (key, value) = pair
+                .split_once('=')
+                .ok_or_else(|| Error::msg(format!("Expected key=value: {}", pair)))?;
+            match key {
+                "tool" => query.tool = Some(value.to_string()),
+                "session" => query.session_id = Some(value.to_string()),
+                "status" => {
+                    query.status = Some(
+                        serde_json::from_value(Value::from(value))
+                            .map_err(|_| Error::msg(format!("Invalid status: {}", value)))?,
+                    )
+                }
+                "since" => query.since = Some(parse_time(value)?),
+                "limit" => {
+                    query.limit = Some(
+                        value
+                            .parse()
+                            .map_err(|_| Error::msg(format!("Invalid limit: {}", value)))?,
+                    )
+                }
+                _ => return Err(Error::msg(format!("Unknown filter: {}", key))),
+            }
+        }
+        Ok(query)
+    }
+
+    /// Whether `entry` is one of the calls asked for
+    pub fn matches(&self, entry: &AuditEntry) -> bool {
+        let tool_matches = match &self.tool {
+            Some(tool) if tool.contains('/') => entry.tool == *tool,
+            Some(tool) => entry.tool.rsplit('/').next() == Some(tool.as_str()),
+            None => true,
+        };
+        tool_matches
+            && self
+                .session_id
+                .as_ref()
+                .is_none_or(|session_id| entry.session_id == *session_id)
+            && self.status.is_none_or(|status| entry.status == status)
+            && self.since.is_none_or(|since| entry.timestamp >= since)
+    }
+}
+
+/// What the writer thread of a log is asked to do
+enum WriterMessage {
+    /// Append a line
+    Line(String),
+    /// Answer once the lines sent before are written
+    Flush(mpsc::Sender<()>),
+}
+
+/// Tool calls of one session, appended to a JSONL file that earlier
+/// sessions may already have written to
+pub struct AuditLog {
+    path: PathBuf,
+    session_id: String,
+    writer: mpsc::Sender<WriterMessage>,
+}
+
+impl AuditLog {
+    /// Opens `path` for appending, creating it and its directory if needed,
+    /// and starts a new session
+    pub fn open(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref().to_path_buf();
+        if let Some(parent) = path
+            .parent()
+            .filter(|parent| !parent.as_os_str().is_empty())
+        {
+            fs::create_dir_all(parent)?;
+        }
+        let file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&path)
+            .map_err(|e| Error::msg(format!("Failed to open {}: {}", path.display(), e)))?;
+        let (writer, messages) = mpsc::channel();
+        let file_path = path.clone();
+        // Ends once the log, and with it the sender, is dropped
+        thread::spawn(move || write_lines(file, &file_path, messages));
+        Ok(Self {
+            path,
+            session_id: uuid::Uuid::new_v4().to_string(),
+            writer,
+        })
+    }
+
+    /// The log `config` asks for, or `None` if auditing is disabled
+    pub fn from_config(config: &AuditConfig) -> Result<Option<Arc<Self>>> {
+        if !config.enabled {
+            return Ok(None);
+        }
+        Ok(Some(Arc::new(Self::open(&config.path)?)))
+    }
+
+    /// The log configured in the `audit` section of `config_path`. Tool
+    /// calls go unrecorded, with a warning, if it cannot be opened.
+    pub fn from_config_file(config_path: &str) -> Option<Arc<Self>> {
+        let config = Configuration::new()
+            .load_audit_config(config_path)
+            .unwrap_or_else(|e| {
+                warn!(
+                    "Failed to load the audit configuration, using defaults: {}",
+                    e
+                );
+                AuditConfig::default()
+            });
+        Self::from_config(&config).unwrap_or_else(|e| {
+            warn!("Tool calls are not audited: {}", e);
+            None
+        })
+    }
+
+    /// Id of the session, recorded with each of its calls
+    pub fn session_id(&self) -> &str {
+        &self.session_id
+    }
+
+    /// The file the calls are appended to
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Hands `entry` to the writer thread, which appends it as one line
+    pub fn record(&self, entry: &AuditEntry) -> Result<()> {
+        let mut line = serde_json::to_string(entry)?;
+        line.push('\n');
+        self.writer
+            .send(WriterMessage::Line(line))
+            .map_err(|_| Error::msg("The audit log writer has stopped"))
+    }
+
+    /// Waits until the entries recorded so far are written
+    pub fn flush(&self) {
+        let (done, wait) = mpsc::channel();
+        if self.writer.send(WriterMessage::Flush(done)).is_ok() {
+            let _ = wait.recv();
+        }
+    }
+
+    /// Records every call of the tools of `mcp_server` from now on
+    pub async fn attach(self: &Arc<Self>, mcp_server: &mut McpServer) {
+        let log = Arc::clone(self);
+        mcp_server
+            .after_tool_call(move |tool, arguments, outcome, elapsed| {
+                let entry = AuditEntry::new(&log.session_id, tool, arguments, outcome, elapsed);
+                if let Err(e) = log.record(&entry) {
+                    warn!(
+                        "Failed to record the call of {} in the audit log: {}",
+                        tool, e
+                    );
+                }
+            })
+            .await;
+    }
+
+    /// The recorded calls `query` asks for, oldest first. Lines that are not
+    /// entries are skipped.
+    pub fn query(&self, query: &AuditQuery) -> Result<Vec<AuditEntry>> {
+        self.flush();
+        let file = File::open(&self.path)?;
+        let mut entries: Vec<AuditEntry> = BufReader::new(file)
+            .lines()
+            .map_while(|line| line.ok())
+            .filter_map(|line| serde_json::from_str(&line).ok())
+            .filter(|entry| query.matches(entry))
+            .collect();
+        if let Some(limit) = query.limit {
+            entries.drain(..entries.len().saturating_sub(limit));
+        }
+        Ok(entries)
+    }
+}
+
+/// Appends the lines of `messages` to `file` until the log is dropped
+fn write_lines(mut file: File, path: &Path, messages: mpsc::Receiver<WriterMessage>) {
+    for message in messages {
+        match message {
+            WriterMessage::Line(line) => {
+                if let Err(e) = file.write_all(line.as_bytes()).and_then(|_| file.flush()) {
+                    warn!("Failed to write to the audit log {}: {}", path.display(), e);
+                }
+            }
+            WriterMessage::Flush(done) => {
+                let _ = done.send(());
+            }
+        }
+    }
+}
+
+/// `arguments` with strings longer than `MAX_RECORDED_CHARS` cut, and
+/// their length noted in place of the rest
+fn shorten(arguments: &Value) -> Value {
+    match arguments {
+        Value::String(text) => match text.char_indices().nth(MAX_RECORDED_CHARS) {
+            Some((end, _)) => Value::String(format!(
+                "{}... ({} chars)",
+                &text[..end],
+                text.chars().count()
+            )),
+            None => arguments.clone(),
+        },
+        Value::Object(object) => Value::Object(
+            object
+                .iter()
+                .map(|(name, value)| (name.clone(), shorten(value)))
+                .collect(),
+        ),
+        Value::Array(items) => Value::Array(items.iter().map(shorten).collect()),
+        value => value.clone(),
+    }
+}
+
+/// `arguments` with the values of secret-looking names replaced by
+/// `REDACTED`
+pub fn redact(arguments: &Value) -> Value {
+    match arguments {
+        Value::Object(object) => Value::Object(
+            object
+                .iter()
+                .map(|(name, value)| {
+                    let value = if is_secret(name) {
+                        Value::from(REDACTED)
+                    } else {
+                        redact(value)
+                    };
+                    (name.clone(), value)
+                })
+                .collect::<Map<_, _>>(),
+        ),
+        Value::Array(items) => Value::Array(items.iter().map(redact).collect()),
+        value => value.clone(),
+    }
+}
+
+/// Whether the argument `name` holds a secret
+fn is_secret(name: &str) -> bool {
+    let name = name.to_lowercase().replace('-', "_");
+    SECRET_NAMES.iter().any(|secret| name.contains(secret))
+}
+
+/// A date (midnight UTC) or an RFC 3339 time
+fn parse_time(value: &str) -> Result<DateTime<Utc>> {
+    if let Ok(time) = DateTime::parse_from_rfc3339(value) {
+        return Ok(time.with_timezone(&Utc));
+    }
+    NaiveDate::parse_from_str(value, "%Y-%m-%d")
+        .map(|date| date.and_hms_opt(0, 0, 0).unwrap_or_default().and_utc())
+        .map_err(|_| Error::msg(format!("Invalid time: {}", value)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mcp_server::Tool;
+    use serde_json::json;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_redact() {
+        let arguments = json!({
+            "path": "notes.txt",
+            "apiKey": "sk-1",
+            "headers": [{ "Authorization": "Bearer x", "accept": "json" }],
+            "db": { "user": "admin", "db-password": "hunter2" }
+        });
+        assert_eq!(
+            redact(&arguments),
+            json!({
+                "path": "notes.txt",
+                "apiKey": REDACTED,
+                "headers": [{ "Authorization": REDACTED, "accept": "json" }],
+                "db": { "user": "admin", "db-password": REDACTED }
+            })
+        );
+        assert_eq!(redact(&json!("token")), json!("token"));
+    }
+
+    #[test]
+    fn test_shorten() {
+        let content = "é".repeat(MAX_RECORDED_CHARS + 10);
+        let shortened = shorten(&json!({ "path": "a.txt", "lines": [content] }));
+        assert_eq!(shortened["path"], "a.txt");
+        assert_eq!(
+            shortened["lines"][0],
+            format!(
+                "{}... ({} chars)",
+                "é".repeat(MAX_RECORDED_CHARS),
+                MAX_RECORDED_CHARS + 10
+            )
+        );
+        let short = "é".repeat(MAX_RECORDED_CHARS);
+        assert_eq!(shorten(&json!(short)), json!(short));
+    }
+
+    #[test]
+    fn test_query() {
+        let query =
+            AuditQuery::parse("tool=file_read status=error since=2024-05-01 limit=2").unwrap();
+        assert_eq!(query.tool.as_deref(), Some("file_read"));
+        assert_eq!(query.status, Some(AuditStatus::Error));
+        assert_eq!(
+            query.since.unwrap().to_rfc3339(),
+            "2024-05-01T00:00:00+00:00"
+        );
+        assert_eq!(query.limit, Some(2));
+        assert_eq!(AuditQuery::parse("").unwrap(), AuditQuery::default());
+        assert!(AuditQuery::parse("status=maybe").is_err());
+        assert!(AuditQuery::parse("colour=red").is_err());
+        assert!(AuditQuery::parse("limit").is_err());
+    }
+
+    #[tokio::test]
+    async fn test_audit_log() {
+        let temp_dir = TempDir::new().unwrap();
+        let path = temp_dir.path().join("logs/audit.jsonl");
+        let log = Arc::new(AuditLog::open(&path).unwrap());
+
+        let mut server = McpServer::new().named("vault");
+        server
+            .register_tool(Tool::new(
+                "unlock".to_string(),
+                "Unlocks the vault".to_string(),
+                json!({ "type": "object" }),
+                None,
+                Box::new(|args| {
+                    Box::pin(async move {
+                        match args["password"].as_str() {
+                            Some("open sesame") => Ok(json!({ "unlocked": true })),
+                            _ => Err(Error::msg("Wrong password")),
+                        }
+                    })
+                }),
+            ))
+            .await
+            .unwrap();
+        log.attach(&mut server).await;
+
+        let retry = Duration::from_millis(1);
+        server
+            .execute_tool("unlock", &json!({ "password": "open sesame" }), 1, retry)
+            .await
+            .unwrap();
+        assert!(server
+            .execute_tool("unlock", &json!({ "password": "guess" }), 1, retry)
+            .await
+            .is_err());
+
+        let entries = log.query(&AuditQuery::default()).unwrap();
+        assert_eq!(entries.len(), 2);
+        assert_eq!(entries[0].tool, "vault/unlock");
+        assert_eq!(entries[0].session_id, log.session_id());
+        assert_eq!(entries[0].status, AuditStatus::Success);
+        assert_eq!(entries[0].arguments, json!({ "password": REDACTED }));
+        assert_eq!(entries[1].status, AuditStatus::Error);
+        assert!(entries[1]
+            .error
+            .as_deref()
+            .unwrap()
+            .contains("Wrong password"));
+        assert!(!std::fs::read_to_string(&path).unwrap().contains("sesame"));
+
+        // A later session appends to the same file
+        std::fs::OpenOptions::new()
+            .append(true)
+            .open(&path)
+            .unwrap()
+            .write_all(b"not json\n")
+            .unwrap();
+        let later = AuditLog::open(&path).unwrap();
+        assert_ne!(later.session_id(), log.session_id());
+        let errors = later
+            .query(&AuditQuery::parse("tool=vault/unlock status=error").unwrap())
+            .unwrap();
+        assert_eq!(errors.len(), 1);
+        let latest = later
+            .query(&AuditQuery {
+                limit: Some(1),
+                ..AuditQuery::default()
+            })
+            .unwrap();
+        assert_eq!(latest[0].status, AuditStatus::Error);
+        let other_session = later
+            .query(&AuditQuery {
+                session_id: Some(later.session_id().to_string()),
+                ..AuditQuery::default()
+            })
+            .unwrap();
+        assert!(other_session.is_empty());
+    }
+}
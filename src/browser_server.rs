@@ -0,0 +1,747 @@
+//! Browser Server Module: The tools of the `puppeteer` server
+//!
+//! This module provides:
+//! - `BrowserConfig`, read from the `puppeteer` entry of
+//!   `servers_config.json`: headless mode, the Chromium executable, the
+//!   viewport, timeouts and where screenshots are saved
+//! - `BrowserServer`: one headless Chromium page driven over the Chrome
+//!   DevTools Protocol, launched on the first tool call
+//! - The `puppeteer_navigate`, `puppeteer_click`, `puppeteer_type`,
+//!   `puppeteer_screenshot` and `puppeteer_page_text` tools, and the
+//!   `browser` resource with the current URL and viewport
+//!
+//! The browser is driven through chromiumoxide, built with the `browser`
+//! feature; without it the tools fail as unavailable. Only http and https
+//! pages are opened, screenshots are saved below `screenshot_dir`, and the
+//! calls share one page, one after the other.
+//!
+//! Author: arkSong <arksong2018@gmail.com>
+//! Version: 1.0.0
+//! License: MIT
+
+use anyhow::{Error, Result};
+use log::{info, warn};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::path::{Component, Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::Mutex;
+
+use crate::mcp_server::{Resource, Tool};
+use crate::tool_error::{ToolError, ToolErrorKind};
+
+/// Settings of the browser, read from the `puppeteer` entry of
+/// `servers_config.json` next to `command` and `args`
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default)]
+pub struct BrowserConfig {
+    /// Whether Chromium runs without a window
+    pub headless: bool,
+    /// Path of the Chromium or Chrome executable; looked up if unset
+    pub chrome_executable: Option<PathBuf>,
+    /// Directory screenshots are saved below
+    pub screenshot_dir: PathBuf,
+    /// Seconds a page load or element lookup may take
+    pub timeout_secs: u64,
+    pub viewport_width: u32,
+    pub viewport_height: u32,
+    /// Most characters `puppeteer_page_text` returns
+    pub max_text_chars: usize,
+}
+
+impl Default for BrowserConfig {
+    fn default() -> Self {
+        Self {
+            headless: true,
+            chrome_executable: None,
+            screenshot_dir: PathBuf::from("screenshots"),
+            timeout_secs: 30,
+            viewport_width: 1024,
+            viewport_height: 768,
+            max_text_chars: 100_000,
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, JsonSchema)]
+struct NavigateArgs {
+    /// http or https URL to open
+    url: String,
+}
+
+#[derive(Debug, Deserialize, JsonSchema)]
+struct ClickArgs {
+    /// CSS selector of the element to click
+    selector: String,
+}
+
+#[derive(Debug, Deserialize, JsonSchema)]
+struct TypeArgs {
+    /// CSS selector of the element to type into
+    selector: String,
+    /// Text to type
+    text: String,
+}
+
+#[derive(Debug, Deserialize, JsonSchema)]
+struct ScreenshotArgs {
+    /// Path of the PNG file, relative to the screenshot directory
+    path: String,
+    /// Whether to capture the whole page rather than the viewport
+    #[serde(default)]
+    full_page: bool,
+}
+
+#[derive(Debug, Deserialize, JsonSchema)]
+struct PageTextArgs {
+    /// CSS selector of the element whose text to return; the whole page if
+    /// omitted
+    #[serde(default)]
+    selector: Option<String>,
+}
+
+/// The page the browser shows
+#[derive(Debug, Clone, PartialEq, Serialize, JsonSchema)]
+pub struct PageInfo {
+    pub url: String,
+    pub title: String,
+}
+
+#[derive(Debug, Serialize, JsonSchema)]
+struct ActionResult {
+    /// Whether the action was performed
+    success: bool,
+    /// URL of the page after the action
+    url: String,
+}
+
+#[derive(Debug, Serialize, JsonSchema)]
+struct ScreenshotResult {
+    /// Where the screenshot was saved
+    path: String,
+    /// Size of the PNG file in bytes
+    bytes: usize,
+}
+
+/// The visible text of a page
+#[derive(Debug, Clone, PartialEq, Serialize, JsonSchema)]
+pub struct PageText {
+    pub url: String,
+    pub title: String,
+    pub text: String,
+    /// Whether the text was cut at `max_text_chars`
+    pub truncated: bool,
+}
+
+/// A headless Chromium page, launched on first use and shared by the tools
+/// of one `puppeteer` server
+pub struct BrowserServer {
+    config: BrowserConfig,
+    driver: Mutex<Option<Driver>>,
+}
+
+impl BrowserServer {
+    pub fn new(config: BrowserConfig) -> Self {
+        Self {
+            config,
+            driver: Mutex::new(None),
+        }
+    }
+
+    /// Registers the browser tools with `mcp_server`, configured by the
+    /// server entry `config`, and returns the browser they share
+    pub async fn register_tools(
+        mcp_server: &mut crate::mcp_server::McpServer,
+        config: &Value,
+    ) -> Result<Arc<Self>> {
+        let browser_config: BrowserConfig = serde_json::from_value(config.clone())
+            .map_err(|e| Error::msg(format!("Invalid puppeteer configuration: {}", e)))?;
+        let browser = Arc::new(Self::new(browser_config));
+        for tool in browser_tools(&browser) {
+            mcp_server.register_tool(tool).await?;
+        }
+        Ok(browser)
+    }
+
+    pub fn config(&self) -> &BrowserConfig {
+        &self.config
+    }
+
+    /// Opens `url`, which must be an http or https URL
+    pub async fn navigate(&self, url: &str) -> Result<PageInfo> {
+        let url = check_url(url)?;
+        let mut driver = self.driver().await?;
+        let driver = driver.as_mut().expect("driver is launched");
+        self.within("Loading the page", driver.goto(url.as_str()))
+            .await?;
+        page_info(driver).await
+    }
+
+    /// Clicks the first element matching `selector`
+    pub async fn click(&self, selector: &str) -> Result<String> {
+        let mut driver = self.driver().await?;
+        let driver = driver.as_mut().expect("driver is launched");
+        self.within("Clicking the element", driver.click(selector))
+            .await?;
+        driver.url().await
+    }
+
+    /// Types `text` into the first element matching `selector`
+    pub async fn type_text(&self, selector: &str, text: &str) -> Result<String> {
+        let mut driver = self.driver().await?;
+        let driver = driver.as_mut().expect("driver is launched");
+        self.within("Typing into the element", driver.type_into(selector, text))
+            .await?;
+        driver.url().await
+    }
+
+    /// Saves a PNG screenshot to `path` below `screenshot_dir` and returns
+    /// where it went and its size
+    pub async fn screenshot(&self, path: &str, full_page: bool) -> Result<(PathBuf, usize)> {
+        let path = self.screenshot_path(path)?;
+        let mut driver = self.driver().await?;
+        let driver = driver.as_mut().expect("driver is launched");
+        let png = self
+            .within("Taking the screenshot", driver.screenshot(full_page))
+            .await?;
+        if let Some(dir) = path.parent() {
+            tokio::fs::create_dir_all(dir).await?;
+        }
+        tokio::fs::write(&path, &png).await?;
+        info!(
+            "Saved a screenshot of {} bytes to {}",
+            png.len(),
+            path.display()
+        );
+        Ok((path, png.len()))
+    }
+
+    /// The visible text of the page, or of the first element matching
+    /// `selector`, cut at `max_text_chars`
+    pub async fn page_text(&self, selector: Option<&str>) -> Result<PageText> {
+        let mut driver = self.driver().await?;
+        let driver = driver.as_mut().expect("driver is launched");
+        let text = self
+            .within("Reading the text", driver.text(selector))
+            .await?;
+        let PageInfo { url, title } = page_info(driver).await?;
+        let (text, truncated) = truncate_chars(normalize_text(&text), self.config.max_text_chars);
+        Ok(PageText {
+            url,
+            title,
+            text,
+            truncated,
+        })
+    }
+
+    /// URL of the page, or `about:blank` before the browser is launched
+    pub async fn current_url(&self) -> Result<String> {
+        match self.driver.lock().await.as_mut() {
+            Some(driver) => driver.url().await,
+            None => Ok("about:blank".to_string()),
+        }
+    }
+
+    /// Closes the browser if it was launched
+    pub async fn close(&self) {
+        if let Some(driver) = self.driver.lock().await.take() {
+            if let Err(e) = driver.close().await {
+                warn!("Failed to close the browser: {}", e);
+            }
+        }
+    }
+
+    /// The driver, launching the browser first if it is not running
+    async fn driver(&self) -> Result<tokio::sync::MutexGuard<'_, Option<Driver>>> {
+        let mut driver = self.driver.lock().await;
+        if driver.is_none() {
+            info!("Launching the browser");
+            *driver = Some(Driver::launch(&self.config).await?);
+        }
+        Ok(driver)
+    }
+
+    /// Waits for `action` at most `timeout_secs`
+    async fn within<T>(
+        &self,
+        what: &str,
+        action: impl std::future::Future<Output = Result<T>>,
+    ) -> Result<T> {
+        let timeout = Duration::from_secs(self.config.timeout_secs);
+        match tokio::time::timeout(timeout, action).await {
+            Ok(result) => result,
+            Err(_) => Err(Error::new(ToolError::new(
+                ToolErrorKind::Timeout,
+                format!("{} took longer than {} seconds", what, timeout.as_secs()),
+            ))),
+        }
+    }
+
+    /// `path` below `screenshot_dir`, with a .png extension; paths leaving
+    /// the directory are refused
+    fn screenshot_path(&self, path: &str) -> Result<PathBuf> {
+        let relative = Path::new(path);
+        if relative
+            .components()
+            .any(|c| !matches!(c, Component::Normal(_) | Component::CurDir))
+        {
+            return Err(Error::new(ToolError::new(
+                ToolErrorKind::InvalidArguments,
+                format!(
+                    "Screenshot path {} must be relative and stay below the screenshot directory",
+                    path
+                ),
+            )));
+        }
+        Ok(self
+            .config
+            .screenshot_dir
+            .join(relative)
+            .with_extension("png"))
+    }
+}
+
+/// URL and title of the page `driver` shows
+async fn page_info(driver: &Driver) -> Result<PageInfo> {
+    Ok(PageInfo {
+        url: driver.url().await?,
+        title: driver.title().await?,
+    })
+}
+
+/// `url` if it is an http or https URL
+fn check_url(url: &str) -> Result<reqwest::Url> {
+    let invalid = |reason: String| {
+        Error::new(ToolError::new(
+            ToolErrorKind::InvalidArguments,
+            format!("Cannot open {}: {}", url, reason),
+        ))
+    };
+    let parsed = reqwest::Url::parse(url).map_err(|e| invalid(e.to_string()))?;
+    match parsed.scheme() {
+        "http" | "https" => Ok(parsed),
+        scheme => Err(invalid(format!("{} URLs are not allowed", scheme))),
+    }
+}
+
+/// `text` with runs of blank lines and trailing spaces removed
+fn normalize_text(text: &str) -> String {
+    let mut lines: Vec<&str> = Vec::new();
+    for line in text.lines().map(str::trim_end) {
+        if line.trim().is_empty() && lines.last().is_none_or(|last| last.is_empty()) {
+            continue;
+        }
+        lines.push(if line.trim().is_empty() { "" } else { line });
+    }
+    lines.join("\n").trim_end().to_string()
+}
+
+/// The first `max` characters of `text` and whether any were cut
+fn truncate_chars(text: String, max: usize) -> (String, bool) {
+    match text.char_indices().nth(max) {
+        Some((end, _)) => (text[..end].to_string(), true),
+        None => (text, false),
+    }
+}
+
+/// The browser tools, all driving `browser`. Browser futures are not Sync,
+/// so each call runs on its own task and the handler only awaits its handle.
+pub(crate) fn browser_tools(browser: &Arc<BrowserServer>) -> Vec<Tool> {
+    let navigate = Arc::clone(browser);
+    let click = Arc::clone(browser);
+    let type_text = Arc::clone(browser);
+    let screenshot = Arc::clone(browser);
+    let page_text = Arc::clone(browser);
+    vec![
+        Tool::typed(
+            "puppeteer_navigate",
+            "Open an http or https URL in the browser",
+            move |args: NavigateArgs| {
+                let browser = Arc::clone(&navigate);
+                async move { tokio::spawn(async move { browser.navigate(&args.url).await }).await? }
+            },
+        ),
+        Tool::typed(
+            "puppeteer_click",
+            "Click the element of the current page matching a CSS selector",
+            move |args: ClickArgs| {
+                let browser = Arc::clone(&click);
+                async move {
+                    let url =
+                        tokio::spawn(async move { browser.click(&args.selector).await }).await??;
+                    Ok(ActionResult { success: true, url })
+                }
+            },
+        ),
+        Tool::typed(
+            "puppeteer_type",
+            "Type text into the element of the current page matching a CSS selector",
+            move |args: TypeArgs| {
+                let browser = Arc::clone(&type_text);
+                async move {
+                    let url =
+                        tokio::spawn(
+                            async move { browser.type_text(&args.selector, &args.text).await },
+                        )
+                        .await??;
+                    Ok(ActionResult { success: true, url })
+                }
+            },
+        ),
+        Tool::typed(
+            "puppeteer_screenshot",
+            "Save a PNG screenshot of the current page",
+            move |args: ScreenshotArgs| {
+                let browser = Arc::clone(&screenshot);
+                async move {
+                    let (path, bytes) = tokio::spawn(async move {
+                        browser.screenshot(&args.path, args.full_page).await
+                    })
+                    .await??;
+                    Ok(ScreenshotResult {
+                        path: path.display().to_string(),
+                        bytes,
+                    })
+                }
+            },
+        ),
+        Tool::typed(
+            "puppeteer_page_text",
+            "Get the visible text of the current page or of one of its elements",
+            move |args: PageTextArgs| {
+                let browser = Arc::clone(&page_text);
+                async move {
+                    tokio::spawn(async move { browser.page_text(args.selector.as_deref()).await })
+                        .await?
+                }
+            },
+        ),
+    ]
+}
+
+/// The `browser` resource: the URL of the current page or the viewport
+pub(crate) fn browser_resource(browser: Arc<BrowserServer>) -> Resource {
+    Resource::new(
+        "browser".to_string(),
+        "Browser automation resources".to_string(),
+        Some(json!({
+            "type": "object",
+            "properties": {
+                "action": {
+                    "type": "string",
+                    "description": "Action to perform (url/viewport)",
+                    "enum": ["url", "viewport"]
+                }
+            },
+            "required": ["action"]
+        })),
+        Some(json!({
+            "type": "object",
+            "properties": {
+                "current_url": {
+                    "type": "string",
+                    "description": "Current page URL"
+                },
+                "viewport": {
+                    "type": "object",
+                    "description": "Browser viewport dimensions",
+                    "properties": {
+                        "width": {
+                            "type": "integer",
+                            "description": "Viewport width in pixels"
+                        },
+                        "height": {
+                            "type": "integer",
+                            "description": "Viewport height in pixels"
+                        }
+                    }
+                }
+            }
+        })),
+        Box::new(move |args| {
+            let browser = Arc::clone(&browser);
+            Box::pin(async move {
+                match args.get("action").and_then(|v| v.as_str()) {
+                    Some("url") => {
+                        let url =
+                            tokio::spawn(async move { browser.current_url().await }).await??;
+                        Ok(json!({ "current_url": url }))
+                    }
+                    Some("viewport") => Ok(json!({
+                        "viewport": {
+                            "width": browser.config().viewport_width,
+                            "height": browser.config().viewport_height
+                        }
+                    })),
+                    _ => Err(Error::msg("Invalid action")),
+                }
+            })
+        }),
+    )
+}
+
+/// A Chromium process and the page the tools drive
+#[cfg(feature = "browser")]
+struct Driver {
+    browser: chromiumoxide::Browser,
+    page: chromiumoxide::Page,
+    events: tokio::task::JoinHandle<()>,
+}
+
+#[cfg(feature = "browser")]
+impl Driver {
+    async fn launch(config: &BrowserConfig) -> Result<Self> {
+        use chromiumoxide::browser::BrowserConfig as ChromeConfig;
+        use chromiumoxide::handler::viewport::Viewport;
+        use futures::StreamExt;
+
+        let mut builder = ChromeConfig::builder()
+            .window_size(config.viewport_width, config.viewport_height)
+            .viewport(Viewport {
+                width: config.viewport_width,
+                height: config.viewport_height,
+                ..Viewport::default()
+            });
+        if !config.headless {
+            builder = builder.with_head();
+        }
+        if let Some(executable) = &config.chrome_executable {
+            builder = builder.chrome_executable(executable);
+        }
+        let unavailable = |message: String| {
+            Error::new(ToolError::new(
+                ToolErrorKind::Unavailable,
+                format!("Failed to launch the browser: {}", message),
+            ))
+        };
+        let chrome_config = builder.build().map_err(unavailable)?;
+        let (browser, mut handler) = chromiumoxide::Browser::launch(chrome_config)
+            .await
+            .map_err(|e| unavailable(e.to_string()))?;
+        // The handler carries the DevTools messages and must be polled
+        // for as long as the browser runs
+        let events = tokio::spawn(async move {
+            while let Some(event) = handler.next().await {
+                if event.is_err() {
+                    break;
+                }
+            }
+        });
+        let page = browser.new_page("about:blank").await?;
+        Ok(Self {
+            browser,
+            page,
+            events,
+        })
+    }
+
+    async fn goto(&mut self, url: &str) -> Result<()> {
+        self.page.goto(url).await?;
+        Ok(())
+    }
+
+    async fn url(&self) -> Result<String> {
+        Ok(self
+            .page
+            .url()
+            .await?
+            .unwrap_or_else(|| "about:blank".to_string()))
+    }
+
+    async fn title(&self) -> Result<String> {
+        Ok(self.page.get_title().await?.unwrap_or_default())
+    }
+
+    async fn element(&self, selector: &str) -> Result<chromiumoxide::Element> {
+        self.page.find_element(selector).await.map_err(|e| {
+            Error::new(ToolError::new(
+                ToolErrorKind::NotFound,
+                format!("No element matches {}: {}", selector, e),
+            ))
+        })
+    }
+
+    async fn click(&mut self, selector: &str) -> Result<()> {
+        self.element(selector).await?.click().await?;
+        Ok(())
+    }
+
+    async fn type_into(&mut self, selector: &str, text: &str) -> Result<()> {
+        self.element(selector)
+            .await?
+            .click()
+            .await?
+            .type_str(text)
+            .await?;
+        Ok(())
+    }
+
+    async fn screenshot(&mut self, full_page: bool) -> Result<Vec<u8>> {
+        use chromiumoxide::cdp::browser_protocol::page::CaptureScreenshotFormat;
+        use chromiumoxide::page::ScreenshotParams;
+
+        let params = ScreenshotParams::builder()
+            .format(CaptureScreenshotFormat::Png)
+            .full_page(full_page)
+            .build();
+        Ok(self.page.screenshot(params).await?)
+    }
+
+    async fn text(&mut self, selector: Option<&str>) -> Result<String> {
+        match selector {
+            Some(selector) => Ok(self
+                .element(selector)
+                .await?
+                .inner_text()
+                .await?
+                .unwrap_or_default()),
+            None => Ok(self
+                .page
+                .evaluate("document.body ? document.body.innerText : ''")
+                .await?
+                .into_value::<String>()?),
+        }
+    }
+
+    async fn close(mut self) -> Result<()> {
+        self.browser.close().await?;
+        self.browser.wait().await?;
+        self.events.abort();
+        Ok(())
+    }
+}
+
+/// Stands in for the browser when built without the `browser` feature; it
+/// cannot be launched
+#[cfg(not(feature = "browser"))]
+enum Driver {}
+
+#[cfg(not(feature = "browser"))]
+impl Driver {
+    async fn launch(_config: &BrowserConfig) -> Result<Self> {
+        Err(Error::new(ToolError::new(
+            ToolErrorKind::Unavailable,
+            "The puppeteer tools require building with `--features browser`",
+        )))
+    }
+
+    async fn goto(&mut self, _url: &str) -> Result<()> {
+        match *self {}
+    }
+
+    async fn url(&self) -> Result<String> {
+        match *self {}
+    }
+
+    async fn title(&self) -> Result<String> {
+        match *self {}
+    }
+
+    async fn click(&mut self, _selector: &str) -> Result<()> {
+        match *self {}
+    }
+
+    async fn type_into(&mut self, _selector: &str, _text: &str) -> Result<()> {
+        match *self {}
+    }
+
+    async fn screenshot(&mut self, _full_page: bool) -> Result<Vec<u8>> {
+        match *self {}
+    }
+
+    async fn text(&mut self, _selector: Option<&str>) -> Result<String> {
+        match *self {}
+    }
+
+    async fn close(self) -> Result<()> {
+        match self {}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_config_and_paths() {
+        let config: BrowserConfig = serde_json::from_value(json!({
+            "command": "puppeteer",
+            "args": [],
+            "env": {},
+            "screenshot_dir": "shots",
+            "timeout_secs": 10
+        }))
+        .unwrap();
+        assert!(config.headless);
+        assert_eq!(config.timeout_secs, 10);
+        assert_eq!(config.viewport_width, 1024);
+
+        let browser = BrowserServer::new(config);
+        assert_eq!(
+            browser.screenshot_path("pages/home").unwrap(),
+            PathBuf::from("shots/pages/home.png")
+        );
+        for path in ["../home.png", "/tmp/home.png"] {
+            let error = browser.screenshot_path(path).unwrap_err();
+            assert_eq!(
+                ToolError::from_error(&error).kind,
+                ToolErrorKind::InvalidArguments
+            );
+        }
+
+        assert!(check_url("https://example.com/a?b=c").is_ok());
+        for url in ["file:///etc/passwd", "javascript:alert(1)", "example.com"] {
+            let error = check_url(url).unwrap_err();
+            assert_eq!(
+                ToolError::from_error(&error).kind,
+                ToolErrorKind::InvalidArguments
+            );
+        }
+    }
+
+    #[test]
+    fn test_text_cleanup() {
+        assert_eq!(
+            normalize_text("Title  \n\n\n\nFirst line\n   \nSecond line\n\n"),
+            "Title\n\nFirst line\n\nSecond line"
+        );
+        assert_eq!(
+            truncate_chars("Grüße".to_string(), 3),
+            ("Grü".to_string(), true)
+        );
+        assert_eq!(
+            truncate_chars("Hi".to_string(), 3),
+            ("Hi".to_string(), false)
+        );
+    }
+
+    #[tokio::test]
+    async fn test_tools() {
+        let browser = Arc::new(BrowserServer::new(BrowserConfig::default()));
+        let tools = browser_tools(&browser);
+        let names: Vec<&str> = tools.iter().map(|tool| tool.name.as_str()).collect();
+        assert_eq!(
+            names,
+            [
+                "puppeteer_navigate",
+                "puppeteer_click",
+                "puppeteer_type",
+                "puppeteer_screenshot",
+                "puppeteer_page_text"
+            ]
+        );
+
+        // Invalid URLs are refused before a browser is launched
+        let error = tools[0]
+            .execute(&json!({ "url": "file:///etc/passwd" }))
+            .await
+            .unwrap_err();
+        assert_eq!(
+            ToolError::from_error(&error).kind,
+            ToolErrorKind::InvalidArguments
+        );
+        assert_eq!(browser.current_url().await.unwrap(), "about:blank");
+    }
+}
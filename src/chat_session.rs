@@ -0,0 +1,2262 @@
+//! Chat Session Module: Interactive chat loop and command handling
+//!
+//! This module provides:
+//! - The interactive read-eval loop with persistent command history, and
+//!   tab completion of commands, tool and model names and paths
+//! - Command processing (/help, /tools, /models, /compare, /rag-*, ...)
+//! - `/prompt`: prompts of the MCP servers, rendered and sent as the next
+//!   message
+//! - `/audit`: the tool calls recorded in the audit log
+//! - `/trace`: the tool calls and answer of the last message
+//! - Conversation memory: earlier turns are sent with each message, as many
+//!   of the latest as `conversation.max_history_tokens` allows, after an
+//!   LLM-written summary of the older ones; `/clear` forgets them and
+//!   starts a new session
+//! - Saved sessions: each turn is saved as it ends; `/sessions` lists past
+//!   sessions and `/resume <id>` continues one
+//! - `/usage`: tokens and estimated cost since the chat started, and of
+//!   the saved conversation per provider and per turn
+//! - `/system set|show|reset`: a system prompt of this session only, saved
+//!   with it
+//! - `/undo` and `/retry [model]`: the last exchange taken back, or asked
+//!   again, optionally of another model
+//! - `/history-search <query>`: saved messages matching keywords and past
+//!   exchanges similar in meaning, with the sessions to `/resume`
+//! - `/export` and `/import`: the session as a Markdown or JSONL transcript,
+//!   and a JSONL transcript replayed into a new session
+//! - Long-term memory: every exchange is embedded, and the past exchanges
+//!   most similar to a new message are sent with it
+//! - Metrics of the tool calls and LLM usage, optionally served to
+//!   Prometheus
+//! - LLM requests with tool-call execution, context trimming and rate-limit retries
+//! - The tool-calling loop: tool results go back to the LLM, which may call
+//!   further tools until it answers or the `agent` limits are reached
+//! - Answers rendered as Markdown with highlighted code, unless disabled in
+//!   the `markdown` section or colors are off
+//! - One-shot asks for `mcp-chatbot ask`: a single turn answered without
+//!   the interactive loop, printing nothing itself
+//!
+//! Key Components:
+//! - `ChatSession`: Owns the LLM client, servers, prompts and usage of one chat
+//!
+//! Author: arkSong <arksong2018@gmail.com>
+//! Version: 1.0.0
+//! License: MIT
+
+use std::collections::HashMap;
+use std::env;
+use std::io::{self, Read, Write};
+use std::path::Path;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use anyhow::{Error, Result};
+use futures::stream::{self, StreamExt};
+use log::{debug, error, info, warn};
+use rustyline::config::Configurer;
+use rustyline::history::FileHistory;
+use rustyline::{Cmd, CompletionType, Editor, KeyCode, KeyEvent, Modifiers};
+use serde_json::{json, Value};
+use tokio::sync::{mpsc, watch, Mutex};
+use tokio::task::JoinHandle;
+use tokio::time::sleep;
+use tokio_util::sync::CancellationToken;
+
+use crate::agent::{parse_tool_calls, AgentConfig, AgentTrace, TraceStep, FINAL_ANSWER_PROMPT};
+use crate::audit_log::{AuditLog, AuditQuery};
+use crate::completion::{quoted_message, ChatHelper};
+use crate::config::Configuration;
+use crate::conversation::{
+    summary_request, ConversationConfig, ConversationContext, ConversationStore,
+};
+use crate::history::History;
+use crate::llm_client::{model_matches, LLMClient, LLMError, LLMProvider};
+use crate::long_term_memory::{
+    recall_prompt, LongTermMemory, LongTermMemoryConfig, RecalledExchange,
+};
+use crate::markdown::{MarkdownConfig, MarkdownRenderer};
+use crate::mcp_server::{ProgressEvent, ProgressSink, ToolResult};
+use crate::metrics::Metrics;
+use crate::prompt_template;
+use crate::prompts::{load_system_prompts, SystemPrompts};
+use crate::protocol::{Content, GetPromptResult, PromptSchema, ToolSchema};
+use crate::rag_server::{
+    build_context_prompt, format_citations, Document, RagConfig, RagServer, SearchResult,
+};
+use crate::server::{find_tool_server, warn_tool_collisions, Server};
+use crate::server_manager::{ServerManager, SupervisorConfig};
+use crate::tool_error::{ToolError, ToolErrorKind};
+use crate::tool_policy::{approval_question, is_approval, Permission};
+use crate::tool_selection::ToolSelector;
+use crate::transcript::{from_jsonl, to_jsonl, to_markdown, TranscriptFormat};
+use crate::usage::UsageTracker;
+use crate::utils::{
+    clear_line, colorize, colors_enabled, is_terminal, print_about, print_audit_entries,
+    print_bot_thinking_continuous, print_colored_ln, print_history_matches, print_menu,
+    print_progress_bar, print_prompts, print_provider_health, print_resources, print_server_status,
+    print_session_usage, print_sessions, print_side_by_side, print_tools, print_trace, print_usage,
+    spawn_cancel_key_listener, typing_animation, Color,
+};
+use crate::whisper_server::WhisperServer;
+
+/// Longest `Retry-After` delay that is waited for automatically
+const MAX_RATE_LIMIT_WAIT_SECS: u64 = 30;
+
+/// Number of chunks added to the system prompt in RAG chat mode
+const RAG_CONTEXT_CHUNKS: u32 = 3;
+
+/// Number of tool calls `/audit` shows unless given a `limit`
+const AUDIT_ENTRIES_SHOWN: usize = 20;
+
+/// Number of sessions `/sessions` shows unless given a number
+const SESSIONS_SHOWN: usize = 20;
+
+/// Number of keyword matches `/history-search` shows
+const HISTORY_MATCHES_SHOWN: usize = 10;
+
+/// Number of the costliest turns `/usage` shows
+const LARGEST_TURNS_SHOWN: usize = 5;
+
+/// Represents a chat session with the LLM
+/// Manages the conversation state, tools, and server interactions
+pub struct ChatSession {
+    pub llm_client: Arc<Mutex<LLMClient>>, // Thread-safe LLM client
+    pub servers: ServerManager,            // Supervised servers, in configuration order
+    pub history: History,                  // Chat history
+    pub current_provider: LLMProvider,     // Currently active AI provider
+    pub running: bool,                     // Session running state
+    pub cached_tools: Vec<ToolSchema>,     // Cached tool definitions
+    tool_changes: Vec<watch::Receiver<u64>>, // Invalidate cached_tools when tools change
+    tool_selector: ToolSelector,           // Picks the cached tools offered in a turn
+    agent_config: AgentConfig,             // Limits of the tool calls of a turn
+    last_trace: Option<AgentTrace>,        // Tool calls of the last turn, for /trace
+    conversation: ConversationContext,     // Earlier turns sent with each message
+    conversation_store: Option<ConversationStore>, // Saved sessions, unless disabled
+    session_id: String,                    // Id the turns of this chat are saved under
+    long_term_memory: Option<LongTermMemory>, // Past exchanges recalled by similarity
+    remembered: Vec<Option<String>>,       // Long-term memory ids of this session's turns
+    system_prompt: Option<String>,         // This session's own system prompt, from /system
+    pub prompts: SystemPrompts,            // System prompts from mcp_prompts.yaml
+    pub rag_server: Arc<Mutex<RagServer>>, // Thread-safe RagServer instance
+    pub usage: UsageTracker,               // Token usage for this session
+    pub rag_chat: bool,                    // Inject RAG context into chat turns
+    prompt_context: Vec<String>,           // Prompt messages sent with the next message
+    audit_log: Option<Arc<AuditLog>>,      // Record of the tool calls, unless disabled
+    metrics: Arc<Metrics>,                 // Tool and LLM metrics for /metrics
+    readline: Editor<ChatHelper, FileHistory>, // Command line editor with completion
+    interactive: bool,                     // Approvals and progress on the terminal
+    markdown: Option<MarkdownRenderer>,    // Renders answers, unless disabled
+    tools_enabled: bool,                   // Whether tools are offered to the LLM
+    busy: Option<Busy>,                    // Spinner and CTRL+K listener while answering
+    replacing_turn: bool,                  // /retry: the next answer replaces the last turn
+}
+
+impl ChatSession {
+    /// Creates a new chat session
+    /// Initializes the LLM client, servers, and command line interface
+    pub async fn new(llm_client: Arc<Mutex<LLMClient>>) -> Result<Self> {
+        let config = Configuration::new();
+        let server_config = config.load_config("config/servers_config.json")?;
+        let mut servers = ServerManager::from_config(
+            &server_config,
+            SupervisorConfig::from_config_file("config/config.yaml"),
+        )
+        .await?;
+
+        // Initialize command line editor with history
+        let mut readline = Editor::<ChatHelper, FileHistory>::new()?;
+        readline.set_max_history_size(1000)?;
+        readline.set_completion_type(CompletionType::List);
+        // Alt+Enter starts a new line; pasted text keeps its line breaks
+        readline.bind_sequence(KeyEvent(KeyCode::Enter, Modifiers::ALT), Cmd::Newline);
+        readline.enable_bracketed_paste(true);
+        if let Err(e) = readline.load_history(".mcp_history") {
+            info!("No history file found: {}", e);
+        }
+
+        // Initialize RagServer
+        let rag_config = config
+            .load_rag_config("config/config.yaml")
+            .unwrap_or_else(|e| {
+                warn!("Failed to load RAG configuration, using defaults: {}", e);
+                RagConfig::default()
+            });
+        let rag_server = Arc::new(Mutex::new(RagServer::with_config(rag_config)?));
+        {
+            let mut rag = rag_server.lock().await;
+            rag.set_summarizer(llm_client.clone());
+            rag.init().await?;
+        }
+
+        // Past exchanges are stored next to the RAG index
+        let long_term_memory = {
+            let rag = rag_server.lock().await;
+            LongTermMemory::open(
+                LongTermMemoryConfig::from_config_file("config/config.yaml"),
+                &rag,
+            )
+            .await
+            .unwrap_or_else(|e| {
+                warn!("Past exchanges are not recalled: {}", e);
+                None
+            })
+        };
+
+        // Give the servers the tools that feed the RAG index
+        for server in &mut servers {
+            server.attach_rag_server(Arc::clone(&rag_server)).await?;
+        }
+
+        warn_tool_collisions(&servers).await?;
+
+        // Record every tool call of this session
+        let audit_log = AuditLog::from_config_file("config/config.yaml");
+        if let Some(audit_log) = &audit_log {
+            for server in &mut servers {
+                audit_log.attach(&mut server.mcp_server).await;
+            }
+        }
+
+        let metrics = Metrics::from_config_file("config/config.yaml").await;
+        for server in &mut servers {
+            metrics.attach(&mut server.mcp_server).await;
+        }
+
+        // Cache tool definitions for quick access, until a server's tools
+        // change
+        let cached_tools = list_all_tools(&servers).await?;
+        let mut helper = ChatHelper::new();
+        helper.set_tools(tool_names(&cached_tools));
+        readline.set_helper(Some(helper));
+        let tool_changes = servers
+            .iter()
+            .map(|server| server.mcp_server.subscribe_tools_changed())
+            .collect();
+
+        let current_provider = llm_client.lock().await.provider().clone();
+
+        // Answers are rendered only in color on a terminal, so redirected
+        // output keeps the Markdown as written
+        let markdown_config = MarkdownConfig::from_config_file("config/config.yaml");
+        let markdown = if markdown_config.enabled && colors_enabled() && is_terminal() {
+            Some(MarkdownRenderer::new(&markdown_config))
+        } else {
+            None
+        };
+
+        let conversation_config = ConversationConfig::from_config_file("config/config.yaml");
+        let conversation_store = ConversationStore::from_config(&conversation_config)
+            .unwrap_or_else(|e| {
+                warn!("Conversations are not saved: {}", e);
+                None
+            });
+
+        Ok(Self {
+            llm_client,
+            servers,
+            history: History::new(),
+            current_provider,
+            running: true,
+            cached_tools,
+            tool_changes,
+            tool_selector: ToolSelector::from_config_file("config/config.yaml"),
+            agent_config: AgentConfig::from_config_file("config/config.yaml"),
+            last_trace: None,
+            conversation: ConversationContext::new(&conversation_config),
+            conversation_store,
+            session_id: uuid::Uuid::new_v4().to_string(),
+            long_term_memory,
+            remembered: Vec::new(),
+            system_prompt: None,
+            prompts: load_system_prompts(), // Load prompts
+            rag_server,
+            usage: UsageTracker::new(),
+            rag_chat: false,
+            prompt_context: Vec::new(),
+            audit_log,
+            metrics,
+            readline,
+            interactive: true,
+            markdown,
+            tools_enabled: true,
+            busy: None,
+            replacing_turn: false,
+        })
+    }
+
+    /// Creates a session answering single questions with `ask`, for
+    /// scripts: it asks nothing on the terminal, so tools needing approval
+    /// are declined, and without `use_tools` no tool is offered or run
+    pub async fn one_shot(llm_client: Arc<Mutex<LLMClient>>, use_tools: bool) -> Result<Self> {
+        let mut session = Self::new(llm_client).await?;
+        session.interactive = false;
+        if !use_tools {
+            session.tools_enabled = false;
+            session.agent_config.max_iterations = 0;
+        }
+        Ok(session)
+    }
+
+    /// Answers `input` as one turn, printing nothing, and returns the
+    /// answer with the trace of the tool calls that led to it
+    pub async fn ask(&mut self, input: &str) -> Result<(String, AgentTrace)> {
+        let answer = self
+            .process_llm_response(input, &CancellationToken::new())
+            .await?;
+        let trace = match &self.last_trace {
+            Some(trace) => trace.clone(),
+            None => AgentTrace::new(input),
+        };
+        Ok((answer, trace))
+    }
+
+    /// Cleans up all servers when the session ends
+    pub async fn cleanup_servers(&mut self) -> Result<()> {
+        info!("Cleaning up all servers");
+        self.servers.cleanup().await;
+        info!("All servers cleaned up");
+        Ok(())
+    }
+
+    /// Rebuilds `cached_tools` if tools were registered, replaced or
+    /// unregistered on any server since they were cached
+    pub async fn refresh_tools(&mut self) -> Result<()> {
+        let mut changed = false;
+        for changes in &mut self.tool_changes {
+            if changes.has_changed().unwrap_or(false) {
+                changes.borrow_and_update();
+                changed = true;
+            }
+        }
+        if changed {
+            self.cached_tools = list_all_tools(&self.servers).await?;
+            info!("Tools changed, {} tools available", self.cached_tools.len());
+            if let Some(helper) = self.readline.helper_mut() {
+                helper.set_tools(tool_names(&self.cached_tools));
+            }
+        }
+        Ok(())
+    }
+
+    /// Gets the appropriate system prompt for the current server and renders
+    /// its template variables (date, server, model, tools, user profile)
+    pub async fn get_system_prompt(&self, server_name: &str) -> String {
+        self.render_system_prompt(server_name, &self.cached_tools)
+            .await
+    }
+
+    /// Renders the system prompt of `server_name` listing only `tools`, or
+    /// the session's own one if `/system` set it
+    async fn render_system_prompt(&self, server_name: &str, tools: &[ToolSchema]) -> String {
+        let template = match (
+            &self.system_prompt,
+            self.prompts.server_prompts.get(server_name),
+        ) {
+            (Some(system_prompt), _) => system_prompt,
+            (None, Some(server_prompt)) => &server_prompt.system_prompt,
+            (None, None) => &self.prompts.default_system_prompt,
+        };
+
+        let model = self.llm_client.lock().await.current_model().to_string();
+        let context =
+            prompt_template::build_context(server_name, &model, tools, &self.prompts.user_profile);
+        prompt_template::render(template, &context)
+    }
+
+    /// Processes an LLM response
+    /// Handles both tool calls and regular responses
+    pub async fn process_llm_response(
+        &mut self,
+        input: &str,
+        cancel: &CancellationToken,
+    ) -> Result<String> {
+        // Format input as a message array with system message
+        let mut messages = Vec::new();
+        self.refresh_tools().await?;
+
+        // With many tools, only those relevant to the input are offered
+        let tools = if self.tools_enabled {
+            let rag = self.rag_server.lock().await;
+            self.tool_selector
+                .select(input, &self.cached_tools, &rag)
+                .await
+        } else {
+            Vec::new()
+        };
+
+        // Get the appropriate system prompt based on the server
+        let mut system_prompt = self
+            .render_system_prompt(self.current_provider.name(), &tools)
+            .await;
+
+        // In RAG chat mode the most similar chunks are prepended as context
+        let sources = if self.rag_chat {
+            self.retrieve_context(input).await
+        } else {
+            Vec::new()
+        };
+        if !sources.is_empty() {
+            system_prompt = format!("{}\n\n{}", build_context_prompt(&sources), system_prompt);
+        }
+
+        messages.push(json!({
+            "role": "system",
+            "content": system_prompt
+        }));
+
+        // System messages of a prompt sent with /prompt apply to this turn,
+        // and are kept for the next one if this turn fails before any tool
+        // runs
+        for context in &self.prompt_context {
+            messages.push(json!({
+                "role": "system",
+                "content": context
+            }));
+        }
+
+        // Similar exchanges of past sessions, then the earlier turns, so
+        // the LLM remembers what was said, then the user message
+        let history = self.conversation.messages();
+        let recalled = self.recall_exchanges(input, &history).await;
+        if !recalled.is_empty() {
+            messages.push(json!({
+                "role": "system",
+                "content": recall_prompt(&recalled)
+            }));
+        }
+        messages.extend(history);
+        let user_message = json!({
+            "role": "user",
+            "content": input
+        });
+        messages.push(user_message.clone());
+        let mut turn = vec![user_message];
+
+        debug!("Processing user input: {}", input);
+        debug!(
+            "Formatted messages: {}",
+            serde_json::to_string_pretty(&messages)?
+        );
+
+        // Run the tools the LLM calls and feed their results back until it
+        // answers, or a limit ends the calls
+        let tokens_before = self.usage.session().total();
+        let usage_before = self.usage.clone();
+        let mut trace = AgentTrace::new(input);
+        let mut stopped: Option<String> = None;
+        let answer = loop {
+            let response = match self.request_llm(&mut messages, cancel).await {
+                Ok(response) => response,
+                Err(e) => {
+                    // The tools that ran may have changed things, so the
+                    // turn is kept without an answer
+                    if trace.tool_calls() > 0 {
+                        self.prompt_context.clear();
+                        self.last_trace = Some(trace);
+                        self.record_turn(turn, &usage_before, cancel).await;
+                        self.remembered.push(None);
+                    }
+                    return Err(e);
+                }
+            };
+            trace.llm_requests += 1;
+            trace.tokens = self.usage.session().total().saturating_sub(tokens_before);
+
+            let mut calls = match (parse_tool_calls(&response), &stopped) {
+                (None, _) => break response,
+                (Some(_), Some(reason)) => {
+                    warn!("LLM called a tool after the limit was reached");
+                    break format!(
+                        "I stopped before finishing because I {}. See /trace for the tool calls made so far.",
+                        reason
+                    );
+                }
+                (Some(calls), None) => calls,
+            };
+            let remaining = self
+                .agent_config
+                .max_iterations
+                .saturating_sub(trace.tool_calls());
+            if calls.len() > remaining {
+                warn!(
+                    "LLM called {} tools, running only the {} left in this turn",
+                    calls.len(),
+                    remaining
+                );
+                calls.truncate(remaining);
+            }
+            for (tool_name, arguments) in &calls {
+                debug!("Executing tool {} with arguments {}", tool_name, arguments);
+            }
+            let results = self.call_tools(&calls).await;
+
+            let call_message = json!({
+                "role": "assistant",
+                "content": response
+            });
+            messages.push(call_message.clone());
+            turn.push(call_message);
+            if trace.tool_calls() == 0 {
+                messages.push(json!({
+                    "role": "system",
+                    "content": self.prompts.common_prompts.tool_response.clone()
+                }));
+            }
+            for ((tool, arguments), result) in calls.into_iter().zip(results) {
+                let result_message = json!({
+                    "role": "tool",
+                    "content": serde_json::to_string(&result)?
+                });
+                messages.push(result_message.clone());
+                turn.push(result_message);
+                trace.steps.push(TraceStep::ToolCall {
+                    tool,
+                    arguments,
+                    result,
+                });
+            }
+
+            if let Some(reason) = self
+                .agent_config
+                .limit_reached(trace.tool_calls(), trace.tokens)
+            {
+                info!("No more tool calls in this turn: {}", reason);
+                messages.push(json!({
+                    "role": "system",
+                    "content": FINAL_ANSWER_PROMPT
+                }));
+                trace.steps.push(TraceStep::Stopped {
+                    reason: reason.clone(),
+                });
+                stopped = Some(reason);
+            }
+        };
+        trace.steps.push(TraceStep::Answer {
+            text: answer.clone(),
+        });
+        let tool_calls = trace.tool_calls();
+        self.last_trace = Some(trace);
+        turn.push(json!({
+            "role": "assistant",
+            "content": answer
+        }));
+        self.prompt_context.clear();
+        self.record_turn(turn, &usage_before, cancel).await;
+        self.remember_exchange(input, &answer).await;
+
+        if tool_calls == 0 && !sources.is_empty() {
+            // Show where the context came from under the answer
+            return Ok(format!("{}\n\n{}", answer, format_citations(&sources)));
+        }
+        Ok(answer)
+    }
+
+    /// Saves `turn` with the usage since `usage_before`, if sessions are
+    /// saved, and adds it to the context, in place of the exchange being
+    /// retried if any
+    async fn record_turn(
+        &mut self,
+        turn: Vec<Value>,
+        usage_before: &UsageTracker,
+        cancel: &CancellationToken,
+    ) {
+        // A retried exchange only gives way once there is a new turn
+        if std::mem::take(&mut self.replacing_turn) {
+            self.forget_saved_turn().await;
+        }
+        if let Some(store) = &self.conversation_store {
+            if let Err(e) = store
+                .save_turn(&self.session_id, self.current_provider.name(), &turn)
+                .and_then(|_| store.save_usage(&self.session_id, &self.usage.since(usage_before)))
+            {
+                warn!(
+                    "Failed to save the turn of session {}: {}",
+                    self.session_id, e
+                );
+            }
+        }
+        let dropped = self.conversation.push_turn(turn);
+        if !dropped.is_empty() && self.conversation.config().summarize {
+            self.summarize_turns(&dropped, cancel).await;
+        }
+    }
+
+    /// Runs `tool_name`, bare or qualified as `server/tool`, on the server
+    /// offering it. Unknown tools and failures become error results, which
+    /// the LLM explains like others. Tools the server's policy marks
+    /// `confirm` only run once the user agrees, and `deny` tools never run.
+    /// Progress the tool reports is drawn as a bar while it runs.
+    pub async fn call_tool(&mut self, tool_name: &str, arguments: &Value) -> ToolResult {
+        let i = match self.prepare_tool_call(tool_name, arguments).await {
+            Ok(i) => i,
+            Err(refused) => return refused,
+        };
+        if !self.interactive {
+            return self.servers[i]
+                .call_tool(tool_name, arguments, &ProgressSink::none())
+                .await;
+        }
+        let (progress, events) = ProgressSink::channel();
+        let display = tokio::spawn(show_progress(tool_name.to_string(), events));
+        let result = self.servers[i]
+            .call_tool(tool_name, arguments, &progress)
+            .await;
+        drop(progress);
+        let _ = display.await;
+        result
+    }
+
+    /// Runs the independent tool `calls` of one LLM response, at most
+    /// `max_parallel_tools` at a time, and returns their results in the
+    /// order of the calls. Approvals are asked for one after the other
+    /// before any call runs; several calls at once draw no progress bars.
+    pub async fn call_tools(&mut self, calls: &[(String, Value)]) -> Vec<ToolResult> {
+        if let [(tool_name, arguments)] = calls {
+            return vec![self.call_tool(tool_name, arguments).await];
+        }
+        let mut prepared = Vec::with_capacity(calls.len());
+        for (tool_name, arguments) in calls {
+            prepared.push(self.prepare_tool_call(tool_name, arguments).await);
+        }
+
+        let servers = &self.servers;
+        let progress = ProgressSink::none();
+        stream::iter(calls.iter().zip(prepared))
+            .map(|((tool_name, arguments), prepared)| {
+                let progress = &progress;
+                async move {
+                    match prepared {
+                        Ok(i) => servers[i].call_tool(tool_name, arguments, progress).await,
+                        Err(refused) => refused,
+                    }
+                }
+            })
+            .buffered(self.agent_config.max_parallel_tools.max(1))
+            .collect()
+            .await
+    }
+
+    /// The index of the server that may run `tool_name`, or the error
+    /// result of a call to a tool that is unknown, kept out by the tool
+    /// selection lists or refused by its server's policy
+    async fn prepare_tool_call(
+        &mut self,
+        tool_name: &str,
+        arguments: &Value,
+    ) -> Result<usize, ToolResult> {
+        let i = match find_tool_server(&self.servers, tool_name).await {
+            Some(i) => i,
+            None => {
+                let error = ToolError::new(
+                    ToolErrorKind::NotFound,
+                    format!("Tool not found: {}", tool_name),
+                );
+                return Err(ToolResult::new(Err(error.into()), Instant::now()));
+            }
+        };
+        // A tool the selection lists keep from the LLM may not run either
+        if !self.tool_selector.is_allowed(tool_name) {
+            let refusal = format!("Tool {} is not allowed by tool_selection", tool_name);
+            info!("{}", refusal);
+            let error = ToolError::new(ToolErrorKind::PermissionDenied, refusal);
+            return Err(ToolResult::new(Err(error.into()), Instant::now()));
+        }
+        let refusal = match self.servers[i].permission(tool_name) {
+            Permission::Safe => None,
+            Permission::Confirm if self.approve(tool_name, arguments).await => None,
+            Permission::Confirm => Some(format!("The user did not allow {} to run", tool_name)),
+            Permission::Deny => Some(format!(
+                "Tool {} is denied by its server's policy",
+                tool_name
+            )),
+        };
+        match refusal {
+            Some(refusal) => {
+                info!("{}", refusal);
+                let error = ToolError::new(ToolErrorKind::PermissionDenied, refusal);
+                Err(ToolResult::new(Err(error.into()), Instant::now()))
+            }
+            None => Ok(i),
+        }
+    }
+
+    /// Asks the user whether `tool_name` may run with `arguments`. Anything
+    /// but yes, including a closed input, declines, and so does a one-shot
+    /// session without asking.
+    async fn approve(&mut self, tool_name: &str, arguments: &Value) -> bool {
+        if !self.interactive {
+            debug!("No approval for {} outside the chat", tool_name);
+            return false;
+        }
+        // The spinner would draw over the question and the CTRL+K listener
+        // would read the answer, so both pause while asking
+        let paused = match self.busy.take() {
+            Some(busy) => Some(busy.stop().await),
+            None => None,
+        };
+        let question = approval_question(tool_name, arguments);
+        let approved = match self.readline.readline(&colorize(&question, Color::Yellow)) {
+            Ok(answer) => is_approval(&answer),
+            Err(e) => {
+                debug!("No approval for {}: {}", tool_name, e);
+                false
+            }
+        };
+        if let Some(cancel) = paused {
+            self.busy = Some(Busy::start(cancel));
+        }
+        approved
+    }
+
+    /// Searches the RAG collection for chunks related to `input`.
+    /// Retrieval errors only disable the context for this turn.
+    async fn retrieve_context(&self, input: &str) -> Vec<SearchResult> {
+        let rag = self.rag_server.lock().await;
+        match rag.search_similar(input, RAG_CONTEXT_CHUNKS, None).await {
+            Ok(results) => {
+                debug!("Retrieved {} context chunks", results.len());
+                results
+            }
+            Err(e) => {
+                warn!("RAG retrieval failed, answering without context: {}", e);
+                Vec::new()
+            }
+        }
+    }
+
+    /// Every turn of this session: all saved turns if sessions are saved,
+    /// else the turns still kept in the context
+    fn session_turns(&self) -> Result<Vec<Vec<Value>>> {
+        match &self.conversation_store {
+            Some(store) => match store.resolve(&self.session_id) {
+                Ok(_) => store.load(&self.session_id),
+                // Nothing has been saved before the first answer
+                Err(_) => Ok(Vec::new()),
+            },
+            None => Ok(self.conversation.recent_turns()),
+        }
+    }
+
+    /// Writes this session to `path` as Markdown or JSONL, by its extension
+    fn export_session(&self, path: &Path) -> Result<usize> {
+        let format = TranscriptFormat::from_path(path)?;
+        let turns = self.session_turns()?;
+        let transcript = match format {
+            TranscriptFormat::Markdown => to_markdown(&self.session_id, &turns),
+            TranscriptFormat::Jsonl => to_jsonl(&self.session_id, &turns)?,
+        };
+        if let Some(parent) = path
+            .parent()
+            .filter(|parent| !parent.as_os_str().is_empty())
+        {
+            std::fs::create_dir_all(parent)?;
+        }
+        std::fs::write(path, transcript)?;
+        Ok(turns.len())
+    }
+
+    /// Replays the JSONL transcript at `path` into a new session: its
+    /// turns become the context and, if sessions are saved, the new
+    /// session's saved turns
+    fn import_session(&mut self, path: &Path) -> Result<usize> {
+        if TranscriptFormat::from_path(path)? != TranscriptFormat::Jsonl {
+            return Err(Error::msg(
+                "Only JSONL transcripts can be imported; Markdown is for reading",
+            ));
+        }
+        let turns = from_jsonl(&std::fs::read_to_string(path)?)?;
+        self.switch_session(uuid::Uuid::new_v4().to_string());
+        if let Some(store) = &self.conversation_store {
+            for turn in &turns {
+                store.save_turn(&self.session_id, self.current_provider.name(), turn)?;
+            }
+        }
+        let count = turns.len();
+        self.conversation.restore(turns);
+        Ok(count)
+    }
+
+    /// The past exchanges most similar to `input` that are not in the
+    /// `context` of earlier turns, if long-term memory is on
+    async fn recall_exchanges(&self, input: &str, context: &[Value]) -> Vec<RecalledExchange> {
+        let memory = match &self.long_term_memory {
+            Some(memory) => memory,
+            None => return Vec::new(),
+        };
+        let rag = self.rag_server.lock().await;
+        memory
+            .recall(input, context, &rag)
+            .await
+            .unwrap_or_else(|e| {
+                warn!("Failed to recall past exchanges: {}", e);
+                Vec::new()
+            })
+    }
+
+    /// Embeds the exchange of `input` and `answer` for later recall, if
+    /// long-term memory is on
+    async fn remember_exchange(&mut self, input: &str, answer: &str) {
+        let id = match &self.long_term_memory {
+            Some(memory) => {
+                let rag = self.rag_server.lock().await;
+                match memory.remember(&self.session_id, input, answer, &rag).await {
+                    Ok(id) => Some(id),
+                    Err(e) => {
+                        warn!("Failed to remember the exchange: {}", e);
+                        None
+                    }
+                }
+            }
+            None => None,
+        };
+        self.remembered.push(id);
+    }
+
+    /// Continues the chat as the session `session_id`, with its own system
+    /// prompt if it has one
+    fn switch_session(&mut self, session_id: String) {
+        self.system_prompt = match &self.conversation_store {
+            Some(store) => store.system_prompt(&session_id).unwrap_or_else(|e| {
+                warn!("Failed to load the system prompt of the session: {}", e);
+                None
+            }),
+            None => None,
+        };
+        self.session_id = session_id;
+        self.remembered.clear();
+    }
+
+    /// Saves the session's own system prompt, if sessions are saved
+    fn save_system_prompt(&self) {
+        if let Some(store) = &self.conversation_store {
+            if let Err(e) = store.set_system_prompt(
+                &self.session_id,
+                self.current_provider.name(),
+                self.system_prompt.as_deref(),
+            ) {
+                warn!("Failed to save the system prompt of the session: {}", e);
+            }
+        }
+    }
+
+    /// Takes the last exchange back: out of the context, the saved session
+    /// and the long-term memory. Returns its messages, or `None` if no
+    /// turn is kept.
+    async fn undo_turn(&mut self) -> Option<Vec<Value>> {
+        let turn = self.conversation.pop_turn()?;
+        self.forget_saved_turn().await;
+        self.last_trace = None;
+        Some(turn)
+    }
+
+    /// Deletes the last exchange from the saved session and the long-term
+    /// memory
+    async fn forget_saved_turn(&mut self) {
+        if let Some(store) = &self.conversation_store {
+            if let Err(e) = store.remove_last_turn(&self.session_id) {
+                warn!("Failed to remove the turn from the saved session: {}", e);
+            }
+        }
+        if let (Some(memory), Some(Some(id))) = (&self.long_term_memory, self.remembered.pop()) {
+            if let Err(e) = memory.forget(&id).await {
+                warn!("Failed to forget the exchange: {}", e);
+            }
+        }
+    }
+
+    /// Folds the `turns` that no longer fit into the summary of the
+    /// conversation. If the LLM fails, they are forgotten and the summary
+    /// stays as it was.
+    async fn summarize_turns(&mut self, turns: &[Vec<Value>], cancel: &CancellationToken) {
+        let mut messages = vec![
+            json!({
+                "role": "system",
+                "content": self.conversation.config().summary_prompt.clone()
+            }),
+            json!({
+                "role": "user",
+                "content": summary_request(self.conversation.summary(), turns)
+            }),
+        ];
+        match self.request_llm(&mut messages, cancel).await {
+            Ok(summary) => {
+                info!("Summarized {} earlier turns", turns.len());
+                self.conversation.set_summary(summary.trim().to_string());
+            }
+            Err(e) => warn!(
+                "Failed to summarize {} earlier turns, they are forgotten: {}",
+                turns.len(),
+                e
+            ),
+        }
+    }
+
+    /// Sends messages to the LLM and records the reported usage.
+    /// Drops the oldest messages when the context is too long and waits once
+    /// when the provider asks to retry after a short delay.
+    async fn request_llm(
+        &mut self,
+        messages: &mut Vec<Value>,
+        cancel: &CancellationToken,
+    ) -> Result<String> {
+        let mut waited_for_rate_limit = false;
+
+        loop {
+            let mut llm_client = self.llm_client.lock().await;
+            let error = match llm_client
+                .get_response_cancellable(messages, None, cancel)
+                .await
+            {
+                Ok(response) => {
+                    let served_by = llm_client.last_provider().map(|p| p.name());
+                    if let (Some(provider), Some(usage)) = (served_by, llm_client.last_usage()) {
+                        self.usage.record(provider, &usage);
+                        self.metrics.record_llm_usage(provider, &usage);
+                    }
+                    return Ok(response);
+                }
+                Err(e) => e,
+            };
+            drop(llm_client);
+
+            let context_too_long = matches!(
+                error.downcast_ref::<LLMError>(),
+                Some(LLMError::ContextTooLong(_))
+            );
+            let retry_after = match error.downcast_ref::<LLMError>() {
+                Some(LLMError::RateLimited {
+                    retry_after: Some(delay),
+                }) => Some(*delay),
+                _ => None,
+            };
+
+            if context_too_long && trim_oldest_message(messages) {
+                warn!("Context too long, dropped the oldest message and retrying");
+            } else if let Some(delay) = retry_after.filter(|d| {
+                !waited_for_rate_limit && *d <= Duration::from_secs(MAX_RATE_LIMIT_WAIT_SECS)
+            }) {
+                warn!("Rate limited, retrying in {}s", delay.as_secs());
+                waited_for_rate_limit = true;
+                tokio::select! {
+                    _ = cancel.cancelled() => return Err(LLMError::Cancelled.into()),
+                    _ = sleep(delay) => {}
+                }
+            } else {
+                return Err(error);
+            }
+        }
+    }
+
+    /// Sends the same message to every provider of the fallback chain
+    /// concurrently and prints the answers side by side
+    pub async fn compare_providers(&mut self, input: &str) -> Result<()> {
+        self.refresh_tools().await?;
+        let messages = vec![
+            json!({
+                "role": "system",
+                "content": self.get_system_prompt(self.current_provider.name()).await
+            }),
+            json!({
+                "role": "user",
+                "content": input
+            }),
+        ];
+
+        let llm_client = self.llm_client.lock().await;
+        let providers = llm_client.provider_chain();
+        print_colored_ln(
+            &format!(
+                "Comparing {} providers: {}",
+                providers.len(),
+                providers
+                    .iter()
+                    .map(|p| p.name())
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            ),
+            Color::Yellow,
+        );
+
+        let cancel = CancellationToken::new();
+        let listener_done = CancellationToken::new();
+        let listener_handle = spawn_cancel_key_listener(cancel.clone(), listener_done.clone());
+        let responses = llm_client
+            .get_responses_parallel_cancellable(&messages, &providers, &cancel)
+            .await;
+        listener_done.cancel();
+        listener_handle.await?;
+
+        if cancel.is_cancelled() {
+            print_colored_ln("Comparison stopped", Color::Yellow);
+            return Ok(());
+        }
+
+        let columns: Vec<(String, String)> = responses
+            .iter()
+            .map(|response| {
+                let title = format!(
+                    "{} ({}, {:.1}s)",
+                    response.provider.name(),
+                    response.model,
+                    response.elapsed.as_secs_f64()
+                );
+                let text = match &response.result {
+                    Ok(text) => text.clone(),
+                    Err(e) => format!("Error: {}", e),
+                };
+                (title, text)
+            })
+            .collect();
+
+        for response in &responses {
+            if let Some(usage) = &response.usage {
+                self.usage.record(response.provider.name(), usage);
+                self.metrics
+                    .record_llm_usage(response.provider.name(), usage);
+            }
+        }
+
+        println!();
+        print_side_by_side(&columns);
+        Ok(())
+    }
+
+    /// Processes a command entered by the user
+    /// Handles various built-in commands like /help, /clear, etc.
+    pub async fn process_command(&mut self, input: &str) -> Result<()> {
+        let (command, args) = match input.split_once(char::is_whitespace) {
+            Some((command, args)) => (command, args.trim()),
+            None => (input, ""),
+        };
+
+        match command {
+            "/help" => {
+                self.show_help();
+            }
+            "/clear" => {
+                self.conversation.clear();
+                self.switch_session(uuid::Uuid::new_v4().to_string());
+                if is_terminal() {
+                    print!("\x1B[2J\x1B[H");
+                }
+                io::stdout().flush()?;
+            }
+            "/usage" => {
+                let llm_client = self.llm_client.lock().await;
+                print_usage(&self.usage, llm_client.pricing());
+                if let Some(store) = &self.conversation_store {
+                    match store.usage(&self.session_id) {
+                        Ok(usage) => {
+                            print_session_usage(&usage, llm_client.pricing(), LARGEST_TURNS_SHOWN)
+                        }
+                        Err(e) => print_colored_ln(
+                            &format!("Failed to read the usage of this conversation: {}", e),
+                            Color::Red,
+                        ),
+                    }
+                }
+            }
+            "/about" => {
+                print_about();
+            }
+            "/exit" => {
+                self.running = false;
+            }
+            "/servers" | "/mcp-servers" => {
+                self.servers.check_health().await;
+                print_server_status(self.servers.status());
+            }
+            "/tools" => {
+                for server in &self.servers {
+                    let tools = server.list_tools().await?;
+                    print_colored_ln(
+                        &format!("\nTools from {} server:", server.name),
+                        Color::Cyan,
+                    );
+                    print_tools(&tools);
+                }
+            }
+            "/resources" => {
+                for server in &self.servers {
+                    let resources = server.list_resources().await?;
+                    print_colored_ln(
+                        &format!("\nResources from {} server:", server.name),
+                        Color::Cyan,
+                    );
+                    print_resources(&resources);
+                }
+            }
+            "/prompt" => {
+                if args.is_empty() {
+                    for server in &self.servers {
+                        let prompts = server.list_prompts().await?;
+                        if prompts.is_empty() {
+                            continue;
+                        }
+                        print_colored_ln(
+                            &format!("\nPrompts from {} server:", server.name),
+                            Color::Cyan,
+                        );
+                        print_prompts(&prompts);
+                    }
+                    return Ok(());
+                }
+                self.send_prompt(args).await?;
+            }
+            "/sessions" => {
+                let store = match &self.conversation_store {
+                    Some(store) => store,
+                    None => {
+                        print_colored_ln(
+                            "Sessions are not saved; see the conversation section of config.yaml",
+                            Color::Yellow,
+                        );
+                        return Ok(());
+                    }
+                };
+                let limit = match args {
+                    "" => SESSIONS_SHOWN,
+                    limit => match limit.parse() {
+                        Ok(limit) => limit,
+                        Err(_) => {
+                            print_colored_ln("Usage: /sessions [number]", Color::Yellow);
+                            return Ok(());
+                        }
+                    },
+                };
+                match store.sessions(limit) {
+                    Ok(sessions) => {
+                        print_colored_ln(&format!("\nSessions in {}:", store.path()), Color::Cyan);
+                        print_sessions(&sessions, &self.session_id);
+                    }
+                    Err(e) => {
+                        print_colored_ln(&format!("Failed to list the sessions: {}", e), Color::Red)
+                    }
+                }
+            }
+            "/resume" => {
+                let store = match &self.conversation_store {
+                    Some(store) => store,
+                    None => {
+                        print_colored_ln(
+                            "Sessions are not saved; see the conversation section of config.yaml",
+                            Color::Yellow,
+                        );
+                        return Ok(());
+                    }
+                };
+                if args.is_empty() {
+                    print_colored_ln("Usage: /resume <session id>", Color::Yellow);
+                    return Ok(());
+                }
+                let resumed = store
+                    .resolve(args)
+                    .and_then(|id| Ok((store.load(&id)?, id)));
+                match resumed {
+                    Ok((turns, id)) => {
+                        let saved = turns.len();
+                        self.conversation.restore(turns);
+                        self.switch_session(id);
+                        print_colored_ln(
+                            &format!(
+                                "Resumed session {} with {} turns, the latest {} sent with each message",
+                                self.session_id,
+                                saved,
+                                self.conversation.turns()
+                            ),
+                            Color::Green,
+                        );
+                    }
+                    Err(e) => print_colored_ln(&format!("{}", e), Color::Red),
+                }
+            }
+            "/history-search" => {
+                if args.is_empty() {
+                    print_colored_ln("Usage: /history-search <query>", Color::Yellow);
+                    return Ok(());
+                }
+                if self.conversation_store.is_none() && self.long_term_memory.is_none() {
+                    print_colored_ln(
+                        "Conversations are neither saved nor remembered; see the conversation and long_term_memory sections of config.yaml",
+                        Color::Yellow,
+                    );
+                    return Ok(());
+                }
+                let matches = match &self.conversation_store {
+                    Some(store) => store
+                        .search(args, HISTORY_MATCHES_SHOWN)
+                        .unwrap_or_else(|e| {
+                            warn!("Failed to search the saved sessions: {}", e);
+                            Vec::new()
+                        }),
+                    None => Vec::new(),
+                };
+                let similar = self.recall_exchanges(args, &[]).await;
+                print_history_matches(&matches, &similar);
+            }
+            "/export" => {
+                if args.is_empty() {
+                    print_colored_ln("Usage: /export <path.md|path.jsonl>", Color::Yellow);
+                    return Ok(());
+                }
+                match self.export_session(Path::new(args)) {
+                    Ok(turns) => print_colored_ln(
+                        &format!("Exported {} turns to {}", turns, args),
+                        Color::Green,
+                    ),
+                    Err(e) => print_colored_ln(&format!("Failed to export: {}", e), Color::Red),
+                }
+            }
+            "/import" => {
+                if args.is_empty() {
+                    print_colored_ln("Usage: /import <path.jsonl>", Color::Yellow);
+                    return Ok(());
+                }
+                match self.import_session(Path::new(args)) {
+                    Ok(turns) => print_colored_ln(
+                        &format!(
+                            "Imported {} turns into session {}, the latest {} sent with each message",
+                            turns,
+                            self.session_id,
+                            self.conversation.turns()
+                        ),
+                        Color::Green,
+                    ),
+                    Err(e) => {
+                        print_colored_ln(&format!("Failed to import: {}", e), Color::Red)
+                    }
+                }
+            }
+            "/system" => {
+                let (action, text) = match args.split_once(char::is_whitespace) {
+                    Some((action, text)) => (action, text.trim()),
+                    None => (args, ""),
+                };
+                match action {
+                    "set" if !text.is_empty() => {
+                        self.system_prompt = Some(text.to_string());
+                        self.save_system_prompt();
+                        print_colored_ln("System prompt of this session set", Color::Green);
+                    }
+                    "show" | "" => {
+                        let source = if self.system_prompt.is_some() {
+                            "set for this session"
+                        } else {
+                            "from mcp_prompts.yaml"
+                        };
+                        print_colored_ln(&format!("\nSystem prompt ({}):", source), Color::Cyan);
+                        println!(
+                            "{}",
+                            self.get_system_prompt(self.current_provider.name()).await
+                        );
+                    }
+                    "reset" => {
+                        self.system_prompt = None;
+                        self.save_system_prompt();
+                        print_colored_ln(
+                            "Back to the system prompt from mcp_prompts.yaml",
+                            Color::Green,
+                        );
+                    }
+                    _ => print_colored_ln(
+                        "Usage: /system set <text> | /system show | /system reset",
+                        Color::Yellow,
+                    ),
+                }
+            }
+            "/undo" => match self.undo_turn().await {
+                Some(turn) => print_colored_ln(
+                    &format!("Took back the exchange \"{}\"", turn_input(&turn)),
+                    Color::Green,
+                ),
+                None => print_colored_ln("There is no exchange to take back", Color::Yellow),
+            },
+            "/retry" => {
+                if self.conversation.turns() == 0 {
+                    print_colored_ln("There is no answer to retry", Color::Yellow);
+                    return Ok(());
+                }
+                // Another model answers this retry only
+                let previous_override = if args.is_empty() {
+                    None
+                } else {
+                    let mut llm_client = self.llm_client.lock().await;
+                    if !check_model(&llm_client, args).await {
+                        return Ok(());
+                    }
+                    let previous = llm_client.model_override().map(str::to_string);
+                    llm_client.set_model(args);
+                    Some(previous)
+                };
+                // The exchange leaves the context for the new answer, but
+                // stays saved and remembered until that answer replaces it
+                let turn = self.conversation.pop_turn().unwrap_or_default();
+                let input = turn_input(&turn);
+                self.replacing_turn = true;
+                println!("\n{}{}", colorize("@Human: ", Color::Blue), input);
+                let result = self.process_message(&input).await;
+                if let Some(previous) = previous_override {
+                    self.llm_client.lock().await.set_model_override(previous);
+                }
+                if std::mem::take(&mut self.replacing_turn) {
+                    // No new answer, so the exchange goes back where it was;
+                    // it fitted before, so no earlier turn is dropped
+                    self.conversation.push_turn(turn);
+                    print_colored_ln("Kept the earlier answer", Color::Yellow);
+                }
+                result?;
+            }
+            "/trace" => match &self.last_trace {
+                Some(trace) => print_trace(trace),
+                None => print_colored_ln("No message has been answered yet", Color::Yellow),
+            },
+            "/audit" => {
+                let audit_log = match &self.audit_log {
+                    Some(audit_log) => audit_log,
+                    None => {
+                        print_colored_ln(
+                            "Tool calls are not audited; see the audit section of config.yaml",
+                            Color::Yellow,
+                        );
+                        return Ok(());
+                    }
+                };
+                let mut query = match AuditQuery::parse(args) {
+                    Ok(query) => query,
+                    Err(e) => {
+                        print_colored_ln(&format!("{}", e), Color::Red);
+                        print_colored_ln(
+                            "Usage: /audit [tool=<name>] [session=current|<id>] [status=success|error] [since=<date>] [limit=<n>]",
+                            Color::Yellow,
+                        );
+                        return Ok(());
+                    }
+                };
+                if query.session_id.as_deref() == Some("current") {
+                    query.session_id = Some(audit_log.session_id().to_string());
+                }
+                query.limit = query.limit.or(Some(AUDIT_ENTRIES_SHOWN));
+                match audit_log.query(&query) {
+                    Ok(entries) => {
+                        print_colored_ln(
+                            &format!("\nTool calls in {}:", audit_log.path().display()),
+                            Color::Cyan,
+                        );
+                        print_audit_entries(&entries);
+                    }
+                    Err(e) => print_colored_ln(
+                        &format!("Failed to read the audit log: {}", e),
+                        Color::Red,
+                    ),
+                }
+            }
+            "/debug" => {
+                // Toggle debug logging level
+                let current_level = log::max_level();
+                let new_level = if current_level == log::LevelFilter::Info {
+                    log::LevelFilter::Debug
+                } else {
+                    log::LevelFilter::Info
+                };
+                log::set_max_level(new_level);
+                print_colored_ln(
+                    &format!(
+                        "Debug information is now {}",
+                        if new_level == log::LevelFilter::Debug {
+                            "enabled"
+                        } else {
+                            "disabled"
+                        }
+                    ),
+                    Color::Green,
+                );
+            }
+            "/ai" => {
+                if self.current_provider == LLMProvider::Ollama {
+                    // Switch to OpenAI when API key is available
+                    if let Ok(api_key) = env::var("OPENAI_API_KEY") {
+                        self.current_provider = LLMProvider::OpenAI;
+                        let config = Configuration::new();
+                        let mut llm_config = config.load_llm_config("config/config.yaml")?;
+                        llm_config.api_keys.openai = api_key;
+                        let mut llm_client = self.llm_client.lock().await;
+                        *llm_client = LLMClient::new(llm_config, LLMProvider::OpenAI);
+                        print_colored_ln("Switched to OpenAI server", Color::Green);
+                    } else {
+                        print_colored_ln(
+                            "Error: OPENAI_API_KEY not found in environment variables",
+                            Color::Red,
+                        );
+                        print_colored_ln(
+                            "\nTo use OpenAI, please set the OPENAI_API_KEY environment variable:",
+                            Color::Yellow,
+                        );
+                        print_colored_ln("export OPENAI_API_KEY='your-api-key-here'", Color::Green);
+                    }
+                } else {
+                    // Switch back to Ollama
+                    self.current_provider = LLMProvider::Ollama;
+                    let config = Configuration::new();
+                    let llm_config = config.load_llm_config("config/config.yaml")?;
+                    let mut llm_client = self.llm_client.lock().await;
+                    *llm_client = LLMClient::new(llm_config, LLMProvider::Ollama);
+                    print_colored_ln("Switched to Ollama server", Color::Green);
+                }
+                self.refresh_model_names().await;
+            }
+            "/models" => {
+                let llm_client = self.llm_client.lock().await;
+                let current = llm_client.current_model().to_string();
+                match llm_client.list_models().await {
+                    Ok(models) if models.is_empty() => {
+                        print_colored_ln("No models reported by the server", Color::Yellow);
+                    }
+                    Ok(models) => {
+                        if let Some(helper) = self.readline.helper_mut() {
+                            helper.set_models(models.clone());
+                        }
+                        print_colored_ln(
+                            &format!("\nAvailable models ({}):", self.current_provider.name()),
+                            Color::Cyan,
+                        );
+                        for model in &models {
+                            if *model == current {
+                                print_colored_ln(&format!("  * {} (current)", model), Color::Green);
+                            } else {
+                                println!("    {}", model);
+                            }
+                        }
+                        print_colored_ln("\nUse /model <name> to switch", Color::Yellow);
+                    }
+                    Err(e) => {
+                        print_colored_ln(&format!("Failed to list models: {}", e), Color::Red);
+                    }
+                }
+            }
+            "/model" => {
+                let mut llm_client = self.llm_client.lock().await;
+                if args.is_empty() {
+                    print_colored_ln(
+                        &format!("Current model: {}", llm_client.current_model()),
+                        Color::Cyan,
+                    );
+                    print_colored_ln("Usage: /model <name>", Color::Yellow);
+                    return Ok(());
+                }
+
+                if check_model(&llm_client, args).await {
+                    llm_client.set_model(args);
+                    print_colored_ln(&format!("Switched model to {}", args), Color::Green);
+                }
+            }
+            "/compare" => {
+                if args.is_empty() {
+                    print_colored_ln("Usage: /compare <message>", Color::Yellow);
+                    return Ok(());
+                }
+                self.compare_providers(args).await?;
+            }
+            "/voice" => {
+                print_colored_ln(
+                    "Starting voice recording... (Press Enter to stop)",
+                    Color::Yellow,
+                );
+                let whisper = WhisperServer::new()?;
+                whisper.start_recording().await?;
+
+                // Wait for Enter key
+                let mut input = String::new();
+                std::io::stdin().read_line(&mut input)?;
+
+                let text = whisper.stop_recording().await?;
+                print_colored_ln(&format!("Recognized text: {}", text), Color::Green);
+
+                // Process the recognized text
+                self.process_message(&text).await?;
+            }
+            "/rag-add" => {
+                print_colored_ln(
+                    "Enter document text (press Ctrl+D when done):",
+                    Color::Yellow,
+                );
+                let mut text = String::new();
+                std::io::stdin().read_to_string(&mut text)?;
+
+                let doc = Document {
+                    id: uuid::Uuid::new_v4().to_string(),
+                    text,
+                    metadata: Some(serde_json::json!({
+                        "added_at": chrono::Utc::now().to_rfc3339(),
+                        "source": "manual_input"
+                    })),
+                };
+                let mut rag = self.rag_server.lock().await;
+                rag.add_document(doc).await?;
+                print_colored_ln("Document added successfully!", Color::Green);
+            }
+            "/rag-add-file" => {
+                if args.is_empty() {
+                    print_colored_ln("Usage: /rag-add-file <path>", Color::Yellow);
+                    return Ok(());
+                }
+                let path = shellexpand::tilde(args).into_owned();
+                let mut rag = self.rag_server.lock().await;
+                match rag.add_file(Path::new(&path)).await {
+                    Ok(id) => print_colored_ln(
+                        &format!("File added successfully as document {}", id),
+                        Color::Green,
+                    ),
+                    Err(e) => print_colored_ln(&format!("Failed to add file: {:#}", e), Color::Red),
+                }
+            }
+            "/rag-add-url" => {
+                if args.is_empty() {
+                    print_colored_ln("Usage: /rag-add-url <url>", Color::Yellow);
+                    return Ok(());
+                }
+                // The page is opened and read by the puppeteer tools, so their
+                // tool policy applies
+                let opened = self
+                    .call_tool("puppeteer_navigate", &json!({ "url": args }))
+                    .await;
+                let page = match opened.is_error {
+                    false => self.call_tool("puppeteer_page_text", &json!({})).await,
+                    true => opened,
+                };
+                if page.is_error {
+                    print_colored_ln(
+                        &format!("Failed to read {}: {}", args, page.content),
+                        Color::Red,
+                    );
+                    return Ok(());
+                }
+                let text = page.content["text"].as_str().unwrap_or_default();
+                if text.trim().is_empty() {
+                    print_colored_ln(&format!("No text found on {}", args), Color::Yellow);
+                    return Ok(());
+                }
+
+                let doc = Document {
+                    id: uuid::Uuid::new_v4().to_string(),
+                    text: text.to_string(),
+                    metadata: Some(json!({
+                        "added_at": chrono::Utc::now().to_rfc3339(),
+                        "source": "web",
+                        "path": page.content["url"],
+                        "title": page.content["title"]
+                    })),
+                };
+                let id = doc.id.clone();
+                let mut rag = self.rag_server.lock().await;
+                rag.add_document(doc).await?;
+                print_colored_ln(
+                    &format!("Page added successfully as document {}", id),
+                    Color::Green,
+                );
+            }
+            "/rag-add-dir" => {
+                let mut parts = args.split_whitespace();
+                let dir = match parts.next() {
+                    Some(dir) => shellexpand::tilde(dir).into_owned(),
+                    None => {
+                        print_colored_ln("Usage: /rag-add-dir <path> [glob]", Color::Yellow);
+                        return Ok(());
+                    }
+                };
+                let glob = parts.next();
+
+                let mut rag = self.rag_server.lock().await;
+                let report = rag
+                    .ingest_directory(Path::new(&dir), glob, true, |progress| {
+                        let name = progress
+                            .path
+                            .file_name()
+                            .map(|n| n.to_string_lossy().into_owned())
+                            .unwrap_or_default();
+                        print_progress_bar(&name, progress.done, progress.total);
+                    })
+                    .await?;
+                println!();
+
+                print_colored_ln(
+                    &format!(
+                        "Added {} files, skipped {} unchanged, {} failed",
+                        report.added,
+                        report.skipped,
+                        report.failed.len()
+                    ),
+                    if report.failed.is_empty() {
+                        Color::Green
+                    } else {
+                        Color::Yellow
+                    },
+                );
+                for (path, error) in &report.failed {
+                    print_colored_ln(&format!("  {}: {}", path.display(), error), Color::Red);
+                }
+            }
+            "/rag-search" => {
+                print_colored_ln("Enter search query:", Color::Yellow);
+                let mut query = String::new();
+                std::io::stdin().read_line(&mut query)?;
+                query = query.trim().to_string();
+
+                print_colored_ln("Enter number of results (default: 5):", Color::Yellow);
+                let mut limit_str = String::new();
+                std::io::stdin().read_line(&mut limit_str)?;
+                let limit = limit_str.trim().parse::<u32>().unwrap_or(5);
+
+                let rag = self.rag_server.lock().await;
+                let results = rag.search_similar(&query, limit, None).await?;
+
+                print_colored_ln("\nFound similar documents:", Color::Green);
+                for (i, result) in results.iter().enumerate() {
+                    println!("\n--- Result {} (Score: {:.3}) ---", i + 1, result.score);
+                    println!("Document ID: {}", result.document_id);
+                    println!("Chunk Index: {}", result.chunk_index);
+                    println!("Source: {}", result.citation());
+                    if let (Some(start), Some(end)) = (result.char_start, result.char_end) {
+                        println!("Characters: {}-{}", start, end);
+                    }
+                    if let Some(metadata) = &result.metadata {
+                        println!("Metadata: {}", serde_json::to_string_pretty(metadata)?);
+                    }
+                    println!("Text: {}", result.text);
+                }
+            }
+            "/rag-info" => {
+                let rag_server = self.rag_server.lock().await;
+                print_colored_ln("\nRAG Database Information:", Color::Green);
+                let backend = rag_server.backend_name();
+                match rag_server.health().await {
+                    Ok(status) => println!("Backend: {} - {}", backend, status),
+                    Err(e) => {
+                        print_colored_ln(
+                            &format!("Backend: {} - unreachable: {:#}", backend, e),
+                            Color::Red,
+                        );
+                        return Ok(());
+                    }
+                }
+                let info = rag_server.get_collection_info().await?;
+                println!("Collection Name: {}", info["name"]);
+                println!("Vector Size: {}", info["vector_size"]);
+                println!("Distance Metric: {}", info["distance"]);
+                println!("Total Documents: {}", info["points_count"]);
+
+                // `/rag-info key=value ...` also counts the matching chunks
+                let filter: HashMap<String, Value> = args
+                    .split_whitespace()
+                    .filter_map(|pair| pair.split_once('='))
+                    .map(|(key, value)| (key.to_string(), Value::from(value)))
+                    .collect();
+                if !filter.is_empty() {
+                    let count = rag_server.count(Some(filter)).await?;
+                    println!("Matching Chunks ({}): {}", args, count);
+                }
+            }
+            "/rag-chat" => {
+                match args {
+                    "on" => self.rag_chat = true,
+                    "off" => self.rag_chat = false,
+                    "" => self.rag_chat = !self.rag_chat,
+                    _ => {
+                        print_colored_ln("Usage: /rag-chat [on|off]", Color::Yellow);
+                        return Ok(());
+                    }
+                }
+                print_colored_ln(
+                    &format!(
+                        "RAG chat mode is now {}",
+                        if self.rag_chat { "on" } else { "off" }
+                    ),
+                    Color::Green,
+                );
+            }
+            "/rag-list" => {
+                let mut numbers = args.split_whitespace().map(|n| n.parse::<usize>());
+                let offset = numbers.next().and_then(|n| n.ok()).unwrap_or(0);
+                let limit = numbers.next().and_then(|n| n.ok()).unwrap_or(20);
+
+                let documents = self
+                    .rag_server
+                    .lock()
+                    .await
+                    .list_documents(offset, limit)
+                    .await?;
+                if documents.is_empty() {
+                    print_colored_ln("No documents found", Color::Yellow);
+                } else {
+                    print_colored_ln("\nIngested documents:", Color::Green);
+                    for (i, document) in documents.iter().enumerate() {
+                        println!(
+                            "\n{}. {} ({} chunks)",
+                            offset + i + 1,
+                            document.document_id,
+                            document.chunk_count
+                        );
+                        if let Some(metadata) = &document.metadata {
+                            println!("   Metadata: {}", metadata);
+                        }
+                    }
+                    if documents.len() == limit {
+                        print_colored_ln(
+                            &format!("\nMore: /rag-list {} {}", offset + limit, limit),
+                            Color::Yellow,
+                        );
+                    }
+                }
+            }
+            "/vector-backup" => {
+                let dir = if args.is_empty() { "./backups" } else { args };
+                let dir = shellexpand::tilde(dir).into_owned();
+                let rag = self.rag_server.lock().await;
+                match rag.create_snapshot(Path::new(&dir)).await {
+                    Ok(path) => print_colored_ln(
+                        &format!("Snapshot saved to {}", path.display()),
+                        Color::Green,
+                    ),
+                    Err(e) => print_colored_ln(&format!("Backup failed: {:#}", e), Color::Red),
+                }
+            }
+            "/vector-restore" => {
+                if args.is_empty() {
+                    print_colored_ln("Usage: /vector-restore <snapshot>", Color::Yellow);
+                    return Ok(());
+                }
+                let path = shellexpand::tilde(args).into_owned();
+                let rag = self.rag_server.lock().await;
+                match rag.restore_snapshot(Path::new(&path)).await {
+                    Ok(()) => print_colored_ln(
+                        &format!("RAG collection restored from {}", path),
+                        Color::Green,
+                    ),
+                    Err(e) => print_colored_ln(&format!("Restore failed: {:#}", e), Color::Red),
+                }
+            }
+            _ => {
+                println!("Unknown command: {}", command);
+            }
+        }
+        Ok(())
+    }
+
+    /// Renders the prompt named in `args`, followed by its `key=value`
+    /// arguments, and sends its user messages as the next message. Its
+    /// other messages are added to the system messages of that turn.
+    async fn send_prompt(&mut self, args: &str) -> Result<()> {
+        let (name, arguments) = parse_prompt_command(args);
+        let mut found = None;
+        for (i, server) in self.servers.iter().enumerate() {
+            if let Some(prompt) = server
+                .list_prompts()
+                .await?
+                .into_iter()
+                .find(|prompt| prompt.name == name)
+            {
+                found = Some((i, prompt));
+                break;
+            }
+        }
+        let (i, prompt) = match found {
+            Some(found) => found,
+            None => {
+                print_colored_ln(
+                    &format!("Unknown prompt: {} (see /prompt for the list)", name),
+                    Color::Red,
+                );
+                return Ok(());
+            }
+        };
+        if let Some(missing) = missing_prompt_argument(&prompt, arguments.as_ref()) {
+            print_colored_ln(
+                &format!("Missing argument for {}: {}=<value>", name, missing),
+                Color::Red,
+            );
+            return Ok(());
+        }
+
+        let result = match self.servers[i].get_prompt(&name, arguments).await {
+            Ok(result) => result,
+            Err(e) => {
+                print_colored_ln(&format!("Failed to render {}: {:#}", name, e), Color::Red);
+                return Ok(());
+            }
+        };
+        let (context, message) = prompt_messages(&result);
+        self.prompt_context.extend(context);
+        if message.is_empty() {
+            print_colored_ln(
+                &format!("Prompt {} will be used with your next message", name),
+                Color::Green,
+            );
+            return Ok(());
+        }
+        println!("\n{}{}", colorize("@Human: ", Color::Blue), message);
+        self.process_message(&message).await
+    }
+
+    /// Processes a user message
+    /// Handles both regular messages and tool calls
+    pub async fn process_message(&mut self, input: &str) -> Result<()> {
+        // CTRL+K cancels the in-flight request and returns to the prompt
+        let cancel = CancellationToken::new();
+        self.busy = Some(Busy::start(cancel.clone()));
+
+        let result = self.process_llm_response(input, &cancel).await;
+
+        if let Some(busy) = self.busy.take() {
+            busy.stop().await;
+        }
+
+        match result {
+            Ok(response) => {
+                clear_line();
+                print!("{}", colorize("@AI-BOT: ", Color::Yellow));
+                match &self.markdown {
+                    Some(renderer) => {
+                        let width = crossterm::terminal::size()
+                            .map(|(width, _)| width as usize)
+                            .unwrap_or(80);
+                        print!("\n{}", renderer.render(&response, width));
+                        io::stdout().flush()?;
+                    }
+                    None => {
+                        io::stdout().flush()?;
+                        typing_animation(&response, 20);
+                    }
+                }
+            }
+            Err(_) if cancel.is_cancelled() => {
+                clear_line();
+                print_colored_ln("Inference stopped", Color::Yellow);
+            }
+            Err(e) => {
+                clear_line();
+                print_colored_ln(&format!("Error: {}", e), Color::Red);
+                match e.downcast_ref::<LLMError>() {
+                    Some(LLMError::AuthFailed(_)) => print_colored_ln(
+                        "Check the API key of the current provider in config.yaml or the environment",
+                        Color::Yellow,
+                    ),
+                    Some(LLMError::ModelNotFound(_)) => print_colored_ln(
+                        "Use /models to list the available models and /model <name> to switch",
+                        Color::Yellow,
+                    ),
+                    Some(LLMError::RateLimited { .. }) => print_colored_ln(
+                        "Rate limit reached, please wait before sending another message",
+                        Color::Yellow,
+                    ),
+                    _ => {}
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Starts the chat session
+    /// Main loop for handling user input and commands
+    pub async fn start(&mut self) -> Result<()> {
+        // Display menu (only once)
+        print_menu(self.current_provider.name()).await;
+
+        // Display welcome message from prompts
+        print_colored_ln(&self.prompts.common_prompts.welcome, Color::Yellow);
+        let model_name = self.llm_client.lock().await.current_model().to_string();
+        print_colored_ln(
+            &format!(
+                "Current AI Server: {} ({})",
+                self.current_provider.name(),
+                model_name
+            ),
+            Color::Cyan,
+        );
+        self.check_provider_ready().await;
+        self.refresh_model_names().await;
+        println!();
+
+        while self.running {
+            let prompt = format!("\n{}", colorize("@Human: ", Color::Blue));
+            match self.readline.readline(&prompt) {
+                Ok(line) => {
+                    if line.trim().is_empty() {
+                        continue;
+                    }
+                    // Restart servers that crashed since the last turn
+                    self.servers.supervise().await;
+                    // Save to history
+                    self.readline.add_history_entry(line.trim());
+                    if let Err(e) = self.readline.save_history(".mcp_history") {
+                        error!("Failed to save history: {}", e);
+                    }
+
+                    // A quoted message is sent as it is, even if it starts
+                    // with a slash
+                    match quoted_message(&line) {
+                        Some(message) if message.trim().is_empty() => {}
+                        Some(message) => self.process_message(message).await?,
+                        None if line.starts_with('/') => self.process_command(line.trim()).await?,
+                        None => self.process_message(line.trim()).await?,
+                    }
+                }
+                Err(rustyline::error::ReadlineError::Interrupted) => {
+                    // CTRL+C pressed
+                    print_colored_ln("\nExiting program...", Color::Yellow);
+                    self.running = false;
+                }
+                Err(rustyline::error::ReadlineError::Eof) => {
+                    // CTRL+D pressed
+                    print_colored_ln("\nExiting program...", Color::Yellow);
+                    self.running = false;
+                }
+                Err(e) => {
+                    error!("Error reading line: {}", e);
+                    break;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Shows the health of the active provider and preloads its model
+    async fn check_provider_ready(&self) {
+        let llm_client = self.llm_client.lock().await;
+        let health = llm_client.health_check().await;
+        print_provider_health(&health);
+
+        if matches!(&health, Ok(h) if h.model_available) {
+            if let Err(e) = llm_client.warmup().await {
+                warn!("Model warmup failed: {}", e);
+            }
+        }
+    }
+
+    /// Lets the prompt complete the models of the current provider
+    async fn refresh_model_names(&mut self) {
+        let models = self.llm_client.lock().await.list_models().await;
+        match models {
+            Ok(models) => {
+                if let Some(helper) = self.readline.helper_mut() {
+                    helper.set_models(models);
+                }
+            }
+            Err(e) => debug!("No model names to complete: {}", e),
+        }
+    }
+
+    /// Initializes all servers from configuration
+    pub async fn initialize_servers(&self) -> Result<Value> {
+        let config = Configuration::new();
+        let server_config = config.load_config("config/servers_config.json")?;
+
+        // Validate configuration format
+        if let Some(_servers) = server_config.get("mcpServers") {
+            Ok(server_config)
+        } else {
+            Err(Error::msg(
+                "Invalid server configuration: missing mcpServers section",
+            ))
+        }
+    }
+
+    fn show_help(&self) {
+        println!("\nAvailable commands:");
+        println!("  /help     - Show this help message");
+        println!("  /clear    - Clear the chat history");
+        println!("  /usage    - Show token usage and estimated cost, with the largest turns");
+        println!("  /about    - Show information about MCP-ChatBot");
+        println!("  /exit     - Exit the program");
+        println!("  /servers  - Show the MCP servers and whether they are up");
+        println!("  /tools    - Show available tools");
+        println!("  /resources - Show available resources");
+        println!("  /prompt [name] [key=value ...] - List the servers' prompts or send one");
+        println!("  /audit [tool=..] [session=current] [status=error] [since=..] [limit=..] - Show recorded tool calls");
+        println!("  /trace    - Show the tool calls and answer of the last message");
+        println!("  /sessions [number] - List saved sessions");
+        println!("  /resume <id> - Continue a saved session");
+        println!("  /export <path> - Save the session as Markdown (.md) or JSONL (.jsonl)");
+        println!("  /import <path> - Replay a JSONL transcript into a new session");
+        println!("  /history-search <query> - Search past conversations");
+        println!("  /undo     - Take back the last exchange");
+        println!("  /retry [model] - Ask the last message again, optionally of another model");
+        println!("  /models   - List models available on the current AI server");
+        println!("  /model <name> - Switch to another model");
+        println!("  /compare <message> - Compare answers of all configured providers");
+        println!("  /voice    - Start voice input (press Enter to stop recording)");
+        println!("  /rag-add   - Add a new document to RAG database");
+        println!("  /rag-add-file <path> - Add a .md/.txt/.pdf/.html file to RAG database");
+        println!("  /rag-add-url <url> - Add the text of a web page to RAG database");
+        println!("  /rag-add-dir <path> [glob] - Add all supported files in a directory");
+        println!("  /rag-search - Search for similar documents");
+        println!("  /rag-info [key=value ...] - Show RAG database information and filtered counts");
+        println!("  /rag-list [offset] [limit] - List ingested documents");
+        println!("  /rag-chat [on|off] - Answer using context from the RAG database");
+        println!("  /vector-backup [dir] - Save a snapshot of the RAG collection");
+        println!("  /vector-restore <snapshot> - Replace the RAG collection with a snapshot");
+        println!("\nYou can also use these commands in your messages:");
+        println!("  /debug    - Toggle debug mode");
+        println!("  /system set <text> | show | reset - Change the system prompt of this session");
+        println!("\nType your message and press Enter to send.");
+        println!("Alt+Enter starts a new line, and a message between \"\"\" and \"\"\" may span several lines.");
+    }
+}
+
+/// The tools of all `servers`, in server order
+async fn list_all_tools(servers: &[Server]) -> Result<Vec<ToolSchema>> {
+    let mut tools = Vec::new();
+    for server in servers {
+        tools.extend(server.list_tools().await?);
+    }
+    Ok(tools)
+}
+
+/// Draws the progress reports of the tool `name` until the call is done,
+/// as a bar when the total is known and as a running count otherwise
+async fn show_progress(name: String, mut events: mpsc::UnboundedReceiver<ProgressEvent>) {
+    let mut shown = false;
+    while let Some(event) = events.recv().await {
+        if !is_terminal() {
+            continue;
+        }
+        let label = match &event.message {
+            Some(message) => format!("{}: {}", name, message),
+            None => name.clone(),
+        };
+        match event.total {
+            Some(total) if total > 0.0 => print_progress_bar(
+                &label,
+                event.progress.clamp(0.0, total) as usize,
+                total as usize,
+            ),
+            _ => {
+                clear_line();
+                print!("{} ({})", label, event.progress);
+                io::stdout().flush().unwrap();
+            }
+        }
+        shown = true;
+    }
+    if shown {
+        println!();
+    }
+}
+
+/// Whether the provider of `llm_client` offers the model `name`, telling
+/// the user if not. A model that cannot be checked is assumed to exist.
+async fn check_model(llm_client: &LLMClient, name: &str) -> bool {
+    match llm_client.list_models().await {
+        Ok(models) if !models.iter().any(|m| model_matches(m, name)) => {
+            print_colored_ln(
+                &format!("Model '{}' is not available, see /models", name),
+                Color::Red,
+            );
+            false
+        }
+        Ok(_) => true,
+        Err(e) => {
+            warn!("Could not verify model '{}': {}", name, e);
+            true
+        }
+    }
+}
+
+/// The thinking spinner and the CTRL+K listener of a message being
+/// answered. The listener holds the terminal in raw mode, so nothing may be
+/// read from it until they stop.
+struct Busy {
+    cancel: CancellationToken,
+    stop_signal: Arc<Mutex<bool>>,
+    thinking: JoinHandle<()>,
+    listener_done: CancellationToken,
+    listener: JoinHandle<()>,
+}
+
+impl Busy {
+    /// Starts the spinner and a listener cancelling `cancel` on CTRL+K
+    fn start(cancel: CancellationToken) -> Self {
+        let stop_signal = Arc::new(Mutex::new(false));
+        let thinking = tokio::spawn(print_bot_thinking_continuous(Arc::clone(&stop_signal)));
+        let listener_done = CancellationToken::new();
+        let listener = spawn_cancel_key_listener(cancel.clone(), listener_done.clone());
+        Self {
+            cancel,
+            stop_signal,
+            thinking,
+            listener_done,
+            listener,
+        }
+    }
+
+    /// Stops both, once the terminal has left raw mode and the spinner's
+    /// line is cleared, and returns the token the listener cancels
+    async fn stop(self) -> CancellationToken {
+        // Stop the key listener first so the terminal leaves raw mode
+        self.listener_done.cancel();
+        if let Err(e) = self.listener.await {
+            warn!("The CTRL+K listener failed: {}", e);
+        }
+        *self.stop_signal.lock().await = true;
+        if let Err(e) = self.thinking.await {
+            warn!("The thinking animation failed: {}", e);
+        }
+        self.cancel
+    }
+}
+
+/// The names of `tools`, for completion
+fn tool_names(tools: &[ToolSchema]) -> Vec<String> {
+    tools.iter().map(|tool| tool.name.clone()).collect()
+}
+
+/// The user's message that started `turn`
+fn turn_input(turn: &[Value]) -> String {
+    turn.iter()
+        .find(|message| message["role"] == "user")
+        .and_then(|message| message["content"].as_str())
+        .unwrap_or_default()
+        .to_string()
+}
+
+/// Removes the oldest message that is neither a system message nor the
+/// latest one. Returns false when there is nothing left to drop.
+fn trim_oldest_message(messages: &mut Vec<Value>) -> bool {
+    let last = messages.len().saturating_sub(1);
+    match messages
+        .iter()
+        .take(last)
+        .position(|m| m["role"].as_str() != Some("system"))
+    {
+        Some(index) => {
+            messages.remove(index);
+            true
+        }
+        None => false,
+    }
+}
+
+/// The prompt name and `key=value` arguments of a `/prompt` command
+fn parse_prompt_command(args: &str) -> (String, Option<Value>) {
+    let mut parts = args.split_whitespace();
+    let name = parts.next().unwrap_or_default().to_string();
+    let arguments: serde_json::Map<String, Value> = parts
+        .filter_map(|pair| pair.split_once('='))
+        .map(|(key, value)| (key.to_string(), Value::from(value)))
+        .collect();
+    if arguments.is_empty() {
+        (name, None)
+    } else {
+        (name, Some(Value::Object(arguments)))
+    }
+}
+
+/// The first required argument of `prompt` not given in `arguments`
+fn missing_prompt_argument<'a>(
+    prompt: &'a PromptSchema,
+    arguments: Option<&Value>,
+) -> Option<&'a str> {
+    prompt
+        .arguments
+        .iter()
+        .filter(|argument| argument.required)
+        .find(|argument| arguments.and_then(|a| a.get(&argument.name)).is_none())
+        .map(|argument| argument.name.as_str())
+}
+
+/// The texts of a rendered prompt's non-user messages, and its user
+/// messages joined into one
+fn prompt_messages(result: &GetPromptResult) -> (Vec<String>, String) {
+    let mut context = Vec::new();
+    let mut user = Vec::new();
+    for message in &result.messages {
+        let text = match &message.content {
+            Content::Text { text } => text.clone(),
+            _ => continue,
+        };
+        if message.role == "user" {
+            user.push(text);
+        } else {
+            context.push(text);
+        }
+    }
+    (context, user.join("\n\n"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::protocol::{PromptArgument, PromptMessage};
+
+    #[test]
+    fn test_trim_oldest_message() {
+        let mut messages = vec![
+            json!({"role": "system", "content": "rules"}),
+            json!({"role": "user", "content": "first"}),
+            json!({"role": "assistant", "content": "answer"}),
+            json!({"role": "user", "content": "second"}),
+        ];
+
+        assert!(trim_oldest_message(&mut messages));
+        assert_eq!(messages.len(), 3);
+        assert_eq!(messages[0]["role"], "system");
+        assert_eq!(messages[1]["content"], "answer");
+
+        assert!(trim_oldest_message(&mut messages));
+        assert!(!trim_oldest_message(&mut messages));
+        assert_eq!(messages[1]["content"], "second");
+    }
+
+    #[test]
+    fn test_prompt_command() {
+        let (name, arguments) = parse_prompt_command("code_review language=rust  strict=yes");
+        assert_eq!(name, "code_review");
+        assert_eq!(
+            arguments,
+            Some(json!({ "language": "rust", "strict": "yes" }))
+        );
+        assert_eq!(
+            parse_prompt_command("summary"),
+            ("summary".to_string(), None)
+        );
+
+        let prompt = PromptSchema {
+            name: "code_review".to_string(),
+            description: "Reviews code".to_string(),
+            arguments: vec![PromptArgument {
+                name: "code".to_string(),
+                description: "Code to review".to_string(),
+                required: true,
+                schema: json!({ "type": "string" }),
+            }],
+        };
+        assert_eq!(missing_prompt_argument(&prompt, None), Some("code"));
+        assert_eq!(
+            missing_prompt_argument(&prompt, arguments.as_ref()),
+            Some("code")
+        );
+        assert_eq!(
+            missing_prompt_argument(&prompt, Some(&json!({ "code": "fn main() {}" }))),
+            None
+        );
+    }
+
+    #[test]
+    fn test_prompt_messages() {
+        let message = |role: &str, text: &str| PromptMessage {
+            role: role.to_string(),
+            content: Content::Text {
+                text: text.to_string(),
+            },
+        };
+        let result = GetPromptResult {
+            description: None,
+            messages: vec![
+                message("assistant", "You review Rust code."),
+                message("user", "Review this:"),
+                message("user", "fn main() {}"),
+            ],
+        };
+        let (context, text) = prompt_messages(&result);
+        assert_eq!(context, vec!["You review Rust code."]);
+        assert_eq!(text, "Review this:\n\nfn main() {}");
+    }
+}
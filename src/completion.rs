@@ -0,0 +1,357 @@
+//! Completion Module: Tab completion, hints and multi-line input at the
+//! chat prompt
+//!
+//! This module provides:
+//! - `ChatHelper`: the rustyline helper of the chat prompt
+//! - Completion of slash commands, of model names after `/model` and
+//!   `/retry`, of tool names after `/audit tool=`, of the choices of
+//!   commands such as `/system` and of file paths after commands taking a
+//!   path, such as `/rag-add-file` and `/export`
+//! - Hints: once a prefix can only be one command, the rest of its name and
+//!   its arguments, dimmed after the cursor
+//! - Multi-line messages: input opened with `"""` goes on over several
+//!   lines until the closing `"""`, and `quoted_message` takes the quotes
+//!   off
+//!
+//! The chat session keeps the tool and model names up to date: the tools
+//! whenever they change, the models whenever they are listed.
+//!
+//! Author: arkSong <arksong2018@gmail.com>
+//! Version: 1.0.0
+//! License: MIT
+
+use rustyline::completion::{Completer, FilenameCompleter, Pair};
+use rustyline::highlight::Highlighter;
+use rustyline::hint::Hinter;
+use rustyline::validate::{ValidationContext, ValidationResult, Validator};
+use rustyline::{Context, Helper};
+use std::borrow::Cow;
+
+use crate::utils::{colorize, Color};
+
+/// What the arguments of a command are completed with
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Argument {
+    /// Nothing
+    None,
+    /// File paths
+    Path,
+    /// Model names of the current provider
+    Model,
+    /// The filters of `/audit`, tool names after `tool=`
+    AuditFilter,
+    /// One of these words
+    Choice(&'static [&'static str]),
+}
+
+/// Opens and closes a message of several lines
+pub const MULTI_LINE_QUOTE: &str = "\"\"\"";
+
+/// Filters `/audit` takes
+const AUDIT_FILTERS: &[&str] = &["tool=", "session=", "status=", "since=", "limit="];
+
+/// The commands of the chat prompt, their arguments as hinted, and what
+/// the arguments are completed with
+const COMMANDS: &[(&str, &str, Argument)] = &[
+    ("/help", "", Argument::None),
+    ("/clear", "", Argument::None),
+    ("/usage", "", Argument::None),
+    ("/about", "", Argument::None),
+    ("/exit", "", Argument::None),
+    ("/servers", "", Argument::None),
+    ("/mcp-servers", "", Argument::None),
+    ("/tools", "", Argument::None),
+    ("/resources", "", Argument::None),
+    ("/prompt", " [name] [key=value ...]", Argument::None),
+    ("/sessions", " [number]", Argument::None),
+    ("/resume", " <session id>", Argument::None),
+    ("/history-search", " <query>", Argument::None),
+    ("/export", " <path.md|path.jsonl>", Argument::Path),
+    ("/import", " <path.jsonl>", Argument::Path),
+    (
+        "/system",
+        " set <text> | show | reset",
+        Argument::Choice(&["set", "show", "reset"]),
+    ),
+    ("/undo", "", Argument::None),
+    ("/retry", " [model]", Argument::Model),
+    ("/trace", "", Argument::None),
+    (
+        "/audit",
+        " [tool=<name>] [session=current|<id>] [status=success|error] [since=<date>] [limit=<n>]",
+        Argument::AuditFilter,
+    ),
+    ("/debug", "", Argument::None),
+    ("/ai", "", Argument::None),
+    ("/models", "", Argument::None),
+    ("/model", " <name>", Argument::Model),
+    ("/compare", " <message>", Argument::None),
+    ("/voice", "", Argument::None),
+    ("/rag-add", "", Argument::None),
+    ("/rag-add-file", " <path>", Argument::Path),
+    ("/rag-add-url", " <url>", Argument::None),
+    ("/rag-add-dir", " <path> [glob]", Argument::Path),
+    ("/rag-search", "", Argument::None),
+    ("/rag-info", " [key=value ...]", Argument::None),
+    ("/rag-chat", " [on|off]", Argument::Choice(&["on", "off"])),
+    ("/rag-list", " [offset] [limit]", Argument::None),
+    ("/vector-backup", " [directory]", Argument::Path),
+    ("/vector-restore", " <snapshot>", Argument::Path),
+];
+
+/// Completes and hints the input at the chat prompt
+pub struct ChatHelper {
+    files: FilenameCompleter,
+    tools: Vec<String>,
+    models: Vec<String>,
+}
+
+impl Default for ChatHelper {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl ChatHelper {
+    pub fn new() -> Self {
+        Self {
+            files: FilenameCompleter::new(),
+            tools: Vec::new(),
+            models: Vec::new(),
+        }
+    }
+
+    /// Sets the tool names completed after `/audit tool=`
+    pub fn set_tools(&mut self, tools: Vec<String>) {
+        self.tools = tools;
+    }
+
+    /// Sets the model names completed after `/model` and `/retry`
+    pub fn set_models(&mut self, models: Vec<String>) {
+        self.models = models;
+    }
+
+    /// Where the word before the cursor at the end of `line` starts and
+    /// what it may be completed to, or `None` if it is a file path
+    fn candidates(&self, line: &str) -> Option<(usize, Vec<String>)> {
+        if !line.starts_with('/') {
+            return Some((line.len(), Vec::new()));
+        }
+        let end = match line.find(char::is_whitespace) {
+            Some(end) => end,
+            None => {
+                let names = COMMANDS.iter().map(|(name, _, _)| *name);
+                return Some((0, matching(line, names)));
+            }
+        };
+        let argument = match COMMANDS.iter().find(|(name, _, _)| *name == &line[..end]) {
+            Some((_, _, argument)) => *argument,
+            None => Argument::None,
+        };
+        let start = line
+            .rfind(char::is_whitespace)
+            .map(|i| i + 1)
+            .unwrap_or_default();
+        let word = &line[start..];
+        let candidates = match argument {
+            Argument::None => Vec::new(),
+            Argument::Path => return None,
+            Argument::Model => matching(word, self.models.iter().map(String::as_str)),
+            Argument::Choice(choices) => matching(word, choices.iter().copied()),
+            Argument::AuditFilter => match word.strip_prefix("tool=") {
+                Some(tool) => {
+                    let tools = matching(tool, self.tools.iter().map(String::as_str));
+                    return Some((start + "tool=".len(), tools));
+                }
+                None => matching(word, AUDIT_FILTERS.iter().copied()),
+            },
+        };
+        Some((start, candidates))
+    }
+}
+
+/// The names starting with `prefix`, sorted and without repeats
+fn matching<'a>(prefix: &str, names: impl Iterator<Item = &'a str>) -> Vec<String> {
+    let mut names: Vec<String> = names
+        .filter(|name| name.starts_with(prefix))
+        .map(str::to_string)
+        .collect();
+    names.sort();
+    names.dedup();
+    names
+}
+
+/// What to show after `line`: the arguments of the command it names, or
+/// the rest of the only command it starts and its arguments
+fn command_hint(line: &str) -> Option<String> {
+    if !line.starts_with('/') || line.contains(char::is_whitespace) {
+        return None;
+    }
+    let hint = match COMMANDS.iter().find(|(name, _, _)| *name == line) {
+        Some((_, arguments, _)) => arguments.to_string(),
+        None => {
+            let mut commands = COMMANDS
+                .iter()
+                .filter(|(name, _, _)| name.starts_with(line));
+            let (name, arguments, _) = commands.next()?;
+            if commands.next().is_some() {
+                return None;
+            }
+            format!("{}{}", &name[line.len()..], arguments)
+        }
+    };
+    if hint.is_empty() {
+        None
+    } else {
+        Some(hint)
+    }
+}
+
+impl Completer for ChatHelper {
+    type Candidate = Pair;
+
+    fn complete(
+        &self,
+        line: &str,
+        pos: usize,
+        ctx: &Context<'_>,
+    ) -> rustyline::Result<(usize, Vec<Pair>)> {
+        match self.candidates(&line[..pos]) {
+            Some((start, candidates)) => Ok((
+                start,
+                candidates
+                    .into_iter()
+                    .map(|candidate| Pair {
+                        display: candidate.clone(),
+                        replacement: candidate,
+                    })
+                    .collect(),
+            )),
+            None => self.files.complete(line, pos, ctx),
+        }
+    }
+}
+
+impl Hinter for ChatHelper {
+    type Hint = String;
+
+    fn hint(&self, line: &str, pos: usize, _ctx: &Context<'_>) -> Option<String> {
+        if pos < line.len() {
+            return None;
+        }
+        command_hint(line)
+    }
+}
+
+impl Highlighter for ChatHelper {
+    fn highlight_hint<'h>(&self, hint: &'h str) -> Cow<'h, str> {
+        Cow::Owned(colorize(hint, Color::Gray))
+    }
+}
+
+/// Whether `input` opens a multi-line message it does not close yet
+fn is_open_quote(input: &str) -> bool {
+    input.trim_start().starts_with(MULTI_LINE_QUOTE)
+        && input.matches(MULTI_LINE_QUOTE).count() % 2 == 1
+}
+
+/// The message between the triple quotes of `input`, without the line
+/// breaks next to them, if `input` is quoted
+pub fn quoted_message(input: &str) -> Option<&str> {
+    let message = input
+        .trim()
+        .strip_prefix(MULTI_LINE_QUOTE)?
+        .strip_suffix(MULTI_LINE_QUOTE)?;
+    Some(message.trim_matches(|c| c == '\n' || c == '\r'))
+}
+
+impl Validator for ChatHelper {
+    /// Keeps Enter adding lines while a `"""` is open
+    fn validate(&self, ctx: &mut ValidationContext) -> rustyline::Result<ValidationResult> {
+        if is_open_quote(ctx.input()) {
+            Ok(ValidationResult::Incomplete)
+        } else {
+            Ok(ValidationResult::Valid(None))
+        }
+    }
+}
+
+impl Helper for ChatHelper {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn helper() -> ChatHelper {
+        let mut helper = ChatHelper::new();
+        helper.set_tools(vec!["file_read".to_string(), "memory_get".to_string()]);
+        helper.set_models(vec!["llama3.2:latest".to_string(), "qwen2.5".to_string()]);
+        helper
+    }
+
+    #[test]
+    fn test_complete_commands_and_arguments() {
+        let helper = helper();
+        let (start, commands) = helper.candidates("/rag-add").unwrap();
+        assert_eq!(start, 0);
+        assert_eq!(
+            commands,
+            vec!["/rag-add", "/rag-add-dir", "/rag-add-file", "/rag-add-url"]
+        );
+        assert_eq!(
+            helper.candidates("/res").unwrap().1,
+            vec!["/resources", "/resume"]
+        );
+        assert!(helper.candidates("/nothing").unwrap().1.is_empty());
+
+        assert_eq!(
+            helper.candidates("/model ll").unwrap(),
+            (7, vec!["llama3.2:latest".to_string()])
+        );
+        assert_eq!(helper.candidates("/retry ").unwrap().1.len(), 2);
+        assert_eq!(
+            helper.candidates("/system s").unwrap().1,
+            vec!["set", "show"]
+        );
+        assert_eq!(
+            helper.candidates("/audit status=error tool=me").unwrap(),
+            (25, vec!["memory_get".to_string()])
+        );
+        assert_eq!(helper.candidates("/audit li").unwrap().1, vec!["limit="]);
+
+        // Paths go to the file completer, messages are not completed
+        assert!(helper.candidates("/rag-add-file src/").is_none());
+        assert!(helper.candidates("/export ").is_none());
+        assert!(helper.candidates("What is in /tmp").unwrap().1.is_empty());
+        assert!(helper.candidates("/clear ").unwrap().1.is_empty());
+    }
+
+    #[test]
+    fn test_command_hint() {
+        assert_eq!(command_hint("/rag-add-f").as_deref(), Some("ile <path>"));
+        assert_eq!(command_hint("/model").as_deref(), Some(" <name>"));
+        assert_eq!(command_hint("/hi").as_deref(), Some("story-search <query>"));
+        assert_eq!(command_hint("/rag-add"), None);
+        assert_eq!(command_hint("/r"), None);
+        assert_eq!(command_hint("/help"), None);
+        assert_eq!(command_hint("/model llama"), None);
+        assert_eq!(command_hint("hello"), None);
+    }
+
+    #[test]
+    fn test_multi_line_message() {
+        assert!(is_open_quote("\"\"\""));
+        assert!(is_open_quote("  \"\"\"fn main() {\n    println!(\"hi\");"));
+        assert!(!is_open_quote("\"\"\"fn main() {}\n\"\"\""));
+        assert!(!is_open_quote("Explain \"\"\" in Python"));
+        assert!(!is_open_quote("hello"));
+
+        assert_eq!(
+            quoted_message("\"\"\"\nfn main() {}\n\n  // done\n\"\"\""),
+            Some("fn main() {}\n\n  // done")
+        );
+        assert_eq!(quoted_message("\"\"\"one line\"\"\"  "), Some("one line"));
+        assert_eq!(quoted_message("\"\"\""), None);
+        assert_eq!(quoted_message("hello"), None);
+    }
+}
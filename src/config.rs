@@ -1,11 +1,23 @@
+use crate::agent::AgentConfig;
+use crate::audit_log::AuditConfig;
+use crate::conversation::ConversationConfig;
 use crate::llm_client::LLMConfig;
+use crate::long_term_memory::LongTermMemoryConfig;
+use crate::markdown::MarkdownConfig;
+use crate::metrics::MetricsConfig;
+use crate::rag_server::RagConfig;
+use crate::server_manager::SupervisorConfig;
+use crate::tool_selection::ToolSelectionConfig;
+use crate::utils::DisplayConfig;
 use anyhow::Result;
+use dotenv::dotenv;
 use log::{debug, warn};
 use serde::{Deserialize, Serialize};
 use serde_json;
 use serde_json::Value;
 use serde_yaml;
 use std::collections::HashMap;
+use std::env;
 use std::fs;
 use std::path::Path;
 use std::sync::Arc;
@@ -16,8 +28,13 @@ pub struct Configuration {
 }
 
 impl Configuration {
+    /// Creates a new Configuration instance
+    /// Loads environment variables from .env file
     pub fn new() -> Self {
-        Self { api_key: None }
+        dotenv().ok(); // Load .env file if it exists
+        Self {
+            api_key: env::var("LLM_API_KEY").ok(), // Get API key from environment
+        }
     }
 
     pub fn load_config(&self, config_path: &str) -> anyhow::Result<serde_json::Value> {
@@ -49,6 +66,178 @@ impl Configuration {
         debug!("Loaded LLM configuration: {:?}", config);
         Ok(config)
     }
+
+    /// Loads the `rag` section of the YAML configuration,
+    /// falling back to the defaults when the section is missing
+    pub fn load_rag_config(&self, config_path: &str) -> anyhow::Result<RagConfig> {
+        let config_str = std::fs::read_to_string(config_path)?;
+        let config: serde_yaml::Value = serde_yaml::from_str(&config_str)?;
+
+        let rag_config = match config.get("rag") {
+            Some(section) => serde_yaml::from_value(section.clone())?,
+            None => {
+                debug!("No rag section in {}, using defaults", config_path);
+                RagConfig::default()
+            }
+        };
+        Ok(rag_config)
+    }
+
+    /// Loads the `audit` section of the YAML configuration, falling back to
+    /// the defaults when the section is missing
+    pub fn load_audit_config(&self, config_path: &str) -> anyhow::Result<AuditConfig> {
+        let config_str = std::fs::read_to_string(config_path)?;
+        let config: serde_yaml::Value = serde_yaml::from_str(&config_str)?;
+
+        match config.get("audit") {
+            Some(section) => Ok(serde_yaml::from_value(section.clone())?),
+            None => {
+                debug!("No audit section in {}, using defaults", config_path);
+                Ok(AuditConfig::default())
+            }
+        }
+    }
+
+    /// Loads the `metrics` section of the YAML configuration, falling back
+    /// to the defaults, which serve no endpoint, when the section is missing
+    pub fn load_metrics_config(&self, config_path: &str) -> anyhow::Result<MetricsConfig> {
+        let config_str = std::fs::read_to_string(config_path)?;
+        let config: serde_yaml::Value = serde_yaml::from_str(&config_str)?;
+
+        match config.get("metrics") {
+            Some(section) => Ok(serde_yaml::from_value(section.clone())?),
+            None => {
+                debug!("No metrics section in {}, using defaults", config_path);
+                Ok(MetricsConfig::default())
+            }
+        }
+    }
+
+    /// Loads the `supervisor` section of the YAML configuration, falling
+    /// back to the defaults when the section is missing
+    pub fn load_supervisor_config(&self, config_path: &str) -> anyhow::Result<SupervisorConfig> {
+        let config_str = std::fs::read_to_string(config_path)?;
+        let config: serde_yaml::Value = serde_yaml::from_str(&config_str)?;
+
+        match config.get("supervisor") {
+            Some(section) => Ok(serde_yaml::from_value(section.clone())?),
+            None => {
+                debug!("No supervisor section in {}, using defaults", config_path);
+                Ok(SupervisorConfig::default())
+            }
+        }
+    }
+
+    /// Loads the `conversation` section of the YAML configuration, falling
+    /// back to the defaults when the section is missing
+    pub fn load_conversation_config(
+        &self,
+        config_path: &str,
+    ) -> anyhow::Result<ConversationConfig> {
+        let config_str = std::fs::read_to_string(config_path)?;
+        let config: serde_yaml::Value = serde_yaml::from_str(&config_str)?;
+
+        match config.get("conversation") {
+            Some(section) => Ok(serde_yaml::from_value(section.clone())?),
+            None => {
+                debug!("No conversation section in {}, using defaults", config_path);
+                Ok(ConversationConfig::default())
+            }
+        }
+    }
+
+    /// Loads the `long_term_memory` section of the YAML configuration,
+    /// falling back to the defaults when the section is missing
+    pub fn load_long_term_memory_config(
+        &self,
+        config_path: &str,
+    ) -> anyhow::Result<LongTermMemoryConfig> {
+        let config_str = std::fs::read_to_string(config_path)?;
+        let config: serde_yaml::Value = serde_yaml::from_str(&config_str)?;
+
+        match config.get("long_term_memory") {
+            Some(section) => Ok(serde_yaml::from_value(section.clone())?),
+            None => {
+                debug!(
+                    "No long_term_memory section in {}, using defaults",
+                    config_path
+                );
+                Ok(LongTermMemoryConfig::default())
+            }
+        }
+    }
+
+    /// Loads the `markdown` section of the YAML configuration, falling back
+    /// to the defaults when the section is missing
+    pub fn load_markdown_config(&self, config_path: &str) -> anyhow::Result<MarkdownConfig> {
+        let config_str = std::fs::read_to_string(config_path)?;
+        let config: serde_yaml::Value = serde_yaml::from_str(&config_str)?;
+
+        match config.get("markdown") {
+            Some(section) => Ok(serde_yaml::from_value(section.clone())?),
+            None => {
+                debug!("No markdown section in {}, using defaults", config_path);
+                Ok(MarkdownConfig::default())
+            }
+        }
+    }
+
+    /// Loads the `display` section of the YAML configuration, falling back
+    /// to the defaults when the section is missing
+    pub fn load_display_config(&self, config_path: &str) -> anyhow::Result<DisplayConfig> {
+        let config_str = std::fs::read_to_string(config_path)?;
+        let config: serde_yaml::Value = serde_yaml::from_str(&config_str)?;
+
+        match config.get("display") {
+            Some(section) => Ok(serde_yaml::from_value(section.clone())?),
+            None => {
+                debug!("No display section in {}, using defaults", config_path);
+                Ok(DisplayConfig::default())
+            }
+        }
+    }
+
+    /// Loads the `agent` section of the YAML configuration, falling back to
+    /// the defaults when the section is missing
+    pub fn load_agent_config(&self, config_path: &str) -> anyhow::Result<AgentConfig> {
+        let config_str = std::fs::read_to_string(config_path)?;
+        let config: serde_yaml::Value = serde_yaml::from_str(&config_str)?;
+
+        match config.get("agent") {
+            Some(section) => Ok(serde_yaml::from_value(section.clone())?),
+            None => {
+                debug!("No agent section in {}, using defaults", config_path);
+                Ok(AgentConfig::default())
+            }
+        }
+    }
+
+    /// Loads the `tool_selection` section of the YAML configuration,
+    /// falling back to the defaults when the section is missing
+    pub fn load_tool_selection_config(
+        &self,
+        config_path: &str,
+    ) -> anyhow::Result<ToolSelectionConfig> {
+        let config_str = std::fs::read_to_string(config_path)?;
+        let config: serde_yaml::Value = serde_yaml::from_str(&config_str)?;
+
+        match config.get("tool_selection") {
+            Some(section) => Ok(serde_yaml::from_value(section.clone())?),
+            None => {
+                debug!(
+                    "No tool_selection section in {}, using defaults",
+                    config_path
+                );
+                Ok(ToolSelectionConfig::default())
+            }
+        }
+    }
+}
+
+impl Default for Configuration {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 #[cfg(test)]
@@ -119,8 +308,234 @@ cache:
     }
 
     #[test]
-    fn test_configuration_new() {
+    fn test_load_rag_config() {
+        let temp_file = create_test_config_file();
+        let config = Configuration::new();
+        let rag_config = config
+            .load_rag_config(temp_file.path().to_str().unwrap())
+            .unwrap();
+        assert_eq!(rag_config.url, "http://localhost:6334");
+        assert_eq!(rag_config.collection_name, "documents");
+
+        let mut rag_file = NamedTempFile::new().unwrap();
+        writeln!(
+            rag_file,
+            "rag:\n  url: \"https://cluster.cloud.qdrant.io:6334\"\n  api_key: \"secret\"\n  chunk_size: 256"
+        )
+        .unwrap();
+        let rag_config = config
+            .load_rag_config(rag_file.path().to_str().unwrap())
+            .unwrap();
+        assert_eq!(rag_config.url, "https://cluster.cloud.qdrant.io:6334");
+        assert_eq!(rag_config.api_key.as_deref(), Some("secret"));
+        assert_eq!(rag_config.chunk_size, 256);
+        assert_eq!(rag_config.chunk_overlap, 128);
+    }
+
+    #[test]
+    fn test_load_audit_config() {
+        let temp_file = create_test_config_file();
+        let config = Configuration::new();
+        let audit_config = config
+            .load_audit_config(temp_file.path().to_str().unwrap())
+            .unwrap();
+        assert_eq!(audit_config, AuditConfig::default());
+        assert!(!audit_config.enabled);
+
+        let mut audit_file = NamedTempFile::new().unwrap();
+        writeln!(audit_file, "audit:\n  enabled: true").unwrap();
+        let audit_config = config
+            .load_audit_config(audit_file.path().to_str().unwrap())
+            .unwrap();
+        assert!(audit_config.enabled);
+        assert_eq!(audit_config.path, "logs/audit.jsonl");
+    }
+
+    #[test]
+    fn test_load_metrics_config() {
+        let temp_file = create_test_config_file();
+        let config = Configuration::new();
+        let metrics_config = config
+            .load_metrics_config(temp_file.path().to_str().unwrap())
+            .unwrap();
+        assert!(!metrics_config.enabled);
+
+        let mut metrics_file = NamedTempFile::new().unwrap();
+        writeln!(
+            metrics_file,
+            "metrics:\n  enabled: true\n  listen: \"0.0.0.0:9100\""
+        )
+        .unwrap();
+        let metrics_config = config
+            .load_metrics_config(metrics_file.path().to_str().unwrap())
+            .unwrap();
+        assert!(metrics_config.enabled);
+        assert_eq!(metrics_config.listen, "0.0.0.0:9100");
+    }
+
+    #[test]
+    fn test_load_supervisor_config() {
+        let temp_file = create_test_config_file();
+        let config = Configuration::new();
+        let supervisor_config = config
+            .load_supervisor_config(temp_file.path().to_str().unwrap())
+            .unwrap();
+        assert_eq!(supervisor_config, SupervisorConfig::default());
+
+        let mut supervisor_file = NamedTempFile::new().unwrap();
+        writeln!(
+            supervisor_file,
+            "supervisor:\n  enabled: false\n  restart:\n    max_retries: 1"
+        )
+        .unwrap();
+        let supervisor_config = config
+            .load_supervisor_config(supervisor_file.path().to_str().unwrap())
+            .unwrap();
+        assert!(!supervisor_config.enabled);
+        assert_eq!(supervisor_config.restart.max_retries, 1);
+        assert_eq!(supervisor_config.health_check_secs, 30);
+    }
+
+    #[test]
+    fn test_load_conversation_config() {
+        let temp_file = create_test_config_file();
         let config = Configuration::new();
-        assert!(config.api_key.is_none());
+        let conversation_config = config
+            .load_conversation_config(temp_file.path().to_str().unwrap())
+            .unwrap();
+        assert_eq!(conversation_config, ConversationConfig::default());
+
+        let mut conversation_file = NamedTempFile::new().unwrap();
+        writeln!(
+            conversation_file,
+            "conversation:\n  max_history_tokens: 0\n  database: \"chats.db\""
+        )
+        .unwrap();
+        let conversation_config = config
+            .load_conversation_config(conversation_file.path().to_str().unwrap())
+            .unwrap();
+        assert_eq!(conversation_config.max_history_tokens, 0);
+        assert_eq!(conversation_config.database, "chats.db");
+        assert!(conversation_config.persist);
+    }
+
+    #[test]
+    fn test_load_long_term_memory_config() {
+        let temp_file = create_test_config_file();
+        let config = Configuration::new();
+        let memory_config = config
+            .load_long_term_memory_config(temp_file.path().to_str().unwrap())
+            .unwrap();
+        assert_eq!(memory_config, LongTermMemoryConfig::default());
+
+        let mut memory_file = NamedTempFile::new().unwrap();
+        writeln!(
+            memory_file,
+            "long_term_memory:\n  max_recalled: 5\n  min_score: 0.7"
+        )
+        .unwrap();
+        let memory_config = config
+            .load_long_term_memory_config(memory_file.path().to_str().unwrap())
+            .unwrap();
+        assert_eq!(memory_config.max_recalled, 5);
+        assert_eq!(memory_config.min_score, 0.7);
+        assert!(memory_config.enabled);
+    }
+
+    #[test]
+    fn test_load_markdown_config() {
+        let temp_file = create_test_config_file();
+        let config = Configuration::new();
+        let markdown_config = config
+            .load_markdown_config(temp_file.path().to_str().unwrap())
+            .unwrap();
+        assert_eq!(markdown_config, MarkdownConfig::default());
+
+        let mut markdown_file = NamedTempFile::new().unwrap();
+        writeln!(markdown_file, "markdown:\n  code_theme: InspiredGitHub").unwrap();
+        let markdown_config = config
+            .load_markdown_config(markdown_file.path().to_str().unwrap())
+            .unwrap();
+        assert_eq!(markdown_config.code_theme, "InspiredGitHub");
+        assert!(markdown_config.enabled);
+    }
+
+    #[test]
+    fn test_load_display_config() {
+        let temp_file = create_test_config_file();
+        let config = Configuration::new();
+        let display_config = config
+            .load_display_config(temp_file.path().to_str().unwrap())
+            .unwrap();
+        assert_eq!(display_config, DisplayConfig::default());
+
+        let mut display_file = NamedTempFile::new().unwrap();
+        writeln!(display_file, "display:\n  theme: light").unwrap();
+        let display_config = config
+            .load_display_config(display_file.path().to_str().unwrap())
+            .unwrap();
+        assert_eq!(display_config.theme, crate::utils::Theme::Light);
+
+        let mut display_file = NamedTempFile::new().unwrap();
+        writeln!(display_file, "display:\n  theme: sepia").unwrap();
+        assert!(config
+            .load_display_config(display_file.path().to_str().unwrap())
+            .is_err());
+    }
+
+    #[test]
+    fn test_load_agent_config() {
+        let temp_file = create_test_config_file();
+        let config = Configuration::new();
+        let agent_config = config
+            .load_agent_config(temp_file.path().to_str().unwrap())
+            .unwrap();
+        assert_eq!(agent_config, AgentConfig::default());
+
+        let mut agent_file = NamedTempFile::new().unwrap();
+        writeln!(agent_file, "agent:\n  token_budget: 20000").unwrap();
+        let agent_config = config
+            .load_agent_config(agent_file.path().to_str().unwrap())
+            .unwrap();
+        assert_eq!(agent_config.max_iterations, 5);
+        assert_eq!(agent_config.token_budget, Some(20000));
+    }
+
+    #[test]
+    fn test_load_tool_selection_config() {
+        let temp_file = create_test_config_file();
+        let config = Configuration::new();
+        let selection_config = config
+            .load_tool_selection_config(temp_file.path().to_str().unwrap())
+            .unwrap();
+        assert_eq!(selection_config, ToolSelectionConfig::default());
+
+        let mut selection_file = NamedTempFile::new().unwrap();
+        writeln!(
+            selection_file,
+            "tool_selection:\n  max_tools: 8\n  deny: [\"puppeteer_*\"]"
+        )
+        .unwrap();
+        let selection_config = config
+            .load_tool_selection_config(selection_file.path().to_str().unwrap())
+            .unwrap();
+        assert!(selection_config.enabled);
+        assert_eq!(selection_config.max_tools, 8);
+        assert_eq!(selection_config.deny, vec!["puppeteer_*"]);
+        assert!(selection_config.allow.is_empty());
+    }
+
+    #[test]
+    fn test_configuration_new() {
+        let api_key = env::var("LLM_API_KEY").ok();
+        env::set_var("LLM_API_KEY", "test_key");
+        assert_eq!(Configuration::new().api_key.as_deref(), Some("test_key"));
+
+        env::remove_var("LLM_API_KEY");
+        assert!(Configuration::new().api_key.is_none());
+
+        if let Some(key) = api_key {
+            env::set_var("LLM_API_KEY", key);
+        }
     }
 }
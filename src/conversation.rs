@@ -1,11 +1,789 @@
-use anyhow::Result;
-use chrono::Utc;
+//! Conversation Module: What has been said in a chat
+//!
+//! This module provides:
+//! - `ConversationContext`: the earlier turns of the chat, sent to the LLM
+//!   with each new message so it remembers what was said
+//! - `ConversationConfig`, read from the `conversation` section of
+//!   `config.yaml`: how many tokens of earlier turns a request may carry,
+//!   when older turns are summarized and where sessions are saved
+//! - `summary_request`: the older turns, written out for the LLM to
+//!   summarize into a compact memory of the conversation
+//! - `ConversationStore`: the turns of each chat session saved in SQLite,
+//!   with the provider that answered and when, to list and resume later,
+//!   and searched by keyword through an FTS5 index of the messages; a
+//!   session's own system prompt, set with `/system`, is saved with it,
+//!   and so are the tokens each turn used, per provider, for `/usage`
+//! - `ConversationManager`: conversations whose messages are stored in the
+//!   vector store for recall by similarity
+//!
+//! A turn is the user's message, the tool calls and results it led to and
+//! the answer. Turns are kept or dropped whole, oldest first, so a tool
+//! result never loses the call it answers. Tokens are estimated at four
+//! characters each. Once the turns pass `summary_threshold` of the budget,
+//! the oldest are handed back to be summarized by the LLM until the rest
+//! fill at most half of it; the summary is sent as a system message before
+//! the recent turns, which stay verbatim. The store saves each turn as it ends, tool calls and
+//! results included, so a session survives a crash as well as `/exit`.
+//!
+//! Author: arkSong <arksong2018@gmail.com>
+//! Version: 1.0.0
+//! License: MIT
+
+use anyhow::{Error, Result};
+use chrono::{DateTime, Utc};
 use dashmap::DashMap;
+use log::{debug, warn};
+use rusqlite::{params, Connection, OptionalExtension};
 use serde::{Deserialize, Serialize};
-use std::sync::Arc;
+use serde_json::{json, Value};
+use std::collections::VecDeque;
+use std::fs;
+use std::path::Path;
+use std::sync::{Arc, Mutex};
 use uuid::Uuid;
 
-use crate::vector_store::{Message, VectorStore};
+use crate::config::Configuration;
+use crate::usage::{TokenUsage, UsageTracker};
+use crate::vector_store::{Filter, Message, VectorStore};
+
+/// Settings of the conversation context, read from the `conversation`
+/// section of `config.yaml`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ConversationConfig {
+    /// Most tokens of earlier turns sent with a message; 0 sends none
+    pub max_history_tokens: usize,
+    /// Whether older turns are summarized rather than forgotten
+    pub summarize: bool,
+    /// Share of `max_history_tokens` the turns may fill before the oldest
+    /// are summarized, from 0 to 1
+    pub summary_threshold: f64,
+    /// System prompt asking the LLM for the summary
+    pub summary_prompt: String,
+    /// Whether sessions are saved, for `/sessions` and `/resume`
+    pub persist: bool,
+    /// The SQLite database sessions are saved in
+    pub database: String,
+}
+
+impl Default for ConversationConfig {
+    fn default() -> Self {
+        Self {
+            max_history_tokens: 4000,
+            summarize: true,
+            summary_threshold: 0.8,
+            summary_prompt: DEFAULT_SUMMARY_PROMPT.to_string(),
+            persist: true,
+            database: "data/conversations.db".to_string(),
+        }
+    }
+}
+
+impl ConversationConfig {
+    /// The `conversation` section of the configuration file `config_path`,
+    /// or the defaults if it cannot be read
+    pub fn from_config_file(config_path: &str) -> Self {
+        Configuration::new()
+            .load_conversation_config(config_path)
+            .unwrap_or_else(|e| {
+                warn!(
+                    "Failed to load the conversation configuration, using defaults: {}",
+                    e
+                );
+                Self::default()
+            })
+    }
+
+    /// Tokens of earlier turns above which the oldest are summarized, or
+    /// dropped if summarizing is off
+    fn limit(&self) -> usize {
+        if !self.summarize {
+            return self.max_history_tokens;
+        }
+        (self.max_history_tokens as f64 * self.summary_threshold.clamp(0.0, 1.0)) as usize
+    }
+
+    /// Tokens the turns are cut down to once they pass the limit
+    fn target(&self) -> usize {
+        if !self.summarize {
+            return self.max_history_tokens;
+        }
+        self.limit().min(self.max_history_tokens / 2)
+    }
+}
+
+/// Asks the LLM for the summary of older turns, unless `config.yaml` has
+/// another `summary_prompt`
+pub const DEFAULT_SUMMARY_PROMPT: &str = "Summarize the conversation below for your own memory, in a few short sentences or bullet points. Start from the earlier summary if there is one. Keep names, facts, preferences, decisions, tool results that still matter and open questions; leave out greetings and small talk. Write only the summary.";
+
+/// Rough number of tokens of `text`, four characters each
+pub fn estimate_tokens(text: &str) -> usize {
+    text.chars().count().div_ceil(4)
+}
+
+/// Rough number of tokens of a chat message
+fn message_tokens(message: &Value) -> usize {
+    match message["content"].as_str() {
+        Some(content) => estimate_tokens(content),
+        None => estimate_tokens(&message["content"].to_string()),
+    }
+}
+
+/// The latest turns of the chat that fit in the token budget, after a
+/// summary of the older ones
+#[derive(Debug, Clone, Default)]
+pub struct ConversationContext {
+    turns: VecDeque<Vec<Value>>,
+    tokens: usize,
+    summary: Option<String>,
+    config: ConversationConfig,
+}
+
+impl ConversationContext {
+    pub fn new(config: &ConversationConfig) -> Self {
+        Self {
+            turns: VecDeque::new(),
+            tokens: 0,
+            summary: None,
+            config: config.clone(),
+        }
+    }
+
+    pub fn config(&self) -> &ConversationConfig {
+        &self.config
+    }
+
+    /// Adds the messages of a finished turn. Once the context passes its
+    /// limit, the oldest turns are taken out and returned, to be summarized
+    /// with `set_summary` or forgotten. A turn larger than the whole budget
+    /// is not kept.
+    pub fn push_turn(&mut self, turn: Vec<Value>) -> Vec<Vec<Value>> {
+        self.tokens += turn.iter().map(message_tokens).sum::<usize>();
+        self.turns.push_back(turn);
+        let dropped = if self.tokens() > self.config.limit() {
+            self.drop_oldest(self.config.target())
+        } else {
+            Vec::new()
+        };
+        debug!(
+            "Conversation context holds {} turns, about {} tokens",
+            self.turns.len(),
+            self.tokens()
+        );
+        dropped
+    }
+
+    /// Takes out the oldest turns until the context fits in `target` tokens
+    fn drop_oldest(&mut self, target: usize) -> Vec<Vec<Value>> {
+        let mut dropped = Vec::new();
+        while self.tokens() > target {
+            match self.turns.pop_front() {
+                Some(oldest) => {
+                    self.tokens -= oldest.iter().map(message_tokens).sum::<usize>();
+                    dropped.push(oldest);
+                }
+                None => break,
+            }
+        }
+        dropped
+    }
+
+    /// The summary of the older turns, if any, as a system message, then
+    /// the messages of the kept turns, oldest first
+    pub fn messages(&self) -> Vec<Value> {
+        let summary = self.summary.iter().map(|summary| {
+            json!({
+                "role": "system",
+                "content": format!("Summary of the earlier conversation:\n{}", summary)
+            })
+        });
+        summary
+            .chain(self.turns.iter().flatten().cloned())
+            .collect()
+    }
+
+    /// The summary of the turns no longer kept
+    pub fn summary(&self) -> Option<&str> {
+        self.summary.as_deref()
+    }
+
+    /// Replaces the summary of the older turns
+    pub fn set_summary(&mut self, summary: String) {
+        self.summary = Some(summary);
+    }
+
+    /// Takes out the latest turn, if one is kept
+    pub fn pop_turn(&mut self) -> Option<Vec<Value>> {
+        let turn = self.turns.pop_back()?;
+        self.tokens -= turn.iter().map(message_tokens).sum::<usize>();
+        Some(turn)
+    }
+
+    /// The messages of each kept turn, oldest first
+    pub fn recent_turns(&self) -> Vec<Vec<Value>> {
+        self.turns.iter().cloned().collect()
+    }
+
+    /// Number of turns kept
+    pub fn turns(&self) -> usize {
+        self.turns.len()
+    }
+
+    /// Estimated tokens of the kept turns and the summary
+    pub fn tokens(&self) -> usize {
+        self.tokens + self.summary.as_deref().map(estimate_tokens).unwrap_or(0)
+    }
+
+    /// Forgets every turn and the summary
+    pub fn clear(&mut self) {
+        self.turns.clear();
+        self.tokens = 0;
+        self.summary = None;
+    }
+
+    /// Replaces the kept turns with `turns` of a resumed session, keeping
+    /// as many of the latest as fit in the budget
+    pub fn restore(&mut self, turns: Vec<Vec<Value>>) {
+        self.clear();
+        for turn in turns {
+            self.tokens += turn.iter().map(message_tokens).sum::<usize>();
+            self.turns.push_back(turn);
+        }
+        self.drop_oldest(self.config.max_history_tokens);
+    }
+}
+
+/// The request for a summary of `turns`, extending the `earlier` summary
+/// if there is one: each message on its own line after its role
+pub fn summary_request(earlier: Option<&str>, turns: &[Vec<Value>]) -> String {
+    let mut request = String::new();
+    if let Some(earlier) = earlier {
+        request.push_str(&format!("Earlier summary:\n{}\n\n", earlier));
+    }
+    request.push_str("Conversation:\n");
+    for message in turns.iter().flatten() {
+        let role = message["role"].as_str().unwrap_or("user");
+        request.push_str(&format!("{}: {}\n", role, content_of(message)));
+    }
+    request
+}
+
+/// Longest title of a saved session, in characters
+const TITLE_CHARS: usize = 60;
+
+/// A saved session, as listed by `/sessions`
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct StoredSession {
+    pub id: String,
+    /// The start of the first message
+    pub title: String,
+    /// The provider that answered the latest turn
+    pub provider: String,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+    pub turns: usize,
+}
+
+/// A saved message matching a `/history-search`
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct MessageMatch {
+    pub session_id: String,
+    /// Title of the session
+    pub title: String,
+    /// Number of the turn in the session, from 0
+    pub turn: usize,
+    pub role: String,
+    /// The matching part of the message, keywords in brackets
+    pub snippet: String,
+    pub created_at: DateTime<Utc>,
+}
+
+/// The tokens one saved turn used, with every provider it asked
+#[derive(Debug, Clone, PartialEq)]
+pub struct TurnUsage {
+    /// Number of the turn in the session, from 0
+    pub turn: usize,
+    /// The user's message, shortened like a session title
+    pub input: String,
+    pub usage: TokenUsage,
+}
+
+/// The tokens a saved session used
+#[derive(Debug, Clone, Default)]
+pub struct SessionUsage {
+    /// Requests and tokens of the whole session, per provider
+    pub totals: UsageTracker,
+    /// Turns that reported usage, oldest first
+    pub turns: Vec<TurnUsage>,
+}
+
+impl SessionUsage {
+    /// The `count` turns that used the most tokens, most first
+    pub fn largest_turns(&self, count: usize) -> Vec<&TurnUsage> {
+        let mut turns: Vec<&TurnUsage> = self.turns.iter().collect();
+        turns.sort_by(|a, b| b.usage.total().cmp(&a.usage.total()));
+        turns.truncate(count);
+        turns
+    }
+}
+
+/// Chat sessions saved in a SQLite database, one row per message
+pub struct ConversationStore {
+    path: String,
+    conn: Mutex<Connection>,
+}
+
+impl ConversationStore {
+    /// Opens the database at `path`, creating it, its directory and its
+    /// tables if needed
+    pub fn open(path: &str) -> Result<Self> {
+        if let Some(parent) = Path::new(path)
+            .parent()
+            .filter(|parent| !parent.as_os_str().is_empty())
+        {
+            fs::create_dir_all(parent)?;
+        }
+        let conn = Connection::open(path)
+            .map_err(|e| Error::msg(format!("Failed to open {}: {}", path, e)))?;
+        conn.execute_batch(
+            "PRAGMA foreign_keys = ON;
+             CREATE TABLE IF NOT EXISTS sessions (
+                 id TEXT PRIMARY KEY,
+                 title TEXT NOT NULL,
+                 provider TEXT NOT NULL,
+                 created_at TEXT NOT NULL,
+                 updated_at TEXT NOT NULL,
+                 system_prompt TEXT
+             );
+             CREATE TABLE IF NOT EXISTS messages (
+                 id INTEGER PRIMARY KEY AUTOINCREMENT,
+                 session_id TEXT NOT NULL REFERENCES sessions(id) ON DELETE CASCADE,
+                 turn INTEGER NOT NULL,
+                 role TEXT NOT NULL,
+                 content TEXT NOT NULL,
+                 provider TEXT NOT NULL,
+                 created_at TEXT NOT NULL
+             );
+             CREATE INDEX IF NOT EXISTS messages_session ON messages(session_id, turn);
+             CREATE TABLE IF NOT EXISTS turn_usage (
+                 session_id TEXT NOT NULL REFERENCES sessions(id) ON DELETE CASCADE,
+                 turn INTEGER NOT NULL,
+                 provider TEXT NOT NULL,
+                 requests INTEGER NOT NULL,
+                 prompt_tokens INTEGER NOT NULL,
+                 completion_tokens INTEGER NOT NULL,
+                 PRIMARY KEY (session_id, turn, provider)
+             );",
+        )?;
+        let has_system_prompt: bool = conn.query_row(
+            "SELECT COUNT(*) > 0 FROM pragma_table_info('sessions') WHERE name = 'system_prompt'",
+            [],
+            |row| row.get(0),
+        )?;
+        if !has_system_prompt {
+            // Stores saved before sessions had their own system prompt
+            conn.execute_batch("ALTER TABLE sessions ADD COLUMN system_prompt TEXT;")?;
+        }
+        let indexed = conn
+            .query_row(
+                "SELECT 1 FROM sqlite_master WHERE name = 'messages_fts'",
+                [],
+                |_| Ok(()),
+            )
+            .optional()?
+            .is_some();
+        if !indexed {
+            // Stores saved before the index existed are indexed once
+            conn.execute_batch(
+                "CREATE VIRTUAL TABLE messages_fts USING fts5(
+                     content, content='messages', content_rowid='id'
+                 );
+                 INSERT INTO messages_fts (messages_fts) VALUES ('rebuild');
+                 CREATE TRIGGER messages_fts_ai AFTER INSERT ON messages BEGIN
+                     INSERT INTO messages_fts (rowid, content) VALUES (new.id, new.content);
+                 END;
+                 CREATE TRIGGER messages_fts_ad AFTER DELETE ON messages BEGIN
+                     INSERT INTO messages_fts (messages_fts, rowid, content)
+                     VALUES ('delete', old.id, old.content);
+                 END;",
+            )?;
+        }
+        Ok(Self {
+            path: path.to_string(),
+            conn: Mutex::new(conn),
+        })
+    }
+
+    /// The store `config` asks for, or `None` if sessions are not saved
+    pub fn from_config(config: &ConversationConfig) -> Result<Option<Self>> {
+        if !config.persist {
+            return Ok(None);
+        }
+        Ok(Some(Self::open(&config.database)?))
+    }
+
+    /// The database the sessions are saved in
+    pub fn path(&self) -> &str {
+        &self.path
+    }
+
+    /// Appends the messages of a finished `turn` to the session
+    /// `session_id`, creating the session with its first turn
+    pub fn save_turn(&self, session_id: &str, provider: &str, turn: &[Value]) -> Result<()> {
+        let now = Utc::now().to_rfc3339();
+        let title = turn
+            .iter()
+            .find(|message| message["role"] == "user")
+            .map(|message| title_of(&content_of(message)))
+            .unwrap_or_default();
+        let mut conn = self.conn.lock().unwrap();
+        let tx = conn.transaction()?;
+        tx.execute(
+            "INSERT INTO sessions (id, title, provider, created_at, updated_at)
+             VALUES (?1, ?2, ?3, ?4, ?4)
+             ON CONFLICT(id) DO UPDATE SET provider = ?3, updated_at = ?4,
+                 title = CASE WHEN title = '' THEN ?2 ELSE title END",
+            params![session_id, title, provider, now],
+        )?;
+        let number: i64 = tx.query_row(
+            "SELECT COALESCE(MAX(turn) + 1, 0) FROM messages WHERE session_id = ?1",
+            params![session_id],
+            |row| row.get(0),
+        )?;
+        {
+            let mut insert = tx.prepare(
+                "INSERT INTO messages (session_id, turn, role, content, provider, created_at)
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+            )?;
+            for message in turn {
+                let role = message["role"].as_str().unwrap_or("user");
+                insert.execute(params![
+                    session_id,
+                    number,
+                    role,
+                    content_of(message),
+                    provider,
+                    now
+                ])?;
+            }
+        }
+        tx.commit()?;
+        Ok(())
+    }
+
+    /// Records `usage`, the requests of the latest turn of the session
+    /// `session_id`, against that turn
+    pub fn save_usage(&self, session_id: &str, usage: &UsageTracker) -> Result<()> {
+        let mut conn = self.conn.lock().unwrap();
+        let tx = conn.transaction()?;
+        let number: Option<i64> = tx.query_row(
+            "SELECT MAX(turn) FROM messages WHERE session_id = ?1",
+            params![session_id],
+            |row| row.get(0),
+        )?;
+        let number = match number {
+            Some(number) => number,
+            None => return Err(Error::msg(format!("No saved turn in {}", session_id))),
+        };
+        {
+            let mut insert = tx.prepare(
+                "INSERT OR REPLACE INTO turn_usage
+                     (session_id, turn, provider, requests, prompt_tokens, completion_tokens)
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+            )?;
+            for (provider, entry) in usage.providers() {
+                insert.execute(params![
+                    session_id,
+                    number,
+                    provider,
+                    entry.requests as i64,
+                    entry.usage.prompt_tokens as i64,
+                    entry.usage.completion_tokens as i64
+                ])?;
+            }
+        }
+        tx.commit()?;
+        Ok(())
+    }
+
+    /// The tokens the session `session_id` used, per provider and per turn
+    pub fn usage(&self, session_id: &str) -> Result<SessionUsage> {
+        let conn = self.conn.lock().unwrap();
+        let mut statement = conn.prepare(
+            "SELECT u.turn, u.provider, u.requests, u.prompt_tokens, u.completion_tokens,
+                    (SELECT m.content FROM messages m
+                     WHERE m.session_id = u.session_id AND m.turn = u.turn AND m.role = 'user'
+                     ORDER BY m.id LIMIT 1)
+             FROM turn_usage u WHERE u.session_id = ?1 ORDER BY u.turn, u.provider",
+        )?;
+        let rows = statement.query_map(params![session_id], |row| {
+            Ok((
+                row.get::<_, i64>(0)?,
+                row.get::<_, String>(1)?,
+                row.get::<_, i64>(2)?,
+                row.get::<_, i64>(3)?,
+                row.get::<_, i64>(4)?,
+                row.get::<_, Option<String>>(5)?,
+            ))
+        })?;
+        let mut usage = SessionUsage::default();
+        for row in rows {
+            let (turn, provider, requests, prompt_tokens, completion_tokens, input) = row?;
+            let tokens = TokenUsage::new(prompt_tokens as u64, completion_tokens as u64);
+            usage
+                .totals
+                .record_requests(&provider, requests as u64, &tokens);
+            let turn = turn as usize;
+            if usage.turns.last().map(|last| last.turn) != Some(turn) {
+                usage.turns.push(TurnUsage {
+                    turn,
+                    input: title_of(&input.unwrap_or_default()),
+                    usage: TokenUsage::default(),
+                });
+            }
+            if let Some(last) = usage.turns.last_mut() {
+                last.usage.add(&tokens);
+            }
+        }
+        Ok(usage)
+    }
+
+    /// The `limit` most recently continued sessions, latest first
+    pub fn sessions(&self, limit: usize) -> Result<Vec<StoredSession>> {
+        let conn = self.conn.lock().unwrap();
+        let mut statement = conn.prepare(
+            "SELECT s.id, s.title, s.provider, s.created_at, s.updated_at,
+                    (SELECT COUNT(DISTINCT turn) FROM messages m WHERE m.session_id = s.id)
+             FROM sessions s ORDER BY s.updated_at DESC LIMIT ?1",
+        )?;
+        let rows = statement.query_map(params![limit as i64], |row| {
+            Ok((
+                row.get::<_, String>(0)?,
+                row.get::<_, String>(1)?,
+                row.get::<_, String>(2)?,
+                row.get::<_, String>(3)?,
+                row.get::<_, String>(4)?,
+                row.get::<_, i64>(5)?,
+            ))
+        })?;
+        let mut sessions = Vec::new();
+        for row in rows {
+            let (id, title, provider, created_at, updated_at, turns) = row?;
+            sessions.push(StoredSession {
+                id,
+                title,
+                provider,
+                created_at: parse_time(&created_at)?,
+                updated_at: parse_time(&updated_at)?,
+                turns: turns as usize,
+            });
+        }
+        Ok(sessions)
+    }
+
+    /// The id of the session `id` starts, which must name exactly one
+    pub fn resolve(&self, id: &str) -> Result<String> {
+        let conn = self.conn.lock().unwrap();
+        let mut statement =
+            conn.prepare("SELECT id FROM sessions WHERE substr(id, 1, length(?1)) = ?1 LIMIT 2")?;
+        let ids = statement
+            .query_map(params![id], |row| row.get::<_, String>(0))?
+            .collect::<rusqlite::Result<Vec<String>>>()?;
+        match ids.as_slice() {
+            [only] => Ok(only.clone()),
+            [] => Err(Error::msg(format!("No saved session {}", id))),
+            _ => Err(Error::msg(format!(
+                "More than one session starts with {}, give more of the id",
+                id
+            ))),
+        }
+    }
+
+    /// The turns of the session `session_id`, oldest first
+    pub fn load(&self, session_id: &str) -> Result<Vec<Vec<Value>>> {
+        let conn = self.conn.lock().unwrap();
+        let exists = conn
+            .query_row(
+                "SELECT 1 FROM sessions WHERE id = ?1",
+                params![session_id],
+                |_| Ok(()),
+            )
+            .optional()?;
+        if exists.is_none() {
+            return Err(Error::msg(format!("No saved session {}", session_id)));
+        }
+        let mut statement = conn.prepare(
+            "SELECT turn, role, content FROM messages WHERE session_id = ?1 ORDER BY turn, id",
+        )?;
+        let rows = statement.query_map(params![session_id], |row| {
+            Ok((
+                row.get::<_, i64>(0)?,
+                row.get::<_, String>(1)?,
+                row.get::<_, String>(2)?,
+            ))
+        })?;
+        let mut turns: Vec<Vec<Value>> = Vec::new();
+        let mut current = None;
+        for row in rows {
+            let (turn, role, content) = row?;
+            if current != Some(turn) {
+                turns.push(Vec::new());
+                current = Some(turn);
+            }
+            if let Some(messages) = turns.last_mut() {
+                messages.push(json!({ "role": role, "content": content }));
+            }
+        }
+        Ok(turns)
+    }
+
+    /// The `limit` user messages and answers that best match the keywords
+    /// of `query`, best first. Every keyword must appear; FTS5 syntax in
+    /// `query` is taken literally.
+    pub fn search(&self, query: &str, limit: usize) -> Result<Vec<MessageMatch>> {
+        let query = fts_query(query);
+        if query.is_empty() {
+            return Ok(Vec::new());
+        }
+        let conn = self.conn.lock().unwrap();
+        let mut statement = conn.prepare(
+            "SELECT m.session_id, s.title, m.turn, m.role,
+                    snippet(messages_fts, 0, '[', ']', '...', 12), m.created_at
+             FROM messages_fts
+             JOIN messages m ON m.id = messages_fts.rowid
+             JOIN sessions s ON s.id = m.session_id
+             WHERE messages_fts MATCH ?1 AND m.role IN ('user', 'assistant')
+             ORDER BY bm25(messages_fts) LIMIT ?2",
+        )?;
+        let rows = statement.query_map(params![query, limit as i64], |row| {
+            Ok((
+                row.get::<_, String>(0)?,
+                row.get::<_, String>(1)?,
+                row.get::<_, i64>(2)?,
+                row.get::<_, String>(3)?,
+                row.get::<_, String>(4)?,
+                row.get::<_, String>(5)?,
+            ))
+        })?;
+        let mut matches = Vec::new();
+        for row in rows {
+            let (session_id, title, turn, role, snippet, created_at) = row?;
+            matches.push(MessageMatch {
+                session_id,
+                title,
+                turn: turn as usize,
+                role,
+                snippet,
+                created_at: parse_time(&created_at)?,
+            });
+        }
+        Ok(matches)
+    }
+
+    /// Deletes the latest turn of the session `session_id`, and the
+    /// session once it has no turns left. Returns whether there was a turn.
+    pub fn remove_last_turn(&self, session_id: &str) -> Result<bool> {
+        let mut conn = self.conn.lock().unwrap();
+        let tx = conn.transaction()?;
+        tx.execute(
+            "DELETE FROM turn_usage WHERE session_id = ?1
+             AND turn = (SELECT MAX(turn) FROM messages WHERE session_id = ?1)",
+            params![session_id],
+        )?;
+        let removed = tx.execute(
+            "DELETE FROM messages WHERE session_id = ?1
+             AND turn = (SELECT MAX(turn) FROM messages WHERE session_id = ?1)",
+            params![session_id],
+        )?;
+        tx.execute(
+            "DELETE FROM sessions WHERE id = ?1 AND system_prompt IS NULL
+             AND NOT EXISTS (SELECT 1 FROM messages WHERE session_id = ?1)",
+            params![session_id],
+        )?;
+        tx.execute(
+            "UPDATE sessions SET updated_at = ?2 WHERE id = ?1",
+            params![session_id, Utc::now().to_rfc3339()],
+        )?;
+        tx.commit()?;
+        Ok(removed > 0)
+    }
+
+    /// Sets the system prompt of the session `session_id`, or with `None`
+    /// goes back to the configured one. A session without turns yet is
+    /// saved without a title.
+    pub fn set_system_prompt(
+        &self,
+        session_id: &str,
+        provider: &str,
+        system_prompt: Option<&str>,
+    ) -> Result<()> {
+        let conn = self.conn.lock().unwrap();
+        conn.execute(
+            "INSERT INTO sessions (id, title, provider, created_at, updated_at, system_prompt)
+             VALUES (?1, '', ?2, ?3, ?3, ?4)
+             ON CONFLICT(id) DO UPDATE SET updated_at = ?3, system_prompt = ?4",
+            params![session_id, provider, Utc::now().to_rfc3339(), system_prompt],
+        )?;
+        Ok(())
+    }
+
+    /// The system prompt set for the session `session_id`, if any
+    pub fn system_prompt(&self, session_id: &str) -> Result<Option<String>> {
+        let conn = self.conn.lock().unwrap();
+        let system_prompt = conn
+            .query_row(
+                "SELECT system_prompt FROM sessions WHERE id = ?1",
+                params![session_id],
+                |row| row.get::<_, Option<String>>(0),
+            )
+            .optional()?;
+        Ok(system_prompt.flatten())
+    }
+
+    /// Deletes the session `session_id` and its messages
+    pub fn delete(&self, session_id: &str) -> Result<()> {
+        let conn = self.conn.lock().unwrap();
+        conn.execute("DELETE FROM sessions WHERE id = ?1", params![session_id])?;
+        Ok(())
+    }
+}
+
+/// The text of a chat message
+fn content_of(message: &Value) -> String {
+    match message["content"].as_str() {
+        Some(content) => content.to_string(),
+        None => message["content"].to_string(),
+    }
+}
+
+/// The title of a session starting with `message`: its first line,
+/// shortened
+fn title_of(message: &str) -> String {
+    let line = message.trim().lines().next().unwrap_or_default();
+    if line.chars().count() <= TITLE_CHARS {
+        return line.to_string();
+    }
+    let mut title: String = line.chars().take(TITLE_CHARS - 3).collect();
+    title.push_str("...");
+    title
+}
+
+/// An FTS5 query matching every word of `query`, each quoted so that
+/// punctuation and operators are searched for as text
+fn fts_query(query: &str) -> String {
+    query
+        .split_whitespace()
+        .map(|word| format!("\"{}\"", word.replace('"', "\"\"")))
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// A time saved by the store
+fn parse_time(value: &str) -> Result<DateTime<Utc>> {
+    DateTime::parse_from_rfc3339(value)
+        .map(|time| time.with_timezone(&Utc))
+        .map_err(|_| Error::msg(format!("Invalid time in the conversation store: {}", value)))
+}
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Conversation {
@@ -56,7 +834,10 @@ impl ConversationManager {
             role: role.to_string(),
             content: content.to_string(),
             timestamp: Utc::now().timestamp(),
-            metadata: std::collections::HashMap::new(),
+            metadata: std::collections::HashMap::from([(
+                "conversation_id".to_string(),
+                conversation_id.to_string(),
+            )]),
         };
 
         // 存储消息到向量数据库
@@ -91,12 +872,37 @@ impl ConversationManager {
         Ok(())
     }
 
+    /// Recalls similar messages from every conversation
     pub async fn search_similar_messages(
         &self,
         embedding: Vec<f32>,
         limit: u64,
     ) -> Result<Vec<Message>> {
-        let results = self.vector_store.search_similar(embedding, limit).await?;
+        self.search_messages(embedding, limit, None).await
+    }
+
+    /// Recalls similar messages of one conversation
+    pub async fn search_conversation_messages(
+        &self,
+        conversation_id: &str,
+        embedding: Vec<f32>,
+        limit: u64,
+    ) -> Result<Vec<Message>> {
+        let filter = Filter::new().metadata("conversation_id", conversation_id);
+        self.search_messages(embedding, limit, Some(&filter)).await
+    }
+
+    /// Recalls similar messages matching `filter`, e.g. a role or time window
+    pub async fn search_messages(
+        &self,
+        embedding: Vec<f32>,
+        limit: u64,
+        filter: Option<&Filter>,
+    ) -> Result<Vec<Message>> {
+        let results = self
+            .vector_store
+            .search_similar(embedding, limit, filter)
+            .await?;
 
         let mut messages = Vec::new();
         for (id, _score, metadata) in results {
@@ -123,8 +929,219 @@ impl ConversationManager {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use serde_json::json;
     use tempfile::tempdir;
 
+    fn turn(question: &str, answer: &str) -> Vec<Value> {
+        vec![
+            json!({ "role": "user", "content": question }),
+            json!({ "role": "assistant", "content": answer }),
+        ]
+    }
+
+    #[test]
+    fn test_conversation_context() {
+        assert_eq!(estimate_tokens("abcdefgh"), 2);
+        assert_eq!(estimate_tokens("abcdefghi"), 3);
+
+        let mut context = ConversationContext::new(&ConversationConfig {
+            max_history_tokens: 8,
+            summarize: false,
+            ..ConversationConfig::default()
+        });
+        context.push_turn(turn("My name is Ada", "Hi Ada"));
+        assert_eq!(context.turns(), 1);
+        assert_eq!(context.tokens(), 6);
+        assert_eq!(context.messages()[0]["content"], "My name is Ada");
+
+        // Older turns are dropped whole once the budget is exceeded
+        context.push_turn(turn("What is it?", "Ada"));
+        assert_eq!(context.turns(), 1);
+        assert_eq!(context.messages()[0]["content"], "What is it?");
+
+        context.push_turn(turn(&"x".repeat(100), "Too long"));
+        assert_eq!(context.turns(), 0);
+        assert_eq!(context.tokens(), 0);
+
+        context.push_turn(turn("Hello", "Hi"));
+        context.clear();
+        assert!(context.messages().is_empty());
+
+        // A resumed session keeps only the latest turns that fit
+        context.restore(vec![turn("My name is Ada", "Hi Ada"), turn("Hi", "Hello")]);
+        assert_eq!(context.turns(), 1);
+        assert_eq!(context.messages()[0]["content"], "Hi");
+
+        // Undone turns give their tokens back
+        assert_eq!(context.pop_turn(), Some(turn("Hi", "Hello")));
+        assert_eq!(context.tokens(), 0);
+        assert_eq!(context.pop_turn(), None);
+    }
+
+    #[test]
+    fn test_summarize_older_turns() {
+        let mut context = ConversationContext::new(&ConversationConfig {
+            max_history_tokens: 20,
+            summary_threshold: 0.75,
+            ..ConversationConfig::default()
+        });
+        assert!(context
+            .push_turn(turn("My name is Ada", "Hi Ada"))
+            .is_empty());
+        assert!(context.push_turn(turn("I like SQL", "Noted")).is_empty());
+        assert_eq!(context.tokens(), 11);
+
+        // Past 15 tokens the oldest turns come back until 10 are left
+        let dropped = context.push_turn(turn("What is my name?", "Ada"));
+        assert_eq!(dropped, vec![turn("My name is Ada", "Hi Ada")]);
+        assert_eq!(context.turns(), 2);
+        assert_eq!(context.tokens(), 10);
+
+        let request = summary_request(None, &dropped);
+        assert_eq!(
+            request,
+            "Conversation:\nuser: My name is Ada\nassistant: Hi Ada\n"
+        );
+        context.set_summary("Ada".to_string());
+        assert_eq!(context.tokens(), 11);
+        let messages = context.messages();
+        assert_eq!(messages.len(), 5);
+        assert_eq!(messages[0]["role"], "system");
+        assert!(messages[0]["content"].as_str().unwrap().ends_with("\nAda"));
+        assert_eq!(messages[1]["content"], "I like SQL");
+        assert!(
+            summary_request(context.summary(), &dropped).starts_with("Earlier summary:\nAda\n\n")
+        );
+
+        context.clear();
+        assert_eq!(context.summary(), None);
+        assert!(context.messages().is_empty());
+    }
+
+    #[test]
+    fn test_conversation_store() {
+        let temp_dir = tempdir().unwrap();
+        let path = temp_dir.path().join("chats").join("conversations.db");
+        let store = ConversationStore::open(path.to_str().unwrap()).unwrap();
+        assert!(store.sessions(10).unwrap().is_empty());
+
+        let first = turn("My name is Ada\nand I like SQL", "Hi Ada");
+        let mut second = vec![json!({ "role": "user", "content": "What is my name?" })];
+        second.push(json!({
+            "role": "assistant",
+            "content": r#"{"tool": "memory_get", "arguments": {"key": "name"}}"#
+        }));
+        second.push(json!({ "role": "tool", "content": r#"{"value":"Ada"}"# }));
+        second.push(json!({ "role": "assistant", "content": "Ada" }));
+        store.save_turn("1234-abcd", "ollama", &first).unwrap();
+        let mut usage = UsageTracker::new();
+        usage.record("ollama", &TokenUsage::new(40, 10));
+        store.save_usage("1234-abcd", &usage).unwrap();
+        store.save_turn("1234-abcd", "openai", &second).unwrap();
+        let mut usage = UsageTracker::new();
+        usage.record("openai", &TokenUsage::new(100, 20));
+        usage.record("openai", &TokenUsage::new(150, 30));
+        usage.record("ollama", &TokenUsage::new(50, 0));
+        store.save_usage("1234-abcd", &usage).unwrap();
+        store
+            .save_turn("5678-efgh", "ollama", &turn(&"x".repeat(100), "Long"))
+            .unwrap();
+
+        let sessions = store.sessions(10).unwrap();
+        assert_eq!(sessions.len(), 2);
+
+        // Keywords are found in user messages and answers, not tool results
+        let matches = store.search("ada", 10).unwrap();
+        assert_eq!(matches.len(), 3);
+        assert!(matches.iter().all(|m| m.session_id == "1234-abcd"));
+        assert!(matches.iter().all(|m| m.role != "tool"));
+        assert!(matches
+            .iter()
+            .any(|m| m.turn == 0 && m.snippet.contains("[Ada]")));
+        assert_eq!(store.search("name SQL", 10).unwrap().len(), 1);
+        assert!(store.search("\"unbalanced OR", 10).unwrap().is_empty());
+        assert!(store.search("  ", 10).unwrap().is_empty());
+        let ada = sessions.iter().find(|s| s.id == "1234-abcd").unwrap();
+        assert_eq!(ada.title, "My name is Ada");
+        assert_eq!(ada.provider, "openai");
+        assert_eq!(ada.turns, 2);
+        assert!(ada.updated_at >= ada.created_at);
+        let long = sessions.iter().find(|s| s.id == "5678-efgh").unwrap();
+        assert_eq!(long.title.chars().count(), TITLE_CHARS);
+        assert_eq!(store.sessions(1).unwrap().len(), 1);
+
+        // Turns come back whole and in order, tool calls included
+        assert_eq!(store.resolve("1234").unwrap(), "1234-abcd");
+        assert!(store.resolve("9").is_err());
+        let turns = store.load("1234-abcd").unwrap();
+        assert_eq!(turns, vec![first, second]);
+
+        // Tokens are kept per turn and per provider
+        let usage = store.usage("1234-abcd").unwrap();
+        assert_eq!(usage.totals.requests(), 4);
+        assert_eq!(usage.totals.session(), &TokenUsage::new(340, 60));
+        let providers = usage.totals.providers();
+        assert_eq!(providers[0].0, "ollama");
+        assert_eq!(providers[0].1.usage, TokenUsage::new(90, 10));
+        assert_eq!(providers[1].1.requests, 2);
+        assert_eq!(usage.turns.len(), 2);
+        let largest = usage.largest_turns(1);
+        assert_eq!(largest.len(), 1);
+        assert_eq!(largest[0].turn, 1);
+        assert_eq!(largest[0].input, "What is my name?");
+        assert_eq!(largest[0].usage, TokenUsage::new(300, 50));
+        assert!(store.usage("5678-efgh").unwrap().turns.is_empty());
+        assert!(store.save_usage("none", &UsageTracker::new()).is_err());
+
+        // Sessions outlive the store that saved them
+        drop(store);
+        let store = ConversationStore::open(path.to_str().unwrap()).unwrap();
+        assert_eq!(store.load("5678-efgh").unwrap().len(), 1);
+        store.delete("5678-efgh").unwrap();
+        assert!(store.load("5678-efgh").is_err());
+        assert_eq!(store.sessions(10).unwrap().len(), 1);
+
+        // Undoing removes the latest turn, and the session with its last
+        assert!(store.remove_last_turn("1234-abcd").unwrap());
+        assert_eq!(
+            store.load("1234-abcd").unwrap(),
+            vec![turn("My name is Ada\nand I like SQL", "Hi Ada")]
+        );
+        assert_eq!(
+            store.usage("1234-abcd").unwrap().totals.session(),
+            &TokenUsage::new(40, 10)
+        );
+        assert!(store.search("memory_get", 10).unwrap().is_empty());
+        assert!(store.remove_last_turn("1234-abcd").unwrap());
+        assert!(store.sessions(10).unwrap().is_empty());
+        assert!(!store.remove_last_turn("1234-abcd").unwrap());
+
+        // A session's system prompt is kept before its first turn and
+        // after its last
+        assert_eq!(store.system_prompt("9abc").unwrap(), None);
+        store
+            .set_system_prompt("9abc", "ollama", Some("Answer in French."))
+            .unwrap();
+        assert_eq!(
+            store.system_prompt("9abc").unwrap().as_deref(),
+            Some("Answer in French.")
+        );
+        store
+            .save_turn("9abc", "ollama", &turn("Bonjour", "Salut"))
+            .unwrap();
+        assert_eq!(store.sessions(10).unwrap()[0].title, "Bonjour");
+        assert!(store.remove_last_turn("9abc").unwrap());
+        assert_eq!(store.sessions(10).unwrap().len(), 1);
+        store.set_system_prompt("9abc", "ollama", None).unwrap();
+        assert_eq!(store.system_prompt("9abc").unwrap(), None);
+        assert!(ConversationStore::from_config(&ConversationConfig {
+            persist: false,
+            ..ConversationConfig::default()
+        })
+        .unwrap()
+        .is_none());
+    }
+
     async fn create_test_manager() -> Result<ConversationManager> {
         let vector_store =
             VectorStore::new("http://localhost:6333", "test_collection", 384).await?;
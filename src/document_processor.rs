@@ -1,16 +1,144 @@
-use anyhow::Result;
-use chrono::Utc;
-use log::{debug, info};
+use anyhow::{Error, Result};
+use chrono::{DateTime, Utc};
+use log::debug;
+#[cfg(feature = "local-embeddings")]
+use log::info;
+use regex::Regex;
+#[cfg(feature = "local-embeddings")]
 use rust_bert::pipelines::sentence_embeddings::{
     SentenceEmbeddingsBuilder, SentenceEmbeddingsModelType,
 };
 use serde::{Deserialize, Serialize};
 use std::path::{Path, PathBuf};
+#[cfg(feature = "local-embeddings")]
 use uuid::Uuid;
+#[cfg(feature = "local-embeddings")]
 use walkdir::WalkDir;
 
+#[cfg(feature = "local-embeddings")]
 use crate::vector_store::{Message, VectorStore};
 
+/// File extensions `extract_file` can read
+pub const SUPPORTED_EXTENSIONS: &[&str] = &["md", "markdown", "txt", "pdf", "html", "htm"];
+
+/// Source files, read as plain text and chunked on function boundaries
+pub const CODE_EXTENSIONS: &[&str] = &[
+    "rs", "py", "js", "ts", "go", "java", "c", "h", "cpp", "hpp", "cs", "rb", "swift", "kt",
+];
+
+/// Plain text of a file together with the details used as RAG metadata
+#[derive(Debug, Clone)]
+pub struct ExtractedFile {
+    pub path: PathBuf,
+    pub text: String,
+    pub modified: Option<DateTime<Utc>>,
+    /// MD5 of the raw file content
+    pub content_hash: String,
+}
+
+impl ExtractedFile {
+    pub fn metadata(&self) -> serde_json::Value {
+        serde_json::json!({
+            "source": "file",
+            "path": self.path.to_string_lossy(),
+            "file_name": self.path.file_name().map(|n| n.to_string_lossy()),
+            "extension": self.path.extension().map(|e| e.to_string_lossy()),
+            "modified_at": self.modified.map(|m| m.to_rfc3339()),
+            "content_hash": self.content_hash,
+            "added_at": Utc::now().to_rfc3339(),
+        })
+    }
+}
+
+/// Reads a .md/.txt/.pdf/.html or source file and extracts its text
+pub async fn extract_file(path: &Path) -> Result<ExtractedFile> {
+    let extension = path
+        .extension()
+        .and_then(|e| e.to_str())
+        .map(|e| e.to_lowercase())
+        .unwrap_or_default();
+    if !is_supported_file(path) {
+        return Err(Error::msg(format!(
+            "Unsupported file type: {} (supported: {})",
+            path.display(),
+            [SUPPORTED_EXTENSIONS, CODE_EXTENSIONS].concat().join(", ")
+        )));
+    }
+
+    let bytes = tokio::fs::read(path).await?;
+    let modified = tokio::fs::metadata(path)
+        .await?
+        .modified()
+        .ok()
+        .map(DateTime::<Utc>::from);
+    let content_hash = content_hash(&bytes);
+
+    let text = match extension.as_str() {
+        "pdf" => {
+            tokio::task::spawn_blocking(move || pdf_extract::extract_text_from_mem(&bytes))
+                .await??
+        }
+        "html" | "htm" => html_to_text(&String::from_utf8_lossy(&bytes)),
+        _ => String::from_utf8_lossy(&bytes).into_owned(),
+    };
+    debug!(
+        "Extracted {} characters from {}",
+        text.len(),
+        path.display()
+    );
+
+    Ok(ExtractedFile {
+        path: path.to_path_buf(),
+        text,
+        modified,
+        content_hash,
+    })
+}
+
+/// MD5 of the raw file content, the same value `extract_file` records
+pub async fn file_hash(path: &Path) -> Result<String> {
+    Ok(content_hash(&tokio::fs::read(path).await?))
+}
+
+fn content_hash(bytes: &[u8]) -> String {
+    format!("{:x}", md5::compute(bytes))
+}
+
+/// Returns true for files `extract_file` can read
+pub fn is_supported_file(path: &Path) -> bool {
+    path.extension()
+        .and_then(|e| e.to_str())
+        .map(|e| e.to_lowercase())
+        .map(|e| {
+            SUPPORTED_EXTENSIONS.contains(&e.as_str()) || CODE_EXTENSIONS.contains(&e.as_str())
+        })
+        .unwrap_or(false)
+}
+
+/// Strips scripts, styles and tags from an HTML page, keeping paragraph breaks
+pub fn html_to_text(html: &str) -> String {
+    let hidden = Regex::new(r"(?is)<(script|style|head)\b.*?</(script|style|head)>").unwrap();
+    let blocks = Regex::new(r"(?i)</?(p|div|br|h[1-6]|li|tr|section|article)\b[^>]*>").unwrap();
+    let tags = Regex::new(r"(?s)<[^>]*>").unwrap();
+
+    let text = hidden.replace_all(html, "");
+    let text = blocks.replace_all(&text, "\n\n");
+    let text = tags.replace_all(&text, "");
+    let text = text
+        .replace("&nbsp;", " ")
+        .replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&#39;", "'")
+        .replace("&amp;", "&");
+
+    text.split("\n\n")
+        .map(|block| block.split_whitespace().collect::<Vec<_>>().join(" "))
+        .filter(|block| !block.is_empty())
+        .collect::<Vec<_>>()
+        .join("\n\n")
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Document {
     pub id: String,
@@ -22,12 +150,14 @@ pub struct Document {
     pub metadata: std::collections::HashMap<String, String>,
 }
 
+#[cfg(feature = "local-embeddings")]
 pub struct DocumentProcessor {
     vector_store: VectorStore,
     model: rust_bert::pipelines::sentence_embeddings::SentenceEmbeddingsModel,
     vault_path: PathBuf,
 }
 
+#[cfg(feature = "local-embeddings")]
 impl DocumentProcessor {
     pub async fn new(vault_path: &str, vector_store: VectorStore) -> Result<Self> {
         let model = SentenceEmbeddingsBuilder::remote(SentenceEmbeddingsModelType::AllMiniLmL6V2)
@@ -107,7 +237,7 @@ impl DocumentProcessor {
         // 在向量数据库中搜索相似文档
         let results = self
             .vector_store
-            .search_similar(query_embedding[0].clone(), limit)
+            .search_similar(query_embedding[0].clone(), limit, None)
             .await?;
 
         let mut documents = Vec::new();
@@ -142,6 +272,32 @@ mod tests {
     use super::*;
     use tempfile::tempdir;
 
+    #[test]
+    fn test_html_to_text() {
+        let html = "<html><head><title>T</title></head><body><script>var x;</script>\
+                    <h1>Title</h1><p>Fish &amp; chips</p><p>Second   line</p></body></html>";
+        assert_eq!(html_to_text(html), "Title\n\nFish & chips\n\nSecond line");
+    }
+
+    #[tokio::test]
+    async fn test_extract_file() -> Result<()> {
+        let temp_dir = tempdir()?;
+        let note = temp_dir.path().join("note.md");
+        tokio::fs::write(&note, "# Note\n\nHello").await?;
+
+        let extracted = extract_file(&note).await?;
+        assert_eq!(extracted.text, "# Note\n\nHello");
+        assert_eq!(extracted.content_hash, file_hash(&note).await?);
+        assert!(extracted.modified.is_some());
+        assert_eq!(extracted.metadata()["file_name"], "note.md");
+
+        let image = temp_dir.path().join("image.png");
+        tokio::fs::write(&image, [0u8; 4]).await?;
+        assert!(extract_file(&image).await.is_err());
+        Ok(())
+    }
+
+    #[cfg(feature = "local-embeddings")]
     async fn create_test_processor() -> Result<DocumentProcessor> {
         let temp_dir = tempdir()?;
         let vector_store =
@@ -149,6 +305,7 @@ mod tests {
         DocumentProcessor::new(temp_dir.path().to_str().unwrap(), vector_store).await
     }
 
+    #[cfg(feature = "local-embeddings")]
     #[tokio::test]
     async fn test_process_document() -> Result<()> {
         let processor = create_test_processor().await?;
@@ -164,6 +321,7 @@ mod tests {
         Ok(())
     }
 
+    #[cfg(feature = "local-embeddings")]
     #[tokio::test]
     async fn test_search_similar_documents() -> Result<()> {
         let processor = create_test_processor().await?;
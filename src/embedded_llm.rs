@@ -0,0 +1,157 @@
+//! Embedded LLM Module: Local GGUF inference without an external server
+//!
+//! This module provides:
+//! - Loading of GGUF models through the llama.cpp bindings
+//! - Prompt construction from chat messages
+//! - Blocking generation run on a worker thread with cancellation support
+//!
+//! Key Components:
+//! - `EmbeddedModel`: A loaded GGUF model and its session parameters
+//!
+//! Only compiled with the `embedded-llm` feature.
+//!
+//! Author: arkSong <arksong2018@gmail.com>
+//! Version: 1.0.0
+//! License: MIT
+
+use anyhow::Result;
+use llama_cpp::standard_sampler::StandardSampler;
+use llama_cpp::{LlamaModel, LlamaParams, SessionParams};
+use log::{debug, info};
+use serde_json::Value;
+use tokio_util::sync::CancellationToken;
+
+use crate::llm_client::{EmbeddedConfig, LLMError};
+use crate::usage::TokenUsage;
+
+/// Marker the model tends to produce when it starts writing the next turn
+const TURN_MARKER: &str = "\nUser:";
+
+#[derive(Clone)]
+pub struct EmbeddedModel {
+    model: LlamaModel,
+    context_size: u32,
+    max_tokens: usize,
+}
+
+impl EmbeddedModel {
+    /// Loads the GGUF file configured in `embedded.model_path`.
+    /// This is slow for large models, call it from a blocking context.
+    pub fn load(config: &EmbeddedConfig) -> Result<Self> {
+        info!("Loading embedded model from {}", config.model_path);
+        let model = LlamaModel::load_from_file(&config.model_path, LlamaParams::default())
+            .map_err(|e| {
+                LLMError::ModelNotFound(format!("Failed to load {}: {}", config.model_path, e))
+            })?;
+
+        Ok(Self {
+            model,
+            context_size: config.context_size,
+            max_tokens: config.max_tokens,
+        })
+    }
+
+    /// Builds a plain-text chat transcript ending with the assistant's turn
+    pub fn build_prompt(messages: &[Value]) -> String {
+        let mut prompt = String::new();
+        for message in messages {
+            let content = message["content"].as_str().unwrap_or("");
+            if content.trim().is_empty() {
+                continue;
+            }
+            let speaker = match message["role"].as_str().unwrap_or("user") {
+                "system" => "System",
+                "assistant" => "Assistant",
+                "tool" => "Tool",
+                _ => "User",
+            };
+            prompt.push_str(&format!("{}: {}\n", speaker, content.trim()));
+        }
+        prompt.push_str("Assistant:");
+        prompt
+    }
+
+    /// Generates a completion for `messages`, stopping early when `cancel` is triggered
+    pub async fn complete(
+        &self,
+        messages: &[Value],
+        cancel: &CancellationToken,
+    ) -> Result<(String, TokenUsage)> {
+        let prompt = Self::build_prompt(messages);
+        let model = self.clone();
+        let cancel = cancel.clone();
+
+        tokio::task::spawn_blocking(move || model.generate(&prompt, &cancel)).await?
+    }
+
+    fn generate(&self, prompt: &str, cancel: &CancellationToken) -> Result<(String, TokenUsage)> {
+        let prompt_tokens = self
+            .model
+            .tokenize_bytes(prompt.as_bytes(), true, true)
+            .map(|tokens| tokens.len() as u64)
+            .unwrap_or(0);
+        if prompt_tokens >= self.context_size as u64 {
+            return Err(LLMError::ContextTooLong(format!(
+                "prompt has {} tokens, context size is {}",
+                prompt_tokens, self.context_size
+            ))
+            .into());
+        }
+
+        let mut session_params = SessionParams::default();
+        session_params.n_ctx = self.context_size;
+
+        let mut session = self
+            .model
+            .create_session(session_params)
+            .map_err(|e| LLMError::Config(format!("Failed to create session: {}", e)))?;
+        session
+            .advance_context(prompt)
+            .map_err(|e| LLMError::InvalidResponse(format!("Failed to evaluate prompt: {}", e)))?;
+
+        let completions = session
+            .start_completing_with(StandardSampler::default(), self.max_tokens)
+            .map_err(|e| LLMError::InvalidResponse(format!("Failed to start completion: {}", e)))?
+            .into_strings();
+
+        let mut output = String::new();
+        let mut completion_tokens = 0;
+        for piece in completions {
+            if cancel.is_cancelled() {
+                return Err(LLMError::Cancelled.into());
+            }
+            output.push_str(&piece);
+            completion_tokens += 1;
+
+            if let Some(pos) = output.find(TURN_MARKER) {
+                output.truncate(pos);
+                break;
+            }
+        }
+
+        debug!("Embedded model generated {} tokens", completion_tokens);
+        Ok((
+            output.trim().to_string(),
+            TokenUsage::new(prompt_tokens, completion_tokens),
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_build_prompt() {
+        let messages = vec![
+            json!({"role": "system", "content": "Be brief."}),
+            json!({"role": "user", "content": "Hello"}),
+        ];
+
+        assert_eq!(
+            EmbeddedModel::build_prompt(&messages),
+            "System: Be brief.\nUser: Hello\nAssistant:"
+        );
+    }
+}
@@ -0,0 +1,518 @@
+//! Embedder Module: Pluggable embedding backends for the RAG pipeline
+//!
+//! This module provides:
+//! - A common `Embedder` trait for turning text into vectors
+//! - Local embeddings through rust-bert (feature `local-embeddings`), loaded
+//!   lazily on the first available CUDA, MPS or CPU device
+//! - Remote embeddings through the Ollama and OpenAI APIs
+//!
+//! Key Components:
+//! - `Embedder`: Trait implemented by every backend
+//! - `EmbedderConfig`: Backend selection read from the `rag.embedder` section of `config.yaml`
+//! - `create_embedder`: Builds the configured backend
+//!
+//! Author: arkSong <arksong2018@gmail.com>
+//! Version: 1.0.0
+//! License: MIT
+
+use anyhow::{Error, Result};
+use async_trait::async_trait;
+use log::{debug, info};
+use reqwest::Client;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::env;
+use std::fmt;
+use std::str::FromStr;
+
+#[cfg(feature = "local-embeddings")]
+use rust_bert::pipelines::sentence_embeddings::{
+    SentenceEmbeddingsBuilder, SentenceEmbeddingsModel, SentenceEmbeddingsModelType,
+};
+
+#[async_trait]
+pub trait Embedder: Send + Sync {
+    /// Backend and model name, used in logs
+    fn name(&self) -> String;
+
+    /// Embeds every text, returning one vector per input in the same order
+    async fn embed(&self, texts: &[&str]) -> Result<Vec<Vec<f32>>>;
+
+    /// Length of the produced vectors. Remote backends embed a probe text
+    /// since the dimension depends on the model served.
+    async fn dimension(&self) -> Result<usize> {
+        self.embed(&["dimension probe"])
+            .await?
+            .first()
+            .map(Vec::len)
+            .ok_or_else(|| Error::msg("Embedder returned no vector"))
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum EmbedderProvider {
+    #[serde(rename = "rust-bert")]
+    RustBert,
+    #[serde(rename = "ollama")]
+    Ollama,
+    #[serde(rename = "openai")]
+    OpenAI,
+}
+
+impl Default for EmbedderProvider {
+    fn default() -> Self {
+        if cfg!(feature = "local-embeddings") {
+            EmbedderProvider::RustBert
+        } else {
+            EmbedderProvider::Ollama
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct EmbedderConfig {
+    pub provider: EmbedderProvider,
+    /// Defaults to all-MiniLM-L6-v2, nomic-embed-text or text-embedding-3-small
+    pub model: Option<String>,
+    /// Server address for the Ollama and OpenAI backends
+    pub url: Option<String>,
+    /// OpenAI API key, falls back to the OPENAI_API_KEY environment variable
+    pub api_key: Option<String>,
+    /// Devices tried in order for the rust-bert model, e.g. `["cuda", "mps", "cpu"]`.
+    /// Unavailable devices are skipped; the CPU is always the last resort.
+    pub devices: Vec<EmbeddingDevice>,
+}
+
+/// Device the rust-bert model runs on
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EmbeddingDevice {
+    Cpu,
+    Cuda(usize),
+    Mps,
+}
+
+impl FromStr for EmbeddingDevice {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.trim().to_lowercase().as_str() {
+            "cpu" => Ok(EmbeddingDevice::Cpu),
+            "cuda" | "gpu" => Ok(EmbeddingDevice::Cuda(0)),
+            "mps" => Ok(EmbeddingDevice::Mps),
+            other => match other.strip_prefix("cuda:").map(str::parse) {
+                Some(Ok(index)) => Ok(EmbeddingDevice::Cuda(index)),
+                _ => Err(Error::msg(format!(
+                    "Unknown embedding device: {} (expected cpu, cuda, cuda:N or mps)",
+                    s
+                ))),
+            },
+        }
+    }
+}
+
+impl fmt::Display for EmbeddingDevice {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            EmbeddingDevice::Cpu => write!(f, "cpu"),
+            EmbeddingDevice::Cuda(index) => write!(f, "cuda:{}", index),
+            EmbeddingDevice::Mps => write!(f, "mps"),
+        }
+    }
+}
+
+impl Serialize for EmbeddingDevice {
+    fn serialize<S: serde::Serializer>(
+        &self,
+        serializer: S,
+    ) -> std::result::Result<S::Ok, S::Error> {
+        serializer.serialize_str(&self.to_string())
+    }
+}
+
+impl<'de> Deserialize<'de> for EmbeddingDevice {
+    fn deserialize<D: serde::Deserializer<'de>>(
+        deserializer: D,
+    ) -> std::result::Result<Self, D::Error> {
+        let s = String::deserialize(deserializer)?;
+        s.parse().map_err(serde::de::Error::custom)
+    }
+}
+
+impl EmbedderConfig {
+    pub fn model(&self) -> &str {
+        self.model.as_deref().unwrap_or(match self.provider {
+            EmbedderProvider::RustBert => "all-MiniLM-L6-v2",
+            EmbedderProvider::Ollama => "nomic-embed-text",
+            EmbedderProvider::OpenAI => "text-embedding-3-small",
+        })
+    }
+
+    /// Configured device chain, ending with the CPU. Defaults to CUDA, then
+    /// MPS, then the CPU.
+    pub fn device_chain(&self) -> Vec<EmbeddingDevice> {
+        let mut devices = if self.devices.is_empty() {
+            vec![EmbeddingDevice::Cuda(0), EmbeddingDevice::Mps]
+        } else {
+            self.devices.clone()
+        };
+        if !devices.contains(&EmbeddingDevice::Cpu) {
+            devices.push(EmbeddingDevice::Cpu);
+        }
+        devices
+    }
+
+    pub fn url(&self) -> &str {
+        self.url.as_deref().unwrap_or(match self.provider {
+            EmbedderProvider::RustBert => "",
+            EmbedderProvider::Ollama => "http://localhost:11434",
+            EmbedderProvider::OpenAI => "https://api.openai.com/v1",
+        })
+    }
+}
+
+/// Builds the backend selected in `config`
+pub fn create_embedder(config: &EmbedderConfig) -> Result<Box<dyn Embedder>> {
+    info!(
+        "Using {:?} embeddings with model {}",
+        config.provider,
+        config.model()
+    );
+    match config.provider {
+        EmbedderProvider::RustBert => create_local_embedder(config),
+        EmbedderProvider::Ollama => Ok(Box::new(OllamaEmbedder::new(config.url(), config.model()))),
+        EmbedderProvider::OpenAI => {
+            let api_key = match &config.api_key {
+                Some(key) => key.clone(),
+                None => env::var("OPENAI_API_KEY").map_err(|_| {
+                    Error::msg("OpenAI embeddings require rag.embedder.api_key or OPENAI_API_KEY")
+                })?,
+            };
+            Ok(Box::new(OpenAIEmbedder::new(
+                config.url(),
+                config.model(),
+                &api_key,
+            )))
+        }
+    }
+}
+
+#[cfg(feature = "local-embeddings")]
+fn create_local_embedder(config: &EmbedderConfig) -> Result<Box<dyn Embedder>> {
+    Ok(Box::new(RustBertEmbedder::new(
+        config.model(),
+        config.device_chain(),
+    )?))
+}
+
+#[cfg(not(feature = "local-embeddings"))]
+fn create_local_embedder(_config: &EmbedderConfig) -> Result<Box<dyn Embedder>> {
+    Err(Error::msg(
+        "The rust-bert embedder requires building with `--features local-embeddings`",
+    ))
+}
+
+/// Sentence-transformers model run locally through rust-bert. The model is
+/// loaded on first use, on the first available device of the chain.
+#[cfg(feature = "local-embeddings")]
+pub struct RustBertEmbedder {
+    model_name: String,
+    model_type: SentenceEmbeddingsModelType,
+    devices: Vec<EmbeddingDevice>,
+    model: tokio::sync::OnceCell<std::sync::Mutex<SentenceEmbeddingsModel>>,
+}
+
+#[cfg(feature = "local-embeddings")]
+impl RustBertEmbedder {
+    pub fn new(model_name: &str, devices: Vec<EmbeddingDevice>) -> Result<Self> {
+        let model_type = match model_name {
+            "all-MiniLM-L6-v2" => SentenceEmbeddingsModelType::AllMiniLmL6V2,
+            "all-MiniLM-L12-v2" => SentenceEmbeddingsModelType::AllMiniLmL12V2,
+            "all-distilroberta-v1" => SentenceEmbeddingsModelType::AllDistilrobertaV1,
+            "paraphrase-albert-small-v2" => SentenceEmbeddingsModelType::ParaphraseAlbertSmallV2,
+            "sentence-t5-base" => SentenceEmbeddingsModelType::SentenceT5Base,
+            other => {
+                return Err(Error::msg(format!(
+                    "Unsupported rust-bert embedding model: {}",
+                    other
+                )))
+            }
+        };
+
+        Ok(Self {
+            model_name: model_name.to_string(),
+            model_type,
+            devices,
+            model: tokio::sync::OnceCell::new(),
+        })
+    }
+
+    async fn model(&self) -> Result<&std::sync::Mutex<SentenceEmbeddingsModel>> {
+        self.model
+            .get_or_try_init(|| async {
+                let model_type = self.model_type.clone();
+                let devices = self.devices.clone();
+                let model =
+                    tokio::task::spawn_blocking(move || load_model(model_type, &devices)).await??;
+                Ok::<_, Error>(std::sync::Mutex::new(model))
+            })
+            .await
+    }
+}
+
+/// Loads the model on the first device of `devices` that is available and
+/// succeeds, e.g. skipping CUDA when libtorch was built without it
+#[cfg(feature = "local-embeddings")]
+fn load_model(
+    model_type: SentenceEmbeddingsModelType,
+    devices: &[EmbeddingDevice],
+) -> Result<SentenceEmbeddingsModel> {
+    let mut last_error = None;
+    for device in devices {
+        let tch_device = match device {
+            EmbeddingDevice::Cpu => tch::Device::Cpu,
+            EmbeddingDevice::Cuda(index) if *index < tch::Cuda::device_count() as usize => {
+                tch::Device::Cuda(*index)
+            }
+            EmbeddingDevice::Mps if tch::utils::has_mps() => tch::Device::Mps,
+            _ => {
+                debug!("Embedding device {} is not available", device);
+                continue;
+            }
+        };
+
+        match SentenceEmbeddingsBuilder::remote(model_type.clone())
+            .with_device(tch_device)
+            .create_model()
+        {
+            Ok(model) => {
+                info!("Loaded embedding model on {}", device);
+                return Ok(model);
+            }
+            Err(e) => {
+                log::warn!("Failed to load embedding model on {}: {}", device, e);
+                last_error = Some(e);
+            }
+        }
+    }
+
+    Err(match last_error {
+        Some(e) => Error::from(e).context("Failed to load embedding model"),
+        None => Error::msg("No embedding device available"),
+    })
+}
+
+#[cfg(feature = "local-embeddings")]
+#[async_trait]
+impl Embedder for RustBertEmbedder {
+    fn name(&self) -> String {
+        format!("rust-bert/{}", self.model_name)
+    }
+
+    async fn embed(&self, texts: &[&str]) -> Result<Vec<Vec<f32>>> {
+        let model = self
+            .model()
+            .await?
+            .lock()
+            .map_err(|_| Error::msg("Embedding model lock poisoned"))?;
+        Ok(model.encode(texts)?)
+    }
+
+    async fn dimension(&self) -> Result<usize> {
+        let model = self
+            .model()
+            .await?
+            .lock()
+            .map_err(|_| Error::msg("Embedding model lock poisoned"))?;
+        Ok(model.get_embedding_dim()? as usize)
+    }
+}
+
+/// Embeddings served by Ollama's `/api/embed` endpoint
+pub struct OllamaEmbedder {
+    client: Client,
+    url: String,
+    model: String,
+}
+
+impl OllamaEmbedder {
+    pub fn new(url: &str, model: &str) -> Self {
+        Self {
+            client: Client::new(),
+            url: format!("{}/api/embed", url.trim_end_matches('/')),
+            model: model.to_string(),
+        }
+    }
+}
+
+#[async_trait]
+impl Embedder for OllamaEmbedder {
+    fn name(&self) -> String {
+        format!("ollama/{}", self.model)
+    }
+
+    async fn embed(&self, texts: &[&str]) -> Result<Vec<Vec<f32>>> {
+        if texts.is_empty() {
+            return Ok(Vec::new());
+        }
+        debug!("Requesting {} embeddings from {}", texts.len(), self.url);
+
+        let response: Value = self
+            .client
+            .post(&self.url)
+            .json(&json!({ "model": self.model, "input": texts }))
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await?;
+
+        let embeddings = response["embeddings"]
+            .as_array()
+            .ok_or_else(|| Error::msg("Invalid Ollama embedding response"))?
+            .iter()
+            .map(parse_vector)
+            .collect::<Result<Vec<_>>>()?;
+        check_count(embeddings, texts.len())
+    }
+}
+
+/// Embeddings served by an OpenAI-compatible `/embeddings` endpoint
+pub struct OpenAIEmbedder {
+    client: Client,
+    url: String,
+    model: String,
+    api_key: String,
+}
+
+impl OpenAIEmbedder {
+    pub fn new(url: &str, model: &str, api_key: &str) -> Self {
+        Self {
+            client: Client::new(),
+            url: format!("{}/embeddings", url.trim_end_matches('/')),
+            model: model.to_string(),
+            api_key: api_key.to_string(),
+        }
+    }
+}
+
+#[async_trait]
+impl Embedder for OpenAIEmbedder {
+    fn name(&self) -> String {
+        format!("openai/{}", self.model)
+    }
+
+    async fn embed(&self, texts: &[&str]) -> Result<Vec<Vec<f32>>> {
+        if texts.is_empty() {
+            return Ok(Vec::new());
+        }
+        debug!("Requesting {} embeddings from {}", texts.len(), self.url);
+
+        let response: Value = self
+            .client
+            .post(&self.url)
+            .bearer_auth(&self.api_key)
+            .json(&json!({ "model": self.model, "input": texts }))
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await?;
+
+        check_count(parse_openai_embeddings(&response)?, texts.len())
+    }
+}
+
+/// Reads `data[].embedding`, ordered by `data[].index`
+fn parse_openai_embeddings(response: &Value) -> Result<Vec<Vec<f32>>> {
+    let mut data: Vec<&Value> = response["data"]
+        .as_array()
+        .ok_or_else(|| Error::msg("Invalid OpenAI embedding response"))?
+        .iter()
+        .collect();
+    data.sort_by_key(|item| item["index"].as_u64().unwrap_or(0));
+
+    data.into_iter()
+        .map(|item| parse_vector(&item["embedding"]))
+        .collect()
+}
+
+fn parse_vector(value: &Value) -> Result<Vec<f32>> {
+    value
+        .as_array()
+        .ok_or_else(|| Error::msg("Embedding is not an array"))?
+        .iter()
+        .map(|v| {
+            v.as_f64()
+                .map(|f| f as f32)
+                .ok_or_else(|| Error::msg("Embedding contains a non-numeric value"))
+        })
+        .collect()
+}
+
+fn check_count(embeddings: Vec<Vec<f32>>, expected: usize) -> Result<Vec<Vec<f32>>> {
+    if embeddings.len() != expected {
+        return Err(Error::msg(format!(
+            "Expected {} embeddings, got {}",
+            expected,
+            embeddings.len()
+        )));
+    }
+    Ok(embeddings)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_config_defaults() {
+        let config: EmbedderConfig = serde_yaml::from_str("provider: ollama").unwrap();
+        assert_eq!(config.provider, EmbedderProvider::Ollama);
+        assert_eq!(config.model(), "nomic-embed-text");
+        assert_eq!(config.url(), "http://localhost:11434");
+
+        let config: EmbedderConfig =
+            serde_yaml::from_str("provider: openai\nmodel: text-embedding-3-large").unwrap();
+        assert_eq!(config.model(), "text-embedding-3-large");
+        assert_eq!(config.url(), "https://api.openai.com/v1");
+    }
+
+    #[test]
+    fn test_device_chain() {
+        let config: EmbedderConfig =
+            serde_yaml::from_str("provider: rust-bert\ndevices: [\"cuda:1\", mps]").unwrap();
+        assert_eq!(
+            config.device_chain(),
+            vec![
+                EmbeddingDevice::Cuda(1),
+                EmbeddingDevice::Mps,
+                EmbeddingDevice::Cpu
+            ]
+        );
+        assert_eq!(
+            EmbedderConfig::default().device_chain(),
+            vec![
+                EmbeddingDevice::Cuda(0),
+                EmbeddingDevice::Mps,
+                EmbeddingDevice::Cpu
+            ]
+        );
+        assert!(serde_yaml::from_str::<EmbedderConfig>("devices: [tpu]").is_err());
+    }
+
+    #[test]
+    fn test_parse_openai_embeddings() {
+        let response = json!({
+            "data": [
+                {"index": 1, "embedding": [0.5, 0.25]},
+                {"index": 0, "embedding": [1.0, 0.0]}
+            ]
+        });
+        let embeddings = parse_openai_embeddings(&response).unwrap();
+        assert_eq!(embeddings, vec![vec![1.0, 0.0], vec![0.5, 0.25]]);
+
+        assert!(parse_openai_embeddings(&json!({"error": "bad key"})).is_err());
+        assert!(check_count(embeddings, 3).is_err());
+    }
+}
@@ -0,0 +1,671 @@
+//! File Archive Module: zip and tar.gz archives for `FileServer`
+//!
+//! This module provides:
+//! - Packing files and directories into `.zip` or `.tar.gz` archives
+//! - Unpacking such archives into a directory
+//! - Path-safety checks for every extracted entry
+//!
+//! Entries are named relative to the parent of each packed path, so packing
+//! `reports` produces `reports/...` entries. Symbolic links are never packed.
+//!
+//! Extraction refuses absolute entry names, `..`, links and other special
+//! entries, and never writes through a symbolic link, so an archive cannot
+//! place files outside its destination. Existing files are only replaced
+//! when asked to, and archives expanding to more than 1 GB (or the given
+//! limit) are refused.
+//!
+//! Every entry is checked before the first one is written, so a refused
+//! archive leaves the destination untouched. Should writing still fail, for
+//! instance because an entry is larger than its header claims, the files
+//! and directories written so far are removed again.
+//!
+//! Author: arkSong <arksong2018@gmail.com>
+//! Version: 1.0.0
+//! License: MIT
+
+use anyhow::{Error, Result};
+use flate2::read::GzDecoder;
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use log::warn;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Read};
+use std::path::{Component, Path, PathBuf};
+use walkdir::WalkDir;
+use zip::write::FileOptions;
+use zip::{CompressionMethod, ZipArchive, ZipWriter};
+
+use crate::tool_error::{invalid_arguments, ToolError, ToolErrorKind};
+
+/// Most bytes an archive may expand to
+pub const MAX_EXTRACTED_SIZE: u64 = 1024 * 1024 * 1024;
+
+/// Filter deciding which entries, named relative to the destination, may be
+/// extracted; the flag tells whether the entry is a directory
+pub type EntryFilter<'a> = &'a (dyn Fn(&Path, bool) -> bool + Sync);
+
+/// How `extract` treats existing files and which entries it accepts
+#[derive(Default)]
+pub struct ExtractOptions<'a> {
+    /// Replace existing files
+    pub overwrite: bool,
+    /// Bytes the archive may expand to, at most `MAX_EXTRACTED_SIZE`
+    pub max_size: Option<u64>,
+    /// Entries it rejects fail the extraction. Unset accepts all.
+    pub permits: Option<EntryFilter<'a>>,
+}
+
+/// Supported archive formats
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ArchiveFormat {
+    Zip,
+    TarGz,
+}
+
+impl ArchiveFormat {
+    /// Parses a format name (`zip`, `tar.gz` or `tgz`), or detects the format
+    /// from the extension of `path` when no name is given
+    pub fn detect(name: Option<&str>, path: &Path) -> Result<Self> {
+        let name = match name {
+            Some(name) => name.to_lowercase(),
+            None => {
+                let file_name = path
+                    .file_name()
+                    .map(|name| name.to_string_lossy().to_lowercase())
+                    .unwrap_or_default();
+                if file_name.ends_with(".tar.gz") {
+                    "tar.gz".to_string()
+                } else {
+                    file_name.rsplit('.').next().unwrap_or_default().to_string()
+                }
+            }
+        };
+        match name.as_str() {
+            "zip" => Ok(Self::Zip),
+            "tar.gz" | "tgz" => Ok(Self::TarGz),
+            _ => Err(invalid_arguments(format!(
+                "Unsupported archive format: {} (use zip or tar.gz)",
+                path.display()
+            ))),
+        }
+    }
+}
+
+/// Packs `sources` into a new archive at `archive` and returns the number of
+/// entries written. `root` bounds the entry names: packing `root` itself
+/// names entries relative to it.
+pub fn create(
+    archive: &Path,
+    root: &Path,
+    sources: &[PathBuf],
+    format: ArchiveFormat,
+) -> Result<usize> {
+    let mut entries = Vec::new();
+    for source in sources {
+        let base = match source.parent() {
+            Some(parent) if source != root => parent,
+            _ => source.as_path(),
+        };
+        for entry in WalkDir::new(source).sort_by_file_name() {
+            let entry = entry?;
+            if entry.path_is_symlink() || entry.path() == archive {
+                continue;
+            }
+            let name = entry_name(entry.path().strip_prefix(base)?);
+            if !name.is_empty() {
+                entries.push((name, entry.path().to_path_buf(), entry.file_type().is_dir()));
+            }
+        }
+    }
+
+    let file = File::create(archive)?;
+    match format {
+        ArchiveFormat::Zip => {
+            let mut writer = ZipWriter::new(file);
+            let options = FileOptions::default().compression_method(CompressionMethod::Deflated);
+            for (name, path, is_dir) in &entries {
+                if *is_dir {
+                    writer.add_directory(name.as_str(), options)?;
+                } else {
+                    writer.start_file(name.as_str(), options)?;
+                    io::copy(&mut File::open(path)?, &mut writer)?;
+                }
+            }
+            writer.finish()?;
+        }
+        ArchiveFormat::TarGz => {
+            let mut builder = tar::Builder::new(GzEncoder::new(file, Compression::default()));
+            for (name, path, is_dir) in &entries {
+                if *is_dir {
+                    builder.append_dir(name, path)?;
+                } else {
+                    builder.append_path_with_name(path, name)?;
+                }
+            }
+            builder.into_inner()?.finish()?;
+        }
+    }
+    Ok(entries.len())
+}
+
+/// Unpacks `archive` into `destination` and returns the names of the
+/// extracted files and directories
+pub fn extract(
+    archive: &Path,
+    destination: &Path,
+    format: ArchiveFormat,
+    options: &ExtractOptions,
+) -> Result<Vec<String>> {
+    fs::create_dir_all(destination)?;
+    let max_size = options
+        .max_size
+        .map_or(MAX_EXTRACTED_SIZE, |max| max.min(MAX_EXTRACTED_SIZE));
+    let mut extractor = Extractor {
+        destination: destination.canonicalize()?,
+        options,
+        max_size,
+        remaining: max_size,
+        names: Vec::new(),
+        created: Vec::new(),
+    };
+
+    // Check every entry before writing any, so a refused archive leaves
+    // the destination as it was
+    let mut declared: u64 = 0;
+    for (name, kind) in list_entries(archive, format)? {
+        match kind {
+            EntryKind::Dir => extractor.check(&name, true)?,
+            EntryKind::File { size } => {
+                extractor.check(&name, false)?;
+                declared = declared.saturating_add(size);
+                if declared > max_size {
+                    return Err(too_large(max_size));
+                }
+            }
+            EntryKind::Other => {
+                return Err(invalid_arguments(format!(
+                    "Unsupported archive entry: {}",
+                    name
+                )))
+            }
+        }
+    }
+
+    match extractor.unpack(archive, format) {
+        Ok(()) => Ok(extractor.names),
+        Err(e) => {
+            extractor.remove_created();
+            Err(e)
+        }
+    }
+}
+
+/// What an archive entry holds
+enum EntryKind {
+    Dir,
+    /// A regular file of `size` bytes, as its header claims
+    File {
+        size: u64,
+    },
+    /// Links and other special entries, which are never extracted
+    Other,
+}
+
+/// The names and kinds of the entries of `archive`, read without
+/// extracting anything
+fn list_entries(archive: &Path, format: ArchiveFormat) -> Result<Vec<(String, EntryKind)>> {
+    let mut entries = Vec::new();
+    match format {
+        ArchiveFormat::Zip => {
+            let mut zip = ZipArchive::new(File::open(archive)?)?;
+            for index in 0..zip.len() {
+                let file = zip.by_index(index)?;
+                let kind = if file.is_dir() {
+                    EntryKind::Dir
+                } else if file.unix_mode().is_some_and(is_symlink_mode) {
+                    EntryKind::Other
+                } else {
+                    EntryKind::File { size: file.size() }
+                };
+                entries.push((file.name().to_string(), kind));
+            }
+        }
+        ArchiveFormat::TarGz => {
+            let mut tar = tar::Archive::new(GzDecoder::new(File::open(archive)?));
+            for entry in tar.entries()? {
+                let entry = entry?;
+                let entry_type = entry.header().entry_type();
+                let kind = if entry_type.is_dir() {
+                    EntryKind::Dir
+                } else if entry_type.is_file() {
+                    EntryKind::File { size: entry.size() }
+                } else {
+                    EntryKind::Other
+                };
+                entries.push((entry.path()?.to_string_lossy().into_owned(), kind));
+            }
+        }
+    }
+    Ok(entries)
+}
+
+/// Whether the Unix `mode` of a zip entry marks a symbolic link
+fn is_symlink_mode(mode: u32) -> bool {
+    mode & 0o170000 == 0o120000
+}
+
+/// The error of an archive expanding to more than `max_size` bytes
+fn too_large(max_size: u64) -> Error {
+    invalid_arguments(format!("Archive expands to more than {} bytes", max_size))
+}
+
+/// Archive entry name of a relative path, always separated by `/`
+fn entry_name(path: &Path) -> String {
+    path.components()
+        .filter(|component| matches!(component, Component::Normal(_)))
+        .map(|component| component.as_os_str().to_string_lossy())
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+/// Writes archive entries below a destination directory
+struct Extractor<'a> {
+    destination: PathBuf,
+    options: &'a ExtractOptions<'a>,
+    max_size: u64,
+    remaining: u64,
+    names: Vec<String>,
+    /// Files and directories that did not exist before, in the order they
+    /// were created
+    created: Vec<PathBuf>,
+}
+
+impl Extractor<'_> {
+    /// The path of an entry relative to the destination, refusing names
+    /// that would leave it or that the filter rejects. A name like `./` is
+    /// the destination itself, an empty path.
+    fn relative(&self, name: &str, is_dir: bool) -> Result<PathBuf> {
+        let relative = Path::new(name);
+        let unsafe_path = relative
+            .components()
+            .any(|component| !matches!(component, Component::Normal(_) | Component::CurDir));
+        if unsafe_path || name.contains('\\') {
+            return Err(unsafe_path(name));
+        }
+        let relative = PathBuf::from(entry_name(relative));
+        if relative.as_os_str().is_empty() {
+            return Ok(relative);
+        }
+        if let Some(permits) = self.options.permits {
+            if !permits(&relative, is_dir) {
+                return Err(Error::new(ToolError::new(
+                    ToolErrorKind::PermissionDenied,
+                    format!("Archive entry not permitted: {}", name),
+                )));
+            }
+        }
+        Ok(relative)
+    }
+
+    /// Fails if the entry `name` cannot be extracted, without writing
+    /// anything: its path is refused, leads through a symbolic link, or,
+    /// for a file, a directory or a file not to be replaced is in the way
+    fn check(&self, name: &str, is_dir: bool) -> Result<()> {
+        let target = self.destination.join(self.relative(name, is_dir)?);
+        let through_link = target
+            .ancestors()
+            .take_while(|path| *path != self.destination)
+            .any(|path| {
+                fs::symlink_metadata(path).is_ok_and(|metadata| metadata.file_type().is_symlink())
+            });
+        if through_link {
+            return Err(unsafe_path(name));
+        }
+        if !is_dir {
+            if target.is_dir() {
+                return Err(invalid_arguments(format!(
+                    "A directory is in the way: {}",
+                    name
+                )));
+            }
+            if target.exists() && !self.options.overwrite {
+                return Err(already_exists(name));
+            }
+        }
+        Ok(())
+    }
+
+    /// Extracts every entry of `archive`
+    fn unpack(&mut self, archive: &Path, format: ArchiveFormat) -> Result<()> {
+        match format {
+            ArchiveFormat::Zip => {
+                let mut zip = ZipArchive::new(File::open(archive)?)?;
+                for index in 0..zip.len() {
+                    let mut file = zip.by_index(index)?;
+                    let name = file.name().to_string();
+                    if file.is_dir() {
+                        self.dir(&name)?;
+                    } else if file.unix_mode().is_some_and(is_symlink_mode) {
+                        return Err(invalid_arguments(format!(
+                            "Unsupported archive entry: {}",
+                            name
+                        )));
+                    } else {
+                        self.file(&name, &mut file)?;
+                    }
+                }
+            }
+            ArchiveFormat::TarGz => {
+                let mut tar = tar::Archive::new(GzDecoder::new(File::open(archive)?));
+                for entry in tar.entries()? {
+                    let mut entry = entry?;
+                    let name = entry.path()?.to_string_lossy().into_owned();
+                    let entry_type = entry.header().entry_type();
+                    if entry_type.is_dir() {
+                        self.dir(&name)?;
+                    } else if entry_type.is_file() {
+                        self.file(&name, &mut entry)?;
+                    } else {
+                        return Err(invalid_arguments(format!(
+                            "Unsupported archive entry: {}",
+                            name
+                        )));
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Resolves an entry name below the destination and creates the
+    /// directories leading to it
+    fn target(&mut self, name: &str, is_dir: bool) -> Result<PathBuf> {
+        let relative = self.relative(name, is_dir)?;
+        if relative.as_os_str().is_empty() {
+            return Ok(self.destination.clone());
+        }
+        let target = self.destination.join(relative);
+        if let Some(parent) = target.parent() {
+            self.create_dirs(parent)?;
+            if !parent.canonicalize()?.starts_with(&self.destination) {
+                return Err(unsafe_path(name));
+            }
+        }
+        if fs::symlink_metadata(&target).is_ok_and(|metadata| metadata.file_type().is_symlink()) {
+            return Err(unsafe_path(name));
+        }
+        Ok(target)
+    }
+
+    /// Creates `dir` and its missing parents, remembering those it created
+    fn create_dirs(&mut self, dir: &Path) -> Result<()> {
+        let missing: Vec<PathBuf> = dir
+            .ancestors()
+            .take_while(|path| !path.exists())
+            .map(Path::to_path_buf)
+            .collect();
+        fs::create_dir_all(dir)?;
+        self.created.extend(missing.into_iter().rev());
+        Ok(())
+    }
+
+    /// Deletes what this extraction created, newest first. Directories
+    /// still holding other files are kept.
+    fn remove_created(&mut self) {
+        for path in self.created.drain(..).rev() {
+            let removed = if path.is_dir() {
+                fs::remove_dir(&path)
+            } else {
+                fs::remove_file(&path)
+            };
+            if let Err(e) = removed {
+                warn!("Failed to remove {}: {}", path.display(), e);
+            }
+        }
+    }
+
+    fn dir(&mut self, name: &str) -> Result<()> {
+        let target = self.target(name, true)?;
+        if target == self.destination {
+            return Ok(());
+        }
+        self.create_dirs(&target)?;
+        self.names.push(entry_name(Path::new(name)));
+        Ok(())
+    }
+
+    fn file(&mut self, name: &str, reader: &mut impl Read) -> Result<()> {
+        let target = self.target(name, false)?;
+        if target.is_dir() {
+            return Err(invalid_arguments(format!(
+                "A directory is in the way: {}",
+                name
+            )));
+        }
+        let existed = target.exists();
+        let mut options = OpenOptions::new();
+        options.write(true);
+        if self.options.overwrite {
+            options.create(true).truncate(true);
+        } else {
+            options.create_new(true);
+        }
+        let mut file = options.open(&target).map_err(|e| {
+            if e.kind() == io::ErrorKind::AlreadyExists {
+                already_exists(name)
+            } else {
+                e.into()
+            }
+        })?;
+
+        let copied = io::copy(&mut reader.take(self.remaining + 1), &mut file);
+        drop(file);
+        let written = match copied {
+            Ok(written) if written <= self.remaining => written,
+            copied => {
+                // A partly written file is of no use, even one replacing an
+                // existing file
+                if let Err(e) = fs::remove_file(&target) {
+                    warn!("Failed to remove {}: {}", target.display(), e);
+                }
+                return Err(match copied {
+                    Err(e) => e.into(),
+                    Ok(_) => too_large(self.max_size),
+                });
+            }
+        };
+        self.remaining -= written;
+        if !existed {
+            self.created.push(target);
+        }
+        self.names.push(entry_name(Path::new(name)));
+        Ok(())
+    }
+}
+
+/// The error of an entry whose path would leave the destination
+fn unsafe_path(name: &str) -> Error {
+    Error::new(ToolError::new(
+        ToolErrorKind::PermissionDenied,
+        format!("Unsafe path in archive: {}", name),
+    ))
+}
+
+/// The error of an entry that would replace a file without `overwrite`
+fn already_exists(name: &str) -> Error {
+    invalid_arguments(format!(
+        "File already exists: {} (set overwrite to replace it)",
+        name
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    fn create_sources(root: &Path) {
+        fs::create_dir_all(root.join("reports/2024")).unwrap();
+        fs::write(root.join("reports/summary.md"), "# Summary").unwrap();
+        fs::write(root.join("reports/2024/q1.csv"), "month,total\njan,3\n").unwrap();
+        fs::write(root.join("notes.txt"), "notes").unwrap();
+    }
+
+    #[test]
+    fn test_format_detection() {
+        let detect = |name: Option<&str>, path: &str| ArchiveFormat::detect(name, Path::new(path));
+        assert_eq!(detect(None, "out/results.zip").unwrap(), ArchiveFormat::Zip);
+        assert_eq!(
+            detect(None, "results.TAR.GZ").unwrap(),
+            ArchiveFormat::TarGz
+        );
+        assert_eq!(detect(None, "results.tgz").unwrap(), ArchiveFormat::TarGz);
+        assert_eq!(detect(Some("zip"), "results").unwrap(), ArchiveFormat::Zip);
+        assert!(detect(None, "results.rar").is_err());
+        assert!(detect(None, "results").is_err());
+    }
+
+    #[test]
+    fn test_round_trip() {
+        for format in [ArchiveFormat::Zip, ArchiveFormat::TarGz] {
+            let temp_dir = TempDir::new().unwrap();
+            let root = temp_dir.path();
+            create_sources(root);
+            let archive = root.join("results");
+            let sources = vec![root.join("reports"), root.join("notes.txt")];
+
+            let count = create(&archive, root, &sources, format).unwrap();
+            assert_eq!(count, 5);
+
+            let names = extract(
+                &archive,
+                &root.join("out"),
+                format,
+                &ExtractOptions::default(),
+            )
+            .unwrap();
+            assert!(names.contains(&"reports/2024/q1.csv".to_string()));
+            assert_eq!(
+                fs::read_to_string(root.join("out/reports/2024/q1.csv")).unwrap(),
+                "month,total\njan,3\n"
+            );
+            assert_eq!(
+                fs::read_to_string(root.join("out/notes.txt")).unwrap(),
+                "notes"
+            );
+
+            // Existing files are kept unless overwrite is set
+            assert!(extract(
+                &archive,
+                &root.join("out"),
+                format,
+                &ExtractOptions::default()
+            )
+            .is_err());
+            let overwrite = ExtractOptions {
+                overwrite: true,
+                ..Default::default()
+            };
+            assert!(extract(&archive, &root.join("out"), format, &overwrite).is_ok());
+
+            // Filters and size limits refuse the whole archive
+            let permits = |name: &Path, _: bool| !name.ends_with("q1.csv");
+            let filtered = ExtractOptions {
+                permits: Some(&permits),
+                ..Default::default()
+            };
+            assert!(extract(&archive, &root.join("filtered"), format, &filtered).is_err());
+            assert_eq!(fs::read_dir(root.join("filtered")).unwrap().count(), 0);
+            let limited = ExtractOptions {
+                max_size: Some(10),
+                ..Default::default()
+            };
+            assert!(extract(&archive, &root.join("limited"), format, &limited).is_err());
+            assert_eq!(fs::read_dir(root.join("limited")).unwrap().count(), 0);
+        }
+    }
+
+    #[test]
+    fn test_failed_extraction_is_undone() {
+        let temp_dir = TempDir::new().unwrap();
+        let root = temp_dir.path();
+        fs::create_dir_all(root.join("out")).unwrap();
+        fs::write(root.join("out/kept.txt"), "kept").unwrap();
+
+        // The second copy of a file fails only once the first is written
+        let archive = root.join("twice.tar.gz");
+        let mut builder = tar::Builder::new(GzEncoder::new(
+            File::create(&archive).unwrap(),
+            Compression::default(),
+        ));
+        for _ in 0..2 {
+            let mut header = tar::Header::new_gnu();
+            header.set_size(5);
+            header.set_mode(0o644);
+            header.set_cksum();
+            builder
+                .append_data(&mut header, "docs/a.txt", &b"hello"[..])
+                .unwrap();
+        }
+        builder.into_inner().unwrap().finish().unwrap();
+
+        let error = extract(
+            &archive,
+            &root.join("out"),
+            ArchiveFormat::TarGz,
+            &ExtractOptions::default(),
+        )
+        .unwrap_err();
+        assert!(error
+            .to_string()
+            .starts_with("File already exists: docs/a.txt"));
+        assert!(!root.join("out/docs").exists());
+        assert_eq!(
+            fs::read_to_string(root.join("out/kept.txt")).unwrap(),
+            "kept"
+        );
+    }
+
+    #[test]
+    fn test_unsafe_entries_are_refused() {
+        let temp_dir = TempDir::new().unwrap();
+        let root = temp_dir.path();
+
+        let archive = root.join("evil.zip");
+        let mut writer = ZipWriter::new(File::create(&archive).unwrap());
+        writer
+            .start_file("../escape.txt", FileOptions::default())
+            .unwrap();
+        io::Write::write_all(&mut writer, b"gotcha").unwrap();
+        writer.finish().unwrap();
+        assert!(extract(
+            &archive,
+            &root.join("out"),
+            ArchiveFormat::Zip,
+            &ExtractOptions::default()
+        )
+        .is_err());
+        assert!(!root.join("escape.txt").exists());
+
+        let archive = root.join("evil.tar.gz");
+        let mut builder = tar::Builder::new(GzEncoder::new(
+            File::create(&archive).unwrap(),
+            Compression::default(),
+        ));
+        let mut header = tar::Header::new_gnu();
+        header.set_entry_type(tar::EntryType::Symlink);
+        header.set_size(0);
+        builder
+            .append_link(&mut header, "link", "/etc/passwd")
+            .unwrap();
+        builder.into_inner().unwrap().finish().unwrap();
+        assert!(extract(
+            &archive,
+            &root.join("out"),
+            ArchiveFormat::TarGz,
+            &ExtractOptions::default()
+        )
+        .is_err());
+        assert!(!root.join("out/link").exists());
+    }
+}
@@ -0,0 +1,432 @@
+//! File Policy Module: Access rules for `FileServer`
+//!
+//! This module provides:
+//! - `FileServerPolicy`, read from the `file` entry of `servers_config.json`
+//! - The sandbox every file tool resolves its paths through
+//!
+//! Paths are always relative to the root directory; absolute paths, `..`
+//! and symbolic links leading out of the root are refused. Allow and deny patterns are globs relative to the root, where
+//! `*` stays within one directory and `**` spans any number of them. A
+//! pattern matching a directory covers everything inside it, and deny wins
+//! over allow.
+//!
+//! With an allow list, files it does not cover cannot be read, listed or
+//! changed, and directories are only shown when an allowed file could be
+//! inside them. A read-only policy refuses every tool that changes files.
+//! Refusals are `permission_denied` tool errors, so failed calls are not
+//! retried.
+//!
+//! A quota caps the bytes of all files below the root. Every write counts
+//! as new bytes, so replacing a file close to the quota may be refused.
+//!
+//! Author: arkSong <arksong2018@gmail.com>
+//! Version: 1.0.0
+//! License: MIT
+
+use anyhow::{Error, Result};
+use glob::{MatchOptions, Pattern};
+use serde::Deserialize;
+use std::fs;
+use std::path::{Component, Path, PathBuf};
+use walkdir::WalkDir;
+
+use crate::tool_error::{ToolError, ToolErrorKind};
+
+/// Glob matching where `*` never crosses a `/`
+const MATCH_OPTIONS: MatchOptions = MatchOptions {
+    case_sensitive: true,
+    require_literal_separator: true,
+    require_literal_leading_dot: false,
+};
+
+/// Access rules for the file tools, read from the `file` entry of
+/// `servers_config.json`
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct FileServerPolicy {
+    /// Refuse every tool that creates, changes or deletes files
+    pub read_only: bool,
+    /// Patterns of the files the tools may access. Unset allows all.
+    pub allow: Option<Vec<String>>,
+    /// Patterns of the files the tools may never access, e.g. `**/.env`
+    pub deny: Vec<String>,
+    /// Bytes a single tool call may write. Unset allows any size.
+    pub max_write_size: Option<u64>,
+    /// Bytes all files below the root may take up. Unset allows any size.
+    pub quota: Option<u64>,
+    /// Directory of the `file_from_template` templates, relative to the
+    /// root unless absolute. Defaults to `templates` below the root.
+    pub templates: Option<PathBuf>,
+}
+
+/// Root directory and policy shared by the file tool handlers
+pub(crate) struct Sandbox {
+    root: PathBuf,
+    policy: FileServerPolicy,
+    allow: Option<Vec<Pattern>>,
+    deny: Vec<Pattern>,
+}
+
+impl Sandbox {
+    /// Compiles the patterns of `policy`, failing on invalid globs
+    pub(crate) fn new(root: PathBuf, policy: FileServerPolicy) -> Result<Self> {
+        let compile = |patterns: &[String]| {
+            patterns
+                .iter()
+                .map(|pattern| {
+                    Pattern::new(pattern.trim_end_matches('/')).map_err(|e| {
+                        Error::msg(format!("Invalid file policy pattern {}: {}", pattern, e))
+                    })
+                })
+                .collect::<Result<Vec<_>>>()
+        };
+        Ok(Self {
+            allow: policy.allow.as_deref().map(compile).transpose()?,
+            deny: compile(&policy.deny)?,
+            root,
+            policy,
+        })
+    }
+
+    pub(crate) fn root(&self) -> &Path {
+        &self.root
+    }
+
+    /// Resolves `path` for a tool that reads it
+    pub(crate) fn readable(&self, path: &str) -> Result<PathBuf> {
+        let full_path = resolve(&self.root, path)?;
+        if !self.permits(&full_path, full_path.is_dir()) {
+            return Err(ToolError::new(
+                ToolErrorKind::PermissionDenied,
+                format!("Access denied by the file server policy: {}", path),
+            )
+            .into());
+        }
+        Ok(full_path)
+    }
+
+    /// Resolves `path` for a tool that changes it
+    pub(crate) fn writable(&self, path: &str) -> Result<PathBuf> {
+        if self.policy.read_only {
+            return Err(ToolError::new(
+                ToolErrorKind::PermissionDenied,
+                "The file server is read-only",
+            )
+            .into());
+        }
+        self.readable(path)
+    }
+
+    /// Whether the policy lets the tools see `path`, a file or directory
+    /// below the root
+    pub(crate) fn permits(&self, path: &Path, is_dir: bool) -> bool {
+        let relative = match path.strip_prefix(&self.root) {
+            Ok(relative) => normalize(relative),
+            Err(_) => return false,
+        };
+        if relative.as_os_str().is_empty() {
+            return true;
+        }
+        if self.deny.iter().any(|pattern| covers(pattern, &relative)) {
+            return false;
+        }
+        match &self.allow {
+            Some(allow) => allow.iter().any(|pattern| {
+                covers(pattern, &relative) || (is_dir && may_contain(pattern, &relative))
+            }),
+            None => true,
+        }
+    }
+
+    /// Fails if the directory `path` holds a file the policy hides, so that
+    /// copying, moving or deleting it cannot touch such files
+    pub(crate) fn check_tree(&self, path: &Path) -> Result<()> {
+        if self.allow.is_none() && self.deny.is_empty() {
+            return Ok(());
+        }
+        for entry in WalkDir::new(path).min_depth(1) {
+            let entry = entry?;
+            if !entry.file_type().is_dir() && !self.permits(entry.path(), false) {
+                return Err(ToolError::new(
+                    ToolErrorKind::PermissionDenied,
+                    format!(
+                        "Access denied by the file server policy: {}",
+                        entry
+                            .path()
+                            .strip_prefix(&self.root)
+                            .unwrap_or(entry.path())
+                            .display()
+                    ),
+                )
+                .into());
+            }
+        }
+        Ok(())
+    }
+
+    /// Directory `file_from_template` reads its templates from
+    pub(crate) fn templates_dir(&self) -> PathBuf {
+        self.root.join(
+            self.policy
+                .templates
+                .as_deref()
+                .unwrap_or(Path::new("templates")),
+        )
+    }
+
+    /// Bytes all files below the root may take up
+    pub(crate) fn quota(&self) -> Option<u64> {
+        self.policy.quota
+    }
+
+    /// Bytes taken up by the files below the root
+    pub(crate) fn usage(&self) -> Result<u64> {
+        tree_size(&self.root)
+    }
+
+    /// Most bytes a single tool call may still write under both
+    /// `max_write_size` and the quota
+    pub(crate) fn write_allowance(&self) -> Result<Option<u64>> {
+        let remaining = match self.policy.quota {
+            Some(quota) => Some(quota.saturating_sub(self.usage()?)),
+            None => None,
+        };
+        Ok(match (self.policy.max_write_size, remaining) {
+            (Some(max), Some(remaining)) => Some(max.min(remaining)),
+            (max, remaining) => max.or(remaining),
+        })
+    }
+
+    /// Fails if writing `size` bytes exceeds `max_write_size` or the quota
+    pub(crate) fn check_size(&self, size: u64) -> Result<()> {
+        self.check_write_limit(size)?;
+        self.check_quota(size)
+    }
+
+    /// Fails if writing `size` bytes exceeds `max_write_size`
+    pub(crate) fn check_write_limit(&self, size: u64) -> Result<()> {
+        match self.policy.max_write_size {
+            Some(max) if size > max => Err(Error::msg(format!(
+                "Writing {} bytes exceeds the file server's limit of {} bytes",
+                size, max
+            ))),
+            _ => Ok(()),
+        }
+    }
+
+    /// Fails if `additional` more bytes below the root exceed the quota
+    pub(crate) fn check_quota(&self, additional: u64) -> Result<()> {
+        let quota = match self.policy.quota {
+            Some(quota) => quota,
+            None => return Ok(()),
+        };
+        let used = self.usage()?;
+        if used.saturating_add(additional) > quota {
+            return Err(Error::msg(format!(
+                "The file server's quota of {} bytes is exceeded: {} bytes are in use and {} more \
+                 were requested",
+                quota, used, additional
+            )));
+        }
+        Ok(())
+    }
+
+    /// Fails if copying `path` writes more than `max_write_size` or the quota
+    pub(crate) fn check_copy_size(&self, path: &Path) -> Result<()> {
+        if self.policy.max_write_size.is_none() && self.policy.quota.is_none() {
+            return Ok(());
+        }
+        self.check_size(tree_size(path)?)
+    }
+}
+
+/// Bytes of the file `path` or of all files inside the directory `path`,
+/// not following symbolic links
+fn tree_size(path: &Path) -> Result<u64> {
+    let mut size = 0;
+    for entry in WalkDir::new(path) {
+        let entry = entry?;
+        if entry.file_type().is_file() {
+            size += entry.metadata()?.len();
+        }
+    }
+    Ok(size)
+}
+
+/// Resolves `path` below `root`, refusing absolute paths, `..` and symbolic
+/// links that lead out of it, so the result cannot leave the root directory
+pub(crate) fn resolve(root: &Path, path: &str) -> Result<PathBuf> {
+    let outside = || -> Error {
+        ToolError::new(
+            ToolErrorKind::PermissionDenied,
+            format!("Path must stay inside the root directory: {}", path),
+        )
+        .into()
+    };
+    let relative = Path::new(path);
+    if relative
+        .components()
+        .any(|component| !matches!(component, Component::Normal(_) | Component::CurDir))
+    {
+        return Err(outside());
+    }
+
+    let full_path = root.join(relative);
+    // A link inside the root may point anywhere, so the deepest part of the
+    // path that exists must still be inside the root once links are followed.
+    // A dangling link fails to canonicalize and is refused as well.
+    if let Ok(canonical_root) = root.canonicalize() {
+        let existing = full_path
+            .ancestors()
+            .find(|ancestor| fs::symlink_metadata(ancestor).is_ok());
+        if let Some(existing) = existing {
+            match existing.canonicalize() {
+                Ok(canonical) if canonical.starts_with(&canonical_root) => {}
+                _ => return Err(outside()),
+            }
+        }
+    }
+    Ok(full_path)
+}
+
+/// `path` without `.` components
+fn normalize(path: &Path) -> PathBuf {
+    path.components()
+        .filter(|component| matches!(component, Component::Normal(_)))
+        .collect()
+}
+
+/// Whether `pattern` matches `relative` or one of its parent directories
+fn covers(pattern: &Pattern, relative: &Path) -> bool {
+    relative
+        .ancestors()
+        .filter(|path| !path.as_os_str().is_empty())
+        .any(|path| pattern.matches_path_with(path, MATCH_OPTIONS))
+}
+
+/// Whether `pattern` could match something inside the directory `relative`
+fn may_contain(pattern: &Pattern, relative: &Path) -> bool {
+    let mut parts = pattern.as_str().split('/');
+    for component in relative.components() {
+        let part = match parts.next() {
+            Some("**") => return true,
+            Some(part) => part,
+            None => return false,
+        };
+        let matches = Pattern::new(part).is_ok_and(|part| {
+            part.matches_with(&component.as_os_str().to_string_lossy(), MATCH_OPTIONS)
+        });
+        if !matches {
+            return false;
+        }
+    }
+    parts.next().is_some()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    fn sandbox(root: &Path, policy: serde_json::Value) -> Sandbox {
+        Sandbox::new(root.to_path_buf(), serde_json::from_value(policy).unwrap()).unwrap()
+    }
+
+    #[test]
+    fn test_allow_and_deny() {
+        let root = Path::new("/home/user");
+        let sandbox = sandbox(
+            root,
+            serde_json::json!({
+                "allow": ["docs", "notes/*.md"],
+                "deny": ["**/.env", "docs/private"]
+            }),
+        );
+        let permits = |path: &str, is_dir: bool| sandbox.permits(&root.join(path), is_dir);
+
+        assert!(permits("docs/guide.md", false));
+        assert!(permits("./docs/api/index.html", false));
+        assert!(permits("notes/todo.md", false));
+        assert!(permits("notes", true));
+        assert!(!permits("notes/todo.txt", false));
+        assert!(!permits("notes/2024", true));
+        assert!(!permits("src/main.rs", false));
+        assert!(!permits("src", true));
+        assert!(!permits("docs/.env", false));
+        assert!(!permits("docs/private/salary.csv", false));
+        assert!(permits(".", true));
+
+        assert!(sandbox.readable("docs/guide.md").is_ok());
+        assert!(sandbox.readable("src/main.rs").is_err());
+        assert!(sandbox.readable("../etc/passwd").is_err());
+    }
+
+    #[test]
+    fn test_read_only_and_size_limit() {
+        let temp_dir = TempDir::new().unwrap();
+        let root = temp_dir.path();
+        fs::create_dir_all(root.join("data")).unwrap();
+        fs::write(root.join("data/a.txt"), "12345").unwrap();
+        fs::write(root.join("data/.env"), "KEY=1").unwrap();
+
+        let read_only = sandbox(root, serde_json::json!({ "read_only": true }));
+        assert!(read_only.readable("data/a.txt").is_ok());
+        assert!(read_only.writable("data/a.txt").is_err());
+
+        let limited = sandbox(
+            root,
+            serde_json::json!({ "max_write_size": 8, "deny": ["**/.env"] }),
+        );
+        assert!(limited.check_size(8).is_ok());
+        assert!(limited.check_size(9).is_err());
+        assert!(limited.check_copy_size(&root.join("data")).is_err());
+        assert!(limited.check_copy_size(&root.join("data/a.txt")).is_ok());
+        assert!(limited.check_tree(&root.join("data")).is_err());
+
+        // data/a.txt and data/.env take up 10 bytes
+        let quota = sandbox(
+            root,
+            serde_json::json!({ "quota": 16, "max_write_size": 4 }),
+        );
+        assert_eq!(quota.usage().unwrap(), 10);
+        assert_eq!(quota.write_allowance().unwrap(), Some(4));
+        assert!(quota.check_quota(6).is_ok());
+        assert!(quota.check_quota(7).is_err());
+        assert!(quota.check_copy_size(&root.join("data")).is_err());
+        fs::write(root.join("data/b.txt"), "1234").unwrap();
+        assert_eq!(quota.write_allowance().unwrap(), Some(2));
+        assert!(quota.check_size(3).is_err());
+
+        assert!(Sandbox::new(
+            root.to_path_buf(),
+            FileServerPolicy {
+                deny: vec!["[".to_string()],
+                ..Default::default()
+            }
+        )
+        .is_err());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_symlink_escape() {
+        let temp_dir = TempDir::new().unwrap();
+        let outside = TempDir::new().unwrap();
+        let root = temp_dir.path();
+        fs::write(outside.path().join("secret.txt"), "secret").unwrap();
+        fs::create_dir(root.join("docs")).unwrap();
+        fs::write(root.join("docs/guide.md"), "guide").unwrap();
+        std::os::unix::fs::symlink(outside.path(), root.join("docs/outside")).unwrap();
+        std::os::unix::fs::symlink(root.join("docs"), root.join("manual")).unwrap();
+        std::os::unix::fs::symlink(outside.path().join("gone"), root.join("dangling")).unwrap();
+
+        let sandbox = sandbox(root, serde_json::json!({}));
+        assert!(sandbox.readable("docs/outside/secret.txt").is_err());
+        assert!(sandbox.writable("docs/outside/new.txt").is_err());
+        assert!(sandbox.readable("docs/outside").is_err());
+        assert!(sandbox.writable("dangling").is_err());
+        // Links that stay inside the root are fine
+        assert!(sandbox.readable("manual/guide.md").is_ok());
+        assert!(sandbox.writable("docs/new/file.txt").is_ok());
+    }
+}
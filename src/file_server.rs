@@ -35,18 +35,63 @@
 // }
 // ```
 
+use crate::file_archive::{self, ArchiveFormat, ExtractOptions};
+use crate::file_policy::{FileServerPolicy, Sandbox};
+use crate::file_template;
 use crate::protocol::ResourceSchema;
 use anyhow::{Context, Error, Result};
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
+use chrono::{DateTime, SecondsFormat, Utc};
+use glob::{MatchOptions, Pattern};
+use ignore::WalkBuilder;
 use log::{error, info};
+use regex::{Regex, RegexBuilder};
 use serde_json::{json, Value};
-use std::fs;
+use similar::TextDiff;
+use std::fs::{self, File, OpenOptions};
 use std::future::Future;
+use std::io::{Read, Seek, SeekFrom, Write};
 use std::path::{Path, PathBuf};
 use std::pin::Pin;
 use std::sync::Arc;
+use std::time::SystemTime;
 use tokio::sync::Mutex;
+use walkdir::WalkDir;
 
-use crate::mcp_server::{McpServer, Tool};
+use crate::mcp_server::{McpServer, Resource, Tool};
+use crate::rag_server::RagServer;
+use crate::tool_error::{invalid_arguments, ToolError, ToolErrorKind};
+
+/// Depth of recursive listings and glob searches unless `max_depth` is given
+const DEFAULT_MAX_DEPTH: usize = 10;
+
+/// Entries returned by a recursive listing or glob search
+const MAX_RESULTS: usize = 1000;
+
+/// Bytes `file_read` returns at once
+const MAX_READ_SIZE: u64 = 10 * 1024 * 1024;
+
+/// Lines returned by `file_tail` unless `lines` is given
+const DEFAULT_TAIL_LINES: usize = 20;
+
+/// Bytes read at a time when `file_tail` scans backwards
+const TAIL_BLOCK_SIZE: u64 = 8192;
+
+/// Matches returned by `file_search` unless `max_results` is given
+const DEFAULT_SEARCH_RESULTS: usize = 100;
+
+/// Lines shown before and after a `file_search` match unless `context` is given
+const DEFAULT_SEARCH_CONTEXT: usize = 2;
+
+/// Unchanged lines shown around a `file_diff` change unless `context` is given
+const DEFAULT_DIFF_CONTEXT: usize = 3;
+
+/// Files larger than this are skipped by `file_search`
+const MAX_SEARCH_FILE_SIZE: u64 = 10 * 1024 * 1024;
+
+/// Bytes inspected for NUL bytes when deciding whether a file is binary
+const BINARY_CHECK_LEN: usize = 8192;
 
 /// FileServer struct represents a file system server that provides
 /// basic file operations through a set of tools.
@@ -55,16 +100,46 @@ use crate::mcp_server::{McpServer, Tool};
 /// # Available Tools
 /// * `file_read` - Read contents of a file
 /// * `file_write` - Write contents to a file
+/// * `file_append` - Append to a file, creating it if needed
+/// * `file_tail` - Read the last lines of a file
 /// * `file_delete` - Delete a file
-/// * `list_directory` - List contents of a directory
+/// * `list_directory` - List contents of a directory, optionally recursively
+/// * `file_glob` - Find files matching a glob pattern such as `src/**/*.rs`
+/// * `file_search` - Search file contents for a regex or literal text
+/// * `dir_create` - Create a directory and its parents
+/// * `dir_delete` - Delete a directory, recursively only with confirmation
+/// * `file_move` - Move a file or directory to another path
+/// * `file_copy` - Copy a file or directory to another path
+/// * `file_rename` - Rename a file or directory in place
+/// * `archive_create` - Pack files into a zip or tar.gz archive
+/// * `archive_extract` - Unpack a zip or tar.gz archive
+/// * `file_diff` - Show a unified diff between two files
+/// * `file_apply_patch` - Apply a unified diff to a file
+/// * `file_from_template` - Create a file from a template with JSON variables
+/// * `file_templates` - List the available templates
+/// * `file_index` - Stream a text file into the RAG index, once a `RagServer`
+///   is attached
 ///
 /// # Tool Arguments and Responses
 /// ## file_read
-/// * Arguments: `{ "path": "string" }`
+/// * Arguments: `{ "path": "string", "offset": number, "length": number, "encoding": "utf-8|base64" }`
+/// * Response: `{ "content": "string", "exists": boolean, "size": number, "mime_type": "string" }`,
+///   plus `offset`, `length` and `eof` for ranged reads
+///
+/// `offset` and `length` are in bytes. A ranged UTF-8 read never splits a
+/// character, so `offset + length` is where the next read continues. Binary
+/// files must be read as base64, and at most 10 MB are returned at once.
+///
+/// ## file_append
+/// * Arguments: `{ "path": "string", "content": "string" }`
+/// * Response: `{ "success": boolean, "size": number }`
+///
+/// ## file_tail
+/// * Arguments: `{ "path": "string", "lines": number }`
 /// * Response: `{ "content": "string", "exists": boolean }`
 ///
 /// ## file_write
-/// * Arguments: `{ "path": "string", "content": "string" }`
+/// * Arguments: `{ "path": "string", "content": "string", "encoding": "utf-8|base64" }`
 /// * Response: `{ "success": boolean }`
 ///
 /// ## file_delete
@@ -72,11 +147,66 @@ use crate::mcp_server::{McpServer, Tool};
 /// * Response: `{ "success": boolean, "deleted": boolean }`
 ///
 /// ## list_directory
+/// * Arguments: `{ "path": "string", "recursive": boolean, "max_depth": number, "sort_by": "name|size|modified", "descending": boolean, "type": "file|directory|symlink", "name": "string", "min_size": number, "max_size": number, "modified_after": "string", "modified_before": "string" }`
+/// * Response: `{ "entries": [{ "name": "string", "type": "file|directory|symlink", "size": number, "modified": "string", "target": "string" }], "truncated": boolean }`
+///
+/// Recursive listings name entries by their path below `path` and skip
+/// hidden files and whatever `.gitignore`/`.ignore` files exclude.
+/// `modified` is an RFC 3339 timestamp in UTC. Symbolic links are not
+/// followed; their `target` is given instead. `name` is a glob matched
+/// against the last component of each entry, and `modified_after` and
+/// `modified_before` take RFC 3339 timestamps. Entries are sorted by name
+/// unless `sort_by` says otherwise; recursive listings keep tree order
+/// when it is not set.
+///
+/// ## file_glob
+/// * Arguments: `{ "pattern": "string", "path": "string", "max_depth": number }`
+/// * Response: `{ "files": ["string"], "truncated": boolean }`
+///
+/// ## file_search
+/// * Arguments: `{ "pattern": "string", "literal": boolean, "case_insensitive": boolean, "path": "string", "glob": "string", "context": number, "max_results": number }`
+/// * Response: `{ "matches": [{ "file": "string", "line": number, "text": "string", "before": ["string"], "after": ["string"] }], "files_searched": number, "truncated": boolean }`
+///
+/// Binary files, files over 10 MB and ignored files are not searched.
+///
+/// ## dir_create
+/// * Arguments: `{ "path": "string" }`
+/// * Response: `{ "success": boolean, "created": boolean }`
+///
+/// ## dir_delete
+/// * Arguments: `{ "path": "string", "recursive": boolean, "confirm": "string" }`
+/// * Response: `{ "success": boolean, "deleted": boolean }`
+///
+/// A recursive delete requires `confirm` to repeat `path`.
+///
+/// ## file_move / file_copy
+/// * Arguments: `{ "source": "string", "destination": "string", "overwrite": boolean, "confirm": "string" }`
+/// * Response: `{ "success": boolean }`
+///
+/// Overwriting a directory requires `confirm` to repeat `destination`.
+///
+/// ## file_rename
+/// * Arguments: `{ "path": "string", "new_name": "string", "overwrite": boolean, "confirm": "string" }`
+/// * Response: `{ "success": boolean, "path": "string" }`
+///
+/// ## file_from_template
+/// * Arguments: `{ "template": "string", "path": "string", "variables": object, "overwrite": boolean }`
+/// * Response: `{ "success": boolean, "size": number, "created": boolean }`
+///
+/// Templates live in the policy's `templates` directory (`templates` below
+/// the root by default); `file_templates` lists them as `{ "templates": ["string"] }`.
+///
+/// ## file_index
 /// * Arguments: `{ "path": "string" }`
-/// * Response: `{ "entries": [{ "name": "string", "type": "file|directory", "size": number, "modified": "string" }] }`
+/// * Response: `{ "success": boolean, "document_id": "string", "segments": number, "chunks": number, "bytes": number }`
+///
+/// Every tool only accepts paths inside the root directory and obeys the
+/// server's `FileServerPolicy`.
 pub struct FileServer {
     /// Base directory for all file operations
     root_path: PathBuf,
+    /// Root directory and policy shared by the tool handlers
+    sandbox: Arc<Sandbox>,
     /// MCP server instance that handles tool registration and execution
     mcp_server: McpServer,
 }
@@ -95,6 +225,17 @@ impl FileServer {
     /// let file_server = FileServer::new("/path/to/root").await?;
     /// ```
     pub async fn new(root_path: &str) -> Result<Self> {
+        Self::with_policy(root_path, FileServerPolicy::default()).await
+    }
+
+    /// Creates a new FileServer whose tools obey `policy`
+    ///
+    /// # Example
+    /// ```rust
+    /// let policy = FileServerPolicy { read_only: true, ..Default::default() };
+    /// let file_server = FileServer::with_policy("/home/user", policy).await?;
+    /// ```
+    pub async fn with_policy(root_path: &str, policy: FileServerPolicy) -> Result<Self> {
         let root_path = PathBuf::from(root_path);
 
         // Create root directory if it doesn't exist
@@ -102,24 +243,41 @@ impl FileServer {
             fs::create_dir_all(&root_path)?;
         }
 
-        let mut mcp_server = McpServer::new();
-        Self::register_tools(&mut mcp_server, root_path.clone()).await?;
+        let mut mcp_server = McpServer::new().named("file");
+        let sandbox = Self::register_tools(&mut mcp_server, root_path.clone(), policy).await?;
+        mcp_server
+            .register_resource(filesystem_resource(Arc::clone(&sandbox)))
+            .await?;
+        mcp_server
+            .register_resource(file_resource(Arc::clone(&sandbox)))
+            .await?;
 
         Ok(Self {
             root_path,
+            sandbox,
             mcp_server,
         })
     }
 
+    /// Registers the `file_index` tool, which streams text files into
+    /// `rag_server` without returning their content to the LLM
+    pub async fn attach_rag_server(&mut self, rag_server: Arc<Mutex<RagServer>>) -> Result<()> {
+        self.mcp_server
+            .register_tool(index_tool(Arc::clone(&self.sandbox), rag_server))
+            .await
+    }
+
     /// Registers all file operation tools with the MCP server.
     /// This includes tools for reading, writing, deleting files and listing directories.
     ///
     /// # Arguments
     /// * `mcp_server` - MCP server instance to register tools with
     /// * `root_path` - Base directory path for all file operations
+    /// * `policy` - Access rules every tool handler enforces
     ///
     /// # Returns
-    /// * `Result<()>` - Success or error during tool registration
+    /// * `Result<Arc<Sandbox>>` - The sandbox the tools work in, for
+    ///   registering `file_index` later, or error during tool registration
     ///
     /// # Tool Registration Details
     /// Each tool is registered with:
@@ -128,9 +286,15 @@ impl FileServer {
     /// * Input schema (JSON Schema)
     /// * Output schema (JSON Schema)
     /// * An async handler function
-    async fn register_tools(mcp_server: &mut McpServer, root_path: PathBuf) -> Result<()> {
-        // Clone root_path for each tool's closure to avoid ownership issues
-        let read_root_path = root_path.clone();
+    pub(crate) async fn register_tools(
+        mcp_server: &mut McpServer,
+        root_path: PathBuf,
+        policy: FileServerPolicy,
+    ) -> Result<Arc<Sandbox>> {
+        let sandbox = Arc::new(Sandbox::new(root_path, policy)?);
+
+        // Clone the sandbox for each tool's closure to avoid ownership issues
+        let read_sandbox = Arc::clone(&sandbox);
 
         // Register read file tool
         // This tool reads the contents of a file and returns them along with existence status
@@ -143,6 +307,19 @@ impl FileServer {
                     "path": {
                         "type": "string",
                         "description": "Path to the file to read"
+                    },
+                    "offset": {
+                        "type": "integer",
+                        "description": "Byte to start reading at (default 0)"
+                    },
+                    "length": {
+                        "type": "integer",
+                        "description": "Maximum number of bytes to read (default: to the end, at most 10 MB)"
+                    },
+                    "encoding": {
+                        "type": "string",
+                        "description": "utf-8 for text (default) or base64 for binary files",
+                        "enum": ["utf-8", "base64"]
                     }
                 },
                 "required": ["path"]
@@ -152,22 +329,40 @@ impl FileServer {
                 "properties": {
                     "content": {
                         "type": "string",
-                        "description": "Contents of the file"
+                        "description": "Contents of the file, or of the requested range"
                     },
                     "exists": {
                         "type": "boolean",
                         "description": "Whether the file exists"
+                    },
+                    "size": {
+                        "type": "integer",
+                        "description": "Size of the whole file in bytes"
+                    },
+                    "mime_type": {
+                        "type": "string",
+                        "description": "MIME type of the file, e.g. text/markdown or image/png"
+                    },
+                    "offset": {
+                        "type": "integer",
+                        "description": "Byte the range starts at"
+                    },
+                    "length": {
+                        "type": "integer",
+                        "description": "Bytes returned; continue at offset + length"
+                    },
+                    "eof": {
+                        "type": "boolean",
+                        "description": "Whether the range reaches the end of the file"
                     }
                 }
             })),
             Box::new(move |args| {
-                let root_path = read_root_path.clone();
+                let sandbox = Arc::clone(&read_sandbox);
                 Box::pin(async move {
-                    let path = args["path"]
-                        .as_str()
-                        .ok_or_else(|| Error::msg("Invalid argument: path"))?;
+                    let path = string_arg(&args, "path")?;
 
-                    let full_path = root_path.join(path);
+                    let full_path = sandbox.readable(path)?;
                     if !full_path.exists() {
                         return Ok(json!({
                             "content": "",
@@ -175,17 +370,58 @@ impl FileServer {
                         }));
                     }
 
-                    let content = fs::read_to_string(&full_path)?;
-                    Ok(json!({
+                    let encoding = Encoding::from_args(&args)?;
+                    let size = fs::metadata(&full_path)?.len();
+                    let ranged = !(args["offset"].is_null() && args["length"].is_null());
+                    let offset = args["offset"].as_u64().unwrap_or(0);
+                    let length = args["length"]
+                        .as_u64()
+                        .unwrap_or_else(|| size.saturating_sub(offset));
+                    if length > MAX_READ_SIZE {
+                        return Err(invalid_arguments(format!(
+                            "File too large: {} bytes requested, at most {} can be read at once; \
+                             read it in parts with offset and length",
+                            length, MAX_READ_SIZE
+                        )));
+                    }
+
+                    let bytes = read_range(&full_path, offset, length)?;
+                    let mime_type = mime_type(&full_path, &bytes);
+                    let (content, offset, length) = match encoding {
+                        Encoding::Utf8 => {
+                            let (text, skipped) = decode_utf8(bytes, ranged).map_err(|_| {
+                                invalid_arguments(format!(
+                                    "{} is not UTF-8 text ({}); read it with encoding base64",
+                                    path, mime_type
+                                ))
+                            })?;
+                            let length = text.len() as u64;
+                            (text, offset + skipped, length)
+                        }
+                        Encoding::Base64 => {
+                            let length = bytes.len() as u64;
+                            (BASE64.encode(bytes), offset, length)
+                        }
+                    };
+
+                    let mut result = json!({
                         "content": content,
-                        "exists": true
-                    }))
+                        "exists": true,
+                        "size": size,
+                        "mime_type": mime_type
+                    });
+                    if ranged {
+                        result["offset"] = json!(offset);
+                        result["length"] = json!(length);
+                        result["eof"] = json!(offset + length >= size);
+                    }
+                    Ok(result)
                 })
             }),
         );
 
-        // Clone root_path for write tool
-        let write_root_path = root_path.clone();
+        // Clone the sandbox for write tool
+        let write_sandbox = Arc::clone(&sandbox);
 
         // Register write file tool
         // This tool writes content to a file, creating parent directories if needed
@@ -202,6 +438,11 @@ impl FileServer {
                     "content": {
                         "type": "string",
                         "description": "Content to write to the file"
+                    },
+                    "encoding": {
+                        "type": "string",
+                        "description": "utf-8 for text (default) or base64 for binary content",
+                        "enum": ["utf-8", "base64"]
                     }
                 },
                 "required": ["path", "content"]
@@ -216,16 +457,20 @@ impl FileServer {
                 }
             })),
             Box::new(move |args| {
-                let root_path = write_root_path.clone();
+                let sandbox = Arc::clone(&write_sandbox);
                 Box::pin(async move {
-                    let path = args["path"]
-                        .as_str()
-                        .ok_or_else(|| Error::msg("Invalid argument: path"))?;
-                    let content = args["content"]
-                        .as_str()
-                        .ok_or_else(|| Error::msg("Invalid argument: content"))?;
+                    let path = string_arg(&args, "path")?;
+                    let content = string_arg(&args, "content")?;
+
+                    let content = match Encoding::from_args(&args)? {
+                        Encoding::Utf8 => content.as_bytes().to_vec(),
+                        Encoding::Base64 => BASE64.decode(content).map_err(|e| {
+                            invalid_arguments(format!("Invalid base64 content: {}", e))
+                        })?,
+                    };
 
-                    let full_path = root_path.join(path);
+                    let full_path = sandbox.writable(path)?;
+                    sandbox.check_size(content.len() as u64)?;
 
                     // Create parent directories if they don't exist
                     if let Some(parent) = full_path.parent() {
@@ -238,8 +483,128 @@ impl FileServer {
             }),
         );
 
-        // Clone root_path for delete tool
-        let delete_root_path = root_path.clone();
+        // Clone the sandbox for append tool
+        let append_sandbox = Arc::clone(&sandbox);
+
+        // Register append file tool
+        // This tool adds content to the end of a file, e.g. a log
+        let append_tool = Tool::new(
+            "file_append".to_string(),
+            "Append content to the end of a file, creating it if needed".to_string(),
+            json!({
+                "type": "object",
+                "properties": {
+                    "path": {
+                        "type": "string",
+                        "description": "Path to the file to append to"
+                    },
+                    "content": {
+                        "type": "string",
+                        "description": "Content to append"
+                    }
+                },
+                "required": ["path", "content"]
+            }),
+            Some(json!({
+                "type": "object",
+                "properties": {
+                    "success": {
+                        "type": "boolean",
+                        "description": "Whether the append was successful"
+                    },
+                    "size": {
+                        "type": "integer",
+                        "description": "Size of the file afterwards in bytes"
+                    }
+                }
+            })),
+            Box::new(move |args| {
+                let sandbox = Arc::clone(&append_sandbox);
+                Box::pin(async move {
+                    let path = string_arg(&args, "path")?;
+                    let content = string_arg(&args, "content")?;
+
+                    let full_path = sandbox.writable(path)?;
+                    sandbox.check_size(content.len() as u64)?;
+                    if let Some(parent) = full_path.parent() {
+                        fs::create_dir_all(parent)?;
+                    }
+
+                    let mut file = OpenOptions::new()
+                        .create(true)
+                        .append(true)
+                        .open(&full_path)?;
+                    file.write_all(content.as_bytes())?;
+                    Ok(json!({
+                        "success": true,
+                        "size": file.metadata()?.len()
+                    }))
+                })
+            }),
+        );
+
+        // Clone the sandbox for tail tool
+        let tail_sandbox = Arc::clone(&sandbox);
+
+        // Register tail tool
+        // This tool returns the last lines of a file without reading all of it
+        let tail_tool = Tool::new(
+            "file_tail".to_string(),
+            "Read the last lines of a file, e.g. of a log".to_string(),
+            json!({
+                "type": "object",
+                "properties": {
+                    "path": {
+                        "type": "string",
+                        "description": "Path to the file to read"
+                    },
+                    "lines": {
+                        "type": "integer",
+                        "description": "Number of lines to return (default 20)"
+                    }
+                },
+                "required": ["path"]
+            }),
+            Some(json!({
+                "type": "object",
+                "properties": {
+                    "content": {
+                        "type": "string",
+                        "description": "The last lines of the file"
+                    },
+                    "exists": {
+                        "type": "boolean",
+                        "description": "Whether the file exists"
+                    }
+                }
+            })),
+            Box::new(move |args| {
+                let sandbox = Arc::clone(&tail_sandbox);
+                Box::pin(async move {
+                    let path = string_arg(&args, "path")?;
+                    let lines = args["lines"]
+                        .as_u64()
+                        .map_or(DEFAULT_TAIL_LINES, |lines| lines as usize);
+
+                    let full_path = sandbox.readable(path)?;
+                    if !full_path.exists() {
+                        return Ok(json!({
+                            "content": "",
+                            "exists": false
+                        }));
+                    }
+
+                    let content = tail(&full_path, lines)?;
+                    Ok(json!({
+                        "content": content,
+                        "exists": true
+                    }))
+                })
+            }),
+        );
+
+        // Clone the sandbox for delete tool
+        let delete_sandbox = Arc::clone(&sandbox);
 
         // Register delete file tool
         // This tool deletes a file if it exists
@@ -270,13 +635,11 @@ impl FileServer {
                 }
             })),
             Box::new(move |args| {
-                let root_path = delete_root_path.clone();
+                let sandbox = Arc::clone(&delete_sandbox);
                 Box::pin(async move {
-                    let path = args["path"]
-                        .as_str()
-                        .ok_or_else(|| Error::msg("Invalid argument: path"))?;
+                    let path = string_arg(&args, "path")?;
 
-                    let full_path = root_path.join(path);
+                    let full_path = sandbox.writable(path)?;
                     if !full_path.exists() {
                         return Ok(json!({
                             "success": true,
@@ -293,8 +656,8 @@ impl FileServer {
             }),
         );
 
-        // Clone root_path for list tool
-        let list_root_path = root_path.clone();
+        // Clone the sandbox for list tool
+        let list_sandbox = Arc::clone(&sandbox);
 
         // Register list directory tool
         // This tool lists all entries in a directory with their metadata
@@ -307,6 +670,48 @@ impl FileServer {
                     "path": {
                         "type": "string",
                         "description": "Path to the directory to list (relative to root)"
+                    },
+                    "recursive": {
+                        "type": "boolean",
+                        "description": "Also list subdirectories, skipping hidden and .gitignore'd entries (default false)"
+                    },
+                    "max_depth": {
+                        "type": "integer",
+                        "description": "How many levels to descend when recursive (default 10)"
+                    },
+                    "sort_by": {
+                        "type": "string",
+                        "enum": ["name", "size", "modified"],
+                        "description": "Sort the entries by name, size or modification time (default name; tree order when recursive)"
+                    },
+                    "descending": {
+                        "type": "boolean",
+                        "description": "Sort in descending order, e.g. largest or newest first (default false)"
+                    },
+                    "type": {
+                        "type": "string",
+                        "enum": ["file", "directory", "symlink"],
+                        "description": "Only list entries of this type"
+                    },
+                    "name": {
+                        "type": "string",
+                        "description": "Only list entries whose name matches this glob pattern, e.g. *.log"
+                    },
+                    "min_size": {
+                        "type": "integer",
+                        "description": "Only list files of at least this many bytes"
+                    },
+                    "max_size": {
+                        "type": "integer",
+                        "description": "Only list files of at most this many bytes"
+                    },
+                    "modified_after": {
+                        "type": "string",
+                        "description": "Only list entries modified after this RFC 3339 timestamp"
+                    },
+                    "modified_before": {
+                        "type": "string",
+                        "description": "Only list entries modified before this RFC 3339 timestamp"
                     }
                 },
                 "required": ["path"]
@@ -322,11 +727,11 @@ impl FileServer {
                             "properties": {
                                 "name": {
                                     "type": "string",
-                                    "description": "Name of the entry"
+                                    "description": "Name of the entry; its path below the listed directory when recursive"
                                 },
                                 "type": {
                                     "type": "string",
-                                    "description": "Type of the entry (file/directory)"
+                                    "description": "Type of the entry (file/directory/symlink)"
                                 },
                                 "size": {
                                     "type": "integer",
@@ -334,59 +739,262 @@ impl FileServer {
                                 },
                                 "modified": {
                                     "type": "string",
-                                    "description": "Last modification time"
+                                    "description": "Last modification time as an RFC 3339 timestamp in UTC"
+                                },
+                                "target": {
+                                    "type": "string",
+                                    "description": "Path a symbolic link points to (for symlinks)"
                                 }
                             }
                         }
+                    },
+                    "truncated": {
+                        "type": "boolean",
+                        "description": "Whether a recursive listing stopped after 1000 entries"
                     }
                 }
             })),
             Box::new(move |args| {
-                let root_path = list_root_path.clone();
+                let sandbox = Arc::clone(&list_sandbox);
                 Box::pin(async move {
-                    let path = args["path"]
-                        .as_str()
-                        .ok_or_else(|| Error::msg("Invalid argument: path"))?;
+                    let path = string_arg(&args, "path")?;
 
-                    let full_path = root_path.join(path);
+                    let options = ListOptions::from_args(&args)?;
+                    let full_path = sandbox.readable(path)?;
                     if !full_path.exists() {
                         return Ok(json!({ "entries": [] }));
                     }
 
+                    if args["recursive"].as_bool().unwrap_or(false) {
+                        let max_depth = max_depth_arg(&args);
+                        let (entries, truncated) = tokio::task::spawn_blocking(move || {
+                            list_recursive(&sandbox, &full_path, max_depth, &options)
+                        })
+                        .await??;
+                        return Ok(json!({ "entries": entries, "truncated": truncated }));
+                    }
+
                     let entries = fs::read_dir(&full_path)?;
                     let mut result = Vec::new();
 
                     // Collect metadata for each directory entry
-                    for entry in entries {
-                        if let Ok(entry) = entry {
-                            let name = entry.file_name().to_string_lossy().into_owned();
-                            let metadata = entry.metadata()?;
-                            let is_dir = metadata.is_dir();
-                            let size = if !is_dir { metadata.len() } else { 0 };
-                            let modified = metadata.modified()?;
-                            let modified_str = format!("{:?}", modified);
-
-                            result.push(json!({
-                                "name": name,
-                                "type": if is_dir { "directory" } else { "file" },
-                                "size": size,
-                                "modified": modified_str
-                            }));
+                    for entry in entries.flatten() {
+                        let name = entry.file_name().to_string_lossy().into_owned();
+                        let metadata = entry.metadata()?;
+                        if !sandbox.permits(&entry.path(), metadata.is_dir()) {
+                            continue;
+                        }
+                        let listing = Listing::new(name, &entry.path(), &metadata)?;
+                        if options.matches(&listing) {
+                            result.push(listing);
+                        }
+                    }
+
+                    Ok(json!({ "entries": options.sorted(result, Some(SortKey::Name)) }))
+                })
+            }),
+        );
+
+        // Clone the sandbox for glob tool
+        let glob_sandbox = Arc::clone(&sandbox);
+
+        // Register glob tool
+        // This tool finds files anywhere below a directory by glob pattern
+        let glob_tool = Tool::new(
+            "file_glob".to_string(),
+            "Find files whose path matches a glob pattern, e.g. src/**/*.rs".to_string(),
+            json!({
+                "type": "object",
+                "properties": {
+                    "pattern": {
+                        "type": "string",
+                        "description": "Glob pattern relative to path; * and ? stay within one directory, ** spans directories"
+                    },
+                    "path": {
+                        "type": "string",
+                        "description": "Directory to search from (relative to root, default \".\")"
+                    },
+                    "max_depth": {
+                        "type": "integer",
+                        "description": "How many levels to descend (default 10)"
+                    }
+                },
+                "required": ["pattern"]
+            }),
+            Some(json!({
+                "type": "object",
+                "properties": {
+                    "files": {
+                        "type": "array",
+                        "description": "Matching files relative to path, skipping hidden and .gitignore'd ones",
+                        "items": {
+                            "type": "string"
+                        }
+                    },
+                    "truncated": {
+                        "type": "boolean",
+                        "description": "Whether the search stopped after 1000 matches"
+                    }
+                }
+            })),
+            Box::new(move |args| {
+                let sandbox = Arc::clone(&glob_sandbox);
+                Box::pin(async move {
+                    let pattern = string_arg(&args, "pattern")?;
+                    let pattern = Pattern::new(pattern)
+                        .map_err(|e| invalid_arguments(format!("Invalid glob pattern: {}", e)))?;
+                    let full_path = sandbox.readable(args["path"].as_str().unwrap_or("."))?;
+                    let max_depth = max_depth_arg(&args);
+
+                    let (files, truncated) = tokio::task::spawn_blocking(move || {
+                        glob_files(&sandbox, &full_path, &pattern, max_depth)
+                    })
+                    .await??;
+                    Ok(json!({ "files": files, "truncated": truncated }))
+                })
+            }),
+        );
+
+        // Clone the sandbox for search tool
+        let search_sandbox = Arc::clone(&sandbox);
+
+        // Register search tool
+        // This tool greps the text files below a directory
+        let search_tool = Tool::new(
+            "file_search".to_string(),
+            "Search the contents of files for a regular expression or literal text".to_string(),
+            json!({
+                "type": "object",
+                "properties": {
+                    "pattern": {
+                        "type": "string",
+                        "description": "Regular expression, or plain text when literal is true"
+                    },
+                    "literal": {
+                        "type": "boolean",
+                        "description": "Match pattern as plain text (default false)"
+                    },
+                    "case_insensitive": {
+                        "type": "boolean",
+                        "description": "Ignore case (default false)"
+                    },
+                    "path": {
+                        "type": "string",
+                        "description": "Directory or file to search (relative to root, default \".\")"
+                    },
+                    "glob": {
+                        "type": "string",
+                        "description": "Only search files whose path relative to path matches this glob, e.g. **/*.rs"
+                    },
+                    "context": {
+                        "type": "integer",
+                        "description": "Lines to include before and after each match (default 2)"
+                    },
+                    "max_results": {
+                        "type": "integer",
+                        "description": "Maximum number of matches (default 100, at most 1000)"
+                    }
+                },
+                "required": ["pattern"]
+            }),
+            Some(json!({
+                "type": "object",
+                "properties": {
+                    "matches": {
+                        "type": "array",
+                        "description": "Matching lines in file order",
+                        "items": {
+                            "type": "object",
+                            "properties": {
+                                "file": {
+                                    "type": "string",
+                                    "description": "File relative to path"
+                                },
+                                "line": {
+                                    "type": "integer",
+                                    "description": "Line number, starting at 1"
+                                },
+                                "text": {
+                                    "type": "string",
+                                    "description": "The matching line"
+                                },
+                                "before": {
+                                    "type": "array",
+                                    "description": "Lines before the match",
+                                    "items": { "type": "string" }
+                                },
+                                "after": {
+                                    "type": "array",
+                                    "description": "Lines after the match",
+                                    "items": { "type": "string" }
+                                }
+                            }
                         }
+                    },
+                    "files_searched": {
+                        "type": "integer",
+                        "description": "Number of text files searched"
+                    },
+                    "truncated": {
+                        "type": "boolean",
+                        "description": "Whether the search stopped at max_results"
                     }
+                }
+            })),
+            Box::new(move |args| {
+                let sandbox = Arc::clone(&search_sandbox);
+                Box::pin(async move {
+                    let pattern = string_arg(&args, "pattern")?;
+                    let pattern = if args["literal"].as_bool().unwrap_or(false) {
+                        regex::escape(pattern)
+                    } else {
+                        pattern.to_string()
+                    };
+                    let regex = RegexBuilder::new(&pattern)
+                        .case_insensitive(args["case_insensitive"].as_bool().unwrap_or(false))
+                        .build()
+                        .map_err(|e| invalid_arguments(format!("Invalid pattern: {}", e)))?;
+                    let glob = args["glob"]
+                        .as_str()
+                        .map(Pattern::new)
+                        .transpose()
+                        .map_err(|e| invalid_arguments(format!("Invalid glob pattern: {}", e)))?;
+                    let options = SearchOptions {
+                        glob,
+                        context: args["context"]
+                            .as_u64()
+                            .map_or(DEFAULT_SEARCH_CONTEXT, |context| context as usize),
+                        max_results: args["max_results"]
+                            .as_u64()
+                            .map_or(DEFAULT_SEARCH_RESULTS, |max| max as usize)
+                            .min(MAX_RESULTS),
+                    };
+                    let full_path = sandbox.readable(args["path"].as_str().unwrap_or("."))?;
 
-                    Ok(json!({ "entries": result }))
+                    tokio::task::spawn_blocking(move || {
+                        search_files(&sandbox, &full_path, &regex, &options)
+                    })
+                    .await?
                 })
             }),
         );
 
+        // Register directory and file management tools
+        for tool in management_tools(&sandbox) {
+            mcp_server.register_tool(tool).await?;
+        }
+
         // Register all tools with the MCP server
         mcp_server.register_tool(read_tool).await?;
         mcp_server.register_tool(write_tool).await?;
+        mcp_server.register_tool(append_tool).await?;
+        mcp_server.register_tool(tail_tool).await?;
         mcp_server.register_tool(delete_tool).await?;
         mcp_server.register_tool(list_tool).await?;
+        mcp_server.register_tool(glob_tool).await?;
+        mcp_server.register_tool(search_tool).await?;
 
-        Ok(())
+        Ok(sandbox)
     }
 
     /// Initializes the file server by initializing the underlying MCP server
@@ -466,6 +1074,1426 @@ impl FileServer {
     pub async fn list_tools(&self) -> Result<Vec<crate::protocol::ToolSchema>> {
         self.mcp_server.list_tools().await
     }
+
+    /// Reads a resource, i.e. `filesystem`
+    pub async fn read_resource(&mut self, pattern: &str, arguments: &Value) -> Result<Value> {
+        self.mcp_server.read_resource(pattern, arguments).await
+    }
+}
+
+/// Builds the `file_index` tool. The file is read and embedded in segments
+/// by `RagServer::add_large_file`, so only a short report goes back to the
+/// LLM however large the file is.
+pub(crate) fn index_tool(sandbox: Arc<Sandbox>, rag_server: Arc<Mutex<RagServer>>) -> Tool {
+    Tool::with_progress(
+        "file_index".to_string(),
+        "Add a text file of any size to the RAG knowledge base without reading it into the \
+         conversation"
+            .to_string(),
+        json!({
+            "type": "object",
+            "properties": {
+                "path": {
+                    "type": "string",
+                    "description": "Text file to index (relative to root)"
+                }
+            },
+            "required": ["path"]
+        }),
+        Some(json!({
+            "type": "object",
+            "properties": {
+                "success": {
+                    "type": "boolean",
+                    "description": "Whether the file was indexed"
+                },
+                "document_id": {
+                    "type": "string",
+                    "description": "Id of the new RAG document"
+                },
+                "segments": {
+                    "type": "integer",
+                    "description": "Segments the file was streamed in"
+                },
+                "chunks": {
+                    "type": "integer",
+                    "description": "Chunks the file was split into"
+                },
+                "bytes": {
+                    "type": "integer",
+                    "description": "Bytes indexed"
+                }
+            }
+        })),
+        Box::new(move |args, progress| {
+            let sandbox = Arc::clone(&sandbox);
+            let rag_server = Arc::clone(&rag_server);
+            Box::pin(async move {
+                let path_arg = string_arg(&args, "path")?;
+                let path = sandbox.readable(path_arg)?;
+                if !path.is_file() {
+                    return Err(Error::new(ToolError::new(
+                        ToolErrorKind::NotFound,
+                        format!("File not found: {}", path_arg),
+                    )));
+                }
+
+                // Embedding futures are not Sync, so the indexing runs on
+                // its own task and the handler only awaits its handle
+                let report = tokio::spawn(async move {
+                    rag_server
+                        .lock()
+                        .await
+                        .add_large_file(&path, |bytes, size| {
+                            progress.report(bytes as f64, Some(size as f64), Some("bytes indexed"))
+                        })
+                        .await
+                })
+                .await??;
+                Ok(json!({
+                    "success": true,
+                    "document_id": report.document_id,
+                    "segments": report.segments,
+                    "chunks": report.chunks,
+                    "bytes": report.bytes
+                }))
+            })
+        }),
+    )
+}
+
+/// Reads up to `length` bytes at `offset`
+fn read_range(path: &Path, offset: u64, length: u64) -> Result<Vec<u8>> {
+    let mut file = File::open(path)?;
+    file.seek(SeekFrom::Start(offset))?;
+    let mut bytes = Vec::new();
+    file.take(length).read_to_end(&mut bytes)?;
+    Ok(bytes)
+}
+
+/// Decodes UTF-8 text. A `ranged` read may start or end inside a character:
+/// continuation bytes at the start and a character cut off at the end of the
+/// range are dropped, and the number of bytes skipped at the start is
+/// returned with the text.
+fn decode_utf8(mut bytes: Vec<u8>, ranged: bool) -> Result<(String, u64)> {
+    let mut skipped = 0;
+    if ranged {
+        // Continuation bytes look like 0b10xxxxxx
+        skipped = bytes
+            .iter()
+            .take(3)
+            .take_while(|&&b| b & 0xC0 == 0x80)
+            .count();
+        bytes.drain(..skipped);
+        if let Err(e) = std::str::from_utf8(&bytes) {
+            // No error length means the bytes end in an incomplete character
+            if e.error_len().is_none() {
+                bytes.truncate(e.valid_up_to());
+            }
+        }
+    }
+    Ok((String::from_utf8(bytes)?, skipped as u64))
+}
+
+/// MIME type of a file, from its content where that is recognisable and from
+/// its extension otherwise
+fn mime_type(path: &Path, bytes: &[u8]) -> String {
+    if let Some(kind) = infer::get(bytes) {
+        return kind.mime_type().to_string();
+    }
+    match mime_guess::from_path(path).first() {
+        Some(mime) => mime.essence_str().to_string(),
+        None if is_binary(bytes) => "application/octet-stream".to_string(),
+        None => "text/plain".to_string(),
+    }
+}
+
+/// The last `lines` lines of a file, reading backwards from the end in
+/// blocks so large files are not loaded whole
+fn tail(path: &Path, lines: usize) -> Result<String> {
+    let mut file = File::open(path)?;
+    let size = file.metadata()?.len();
+    let mut start = size;
+    let mut bytes = Vec::new();
+
+    // A trailing newline ends the last line rather than starting a new one
+    let wanted = lines + 1;
+    while start > 0 && bytes.iter().filter(|&&b| b == b'\n').count() < wanted {
+        let block = TAIL_BLOCK_SIZE.min(start);
+        start -= block;
+        file.seek(SeekFrom::Start(start))?;
+        let mut buffer = vec![0; block as usize];
+        file.read_exact(&mut buffer)?;
+        buffer.extend_from_slice(&bytes);
+        bytes = buffer;
+    }
+
+    let content = String::from_utf8_lossy(&bytes);
+    let all_lines: Vec<&str> = content.lines().collect();
+    let skip = all_lines.len().saturating_sub(lines);
+    Ok(all_lines[skip..].join("\n"))
+}
+
+/// How `file_read` returns and `file_write` accepts content
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Encoding {
+    Utf8,
+    Base64,
+}
+
+impl Encoding {
+    /// Reads the `encoding` argument; UTF-8 if it is missing
+    fn from_args(args: &Value) -> Result<Self> {
+        match args["encoding"].as_str() {
+            None | Some("utf-8") | Some("utf8") => Ok(Self::Utf8),
+            Some("base64") => Ok(Self::Base64),
+            Some(other) => Err(invalid_arguments(format!(
+                "Invalid argument: encoding must be utf-8 or base64, not {}",
+                other
+            ))),
+        }
+    }
+}
+
+/// Reads the `max_depth` argument of the recursive tools
+fn max_depth_arg(args: &Value) -> usize {
+    args["max_depth"]
+        .as_u64()
+        .map_or(DEFAULT_MAX_DEPTH, |depth| depth as usize)
+}
+
+/// Walks `dir` up to `max_depth` levels deep, skipping hidden entries,
+/// those excluded by `.gitignore` or `.ignore` files and those the policy
+/// hides, in sorted order
+fn walk(sandbox: &Arc<Sandbox>, dir: &Path, max_depth: usize) -> ignore::Walk {
+    let sandbox = Arc::clone(sandbox);
+    WalkBuilder::new(dir)
+        .max_depth(Some(max_depth))
+        // Honour .gitignore files outside of git repositories too
+        .require_git(false)
+        .sort_by_file_name(|a, b| a.cmp(b))
+        .filter_entry(move |entry| {
+            sandbox.permits(entry.path(), entry.file_type().is_some_and(|t| t.is_dir()))
+        })
+        .build()
+}
+
+/// Path of `path` below `dir`, with `/` separators on every platform
+fn relative_name(dir: &Path, path: &Path) -> String {
+    let relative = path.strip_prefix(dir).unwrap_or(path);
+    relative
+        .components()
+        .map(|component| component.as_os_str().to_string_lossy())
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+/// Entries below `dir` matching `options` in the format of
+/// `list_directory`, and whether the listing was cut off at `MAX_RESULTS`
+fn list_recursive(
+    sandbox: &Arc<Sandbox>,
+    dir: &Path,
+    max_depth: usize,
+    options: &ListOptions,
+) -> Result<(Vec<Value>, bool)> {
+    let mut result = Vec::new();
+    let mut truncated = false;
+    // Depth 0 is `dir` itself
+    for entry in walk(sandbox, dir, max_depth).filter_map(|entry| entry.ok()) {
+        if entry.depth() == 0 {
+            continue;
+        }
+        let listing = Listing::new(
+            relative_name(dir, entry.path()),
+            entry.path(),
+            &entry.metadata()?,
+        )?;
+        if !options.matches(&listing) {
+            continue;
+        }
+        if result.len() == MAX_RESULTS {
+            truncated = true;
+            break;
+        }
+        result.push(listing);
+    }
+    Ok((options.sorted(result, None), truncated))
+}
+
+/// Type of a `list_directory` entry. Symbolic links are not followed.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum EntryKind {
+    File,
+    Directory,
+    Symlink,
+}
+
+impl EntryKind {
+    fn of(file_type: fs::FileType) -> Self {
+        if file_type.is_symlink() {
+            Self::Symlink
+        } else if file_type.is_dir() {
+            Self::Directory
+        } else {
+            Self::File
+        }
+    }
+
+    fn as_str(self) -> &'static str {
+        match self {
+            Self::File => "file",
+            Self::Directory => "directory",
+            Self::Symlink => "symlink",
+        }
+    }
+}
+
+/// What `list_directory` can sort its entries by
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum SortKey {
+    Name,
+    Size,
+    Modified,
+}
+
+/// One entry of a `list_directory` response
+struct Listing {
+    name: String,
+    kind: EntryKind,
+    size: u64,
+    modified: SystemTime,
+    target: Option<String>,
+}
+
+impl Listing {
+    /// Describes the entry at `path` from its own (not followed) metadata
+    fn new(name: String, path: &Path, metadata: &fs::Metadata) -> Result<Self> {
+        let kind = EntryKind::of(metadata.file_type());
+        let target = match kind {
+            EntryKind::Symlink => Some(fs::read_link(path)?.to_string_lossy().into_owned()),
+            _ => None,
+        };
+        Ok(Self {
+            name,
+            kind,
+            size: if kind == EntryKind::File {
+                metadata.len()
+            } else {
+                0
+            },
+            modified: metadata.modified()?,
+            target,
+        })
+    }
+
+    fn to_json(&self) -> Value {
+        let mut entry = json!({
+            "name": self.name,
+            "type": self.kind.as_str(),
+            "size": self.size,
+            "modified": DateTime::<Utc>::from(self.modified)
+                .to_rfc3339_opts(SecondsFormat::Secs, true)
+        });
+        if let Some(target) = &self.target {
+            entry["target"] = json!(target);
+        }
+        entry
+    }
+}
+
+/// Sorting and filtering arguments of `list_directory`
+struct ListOptions {
+    sort_by: Option<SortKey>,
+    descending: bool,
+    kind: Option<EntryKind>,
+    name: Option<Pattern>,
+    min_size: Option<u64>,
+    max_size: Option<u64>,
+    modified_after: Option<SystemTime>,
+    modified_before: Option<SystemTime>,
+}
+
+impl ListOptions {
+    fn from_args(args: &Value) -> Result<Self> {
+        let sort_by = match args["sort_by"].as_str() {
+            None => None,
+            Some("name") => Some(SortKey::Name),
+            Some("size") => Some(SortKey::Size),
+            Some("modified") => Some(SortKey::Modified),
+            Some(other) => {
+                return Err(invalid_arguments(format!(
+                    "Invalid argument: sort_by must be name, size or modified, not {}",
+                    other
+                )))
+            }
+        };
+        let kind = match args["type"].as_str() {
+            None => None,
+            Some("file") => Some(EntryKind::File),
+            Some("directory") => Some(EntryKind::Directory),
+            Some("symlink") => Some(EntryKind::Symlink),
+            Some(other) => {
+                return Err(invalid_arguments(format!(
+                    "Invalid argument: type must be file, directory or symlink, not {}",
+                    other
+                )))
+            }
+        };
+        let name = args["name"]
+            .as_str()
+            .map(|pattern| {
+                Pattern::new(pattern)
+                    .map_err(|e| invalid_arguments(format!("Invalid glob pattern: {}", e)))
+            })
+            .transpose()?;
+        let timestamp = |key: &str| {
+            args[key]
+                .as_str()
+                .map(|value| {
+                    DateTime::parse_from_rfc3339(value)
+                        .map(SystemTime::from)
+                        .map_err(|e| {
+                            invalid_arguments(format!(
+                                "Invalid argument: {} must be an RFC 3339 timestamp: {}",
+                                key, e
+                            ))
+                        })
+                })
+                .transpose()
+        };
+        Ok(Self {
+            sort_by,
+            descending: args["descending"].as_bool().unwrap_or(false),
+            kind,
+            name,
+            min_size: args["min_size"].as_u64(),
+            max_size: args["max_size"].as_u64(),
+            modified_after: timestamp("modified_after")?,
+            modified_before: timestamp("modified_before")?,
+        })
+    }
+
+    /// Whether `listing` passes every filter. The size filters only apply
+    /// to files.
+    fn matches(&self, listing: &Listing) -> bool {
+        let file_name = listing.name.rsplit('/').next().unwrap_or(&listing.name);
+        let is_file = listing.kind == EntryKind::File;
+        !matches!(self.kind, Some(kind) if kind != listing.kind)
+            && !matches!(&self.name, Some(pattern) if !pattern.matches(file_name))
+            && !matches!(self.min_size, Some(min) if !is_file || listing.size < min)
+            && !matches!(self.max_size, Some(max) if !is_file || listing.size > max)
+            && !matches!(self.modified_after, Some(after) if listing.modified <= after)
+            && !matches!(self.modified_before, Some(before) if listing.modified >= before)
+    }
+
+    /// `listings` as JSON, sorted by `sort_by` or else by `default`
+    fn sorted(&self, mut listings: Vec<Listing>, default: Option<SortKey>) -> Vec<Value> {
+        if let Some(key) = self.sort_by.or(default) {
+            listings.sort_by(|a, b| {
+                let order = match key {
+                    SortKey::Name => a.name.cmp(&b.name),
+                    SortKey::Size => a.size.cmp(&b.size).then_with(|| a.name.cmp(&b.name)),
+                    SortKey::Modified => a
+                        .modified
+                        .cmp(&b.modified)
+                        .then_with(|| a.name.cmp(&b.name)),
+                };
+                if self.descending {
+                    order.reverse()
+                } else {
+                    order
+                }
+            });
+        }
+        listings.iter().map(Listing::to_json).collect()
+    }
+}
+
+/// Files below `dir` whose path relative to `dir` matches `pattern`, and
+/// whether the search was cut off at `MAX_RESULTS`
+fn glob_files(
+    sandbox: &Arc<Sandbox>,
+    dir: &Path,
+    pattern: &Pattern,
+    max_depth: usize,
+) -> Result<(Vec<String>, bool)> {
+    let options = MatchOptions {
+        require_literal_separator: true,
+        ..MatchOptions::new()
+    };
+    let mut files = Vec::new();
+    for entry in walk(sandbox, dir, max_depth).filter_map(|entry| entry.ok()) {
+        if !entry.file_type().is_some_and(|t| t.is_file()) {
+            continue;
+        }
+        let name = relative_name(dir, entry.path());
+        if pattern.matches_with(&name, options) {
+            if files.len() == MAX_RESULTS {
+                return Ok((files, true));
+            }
+            files.push(name);
+        }
+    }
+    Ok((files, false))
+}
+
+/// Reads the string argument `key` of a tool call
+fn string_arg<'a>(args: &'a Value, key: &str) -> Result<&'a str> {
+    args[key]
+        .as_str()
+        .ok_or_else(|| invalid_arguments(format!("Invalid argument: {}", key)))
+}
+
+/// Builds the `dir_create`, `dir_delete`, `file_move`, `file_copy`,
+/// `file_rename`, `archive_create`, `archive_extract`, `file_diff` and
+/// `file_apply_patch` tools
+fn management_tools(sandbox: &Arc<Sandbox>) -> Vec<Tool> {
+    let success_schema = |extra: Value| {
+        let mut properties = json!({
+            "success": {
+                "type": "boolean",
+                "description": "Whether the operation was successful"
+            }
+        });
+        if let (Some(properties), Value::Object(extra)) = (properties.as_object_mut(), extra) {
+            properties.extend(extra);
+        }
+        json!({ "type": "object", "properties": properties })
+    };
+    let transfer_schema = |verb: &str| {
+        json!({
+            "type": "object",
+            "properties": {
+                "source": {
+                    "type": "string",
+                    "description": format!("File or directory to {} (relative to root)", verb)
+                },
+                "destination": {
+                    "type": "string",
+                    "description": "New path (relative to root); parent directories are created"
+                },
+                "overwrite": {
+                    "type": "boolean",
+                    "description": "Replace an existing destination (default false)"
+                },
+                "confirm": {
+                    "type": "string",
+                    "description": "Must repeat destination to overwrite a directory"
+                }
+            },
+            "required": ["source", "destination"]
+        })
+    };
+
+    let tool = |name: &str,
+                description: &str,
+                input_schema: Value,
+                output_schema: Value,
+                handler: fn(&Sandbox, &Value) -> Result<Value>| {
+        let sandbox = Arc::clone(sandbox);
+        Tool::new(
+            name.to_string(),
+            description.to_string(),
+            input_schema,
+            Some(output_schema),
+            Box::new(move |args| {
+                let sandbox = Arc::clone(&sandbox);
+                Box::pin(async move {
+                    tokio::task::spawn_blocking(move || handler(&sandbox, &args)).await?
+                })
+            }),
+        )
+    };
+
+    vec![
+        tool(
+            "dir_create",
+            "Create a directory, including missing parent directories",
+            json!({
+                "type": "object",
+                "properties": {
+                    "path": {
+                        "type": "string",
+                        "description": "Directory to create (relative to root)"
+                    }
+                },
+                "required": ["path"]
+            }),
+            success_schema(json!({
+                "created": {
+                    "type": "boolean",
+                    "description": "Whether the directory did not exist before"
+                }
+            })),
+            |sandbox, args| {
+                let path = sandbox.writable(string_arg(args, "path")?)?;
+                let created = !path.is_dir();
+                fs::create_dir_all(&path)?;
+                Ok(json!({ "success": true, "created": created }))
+            },
+        ),
+        tool(
+            "dir_delete",
+            "Delete a directory. Non-empty directories need recursive: true and confirm set to \
+             the same path.",
+            json!({
+                "type": "object",
+                "properties": {
+                    "path": {
+                        "type": "string",
+                        "description": "Directory to delete (relative to root)"
+                    },
+                    "recursive": {
+                        "type": "boolean",
+                        "description": "Also delete everything inside (default false)"
+                    },
+                    "confirm": {
+                        "type": "string",
+                        "description": "Must repeat path to confirm a recursive delete"
+                    }
+                },
+                "required": ["path"]
+            }),
+            success_schema(json!({
+                "deleted": {
+                    "type": "boolean",
+                    "description": "Whether the directory was found and deleted"
+                }
+            })),
+            |sandbox, args| {
+                let path_arg = string_arg(args, "path")?;
+                let path = sandbox.writable(path_arg)?;
+                if path == sandbox.root()
+                    || path_arg.trim_matches(|c| c == '.' || c == '/').is_empty()
+                {
+                    return Err(Error::new(ToolError::new(
+                        ToolErrorKind::PermissionDenied,
+                        "Refusing to delete the root directory",
+                    )));
+                }
+                if !path.exists() {
+                    return Ok(json!({ "success": true, "deleted": false }));
+                }
+                if !path.is_dir() {
+                    return Err(invalid_arguments(format!("Not a directory: {}", path_arg)));
+                }
+
+                if args["recursive"].as_bool().unwrap_or(false) {
+                    if args["confirm"].as_str() != Some(path_arg) {
+                        return Err(invalid_arguments(
+                            "Recursive delete needs confirm set to the same path",
+                        ));
+                    }
+                    sandbox.check_tree(&path)?;
+                    fs::remove_dir_all(&path)?;
+                } else {
+                    fs::remove_dir(&path)
+                        .with_context(|| format!("Failed to delete {} (is it empty?)", path_arg))?;
+                }
+                info!("Deleted directory {}", path.display());
+                Ok(json!({ "success": true, "deleted": true }))
+            },
+        ),
+        tool(
+            "file_move",
+            "Move a file or directory to another path",
+            transfer_schema("move"),
+            success_schema(json!({})),
+            |sandbox, args| {
+                let (source, destination) = transfer_paths(sandbox, args)?;
+                replace_entry(&destination, &source)?;
+                Ok(json!({ "success": true }))
+            },
+        ),
+        tool(
+            "file_copy",
+            "Copy a file or directory to another path",
+            transfer_schema("copy"),
+            success_schema(json!({})),
+            |sandbox, args| {
+                sandbox.check_copy_size(&sandbox.readable(string_arg(args, "source")?)?)?;
+                let (source, destination) = transfer_paths(sandbox, args)?;
+                // Copied into a temporary sibling first, so a failed copy
+                // leaves the destination as it was
+                let staging = staging_dir(&destination)?;
+                let copy = staging.path().join("copy");
+                copy_recursive(&source, &copy)?;
+                replace_entry(&destination, &copy)?;
+                Ok(json!({ "success": true }))
+            },
+        ),
+        tool(
+            "file_rename",
+            "Rename a file or directory, keeping it in the same directory",
+            json!({
+                "type": "object",
+                "properties": {
+                    "path": {
+                        "type": "string",
+                        "description": "File or directory to rename (relative to root)"
+                    },
+                    "new_name": {
+                        "type": "string",
+                        "description": "New name, without any directory"
+                    },
+                    "overwrite": {
+                        "type": "boolean",
+                        "description": "Replace an existing entry with that name (default false)"
+                    },
+                    "confirm": {
+                        "type": "string",
+                        "description": "Must repeat the new path (relative to root) to overwrite a directory"
+                    }
+                },
+                "required": ["path", "new_name"]
+            }),
+            success_schema(json!({
+                "path": {
+                    "type": "string",
+                    "description": "New path relative to root"
+                }
+            })),
+            |sandbox, args| {
+                let path_arg = string_arg(args, "path")?;
+                let new_name = string_arg(args, "new_name")?;
+                if new_name.is_empty()
+                    || new_name == "."
+                    || new_name == ".."
+                    || new_name.contains(['/', '\\'])
+                {
+                    return Err(invalid_arguments(format!("Invalid new name: {}", new_name)));
+                }
+                let new_path = Path::new(path_arg).with_file_name(new_name);
+                let new_path = new_path.to_string_lossy();
+                let (source, destination) = checked_transfer(
+                    sandbox,
+                    path_arg,
+                    &new_path,
+                    args["overwrite"].as_bool().unwrap_or(false),
+                    args["confirm"].as_str(),
+                )?;
+                replace_entry(&destination, &source)?;
+                Ok(json!({ "success": true, "path": new_path }))
+            },
+        ),
+        tool(
+            "archive_create",
+            "Pack files and directories into a zip or tar.gz archive",
+            json!({
+                "type": "object",
+                "properties": {
+                    "path": {
+                        "type": "string",
+                        "description": "Archive to create (relative to root), e.g. results.zip"
+                    },
+                    "sources": {
+                        "type": "array",
+                        "items": { "type": "string" },
+                        "description": "Files and directories to pack (relative to root)"
+                    },
+                    "format": {
+                        "type": "string",
+                        "enum": ["zip", "tar.gz"],
+                        "description": "Archive format (default: from the path's extension)"
+                    },
+                    "overwrite": {
+                        "type": "boolean",
+                        "description": "Replace an existing archive (default false)"
+                    }
+                },
+                "required": ["path", "sources"]
+            }),
+            success_schema(json!({
+                "entries": {
+                    "type": "integer",
+                    "description": "Number of files and directories packed"
+                },
+                "size": {
+                    "type": "integer",
+                    "description": "Size of the archive in bytes"
+                }
+            })),
+            |sandbox, args| {
+                let path_arg = string_arg(args, "path")?;
+                let archive = sandbox.writable(path_arg)?;
+                let format = ArchiveFormat::detect(args["format"].as_str(), &archive)?;
+                let sources = args["sources"]
+                    .as_array()
+                    .filter(|sources| !sources.is_empty())
+                    .ok_or_else(|| invalid_arguments("Invalid argument: sources"))?
+                    .iter()
+                    .map(|source| {
+                        let source = source
+                            .as_str()
+                            .ok_or_else(|| invalid_arguments("Invalid argument: sources"))?;
+                        let path = sandbox.readable(source)?;
+                        if !path.exists() {
+                            return Err(Error::new(ToolError::new(
+                                ToolErrorKind::NotFound,
+                                format!("Source not found: {}", source),
+                            )));
+                        }
+                        sandbox.check_tree(&path)?;
+                        Ok(path)
+                    })
+                    .collect::<Result<Vec<_>>>()?;
+                if archive.exists() && !args["overwrite"].as_bool().unwrap_or(false) {
+                    return Err(invalid_arguments(format!(
+                        "Destination already exists: {} (set overwrite to replace it)",
+                        path_arg
+                    )));
+                }
+                if let Some(parent) = archive.parent() {
+                    fs::create_dir_all(parent)?;
+                }
+
+                let entries = file_archive::create(&archive, sandbox.root(), &sources, format)?;
+                // The archive itself now counts towards the quota
+                let size = fs::metadata(&archive)?.len();
+                if let Err(e) = sandbox
+                    .check_write_limit(size)
+                    .and_then(|_| sandbox.check_quota(0))
+                {
+                    fs::remove_file(&archive)?;
+                    return Err(e);
+                }
+                info!(
+                    "Created archive {} with {} entries",
+                    archive.display(),
+                    entries
+                );
+                Ok(json!({
+                    "success": true,
+                    "entries": entries,
+                    "size": fs::metadata(&archive)?.len()
+                }))
+            },
+        ),
+        tool(
+            "archive_extract",
+            "Unpack a zip or tar.gz archive. Entries that would land outside the destination \
+             are refused.",
+            json!({
+                "type": "object",
+                "properties": {
+                    "path": {
+                        "type": "string",
+                        "description": "Archive to unpack (relative to root)"
+                    },
+                    "destination": {
+                        "type": "string",
+                        "description": "Directory to unpack into (relative to root, default: \
+                                        the archive's directory)"
+                    },
+                    "format": {
+                        "type": "string",
+                        "enum": ["zip", "tar.gz"],
+                        "description": "Archive format (default: from the path's extension)"
+                    },
+                    "overwrite": {
+                        "type": "boolean",
+                        "description": "Replace existing files (default false)"
+                    }
+                },
+                "required": ["path"]
+            }),
+            success_schema(json!({
+                "entries": {
+                    "type": "array",
+                    "items": { "type": "string" },
+                    "description": "Extracted files and directories, relative to the destination"
+                },
+                "truncated": {
+                    "type": "boolean",
+                    "description": "Whether more entries were extracted than listed"
+                }
+            })),
+            |sandbox, args| {
+                let path_arg = string_arg(args, "path")?;
+                let archive = sandbox.readable(path_arg)?;
+                if !archive.is_file() {
+                    return Err(Error::new(ToolError::new(
+                        ToolErrorKind::NotFound,
+                        format!("Archive not found: {}", path_arg),
+                    )));
+                }
+                let format = ArchiveFormat::detect(args["format"].as_str(), &archive)?;
+                let destination = match args["destination"].as_str() {
+                    Some(destination) => sandbox.writable(destination)?,
+                    None => {
+                        let directory = Path::new(path_arg).parent().unwrap_or(Path::new(""));
+                        sandbox.writable(&directory.to_string_lossy())?
+                    }
+                };
+
+                let permits =
+                    |name: &Path, is_dir: bool| sandbox.permits(&destination.join(name), is_dir);
+                let options = ExtractOptions {
+                    overwrite: args["overwrite"].as_bool().unwrap_or(false),
+                    max_size: sandbox.write_allowance()?,
+                    permits: Some(&permits),
+                };
+                let mut entries = file_archive::extract(&archive, &destination, format, &options)?;
+                info!(
+                    "Extracted {} entries from {} into {}",
+                    entries.len(),
+                    archive.display(),
+                    destination.display()
+                );
+                let truncated = entries.len() > MAX_RESULTS;
+                entries.truncate(MAX_RESULTS);
+                Ok(json!({ "success": true, "entries": entries, "truncated": truncated }))
+            },
+        ),
+        tool(
+            "file_diff",
+            "Show the differences between two text files as a unified diff",
+            json!({
+                "type": "object",
+                "properties": {
+                    "path_a": {
+                        "type": "string",
+                        "description": "Original file (relative to root)"
+                    },
+                    "path_b": {
+                        "type": "string",
+                        "description": "Changed file (relative to root)"
+                    },
+                    "context": {
+                        "type": "integer",
+                        "description": "Unchanged lines shown around each change (default 3)"
+                    }
+                },
+                "required": ["path_a", "path_b"]
+            }),
+            success_schema(json!({
+                "diff": {
+                    "type": "string",
+                    "description": "Unified diff, empty when the files are identical"
+                },
+                "changed": {
+                    "type": "boolean",
+                    "description": "Whether the files differ"
+                }
+            })),
+            |sandbox, args| {
+                let path_a = string_arg(args, "path_a")?;
+                let path_b = string_arg(args, "path_b")?;
+                let text_a = read_text(&sandbox.readable(path_a)?, path_a)?;
+                let text_b = read_text(&sandbox.readable(path_b)?, path_b)?;
+                let context = args["context"]
+                    .as_u64()
+                    .map_or(DEFAULT_DIFF_CONTEXT, |context| context as usize);
+
+                let diff = TextDiff::from_lines(&text_a, &text_b)
+                    .unified_diff()
+                    .context_radius(context)
+                    .header(path_a, path_b)
+                    .to_string();
+                Ok(json!({ "success": true, "diff": diff, "changed": text_a != text_b }))
+            },
+        ),
+        tool(
+            "file_apply_patch",
+            "Apply a unified diff to a text file. The file is only replaced if every hunk \
+             applies.",
+            json!({
+                "type": "object",
+                "properties": {
+                    "path": {
+                        "type": "string",
+                        "description": "File to patch (relative to root); a missing file is \
+                                        patched as empty"
+                    },
+                    "patch": {
+                        "type": "string",
+                        "description": "Unified diff for this one file, as produced by file_diff"
+                    }
+                },
+                "required": ["path", "patch"]
+            }),
+            success_schema(json!({
+                "hunks": {
+                    "type": "integer",
+                    "description": "Number of hunks applied"
+                },
+                "created": {
+                    "type": "boolean",
+                    "description": "Whether the file did not exist before"
+                }
+            })),
+            |sandbox, args| {
+                let path_arg = string_arg(args, "path")?;
+                let path = sandbox.writable(path_arg)?;
+                let created = !path.exists();
+                let original = if created {
+                    String::new()
+                } else {
+                    read_text(&path, path_arg)?
+                };
+
+                let (patched, hunks) = apply_patch(&original, string_arg(args, "patch")?)?;
+                sandbox.check_size(patched.len() as u64)?;
+                write_atomic(&path, patched.as_bytes())?;
+                info!("Applied {} hunks to {}", hunks, path.display());
+                Ok(json!({ "success": true, "hunks": hunks, "created": created }))
+            },
+        ),
+        tool(
+            "file_from_template",
+            "Create a file from a named template, filling in JSON variables. Templates use \
+             Jinja syntax ({{ name }}, {% if %}, {% for %}); see file_templates for the names.",
+            json!({
+                "type": "object",
+                "properties": {
+                    "template": {
+                        "type": "string",
+                        "description": "Template name, its path below the templates directory"
+                    },
+                    "path": {
+                        "type": "string",
+                        "description": "File to create (relative to root); parent directories \
+                                        are created"
+                    },
+                    "variables": {
+                        "type": "object",
+                        "description": "Values for the template's variables; path, file_name, \
+                                        file_stem, date and year are filled in unless given"
+                    },
+                    "overwrite": {
+                        "type": "boolean",
+                        "description": "Replace an existing file (default false)"
+                    }
+                },
+                "required": ["template", "path"]
+            }),
+            success_schema(json!({
+                "size": {
+                    "type": "integer",
+                    "description": "Bytes written"
+                },
+                "created": {
+                    "type": "boolean",
+                    "description": "Whether the file did not exist before"
+                }
+            })),
+            |sandbox, args| {
+                let name = string_arg(args, "template")?;
+                let path_arg = string_arg(args, "path")?;
+                let path = sandbox.writable(path_arg)?;
+                let created = !path.exists();
+                if !created && !args["overwrite"].as_bool().unwrap_or(false) {
+                    return Err(invalid_arguments(format!(
+                        "File already exists: {} (set overwrite to replace it)",
+                        path_arg
+                    )));
+                }
+
+                let content = file_template::render(
+                    &sandbox.templates_dir(),
+                    name,
+                    path_arg,
+                    &args["variables"],
+                )?;
+                sandbox.check_size(content.len() as u64)?;
+                if let Some(parent) = path.parent() {
+                    fs::create_dir_all(parent)?;
+                }
+                write_atomic(&path, content.as_bytes())?;
+                info!("Created {} from template {}", path.display(), name);
+                Ok(json!({ "success": true, "size": content.len(), "created": created }))
+            },
+        ),
+        tool(
+            "file_templates",
+            "List the templates file_from_template can create files from",
+            json!({
+                "type": "object",
+                "properties": {}
+            }),
+            json!({
+                "type": "object",
+                "properties": {
+                    "templates": {
+                        "type": "array",
+                        "description": "Template names, relative to the templates directory",
+                        "items": {
+                            "type": "string"
+                        }
+                    }
+                }
+            }),
+            |sandbox, _args| {
+                Ok(json!({ "templates": file_template::available(&sandbox.templates_dir()) }))
+            },
+        ),
+    ]
+}
+
+/// The `filesystem` resource: `cwd` returns the current directory, `space`
+/// the disk space of the root's file system and the bytes used below the root
+pub(crate) fn filesystem_resource(sandbox: Arc<Sandbox>) -> Resource {
+    Resource::new(
+        "filesystem".to_string(),
+        "Local file system access".to_string(),
+        Some(json!({
+            "type": "object",
+            "properties": {
+                "action": {
+                    "type": "string",
+                    "description": "Action to perform (cwd/space)",
+                    "enum": ["cwd", "space"]
+                }
+            },
+            "required": ["action"]
+        })),
+        Some(json!({
+            "type": "object",
+            "properties": {
+                "current_directory": {
+                    "type": "string",
+                    "description": "Current working directory"
+                },
+                "available_space": {
+                    "type": "integer",
+                    "description": "Disk space available to this process in bytes"
+                },
+                "total_space": {
+                    "type": "integer",
+                    "description": "Size of the file system holding the root in bytes"
+                },
+                "used": {
+                    "type": "integer",
+                    "description": "Bytes taken up by the files below the root"
+                },
+                "quota": {
+                    "type": ["integer", "null"],
+                    "description": "Bytes the files below the root may take up, if limited"
+                },
+                "quota_remaining": {
+                    "type": ["integer", "null"],
+                    "description": "Bytes that may still be written under the quota"
+                }
+            }
+        })),
+        Box::new(move |args| {
+            let sandbox = Arc::clone(&sandbox);
+            Box::pin(async move {
+                match args.get("action").and_then(|v| v.as_str()) {
+                    Some("cwd") => Ok(json!({
+                        "current_directory": std::env::current_dir()
+                            .unwrap_or_default()
+                            .to_string_lossy()
+                            .to_string()
+                    })),
+                    Some("space") => {
+                        tokio::task::spawn_blocking(move || disk_space(&sandbox)).await?
+                    }
+                    _ => Err(invalid_arguments("Invalid action")),
+                }
+            })
+        }),
+    )
+}
+
+/// The `file:///{path}` resource: the text of a file below the root, read
+/// with the same policy as `file_read`
+pub(crate) fn file_resource(sandbox: Arc<Sandbox>) -> Resource {
+    Resource::new(
+        "file:///{path}".to_string(),
+        "Text of a file below the root".to_string(),
+        Some(json!({
+            "type": "object",
+            "properties": {
+                "path": {
+                    "type": "string",
+                    "description": "Path of the file relative to the root"
+                }
+            },
+            "required": ["path"]
+        })),
+        Some(json!({
+            "type": "object",
+            "properties": {
+                "path": { "type": "string" },
+                "content": { "type": "string" },
+                "size": { "type": "integer" }
+            }
+        })),
+        Box::new(move |args| {
+            let sandbox = Arc::clone(&sandbox);
+            Box::pin(async move {
+                let path = string_arg(&args, "path")?.to_string();
+                tokio::task::spawn_blocking(move || {
+                    let content = read_text(&sandbox.readable(&path)?, &path)?;
+                    Ok(json!({ "path": path, "size": content.len(), "content": content }))
+                })
+                .await?
+            })
+        }),
+    )
+}
+
+/// Response of the `filesystem` resource's `space` action
+fn disk_space(sandbox: &Sandbox) -> Result<Value> {
+    let root = sandbox.root();
+    let used = sandbox.usage()?;
+    let quota = sandbox.quota();
+    Ok(json!({
+        "available_space": fs2::available_space(root)?,
+        "total_space": fs2::total_space(root)?,
+        "used": used,
+        "quota": quota,
+        "quota_remaining": quota.map(|quota| quota.saturating_sub(used))
+    }))
+}
+
+/// Reads a whole text file for `file_diff`, `file_apply_patch` and the
+/// `file:///{path}` resource
+fn read_text(path: &Path, name: &str) -> Result<String> {
+    if !path.is_file() {
+        return Err(Error::new(ToolError::new(
+            ToolErrorKind::NotFound,
+            format!("File not found: {}", name),
+        )));
+    }
+    if fs::metadata(path)?.len() > MAX_READ_SIZE {
+        return Err(invalid_arguments(format!("File too large: {}", name)));
+    }
+    String::from_utf8(fs::read(path)?)
+        .map_err(|_| invalid_arguments(format!("Not a text file: {}", name)))
+}
+
+/// Applies a single-file unified diff to `original` and returns the new text
+/// and the number of hunks
+fn apply_patch(original: &str, patch: &str) -> Result<(String, usize)> {
+    let patch = diffy::Patch::from_str(patch)
+        .map_err(|e| invalid_arguments(format!("Invalid patch: {}", e)))?;
+    let patched = diffy::apply(original, &patch)
+        .map_err(|e| invalid_arguments(format!("Patch does not apply: {}", e)))?;
+    Ok((patched, patch.hunks().len()))
+}
+
+/// Replaces `path` with `content` by writing a temporary file next to it and
+/// renaming it over the original, so readers never see a partial write
+fn write_atomic(path: &Path, content: &[u8]) -> Result<()> {
+    let parent = path
+        .parent()
+        .ok_or_else(|| invalid_arguments(format!("Invalid path: {}", path.display())))?;
+    fs::create_dir_all(parent)?;
+    let mut temp = tempfile::NamedTempFile::new_in(parent)?;
+    temp.write_all(content)?;
+    if let Ok(metadata) = fs::metadata(path) {
+        fs::set_permissions(temp.path(), metadata.permissions())?;
+    }
+    temp.persist(path).map_err(|e| e.error)?;
+    Ok(())
+}
+
+/// Source and destination of `file_move` and `file_copy`
+fn transfer_paths(sandbox: &Sandbox, args: &Value) -> Result<(PathBuf, PathBuf)> {
+    checked_transfer(
+        sandbox,
+        string_arg(args, "source")?,
+        string_arg(args, "destination")?,
+        args["overwrite"].as_bool().unwrap_or(false),
+        args["confirm"].as_str(),
+    )
+}
+
+/// Resolves a transfer from `source` to `destination`, which must exist and
+/// not exist (unless `overwrite`) respectively. A directory is only
+/// overwritten when `confirm` repeats `destination`. Missing parent
+/// directories of the destination are created. Sources or replaced
+/// directories holding files the policy hides are refused.
+fn checked_transfer(
+    sandbox: &Sandbox,
+    source: &str,
+    destination: &str,
+    overwrite: bool,
+    confirm: Option<&str>,
+) -> Result<(PathBuf, PathBuf)> {
+    let source_path = sandbox.readable(source)?;
+    let destination_path = sandbox.writable(destination)?;
+    if !source_path.exists() {
+        return Err(Error::new(ToolError::new(
+            ToolErrorKind::NotFound,
+            format!("Source not found: {}", source),
+        )));
+    }
+    sandbox.check_tree(&source_path)?;
+    if destination_path.starts_with(&source_path) {
+        return Err(invalid_arguments("Destination is inside the source"));
+    }
+    if destination_path.exists() {
+        if !overwrite {
+            return Err(invalid_arguments(format!(
+                "Destination already exists: {} (set overwrite to replace it)",
+                destination
+            )));
+        }
+        sandbox.check_tree(&destination_path)?;
+        if destination_path.is_dir() && confirm != Some(destination) {
+            return Err(invalid_arguments(
+                "Overwriting a directory needs confirm set to the destination path",
+            ));
+        }
+    }
+    if let Some(parent) = destination_path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    Ok((source_path, destination_path))
+}
+
+/// Temporary directory next to `path`, deleted with its contents when dropped
+fn staging_dir(path: &Path) -> Result<tempfile::TempDir> {
+    let parent = path
+        .parent()
+        .ok_or_else(|| invalid_arguments(format!("Invalid path: {}", path.display())))?;
+    Ok(tempfile::Builder::new()
+        .prefix(".transfer-")
+        .tempdir_in(parent)?)
+}
+
+/// Renames `new` to `destination`. An existing destination is first renamed
+/// to a temporary sibling, and deleted once `new` has taken its place or
+/// renamed back if that fails.
+fn replace_entry(destination: &Path, new: &Path) -> Result<()> {
+    if fs::symlink_metadata(destination).is_err() {
+        fs::rename(new, destination)?;
+        return Ok(());
+    }
+
+    let staging = staging_dir(destination)?;
+    let old = staging.path().join("old");
+    fs::rename(destination, &old)?;
+    if let Err(e) = fs::rename(new, destination) {
+        fs::rename(&old, destination)?;
+        return Err(e.into());
+    }
+    Ok(())
+}
+
+/// Copies a file, or a directory with everything inside it
+fn copy_recursive(source: &Path, destination: &Path) -> Result<()> {
+    // The target of a link may lie outside the root and is not counted by
+    // the quota, so links are refused rather than followed
+    if fs::symlink_metadata(source)?.file_type().is_symlink() {
+        return Err(Error::new(ToolError::new(
+            ToolErrorKind::PermissionDenied,
+            "Symbolic links cannot be copied",
+        )));
+    }
+    if !source.is_dir() {
+        fs::copy(source, destination)?;
+        return Ok(());
+    }
+    for entry in WalkDir::new(source) {
+        let entry = entry?;
+        let relative = entry.path().strip_prefix(source)?;
+        if entry.path_is_symlink() {
+            return Err(Error::new(ToolError::new(
+                ToolErrorKind::PermissionDenied,
+                format!("Symbolic links cannot be copied: {}", relative.display()),
+            )));
+        }
+        let target = destination.join(relative);
+        if entry.file_type().is_dir() {
+            fs::create_dir_all(&target)?;
+        } else {
+            fs::copy(entry.path(), &target)?;
+        }
+    }
+    Ok(())
+}
+
+/// Limits of a `file_search`
+struct SearchOptions {
+    glob: Option<Pattern>,
+    context: usize,
+    max_results: usize,
+}
+
+/// Searches the text files at or below `path` for `regex` and returns the
+/// `file_search` response
+fn search_files(
+    sandbox: &Arc<Sandbox>,
+    path: &Path,
+    regex: &Regex,
+    options: &SearchOptions,
+) -> Result<Value> {
+    let glob_options = MatchOptions {
+        require_literal_separator: true,
+        ..MatchOptions::new()
+    };
+    let mut matches = Vec::new();
+    let mut files_searched = 0;
+
+    for entry in walk(sandbox, path, DEFAULT_MAX_DEPTH).filter_map(|entry| entry.ok()) {
+        if !entry.file_type().is_some_and(|t| t.is_file()) {
+            continue;
+        }
+        // A file given as `path` is named by its file name
+        let name = if entry.path() == path {
+            entry.file_name().to_string_lossy().into_owned()
+        } else {
+            relative_name(path, entry.path())
+        };
+        if let Some(glob) = &options.glob {
+            if !glob.matches_with(&name, glob_options) {
+                continue;
+            }
+        }
+        if entry.metadata()?.len() > MAX_SEARCH_FILE_SIZE {
+            continue;
+        }
+        let bytes = match fs::read(entry.path()) {
+            Ok(bytes) => bytes,
+            Err(e) => {
+                error!("Failed to read {}: {}", entry.path().display(), e);
+                continue;
+            }
+        };
+        if is_binary(&bytes) {
+            continue;
+        }
+        files_searched += 1;
+
+        let content = String::from_utf8_lossy(&bytes);
+        let lines: Vec<&str> = content.lines().collect();
+        for (index, line) in lines.iter().enumerate() {
+            if !regex.is_match(line) {
+                continue;
+            }
+            if matches.len() == options.max_results {
+                return Ok(json!({
+                    "matches": matches,
+                    "files_searched": files_searched,
+                    "truncated": true
+                }));
+            }
+            let before = index.saturating_sub(options.context);
+            let after = (index + 1 + options.context).min(lines.len());
+            matches.push(json!({
+                "file": name,
+                "line": index + 1,
+                "text": line,
+                "before": lines[before..index],
+                "after": lines[index + 1..after]
+            }));
+        }
+    }
+
+    Ok(json!({
+        "matches": matches,
+        "files_searched": files_searched,
+        "truncated": false
+    }))
+}
+
+/// Treats content with a NUL byte near the start as binary, like git does
+fn is_binary(bytes: &[u8]) -> bool {
+    bytes[..bytes.len().min(BINARY_CHECK_LEN)].contains(&0)
 }
 
 /// Test module for file server functionality
@@ -614,4 +2642,931 @@ mod tests {
 
         file_server.cleanup().await.unwrap();
     }
+
+    /// Creates a small source tree with a `.gitignore`d build directory
+    /// Runs the tool `name` as the chatbot would
+    async fn run(file_server: &mut FileServer, name: &str, args: Value) -> Result<Value> {
+        file_server
+            .execute_tool(name, &args, 1, std::time::Duration::from_millis(10))
+            .await
+    }
+
+    fn create_tree(root: &Path) {
+        for (path, content) in [
+            ("src/main.rs", "fn main() {}"),
+            ("src/server/mod.rs", "mod file;"),
+            ("src/server/notes.md", "notes"),
+            ("target/debug/build.rs", "// generated"),
+            (".gitignore", "target/\n"),
+            ("README.md", "readme"),
+        ] {
+            let path = root.join(path);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, content).unwrap();
+        }
+    }
+
+    /// Tests recursive listing with depth limits and .gitignore rules
+    #[tokio::test]
+    async fn test_recursive_listing() {
+        let temp_dir = TempDir::new().unwrap();
+        create_tree(temp_dir.path());
+        let mut file_server = FileServer::new(temp_dir.path().to_str().unwrap())
+            .await
+            .unwrap();
+
+        let list_result = file_server
+            .execute_tool(
+                "list_directory",
+                &json!({ "path": ".", "recursive": true }),
+                3,
+                std::time::Duration::from_secs(1),
+            )
+            .await
+            .unwrap();
+        let names: Vec<&str> = list_result["entries"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|entry| entry["name"].as_str().unwrap())
+            .collect();
+        assert_eq!(
+            names,
+            vec![
+                "README.md",
+                "src",
+                "src/main.rs",
+                "src/server",
+                "src/server/mod.rs",
+                "src/server/notes.md"
+            ]
+        );
+        assert_eq!(list_result["truncated"], false);
+
+        let list_result = file_server
+            .execute_tool(
+                "list_directory",
+                &json!({ "path": "src", "recursive": true, "max_depth": 1 }),
+                3,
+                std::time::Duration::from_secs(1),
+            )
+            .await
+            .unwrap();
+        assert_eq!(list_result["entries"].as_array().unwrap().len(), 2);
+    }
+
+    /// Tests timestamps, entry types, sorting and filtering in listings
+    #[tokio::test]
+    async fn test_listing_sort_and_filter() {
+        async fn names(file_server: &mut FileServer, args: Value) -> Vec<String> {
+            let result = file_server
+                .execute_tool(
+                    "list_directory",
+                    &args,
+                    1,
+                    std::time::Duration::from_millis(10),
+                )
+                .await
+                .unwrap();
+            result["entries"]
+                .as_array()
+                .unwrap()
+                .iter()
+                .map(|entry| entry["name"].as_str().unwrap().to_string())
+                .collect()
+        }
+
+        let temp_dir = TempDir::new().unwrap();
+        let root = temp_dir.path();
+        let day = std::time::Duration::from_secs(24 * 60 * 60);
+        let now = SystemTime::now();
+        for (name, size, age) in [("b.log", 30, 1), ("a.txt", 10, 3), ("c.log", 20, 2)] {
+            fs::write(root.join(name), "x".repeat(size)).unwrap();
+            File::options()
+                .write(true)
+                .open(root.join(name))
+                .unwrap()
+                .set_modified(now - day * age)
+                .unwrap();
+        }
+        fs::create_dir(root.join("logs")).unwrap();
+        let mut file_server = FileServer::new(root.to_str().unwrap()).await.unwrap();
+
+        let result = file_server
+            .execute_tool(
+                "list_directory",
+                &json!({ "path": "." }),
+                1,
+                std::time::Duration::from_millis(10),
+            )
+            .await
+            .unwrap();
+        let entry = &result["entries"][0];
+        assert_eq!(entry["name"], "a.txt");
+        assert_eq!(entry["type"], "file");
+        assert_eq!(entry["size"], 10);
+        let modified = entry["modified"].as_str().unwrap();
+        assert!(DateTime::parse_from_rfc3339(modified).is_ok());
+        assert!(modified.ends_with('Z'));
+        assert_eq!(result["entries"][3]["type"], "directory");
+
+        assert_eq!(
+            names(
+                &mut file_server,
+                json!({ "path": ".", "sort_by": "size", "descending": true })
+            )
+            .await,
+            vec!["b.log", "c.log", "a.txt", "logs"]
+        );
+        assert_eq!(
+            names(
+                &mut file_server,
+                json!({ "path": ".", "sort_by": "modified", "type": "file" })
+            )
+            .await,
+            vec!["a.txt", "c.log", "b.log"]
+        );
+        assert_eq!(
+            names(
+                &mut file_server,
+                json!({ "path": ".", "name": "*.log", "min_size": 25 })
+            )
+            .await,
+            vec!["b.log"]
+        );
+        let after = DateTime::<Utc>::from(now - day * 2 - day / 2).to_rfc3339();
+        assert_eq!(
+            names(
+                &mut file_server,
+                json!({ "path": ".", "type": "file", "modified_after": after })
+            )
+            .await,
+            vec!["b.log", "c.log"]
+        );
+
+        #[cfg(unix)]
+        {
+            std::os::unix::fs::symlink("b.log", root.join("latest.log")).unwrap();
+            let result = file_server
+                .execute_tool(
+                    "list_directory",
+                    &json!({ "path": ".", "type": "symlink" }),
+                    1,
+                    std::time::Duration::from_millis(10),
+                )
+                .await
+                .unwrap();
+            assert_eq!(result["entries"][0]["name"], "latest.log");
+            assert_eq!(result["entries"][0]["type"], "symlink");
+            assert_eq!(result["entries"][0]["target"], "b.log");
+        }
+
+        assert!(file_server
+            .execute_tool(
+                "list_directory",
+                &json!({ "path": ".", "sort_by": "color" }),
+                1,
+                std::time::Duration::from_millis(10),
+            )
+            .await
+            .is_err());
+    }
+
+    /// Tests glob search across directories
+    #[tokio::test]
+    async fn test_file_glob() {
+        let temp_dir = TempDir::new().unwrap();
+        create_tree(temp_dir.path());
+        let mut file_server = FileServer::new(temp_dir.path().to_str().unwrap())
+            .await
+            .unwrap();
+
+        let glob_result = file_server
+            .execute_tool(
+                "file_glob",
+                &json!({ "pattern": "src/**/*.rs" }),
+                3,
+                std::time::Duration::from_secs(1),
+            )
+            .await
+            .unwrap();
+        assert_eq!(
+            glob_result["files"],
+            json!(["src/main.rs", "src/server/mod.rs"])
+        );
+
+        // * does not cross directories, and paths are relative to `path`
+        let glob_result = file_server
+            .execute_tool(
+                "file_glob",
+                &json!({ "pattern": "*.md", "path": "src" }),
+                3,
+                std::time::Duration::from_secs(1),
+            )
+            .await
+            .unwrap();
+        assert_eq!(glob_result["files"], json!([]));
+
+        assert!(file_server
+            .execute_tool(
+                "file_glob",
+                &json!({ "pattern": "src/[" }),
+                1,
+                std::time::Duration::from_secs(1),
+            )
+            .await
+            .is_err());
+    }
+
+    /// Tests content search with context, literals and binary files
+    #[tokio::test]
+    async fn test_file_search() {
+        let temp_dir = TempDir::new().unwrap();
+        create_tree(temp_dir.path());
+        fs::write(
+            temp_dir.path().join("src/lib.rs"),
+            "// header\nuse std::fs;\nfn read() {}\nfn write() {}\n",
+        )
+        .unwrap();
+        fs::write(temp_dir.path().join("src/data.bin"), b"fn \0binary").unwrap();
+        let mut file_server = FileServer::new(temp_dir.path().to_str().unwrap())
+            .await
+            .unwrap();
+
+        let search_result = file_server
+            .execute_tool(
+                "file_search",
+                &json!({ "pattern": "^fn \\w+", "path": "src", "context": 1 }),
+                3,
+                std::time::Duration::from_secs(1),
+            )
+            .await
+            .unwrap();
+        let matches = search_result["matches"].as_array().unwrap();
+        assert_eq!(matches.len(), 3);
+        assert_eq!(matches[0]["file"], "lib.rs");
+        assert_eq!(matches[0]["line"], 3);
+        assert_eq!(matches[0]["before"], json!(["use std::fs;"]));
+        assert_eq!(matches[0]["after"], json!(["fn write() {}"]));
+        assert_eq!(matches[2]["file"], "main.rs");
+        assert_eq!(search_result["truncated"], false);
+
+        // Regex characters are plain text when literal
+        let search_result = file_server
+            .execute_tool(
+                "file_search",
+                &json!({ "pattern": "READ()", "literal": true, "case_insensitive": true, "max_results": 1 }),
+                3,
+                std::time::Duration::from_secs(1),
+            )
+            .await
+            .unwrap();
+        assert_eq!(search_result["matches"][0]["text"], "fn read() {}");
+
+        let search_result = file_server
+            .execute_tool(
+                "file_search",
+                &json!({ "pattern": "fn", "glob": "**/*.rs", "max_results": 2 }),
+                3,
+                std::time::Duration::from_secs(1),
+            )
+            .await
+            .unwrap();
+        assert_eq!(search_result["matches"].as_array().unwrap().len(), 2);
+        assert_eq!(search_result["truncated"], true);
+    }
+
+    /// Tests directory creation, deletion, move, copy and rename
+    #[tokio::test]
+    async fn test_file_management() {
+        let temp_dir = TempDir::new().unwrap();
+        create_tree(temp_dir.path());
+        let root = temp_dir.path();
+        let mut file_server = FileServer::new(root.to_str().unwrap()).await.unwrap();
+        let result = run(
+            &mut file_server,
+            "dir_create",
+            json!({ "path": "docs/api" }),
+        )
+        .await
+        .unwrap();
+        assert_eq!(result["created"], true);
+        assert!(root.join("docs/api").is_dir());
+
+        run(
+            &mut file_server,
+            "file_copy",
+            json!({ "source": "src", "destination": "backup/src" }),
+        )
+        .await
+        .unwrap();
+        assert_eq!(
+            fs::read_to_string(root.join("backup/src/server/mod.rs")).unwrap(),
+            "mod file;"
+        );
+        assert!(run(
+            &mut file_server,
+            "file_copy",
+            json!({ "source": "src", "destination": "backup/src" })
+        )
+        .await
+        .is_err());
+
+        run(
+            &mut file_server,
+            "file_move",
+            json!({ "source": "README.md", "destination": "docs/README.md" }),
+        )
+        .await
+        .unwrap();
+        assert!(!root.join("README.md").exists());
+
+        let result = run(
+            &mut file_server,
+            "file_rename",
+            json!({ "path": "docs/README.md", "new_name": "index.md" }),
+        )
+        .await
+        .unwrap();
+        assert_eq!(result["path"], "docs/index.md");
+        assert!(root.join("docs/index.md").is_file());
+        assert!(run(
+            &mut file_server,
+            "file_rename",
+            json!({ "path": "docs/index.md", "new_name": "../escape.md" })
+        )
+        .await
+        .is_err());
+
+        // Overwriting a directory needs confirm to repeat the destination
+        fs::write(root.join("backup/src/extra.txt"), "extra").unwrap();
+        let copy = json!({ "source": "src", "destination": "backup/src", "overwrite": true });
+        assert!(run(&mut file_server, "file_copy", copy.clone())
+            .await
+            .is_err());
+        assert!(root.join("backup/src/extra.txt").is_file());
+        let mut confirmed = copy;
+        confirmed["confirm"] = json!("backup/src");
+        run(&mut file_server, "file_copy", confirmed).await.unwrap();
+        assert!(!root.join("backup/src/extra.txt").exists());
+        assert!(root.join("backup/src/main.rs").is_file());
+        let entries: Vec<_> = fs::read_dir(root.join("backup")).unwrap().collect();
+        assert_eq!(entries.len(), 1);
+
+        // Non-empty directories need a confirmed recursive delete
+        assert!(
+            run(&mut file_server, "dir_delete", json!({ "path": "backup" }))
+                .await
+                .is_err()
+        );
+        assert!(run(
+            &mut file_server,
+            "dir_delete",
+            json!({ "path": "backup", "recursive": true })
+        )
+        .await
+        .is_err());
+        let result = run(
+            &mut file_server,
+            "dir_delete",
+            json!({ "path": "backup", "recursive": true, "confirm": "backup" }),
+        )
+        .await
+        .unwrap();
+        assert_eq!(result["deleted"], true);
+        assert!(!root.join("backup").exists());
+
+        assert!(run(
+            &mut file_server,
+            "dir_delete",
+            json!({ "path": ".", "recursive": true, "confirm": "." })
+        )
+        .await
+        .is_err());
+        assert!(run(
+            &mut file_server,
+            "dir_create",
+            json!({ "path": "../outside" })
+        )
+        .await
+        .is_err());
+    }
+
+    /// Tests ranged reads, appends and tails
+    #[tokio::test]
+    async fn test_large_file_tools() {
+        let temp_dir = TempDir::new().unwrap();
+        let mut file_server = FileServer::new(temp_dir.path().to_str().unwrap())
+            .await
+            .unwrap();
+        for line in ["first", "second", "third"] {
+            let result = run(
+                &mut file_server,
+                "file_append",
+                json!({ "path": "logs/app.log", "content": format!("{}\n", line) }),
+            )
+            .await
+            .unwrap();
+            assert_eq!(result["success"], true);
+        }
+        assert_eq!(
+            fs::read_to_string(temp_dir.path().join("logs/app.log")).unwrap(),
+            "first\nsecond\nthird\n"
+        );
+
+        let result = run(
+            &mut file_server,
+            "file_tail",
+            json!({ "path": "logs/app.log", "lines": 2 }),
+        )
+        .await
+        .unwrap();
+        assert_eq!(result["content"], "second\nthird");
+
+        let result = run(
+            &mut file_server,
+            "file_read",
+            json!({ "path": "logs/app.log", "offset": 6, "length": 6 }),
+        )
+        .await
+        .unwrap();
+        assert_eq!(result["content"], "second");
+        assert_eq!(result["size"], 19);
+        assert_eq!(result["eof"], false);
+
+        // "é" is two bytes; the range stops before it instead of splitting it
+        fs::write(temp_dir.path().join("accent.txt"), "café au lait").unwrap();
+        let result = run(
+            &mut file_server,
+            "file_read",
+            json!({ "path": "accent.txt", "length": 4 }),
+        )
+        .await
+        .unwrap();
+        assert_eq!(result["content"], "caf");
+        assert_eq!(result["length"], 3);
+        let result = run(
+            &mut file_server,
+            "file_read",
+            json!({ "path": "accent.txt", "offset": 3 }),
+        )
+        .await
+        .unwrap();
+        assert_eq!(result["content"], "é au lait");
+        assert_eq!(result["eof"], true);
+    }
+
+    /// Tests that file_tail reads across block boundaries
+    #[test]
+    fn test_tail_blocks() {
+        let temp_dir = TempDir::new().unwrap();
+        let path = temp_dir.path().join("big.log");
+        let content: String = (0..5000).map(|i| format!("line {}\n", i)).collect();
+        fs::write(&path, content).unwrap();
+
+        assert_eq!(tail(&path, 3).unwrap(), "line 4997\nline 4998\nline 4999");
+        assert_eq!(tail(&path, 6000).unwrap().lines().count(), 5000);
+    }
+
+    /// Tests base64 reads and writes, MIME types and the binary error
+    #[tokio::test]
+    async fn test_binary_files() {
+        let temp_dir = TempDir::new().unwrap();
+        let mut file_server = FileServer::new(temp_dir.path().to_str().unwrap())
+            .await
+            .unwrap();
+        // The PNG signature followed by a NUL byte
+        let png = [0x89, b'P', b'N', b'G', 0x0D, 0x0A, 0x1A, 0x0A, 0x00];
+        run(
+            &mut file_server,
+            "file_write",
+            json!({ "path": "image.png", "content": BASE64.encode(png), "encoding": "base64" }),
+        )
+        .await
+        .unwrap();
+        assert_eq!(fs::read(temp_dir.path().join("image.png")).unwrap(), png);
+
+        let result = run(
+            &mut file_server,
+            "file_read",
+            json!({ "path": "image.png", "encoding": "base64" }),
+        )
+        .await
+        .unwrap();
+        assert_eq!(result["mime_type"], "image/png");
+        assert_eq!(
+            BASE64.decode(result["content"].as_str().unwrap()).unwrap(),
+            png
+        );
+
+        let error = run(
+            &mut file_server,
+            "file_read",
+            json!({ "path": "image.png" }),
+        )
+        .await
+        .unwrap_err();
+        assert!(error.to_string().contains("base64"));
+
+        fs::write(temp_dir.path().join("notes.md"), "# Notes").unwrap();
+        let result = run(&mut file_server, "file_read", json!({ "path": "notes.md" }))
+            .await
+            .unwrap();
+        assert_eq!(result["mime_type"], "text/markdown");
+
+        let error = run(
+            &mut file_server,
+            "file_read",
+            json!({ "path": "notes.md", "length": MAX_READ_SIZE + 1 }),
+        )
+        .await
+        .unwrap_err();
+        assert!(error.to_string().starts_with("File too large"));
+    }
+
+    /// Tests packing and unpacking archives through the tools
+    #[tokio::test]
+    async fn test_archive_tools() {
+        let temp_dir = TempDir::new().unwrap();
+        create_tree(temp_dir.path());
+        let root = temp_dir.path();
+        let mut file_server = FileServer::new(root.to_str().unwrap()).await.unwrap();
+        for archive in ["out/src.zip", "out/src.tar.gz"] {
+            let result = run(
+                &mut file_server,
+                "archive_create",
+                json!({ "path": archive, "sources": ["src", "README.md"] }),
+            )
+            .await
+            .unwrap();
+            assert!(result["entries"].as_u64().unwrap() > 0);
+            assert!(root.join(archive).is_file());
+
+            let result = run(
+                &mut file_server,
+                "archive_extract",
+                json!({ "path": archive, "destination": "unpacked" }),
+            )
+            .await
+            .unwrap();
+            assert!(result["entries"]
+                .as_array()
+                .unwrap()
+                .contains(&json!("src/server/mod.rs")));
+            assert_eq!(
+                fs::read_to_string(root.join("unpacked/src/server/mod.rs")).unwrap(),
+                "mod file;"
+            );
+            fs::remove_dir_all(root.join("unpacked")).unwrap();
+        }
+
+        assert!(run(
+            &mut file_server,
+            "archive_create",
+            json!({ "path": "out/src.zip", "sources": ["src"] })
+        )
+        .await
+        .is_err());
+        assert!(run(
+            &mut file_server,
+            "archive_create",
+            json!({ "path": "out/src.rar", "sources": ["src"] })
+        )
+        .await
+        .is_err());
+        assert!(run(
+            &mut file_server,
+            "archive_extract",
+            json!({ "path": "out/src.zip", "destination": "../outside" })
+        )
+        .await
+        .is_err());
+    }
+
+    /// Tests diffing two files and applying the diff as a patch
+    #[tokio::test]
+    async fn test_diff_and_patch() {
+        let temp_dir = TempDir::new().unwrap();
+        let root = temp_dir.path();
+        let mut file_server = FileServer::new(root.to_str().unwrap()).await.unwrap();
+        let original = "fn main() {\n    println!(\"hello\");\n}\n";
+        let changed = "fn main() {\n    println!(\"hello, world\");\n}\n";
+        fs::write(root.join("main.rs"), original).unwrap();
+        fs::write(root.join("main_new.rs"), changed).unwrap();
+
+        let result = run(
+            &mut file_server,
+            "file_diff",
+            json!({ "path_a": "main.rs", "path_b": "main_new.rs" }),
+        )
+        .await
+        .unwrap();
+        assert_eq!(result["changed"], true);
+        let diff = result["diff"].as_str().unwrap().to_string();
+        assert!(diff.starts_with("--- main.rs\n+++ main_new.rs\n"));
+        assert!(diff.contains("-    println!(\"hello\");\n+    println!(\"hello, world\");"));
+
+        let result = run(
+            &mut file_server,
+            "file_apply_patch",
+            json!({ "path": "main.rs", "patch": diff }),
+        )
+        .await
+        .unwrap();
+        assert_eq!(result["hunks"], 1);
+        assert_eq!(fs::read_to_string(root.join("main.rs")).unwrap(), changed);
+
+        // A patch that no longer applies leaves the file untouched
+        assert!(run(
+            &mut file_server,
+            "file_apply_patch",
+            json!({ "path": "main.rs", "patch": diff })
+        )
+        .await
+        .is_err());
+        assert_eq!(fs::read_to_string(root.join("main.rs")).unwrap(), changed);
+
+        let result = run(
+            &mut file_server,
+            "file_diff",
+            json!({ "path_a": "main.rs", "path_b": "main_new.rs" }),
+        )
+        .await
+        .unwrap();
+        assert_eq!(result["changed"], false);
+        assert_eq!(result["diff"], "");
+    }
+
+    /// Tests creating files from templates
+    #[tokio::test]
+    async fn test_file_from_template() {
+        let temp_dir = TempDir::new().unwrap();
+        let root = temp_dir.path();
+        fs::create_dir_all(root.join("templates/rust")).unwrap();
+        fs::write(
+            root.join("templates/rust/module.rs"),
+            "//! {{ file_stem }}: {{ summary }}\n//! License: {{ license | default(\"MIT\") }}\n",
+        )
+        .unwrap();
+        let mut file_server = FileServer::new(root.to_str().unwrap()).await.unwrap();
+        let result = run(&mut file_server, "file_templates", json!({}))
+            .await
+            .unwrap();
+        assert_eq!(result["templates"], json!(["rust/module.rs"]));
+
+        let args = json!({
+            "template": "rust/module.rs",
+            "path": "src/cache.rs",
+            "variables": { "summary": "LRU cache" }
+        });
+        let result = run(&mut file_server, "file_from_template", args.clone())
+            .await
+            .unwrap();
+        assert_eq!(result["created"], true);
+        assert_eq!(
+            fs::read_to_string(root.join("src/cache.rs")).unwrap(),
+            "//! cache: LRU cache\n//! License: MIT\n"
+        );
+
+        // Existing files are only replaced with overwrite
+        assert!(run(&mut file_server, "file_from_template", args.clone())
+            .await
+            .is_err());
+        let mut args = args;
+        args["overwrite"] = json!(true);
+        args["variables"]["license"] = json!("Apache-2.0");
+        let result = run(&mut file_server, "file_from_template", args)
+            .await
+            .unwrap();
+        assert_eq!(result["created"], false);
+        assert!(fs::read_to_string(root.join("src/cache.rs"))
+            .unwrap()
+            .ends_with("Apache-2.0\n"));
+
+        // Missing variables fail without creating the file
+        assert!(run(
+            &mut file_server,
+            "file_from_template",
+            json!({ "template": "rust/module.rs", "path": "src/other.rs" })
+        )
+        .await
+        .is_err());
+        assert!(!root.join("src/other.rs").exists());
+    }
+
+    /// Tests the space report and that writes stay within the quota
+    #[tokio::test]
+    async fn test_quota() {
+        let temp_dir = TempDir::new().unwrap();
+        let root = temp_dir.path();
+        fs::write(root.join("notes.txt"), "0123456789").unwrap();
+        let policy = FileServerPolicy {
+            quota: Some(32),
+            ..Default::default()
+        };
+        let mut file_server = FileServer::with_policy(root.to_str().unwrap(), policy)
+            .await
+            .unwrap();
+        let space = file_server
+            .read_resource("filesystem", &json!({ "action": "space" }))
+            .await
+            .unwrap();
+        assert_eq!(space["used"], 10);
+        assert_eq!(space["quota"], 32);
+        assert_eq!(space["quota_remaining"], 22);
+        assert!(space["total_space"].as_u64().unwrap() > 0);
+        assert!(space["available_space"].as_u64().is_some());
+
+        run(
+            &mut file_server,
+            "file_write",
+            json!({ "path": "a.txt", "content": "x".repeat(20) }),
+        )
+        .await
+        .unwrap();
+        assert!(run(
+            &mut file_server,
+            "file_append",
+            json!({ "path": "a.txt", "content": "xxx" })
+        )
+        .await
+        .is_err());
+        assert!(run(
+            &mut file_server,
+            "file_copy",
+            json!({ "source": "notes.txt", "destination": "copy.txt" })
+        )
+        .await
+        .is_err());
+        assert!(!root.join("copy.txt").exists());
+
+        // Deleting files frees the quota again
+        run(&mut file_server, "file_delete", json!({ "path": "a.txt" }))
+            .await
+            .unwrap();
+        run(
+            &mut file_server,
+            "file_copy",
+            json!({ "source": "notes.txt", "destination": "copy.txt" }),
+        )
+        .await
+        .unwrap();
+
+        // Links are not followed, so their targets cannot slip past the quota
+        #[cfg(unix)]
+        {
+            let outside = TempDir::new().unwrap();
+            fs::write(outside.path().join("big.bin"), vec![0u8; 64]).unwrap();
+            fs::create_dir(root.join("linked")).unwrap();
+            std::os::unix::fs::symlink(outside.path().join("big.bin"), root.join("linked/big.bin"))
+                .unwrap();
+            std::os::unix::fs::symlink(outside.path().join("big.bin"), root.join("big.bin"))
+                .unwrap();
+            let error = run(
+                &mut file_server,
+                "file_copy",
+                json!({ "source": "linked", "destination": "copies/out" }),
+            )
+            .await
+            .unwrap_err();
+            assert!(error
+                .to_string()
+                .contains("Symbolic links cannot be copied"));
+            assert!(run(
+                &mut file_server,
+                "file_copy",
+                json!({ "source": "big.bin", "destination": "copies/out" })
+            )
+            .await
+            .is_err());
+            assert!(!root.join("copies/out").exists());
+        }
+    }
+
+    /// Tests that the tools obey allow and deny lists and the write limit
+    #[tokio::test]
+    async fn test_policy() {
+        let temp_dir = TempDir::new().unwrap();
+        create_tree(temp_dir.path());
+        let root = temp_dir.path();
+        let policy = FileServerPolicy {
+            allow: Some(vec!["src".to_string(), "*.md".to_string()]),
+            deny: vec!["src/server/notes.md".to_string()],
+            max_write_size: Some(16),
+            ..Default::default()
+        };
+        let mut file_server = FileServer::with_policy(root.to_str().unwrap(), policy)
+            .await
+            .unwrap();
+        let result = run(&mut file_server, "file_glob", json!({ "pattern": "**/*" }))
+            .await
+            .unwrap();
+        assert_eq!(
+            result["files"],
+            json!(["README.md", "src/main.rs", "src/server/mod.rs"])
+        );
+        let result = run(
+            &mut file_server,
+            "file_search",
+            json!({ "pattern": "notes", "literal": true }),
+        )
+        .await
+        .unwrap();
+        assert_eq!(result["matches"], json!([]));
+
+        for (tool, args) in [
+            ("file_read", json!({ "path": "src/server/notes.md" })),
+            ("file_read", json!({ "path": ".gitignore" })),
+            (
+                "file_write",
+                json!({ "path": "src/lib.rs", "content": "x".repeat(17) }),
+            ),
+            (
+                "file_copy",
+                json!({ "source": "src", "destination": "src_backup" }),
+            ),
+            ("dir_create", json!({ "path": "other" })),
+        ] {
+            assert!(run(&mut file_server, tool, args).await.is_err(), "{}", tool);
+        }
+        assert!(!root.join("src/lib.rs").exists());
+
+        run(
+            &mut file_server,
+            "file_write",
+            json!({ "path": "src/lib.rs", "content": "mod server;" }),
+        )
+        .await
+        .unwrap();
+        assert!(root.join("src/lib.rs").is_file());
+    }
+
+    /// Embeds texts as their length, enough to store and count chunks
+    struct LengthEmbedder;
+
+    #[async_trait::async_trait]
+    impl crate::embedder::Embedder for LengthEmbedder {
+        fn name(&self) -> String {
+            "length".to_string()
+        }
+
+        async fn embed(&self, texts: &[&str]) -> Result<Vec<Vec<f32>>> {
+            Ok(texts
+                .iter()
+                .map(|text| vec![text.len() as f32, 1.0])
+                .collect())
+        }
+    }
+
+    /// Tests streaming a file larger than one segment into the RAG index
+    #[tokio::test]
+    async fn test_file_index() {
+        let temp_dir = TempDir::new().unwrap();
+        let root = temp_dir.path().join("files");
+        let mut file_server = FileServer::new(root.to_str().unwrap()).await.unwrap();
+        let backend =
+            crate::local_backend::LocalBackend::open(temp_dir.path().join("vectors")).unwrap();
+        let rag_server = crate::rag_server::RagServer::with_backend(
+            crate::rag_server::RagConfig::default(),
+            Box::new(LengthEmbedder),
+            Arc::new(backend),
+        )
+        .unwrap();
+        rag_server.init().await.unwrap();
+        let rag_server = Arc::new(Mutex::new(rag_server));
+        file_server
+            .attach_rag_server(Arc::clone(&rag_server))
+            .await
+            .unwrap();
+
+        let log: String = (0..40_000)
+            .map(|i| format!("request {} served in {} ms\n", i, i % 97))
+            .collect();
+        fs::write(root.join("server.log"), &log).unwrap();
+
+        let result = file_server
+            .execute_tool(
+                "file_index",
+                &json!({ "path": "server.log" }),
+                1,
+                std::time::Duration::from_millis(10),
+            )
+            .await
+            .unwrap();
+        assert_eq!(result["bytes"], log.len());
+        assert!(result["segments"].as_u64().unwrap() > 1);
+        assert!(result.get("content").is_none());
+        let chunks = result["chunks"].as_u64().unwrap();
+        assert_eq!(rag_server.lock().await.count(None).await.unwrap(), chunks);
+
+        assert!(file_server
+            .execute_tool(
+                "file_index",
+                &json!({ "path": "missing.log" }),
+                1,
+                std::time::Duration::from_millis(10),
+            )
+            .await
+            .is_err());
+    }
 }
@@ -0,0 +1,137 @@
+//! File Template Module: Scaffolding for `FileServer`
+//!
+//! This module provides:
+//! - Rendering of Jinja-style templates from a templates directory
+//! - The names of the templates found there
+//!
+//! Templates are named by their path below the templates directory, such as
+//! `rust/module.rs`, and may include or extend each other. Besides the
+//! caller's variables, every template sees the `path`, `file_name` and
+//! `file_stem` of the file being created and today's `date` and `year`;
+//! variables of the same name take precedence. Using a variable that was
+//! not given is an error instead of an empty string, unlike in system
+//! prompts, which `prompt_template` renders without Jinja.
+//!
+//! Author: arkSong <arksong2018@gmail.com>
+//! Version: 1.0.0
+//! License: MIT
+
+use anyhow::{Error, Result};
+use minijinja::{path_loader, Environment, ErrorKind, UndefinedBehavior};
+use serde_json::{json, Map, Value};
+use std::path::Path;
+use walkdir::WalkDir;
+
+/// Renders the template `name` for the file at `path` (relative to the
+/// root) with the JSON object `variables`
+pub fn render(templates_dir: &Path, name: &str, path: &str, variables: &Value) -> Result<String> {
+    let target = Path::new(path);
+    let today = chrono::Local::now();
+    let mut context = Map::new();
+    context.insert("path".to_string(), json!(path));
+    context.insert(
+        "file_name".to_string(),
+        json!(target.file_name().map(|name| name.to_string_lossy())),
+    );
+    context.insert(
+        "file_stem".to_string(),
+        json!(target.file_stem().map(|stem| stem.to_string_lossy())),
+    );
+    context.insert(
+        "date".to_string(),
+        json!(today.format("%Y-%m-%d").to_string()),
+    );
+    context.insert("year".to_string(), json!(today.format("%Y").to_string()));
+    match variables {
+        Value::Null => {}
+        Value::Object(variables) => context.extend(variables.clone()),
+        _ => return Err(Error::msg("Invalid argument: variables must be an object")),
+    }
+
+    let mut env = Environment::new();
+    env.set_loader(path_loader(templates_dir));
+    env.set_undefined_behavior(UndefinedBehavior::Strict);
+    env.set_keep_trailing_newline(true);
+    let template = match env.get_template(name) {
+        Ok(template) => template,
+        Err(e) if e.kind() == ErrorKind::TemplateNotFound => {
+            let templates = available(templates_dir);
+            return Err(Error::msg(format!(
+                "Template not found: {} (available: {})",
+                name,
+                if templates.is_empty() {
+                    "none".to_string()
+                } else {
+                    templates.join(", ")
+                }
+            )));
+        }
+        Err(e) => return Err(Error::msg(format!("Invalid template {}: {}", name, e))),
+    };
+    template
+        .render(Value::Object(context))
+        .map_err(|e| Error::msg(format!("Failed to render template {}: {}", name, e)))
+}
+
+/// Names of the templates in `templates_dir`, skipping hidden files, in
+/// sorted order. A missing directory has none.
+pub fn available(templates_dir: &Path) -> Vec<String> {
+    WalkDir::new(templates_dir)
+        .min_depth(1)
+        .sort_by_file_name()
+        .into_iter()
+        .filter_entry(|entry| !entry.file_name().to_string_lossy().starts_with('.'))
+        .filter_map(|entry| entry.ok())
+        .filter(|entry| entry.file_type().is_file())
+        .filter_map(|entry| {
+            entry.path().strip_prefix(templates_dir).ok().map(|path| {
+                path.components()
+                    .map(|component| component.as_os_str().to_string_lossy())
+                    .collect::<Vec<_>>()
+                    .join("/")
+            })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_render() {
+        let temp_dir = TempDir::new().unwrap();
+        let dir = temp_dir.path();
+        fs::create_dir_all(dir.join("rust")).unwrap();
+        fs::write(dir.join("header.txt"), "// {{ file_name }} ({{ year }})\n").unwrap();
+        fs::write(
+            dir.join("rust/module.rs"),
+            "{% include \"header.txt\" %}pub struct {{ name }};\n",
+        )
+        .unwrap();
+        fs::write(dir.join(".draft"), "hidden").unwrap();
+
+        let year = chrono::Local::now().format("%Y").to_string();
+        assert_eq!(
+            render(
+                dir,
+                "rust/module.rs",
+                "src/cache.rs",
+                &json!({ "name": "Cache" })
+            )
+            .unwrap(),
+            format!("// cache.rs ({})\npub struct Cache;\n", year)
+        );
+        assert_eq!(available(dir), vec!["header.txt", "rust/module.rs"]);
+
+        // Missing variables and templates are errors
+        let error = render(dir, "rust/module.rs", "a.rs", &Value::Null).unwrap_err();
+        assert!(error.to_string().contains("Failed to render"));
+        let error = render(dir, "python.py", "a.py", &Value::Null).unwrap_err();
+        assert!(error.to_string().contains("rust/module.rs"));
+        assert!(render(dir, "../header.txt", "a.rs", &Value::Null).is_err());
+        assert!(render(dir, "header.txt", "a.rs", &json!(["x"])).is_err());
+    }
+}
@@ -0,0 +1,516 @@
+//! LanceDB Backend Module: Vector storage in a local LanceDB directory
+//!
+//! This module provides:
+//! - A `VectorBackend` on top of LanceDB, an embedded columnar vector
+//!   database, so collections live in plain files without any server
+//! - One LanceDB table per collection with `id`, `vector` and `payload` columns
+//!
+//! Key Components:
+//! - `LanceDbBackend`: Lazily connected LanceDB database at a path or URI
+//!
+//! Payloads are stored as JSON text, so payload filters are applied after
+//! the table scan rather than pushed down to LanceDB. Scrolling pushes the id
+//! cursor down and keeps no more than a page of rows in memory.
+//!
+//! Author: arkSong <arksong2018@gmail.com>
+//! Version: 1.0.0
+//! License: MIT
+
+use crate::vector_backend::{
+    CollectionInfo, DistanceMetric, Payload, PayloadFilter, ScoredPoint, SearchQuery,
+    VectorBackend, VectorPoint, VectorStoreError,
+};
+use anyhow::{Context, Error, Result};
+use arrow_array::types::Float32Type;
+use arrow_array::{
+    Array, FixedSizeListArray, Float32Array, RecordBatch, RecordBatchIterator, StringArray,
+};
+use arrow_schema::{DataType, Field, Schema, SchemaRef};
+use async_trait::async_trait;
+use futures::TryStreamExt;
+use lancedb::query::{ExecutableQuery, QueryBase, Select};
+use lancedb::{Connection, DistanceType, Table};
+use log::info;
+use std::collections::BTreeMap;
+use std::sync::Arc;
+use tokio::sync::OnceCell;
+
+pub struct LanceDbBackend {
+    uri: String,
+    distance: DistanceMetric,
+    connection: OnceCell<Connection>,
+}
+
+impl LanceDbBackend {
+    /// Uses the database at `uri`, a directory path or an object store URI.
+    /// The connection is opened on first use.
+    pub fn new(uri: &str, distance: DistanceMetric) -> Self {
+        Self {
+            uri: shellexpand::tilde(uri).into_owned(),
+            distance,
+            connection: OnceCell::new(),
+        }
+    }
+
+    async fn connection(&self) -> Result<&Connection> {
+        self.connection
+            .get_or_try_init(|| async {
+                info!("Opening LanceDB database at {}", self.uri);
+                lancedb::connect(&self.uri)
+                    .execute()
+                    .await
+                    .with_context(|| format!("Failed to open LanceDB database {}", self.uri))
+            })
+            .await
+    }
+
+    async fn table(&self, collection: &str) -> Result<Table> {
+        self.connection()
+            .await?
+            .open_table(collection)
+            .execute()
+            .await
+            .map_err(|_| {
+                Error::new(VectorStoreError::CollectionError(format!(
+                    "Collection {} does not exist",
+                    collection
+                )))
+            })
+    }
+
+    fn distance_type(&self) -> DistanceType {
+        match self.distance {
+            DistanceMetric::Cosine => DistanceType::Cosine,
+            DistanceMetric::Euclid => DistanceType::L2,
+            DistanceMetric::Dot => DistanceType::Dot,
+        }
+    }
+
+    /// Turns a LanceDB distance into the score Qdrant would report
+    fn score(&self, distance: f32) -> f32 {
+        match self.distance {
+            DistanceMetric::Cosine | DistanceMetric::Dot => 1.0 - distance,
+            // LanceDB reports the squared Euclidean distance
+            DistanceMetric::Euclid => distance.sqrt(),
+        }
+    }
+}
+
+fn schema(dimension: usize) -> SchemaRef {
+    Arc::new(Schema::new(vec![
+        Field::new("id", DataType::Utf8, false),
+        Field::new(
+            "vector",
+            DataType::FixedSizeList(
+                Arc::new(Field::new("item", DataType::Float32, true)),
+                dimension as i32,
+            ),
+            false,
+        ),
+        Field::new("payload", DataType::Utf8, false),
+    ]))
+}
+
+fn vector_dimension(schema: &Schema) -> Option<usize> {
+    match schema.field_with_name("vector").ok()?.data_type() {
+        DataType::FixedSizeList(_, size) => Some(*size as usize),
+        _ => None,
+    }
+}
+
+fn string_column<'a>(batch: &'a RecordBatch, name: &str) -> Result<&'a StringArray> {
+    batch
+        .column_by_name(name)
+        .and_then(|column| column.as_any().downcast_ref::<StringArray>())
+        .ok_or_else(|| Error::msg(format!("LanceDB result has no {} column", name)))
+}
+
+fn parse_payload(text: &str) -> Result<Payload> {
+    serde_json::from_str(text).context("Invalid payload stored in LanceDB")
+}
+
+fn vectors_at(batch: &RecordBatch, row: usize) -> Option<Vec<f32>> {
+    let vectors = batch
+        .column_by_name("vector")?
+        .as_any()
+        .downcast_ref::<FixedSizeListArray>()?;
+    let vector = vectors.value(row);
+    let values = vector.as_any().downcast_ref::<Float32Array>()?;
+    Some(values.values().to_vec())
+}
+
+/// `id` as an SQL string literal
+fn quote(id: &str) -> String {
+    format!("'{}'", id.replace('\'', "''"))
+}
+
+/// SQL predicate selecting the given ids
+fn id_predicate(ids: &[String]) -> String {
+    let quoted: Vec<String> = ids.iter().map(|id| quote(id)).collect();
+    format!("id IN ({})", quoted.join(", "))
+}
+
+fn accepts(filter: &Option<PayloadFilter>, payload: &Payload) -> bool {
+    match filter {
+        Some(filter) => filter.accepts(payload),
+        None => true,
+    }
+}
+
+#[async_trait]
+impl VectorBackend for LanceDbBackend {
+    fn name(&self) -> String {
+        format!("lancedb ({})", self.uri)
+    }
+
+    async fn health(&self) -> Result<String> {
+        let names = self.connection().await?.table_names().execute().await?;
+        Ok(format!("{} tables", names.len()))
+    }
+
+    async fn collection_info(&self, collection: &str) -> Result<Option<CollectionInfo>> {
+        let names = self.connection().await?.table_names().execute().await?;
+        if !names.iter().any(|name| name == collection) {
+            return Ok(None);
+        }
+
+        let table = self.table(collection).await?;
+        let dimension = vector_dimension(&table.schema().await?).ok_or_else(|| {
+            Error::new(VectorStoreError::CollectionError(format!(
+                "Table {} has no fixed-size vector column",
+                collection
+            )))
+        })?;
+        Ok(Some(CollectionInfo {
+            dimension,
+            distance: self.distance,
+            points_count: table.count_rows(None).await? as u64,
+            vector_names: Vec::new(),
+        }))
+    }
+
+    async fn create_collection(&self, collection: &str, dimension: usize) -> Result<()> {
+        self.connection()
+            .await?
+            .create_empty_table(collection, schema(dimension))
+            .execute()
+            .await
+            .with_context(|| format!("Failed to create collection {}", collection))?;
+        Ok(())
+    }
+
+    async fn upsert(&self, collection: &str, points: Vec<VectorPoint>) -> Result<()> {
+        if points.is_empty() {
+            return Ok(());
+        }
+
+        let table = self.table(collection).await?;
+        let dimension = vector_dimension(&table.schema().await?).unwrap_or_default();
+        if let Some(point) = points.iter().find(|p| p.vector.len() != dimension) {
+            return Err(Error::new(VectorStoreError::InvalidDimension {
+                expected: dimension as u64,
+                actual: point.vector.len() as u64,
+            }));
+        }
+
+        let schema = schema(dimension);
+        let ids = StringArray::from_iter_values(points.iter().map(|p| p.id.as_str()));
+        let vectors = FixedSizeListArray::from_iter_primitive::<Float32Type, _, _>(
+            points
+                .iter()
+                .map(|p| Some(p.vector.iter().map(|v| Some(*v)).collect::<Vec<_>>())),
+            dimension as i32,
+        );
+        let payloads = points
+            .iter()
+            .map(|p| serde_json::to_string(&p.payload))
+            .collect::<Result<Vec<_>, _>>()?;
+        let batch = RecordBatch::try_new(
+            schema.clone(),
+            vec![
+                Arc::new(ids),
+                Arc::new(vectors),
+                Arc::new(StringArray::from(payloads)),
+            ],
+        )?;
+
+        // Replaces rows with the same id, inserts the rest
+        let mut merge = table.merge_insert(&["id"]);
+        merge
+            .when_matched_update_all(None)
+            .when_not_matched_insert_all();
+        merge
+            .execute(Box::new(RecordBatchIterator::new(vec![Ok(batch)], schema)))
+            .await
+            .context("Failed to upsert points")?;
+        Ok(())
+    }
+
+    async fn search(&self, collection: &str, query: SearchQuery) -> Result<Vec<ScoredPoint>> {
+        if let Some(name) = &query.using {
+            return Err(Error::new(VectorStoreError::SearchError(format!(
+                "{} does not support named vectors such as '{}'",
+                self.name(),
+                name
+            ))));
+        }
+
+        let table = self.table(collection).await?;
+        // Filters run on the results, so a filtered search ranks every row
+        let candidates = match query.filter {
+            Some(_) => table.count_rows(None).await?.max(1),
+            None => query.limit,
+        };
+
+        let batches: Vec<RecordBatch> = table
+            .query()
+            .nearest_to(query.vector.as_slice())?
+            .distance_type(self.distance_type())
+            .limit(candidates)
+            .execute()
+            .await
+            .context("Failed to search points")?
+            .try_collect()
+            .await?;
+
+        let mut results = Vec::new();
+        for batch in &batches {
+            let ids = string_column(batch, "id")?;
+            let payloads = string_column(batch, "payload")?;
+            let distances = batch
+                .column_by_name("_distance")
+                .and_then(|column| column.as_any().downcast_ref::<Float32Array>())
+                .ok_or_else(|| Error::msg("LanceDB result has no _distance column"))?;
+
+            for row in 0..batch.num_rows() {
+                let payload = parse_payload(payloads.value(row))?;
+                if !accepts(&query.filter, &payload) {
+                    continue;
+                }
+                let score = self.score(distances.value(row));
+                let below_threshold = match (query.score_threshold, self.distance) {
+                    (Some(threshold), DistanceMetric::Euclid) => score > threshold,
+                    (Some(threshold), _) => score < threshold,
+                    (None, _) => false,
+                };
+                if below_threshold {
+                    continue;
+                }
+                results.push(ScoredPoint {
+                    id: ids.value(row).to_string(),
+                    score,
+                    payload,
+                    vector: if query.with_vectors {
+                        vectors_at(batch, row)
+                    } else {
+                        None
+                    },
+                });
+            }
+        }
+
+        results.truncate(query.limit);
+        Ok(results)
+    }
+
+    async fn get(&self, collection: &str, ids: &[String]) -> Result<Vec<VectorPoint>> {
+        if ids.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        let table = self.table(collection).await?;
+        let batches: Vec<RecordBatch> = table
+            .query()
+            .only_if(id_predicate(ids))
+            .execute()
+            .await
+            .context("Failed to get points")?
+            .try_collect()
+            .await?;
+
+        let mut points = Vec::new();
+        for batch in &batches {
+            let found = string_column(batch, "id")?;
+            let payloads = string_column(batch, "payload")?;
+            for row in 0..batch.num_rows() {
+                points.push(VectorPoint::new(
+                    found.value(row).to_string(),
+                    vectors_at(batch, row).unwrap_or_default(),
+                    parse_payload(payloads.value(row))?,
+                ));
+            }
+        }
+        Ok(points)
+    }
+
+    async fn delete(&self, collection: &str, ids: &[String]) -> Result<()> {
+        if ids.is_empty() {
+            return Ok(());
+        }
+
+        self.table(collection)
+            .await?
+            .delete(&id_predicate(ids))
+            .await
+            .context("Failed to delete points")?;
+        Ok(())
+    }
+
+    async fn scroll(
+        &self,
+        collection: &str,
+        filter: Option<PayloadFilter>,
+        offset: Option<String>,
+        limit: usize,
+    ) -> Result<(Vec<VectorPoint>, Option<String>)> {
+        let table = self.table(collection).await?;
+        let mut query = table.query().select(Select::Columns(vec![
+            "id".to_string(),
+            "payload".to_string(),
+        ]));
+        // The offset is the id of the first point of the page, as with Qdrant
+        if let Some(offset) = &offset {
+            query = query.only_if(format!("id >= {}", quote(offset)));
+        }
+
+        // Rows come in storage order, so the page and the id of the next one
+        // are the `limit + 1` smallest matching ids seen while streaming
+        let mut page: BTreeMap<String, Payload> = BTreeMap::new();
+        let mut batches = query.execute().await.context("Failed to scroll points")?;
+        while let Some(batch) = batches.try_next().await? {
+            let ids = string_column(&batch, "id")?;
+            let payloads = string_column(&batch, "payload")?;
+            for row in 0..batch.num_rows() {
+                let id = ids.value(row);
+                let page_full = page.len() > limit;
+                if page_full
+                    && page
+                        .last_key_value()
+                        .is_some_and(|(last, _)| id >= last.as_str())
+                {
+                    continue;
+                }
+                let payload = parse_payload(payloads.value(row))?;
+                if !accepts(&filter, &payload) {
+                    continue;
+                }
+                page.insert(id.to_string(), payload);
+                if page.len() > limit + 1 {
+                    page.pop_last();
+                }
+            }
+        }
+
+        let next = if page.len() > limit {
+            page.pop_last().map(|(id, _)| id)
+        } else {
+            None
+        };
+        let points = page
+            .into_iter()
+            .map(|(id, payload)| VectorPoint::new(id, Vec::new(), payload))
+            .collect();
+        Ok((points, next))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn point(id: &str, vector: Vec<f32>, role: &str) -> VectorPoint {
+        VectorPoint::new(
+            id.to_string(),
+            vector,
+            json!({ "role": role }).as_object().unwrap().clone(),
+        )
+    }
+
+    async fn notes(dir: &tempfile::TempDir) -> Result<LanceDbBackend> {
+        let backend = LanceDbBackend::new(dir.path().to_str().unwrap(), DistanceMetric::Cosine);
+        backend.create_collection("notes", 2).await?;
+        backend
+            .upsert(
+                "notes",
+                vec![
+                    point("a", vec![1.0, 0.0], "user"),
+                    point("b", vec![0.7, 0.7], "assistant"),
+                    point("c", vec![0.0, 1.0], "user"),
+                ],
+            )
+            .await?;
+        Ok(backend)
+    }
+
+    #[test]
+    fn test_id_predicate_escapes_quotes() {
+        assert_eq!(
+            id_predicate(&["a".to_string(), "it's".to_string()]),
+            "id IN ('a', 'it''s')"
+        );
+    }
+
+    #[test]
+    fn test_schema_dimension() {
+        assert_eq!(vector_dimension(&schema(384)), Some(384));
+    }
+
+    #[tokio::test]
+    async fn test_search_and_delete() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let backend = notes(&dir).await?;
+
+        let results = backend
+            .search("notes", SearchQuery::new(vec![1.0, 0.1], 2))
+            .await?;
+        let ids: Vec<&str> = results.iter().map(|r| r.id.as_str()).collect();
+        assert_eq!(ids, vec!["a", "b"]);
+
+        let results = backend
+            .search(
+                "notes",
+                SearchQuery::new(vec![1.0, 0.1], 3)
+                    .filter(PayloadFilter::new().matches("role", "user"))
+                    .score_threshold(0.5),
+            )
+            .await?;
+        assert_eq!(results.len(), 1);
+        assert_eq!(results[0].id, "a");
+
+        backend.delete("notes", &["a".to_string()]).await?;
+        let results = backend
+            .search("notes", SearchQuery::new(vec![1.0, 0.1], 1))
+            .await?;
+        assert_eq!(results[0].id, "b");
+        assert_eq!(backend.count("notes", None).await?, 2);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_scroll_pages() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let backend = notes(&dir).await?;
+
+        let (page, next) = backend.scroll("notes", None, None, 2).await?;
+        let ids: Vec<&str> = page.iter().map(|p| p.id.as_str()).collect();
+        assert_eq!(ids, vec!["a", "b"]);
+        assert_eq!(next.as_deref(), Some("c"));
+
+        let (page, next) = backend.scroll("notes", None, next, 2).await?;
+        assert_eq!(page.len(), 1);
+        assert_eq!(page[0].id, "c");
+        assert!(next.is_none());
+
+        let user_filter = PayloadFilter::new().matches("role", "user");
+        let (page, next) = backend
+            .scroll("notes", Some(user_filter.clone()), None, 1)
+            .await?;
+        assert_eq!(page[0].id, "a");
+        assert_eq!(next.as_deref(), Some("c"));
+        let (page, next) = backend.scroll("notes", Some(user_filter), next, 1).await?;
+        assert_eq!(page[0].id, "c");
+        assert!(next.is_none());
+        Ok(())
+    }
+}
@@ -8,155 +8,107 @@
 //! - Configuration management
 //!
 //! The modules are organized as follows:
+//! - `agent`: Limits and trace of the tool-calling loop of a chat turn
+//! - `audit_log`: Append-only record of every tool call
+//! - `browser_server`: Headless Chromium behind the puppeteer tools (feature `browser`)
+//! - `chat_session`: Interactive chat loop and command handling
+//! - `completion`: Tab completion and hints at the chat prompt
+//! - `server`: Built-in MCP servers and their tools
+//! - `server_manager`: Health checks and restarts of the configured servers
+//! - `plugin_server`: Tools of WebAssembly plugins run in wasmtime (feature `plugins`)
+//! - `prompts`: System prompt definitions loaded from `mcp_prompts.yaml`
 //! - `file_server`: File system operations and management
+//! - `file_archive`: zip and tar.gz archives for the file server
+//! - `file_policy`: Read-only mode and allow/deny rules for the file server
+//! - `file_template`: Templates the file server creates files from
 //! - `llm_client`: Language model client implementations
 //! - `mcp_server`: Core MCP server functionality
+//! - `metrics`: Prometheus metrics of tool calls and LLM usage
 //! - `protocol`: Communication protocol definitions
+//! - `prompt_template`: Variable substitution for system prompts
 //! - `sqlite_server`: SQLite database integration
+//! - `sqlite_migrations`: Versioned schema migrations for the SQLite database
 //! - `stdio_server`: Standard I/O handling
+//! - `tool_policy`: Tools that are safe, need approval or are denied
+//! - `tool_error`: Structured failures of tool calls, for retries and the LLM
+//! - `tool_selection`: The tools most relevant to a turn, to fit the LLM context
+//! - `transcript`: Sessions exported as Markdown or JSONL and imported back
+//! - `uri_template`: URI templates that resources are addressed by
 //! - `utils`: Common utility functions
 //! - `config`: Configuration management
 //! - `rag_server`: RAG server functionality
+//! - `retry`: Retry policies with backoff for tool calls and vector backends
+//! - `vector_backend`: Storage abstraction shared by the vector stores (Qdrant)
+//! - `local_backend`: In-process vector storage that needs no Qdrant server
+//! - `long_term_memory`: Past exchanges of the chat recalled by similarity
+//! - `markdown`: Answers rendered as Markdown, code highlighted, for the terminal
+//! - `lancedb_backend`: LanceDB vector storage in a local directory (feature `lancedb`)
+//! - `embedder`: Embedding backends for RAG (rust-bert, Ollama, OpenAI)
+//! - `embedded_llm`: Local GGUF inference (feature `embedded-llm`)
+//! - `usage`: Token usage and cost accounting
+//! - `history`: Chat history storage
+//! - `whisper_server`: Voice input through Whisper
+//! - `doc_processor`: Obsidian vault processing
 //!
 //! Author: arkSong <arksong2018@gmail.com>
 //! Version: 1.0.0
 //! License: MIT
 
+pub mod agent;
+pub mod audit_log;
+pub mod browser_server;
+pub mod chat_session;
+pub mod completion;
 pub mod config;
 pub mod conversation;
+pub mod doc_processor;
 pub mod document_processor;
+#[cfg(feature = "embedded-llm")]
+pub mod embedded_llm;
+pub mod embedder;
+pub mod file_archive;
+pub mod file_policy;
 pub mod file_server;
+pub mod file_template;
+pub mod history;
+#[cfg(feature = "lancedb")]
+pub mod lancedb_backend;
 pub mod llm_client;
+pub mod local_backend;
+pub mod long_term_memory;
+pub mod markdown;
 pub mod mcp_server;
+pub mod metrics;
+pub mod plugin_server;
+pub mod prompt_template;
+pub mod prompts;
 pub mod protocol;
 pub mod rag_server;
+pub mod retry;
+pub mod server;
+pub mod server_manager;
+pub mod sqlite_migrations;
 pub mod sqlite_server;
 pub mod stdio_server;
+pub mod tool_error;
+pub mod tool_policy;
+pub mod tool_selection;
+pub mod transcript;
+pub mod uri_template;
+pub mod usage;
 pub mod utils;
+pub mod vector_backend;
 pub mod vector_db;
 pub mod vector_store;
+pub mod whisper_server;
 
 // Re-export commonly used types
+pub use chat_session::ChatSession;
 pub use config::Configuration;
 pub use llm_client::{LLMClient, LLMConfig, LLMProvider};
 pub use mcp_server::{McpServer, Resource, Tool};
+pub use prompts::{load_system_prompts, SystemPrompts};
 pub use protocol::{Message, ToolSchema};
+pub use server::Server;
 pub use sqlite_server::SqliteServer;
 pub use vector_db::*;
-
-pub struct ChatSession {
-    pub llm_client: std::sync::Arc<tokio::sync::Mutex<LLMClient>>,
-    pub servers: Vec<Server>,
-    pub current_provider: LLMProvider,
-    pub running: bool,
-}
-
-impl ChatSession {
-    pub async fn new(
-        llm_client: std::sync::Arc<tokio::sync::Mutex<LLMClient>>,
-    ) -> anyhow::Result<Self> {
-        let mut servers = Vec::new();
-        let config = Configuration::new();
-        let llm_config = config.load_llm_config("config/config.yaml")?;
-
-        let server_config = std::fs::read_to_string("config/servers_config.json")?;
-        let server_config: serde_json::Value = serde_json::from_str(&server_config)?;
-
-        if let Some(servers_config) = server_config.get("mcpServers") {
-            for (name, config) in servers_config.as_object().unwrap() {
-                let server = Server::new(name.clone(), config.clone()).await?;
-                servers.push(server);
-            }
-        }
-
-        Ok(Self {
-            llm_client,
-            servers,
-            current_provider: LLMProvider::Ollama,
-            running: true,
-        })
-    }
-
-    pub async fn process_command(&mut self, command: &str) -> anyhow::Result<()> {
-        match command {
-            "/help" => Ok(()),
-            "/clear" => Ok(()),
-            "/exit" => {
-                self.running = false;
-                Ok(())
-            }
-            _ => Ok(()),
-        }
-    }
-}
-
-pub struct Server {
-    pub name: String,
-    pub config: serde_json::Value,
-    pub mcp_server: McpServer,
-}
-
-impl Server {
-    pub async fn new(name: String, config: serde_json::Value) -> anyhow::Result<Self> {
-        let mut mcp_server = McpServer::new();
-        mcp_server.initialize().await?;
-
-        Ok(Self {
-            name,
-            config,
-            mcp_server,
-        })
-    }
-
-    pub async fn execute_tool(
-        &mut self,
-        tool_name: &str,
-        arguments: &serde_json::Value,
-        retries: u32,
-        delay: std::time::Duration,
-    ) -> anyhow::Result<serde_json::Value> {
-        self.mcp_server
-            .execute_tool(tool_name, arguments, retries, delay)
-            .await
-    }
-}
-
-// Re-export the load_system_prompts function
-pub fn load_system_prompts() -> SystemPrompts {
-    match std::fs::read_to_string("mcp_prompts.yaml") {
-        Ok(content) => match serde_yaml::from_str::<SystemPrompts>(&content) {
-            Ok(prompts) => prompts,
-            Err(_) => create_default_prompts(),
-        },
-        Err(_) => create_default_prompts(),
-    }
-}
-
-#[derive(Debug, serde::Deserialize)]
-pub struct SystemPrompts {
-    pub server_prompts: std::collections::HashMap<String, ServerPrompt>,
-    pub default_system_prompt: String,
-    pub common_prompts: CommonPrompts,
-}
-
-#[derive(Debug, serde::Deserialize)]
-pub struct ServerPrompt {
-    pub system_prompt: String,
-}
-
-#[derive(Debug, serde::Deserialize)]
-pub struct CommonPrompts {
-    pub tool_response: String,
-    pub welcome: String,
-}
-
-fn create_default_prompts() -> SystemPrompts {
-    SystemPrompts {
-        server_prompts: std::collections::HashMap::new(),
-        default_system_prompt: "You are an intelligent assistant.".to_string(),
-        common_prompts: CommonPrompts {
-            tool_response: "Tool response received.".to_string(),
-            welcome: "Welcome to MCP-ChatBot!".to_string(),
-        },
-    }
-}
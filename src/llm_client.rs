@@ -16,18 +16,127 @@
 //! Version: 1.0.0
 //! License: MIT
 
-use anyhow::{Error, Result};
-use log::{debug, error, info};
-use reqwest::Client;
+use anyhow::Result;
+use futures::future::join_all;
+use log::{debug, error, info, warn};
+use reqwest::{Client, StatusCode};
 use serde::{Deserialize, Serialize};
-use serde_json::{json, Value};
-use std::time::Duration;
+use serde_json::{json, Map, Value};
+use std::collections::HashMap;
+use std::str::FromStr;
+use std::time::{Duration, Instant};
+use thiserror::Error;
+use tokio_util::sync::CancellationToken;
+
+#[cfg(feature = "embedded-llm")]
+use tokio::sync::OnceCell;
+
+#[cfg(feature = "embedded-llm")]
+use crate::embedded_llm::EmbeddedModel;
+use crate::usage::{ProviderPricing, TokenUsage};
 
 #[derive(Debug, Clone, PartialEq)]
 pub enum LLMProvider {
     Ollama,
     OpenAI,
     Deepseek,
+    Embedded,
+}
+
+/// Failures of LLM requests, wrapped in `anyhow::Error` by the client methods.
+/// Use `error.downcast_ref::<LLMError>()` to react to a specific kind.
+#[derive(Debug, Error)]
+pub enum LLMError {
+    #[error("Rate limited, retry after {}", format_retry_after(.retry_after))]
+    RateLimited { retry_after: Option<Duration> },
+    #[error("Context too long: {0}")]
+    ContextTooLong(String),
+    #[error("Authentication failed: {0}")]
+    AuthFailed(String),
+    #[error("Model not found: {0}")]
+    ModelNotFound(String),
+    #[error("Network error: {0}")]
+    Network(#[from] reqwest::Error),
+    #[error("Invalid response: {0}")]
+    InvalidResponse(String),
+    #[error("Server returned error ({status}): {message}")]
+    Server { status: u16, message: String },
+    #[error("Configuration error: {0}")]
+    Config(String),
+    #[error("Request cancelled")]
+    Cancelled,
+}
+
+fn format_retry_after(retry_after: &Option<Duration>) -> String {
+    match retry_after {
+        Some(duration) => format!("{}s", duration.as_secs()),
+        None => "a while".to_string(),
+    }
+}
+
+impl LLMError {
+    /// Maps an unsuccessful HTTP response to the matching error kind
+    fn from_response(status: StatusCode, retry_after: Option<&str>, body: &str) -> Self {
+        // Both Ollama ({"error": "..."}) and OpenAI ({"error": {"message": "..."}})
+        // wrap the message in an "error" field
+        let message = serde_json::from_str::<Value>(body)
+            .ok()
+            .and_then(|json| {
+                json["error"]["message"]
+                    .as_str()
+                    .or_else(|| json["error"].as_str())
+                    .map(|s| s.to_string())
+            })
+            .unwrap_or_else(|| body.trim().to_string());
+        let lower = message.to_lowercase();
+
+        match status.as_u16() {
+            429 => LLMError::RateLimited {
+                retry_after: retry_after
+                    .and_then(|v| v.trim().parse::<u64>().ok())
+                    .map(Duration::from_secs),
+            },
+            401 | 403 => LLMError::AuthFailed(message),
+            _ if lower.contains("context length")
+                || lower.contains("context_length")
+                || lower.contains("maximum context")
+                || lower.contains("too many tokens") =>
+            {
+                LLMError::ContextTooLong(message)
+            }
+            404 => LLMError::ModelNotFound(message),
+            _ if lower.contains("model") && lower.contains("not found") => {
+                LLMError::ModelNotFound(message)
+            }
+            status => LLMError::Server { status, message },
+        }
+    }
+}
+
+impl FromStr for LLMProvider {
+    type Err = LLMError;
+
+    fn from_str(s: &str) -> std::result::Result<Self, LLMError> {
+        match s.trim().to_lowercase().as_str() {
+            "ollama" => Ok(LLMProvider::Ollama),
+            "openai" => Ok(LLMProvider::OpenAI),
+            "deepseek" => Ok(LLMProvider::Deepseek),
+            "embedded" => Ok(LLMProvider::Embedded),
+            other => Err(LLMError::Config(format!("Unknown LLM provider: {}", other))),
+        }
+    }
+}
+
+impl LLMProvider {
+    /// Lowercase name used as key in configuration sections such as `pricing`
+    pub fn name(&self) -> &'static str {
+        match self {
+            LLMProvider::Ollama => "ollama",
+            LLMProvider::OpenAI => "openai",
+            LLMProvider::Deepseek => "deepseek",
+            LLMProvider::Embedded => "embedded",
+        }
+    }
 }
 
 #[derive(Debug, Deserialize)]
@@ -39,6 +148,14 @@ pub struct LLMConfig {
     pub logging: LoggingConfig,
     pub cache: CacheConfig,
     pub endpoints: EndpointsConfig,
+    #[serde(default)]
+    pub pricing: HashMap<String, ProviderPricing>,
+    /// Providers tried in order when the active one cannot be reached
+    #[serde(default)]
+    pub fallback_chain: Vec<String>,
+    /// Local GGUF model used by the `embedded` provider
+    #[serde(default)]
+    pub embedded: Option<EmbeddedConfig>,
 }
 
 #[derive(Debug, Deserialize)]
@@ -65,12 +182,54 @@ pub struct ModelsConfig {
     pub deepseek: String,
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Clone, Deserialize)]
 pub struct ModelConfig {
     pub name: String,
     pub max_tokens: u32,
     pub temperature: f32,
     pub specialized: Option<String>,
+    /// Sequences that end generation, e.g. to stop right after a tool call
+    pub stop: Option<Vec<String>>,
+    pub presence_penalty: Option<f32>,
+    pub frequency_penalty: Option<f32>,
+    /// Token id -> bias (-100..100), only supported by OpenAI-compatible APIs
+    pub logit_bias: Option<HashMap<String, f32>>,
+}
+
+impl ModelConfig {
+    /// Settings used for models that have no entry in `models.available`
+    fn default_for(name: &str) -> Self {
+        Self {
+            name: name.to_string(),
+            max_tokens: 4096,
+            temperature: 0.7,
+            specialized: None,
+            stop: None,
+            presence_penalty: None,
+            frequency_penalty: None,
+            logit_bias: None,
+        }
+    }
+
+    /// Adds the optional sampling settings that are set to `target`
+    fn apply_sampling_options(&self, target: &mut Map<String, Value>, with_logit_bias: bool) {
+        if let Some(stop) = &self.stop {
+            target.insert("stop".to_string(), json!(stop));
+        }
+        if let Some(penalty) = self.presence_penalty {
+            target.insert("presence_penalty".to_string(), json!(penalty));
+        }
+        if let Some(penalty) = self.frequency_penalty {
+            target.insert("frequency_penalty".to_string(), json!(penalty));
+        }
+        if let Some(logit_bias) = &self.logit_bias {
+            if with_logit_bias {
+                target.insert("logit_bias".to_string(), json!(logit_bias));
+            } else {
+                debug!("logit_bias is not supported by this provider, ignoring it");
+            }
+        }
+    }
 }
 
 #[derive(Debug, Deserialize)]
@@ -101,10 +260,54 @@ pub struct EndpointsConfig {
     pub deepseek: String,
 }
 
+#[derive(Debug, Clone, Deserialize)]
+pub struct EmbeddedConfig {
+    pub model_path: String,
+    #[serde(default = "default_context_size")]
+    pub context_size: u32,
+    #[serde(default = "default_embedded_max_tokens")]
+    pub max_tokens: usize,
+}
+
+fn default_context_size() -> u32 {
+    4096
+}
+
+fn default_embedded_max_tokens() -> usize {
+    512
+}
+
+/// Answer of one provider in a parallel fan-out
+#[derive(Debug)]
+pub struct ProviderResponse {
+    pub provider: LLMProvider,
+    pub model: String,
+    pub result: Result<String>,
+    pub usage: Option<TokenUsage>,
+    pub elapsed: Duration,
+}
+
+/// Readiness of a provider as reported by `LLMClient::health_check`
+#[derive(Debug, Clone)]
+pub struct ProviderHealth {
+    pub provider: LLMProvider,
+    pub model: String,
+    pub latency: Duration,
+    pub model_available: bool,
+    /// Context window in tokens, when the provider reports it
+    pub context_size: Option<u64>,
+}
+
 pub struct LLMClient {
     config: LLMConfig,
     provider: LLMProvider,
     client: Client,
+    model_override: Option<String>,
+    last_usage: Option<TokenUsage>,
+    last_provider: Option<LLMProvider>,
+    /// Loaded on first use, loading a GGUF file can take several seconds
+    #[cfg(feature = "embedded-llm")]
+    embedded_model: OnceCell<EmbeddedModel>,
 }
 
 impl LLMClient {
@@ -128,14 +331,253 @@ impl LLMClient {
             config,
             provider,
             client,
+            model_override: None,
+            last_usage: None,
+            last_provider: None,
+            #[cfg(feature = "embedded-llm")]
+            embedded_model: OnceCell::new(),
         }
     }
 
-    pub fn get_api_key(&self) -> &str {
+    pub fn provider(&self) -> &LLMProvider {
+        &self.provider
+    }
+
+    /// Returns the model used for requests: the one selected with `set_model`,
+    /// or the configured default for the current provider.
+    pub fn current_model(&self) -> &str {
+        self.model_for(&self.provider)
+    }
+
+    /// The `set_model` override only applies to the active provider,
+    /// fallback providers always use their configured model
+    fn model_for(&self, provider: &LLMProvider) -> &str {
+        if let Some(model) = &self.model_override {
+            if *provider == self.provider {
+                return model;
+            }
+        }
+        match provider {
+            LLMProvider::Ollama => &self.config.models.ollama,
+            LLMProvider::OpenAI => &self.config.models.openai,
+            LLMProvider::Deepseek => &self.config.models.deepseek,
+            LLMProvider::Embedded => self
+                .config
+                .embedded
+                .as_ref()
+                .map(|e| e.model_path.as_str())
+                .unwrap_or("embedded"),
+        }
+    }
+
+    fn endpoint_for(&self, provider: &LLMProvider) -> &str {
+        match provider {
+            LLMProvider::OpenAI => &self.config.endpoints.openai,
+            LLMProvider::Deepseek => &self.config.endpoints.deepseek,
+            LLMProvider::Ollama => &self.config.endpoints.ollama,
+            LLMProvider::Embedded => "", // Runs in-process
+        }
+    }
+
+    /// Provider that served the last successful request, which differs from
+    /// `provider()` when the request failed over to the fallback chain
+    pub fn last_provider(&self) -> Option<&LLMProvider> {
+        self.last_provider.as_ref()
+    }
+
+    /// The active provider followed by the configured fallback providers
+    pub fn provider_chain(&self) -> Vec<LLMProvider> {
+        let mut chain = vec![self.provider.clone()];
+        for name in &self.config.fallback_chain {
+            match name.parse::<LLMProvider>() {
+                Ok(provider) if !chain.contains(&provider) => chain.push(provider),
+                Ok(_) => {}
+                Err(e) => warn!("Ignoring fallback provider: {}", e),
+            }
+        }
+        chain
+    }
+
+    /// Token usage reported by the provider for the last successful request
+    pub fn last_usage(&self) -> Option<TokenUsage> {
+        self.last_usage
+    }
+
+    pub fn pricing(&self) -> &HashMap<String, ProviderPricing> {
+        &self.config.pricing
+    }
+
+    pub fn set_model(&mut self, model_name: &str) {
+        info!("Switching {:?} model to {}", self.provider, model_name);
+        self.model_override = Some(model_name.to_string());
+    }
+
+    /// The model selected with `set_model`, if any
+    pub fn model_override(&self) -> Option<&str> {
+        self.model_override.as_deref()
+    }
+
+    /// Selects `model_override` as `set_model` does, or with `None` goes
+    /// back to the configured model
+    pub fn set_model_override(&mut self, model_override: Option<String>) {
+        self.model_override = model_override;
+    }
+
+    /// Queries the provider for the models it can serve
+    /// (`/api/tags` for Ollama, `/v1/models` for OpenAI-compatible APIs)
+    pub async fn list_models(&self) -> Result<Vec<String>> {
+        if self.provider == LLMProvider::Embedded {
+            return Ok(vec![self.current_model().to_string()]);
+        }
+
+        let url = self.models_url();
+        debug!("Listing models from {}", url);
+
+        let response = self
+            .client
+            .get(&url)
+            .headers(self.auth_headers(&self.provider))
+            .send()
+            .await
+            .map_err(LLMError::Network)?;
+        let response = check_status(response).await?;
+
+        let response_json: Value = response.json().await.map_err(LLMError::Network)?;
+        let (list_key, name_key) = match self.provider {
+            LLMProvider::Ollama => ("models", "name"),
+            _ => ("data", "id"),
+        };
+
+        let models = response_json[list_key]
+            .as_array()
+            .ok_or_else(|| LLMError::InvalidResponse("Invalid model list format".to_string()))?
+            .iter()
+            .filter_map(|m| m[name_key].as_str().map(|s| s.to_string()))
+            .collect();
+
+        Ok(models)
+    }
+
+    fn models_url(&self) -> String {
         match self.provider {
+            LLMProvider::Ollama => format!("{}/api/tags", self.ollama_base_url()),
+            LLMProvider::OpenAI | LLMProvider::Deepseek => {
+                let base = self
+                    .endpoint_for(&self.provider)
+                    .trim_end_matches('/')
+                    .trim_end_matches("/chat/completions");
+                format!("{}/models", base)
+            }
+            LLMProvider::Embedded => String::new(),
+        }
+    }
+
+    /// Ollama server address without the `/api/...` path of the chat endpoint
+    fn ollama_base_url(&self) -> &str {
+        let endpoint = self
+            .endpoint_for(&LLMProvider::Ollama)
+            .trim_end_matches('/');
+        endpoint
+            .find("/api/")
+            .map(|i| &endpoint[..i])
+            .unwrap_or(endpoint)
+    }
+
+    /// Checks that the active provider is reachable and serves the current model.
+    /// Latency is measured on the model listing request.
+    pub async fn health_check(&self) -> Result<ProviderHealth> {
+        let model = self.current_model().to_string();
+
+        if self.provider == LLMProvider::Embedded {
+            let config = self.config.embedded.as_ref().ok_or_else(|| {
+                LLMError::Config("Missing `embedded` section in config.yaml".into())
+            })?;
+            return Ok(ProviderHealth {
+                provider: self.provider.clone(),
+                model_available: std::path::Path::new(&config.model_path).exists(),
+                model,
+                latency: Duration::ZERO,
+                context_size: Some(config.context_size as u64),
+            });
+        }
+
+        let started = Instant::now();
+        let models = self.list_models().await?;
+        let latency = started.elapsed();
+
+        let model_available = models.iter().any(|m| model_matches(m, &model));
+        let context_size = match self.provider {
+            LLMProvider::Ollama if model_available => self.ollama_context_size(&model).await,
+            _ => None, // OpenAI-compatible APIs do not report it
+        };
+
+        debug!(
+            "Health check for {:?}: {:?}, model available: {}",
+            self.provider, latency, model_available
+        );
+
+        Ok(ProviderHealth {
+            provider: self.provider.clone(),
+            model,
+            latency,
+            model_available,
+            context_size,
+        })
+    }
+
+    /// Reads the context length from `/api/show`, None if it is not reported
+    async fn ollama_context_size(&self, model: &str) -> Option<u64> {
+        let url = format!("{}/api/show", self.ollama_base_url());
+        let response = self
+            .client
+            .post(&url)
+            .json(&json!({ "model": model }))
+            .send()
+            .await
+            .ok()?;
+        let response_json: Value = check_status(response).await.ok()?.json().await.ok()?;
+        parse_context_length(&response_json)
+    }
+
+    /// Sends a one-token prompt so Ollama loads the model into memory before
+    /// the first real message. Hosted and embedded providers are left alone.
+    pub async fn warmup(&self) -> Result<Duration> {
+        if self.provider != LLMProvider::Ollama {
+            return Ok(Duration::ZERO);
+        }
+
+        let model = self.current_model();
+        info!("Warming up Ollama model {}", model);
+        let request_body = json!({
+            "model": model,
+            "messages": [{"role": "user", "content": "Hi"}],
+            "stream": false,
+            "options": {"num_predict": 1}
+        });
+
+        let started = Instant::now();
+        let url = self.endpoint_for(&self.provider);
+        self.send_request(&self.provider, url, &request_body)
+            .await?
+            .bytes()
+            .await
+            .map_err(LLMError::Network)?;
+        let elapsed = started.elapsed();
+
+        info!("Model {} loaded in {:?}", model, elapsed);
+        Ok(elapsed)
+    }
+
+    pub fn get_api_key(&self) -> &str {
+        self.api_key_for(&self.provider)
+    }
+
+    fn api_key_for(&self, provider: &LLMProvider) -> &str {
+        match provider {
             LLMProvider::OpenAI => &self.config.api_keys.openai,
             LLMProvider::Deepseek => &self.config.api_keys.deepseek,
             LLMProvider::Ollama => "ollama", // Ollama doesn't need an API key
+            LLMProvider::Embedded => "embedded",
         }
     }
 
@@ -150,97 +592,232 @@ impl LLMClient {
     pub async fn get_response(
         &mut self,
         messages: &[Value],
-        _model_name: Option<&str>,
+        model_name: Option<&str>,
     ) -> Result<String> {
-        let model = match self.provider {
-            LLMProvider::Ollama => &self.config.models.ollama,
-            LLMProvider::OpenAI => &self.config.models.openai,
-            LLMProvider::Deepseek => &self.config.models.deepseek,
-        };
+        self.get_response_cancellable(messages, model_name, &CancellationToken::new())
+            .await
+    }
 
-        let model_config = ModelConfig {
-            name: model.clone(),
-            max_tokens: 4096,
-            temperature: 0.7,
-            specialized: None,
-        };
+    /// Queries all `providers` concurrently with the same messages and returns
+    /// every answer in the order of `providers`. Failures are reported per
+    /// provider and do not fall back to other providers.
+    pub async fn get_responses_parallel(
+        &self,
+        messages: &[Value],
+        providers: &[LLMProvider],
+    ) -> Vec<ProviderResponse> {
+        self.get_responses_parallel_cancellable(messages, providers, &CancellationToken::new())
+            .await
+    }
 
-        let url = match self.provider {
-            LLMProvider::OpenAI => self.config.endpoints.openai.clone(),
-            LLMProvider::Deepseek => self.config.endpoints.deepseek.clone(),
-            LLMProvider::Ollama => self.config.endpoints.ollama.clone(),
-        };
+    pub async fn get_responses_parallel_cancellable(
+        &self,
+        messages: &[Value],
+        providers: &[LLMProvider],
+        cancel: &CancellationToken,
+    ) -> Vec<ProviderResponse> {
+        let requests = providers.iter().map(|provider| async move {
+            let start = Instant::now();
+            let result = self.request_provider(provider, messages, cancel).await;
+            let elapsed = start.elapsed();
+            info!("{:?} answered in {:.2?}", provider, elapsed);
 
-        let formatted_messages = self.format_messages(messages)?;
-        let request_body = self.create_request_body(model, &model_config, &formatted_messages)?;
+            let (result, usage) = match result {
+                Ok((content, usage)) => (Ok(content), usage),
+                Err(e) => (Err(e), None),
+            };
+            ProviderResponse {
+                provider: provider.clone(),
+                model: self.model_for(provider).to_string(),
+                result,
+                usage,
+                elapsed,
+            }
+        });
 
-        let response = self.send_request(&url, &request_body).await?;
-        let response_text = response.text().await?;
+        join_all(requests).await
+    }
 
-        if response_text.is_empty() {
-            error!("Received empty response from server");
-            return Err(Error::msg("Empty response from server"));
-        }
+    /// Same as `get_response`, but aborts the in-flight request as soon as
+    /// `cancel` is triggered and returns a "Request cancelled" error
+    pub async fn get_response_cancellable(
+        &mut self,
+        messages: &[Value],
+        _model_name: Option<&str>,
+        cancel: &CancellationToken,
+    ) -> Result<String> {
+        self.last_usage = None;
+        self.last_provider = None;
 
-        match self.provider {
-            LLMProvider::Ollama => {
-                let mut full_response = String::new();
-                let mut is_done = false;
-                let mut has_content = false;
+        let chain = self.provider_chain();
+        let mut last_error = None;
 
-                for line in response_text.lines() {
-                    if line.trim().is_empty() {
-                        continue;
+        for (i, provider) in chain.iter().enumerate() {
+            match self.request_provider(provider, messages, cancel).await {
+                Ok((response, usage)) => {
+                    if i > 0 {
+                        warn!(
+                            "{:?} unavailable, response served by fallback provider {:?}",
+                            self.provider, provider
+                        );
+                    } else {
+                        info!("Response served by {:?}", provider);
                     }
+                    self.last_usage = usage;
+                    self.last_provider = Some(provider.clone());
+                    return Ok(response);
+                }
+                Err(e) if is_connection_error(&e) && i + 1 < chain.len() => {
+                    warn!("{:?} request failed, trying next provider: {}", provider, e);
+                    last_error = Some(e);
+                }
+                Err(e) => return Err(e),
+            }
+        }
 
-                    if let Ok(response_json) = serde_json::from_str::<Value>(line) {
-                        if let Some(message) = response_json.get("message") {
-                            if let Some(content) = message.get("content") {
-                                if let Some(text) = content.as_str() {
-                                    full_response.push_str(text);
-                                    has_content = true;
-                                }
-                            }
-                        }
+        Err(last_error
+            .unwrap_or_else(|| LLMError::Config("No LLM provider available".into()).into()))
+    }
 
-                        if let Some(done) = response_json.get("done") {
-                            is_done = done.as_bool().unwrap_or(false);
-                            if is_done {
-                                break;
-                            }
+    /// Sends one chat request to `provider` and returns its answer together
+    /// with the token usage it reported
+    async fn request_provider(
+        &self,
+        provider: &LLMProvider,
+        messages: &[Value],
+        cancel: &CancellationToken,
+    ) -> Result<(String, Option<TokenUsage>)> {
+        if *provider == LLMProvider::Embedded {
+            let (content, usage) = self.embedded_complete(messages, cancel).await?;
+            return Ok((content, Some(usage)));
+        }
+
+        let model = self.model_for(provider).to_string();
+
+        let model_config = self
+            .get_model_config(&model)
+            .cloned()
+            .unwrap_or_else(|| ModelConfig::default_for(&model));
+
+        let url = self.endpoint_for(provider);
+
+        let formatted_messages = self.format_messages(provider, messages)?;
+        let request_body =
+            self.create_request_body(provider, &model, &model_config, &formatted_messages)?;
+
+        let mut response = tokio::select! {
+            biased;
+            _ = cancel.cancelled() => return Err(LLMError::Cancelled.into()),
+            response = self.send_request(provider, url, &request_body) => response?,
+        };
+
+        match provider {
+            LLMProvider::Ollama => {
+                // Ollama streams one JSON object per line, read it chunk by chunk
+                // so that cancellation takes effect mid-generation
+                let mut stream = OllamaStream::default();
+
+                loop {
+                    let chunk = tokio::select! {
+                        biased;
+                        _ = cancel.cancelled() => {
+                            info!("Ollama request cancelled by user");
+                            return Err(LLMError::Cancelled.into());
                         }
+                        chunk = response.chunk() => chunk.map_err(LLMError::Network)?,
+                    };
+
+                    let is_last = chunk.is_none();
+                    let is_done = match chunk {
+                        Some(bytes) => stream.push_bytes(&bytes),
+                        None => stream.push_bytes(b"\n"),
+                    };
+
+                    if is_done || is_last {
+                        break;
                     }
                 }
 
-                if has_content {
-                    Ok(full_response)
+                if stream.has_content {
+                    Ok((stream.content, stream.usage))
                 } else {
-                    Err(Error::msg("Invalid Ollama response format"))
+                    Err(LLMError::InvalidResponse("Invalid Ollama response format".into()).into())
                 }
             }
             LLMProvider::OpenAI | LLMProvider::Deepseek => {
+                let response_text = tokio::select! {
+                    biased;
+                    _ = cancel.cancelled() => return Err(LLMError::Cancelled.into()),
+                    text = response.text() => text.map_err(LLMError::Network)?,
+                };
+
+                if response_text.is_empty() {
+                    error!("Received empty response from server");
+                    return Err(
+                        LLMError::InvalidResponse("Empty response from server".into()).into(),
+                    );
+                }
+
                 match serde_json::from_str::<Value>(&response_text) {
-                    Ok(response_json) => match self.provider {
-                        LLMProvider::OpenAI => response_json["choices"][0]["message"]["content"]
+                    Ok(response_json) => {
+                        let content = response_json["choices"][0]["message"]["content"]
                             .as_str()
                             .map(|s| s.to_string())
-                            .ok_or_else(|| Error::msg("Invalid OpenAI response format")),
-                        LLMProvider::Deepseek => response_json["choices"][0]["message"]["content"]
-                            .as_str()
-                            .map(|s| s.to_string())
-                            .ok_or_else(|| Error::msg("Invalid Deepseek response format")),
-                        _ => unreachable!(),
-                    },
+                            .ok_or_else(|| {
+                                LLMError::InvalidResponse(format!(
+                                    "Invalid {:?} response format",
+                                    provider
+                                ))
+                            })?;
+                        Ok((content, TokenUsage::from_openai(&response_json)))
+                    }
                     Err(e) => {
                         error!("Failed to parse response as JSON: {}", e);
-                        Err(Error::msg(format!("Failed to parse response: {}", e)))
+                        Err(
+                            LLMError::InvalidResponse(format!("Failed to parse response: {}", e))
+                                .into(),
+                        )
                     }
                 }
             }
+            LLMProvider::Embedded => unreachable!("embedded requests never reach HTTP"),
         }
     }
 
-    fn format_messages(&self, messages: &[Value]) -> Result<Vec<Value>> {
+    #[cfg(feature = "embedded-llm")]
+    async fn embedded_complete(
+        &self,
+        messages: &[Value],
+        cancel: &CancellationToken,
+    ) -> Result<(String, TokenUsage)> {
+        let config =
+            self.config.embedded.clone().ok_or_else(|| {
+                LLMError::Config("Missing `embedded` section in config.yaml".into())
+            })?;
+
+        let model = self
+            .embedded_model
+            .get_or_try_init(|| async move {
+                tokio::task::spawn_blocking(move || EmbeddedModel::load(&config)).await?
+            })
+            .await?;
+
+        model.complete(messages, cancel).await
+    }
+
+    #[cfg(not(feature = "embedded-llm"))]
+    async fn embedded_complete(
+        &self,
+        _messages: &[Value],
+        _cancel: &CancellationToken,
+    ) -> Result<(String, TokenUsage)> {
+        Err(LLMError::Config(
+            "The embedded provider requires building with `--features embedded-llm`".into(),
+        )
+        .into())
+    }
+
+    fn format_messages(&self, provider: &LLMProvider, messages: &[Value]) -> Result<Vec<Value>> {
         let formatted = messages
             .iter()
             .filter(|msg| {
@@ -251,7 +828,16 @@ impl LLMClient {
                 let role = msg["role"].as_str().unwrap_or("user");
                 let content = msg["content"].as_str().unwrap_or("");
 
-                match self.provider {
+                // Tool results are sent as text where tool messages must
+                // answer a native tool call
+                if role == "tool" && *provider != LLMProvider::Ollama {
+                    return json!({
+                        "role": "user",
+                        "content": format!("Tool result: {}", content)
+                    });
+                }
+
+                match provider {
                     LLMProvider::Ollama => {
                         if role == "system" {
                             if !content.trim().is_empty() {
@@ -286,11 +872,12 @@ impl LLMClient {
 
     fn create_request_body(
         &self,
+        provider: &LLMProvider,
         model: &str,
         config: &ModelConfig,
         messages: &[Value],
     ) -> Result<Value> {
-        let body = match self.provider {
+        let mut body = match provider {
             LLMProvider::Ollama => {
                 // info!("Creating Ollama request body with model: {}", model);
                 // info!(
@@ -317,12 +904,31 @@ impl LLMClient {
                 "temperature": config.temperature,
                 "stream": false
             }),
+            LLMProvider::Embedded => {
+                return Err(
+                    LLMError::Config("Embedded provider has no HTTP request body".into()).into(),
+                )
+            }
         };
 
+        if let Some(body) = body.as_object_mut() {
+            match provider {
+                // Ollama expects sampling settings inside "options"
+                LLMProvider::Ollama => {
+                    let mut options = Map::new();
+                    config.apply_sampling_options(&mut options, false);
+                    if !options.is_empty() {
+                        body.insert("options".to_string(), Value::Object(options));
+                    }
+                }
+                _ => config.apply_sampling_options(body, true),
+            }
+        }
+
         Ok(body)
     }
 
-    async fn send_request(&self, url: &str, body: &Value) -> Result<reqwest::Response> {
+    fn auth_headers(&self, provider: &LLMProvider) -> reqwest::header::HeaderMap {
         let mut headers = reqwest::header::HeaderMap::new();
         headers.insert(
             reqwest::header::CONTENT_TYPE,
@@ -330,37 +936,143 @@ impl LLMClient {
         );
         headers.insert(reqwest::header::ACCEPT, "application/json".parse().unwrap());
 
-        match self.provider {
+        match provider {
             LLMProvider::OpenAI => {
                 headers.insert(
                     reqwest::header::AUTHORIZATION,
-                    format!("Bearer {}", self.get_api_key()).parse().unwrap(),
+                    format!("Bearer {}", self.api_key_for(provider))
+                        .parse()
+                        .unwrap(),
                 );
             }
             LLMProvider::Deepseek => {
                 headers.insert(
                     reqwest::header::AUTHORIZATION,
-                    format!("Bearer {}", self.get_api_key()).parse().unwrap(),
+                    format!("Bearer {}", self.api_key_for(provider))
+                        .parse()
+                        .unwrap(),
                 );
             }
-            LLMProvider::Ollama => {}
+            LLMProvider::Ollama | LLMProvider::Embedded => {}
         }
 
+        headers
+    }
+
+    async fn send_request(
+        &self,
+        provider: &LLMProvider,
+        url: &str,
+        body: &Value,
+    ) -> Result<reqwest::Response> {
+        let headers = self.auth_headers(provider);
+
         let response = self
             .client
             .post(url)
             .headers(headers)
             .json(body)
             .send()
-            .await?;
+            .await
+            .map_err(LLMError::Network)?;
+
+        check_status(response).await
+    }
+}
+
+/// Turns non-2xx responses into the matching `LLMError`
+async fn check_status(response: reqwest::Response) -> Result<reqwest::Response> {
+    let status = response.status();
+    if status.is_success() {
+        return Ok(response);
+    }
+
+    let retry_after = response
+        .headers()
+        .get(reqwest::header::RETRY_AFTER)
+        .and_then(|v| v.to_str().ok())
+        .map(|v| v.to_string());
+    let error_text = response.text().await.map_err(LLMError::Network)?;
+    error!("Error response from server ({}): {}", status, error_text);
+
+    Err(LLMError::from_response(status, retry_after.as_deref(), &error_text).into())
+}
+
+/// Connection failures and timeouts are worth retrying on another provider,
+/// API errors (bad request, auth, ...) are returned as-is
+fn is_connection_error(error: &anyhow::Error) -> bool {
+    match error.downcast_ref::<LLMError>() {
+        Some(LLMError::Network(e)) => e.is_connect() || e.is_timeout(),
+        _ => false,
+    }
+}
+
+/// Ollama lists models with their tag, so `llama3.2` matches `llama3.2:latest`
+pub fn model_matches(listed: &str, wanted: &str) -> bool {
+    listed == wanted || (!wanted.contains(':') && listed == format!("{}:latest", wanted))
+}
 
-        if !response.status().is_success() {
-            let error_text = response.text().await?;
-            error!("Error response from server: {}", error_text);
-            return Err(Error::msg(format!("Server returned error: {}", error_text)));
+/// Finds `<architecture>.context_length` in the `model_info` of an `/api/show` response
+fn parse_context_length(show: &Value) -> Option<u64> {
+    show["model_info"]
+        .as_object()?
+        .iter()
+        .find(|(key, _)| key.ends_with(".context_length"))
+        .and_then(|(_, value)| value.as_u64())
+}
+
+/// Accumulates the NDJSON lines streamed back by Ollama's chat endpoint
+#[derive(Default)]
+struct OllamaStream {
+    content: String,
+    has_content: bool,
+    usage: Option<TokenUsage>,
+    /// Bytes of a line not received in full yet, which may end in the
+    /// middle of a multi-byte character
+    pending: Vec<u8>,
+}
+
+impl OllamaStream {
+    /// Appends a chunk of the response and the content of the lines it
+    /// completes. Returns true once the server reports that generation is
+    /// done.
+    fn push_bytes(&mut self, bytes: &[u8]) -> bool {
+        self.pending.extend_from_slice(bytes);
+        while let Some(pos) = self.pending.iter().position(|b| *b == b'\n') {
+            let line: Vec<u8> = self.pending.drain(..=pos).collect();
+            if self.push_line(&String::from_utf8_lossy(&line)) {
+                return true;
+            }
         }
+        false
+    }
+
+    /// Appends the content of a single streamed line.
+    /// Returns true once the server reports that generation is done.
+    fn push_line(&mut self, line: &str) -> bool {
+        let line = line.trim();
+        if line.is_empty() {
+            return false;
+        }
+
+        match serde_json::from_str::<Value>(line) {
+            Ok(response_json) => {
+                if let Some(text) = response_json["message"]["content"].as_str() {
+                    self.content.push_str(text);
+                    self.has_content = true;
+                }
 
-        Ok(response)
+                let done = response_json["done"].as_bool().unwrap_or(false);
+                if done {
+                    self.usage = TokenUsage::from_ollama(&response_json);
+                }
+                done
+            }
+            Err(e) => {
+                debug!("Skipping malformed Ollama line: {}", e);
+                false
+            }
+        }
     }
 }
 
@@ -388,6 +1100,10 @@ mod tests {
                     max_tokens: 8192,
                     temperature: 0.7,
                     specialized: None,
+                    stop: Some(vec!["\nObservation:".to_string()]),
+                    presence_penalty: Some(0.5),
+                    frequency_penalty: None,
+                    logit_bias: Some(HashMap::from([("50256".to_string(), -100.0)])),
                 }],
                 ollama: "llama3.2:latest".to_string(),
                 openai: "https://api.test.com/v1/chat/completions".to_string(),
@@ -413,6 +1129,9 @@ mod tests {
                 openai: "https://api.test.com/v1/chat/completions".to_string(),
                 deepseek: "https://api.test.com/v1/chat/completions".to_string(),
             },
+            pricing: HashMap::new(),
+            fallback_chain: vec!["ollama".to_string(), "deepseek".to_string()],
+            embedded: None,
         }
     }
 
@@ -433,4 +1152,226 @@ mod tests {
         assert_eq!(model_config.max_tokens, 8192);
         assert_eq!(model_config.temperature, 0.7);
     }
+
+    #[test]
+    fn test_format_tool_messages() {
+        let client = LLMClient::new(create_test_config(), LLMProvider::OpenAI);
+        let messages = vec![
+            json!({"role": "assistant", "content": "{\"tool\": \"memory_get\", \"arguments\": {}}"}),
+            json!({"role": "tool", "content": "{\"content\": \"Ada\"}"}),
+        ];
+        let formatted = client
+            .format_messages(&LLMProvider::OpenAI, &messages)
+            .unwrap();
+        assert_eq!(formatted[0]["role"], "assistant");
+        assert_eq!(formatted[1]["role"], "user");
+        assert_eq!(
+            formatted[1]["content"],
+            "Tool result: {\"content\": \"Ada\"}"
+        );
+
+        let formatted = client
+            .format_messages(&LLMProvider::Ollama, &messages)
+            .unwrap();
+        assert_eq!(formatted[1]["role"], "tool");
+    }
+
+    #[test]
+    fn test_models_url() {
+        let client = LLMClient::new(create_test_config(), LLMProvider::Ollama);
+        assert_eq!(client.models_url(), "http://localhost:11434/api/tags");
+
+        let client = LLMClient::new(create_test_config(), LLMProvider::OpenAI);
+        assert_eq!(client.models_url(), "https://api.test.com/v1/models");
+    }
+
+    #[test]
+    fn test_set_model() {
+        let mut client = LLMClient::new(create_test_config(), LLMProvider::Ollama);
+        assert_eq!(client.current_model(), "llama3.2:latest");
+
+        client.set_model("qwen2.5:7b");
+        assert_eq!(client.current_model(), "qwen2.5:7b");
+    }
+
+    #[test]
+    fn test_provider_chain() {
+        assert_eq!(
+            "Deepseek".parse::<LLMProvider>().unwrap(),
+            LLMProvider::Deepseek
+        );
+        assert!("claude".parse::<LLMProvider>().is_err());
+
+        let client = LLMClient::new(create_test_config(), LLMProvider::OpenAI);
+        assert_eq!(
+            client.provider_chain(),
+            vec![
+                LLMProvider::OpenAI,
+                LLMProvider::Ollama,
+                LLMProvider::Deepseek
+            ]
+        );
+
+        let client = LLMClient::new(create_test_config(), LLMProvider::Ollama);
+        assert_eq!(
+            client.provider_chain(),
+            vec![LLMProvider::Ollama, LLMProvider::Deepseek]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_embedded_provider_without_model() {
+        let mut config = create_test_config();
+        config.fallback_chain.clear();
+        let mut client = LLMClient::new(config, LLMProvider::Embedded);
+        assert_eq!(client.current_model(), "embedded");
+
+        let messages = vec![json!({"role": "user", "content": "Hello"})];
+        assert!(client.get_response(&messages, None).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_health_check_embedded() {
+        let mut config = create_test_config();
+        config.embedded = Some(EmbeddedConfig {
+            model_path: "/nonexistent/model.gguf".to_string(),
+            context_size: 2048,
+            max_tokens: 128,
+        });
+        let client = LLMClient::new(config, LLMProvider::Embedded);
+
+        let health = client.health_check().await.unwrap();
+        assert!(!health.model_available);
+        assert_eq!(health.context_size, Some(2048));
+        assert_eq!(client.warmup().await.unwrap(), Duration::ZERO);
+    }
+
+    #[test]
+    fn test_model_matches_and_context_length() {
+        assert!(model_matches("llama3.2:latest", "llama3.2"));
+        assert!(model_matches("llama3.2:latest", "llama3.2:latest"));
+        assert!(!model_matches("llama3.2:1b", "llama3.2"));
+
+        let show = json!({
+            "model_info": {"general.architecture": "llama", "llama.context_length": 131072}
+        });
+        assert_eq!(parse_context_length(&show), Some(131072));
+        assert_eq!(parse_context_length(&json!({})), None);
+    }
+
+    #[tokio::test]
+    async fn test_get_responses_parallel_keeps_order() {
+        let client = LLMClient::new(create_test_config(), LLMProvider::Ollama);
+        let cancel = CancellationToken::new();
+        cancel.cancel();
+
+        let providers = vec![LLMProvider::Deepseek, LLMProvider::Ollama];
+        let messages = vec![json!({"role": "user", "content": "Hello"})];
+        let responses = client
+            .get_responses_parallel_cancellable(&messages, &providers, &cancel)
+            .await;
+
+        assert_eq!(responses.len(), 2);
+        assert_eq!(responses[0].provider, LLMProvider::Deepseek);
+        assert_eq!(responses[1].provider, LLMProvider::Ollama);
+        assert!(responses.iter().all(|r| r.result.is_err()));
+    }
+
+    #[test]
+    fn test_request_body_sampling_options() {
+        let client = LLMClient::new(create_test_config(), LLMProvider::OpenAI);
+        let model_config = client.get_model_config("gpt-4").unwrap().clone();
+        let messages = vec![json!({"role": "user", "content": "Hello"})];
+
+        let body = client
+            .create_request_body(&LLMProvider::OpenAI, "gpt-4", &model_config, &messages)
+            .unwrap();
+        assert_eq!(body["stop"], json!(["\nObservation:"]));
+        assert_eq!(body["presence_penalty"], json!(0.5));
+        assert!(body.get("frequency_penalty").is_none());
+        assert_eq!(body["logit_bias"]["50256"], json!(-100.0));
+
+        let body = client
+            .create_request_body(&LLMProvider::Ollama, "gpt-4", &model_config, &messages)
+            .unwrap();
+        assert_eq!(body["options"]["stop"], json!(["\nObservation:"]));
+        assert!(body["options"].get("logit_bias").is_none());
+
+        let body = client
+            .create_request_body(
+                &LLMProvider::Ollama,
+                "llama3.2:latest",
+                &ModelConfig::default_for("llama3.2:latest"),
+                &messages,
+            )
+            .unwrap();
+        assert!(body.get("options").is_none());
+    }
+
+    #[test]
+    fn test_error_from_response() {
+        let error = LLMError::from_response(StatusCode::TOO_MANY_REQUESTS, Some("20"), "");
+        assert!(matches!(
+            error,
+            LLMError::RateLimited { retry_after: Some(d) } if d == Duration::from_secs(20)
+        ));
+
+        let body =
+            r#"{"error":{"message":"Incorrect API key provided","type":"invalid_request_error"}}"#;
+        let error = LLMError::from_response(StatusCode::UNAUTHORIZED, None, body);
+        assert!(matches!(error, LLMError::AuthFailed(ref m) if m == "Incorrect API key provided"));
+
+        let body = r#"{"error":"model \"llama9\" not found, try pulling it first"}"#;
+        let error = LLMError::from_response(StatusCode::NOT_FOUND, None, body);
+        assert!(matches!(error, LLMError::ModelNotFound(_)));
+
+        let body = r#"{"error":{"message":"This model's maximum context length is 8192 tokens"}}"#;
+        let error = LLMError::from_response(StatusCode::BAD_REQUEST, None, body);
+        assert!(matches!(error, LLMError::ContextTooLong(_)));
+
+        let error = LLMError::from_response(StatusCode::BAD_GATEWAY, None, "upstream down");
+        assert_eq!(
+            error.to_string(),
+            "Server returned error (502): upstream down"
+        );
+    }
+
+    #[test]
+    fn test_ollama_stream() {
+        let mut stream = OllamaStream::default();
+
+        let done =
+            stream.push_line(r#"{"message":{"role":"assistant","content":"Hel"},"done":false}"#);
+        assert!(!done);
+        let done = stream.push_line(
+            r#"{"message":{"role":"assistant","content":"lo"},"done":true,"prompt_eval_count":26,"eval_count":2}"#,
+        );
+        assert!(done);
+        assert!(stream.has_content);
+        assert_eq!(stream.content, "Hello");
+        assert_eq!(stream.usage, Some(TokenUsage::new(26, 2)));
+
+        // Characters split between chunks arrive whole
+        let mut stream = OllamaStream::default();
+        let line = "{\"message\":{\"content\":\"你好 🦀\"},\"done\":false}\n".as_bytes();
+        let split = line.iter().position(|b| *b == 0xF0).unwrap() + 2;
+        assert!(!stream.push_bytes(&line[..split]));
+        assert!(!stream.has_content);
+        assert!(!stream.push_bytes(&line[split..]));
+        assert_eq!(stream.content, "你好 🦀");
+        assert!(stream.push_bytes(b"{\"done\":true}\n"));
+    }
+
+    #[tokio::test]
+    async fn test_get_response_cancelled() {
+        let mut client = LLMClient::new(create_test_config(), LLMProvider::Ollama);
+        let cancel = CancellationToken::new();
+        cancel.cancel();
+
+        let messages = vec![json!({"role": "user", "content": "Hello"})];
+        let result = client
+            .get_response_cancellable(&messages, None, &cancel)
+            .await;
+        assert_eq!(result.unwrap_err().to_string(), "Request cancelled");
+    }
 }
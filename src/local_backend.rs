@@ -0,0 +1,634 @@
+//! Local Backend Module: In-process vector storage persisted to disk
+//!
+//! This module provides:
+//! - A `VectorBackend` that needs no external service, for RAG and semantic
+//!   memory on machines without a Qdrant container
+//! - Exact (flat) nearest neighbour search over every stored vector
+//! - One JSON file per collection, rewritten atomically after each change
+//!
+//! Key Components:
+//! - `LocalBackend`: Collections kept in memory and mirrored to a directory
+//!
+//! A flat index scans all points on each query, which stays fast for the
+//! tens of thousands of chunks a personal knowledge base produces.
+//!
+//! Author: arkSong <arksong2018@gmail.com>
+//! Version: 1.0.0
+//! License: MIT
+
+use crate::vector_backend::{
+    CollectionInfo, DistanceMetric, Payload, PayloadFilter, ScoredPoint, SearchQuery,
+    VectorBackend, VectorPoint, VectorStoreError, CONTENT_VECTOR,
+};
+use anyhow::{Context, Error, Result};
+use async_trait::async_trait;
+use log::info;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::path::{Path, PathBuf};
+use tokio::sync::RwLock;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct StoredPoint {
+    vector: Vec<f32>,
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    named_vectors: HashMap<String, Vec<f32>>,
+    payload: Payload,
+}
+
+impl StoredPoint {
+    /// The vector a query compares against, if the point has it
+    fn vector_for(&self, using: &Option<String>) -> Option<&Vec<f32>> {
+        match using.as_deref() {
+            None | Some(CONTENT_VECTOR) => Some(&self.vector),
+            Some(name) => self.named_vectors.get(name),
+        }
+    }
+
+    fn to_point(&self, id: &str) -> VectorPoint {
+        VectorPoint {
+            id: id.to_string(),
+            vector: self.vector.clone(),
+            named_vectors: self.named_vectors.clone(),
+            payload: self.payload.clone(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct LocalCollection {
+    dimension: usize,
+    distance: DistanceMetric,
+    /// Named vectors besides the main one
+    #[serde(default)]
+    vector_names: Vec<String>,
+    /// Ordered by id so that `scroll` pages are stable
+    points: BTreeMap<String, StoredPoint>,
+}
+
+pub struct LocalBackend {
+    dir: PathBuf,
+    distance: DistanceMetric,
+    collections: RwLock<HashMap<String, LocalCollection>>,
+}
+
+impl LocalBackend {
+    /// Opens the store in `dir`, creating the directory if needed and loading
+    /// every collection saved there
+    pub fn open(dir: impl AsRef<Path>) -> Result<Self> {
+        let dir = dir.as_ref().to_path_buf();
+        std::fs::create_dir_all(&dir)
+            .with_context(|| format!("Failed to create vector directory {}", dir.display()))?;
+
+        let mut collections = HashMap::new();
+        for entry in std::fs::read_dir(&dir)? {
+            let path = entry?.path();
+            if path.extension().and_then(|ext| ext.to_str()) != Some("json") {
+                continue;
+            }
+            let name = match path.file_stem().and_then(|stem| stem.to_str()) {
+                Some(name) => name.to_string(),
+                None => continue,
+            };
+            let content = std::fs::read_to_string(&path)?;
+            let collection: LocalCollection = serde_json::from_str(&content)
+                .with_context(|| format!("Failed to load collection {}", path.display()))?;
+            collections.insert(name, collection);
+        }
+
+        info!(
+            "Opened local vector store at {} ({} collections)",
+            dir.display(),
+            collections.len()
+        );
+        Ok(Self {
+            dir,
+            distance: DistanceMetric::Cosine,
+            collections: RwLock::new(collections),
+        })
+    }
+
+    /// Distance used for collections created from now on
+    pub fn with_distance(mut self, distance: DistanceMetric) -> Self {
+        self.distance = distance;
+        self
+    }
+
+    fn collection_path(&self, collection: &str) -> PathBuf {
+        self.dir.join(format!("{}.json", collection))
+    }
+
+    /// Writes the collection to a temporary file and renames it over the old
+    /// one, so a crash never leaves a half-written collection behind
+    async fn save(&self, collection: &str, data: &LocalCollection) -> Result<()> {
+        let path = self.collection_path(collection);
+        let tmp_path = path.with_extension("json.tmp");
+        let content = serde_json::to_vec(data)?;
+        tokio::fs::write(&tmp_path, content)
+            .await
+            .with_context(|| format!("Failed to write {}", tmp_path.display()))?;
+        tokio::fs::rename(&tmp_path, &path)
+            .await
+            .with_context(|| format!("Failed to write {}", path.display()))?;
+        Ok(())
+    }
+
+    /// Applies `change` to a copy of the collection and saves the copy before
+    /// it replaces the collection in memory, so a failed change or write
+    /// leaves memory matching the file on disk
+    async fn update<F>(&self, collection: &str, change: F) -> Result<()>
+    where
+        F: FnOnce(&mut LocalCollection) -> Result<()>,
+    {
+        let mut collections = self.collections.write().await;
+        let data = collections
+            .get_mut(collection)
+            .ok_or_else(|| missing_collection(collection))?;
+        let mut updated = data.clone();
+        change(&mut updated)?;
+        self.save(collection, &updated).await?;
+        *data = updated;
+        Ok(())
+    }
+}
+
+fn missing_collection(collection: &str) -> Error {
+    Error::new(VectorStoreError::CollectionError(format!(
+        "Collection {} does not exist",
+        collection
+    )))
+}
+
+/// Similarity for cosine and dot product, distance for Euclid, matching the
+/// scores Qdrant reports
+fn score(distance: DistanceMetric, a: &[f32], b: &[f32]) -> f32 {
+    match distance {
+        DistanceMetric::Cosine => {
+            let dot: f32 = a.iter().zip(b).map(|(x, y)| x * y).sum();
+            let norm_a = a.iter().map(|x| x * x).sum::<f32>().sqrt();
+            let norm_b = b.iter().map(|x| x * x).sum::<f32>().sqrt();
+            if norm_a == 0.0 || norm_b == 0.0 {
+                0.0
+            } else {
+                dot / (norm_a * norm_b)
+            }
+        }
+        DistanceMetric::Dot => a.iter().zip(b).map(|(x, y)| x * y).sum(),
+        DistanceMetric::Euclid => a
+            .iter()
+            .zip(b)
+            .map(|(x, y)| (x - y) * (x - y))
+            .sum::<f32>()
+            .sqrt(),
+    }
+}
+
+/// True when `a` ranks before `b` for the metric
+fn better(distance: DistanceMetric, a: f32, b: f32) -> bool {
+    match distance {
+        DistanceMetric::Euclid => a < b,
+        _ => a > b,
+    }
+}
+
+fn filter_accepts(filter: &Option<PayloadFilter>, payload: &Payload) -> bool {
+    match filter {
+        Some(filter) => filter.accepts(payload),
+        None => true,
+    }
+}
+
+#[async_trait]
+impl VectorBackend for LocalBackend {
+    fn name(&self) -> String {
+        format!("local ({})", self.dir.display())
+    }
+
+    async fn health(&self) -> Result<String> {
+        Ok(format!(
+            "{} collections",
+            self.collections.read().await.len()
+        ))
+    }
+
+    async fn collection_info(&self, collection: &str) -> Result<Option<CollectionInfo>> {
+        let collections = self.collections.read().await;
+        Ok(collections.get(collection).map(|data| CollectionInfo {
+            dimension: data.dimension,
+            distance: data.distance,
+            points_count: data.points.len() as u64,
+            vector_names: data.vector_names.clone(),
+        }))
+    }
+
+    async fn create_collection(&self, collection: &str, dimension: usize) -> Result<()> {
+        self.create_collection_with_vectors(collection, dimension, &[])
+            .await
+    }
+
+    async fn create_collection_with_vectors(
+        &self,
+        collection: &str,
+        dimension: usize,
+        names: &[String],
+    ) -> Result<()> {
+        let mut collections = self.collections.write().await;
+        if collections.contains_key(collection) {
+            return Err(Error::new(VectorStoreError::CollectionError(format!(
+                "Collection {} already exists",
+                collection
+            ))));
+        }
+
+        let data = LocalCollection {
+            dimension,
+            distance: self.distance,
+            vector_names: names.to_vec(),
+            points: BTreeMap::new(),
+        };
+        self.save(collection, &data).await?;
+        collections.insert(collection.to_string(), data);
+        Ok(())
+    }
+
+    async fn upsert(&self, collection: &str, points: Vec<VectorPoint>) -> Result<()> {
+        if points.is_empty() {
+            return Ok(());
+        }
+
+        self.update(collection, |data| {
+            for point in &points {
+                let mut vectors =
+                    std::iter::once(&point.vector).chain(point.named_vectors.values());
+                if let Some(vector) = vectors.find(|v| v.len() != data.dimension) {
+                    return Err(Error::new(VectorStoreError::InvalidDimension {
+                        expected: data.dimension as u64,
+                        actual: vector.len() as u64,
+                    }));
+                }
+                if let Some(name) = point
+                    .named_vectors
+                    .keys()
+                    .find(|name| !data.vector_names.contains(name))
+                {
+                    return Err(Error::new(VectorStoreError::CollectionError(format!(
+                        "Collection {} has no '{}' vector",
+                        collection, name
+                    ))));
+                }
+            }
+
+            for point in points {
+                data.points.insert(
+                    point.id,
+                    StoredPoint {
+                        vector: point.vector,
+                        named_vectors: point.named_vectors,
+                        payload: point.payload,
+                    },
+                );
+            }
+            Ok(())
+        })
+        .await
+    }
+
+    async fn search(&self, collection: &str, query: SearchQuery) -> Result<Vec<ScoredPoint>> {
+        let collections = self.collections.read().await;
+        let data = collections
+            .get(collection)
+            .ok_or_else(|| missing_collection(collection))?;
+        if query.vector.len() != data.dimension {
+            return Err(Error::new(VectorStoreError::InvalidDimension {
+                expected: data.dimension as u64,
+                actual: query.vector.len() as u64,
+            }));
+        }
+
+        if let Some(name) = &query.using {
+            if name != CONTENT_VECTOR && !data.vector_names.contains(name) {
+                return Err(Error::new(VectorStoreError::SearchError(format!(
+                    "Collection {} has no '{}' vector",
+                    collection, name
+                ))));
+            }
+        }
+
+        // Points without the queried named vector are skipped, as in Qdrant
+        let mut scored: Vec<(f32, &String, &StoredPoint)> = data
+            .points
+            .iter()
+            .filter(|(_, point)| filter_accepts(&query.filter, &point.payload))
+            .filter_map(|(id, point)| {
+                let vector = point.vector_for(&query.using)?;
+                Some((score(data.distance, &query.vector, vector), id, point))
+            })
+            .filter(|(score, _, _)| match query.score_threshold {
+                Some(threshold) => !better(data.distance, threshold, *score),
+                None => true,
+            })
+            .collect();
+        scored.sort_by(|a, b| {
+            let order = a.0.partial_cmp(&b.0).unwrap_or(std::cmp::Ordering::Equal);
+            match data.distance {
+                DistanceMetric::Euclid => order,
+                _ => order.reverse(),
+            }
+        });
+
+        Ok(scored
+            .into_iter()
+            .take(query.limit)
+            .map(|(score, id, point)| ScoredPoint {
+                id: id.clone(),
+                score,
+                payload: point.payload.clone(),
+                vector: query.with_vectors.then(|| point.vector.clone()),
+            })
+            .collect())
+    }
+
+    async fn get(&self, collection: &str, ids: &[String]) -> Result<Vec<VectorPoint>> {
+        let collections = self.collections.read().await;
+        let data = collections
+            .get(collection)
+            .ok_or_else(|| missing_collection(collection))?;
+
+        Ok(ids
+            .iter()
+            .filter_map(|id| data.points.get(id).map(|point| point.to_point(id)))
+            .collect())
+    }
+
+    async fn delete(&self, collection: &str, ids: &[String]) -> Result<()> {
+        if ids.is_empty() {
+            return Ok(());
+        }
+
+        self.update(collection, |data| {
+            for id in ids {
+                data.points.remove(id);
+            }
+            Ok(())
+        })
+        .await
+    }
+
+    async fn scroll(
+        &self,
+        collection: &str,
+        filter: Option<PayloadFilter>,
+        offset: Option<String>,
+        limit: usize,
+    ) -> Result<(Vec<VectorPoint>, Option<String>)> {
+        let collections = self.collections.read().await;
+        let data = collections
+            .get(collection)
+            .ok_or_else(|| missing_collection(collection))?;
+
+        // Like Qdrant, the offset is the id of the first point of the page
+        let mut matching = data
+            .points
+            .range(offset.unwrap_or_default()..)
+            .filter(|(_, point)| filter_accepts(&filter, &point.payload));
+        let points = matching
+            .by_ref()
+            .take(limit)
+            .map(|(id, point)| VectorPoint::new(id.clone(), Vec::new(), point.payload.clone()))
+            .collect();
+        let next = matching.next().map(|(id, _)| id.clone());
+        Ok((points, next))
+    }
+
+    async fn update_payload(&self, collection: &str, id: &str, patch: Payload) -> Result<()> {
+        self.update(collection, |data| {
+            let point = data
+                .points
+                .get_mut(id)
+                .ok_or_else(|| Error::msg(format!("No point {} in {}", id, collection)))?;
+            point.payload.extend(patch);
+            Ok(())
+        })
+        .await
+    }
+
+    async fn count(&self, collection: &str, filter: Option<PayloadFilter>) -> Result<u64> {
+        let collections = self.collections.read().await;
+        let data = collections
+            .get(collection)
+            .ok_or_else(|| missing_collection(collection))?;
+        Ok(data
+            .points
+            .values()
+            .filter(|point| filter_accepts(&filter, &point.payload))
+            .count() as u64)
+    }
+
+    /// Copies the collection file, named after the collection and the time
+    async fn create_snapshot(&self, collection: &str, dir: &Path) -> Result<PathBuf> {
+        let collections = self.collections.read().await;
+        let data = collections
+            .get(collection)
+            .ok_or_else(|| missing_collection(collection))?;
+
+        tokio::fs::create_dir_all(dir).await?;
+        let path = dir.join(format!(
+            "{}-{}.snapshot.json",
+            collection,
+            chrono::Utc::now().format("%Y%m%d-%H%M%S")
+        ));
+        tokio::fs::write(&path, serde_json::to_vec(data)?)
+            .await
+            .with_context(|| format!("Failed to write {}", path.display()))?;
+        Ok(path)
+    }
+
+    async fn restore_snapshot(&self, collection: &str, path: &Path) -> Result<()> {
+        let content = tokio::fs::read(path)
+            .await
+            .with_context(|| format!("Failed to read {}", path.display()))?;
+        let data: LocalCollection = serde_json::from_slice(&content)
+            .with_context(|| format!("{} is not a collection snapshot", path.display()))?;
+
+        let mut collections = self.collections.write().await;
+        self.save(collection, &data).await?;
+        collections.insert(collection.to_string(), data);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+    use tempfile::TempDir;
+
+    fn point(id: &str, vector: Vec<f32>, role: &str) -> VectorPoint {
+        VectorPoint::new(
+            id.to_string(),
+            vector,
+            json!({ "role": role }).as_object().unwrap().clone(),
+        )
+    }
+
+    #[tokio::test]
+    async fn test_search_and_persistence() -> Result<()> {
+        let dir = TempDir::new()?;
+        let backend = LocalBackend::open(dir.path())?;
+        backend.ensure_collection("notes", 2).await?;
+        backend
+            .upsert(
+                "notes",
+                vec![
+                    point("a", vec![1.0, 0.0], "user"),
+                    point("b", vec![0.7, 0.7], "assistant"),
+                    point("c", vec![0.0, 1.0], "user"),
+                ],
+            )
+            .await?;
+
+        let results = backend
+            .search("notes", SearchQuery::new(vec![1.0, 0.1], 2))
+            .await?;
+        let ids: Vec<&str> = results.iter().map(|r| r.id.as_str()).collect();
+        assert_eq!(ids, vec!["a", "b"]);
+
+        let results = backend
+            .search(
+                "notes",
+                SearchQuery::new(vec![1.0, 0.1], 3)
+                    .filter(PayloadFilter::new().matches("role", "user"))
+                    .score_threshold(0.5),
+            )
+            .await?;
+        assert_eq!(results.len(), 1);
+        assert_eq!(results[0].id, "a");
+
+        backend
+            .update_payload(
+                "notes",
+                "c",
+                json!({ "role": "assistant" }).as_object().unwrap().clone(),
+            )
+            .await?;
+        let user_filter = PayloadFilter::new().matches("role", "user");
+        assert_eq!(backend.count("notes", Some(user_filter)).await?, 1);
+        assert_eq!(backend.count("notes", None).await?, 3);
+
+        // Reopening reads the collection back from disk
+        backend.delete("notes", &["b".to_string()]).await?;
+        let reopened = LocalBackend::open(dir.path())?;
+        let info = reopened.collection_info("notes").await?.unwrap();
+        assert_eq!(info.dimension, 2);
+        assert_eq!(info.points_count, 2);
+        assert!(reopened.ensure_collection("notes", 3).await.is_err());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_snapshot_round_trip() -> Result<()> {
+        let dir = TempDir::new()?;
+        let backend = LocalBackend::open(dir.path().join("data"))?;
+        backend.create_collection("notes", 2).await?;
+        backend
+            .upsert("notes", vec![point("a", vec![1.0, 0.0], "user")])
+            .await?;
+
+        let snapshot = backend
+            .create_snapshot("notes", &dir.path().join("backups"))
+            .await?;
+        backend.delete("notes", &["a".to_string()]).await?;
+        backend.restore_snapshot("notes", &snapshot).await?;
+
+        let points = backend.get("notes", &["a".to_string()]).await?;
+        assert_eq!(points.len(), 1);
+        assert_eq!(points[0].vector, vec![1.0, 0.0]);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_named_vectors() -> Result<()> {
+        let dir = TempDir::new()?;
+        let backend = LocalBackend::open(dir.path())?;
+        backend
+            .create_collection_with_vectors("chunks", 2, &["title".to_string()])
+            .await?;
+        backend
+            .upsert(
+                "chunks",
+                vec![
+                    point("a", vec![1.0, 0.0], "user").with_named_vector("title", vec![0.0, 1.0]),
+                    point("b", vec![0.0, 1.0], "user").with_named_vector("title", vec![1.0, 0.0]),
+                ],
+            )
+            .await?;
+
+        let by_content = backend
+            .search("chunks", SearchQuery::new(vec![1.0, 0.0], 1))
+            .await?;
+        assert_eq!(by_content[0].id, "a");
+        let by_title = backend
+            .search("chunks", SearchQuery::new(vec![1.0, 0.0], 1).using("title"))
+            .await?;
+        assert_eq!(by_title[0].id, "b");
+
+        assert!(backend
+            .search(
+                "chunks",
+                SearchQuery::new(vec![1.0, 0.0], 1).using("summary")
+            )
+            .await
+            .is_err());
+        let info = backend.collection_info("chunks").await?.unwrap();
+        assert_eq!(info.vector_names, vec!["title".to_string()]);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_scroll_pages() -> Result<()> {
+        let dir = TempDir::new()?;
+        let backend = LocalBackend::open(dir.path())?;
+        backend.create_collection("notes", 1).await?;
+        let points = (0..5)
+            .map(|i| point(&format!("p{}", i), vec![i as f32], "user"))
+            .collect();
+        backend.upsert("notes", points).await?;
+
+        let (page, next) = backend.scroll("notes", None, None, 2).await?;
+        assert_eq!(page.len(), 2);
+        assert_eq!(next.as_deref(), Some("p2"));
+
+        let (page, next) = backend.scroll("notes", None, next, 10).await?;
+        assert_eq!(page.len(), 3);
+        assert_eq!(page[0].id, "p2");
+        assert!(next.is_none());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_failed_save_keeps_memory_unchanged() -> Result<()> {
+        let dir = TempDir::new()?;
+        let backend = LocalBackend::open(dir.path())?;
+        backend.create_collection("notes", 1).await?;
+        backend
+            .upsert("notes", vec![point("a", vec![1.0], "user")])
+            .await?;
+
+        // A directory in the way of the temporary file makes every save fail
+        std::fs::create_dir(dir.path().join("notes.json.tmp"))?;
+        assert!(backend
+            .upsert("notes", vec![point("b", vec![1.0], "user")])
+            .await
+            .is_err());
+        assert!(backend.delete("notes", &["a".to_string()]).await.is_err());
+        let patch = json!({ "role": "assistant" }).as_object().unwrap().clone();
+        assert!(backend.update_payload("notes", "a", patch).await.is_err());
+
+        let points = backend
+            .get("notes", &["a".to_string(), "b".to_string()])
+            .await?;
+        assert_eq!(points.len(), 1);
+        assert_eq!(points[0].payload["role"], "user");
+        Ok(())
+    }
+}
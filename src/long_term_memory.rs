@@ -0,0 +1,326 @@
+//! Long-Term Memory Module: Past exchanges recalled by similarity
+//!
+//! This module provides:
+//! - `LongTermMemoryConfig`, read from the `long_term_memory` section of
+//!   `config.yaml`
+//! - `LongTermMemory`: every exchange of the chat, the user's message and
+//!   the answer, embedded into a `VectorStore` collection
+//! - `LongTermMemory::recall`: the past exchanges most similar to a new
+//!   message, from any session
+//! - `recall_prompt`: the recalled exchanges as a system message
+//!
+//! The collection lives in the vector backend of the RAG index, Qdrant,
+//! local or LanceDB, next to the documents, and is embedded by the RAG
+//! embedder. An exchange is stored as the user's message with the answer
+//! and the session in its metadata, embedded as both together, so a
+//! question recalls what was answered as well as what was asked. Exchanges
+//! still sent with each message as conversation context are not recalled
+//! again.
+//!
+//! Author: arkSong <arksong2018@gmail.com>
+//! Version: 1.0.0
+//! License: MIT
+
+use anyhow::Result;
+use chrono::{TimeZone, Utc};
+use log::{debug, warn};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::HashMap;
+
+use crate::config::Configuration;
+use crate::rag_server::RagServer;
+use crate::vector_store::{Message, VectorStore};
+
+/// Settings of the long-term memory, read from the `long_term_memory`
+/// section of `config.yaml`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct LongTermMemoryConfig {
+    /// Whether exchanges are stored and recalled
+    pub enabled: bool,
+    /// Collection of the RAG vector backend the exchanges are stored in
+    pub collection: String,
+    /// Most past exchanges recalled for a message
+    pub max_recalled: usize,
+    /// Least similarity, from 0 to 1, of a recalled exchange
+    pub min_score: f32,
+}
+
+impl Default for LongTermMemoryConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            collection: "chat_memory".to_string(),
+            max_recalled: 3,
+            min_score: 0.5,
+        }
+    }
+}
+
+impl LongTermMemoryConfig {
+    /// The `long_term_memory` section of the configuration file
+    /// `config_path`, or the defaults if it cannot be read
+    pub fn from_config_file(config_path: &str) -> Self {
+        Configuration::new()
+            .load_long_term_memory_config(config_path)
+            .unwrap_or_else(|e| {
+                warn!(
+                    "Failed to load the long-term memory configuration, using defaults: {}",
+                    e
+                );
+                Self::default()
+            })
+    }
+}
+
+/// A past exchange recalled for a new message
+#[derive(Debug, Clone, PartialEq)]
+pub struct RecalledExchange {
+    pub input: String,
+    pub answer: String,
+    /// Similarity to the new message
+    pub score: f32,
+    /// When the exchange happened, in Unix seconds
+    pub timestamp: i64,
+    pub session_id: String,
+}
+
+/// Exchanges of every session, embedded for recall by similarity
+pub struct LongTermMemory {
+    store: VectorStore,
+    config: LongTermMemoryConfig,
+}
+
+impl LongTermMemory {
+    /// Opens the collection in the vector backend of `rag_server`, creating
+    /// it if needed, or returns `None` if the memory is disabled
+    pub async fn open(
+        config: LongTermMemoryConfig,
+        rag_server: &RagServer,
+    ) -> Result<Option<Self>> {
+        if !config.enabled {
+            return Ok(None);
+        }
+        let dimension = rag_server.embedding_dimension().await?;
+        let store =
+            VectorStore::with_backend(rag_server.backend(), &config.collection, dimension as u64)
+                .await?;
+        Ok(Some(Self { store, config }))
+    }
+
+    pub fn config(&self) -> &LongTermMemoryConfig {
+        &self.config
+    }
+
+    /// Stores the exchange of `input` and `answer` in the session
+    /// `session_id` and returns its id
+    pub async fn remember(
+        &self,
+        session_id: &str,
+        input: &str,
+        answer: &str,
+        rag_server: &RagServer,
+    ) -> Result<String> {
+        let embedding = rag_server
+            .generate_embedding(&exchange_text(input, answer))
+            .await?;
+        let id = uuid::Uuid::new_v4().to_string();
+        let message = Message {
+            id: id.clone(),
+            role: "user".to_string(),
+            content: input.to_string(),
+            timestamp: Utc::now().timestamp(),
+            metadata: HashMap::from([
+                ("answer".to_string(), answer.to_string()),
+                ("session_id".to_string(), session_id.to_string()),
+            ]),
+        };
+        self.store.store_message(message, embedding).await?;
+        Ok(id)
+    }
+
+    /// Deletes the exchange `id`, so it is no longer recalled
+    pub async fn forget(&self, id: &str) -> Result<()> {
+        self.store.delete_message(id).await
+    }
+
+    /// The past exchanges most similar to `input`, most similar first,
+    /// leaving out those whose message is one of the user messages in
+    /// `context`
+    pub async fn recall(
+        &self,
+        input: &str,
+        context: &[Value],
+        rag_server: &RagServer,
+    ) -> Result<Vec<RecalledExchange>> {
+        if self.config.max_recalled == 0 {
+            return Ok(Vec::new());
+        }
+        let embedding = rag_server.generate_embedding(input).await?;
+        // Fetch extra results to make up for those still in the context
+        let limit = self.config.max_recalled + context.len();
+        let results = self
+            .store
+            .search_similar(embedding, limit as u64, None)
+            .await?;
+        let in_context = |content: &str| {
+            context
+                .iter()
+                .any(|message| message["role"] == "user" && message["content"] == content)
+        };
+        let recalled: Vec<RecalledExchange> = results
+            .into_iter()
+            .filter(|(_, score, _)| *score >= self.config.min_score)
+            .filter_map(|(_, score, mut metadata)| {
+                let input = metadata.remove("content")?;
+                if in_context(&input) {
+                    return None;
+                }
+                Some(RecalledExchange {
+                    input,
+                    answer: metadata.remove("answer").unwrap_or_default(),
+                    score,
+                    timestamp: metadata
+                        .get("timestamp")
+                        .and_then(|t| t.parse().ok())
+                        .unwrap_or(0),
+                    session_id: metadata.remove("session_id").unwrap_or_default(),
+                })
+            })
+            .take(self.config.max_recalled)
+            .collect();
+        debug!("Recalled {} past exchanges", recalled.len());
+        Ok(recalled)
+    }
+}
+
+/// The text an exchange is embedded as
+fn exchange_text(input: &str, answer: &str) -> String {
+    format!("{}\n{}", input, answer)
+}
+
+/// The system message telling the LLM what it remembers from earlier
+/// conversations
+pub fn recall_prompt(exchanges: &[RecalledExchange]) -> String {
+    let mut prompt = String::from(
+        "You remember these exchanges from earlier conversations. Use them if they help with the user's message, and ignore them otherwise.\n",
+    );
+    for exchange in exchanges {
+        let date = Utc
+            .timestamp_opt(exchange.timestamp, 0)
+            .single()
+            .map(|time| time.format("%Y-%m-%d").to_string())
+            .unwrap_or_default();
+        prompt.push_str(&format!(
+            "\n[{}]\nUser: {}\nAssistant: {}\n",
+            date, exchange.input, exchange.answer
+        ));
+    }
+    prompt
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::embedder::Embedder;
+    use crate::local_backend::LocalBackend;
+    use crate::rag_server::RagConfig;
+    use serde_json::json;
+    use std::sync::Arc;
+
+    /// Embeds texts by which of a few topics they mention
+    struct TopicEmbedder;
+
+    #[async_trait::async_trait]
+    impl Embedder for TopicEmbedder {
+        fn name(&self) -> String {
+            "topics".to_string()
+        }
+
+        async fn embed(&self, texts: &[&str]) -> Result<Vec<Vec<f32>>> {
+            Ok(texts
+                .iter()
+                .map(|text| {
+                    ["cat", "rust", "paris"]
+                        .iter()
+                        .map(|topic| text.to_lowercase().matches(topic).count() as f32)
+                        .collect()
+                })
+                .collect())
+        }
+    }
+
+    #[tokio::test]
+    async fn test_remember_and_recall() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let rag_server = RagServer::with_backend(
+            RagConfig::default(),
+            Box::new(TopicEmbedder),
+            Arc::new(LocalBackend::open(temp_dir.path()).unwrap()),
+        )
+        .unwrap();
+        let memory = LongTermMemory::open(LongTermMemoryConfig::default(), &rag_server)
+            .await
+            .unwrap()
+            .unwrap();
+        memory
+            .remember("one", "My cat is called Tom", "Nice name!", &rag_server)
+            .await
+            .unwrap();
+        memory
+            .remember("one", "I write Rust", "Rust is great", &rag_server)
+            .await
+            .unwrap();
+        let paris = memory
+            .remember("two", "Flights to Paris?", "Try the train", &rag_server)
+            .await
+            .unwrap();
+
+        let recalled = memory
+            .recall("What is my cat called?", &[], &rag_server)
+            .await
+            .unwrap();
+        assert_eq!(recalled.len(), 1);
+        assert_eq!(recalled[0].input, "My cat is called Tom");
+        assert_eq!(recalled[0].answer, "Nice name!");
+        assert_eq!(recalled[0].session_id, "one");
+        let prompt = recall_prompt(&recalled);
+        assert!(prompt.contains("User: My cat is called Tom\nAssistant: Nice name!"));
+
+        // Exchanges still in the context are not recalled again
+        let context = vec![json!({ "role": "user", "content": "My cat is called Tom" })];
+        assert!(memory
+            .recall("What is my cat called?", &context, &rag_server)
+            .await
+            .unwrap()
+            .is_empty());
+
+        // Forgotten exchanges are not recalled
+        assert_eq!(
+            memory
+                .recall("Paris", &[], &rag_server)
+                .await
+                .unwrap()
+                .len(),
+            1
+        );
+        memory.forget(&paris).await.unwrap();
+        assert!(memory
+            .recall("Paris", &[], &rag_server)
+            .await
+            .unwrap()
+            .is_empty());
+
+        assert!(LongTermMemory::open(
+            LongTermMemoryConfig {
+                enabled: false,
+                ..LongTermMemoryConfig::default()
+            },
+            &rag_server
+        )
+        .await
+        .unwrap()
+        .is_none());
+    }
+}
@@ -0,0 +1,301 @@
+//! Markdown Module: Answers rendered for the terminal
+//!
+//! This module provides:
+//! - `MarkdownConfig`, read from the `markdown` section of `config.yaml`
+//! - `MarkdownRenderer`: an answer's Markdown as styled terminal text, with
+//!   headings, emphasis, lists, quotes and tables drawn by termimad and
+//!   code fences highlighted by syntect for their language
+//! - `split_code_blocks`: the fenced code blocks of a Markdown text and the
+//!   text between them
+//!
+//! Code blocks keep their fence lines, dimmed, so that code copied from the
+//! terminal is exactly what the LLM wrote. Blocks of unknown languages are
+//! shown as plain text.
+//!
+//! Author: arkSong <arksong2018@gmail.com>
+//! Version: 1.0.0
+//! License: MIT
+
+use log::{debug, warn};
+use serde::{Deserialize, Serialize};
+use syntect::easy::HighlightLines;
+use syntect::highlighting::{Theme, ThemeSet};
+use syntect::parsing::SyntaxSet;
+use syntect::util::{as_24_bit_terminal_escaped, LinesWithEndings};
+use termimad::MadSkin;
+
+use crate::config::Configuration;
+use crate::utils::{colorize, Color};
+
+/// The syntect theme code is highlighted with unless configured
+pub const DEFAULT_CODE_THEME: &str = "base16-ocean.dark";
+
+/// How answers are shown, read from the `markdown` section of
+/// `config.yaml`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct MarkdownConfig {
+    /// Whether answers are rendered, rather than printed as they are
+    pub enabled: bool,
+    /// The syntect theme code blocks are highlighted with, such as
+    /// `base16-ocean.dark`, `Solarized (light)` or `InspiredGitHub`
+    pub code_theme: String,
+}
+
+impl Default for MarkdownConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            code_theme: DEFAULT_CODE_THEME.to_string(),
+        }
+    }
+}
+
+impl MarkdownConfig {
+    /// The `markdown` section of the configuration file `config_path`, or
+    /// the defaults if it cannot be read
+    pub fn from_config_file(config_path: &str) -> Self {
+        Configuration::new()
+            .load_markdown_config(config_path)
+            .unwrap_or_else(|e| {
+                warn!(
+                    "Failed to load the markdown configuration, using defaults: {}",
+                    e
+                );
+                Self::default()
+            })
+    }
+}
+
+/// A part of a Markdown text
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Segment {
+    /// Markdown outside code blocks
+    Text(String),
+    /// A fenced code block
+    Code {
+        /// The first word of the info string, such as `rust`; may be empty
+        language: String,
+        /// The opening fence line as written
+        fence: String,
+        code: String,
+        /// The closing fence line, unless the text ended first
+        closing: Option<String>,
+    },
+}
+
+/// The fence opening a code block on `line`: its character and length
+fn opening_fence(line: &str) -> Option<(char, usize)> {
+    let indent = line.len() - line.trim_start_matches(' ').len();
+    if indent > 3 {
+        return None;
+    }
+    let rest = &line[indent..];
+    let fence_char = rest.chars().next().filter(|c| *c == '`' || *c == '~')?;
+    let length = rest.chars().take_while(|c| *c == fence_char).count();
+    if length < 3 || (fence_char == '`' && rest[length..].contains('`')) {
+        return None;
+    }
+    Some((fence_char, length))
+}
+
+/// Whether `line` closes a block opened by `length` times `fence_char`
+fn is_closing_fence(line: &str, fence_char: char, length: usize) -> bool {
+    let trimmed = line.trim();
+    let run = trimmed.chars().take_while(|c| *c == fence_char).count();
+    run >= length && run == trimmed.chars().count() && line.len() - line.trim_start().len() <= 3
+}
+
+/// The fenced code blocks of `markdown` and the text between them, in
+/// order. A block left open runs to the end of the text.
+pub fn split_code_blocks(markdown: &str) -> Vec<Segment> {
+    let mut segments = Vec::new();
+    let mut text = String::new();
+    let mut lines = markdown.lines();
+    while let Some(line) = lines.next() {
+        let (fence_char, length) = match opening_fence(line) {
+            Some(fence) => fence,
+            None => {
+                text.push_str(line);
+                text.push('\n');
+                continue;
+            }
+        };
+        if !text.is_empty() {
+            segments.push(Segment::Text(std::mem::take(&mut text)));
+        }
+        let language = line
+            .trim_start()
+            .trim_start_matches(fence_char)
+            .split_whitespace()
+            .next()
+            .unwrap_or_default()
+            .to_string();
+        let mut code = String::new();
+        let mut closing = None;
+        for line in lines.by_ref() {
+            if is_closing_fence(line, fence_char, length) {
+                closing = Some(line.to_string());
+                break;
+            }
+            code.push_str(line);
+            code.push('\n');
+        }
+        segments.push(Segment::Code {
+            language,
+            fence: line.to_string(),
+            code,
+            closing,
+        });
+    }
+    if !text.is_empty() {
+        segments.push(Segment::Text(text));
+    }
+    segments
+}
+
+/// Renders Markdown answers as styled terminal text
+pub struct MarkdownRenderer {
+    skin: MadSkin,
+    syntaxes: SyntaxSet,
+    theme: Theme,
+}
+
+impl MarkdownRenderer {
+    /// A renderer highlighting code with the theme `config` names, or the
+    /// default theme if there is no such theme
+    pub fn new(config: &MarkdownConfig) -> Self {
+        let mut themes = ThemeSet::load_defaults().themes;
+        let theme = match themes.remove(&config.code_theme) {
+            Some(theme) => theme,
+            None => {
+                warn!(
+                    "Unknown code theme {}, using {}",
+                    config.code_theme, DEFAULT_CODE_THEME
+                );
+                themes.remove(DEFAULT_CODE_THEME).unwrap_or_default()
+            }
+        };
+        Self {
+            skin: MadSkin::default(),
+            syntaxes: SyntaxSet::load_defaults_newlines(),
+            theme,
+        }
+    }
+
+    /// `markdown` as terminal text at most `width` columns wide
+    pub fn render(&self, markdown: &str, width: usize) -> String {
+        let mut rendered = String::new();
+        for segment in split_code_blocks(markdown) {
+            match segment {
+                Segment::Text(text) => {
+                    rendered.push_str(&self.skin.text(&text, Some(width)).to_string());
+                }
+                Segment::Code {
+                    language,
+                    fence,
+                    code,
+                    closing,
+                } => {
+                    rendered.push_str(&format!("{}\n", colorize(&fence, Color::Gray)));
+                    rendered.push_str(&self.highlight(&code, &language));
+                    if let Some(closing) = closing {
+                        rendered.push_str(&format!("{}\n", colorize(&closing, Color::Gray)));
+                    }
+                }
+            }
+        }
+        rendered
+    }
+
+    /// `code` highlighted as `language`, or as it is if the language is
+    /// unknown
+    fn highlight(&self, code: &str, language: &str) -> String {
+        let syntax = match self.syntaxes.find_syntax_by_token(language) {
+            Some(syntax) if !language.is_empty() => syntax,
+            _ => return code.to_string(),
+        };
+        let mut highlighter = HighlightLines::new(syntax, &self.theme);
+        let mut highlighted = String::new();
+        for line in LinesWithEndings::from(code) {
+            match highlighter.highlight_line(line, &self.syntaxes) {
+                Ok(ranges) => highlighted.push_str(&as_24_bit_terminal_escaped(&ranges, false)),
+                Err(e) => {
+                    debug!("Cannot highlight {} code: {}", language, e);
+                    highlighted.push_str(line);
+                }
+            }
+        }
+        highlighted.push_str("\x1b[0m");
+        highlighted
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_split_code_blocks() {
+        let markdown = "Use this:\n\n```rust\nfn main() {}\n```\n\nOr:\n~~~~ sh\necho ```\n~~~~\n";
+        assert_eq!(
+            split_code_blocks(markdown),
+            vec![
+                Segment::Text("Use this:\n\n".to_string()),
+                Segment::Code {
+                    language: "rust".to_string(),
+                    fence: "```rust".to_string(),
+                    code: "fn main() {}\n".to_string(),
+                    closing: Some("```".to_string()),
+                },
+                Segment::Text("\nOr:\n".to_string()),
+                Segment::Code {
+                    language: "sh".to_string(),
+                    fence: "~~~~ sh".to_string(),
+                    code: "echo ```\n".to_string(),
+                    closing: Some("~~~~".to_string()),
+                },
+            ]
+        );
+
+        // Inline code is not a fence, and an open block runs to the end
+        assert_eq!(
+            split_code_blocks("Run ```ls``` now"),
+            vec![Segment::Text("Run ```ls``` now\n".to_string())]
+        );
+        assert_eq!(
+            split_code_blocks("```\nstill code"),
+            vec![Segment::Code {
+                language: String::new(),
+                fence: "```".to_string(),
+                code: "still code\n".to_string(),
+                closing: None,
+            }]
+        );
+    }
+
+    #[test]
+    fn test_render() {
+        let renderer = MarkdownRenderer::new(&MarkdownConfig::default());
+        let rendered = renderer.render(
+            "# Title\n\nSome **bold** text.\n\n| a | b |\n|---|---|\n| 1 | 2 |\n\n```rust\nlet x = 1;\n```\n",
+            80,
+        );
+        assert!(rendered.contains("Title"));
+        assert!(rendered.contains("bold"));
+        assert!(!rendered.contains("**bold**"));
+        assert!(rendered.contains("```rust"));
+        // Highlighted code is split by color codes but keeps its text
+        assert!(rendered.contains("\x1b[38;2;"));
+        assert!(rendered.contains("let"));
+
+        // Unknown languages and themes fall back to plain text
+        let renderer = MarkdownRenderer::new(&MarkdownConfig {
+            code_theme: "no such theme".to_string(),
+            ..MarkdownConfig::default()
+        });
+        assert!(renderer
+            .render("```klingon\nqapla'\n```", 80)
+            .contains("qapla'\n"));
+    }
+}
@@ -1,24 +1,124 @@
 //! MCP Server Module: Core server functionality for the MCP-ChatBot framework
 //!
 //! This module implements the main server functionality including:
-//! - Tool registration and execution
-//! - Resource management
-//! - Prompt handling
-//! - Retry mechanisms for tool execution
-//! - Progress tracking
+//! - Typed tools: `Tool::typed` derives the input and output schemas from
+//!   Rust types and passes the handler its arguments deserialized
+//! - Tool registration and execution, including unregistering and
+//!   replacing tools while the server runs; every change bumps a counter
+//!   that `subscribe_tools_changed` watches
+//! - Resource management: resources are found by their exact pattern or,
+//!   for URI templates such as `file:///{path}`, by matching the URI, whose
+//!   parameters are passed to the handler with the other arguments
+//! - Prompt handling: prompts render their text synchronously or, like the
+//!   prompts of external servers, asynchronously
+//! - Concurrent tool execution: the registries are only locked to look a
+//!   tool or resource up, not while it runs
+//! - Retries of failed tool calls as a `RetryPolicy` says; unknown tools
+//!   and results not matching the output schema are not retried
+//! - Hooks run around every tool call (`before_tool_call` and
+//!   `after_tool_call`) for logging, access checks, redaction and metrics
+//! - Progress reporting: tools get a `ProgressSink` for `{progress, total,
+//!   message}` events, which callers show as they arrive
+//! - The `initialize` handshake: protocol version and capability negotiation
+//! - `ToolResult`: tool outcomes in one shape, optionally checked against
+//!   the tool's output schema
+//! - `ToolId`: tools keyed by `server_name/tool_name`, so that calls may
+//!   name the server when several servers offer a tool of the same name
+//!
+//! A server offers a set of `Capabilities`. Once a client has completed the
+//! handshake, methods of groups outside the negotiated set are refused.
+//! Servers used in-process without a handshake allow every method.
 //!
 //! Author: arkSong <arksong2018@gmail.com>
 //! Version: 1.0.0
 //! License: MIT
 
 use anyhow::{Error, Result};
+use jsonschema::JSONSchema;
 use log::{info, warn};
+use schemars::gen::SchemaSettings;
+use schemars::JsonSchema;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
 use serde_json::Value;
-use std::sync::Arc;
-use tokio::sync::Mutex;
-use tokio::time::{sleep, Duration};
+use std::collections::HashMap;
+use std::fmt;
+use std::future::Future;
+use std::sync::{Arc, OnceLock};
+use std::time::Instant;
+use tokio::sync::{mpsc, watch, Mutex, RwLock};
+use tokio::time::Duration;
+
+use crate::protocol::{
+    negotiate_protocol_version, Capabilities, Implementation, InitializeParams, InitializeResult,
+    PromptArgument, PromptSchema, ResourceSchema, ServerCapabilities, ToolSchema,
+    MCP_PROTOCOL_VERSION,
+};
+use crate::retry::{is_transient, RetryPolicy};
+use crate::tool_error::{ToolError, ToolErrorKind};
+use crate::uri_template::UriTemplate;
+
+/// One progress update of a running tool
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ProgressEvent {
+    /// Work done so far, in any unit that grows
+    pub progress: f64,
+    /// Work to do in the same unit, if known
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub total: Option<f64>,
+    /// What the tool is doing
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub message: Option<String>,
+}
+
+/// Where a running tool reports its progress. Every call hands one to the
+/// tool; reports are dropped unless the caller listens on the receiver of
+/// `ProgressSink::channel`.
+#[derive(Debug, Clone, Default)]
+pub struct ProgressSink {
+    sender: Option<mpsc::UnboundedSender<ProgressEvent>>,
+}
+
+impl ProgressSink {
+    /// A sink nobody listens to
+    pub fn none() -> Self {
+        Self::default()
+    }
+
+    /// A sink and the receiver of its events. The receiver ends once every
+    /// clone of the sink is dropped.
+    pub fn channel() -> (Self, mpsc::UnboundedReceiver<ProgressEvent>) {
+        let (sender, receiver) = mpsc::unbounded_channel();
+        (
+            Self {
+                sender: Some(sender),
+            },
+            receiver,
+        )
+    }
+
+    /// Whether anybody receives the reports
+    pub fn is_listening(&self) -> bool {
+        self.sender.is_some()
+    }
 
-use crate::protocol::{PromptArgument, PromptSchema, ResourceSchema, ToolSchema};
+    /// Reports `progress` out of `total`
+    pub fn report(&self, progress: f64, total: Option<f64>, message: Option<&str>) {
+        self.send(ProgressEvent {
+            progress,
+            total,
+            message: message.map(str::to_string),
+        });
+    }
+
+    /// Passes `event` on to the listener, if any
+    pub fn send(&self, event: ProgressEvent) {
+        if let Some(sender) = &self.sender {
+            // A listener that went away only misses the update
+            let _ = sender.send(event);
+        }
+    }
+}
 
 /// Represents a tool that can be registered and executed by the MCP server
 pub struct Tool {
@@ -30,10 +130,14 @@ pub struct Tool {
     pub input_schema: Value,
     /// Optional JSON schema defining the output format
     pub output_schema: Option<Value>,
+    /// The output schema compiled, or why it does not compile; compiled
+    /// at registration or else on the first check
+    output_validator: OnceLock<std::result::Result<JSONSchema, String>>,
     /// Async function that implements the tool's functionality
     handler: Box<
         dyn Fn(
                 Value,
+                ProgressSink,
             )
                 -> std::pin::Pin<Box<dyn std::future::Future<Output = Result<Value>> + Send + Sync>>
             + Send
@@ -62,10 +166,98 @@ impl Tool {
             description,
             input_schema,
             output_schema,
+            output_validator: OnceLock::new(),
+            handler: Box::new(move |args, _| handler(args)),
+        }
+    }
+
+    /// Creates a tool whose handler also gets a `ProgressSink` to report
+    /// its progress on
+    pub fn with_progress(
+        name: String,
+        description: String,
+        input_schema: Value,
+        output_schema: Option<Value>,
+        handler: Box<
+            dyn Fn(
+                    Value,
+                    ProgressSink,
+                ) -> std::pin::Pin<
+                    Box<dyn std::future::Future<Output = Result<Value>> + Send + Sync>,
+                > + Send
+                + Sync,
+        >,
+    ) -> Self {
+        Self {
+            name,
+            description,
+            input_schema,
+            output_schema,
+            output_validator: OnceLock::new(),
             handler,
         }
     }
 
+    /// Creates a tool from an async function taking its arguments as `A`
+    /// and returning `O`. The input schema is derived from `A` and the
+    /// output schema from `O`, with doc comments of fields as their
+    /// descriptions; a handler returning `Value` has no output schema.
+    /// Arguments that do not deserialize into `A` fail the call without
+    /// running the handler.
+    ///
+    /// ```ignore
+    /// /// Arguments of `greet`
+    /// #[derive(Deserialize, JsonSchema)]
+    /// struct GreetArgs {
+    ///     /// Who to greet
+    ///     name: String,
+    /// }
+    ///
+    /// let tool = Tool::typed("greet", "Greets someone", |args: GreetArgs| async move {
+    ///     Ok(format!("Hello, {}!", args.name))
+    /// });
+    /// ```
+    pub fn typed<A, O, F, Fut>(name: &str, description: &str, handler: F) -> Self
+    where
+        A: DeserializeOwned + JsonSchema,
+        O: Serialize + JsonSchema,
+        F: Fn(A) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = Result<O>> + Send + Sync + 'static,
+    {
+        Self::typed_with_progress(name, description, move |args, _| handler(args))
+    }
+
+    /// Like `typed`, for a handler that also gets a `ProgressSink` to report
+    /// its progress on
+    pub fn typed_with_progress<A, O, F, Fut>(name: &str, description: &str, handler: F) -> Self
+    where
+        A: DeserializeOwned + JsonSchema,
+        O: Serialize + JsonSchema,
+        F: Fn(A, ProgressSink) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = Result<O>> + Send + Sync + 'static,
+    {
+        let tool_name = name.to_string();
+        let output_schema = Some(schema_of::<O>())
+            .filter(|schema| !schema.as_object().is_some_and(|schema| schema.is_empty()));
+        Self::with_progress(
+            name.to_string(),
+            description.to_string(),
+            schema_of::<A>(),
+            output_schema,
+            Box::new(move |args, progress| {
+                let call = serde_json::from_value::<A>(args)
+                    .map(|args| handler(args, progress))
+                    .map_err(|e| {
+                        Error::new(ToolError::new(
+                            ToolErrorKind::InvalidArguments,
+                            format!("Invalid arguments of tool {}: {}", tool_name, e),
+                        ))
+                    });
+                Box::pin(async move { Ok(serde_json::to_value(call?.await?)?) })
+            }),
+        )
+    }
+
     /// Returns the tool's schema information
     pub fn schema(&self) -> ToolSchema {
         ToolSchema {
@@ -78,7 +270,63 @@ impl Tool {
 
     /// Executes the tool with the given arguments
     pub async fn execute(&self, arguments: &Value) -> Result<Value> {
-        (self.handler)(arguments.clone()).await
+        self.execute_with_progress(arguments, &ProgressSink::none())
+            .await
+    }
+
+    /// Executes the tool, passing its progress reports to `progress`
+    pub async fn execute_with_progress(
+        &self,
+        arguments: &Value,
+        progress: &ProgressSink,
+    ) -> Result<Value> {
+        (self.handler)(arguments.clone(), progress.clone()).await
+    }
+
+    /// Compiles the output schema, if the tool has one and it is not
+    /// compiled yet
+    fn compile_output_schema(&self) -> Option<&std::result::Result<JSONSchema, String>> {
+        let schema = self.output_schema.as_ref()?;
+        Some(
+            self.output_validator
+                .get_or_init(|| JSONSchema::compile(schema).map_err(|e| e.to_string())),
+        )
+    }
+
+    /// Checks `result` against the output schema, if the tool has one.
+    /// A schema that does not compile fails every call, without retries.
+    pub fn validate_output(&self, result: &Value) -> Result<()> {
+        let compiled = match self.compile_output_schema() {
+            Some(Ok(compiled)) => compiled,
+            Some(Err(e)) => {
+                return Err(ToolError::new(
+                    ToolErrorKind::Internal,
+                    format!("Invalid output schema of tool {}: {}", self.name, e),
+                )
+                .with_retryable(false)
+                .into())
+            }
+            None => return Ok(()),
+        };
+        if let Err(errors) = compiled.validate(result) {
+            let errors: Vec<String> = errors
+                .map(|e| match e.instance_path.to_string().as_str() {
+                    "" => e.to_string(),
+                    path => format!("{} (at {})", e, path),
+                })
+                .collect();
+            return Err(ToolError::new(
+                ToolErrorKind::InvalidOutput,
+                format!(
+                    "Result of tool {} does not match its output schema: {}",
+                    self.name,
+                    errors.join("; ")
+                ),
+            )
+            .with_data(serde_json::json!({ "errors": errors }))
+            .into());
+        }
+        Ok(())
     }
 
     /// Formats the tool information for LLM consumption
@@ -117,9 +365,35 @@ impl Tool {
     }
 }
 
+/// Compiles the output schema of `tool` ahead of its calls, warning about
+/// one that does not compile
+fn check_output_schema(id: &ToolId, tool: &Tool) {
+    if let Some(Err(e)) = tool.compile_output_schema() {
+        warn!("Invalid output schema of tool {}: {}", id, e);
+    }
+}
+
+/// The JSON schema of `T`, with subschemas inlined and without the
+/// `$schema` and `title` keys, which only add noise to tool listings
+fn schema_of<T: JsonSchema>() -> Value {
+    let generator = SchemaSettings::draft07()
+        .with(|settings| {
+            settings.inline_subschemas = true;
+            settings.meta_schema = None;
+        })
+        .into_generator();
+    let mut schema =
+        serde_json::to_value(generator.into_root_schema_for::<T>()).unwrap_or_default();
+    if let Some(schema) = schema.as_object_mut() {
+        schema.remove("title");
+    }
+    schema
+}
+
 /// Represents a resource that can be accessed by the MCP server
 pub struct Resource {
-    /// Pattern used to match resource requests
+    /// Pattern used to match resource requests, either a fixed URI or a URI
+    /// template
     pub pattern: String,
     /// Description of the resource
     pub description: String,
@@ -127,6 +401,8 @@ pub struct Resource {
     pub input_schema: Option<Value>,
     /// Optional JSON schema defining the output format
     pub output_schema: Option<Value>,
+    /// The parsed pattern, if it is a URI template
+    template: Option<UriTemplate>,
     /// Async function that implements resource access
     handler: Box<
         dyn Fn(
@@ -154,15 +430,45 @@ impl Resource {
                 + Sync,
         >,
     ) -> Self {
+        let template = if UriTemplate::is_template(&pattern) {
+            match UriTemplate::parse(&pattern) {
+                Ok(template) => Some(template),
+                Err(e) => {
+                    warn!("{}; matching the pattern as it is", e);
+                    None
+                }
+            }
+        } else {
+            None
+        };
         Self {
             pattern,
             description,
             input_schema,
             output_schema,
+            template,
             handler,
         }
     }
 
+    /// Whether the resource is addressed by a URI template
+    pub fn is_template(&self) -> bool {
+        self.template.is_some()
+    }
+
+    /// The arguments to read `uri` with, or `None` if the resource's template
+    /// does not match it. Parameters of the URI take precedence over
+    /// arguments of the same name.
+    pub fn match_uri(&self, uri: &str, arguments: &Value) -> Option<Value> {
+        let params = self.template.as_ref()?.matches(uri)?;
+        let mut merged = match arguments {
+            Value::Object(arguments) => arguments.clone(),
+            _ => serde_json::Map::new(),
+        };
+        merged.extend(params);
+        Some(Value::Object(merged))
+    }
+
     /// Returns the resource's schema information
     pub fn schema(&self) -> ResourceSchema {
         ResourceSchema {
@@ -187,8 +493,15 @@ pub struct Prompt {
     pub description: String,
     /// List of arguments the prompt accepts
     pub arguments: Vec<PromptArgument>,
-    /// Function that generates the prompt text
-    handler: Box<dyn Fn(Option<Value>) -> Result<Value> + Send + Sync>,
+    /// Async function that generates the prompt text
+    handler: Box<
+        dyn Fn(
+                Option<Value>,
+            )
+                -> std::pin::Pin<Box<dyn std::future::Future<Output = Result<Value>> + Send + Sync>>
+            + Send
+            + Sync,
+    >,
 }
 
 impl Prompt {
@@ -198,6 +511,32 @@ impl Prompt {
         description: String,
         arguments: Vec<PromptArgument>,
         handler: Box<dyn Fn(Option<Value>) -> Result<Value> + Send + Sync>,
+    ) -> Self {
+        Self::with_async(
+            name,
+            description,
+            arguments,
+            Box::new(move |args| {
+                let result = handler(args);
+                Box::pin(async move { result })
+            }),
+        )
+    }
+
+    /// Creates a prompt whose text is generated asynchronously, e.g. by
+    /// another MCP server
+    pub fn with_async(
+        name: String,
+        description: String,
+        arguments: Vec<PromptArgument>,
+        handler: Box<
+            dyn Fn(
+                    Option<Value>,
+                ) -> std::pin::Pin<
+                    Box<dyn std::future::Future<Output = Result<Value>> + Send + Sync>,
+                > + Send
+                + Sync,
+        >,
     ) -> Self {
         Self {
             name,
@@ -218,141 +557,520 @@ impl Prompt {
 
     /// Generates the prompt text with optional arguments
     pub async fn get(&self, arguments: Option<Value>) -> Result<Value> {
-        (self.handler)(arguments)
+        (self.handler)(arguments).await
+    }
+}
+
+/// Name of the server an `McpServer` registers its tools under, unless it
+/// is given one with `named`
+pub const DEFAULT_SERVER_NAME: &str = "mcp";
+
+/// Identifies a tool across servers, written `server_name/tool_name`
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct ToolId {
+    pub server: String,
+    pub tool: String,
+}
+
+impl ToolId {
+    pub fn new(server: &str, tool: &str) -> Self {
+        Self {
+            server: server.to_string(),
+            tool: tool.to_string(),
+        }
+    }
+
+    /// Splits a qualified `server/tool` name; bare names have no server
+    pub fn parse(name: &str) -> (Option<&str>, &str) {
+        match name.split_once('/') {
+            Some((server, tool)) => (Some(server), tool),
+            None => (None, name),
+        }
+    }
+}
+
+impl fmt::Display for ToolId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}/{}", self.server, self.tool)
+    }
+}
+
+/// Outcome of a tool call in the same shape whichever server produced it,
+/// for the LLM and the logs
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ToolResult {
+    /// What the tool returned, or the error message of a failed call
+    pub content: Value,
+    /// Whether the call failed
+    pub is_error: bool,
+    /// Kind and retryability of the failure of a failed call
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<ToolError>,
+    /// How long the call took, retries included
+    pub duration_ms: u64,
+}
+
+impl ToolResult {
+    /// Wraps the outcome of a call that started at `started`
+    pub fn new(outcome: Result<Value>, started: Instant) -> Self {
+        let duration_ms = started.elapsed().as_millis() as u64;
+        match outcome {
+            Ok(content) => Self {
+                content,
+                is_error: false,
+                error: None,
+                duration_ms,
+            },
+            Err(e) => Self {
+                content: Value::String(e.to_string()),
+                is_error: true,
+                error: Some(ToolError::from_error(&e)),
+                duration_ms,
+            },
+        }
     }
+
+    /// The content of a successful call, or the error of a failed one
+    pub fn into_result(self) -> Result<Value> {
+        match (self.is_error, self.error) {
+            (false, _) => Ok(self.content),
+            (true, Some(error)) => Err(error.into()),
+            (true, None) => Err(Error::msg(match self.content {
+                Value::String(message) => message,
+                content => content.to_string(),
+            })),
+        }
+    }
+}
+
+/// Hook run before every tool call with the tool's id and the arguments,
+/// which it may rewrite. An error refuses the call.
+pub type BeforeToolCall = Arc<dyn Fn(&ToolId, &mut Value) -> Result<()> + Send + Sync>;
+
+/// Hook run after every tool call with the tool's id, the arguments it ran
+/// with, the outcome, which it may rewrite, and how long the call took
+pub type AfterToolCall = Arc<dyn Fn(&ToolId, &Value, &mut Result<Value>, Duration) + Send + Sync>;
+
+/// Hooks of a server, run in the order they were added
+#[derive(Clone, Default)]
+struct ToolHooks {
+    before: Vec<BeforeToolCall>,
+    after: Vec<AfterToolCall>,
+}
+
+/// What was agreed on during the `initialize` handshake
+#[derive(Debug, Clone, PartialEq)]
+pub struct Session {
+    /// Protocol revision both sides speak
+    pub protocol_version: String,
+    /// Method groups the client may use
+    pub capabilities: Capabilities,
+    /// Name and version of the client
+    pub client_info: Implementation,
 }
 
 /// Main MCP server struct that manages tools, resources and prompts
 #[derive(Clone)]
 pub struct McpServer {
-    /// Thread-safe collection of registered tools
-    tools: Arc<Mutex<Vec<Tool>>>,
+    /// Name of the server, the first part of its tools' ids
+    name: String,
+    /// Thread-safe registry of tools by id
+    tools: Arc<RwLock<HashMap<ToolId, Arc<Tool>>>>,
     /// Thread-safe collection of registered resources
-    resources: Arc<Mutex<Vec<Resource>>>,
+    resources: Arc<RwLock<Vec<Arc<Resource>>>>,
     /// Thread-safe collection of registered prompts
-    prompts: Arc<Mutex<Vec<Prompt>>>,
+    prompts: Arc<Mutex<Vec<Arc<Prompt>>>>,
+    /// Method groups offered during the handshake
+    capabilities: Capabilities,
+    /// Whether tool results are checked against their output schema
+    validate_output: bool,
+    /// Hooks run around every tool call
+    hooks: Arc<RwLock<ToolHooks>>,
+    /// Counts the changes of the tool registry, for those watching it
+    tools_changed: Arc<watch::Sender<u64>>,
+    /// The negotiated session, once a client has initialized
+    session: Arc<Mutex<Option<Session>>>,
 }
 
 impl McpServer {
-    /// Creates a new McpServer instance
+    /// Creates a new McpServer instance offering tools, resources and prompts
     pub fn new() -> Self {
+        Self::with_capabilities(Capabilities {
+            tools: true,
+            resources: true,
+            prompts: true,
+            notifications: false,
+        })
+    }
+
+    /// Creates a server offering only the method groups of `capabilities`
+    pub fn with_capabilities(capabilities: Capabilities) -> Self {
         Self {
-            tools: Arc::new(Mutex::new(Vec::new())),
-            resources: Arc::new(Mutex::new(Vec::new())),
+            name: DEFAULT_SERVER_NAME.to_string(),
+            tools: Arc::new(RwLock::new(HashMap::new())),
+            resources: Arc::new(RwLock::new(Vec::new())),
             prompts: Arc::new(Mutex::new(Vec::new())),
+            capabilities,
+            validate_output: false,
+            hooks: Arc::new(RwLock::new(ToolHooks::default())),
+            tools_changed: Arc::new(watch::channel(0).0),
+            session: Arc::new(Mutex::new(None)),
         }
     }
 
-    /// Initializes the server
+    /// Turns on or off checking tool results against the tools' output
+    /// schemas. Results that do not match fail the call.
+    pub fn set_output_validation(&mut self, enabled: bool) {
+        self.validate_output = enabled;
+    }
+
+    /// Adds a hook run before every tool call, after the ones added before.
+    /// It may rewrite the arguments or refuse the call with an error; a
+    /// refused call is not retried.
+    pub async fn before_tool_call<F>(&mut self, hook: F)
+    where
+        F: Fn(&ToolId, &mut Value) -> Result<()> + Send + Sync + 'static,
+    {
+        self.hooks.write().await.before.push(Arc::new(hook));
+    }
+
+    /// Adds a hook run after every tool call, successful, failed or
+    /// refused, after the ones added before. It may rewrite the outcome.
+    pub async fn after_tool_call<F>(&mut self, hook: F)
+    where
+        F: Fn(&ToolId, &Value, &mut Result<Value>, Duration) + Send + Sync + 'static,
+    {
+        self.hooks.write().await.after.push(Arc::new(hook));
+    }
+
+    /// Names the server, which qualifies the ids of the tools registered
+    /// afterwards
+    pub fn named(mut self, name: &str) -> Self {
+        self.name = name.to_string();
+        self
+    }
+
+    /// Name of the server
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Id of the tool `name`, which is either bare or qualified with this
+    /// server's name. A bare name may itself contain a slash.
+    pub fn tool_id(&self, name: &str) -> ToolId {
+        match ToolId::parse(name) {
+            (Some(server), tool) if server == self.name => ToolId::new(server, tool),
+            _ => ToolId::new(&self.name, name),
+        }
+    }
+
+    /// Whether the tool `name`, bare or qualified, is registered
+    pub async fn has_tool(&self, name: &str) -> bool {
+        self.tools.read().await.contains_key(&self.tool_id(name))
+    }
+
+    /// Method groups the server offers
+    pub fn capabilities(&self) -> Capabilities {
+        self.capabilities
+    }
+
+    /// Initializes the server for in-process use: the handshake with this
+    /// crate as the client, which accepts everything the server offers
     pub async fn initialize(&mut self) -> Result<()> {
         info!("Initializing MCP server");
+        self.handshake(&InitializeParams {
+            protocol_version: MCP_PROTOCOL_VERSION.to_string(),
+            capabilities: Value::Object(Default::default()),
+            client_info: Implementation {
+                name: env!("CARGO_PKG_NAME").to_string(),
+                version: env!("CARGO_PKG_VERSION").to_string(),
+            },
+        })
+        .await?;
         Ok(())
     }
 
+    /// Answers a client's `initialize` request: agrees on the protocol
+    /// revision and records the offered capabilities as the session's
+    pub async fn handshake(&self, params: &InitializeParams) -> Result<InitializeResult> {
+        let protocol_version = negotiate_protocol_version(&params.protocol_version);
+        if protocol_version != params.protocol_version {
+            warn!(
+                "Client {} requested protocol {}, offering {}",
+                params.client_info.name, params.protocol_version, protocol_version
+            );
+        }
+        *self.session.lock().await = Some(Session {
+            protocol_version: protocol_version.to_string(),
+            capabilities: self.capabilities,
+            client_info: params.client_info.clone(),
+        });
+        Ok(InitializeResult {
+            protocol_version: protocol_version.to_string(),
+            capabilities: ServerCapabilities::from(&self.capabilities),
+            server_info: Implementation {
+                name: env!("CARGO_PKG_NAME").to_string(),
+                version: env!("CARGO_PKG_VERSION").to_string(),
+            },
+            instructions: None,
+        })
+    }
+
+    /// The negotiated session, if a client has initialized
+    pub async fn session(&self) -> Option<Session> {
+        self.session.lock().await.clone()
+    }
+
+    /// Fails if a session was negotiated without the group of `method`
+    async fn require(&self, method: &str) -> Result<()> {
+        match &*self.session.lock().await {
+            Some(session) if !session.capabilities.allows(method) => Err(Error::msg(format!(
+                "Method not supported: {} (capability not negotiated)",
+                method
+            ))),
+            _ => Ok(()),
+        }
+    }
+
     /// Cleans up server resources
     pub async fn cleanup(&mut self) -> Result<()> {
         info!("Cleaning up MCP server");
-        self.tools.lock().await.clear();
-        self.resources.lock().await.clear();
+        self.tools.write().await.clear();
+        self.resources.write().await.clear();
         self.prompts.lock().await.clear();
+        *self.session.lock().await = None;
         Ok(())
     }
 
-    /// Registers a new tool with the server
+    /// Registers a new tool with the server. A second tool of the same
+    /// name is refused, as calls could not tell them apart.
     pub async fn register_tool(&mut self, tool: Tool) -> Result<()> {
-        self.tools.lock().await.push(tool);
+        let id = ToolId::new(&self.name, &tool.name);
+        let mut tools = self.tools.write().await;
+        if tools.contains_key(&id) {
+            return Err(Error::msg(format!("Tool already registered: {}", id)));
+        }
+        check_output_schema(&id, &tool);
+        tools.insert(id, Arc::new(tool));
+        self.tools_changed.send_modify(|changes| *changes += 1);
         Ok(())
     }
 
+    /// Removes the tool `name`, bare or qualified. Calls already running
+    /// finish.
+    pub async fn unregister_tool(&mut self, name: &str) -> Result<()> {
+        let id = self.tool_id(name);
+        if self.tools.write().await.remove(&id).is_none() {
+            return Err(Error::msg(format!("Tool not found: {}", name)));
+        }
+        self.tools_changed.send_modify(|changes| *changes += 1);
+        Ok(())
+    }
+
+    /// Registers `tool` in place of the tool of the same name, or as a new
+    /// tool if there is none. Calls already running finish with the old one.
+    pub async fn replace_tool(&mut self, tool: Tool) -> Result<()> {
+        let id = ToolId::new(&self.name, &tool.name);
+        check_output_schema(&id, &tool);
+        self.tools.write().await.insert(id, Arc::new(tool));
+        self.tools_changed.send_modify(|changes| *changes += 1);
+        Ok(())
+    }
+
+    /// A receiver that is marked changed whenever a tool is registered,
+    /// replaced or unregistered after this call
+    pub fn subscribe_tools_changed(&self) -> watch::Receiver<u64> {
+        self.tools_changed.subscribe()
+    }
+
     /// Registers a new resource with the server
     pub async fn register_resource(&mut self, resource: Resource) -> Result<()> {
-        self.resources.lock().await.push(resource);
+        self.resources.write().await.push(Arc::new(resource));
         Ok(())
     }
 
     /// Registers a new prompt with the server
     pub async fn register_prompt(&mut self, prompt: Prompt) -> Result<()> {
-        self.prompts.lock().await.push(prompt);
+        self.prompts.lock().await.push(Arc::new(prompt));
         Ok(())
     }
 
     /// Lists all registered tools
     pub async fn list_tools(&self) -> Result<Vec<ToolSchema>> {
-        Ok(self.tools.lock().await.iter().map(|t| t.schema()).collect())
+        self.require("tools/list").await?;
+        let mut tools: Vec<ToolSchema> = self
+            .tools
+            .read()
+            .await
+            .values()
+            .map(|t| t.schema())
+            .collect();
+        tools.sort_by(|a, b| a.name.cmp(&b.name));
+        Ok(tools)
     }
 
-    /// Executes a tool with retry mechanism
+    /// Executes a tool with retry mechanism. Calls of the same or other
+    /// tools may run at the same time.
     pub async fn execute_tool(
-        &mut self,
+        &self,
         name: &str,
         arguments: &Value,
         retries: u32,
         delay: Duration,
     ) -> Result<Value> {
-        let mut attempt = 0;
-        while attempt < retries {
-            match self.tools.lock().await.iter().find(|t| t.name == *name) {
-                Some(tool) => {
-                    match tool.execute(arguments).await {
-                        Ok(result) => {
-                            // Handle progress information
-                            if let Some(progress) = result.get("progress") {
-                                if let Some(total) = result.get("total") {
-                                    let progress = progress.as_f64().unwrap_or(0.0);
-                                    let total = total.as_f64().unwrap_or(1.0);
-                                    let percentage = (progress / total) * 100.0;
-                                    info!("Progress: {}/{} ({:.1}%)", progress, total, percentage);
-                                }
-                            }
-                            return Ok(result);
-                        }
-                        Err(e) => {
-                            attempt += 1;
-                            if attempt < retries {
-                                warn!(
-                                    "Error executing tool. Attempt {} of {}: {}",
-                                    attempt, retries, e
-                                );
-                                sleep(delay).await;
-                            } else {
-                                return Err(e);
-                            }
-                        }
-                    }
+        self.execute_tool_with_progress(name, arguments, retries, delay, &ProgressSink::none())
+            .await
+    }
+
+    /// Executes a tool like `execute_tool`, passing its progress reports
+    /// to `progress`
+    pub async fn execute_tool_with_progress(
+        &self,
+        name: &str,
+        arguments: &Value,
+        retries: u32,
+        delay: Duration,
+        progress: &ProgressSink,
+    ) -> Result<Value> {
+        let policy = RetryPolicy::fixed(retries, delay);
+        self.execute_tool_with_policy(name, arguments, &policy, progress)
+            .await
+    }
+
+    /// Executes a tool, retrying failed attempts as `policy` says and
+    /// passing its progress reports to `progress`
+    pub async fn execute_tool_with_policy(
+        &self,
+        name: &str,
+        arguments: &Value,
+        policy: &RetryPolicy,
+        progress: &ProgressSink,
+    ) -> Result<Value> {
+        self.require("tools/call").await?;
+        // Only the lookup holds the lock, so long-running tools do not
+        // block other calls
+        let id = self.tool_id(name);
+        let tool = match self.tools.read().await.get(&id) {
+            Some(tool) => Arc::clone(tool),
+            None => {
+                return Err(ToolError::new(
+                    ToolErrorKind::NotFound,
+                    format!("Tool not found: {}", name),
+                )
+                .into())
+            }
+        };
+        let hooks = self.hooks.read().await.clone();
+
+        let started = Instant::now();
+        let mut arguments = arguments.clone();
+        let mut outcome = match hooks
+            .before
+            .iter()
+            .try_for_each(|hook| hook(&id, &mut arguments))
+        {
+            Ok(()) => self.run_tool(&tool, &arguments, policy, progress).await,
+            Err(e) => Err(e),
+        };
+        for hook in &hooks.after {
+            hook(&id, &arguments, &mut outcome, started.elapsed());
+        }
+        outcome
+    }
+
+    /// Runs `tool` until it succeeds or `policy` gives up
+    async fn run_tool(
+        &self,
+        tool: &Tool,
+        arguments: &Value,
+        policy: &RetryPolicy,
+        progress: &ProgressSink,
+    ) -> Result<Value> {
+        let what = format!("Tool {}", tool.name);
+        let result = policy
+            .run(&what, is_transient, || async move {
+                let result = tool.execute_with_progress(arguments, progress).await?;
+                // A wrong shape is not retryable, so it is not retried
+                if self.validate_output {
+                    tool.validate_output(&result)?;
                 }
-                None => return Err(Error::msg(format!("Tool not found: {}", name))),
+                Ok(result)
+            })
+            .await?;
+        // Progress in the result is passed on as a last report
+        if let Some(done) = result.get("progress").and_then(Value::as_f64) {
+            let total = result.get("total").and_then(Value::as_f64);
+            if let Some(total) = total {
+                let percentage = (done / total) * 100.0;
+                info!("Progress: {}/{} ({:.1}%)", done, total, percentage);
             }
+            progress.report(done, total, None);
         }
-        Err(Error::msg("Max retries reached"))
+        Ok(result)
+    }
+
+    /// Executes a tool like `execute_tool_with_progress`, reporting the
+    /// outcome and its duration as a `ToolResult`
+    pub async fn call_tool(
+        &self,
+        name: &str,
+        arguments: &Value,
+        retries: u32,
+        delay: Duration,
+        progress: &ProgressSink,
+    ) -> ToolResult {
+        let started = Instant::now();
+        let outcome = self
+            .execute_tool_with_progress(name, arguments, retries, delay, progress)
+            .await;
+        ToolResult::new(outcome, started)
     }
 
     /// Lists all registered resources
     pub async fn list_resources(&self) -> Result<Vec<ResourceSchema>> {
+        self.require("resources/list").await?;
         Ok(self
             .resources
-            .lock()
+            .read()
             .await
             .iter()
             .map(|r| r.schema())
             .collect())
     }
 
-    /// Reads from a resource with the given pattern and arguments
-    pub async fn read_resource(&mut self, pattern: &str, arguments: &Value) -> Result<Value> {
-        match self
-            .resources
-            .lock()
-            .await
-            .iter()
-            .find(|r| r.pattern == *pattern)
-        {
-            Some(resource) => resource.read(arguments).await,
-            None => Err(Error::msg(format!("Resource not found: {}", pattern))),
+    /// Reads from the resource with the given pattern, or from the first
+    /// resource whose URI template matches it, with the given arguments
+    pub async fn read_resource(&self, pattern: &str, arguments: &Value) -> Result<Value> {
+        self.require("resources/read").await?;
+        let (resource, arguments) = match self.find_resource(pattern, arguments).await {
+            Some(found) => found,
+            None => return Err(Error::msg(format!("Resource not found: {}", pattern))),
+        };
+        resource.read(&arguments).await
+    }
+
+    /// Whether `uri` names a resource, by its pattern or a matching template
+    pub async fn has_resource(&self, uri: &str) -> bool {
+        self.find_resource(uri, &Value::Null).await.is_some()
+    }
+
+    /// The resource `uri` names and the arguments to read it with. Exact
+    /// patterns are preferred over templates.
+    async fn find_resource(&self, uri: &str, arguments: &Value) -> Option<(Arc<Resource>, Value)> {
+        let resources = self.resources.read().await;
+        if let Some(resource) = resources.iter().find(|r| r.pattern == *uri) {
+            return Some((Arc::clone(resource), arguments.clone()));
         }
+        resources.iter().find_map(|resource| {
+            resource
+                .match_uri(uri, arguments)
+                .map(|arguments| (Arc::clone(resource), arguments))
+        })
     }
 
     /// Lists all registered prompts
     pub async fn list_prompts(&self) -> Result<Vec<PromptSchema>> {
+        self.require("prompts/list").await?;
         Ok(self
             .prompts
             .lock()
@@ -364,18 +1082,22 @@ impl McpServer {
 
     /// Gets a prompt with optional arguments
     pub async fn get_prompt(&mut self, name: &str, arguments: Option<Value>) -> Result<Value> {
-        match self.prompts.lock().await.iter().find(|p| p.name == *name) {
-            Some(prompt) => prompt.get(arguments).await,
-            None => Err(Error::msg(format!("Prompt not found: {}", name))),
-        }
+        self.require("prompts/get").await?;
+        let prompt = match self.prompts.lock().await.iter().find(|p| p.name == *name) {
+            Some(prompt) => Arc::clone(prompt),
+            None => return Err(Error::msg(format!("Prompt not found: {}", name))),
+        };
+        prompt.get(arguments).await
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::retry::permanent;
     use serde_json::json;
     use std::time::Duration;
+    use tokio::time::sleep;
 
     async fn create_test_tool() -> Tool {
         Tool::new(
@@ -470,6 +1192,516 @@ mod tests {
         assert!(server.cleanup().await.is_ok());
     }
 
+    #[tokio::test]
+    async fn test_capability_negotiation() {
+        let mut server = McpServer::with_capabilities(Capabilities {
+            tools: true,
+            ..Capabilities::default()
+        });
+        server
+            .register_tool(create_test_tool().await)
+            .await
+            .unwrap();
+        server
+            .register_prompt(create_test_prompt().await)
+            .await
+            .unwrap();
+
+        // Without a handshake every method is allowed
+        assert!(server.session().await.is_none());
+        assert_eq!(server.list_prompts().await.unwrap().len(), 1);
+
+        let result = server
+            .handshake(&InitializeParams {
+                protocol_version: "1999-01-01".to_string(),
+                capabilities: json!({}),
+                client_info: Implementation {
+                    name: "test".to_string(),
+                    version: "1.0".to_string(),
+                },
+            })
+            .await
+            .unwrap();
+        assert_eq!(result.protocol_version, MCP_PROTOCOL_VERSION);
+        assert!(result.capabilities.tools.is_some());
+        assert!(result.capabilities.prompts.is_none());
+        let session = server.session().await.unwrap();
+        assert_eq!(session.client_info.name, "test");
+        assert!(!session.capabilities.prompts);
+
+        assert_eq!(server.list_tools().await.unwrap().len(), 1);
+        let error = server.list_prompts().await.unwrap_err();
+        assert!(error.to_string().contains("prompts/list"));
+        assert!(server.get_prompt("test_prompt", None).await.is_err());
+        assert!(server.list_resources().await.is_err());
+
+        server.cleanup().await.unwrap();
+        assert!(server.session().await.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_tool_result_validation() {
+        let mut server = McpServer::new();
+        let tool = Tool::new(
+            "echo".to_string(),
+            "Returns its arguments".to_string(),
+            json!({ "type": "object" }),
+            Some(json!({
+                "type": "object",
+                "properties": {
+                    "result": { "type": "string" }
+                },
+                "required": ["result"]
+            })),
+            Box::new(|args| Box::pin(async move { Ok(args) })),
+        );
+        server.register_tool(tool).await.unwrap();
+        let delay = Duration::from_millis(10);
+        let progress = ProgressSink::none();
+
+        // Off by default
+        let result = server
+            .call_tool("echo", &json!({ "result": 1 }), 1, delay, &progress)
+            .await;
+        assert!(!result.is_error);
+        assert_eq!(result.content, json!({ "result": 1 }));
+
+        server.set_output_validation(true);
+        let result = server
+            .call_tool("echo", &json!({ "result": "ok" }), 1, delay, &progress)
+            .await;
+        assert_eq!(
+            result.clone().into_result().unwrap(),
+            json!({ "result": "ok" })
+        );
+        let result = server
+            .call_tool("echo", &json!({ "result": 1 }), 1, delay, &progress)
+            .await;
+        assert!(result.is_error);
+        let message = result.content.as_str().unwrap();
+        assert!(message.contains("does not match its output schema"));
+        assert!(message.contains("/result"));
+        let error = result.error.clone().unwrap();
+        assert_eq!(error.kind, ToolErrorKind::InvalidOutput);
+        assert!(!error.retryable);
+        let error = result.into_result().unwrap_err();
+        assert_eq!(
+            error.downcast_ref::<ToolError>().unwrap().kind,
+            ToolErrorKind::InvalidOutput
+        );
+        assert!(server
+            .call_tool("echo", &json!({}), 1, delay, &progress)
+            .await
+            .into_result()
+            .is_err());
+
+        let result = server
+            .call_tool("missing", &json!({}), 1, delay, &progress)
+            .await;
+        assert_eq!(result.content, json!("Tool not found: missing"));
+        assert_eq!(
+            serde_json::to_value(&result).unwrap()["is_error"],
+            json!(true)
+        );
+        assert_eq!(
+            serde_json::to_value(&result).unwrap()["error"],
+            json!({
+                "kind": "not_found",
+                "message": "Tool not found: missing",
+                "retryable": false
+            })
+        );
+
+        // A schema that does not compile fails the call without retries
+        let tool = Tool::new(
+            "broken".to_string(),
+            "Has a broken output schema".to_string(),
+            json!({ "type": "object" }),
+            Some(json!({ "type": 5 })),
+            Box::new(|args| Box::pin(async move { Ok(args) })),
+        );
+        server.register_tool(tool).await.unwrap();
+        let result = server
+            .call_tool("broken", &json!({}), 3, delay, &progress)
+            .await;
+        let error = result.error.unwrap();
+        assert!(error
+            .message
+            .contains("Invalid output schema of tool broken"));
+        assert!(!error.retryable);
+    }
+
+    #[tokio::test]
+    async fn test_tool_ids() {
+        let mut server = McpServer::new().named("files");
+        assert_eq!(server.name(), "files");
+        server
+            .register_tool(create_test_tool().await)
+            .await
+            .unwrap();
+
+        // A second tool of the same name is refused
+        let error = server
+            .register_tool(create_test_tool().await)
+            .await
+            .unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "Tool already registered: files/test_tool"
+        );
+        assert_eq!(server.list_tools().await.unwrap().len(), 1);
+
+        assert_eq!(
+            server.tool_id("files/test_tool"),
+            ToolId::new("files", "test_tool")
+        );
+        assert_eq!(
+            server.tool_id("other/test_tool").to_string(),
+            "files/other/test_tool"
+        );
+        assert!(server.has_tool("test_tool").await);
+        assert!(server.has_tool("files/test_tool").await);
+        assert!(!server.has_tool("other/test_tool").await);
+
+        let delay = Duration::from_millis(10);
+        let result = server
+            .execute_tool("files/test_tool", &json!({ "input": "x" }), 1, delay)
+            .await
+            .unwrap();
+        assert_eq!(result["result"], "Processed: x");
+        assert!(server
+            .execute_tool("other/test_tool", &json!({ "input": "x" }), 1, delay)
+            .await
+            .is_err());
+        assert_eq!(ToolId::parse("a/b/c"), (Some("a"), "b/c"));
+        assert_eq!(ToolId::parse("b"), (None, "b"));
+    }
+
+    #[tokio::test]
+    async fn test_typed_tools() {
+        #[derive(Deserialize, JsonSchema)]
+        struct RepeatArgs {
+            /// Text to repeat
+            text: String,
+            /// How often, once if left out
+            times: Option<usize>,
+        }
+
+        #[derive(Serialize, JsonSchema)]
+        struct Repeated {
+            /// The repeated text
+            result: String,
+        }
+
+        let tool = Tool::typed("repeat", "Repeats text", |args: RepeatArgs| async move {
+            Ok(Repeated {
+                result: args.text.repeat(args.times.unwrap_or(1)),
+            })
+        });
+        assert_eq!(tool.input_schema["type"], "object");
+        assert_eq!(tool.input_schema["required"], json!(["text"]));
+        assert_eq!(
+            tool.input_schema["properties"]["text"]["description"],
+            "Text to repeat"
+        );
+        assert!(tool.input_schema.get("title").is_none());
+        assert_eq!(
+            tool.output_schema.as_ref().unwrap()["properties"]["result"]["type"],
+            "string"
+        );
+        assert!(tool
+            .format_for_llm()
+            .contains("- text: Text to repeat (required)"));
+
+        let result = tool
+            .execute(&json!({ "text": "ab", "times": 3 }))
+            .await
+            .unwrap();
+        assert_eq!(result, json!({ "result": "ababab" }));
+        tool.validate_output(&result).unwrap();
+
+        // Arguments of the wrong shape never reach the handler
+        let error = tool.execute(&json!({ "times": 2 })).await.unwrap_err();
+        assert!(!is_transient(&error));
+        assert!(error.to_string().contains("missing field `text`"));
+
+        // Results of type `Value` have no output schema
+        let tool = Tool::typed("echo", "Echoes", |args: Value| async move { Ok(args) });
+        assert!(tool.output_schema.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_progress_reporting() {
+        let mut server = McpServer::new();
+        let tool = Tool::with_progress(
+            "count".to_string(),
+            "Counts to three".to_string(),
+            json!({ "type": "object" }),
+            None,
+            Box::new(|_, progress| {
+                Box::pin(async move {
+                    for i in 1..=3 {
+                        progress.report(i as f64, Some(3.0), Some(&format!("Step {}", i)));
+                    }
+                    Ok(json!({ "counted": 3, "progress": 4, "total": 4 }))
+                })
+            }),
+        );
+        server.register_tool(tool).await.unwrap();
+
+        let (progress, mut events) = ProgressSink::channel();
+        let result = server
+            .call_tool("count", &json!({}), 1, Duration::ZERO, &progress)
+            .await;
+        assert_eq!(result.content["counted"], 3);
+        drop(progress);
+
+        let mut received = Vec::new();
+        while let Some(event) = events.recv().await {
+            received.push(event);
+        }
+        assert_eq!(received.len(), 4);
+        assert_eq!(received[0].message.as_deref(), Some("Step 1"));
+        assert_eq!(received[2].progress, 3.0);
+        // Progress in the result is reported last
+        assert_eq!(
+            serde_json::to_value(&received[3]).unwrap(),
+            json!({ "progress": 4.0, "total": 4.0 })
+        );
+
+        // Nobody listening is fine too
+        let result = server
+            .call_tool(
+                "count",
+                &json!({}),
+                1,
+                Duration::ZERO,
+                &ProgressSink::none(),
+            )
+            .await;
+        assert!(!result.is_error);
+    }
+
+    #[tokio::test]
+    async fn test_dynamic_tools() {
+        let mut server = McpServer::new().named("math");
+        let mut changes = server.subscribe_tools_changed();
+        let constant = |value: i64| {
+            Tool::new(
+                "answer".to_string(),
+                format!("Returns {}", value),
+                json!({ "type": "object" }),
+                None,
+                Box::new(move |_| Box::pin(async move { Ok(json!(value)) })),
+            )
+        };
+        let delay = Duration::from_millis(10);
+
+        server.register_tool(constant(41)).await.unwrap();
+        assert!(changes.has_changed().unwrap());
+        changes.borrow_and_update();
+        assert_eq!(
+            server
+                .execute_tool("answer", &json!({}), 1, delay)
+                .await
+                .unwrap(),
+            json!(41)
+        );
+
+        server.replace_tool(constant(42)).await.unwrap();
+        assert!(changes.has_changed().unwrap());
+        changes.borrow_and_update();
+        let tools = server.list_tools().await.unwrap();
+        assert_eq!(tools.len(), 1);
+        assert_eq!(tools[0].description, "Returns 42");
+        assert_eq!(
+            server
+                .execute_tool("answer", &json!({}), 1, delay)
+                .await
+                .unwrap(),
+            json!(42)
+        );
+
+        server.unregister_tool("math/answer").await.unwrap();
+        assert!(changes.has_changed().unwrap());
+        assert!(server.list_tools().await.unwrap().is_empty());
+        assert!(server
+            .execute_tool("answer", &json!({}), 1, delay)
+            .await
+            .is_err());
+        assert!(server.unregister_tool("answer").await.is_err());
+        assert_eq!(*changes.borrow_and_update(), 3);
+    }
+
+    #[tokio::test]
+    async fn test_tool_hooks() {
+        let mut server = McpServer::new().named("vault");
+        let tool = Tool::new(
+            "login".to_string(),
+            "Returns its arguments".to_string(),
+            json!({ "type": "object" }),
+            None,
+            Box::new(|args| Box::pin(async move { Ok(args) })),
+        );
+        server.register_tool(tool).await.unwrap();
+
+        // Secrets are redacted and anonymous calls refused
+        server
+            .before_tool_call(|_, args| {
+                if args.get("user").is_none() {
+                    return Err(Error::msg("Not allowed"));
+                }
+                if args.get("password").is_some() {
+                    args["password"] = json!("***");
+                }
+                Ok(())
+            })
+            .await;
+        let calls = Arc::new(std::sync::Mutex::new(Vec::new()));
+        let recorded = Arc::clone(&calls);
+        server
+            .after_tool_call(move |id, args, outcome, _| {
+                recorded
+                    .lock()
+                    .unwrap()
+                    .push((id.to_string(), args.clone(), outcome.is_ok()));
+                if let Ok(result) = outcome {
+                    result["checked"] = json!(true);
+                }
+            })
+            .await;
+
+        let delay = Duration::from_millis(10);
+        let result = server
+            .execute_tool(
+                "login",
+                &json!({ "user": "ann", "password": "x" }),
+                1,
+                delay,
+            )
+            .await
+            .unwrap();
+        assert_eq!(
+            result,
+            json!({ "user": "ann", "password": "***", "checked": true })
+        );
+        let error = server
+            .execute_tool("login", &json!({}), 3, delay)
+            .await
+            .unwrap_err();
+        assert_eq!(error.to_string(), "Not allowed");
+
+        let calls = calls.lock().unwrap();
+        assert_eq!(calls.len(), 2);
+        assert_eq!(calls[0].0, "vault/login");
+        assert_eq!(calls[0].1["password"], "***");
+        assert!(calls[0].2);
+        assert!(!calls[1].2);
+    }
+
+    #[tokio::test]
+    async fn test_retry_policy() {
+        use std::sync::atomic::{AtomicUsize, Ordering};
+
+        let mut server = McpServer::new();
+        let calls = Arc::new(AtomicUsize::new(0));
+        let counter = Arc::clone(&calls);
+        let tool = Tool::new(
+            "flaky".to_string(),
+            "Fails twice, then echoes the name".to_string(),
+            json!({ "type": "object" }),
+            None,
+            Box::new(move |args| {
+                let attempt = counter.fetch_add(1, Ordering::SeqCst);
+                Box::pin(async move {
+                    match args.get("name") {
+                        None => Err(permanent("Missing argument name")),
+                        Some(_) if attempt < 2 => Err(Error::new(ToolError::new(
+                            ToolErrorKind::Unavailable,
+                            "Connection reset",
+                        ))),
+                        Some(name) => Ok(name.clone()),
+                    }
+                })
+            }),
+        );
+        server.register_tool(tool).await.unwrap();
+
+        let policy = RetryPolicy::fixed(3, Duration::ZERO);
+        let progress = ProgressSink::none();
+        let result = server
+            .execute_tool_with_policy("flaky", &json!({ "name": "x" }), &policy, &progress)
+            .await
+            .unwrap();
+        assert_eq!(result, "x");
+        assert_eq!(calls.load(Ordering::SeqCst), 3);
+
+        // Permanent errors and unknown tools are not retried
+        calls.store(0, Ordering::SeqCst);
+        let error = server
+            .execute_tool_with_policy("flaky", &json!({}), &policy, &progress)
+            .await
+            .unwrap_err();
+        assert_eq!(error.to_string(), "Missing argument name");
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+        let error = server
+            .execute_tool_with_policy("missing", &json!({}), &policy, &progress)
+            .await
+            .unwrap_err();
+        assert!(!is_transient(&error));
+
+        // A single attempt gives up on the first transient error
+        calls.store(0, Ordering::SeqCst);
+        let error = server
+            .execute_tool_with_policy(
+                "flaky",
+                &json!({ "name": "x" }),
+                &RetryPolicy::none(),
+                &progress,
+            )
+            .await
+            .unwrap_err();
+        assert_eq!(error.to_string(), "Connection reset");
+    }
+
+    /// Two calls must be running at the same time for either to finish
+    #[tokio::test]
+    async fn test_concurrent_tool_execution() {
+        let mut server = McpServer::new();
+        let running = Arc::new(std::sync::atomic::AtomicUsize::new(0));
+        for name in ["fetch", "query"] {
+            let running = Arc::clone(&running);
+            let tool = Tool::new(
+                name.to_string(),
+                "Waits for another call".to_string(),
+                json!({ "type": "object" }),
+                None,
+                Box::new(move |_| {
+                    let running = Arc::clone(&running);
+                    Box::pin(async move {
+                        running.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+                        for _ in 0..100 {
+                            if running.load(std::sync::atomic::Ordering::SeqCst) == 2 {
+                                return Ok(json!({ "overlapped": true }));
+                            }
+                            sleep(Duration::from_millis(10)).await;
+                        }
+                        Err(Error::msg("The other call never started"))
+                    })
+                }),
+            );
+            server.register_tool(tool).await.unwrap();
+        }
+
+        let delay = Duration::from_millis(10);
+        let (fetch, query) = tokio::join!(
+            server.execute_tool("fetch", &json!({}), 1, delay),
+            server.execute_tool("query", &json!({}), 1, delay)
+        );
+        assert_eq!(fetch.unwrap()["overlapped"], true);
+        assert_eq!(query.unwrap()["overlapped"], true);
+    }
+
     #[tokio::test]
     async fn test_tool_registration_and_execution() {
         let mut server = McpServer::new();
@@ -517,6 +1749,38 @@ mod tests {
         assert_eq!(result["data"], "Resource data for: test");
     }
 
+    #[tokio::test]
+    async fn test_resource_templates() {
+        let mut server = McpServer::new();
+        let resource = Resource::new(
+            "db://tables/{name}".to_string(),
+            "A table".to_string(),
+            None,
+            None,
+            Box::new(|args| Box::pin(async move { Ok(args) })),
+        );
+        assert!(resource.is_template());
+        server.register_resource(resource).await.unwrap();
+        server
+            .register_resource(create_test_resource().await)
+            .await
+            .unwrap();
+
+        // Parameters of the URI win over arguments of the same name
+        let result = server
+            .read_resource("db://tables/users", &json!({ "name": "x", "limit": 5 }))
+            .await
+            .unwrap();
+        assert_eq!(result, json!({ "name": "users", "limit": 5 }));
+        assert!(server.has_resource("db://tables/users").await);
+        assert!(server.has_resource("test_resource").await);
+        assert!(!server.has_resource("db://tables/").await);
+        assert!(server
+            .read_resource("db://views/users", &json!({}))
+            .await
+            .is_err());
+    }
+
     #[tokio::test]
     async fn test_prompt_registration_and_generation() {
         let mut server = McpServer::new();
@@ -536,6 +1800,25 @@ mod tests {
             .await
             .unwrap();
         assert_eq!(result["text"], "Test prompt with context: test context");
+
+        // Prompts may also be rendered asynchronously
+        let prompt = Prompt::with_async(
+            "async_prompt".to_string(),
+            "An async prompt".to_string(),
+            Vec::new(),
+            Box::new(|_| {
+                Box::pin(async {
+                    sleep(Duration::from_millis(1)).await;
+                    Ok(json!("rendered later"))
+                })
+            }),
+        );
+        server.register_prompt(prompt).await.unwrap();
+        assert_eq!(
+            server.get_prompt("async_prompt", None).await.unwrap(),
+            "rendered later"
+        );
+        assert!(server.get_prompt("missing", None).await.is_err());
     }
 
     #[tokio::test]
@@ -563,7 +1846,10 @@ mod tests {
                     let mut count = attempts.lock().await;
                     *count += 1;
                     if *count < 3 {
-                        Err(Error::msg("Temporary failure"))
+                        Err(Error::new(ToolError::new(
+                            ToolErrorKind::Unavailable,
+                            "Temporary failure",
+                        )))
                     } else {
                         Ok(json!({ "result": "success" }))
                     }
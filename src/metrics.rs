@@ -0,0 +1,438 @@
+//! Metrics Module: Prometheus metrics of tool calls and LLM usage
+//!
+//! This module provides:
+//! - `Metrics`: calls, errors and a latency histogram per tool, and
+//!   requests and tokens per LLM provider
+//! - Rendering in the Prometheus text exposition format
+//! - `MetricsConfig`, read from the `metrics` section of `config.yaml`
+//! - An optional HTTP endpoint answering `GET /metrics`
+//!
+//! Tool calls are counted by an `after_tool_call` hook of each server, so
+//! calls from the chat and from MCP clients of `serve --stdio` alike are
+//! measured. The endpoint is off by default; when enabled it listens on
+//! `listen`, which should stay on localhost unless the network is trusted,
+//! as the metrics name the tools and providers in use.
+//!
+//! Author: arkSong <arksong2018@gmail.com>
+//! Version: 1.0.0
+//! License: MIT
+
+use anyhow::{Error, Result};
+use log::{debug, info, warn};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fmt::Write as _;
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::task::JoinHandle;
+
+use crate::config::Configuration;
+use crate::mcp_server::{McpServer, ToolId};
+use crate::usage::TokenUsage;
+
+/// Upper bounds in seconds of the tool latency histogram's buckets
+const LATENCY_BUCKETS: [f64; 12] = [
+    0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0, 30.0,
+];
+
+/// Longest request head the endpoint reads
+const MAX_REQUEST_SIZE: usize = 8192;
+
+/// Settings of the metrics endpoint, read from the `metrics` section of
+/// `config.yaml`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct MetricsConfig {
+    /// Whether `/metrics` is served over HTTP
+    pub enabled: bool,
+    /// Address the endpoint listens on
+    pub listen: String,
+}
+
+impl Default for MetricsConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            listen: "127.0.0.1:9898".to_string(),
+        }
+    }
+}
+
+/// Calls of one tool
+#[derive(Debug, Clone, Default)]
+struct ToolMetrics {
+    calls: u64,
+    errors: u64,
+    /// Calls per latency bucket, not cumulative
+    buckets: [u64; LATENCY_BUCKETS.len()],
+    seconds: f64,
+}
+
+/// Requests to one LLM provider
+#[derive(Debug, Clone, Default)]
+struct ProviderMetrics {
+    requests: u64,
+    usage: TokenUsage,
+}
+
+/// Counters of a running chatbot or MCP server, shared by its servers
+#[derive(Debug, Default)]
+pub struct Metrics {
+    tools: Mutex<BTreeMap<(String, String), ToolMetrics>>,
+    providers: Mutex<BTreeMap<String, ProviderMetrics>>,
+}
+
+impl Metrics {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Metrics set up as the `metrics` section of `config_path` says,
+    /// serving them over HTTP if enabled. A failure to load the section or
+    /// to bind the endpoint is logged, and the metrics are still collected.
+    pub async fn from_config_file(config_path: &str) -> Arc<Self> {
+        let metrics = Arc::new(Self::new());
+        let config = Configuration::new()
+            .load_metrics_config(config_path)
+            .unwrap_or_else(|e| {
+                warn!(
+                    "Failed to load the metrics configuration, using defaults: {}",
+                    e
+                );
+                MetricsConfig::default()
+            });
+        if config.enabled {
+            if let Err(e) = serve(Arc::clone(&metrics), &config.listen).await {
+                warn!("Metrics are not served: {}", e);
+            }
+        }
+        metrics
+    }
+
+    /// Counts a call of `tool` that took `elapsed`
+    pub fn record_tool_call(&self, tool: &ToolId, success: bool, elapsed: Duration) {
+        let mut tools = self.tools.lock().unwrap();
+        let metrics = tools
+            .entry((tool.server.clone(), tool.tool.clone()))
+            .or_default();
+        metrics.calls += 1;
+        if !success {
+            metrics.errors += 1;
+        }
+        let seconds = elapsed.as_secs_f64();
+        metrics.seconds += seconds;
+        if let Some(bucket) = LATENCY_BUCKETS.iter().position(|bound| seconds <= *bound) {
+            metrics.buckets[bucket] += 1;
+        }
+    }
+
+    /// Counts a request to `provider` and the tokens it used
+    pub fn record_llm_usage(&self, provider: &str, usage: &TokenUsage) {
+        let mut providers = self.providers.lock().unwrap();
+        let metrics = providers.entry(provider.to_string()).or_default();
+        metrics.requests += 1;
+        metrics.usage.add(usage);
+    }
+
+    /// Counts every call of the tools of `mcp_server` from now on
+    pub async fn attach(self: &Arc<Self>, mcp_server: &mut McpServer) {
+        let metrics = Arc::clone(self);
+        mcp_server
+            .after_tool_call(move |tool, _arguments, outcome, elapsed| {
+                metrics.record_tool_call(tool, outcome.is_ok(), elapsed);
+            })
+            .await;
+    }
+
+    /// The metrics in the Prometheus text exposition format
+    pub fn render(&self) -> String {
+        let mut out = String::new();
+        let tools = self.tools.lock().unwrap().clone();
+        let providers = self.providers.lock().unwrap().clone();
+
+        let label = |(server, tool): &(String, String)| {
+            format!("server=\"{}\",tool=\"{}\"", escape(server), escape(tool))
+        };
+        header(&mut out, "mcp_tool_calls_total", "counter", "Tool calls");
+        for (id, metrics) in &tools {
+            let _ = writeln!(
+                out,
+                "mcp_tool_calls_total{{{}}} {}",
+                label(id),
+                metrics.calls
+            );
+        }
+        header(
+            &mut out,
+            "mcp_tool_errors_total",
+            "counter",
+            "Tool calls that failed",
+        );
+        for (id, metrics) in &tools {
+            let _ = writeln!(
+                out,
+                "mcp_tool_errors_total{{{}}} {}",
+                label(id),
+                metrics.errors
+            );
+        }
+        header(
+            &mut out,
+            "mcp_tool_duration_seconds",
+            "histogram",
+            "Time tool calls took, retries included",
+        );
+        for (id, metrics) in &tools {
+            let label = label(id);
+            let mut cumulative = 0;
+            for (bound, count) in LATENCY_BUCKETS.iter().zip(metrics.buckets) {
+                cumulative += count;
+                let _ = writeln!(
+                    out,
+                    "mcp_tool_duration_seconds_bucket{{{},le=\"{}\"}} {}",
+                    label, bound, cumulative
+                );
+            }
+            let _ = writeln!(
+                out,
+                "mcp_tool_duration_seconds_bucket{{{},le=\"+Inf\"}} {}",
+                label, metrics.calls
+            );
+            let _ = writeln!(
+                out,
+                "mcp_tool_duration_seconds_sum{{{}}} {}",
+                label, metrics.seconds
+            );
+            let _ = writeln!(
+                out,
+                "mcp_tool_duration_seconds_count{{{}}} {}",
+                label, metrics.calls
+            );
+        }
+
+        header(
+            &mut out,
+            "mcp_llm_requests_total",
+            "counter",
+            "Requests to LLM providers",
+        );
+        for (provider, metrics) in &providers {
+            let _ = writeln!(
+                out,
+                "mcp_llm_requests_total{{provider=\"{}\"}} {}",
+                escape(provider),
+                metrics.requests
+            );
+        }
+        header(
+            &mut out,
+            "mcp_llm_tokens_total",
+            "counter",
+            "Tokens used by LLM providers",
+        );
+        for (provider, metrics) in &providers {
+            for (kind, tokens) in [
+                ("prompt", metrics.usage.prompt_tokens),
+                ("completion", metrics.usage.completion_tokens),
+            ] {
+                let _ = writeln!(
+                    out,
+                    "mcp_llm_tokens_total{{provider=\"{}\",kind=\"{}\"}} {}",
+                    escape(provider),
+                    kind,
+                    tokens
+                );
+            }
+        }
+        out
+    }
+}
+
+/// Serves `metrics` on `GET /metrics` at `listen` until the process exits.
+/// Returns the address bound, which tells the port when `listen` asks for
+/// any.
+pub async fn serve(metrics: Arc<Metrics>, listen: &str) -> Result<(SocketAddr, JoinHandle<()>)> {
+    let listener = TcpListener::bind(listen)
+        .await
+        .map_err(|e| Error::msg(format!("Failed to listen on {}: {}", listen, e)))?;
+    let addr = listener.local_addr()?;
+    info!("Serving metrics on http://{}/metrics", addr);
+    let handle = tokio::spawn(async move {
+        loop {
+            let (stream, peer) = match listener.accept().await {
+                Ok(connection) => connection,
+                Err(e) => {
+                    warn!("Failed to accept a metrics connection: {}", e);
+                    continue;
+                }
+            };
+            let metrics = Arc::clone(&metrics);
+            tokio::spawn(async move {
+                if let Err(e) = respond(stream, &metrics).await {
+                    debug!("Metrics request from {} failed: {}", peer, e);
+                }
+            });
+        }
+    });
+    Ok((addr, handle))
+}
+
+/// Answers one HTTP request on `stream` and closes it
+async fn respond(mut stream: TcpStream, metrics: &Metrics) -> Result<()> {
+    let mut request = Vec::new();
+    let mut buffer = [0u8; 1024];
+    while !request.windows(4).any(|window| window == b"\r\n\r\n") {
+        let read = stream.read(&mut buffer).await?;
+        if read == 0 {
+            break;
+        }
+        request.extend_from_slice(&buffer[..read]);
+        if request.len() > MAX_REQUEST_SIZE {
+            return Err(Error::msg("Request too large"));
+        }
+    }
+    let request = String::from_utf8_lossy(&request);
+    let mut request_line = request
+        .lines()
+        .next()
+        .unwrap_or_default()
+        .split_whitespace();
+    let (status, content_type, body) = match (request_line.next(), request_line.next()) {
+        (Some("GET"), Some("/metrics")) => (
+            "200 OK",
+            "text/plain; version=0.0.4; charset=utf-8",
+            metrics.render(),
+        ),
+        (Some("GET"), _) => ("404 Not Found", "text/plain", "Not found\n".to_string()),
+        _ => (
+            "405 Method Not Allowed",
+            "text/plain",
+            "Method not allowed\n".to_string(),
+        ),
+    };
+    let response = format!(
+        "HTTP/1.1 {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        status,
+        content_type,
+        body.len(),
+        body
+    );
+    stream.write_all(response.as_bytes()).await?;
+    stream.shutdown().await?;
+    Ok(())
+}
+
+/// Writes the `# HELP` and `# TYPE` lines of a metric
+fn header(out: &mut String, name: &str, kind: &str, help: &str) {
+    let _ = writeln!(out, "# HELP {} {}", name, help);
+    let _ = writeln!(out, "# TYPE {} {}", name, kind);
+}
+
+/// `value` escaped for a label value
+fn escape(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mcp_server::Tool;
+    use serde_json::json;
+
+    #[tokio::test]
+    async fn test_tool_metrics() {
+        let metrics = Arc::new(Metrics::new());
+        let mut server = McpServer::new().named("file");
+        server
+            .register_tool(Tool::new(
+                "file_read".to_string(),
+                "Reads a file".to_string(),
+                json!({ "type": "object" }),
+                None,
+                Box::new(|args| {
+                    Box::pin(async move {
+                        match args["path"].as_str() {
+                            Some("notes.txt") => Ok(json!({ "content": "hello" })),
+                            _ => Err(Error::msg("File not found")),
+                        }
+                    })
+                }),
+            ))
+            .await
+            .unwrap();
+        metrics.attach(&mut server).await;
+
+        let retry = Duration::from_millis(1);
+        for path in ["notes.txt", "notes.txt", "missing.txt"] {
+            let _ = server
+                .execute_tool("file_read", &json!({ "path": path }), 1, retry)
+                .await;
+        }
+        metrics.record_llm_usage("openai", &TokenUsage::new(10, 5));
+        metrics.record_llm_usage("openai", &TokenUsage::new(2, 1));
+
+        let text = metrics.render();
+        assert!(text.contains("# TYPE mcp_tool_calls_total counter"));
+        assert!(text.contains("mcp_tool_calls_total{server=\"file\",tool=\"file_read\"} 3"));
+        assert!(text.contains("mcp_tool_errors_total{server=\"file\",tool=\"file_read\"} 1"));
+        assert!(text.contains(
+            "mcp_tool_duration_seconds_bucket{server=\"file\",tool=\"file_read\",le=\"+Inf\"} 3"
+        ));
+        assert!(
+            text.contains("mcp_tool_duration_seconds_count{server=\"file\",tool=\"file_read\"} 3")
+        );
+        assert!(text.contains("mcp_llm_requests_total{provider=\"openai\"} 2"));
+        assert!(text.contains("mcp_llm_tokens_total{provider=\"openai\",kind=\"prompt\"} 12"));
+        assert!(text.contains("mcp_llm_tokens_total{provider=\"openai\",kind=\"completion\"} 6"));
+    }
+
+    #[test]
+    fn test_histogram_buckets() {
+        let metrics = Metrics::new();
+        let tool = ToolId::new("sqlite", "sql_query");
+        metrics.record_tool_call(&tool, true, Duration::from_millis(20));
+        metrics.record_tool_call(&tool, true, Duration::from_secs(60));
+        let text = metrics.render();
+        let bucket = |le: &str| {
+            format!(
+                "mcp_tool_duration_seconds_bucket{{server=\"sqlite\",tool=\"sql_query\",le=\"{}\"}}",
+                le
+            )
+        };
+        assert!(text.contains(&format!("{} 0", bucket("0.01"))));
+        assert!(text.contains(&format!("{} 1", bucket("0.025"))));
+        assert!(text.contains(&format!("{} 1", bucket("30"))));
+        assert!(text.contains(&format!("{} 2", bucket("+Inf"))));
+        assert_eq!(escape("a\"b\\c\n"), "a\\\"b\\\\c\\n");
+    }
+
+    #[tokio::test]
+    async fn test_metrics_endpoint() {
+        let metrics = Arc::new(Metrics::new());
+        metrics.record_tool_call(&ToolId::new("file", "file_read"), true, Duration::ZERO);
+        let (addr, handle) = serve(Arc::clone(&metrics), "127.0.0.1:0").await.unwrap();
+
+        let get = |path: &'static str| async move {
+            let mut stream = TcpStream::connect(addr).await.unwrap();
+            stream
+                .write_all(format!("GET {} HTTP/1.1\r\nHost: localhost\r\n\r\n", path).as_bytes())
+                .await
+                .unwrap();
+            let mut response = String::new();
+            stream.read_to_string(&mut response).await.unwrap();
+            response
+        };
+        let response = get("/metrics").await;
+        assert!(response.starts_with("HTTP/1.1 200 OK"));
+        assert!(response.contains("text/plain; version=0.0.4"));
+        assert!(response.contains("mcp_tool_calls_total{server=\"file\",tool=\"file_read\"} 1"));
+        assert!(get("/").await.starts_with("HTTP/1.1 404"));
+        handle.abort();
+    }
+}
@@ -0,0 +1,540 @@
+//! Plugin Server Module: Tools of third-party WebAssembly plugins
+//!
+//! This module provides:
+//! - Discovery of the `.wasm` modules in the directory named in the
+//!   `plugins` entry of `servers_config.json`
+//! - `PluginLimits`, read from the same entry: the fuel and memory each
+//!   call may use
+//! - `Plugin`: a compiled module and the tools its manifest declares, run
+//!   in wasmtime with no access to files, network, clock or environment
+//!
+//! A plugin is a core WebAssembly module exporting:
+//! - `memory`
+//! - `alloc(len: i32) -> i32`: room for `len` bytes the host writes into
+//! - `manifest() -> i64`: the JSON manifest, `{"name": "weather",
+//!   "version": "0.1.0", "tools": [{"name", "description", "input_schema",
+//!   "output_schema"}]}`
+//! - `call(name_ptr, name_len, args_ptr, args_len: i32) -> i64`: runs a
+//!   tool on JSON arguments and returns `{"ok": <result>}` or
+//!   `{"error": {"kind": "not_found", "message": "..."}}`
+//!
+//! Strings are UTF-8 and results are returned as `ptr << 32 | len`. The only
+//! import the host offers is `host.log(level: i32, ptr: i32, len: i32)`,
+//! with levels 0 (error) to 4 (trace). Lengths are checked against the
+//! plugin's memory, and capped, before anything is copied out of it.
+//! Every call runs in a fresh instance, so plugins keep no state between
+//! calls. Calls that run out of fuel fail as timeouts and are not retried.
+//!
+//! Running plugins requires building with the `plugins` feature.
+//!
+//! Author: arkSong <arksong2018@gmail.com>
+//! Version: 1.0.0
+//! License: MIT
+
+use anyhow::{Error, Result};
+use log::{info, warn};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::path::{Path, PathBuf};
+
+use crate::mcp_server::McpServer;
+use crate::protocol::ToolSchema;
+use crate::tool_error::{ToolError, ToolErrorKind};
+
+/// What a plugin may use in one call, read from the `plugins` entry of
+/// `servers_config.json` next to `command` and `args`
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(default)]
+pub struct PluginLimits {
+    /// WebAssembly instructions, roughly, a call may run
+    pub fuel: u64,
+    /// Linear memory a call may grow to, in MiB
+    pub max_memory_mb: usize,
+}
+
+impl Default for PluginLimits {
+    fn default() -> Self {
+        Self {
+            fuel: 1_000_000_000,
+            max_memory_mb: 64,
+        }
+    }
+}
+
+/// What a plugin says about itself and the tools it offers
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PluginManifest {
+    pub name: String,
+    #[serde(default)]
+    pub version: Option<String>,
+    pub tools: Vec<ToolSchema>,
+}
+
+/// What a plugin's `call` returns
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "snake_case")]
+enum CallOutcome {
+    Ok(Value),
+    Error(PluginError),
+}
+
+/// A failure a plugin reports; `retryable` follows the kind if omitted
+#[derive(Debug, Deserialize)]
+struct PluginError {
+    kind: ToolErrorKind,
+    message: String,
+    #[serde(default)]
+    retryable: Option<bool>,
+    #[serde(default)]
+    data: Option<Value>,
+}
+
+impl CallOutcome {
+    fn into_result(self) -> Result<Value> {
+        match self {
+            CallOutcome::Ok(value) => Ok(value),
+            CallOutcome::Error(error) => {
+                let mut tool_error = ToolError::new(error.kind, error.message);
+                if let Some(retryable) = error.retryable {
+                    tool_error = tool_error.with_retryable(retryable);
+                }
+                if let Some(data) = error.data {
+                    tool_error = tool_error.with_data(data);
+                }
+                Err(Error::new(tool_error))
+            }
+        }
+    }
+}
+
+/// The `.wasm` files directly in `dir`, sorted by name
+fn plugin_files(dir: &Path) -> Result<Vec<PathBuf>> {
+    let mut files: Vec<PathBuf> = std::fs::read_dir(dir)?
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter(|path| path.is_file() && path.extension().is_some_and(|ext| ext == "wasm"))
+        .collect();
+    files.sort();
+    Ok(files)
+}
+
+/// Registers the tools of the plugins in `dir` with `mcp_server`. Plugins
+/// that fail to load and tools whose names are taken are skipped with a
+/// warning. Returns the number of tools registered.
+pub async fn register_plugin_tools(
+    mcp_server: &mut McpServer,
+    dir: &Path,
+    limits: PluginLimits,
+) -> Result<usize> {
+    if !dir.is_dir() {
+        warn!("Plugin directory {} does not exist", dir.display());
+        return Ok(0);
+    }
+    let mut registered = 0;
+    for path in plugin_files(dir)? {
+        let plugin = match wasm::Plugin::load(&path, limits) {
+            Ok(plugin) => std::sync::Arc::new(plugin),
+            Err(e) => {
+                warn!("Skipping plugin {}: {:#}", path.display(), e);
+                continue;
+            }
+        };
+        let manifest = plugin.manifest();
+        info!(
+            "Loaded plugin {} {} from {} with {} tools",
+            manifest.name,
+            manifest.version.as_deref().unwrap_or(""),
+            path.display(),
+            manifest.tools.len()
+        );
+        for schema in &manifest.tools {
+            if let Err(e) = mcp_server.register_tool(wasm::tool(&plugin, schema)).await {
+                warn!("Skipping tool of plugin {}: {}", manifest.name, e);
+                continue;
+            }
+            registered += 1;
+        }
+    }
+    Ok(registered)
+}
+
+#[cfg(feature = "plugins")]
+mod wasm {
+    use super::{CallOutcome, PluginLimits, PluginManifest};
+    use crate::mcp_server::Tool;
+    use crate::protocol::ToolSchema;
+    use crate::tool_error::{ToolError, ToolErrorKind};
+    use anyhow::{Context, Error, Result};
+    use log::{debug, error, info, trace, warn};
+    use serde_json::Value;
+    use std::path::Path;
+    use std::sync::Arc;
+    use wasmtime::{
+        Caller, Config, Engine, Instance, Linker, Memory, Module, Store, StoreLimits,
+        StoreLimitsBuilder, Trap,
+    };
+
+    /// Most bytes of a manifest or result the host reads from a plugin
+    const MAX_OUTPUT_SIZE: usize = 16 * 1024 * 1024;
+    /// Most bytes of a log message the host reads; the rest is dropped
+    const MAX_LOG_SIZE: usize = 4096;
+
+    /// What the host keeps per call
+    struct HostState {
+        plugin: String,
+        limits: StoreLimits,
+    }
+
+    /// A compiled plugin and its manifest
+    pub struct Plugin {
+        engine: Engine,
+        module: Module,
+        linker: Linker<HostState>,
+        limits: PluginLimits,
+        manifest: PluginManifest,
+    }
+
+    impl Plugin {
+        /// Compiles the module at `path` and reads its manifest
+        pub fn load(path: &Path, limits: PluginLimits) -> Result<Self> {
+            let mut config = Config::new();
+            config.consume_fuel(true);
+            let engine = Engine::new(&config)?;
+            let module = Module::from_file(&engine, path)?;
+            let mut linker = Linker::new(&engine);
+            linker.func_wrap("host", "log", host_log)?;
+
+            let mut plugin = Self {
+                engine,
+                module,
+                linker,
+                limits,
+                manifest: PluginManifest {
+                    name: String::new(),
+                    version: None,
+                    tools: Vec::new(),
+                },
+            };
+            let (mut store, instance) = plugin.instantiate()?;
+            let manifest = instance
+                .get_typed_func::<(), i64>(&mut store, "manifest")?
+                .call(&mut store, ())?;
+            let manifest = read_output(&mut store, &instance, manifest)?;
+            plugin.manifest =
+                serde_json::from_slice(&manifest).context("Invalid plugin manifest")?;
+            Ok(plugin)
+        }
+
+        pub fn manifest(&self) -> &PluginManifest {
+            &self.manifest
+        }
+
+        /// Runs the tool `name` on `arguments` in a fresh instance
+        pub fn call(&self, name: &str, arguments: &Value) -> Result<Value> {
+            let (mut store, instance) = self.instantiate()?;
+            let name_ptr = write_input(&mut store, &instance, name.as_bytes())?;
+            let arguments = serde_json::to_vec(arguments)?;
+            let args_ptr = write_input(&mut store, &instance, &arguments)?;
+            let output = instance
+                .get_typed_func::<(i32, i32, i32, i32), i64>(&mut store, "call")?
+                .call(
+                    &mut store,
+                    (
+                        name_ptr,
+                        name.len() as i32,
+                        args_ptr,
+                        arguments.len() as i32,
+                    ),
+                )
+                .map_err(|e| self.trap_error(name, e))?;
+            let output = read_output(&mut store, &instance, output)?;
+            let outcome: CallOutcome = serde_json::from_slice(&output).map_err(|e| {
+                Error::new(ToolError::new(
+                    ToolErrorKind::InvalidOutput,
+                    format!(
+                        "Plugin {} returned an invalid result: {}",
+                        self.manifest.name, e
+                    ),
+                ))
+            })?;
+            outcome.into_result()
+        }
+
+        /// A new instance with its own store, fuel and memory limit
+        fn instantiate(&self) -> Result<(Store<HostState>, Instance)> {
+            let state = HostState {
+                plugin: self.manifest.name.clone(),
+                limits: StoreLimitsBuilder::new()
+                    .memory_size(self.limits.max_memory_mb << 20)
+                    .build(),
+            };
+            let mut store = Store::new(&self.engine, state);
+            store.limiter(|state| &mut state.limits);
+            store.set_fuel(self.limits.fuel)?;
+            let instance = self.linker.instantiate(&mut store, &self.module)?;
+            Ok((store, instance))
+        }
+
+        /// The tool error of a call to `tool` that trapped
+        fn trap_error(&self, tool: &str, error: Error) -> Error {
+            let (kind, message) = match error.downcast_ref::<Trap>() {
+                Some(Trap::OutOfFuel) => (
+                    ToolErrorKind::Timeout,
+                    format!("Tool {} used up its fuel of {}", tool, self.limits.fuel),
+                ),
+                _ => (
+                    ToolErrorKind::Internal,
+                    format!(
+                        "Plugin {} failed in tool {}: {:#}",
+                        self.manifest.name, tool, error
+                    ),
+                ),
+            };
+            Error::new(ToolError::new(kind, message).with_retryable(false))
+        }
+    }
+
+    /// The tool of `plugin` that `schema` describes. Plugins run
+    /// synchronously, so each call runs on a blocking thread.
+    pub fn tool(plugin: &Arc<Plugin>, schema: &ToolSchema) -> Tool {
+        let plugin = Arc::clone(plugin);
+        let name = schema.name.clone();
+        Tool::new(
+            schema.name.clone(),
+            schema.description.clone(),
+            schema.input_schema.clone(),
+            schema.output_schema.clone(),
+            Box::new(move |args| {
+                let plugin = Arc::clone(&plugin);
+                let name = name.clone();
+                Box::pin(async move {
+                    tokio::task::spawn_blocking(move || plugin.call(&name, &args)).await?
+                })
+            }),
+        )
+    }
+
+    fn memory(store: &mut Store<HostState>, instance: &Instance) -> Result<Memory> {
+        instance
+            .get_memory(&mut *store, "memory")
+            .ok_or_else(|| Error::msg("Plugin does not export its memory"))
+    }
+
+    /// Copies `bytes` into memory the plugin allocates and returns where
+    fn write_input(store: &mut Store<HostState>, instance: &Instance, bytes: &[u8]) -> Result<i32> {
+        let ptr = instance
+            .get_typed_func::<i32, i32>(&mut *store, "alloc")?
+            .call(&mut *store, bytes.len() as i32)?;
+        memory(store, instance)?.write(&mut *store, ptr as u32 as usize, bytes)?;
+        Ok(ptr)
+    }
+
+    /// The bytes at `ptr << 32 | len` in the plugin's memory, checked to
+    /// lie within it and to be at most `MAX_OUTPUT_SIZE` before copying
+    fn read_output(
+        store: &mut Store<HostState>,
+        instance: &Instance,
+        packed: i64,
+    ) -> Result<Vec<u8>> {
+        let ptr = (packed as u64 >> 32) as usize;
+        let len = (packed as u64 & 0xffff_ffff) as usize;
+        if len > MAX_OUTPUT_SIZE {
+            return Err(Error::new(ToolError::new(
+                ToolErrorKind::InvalidOutput,
+                format!(
+                    "Plugin returned {} bytes, more than the {} allowed",
+                    len, MAX_OUTPUT_SIZE
+                ),
+            )));
+        }
+        memory(store, instance)?
+            .data(&*store)
+            .get(ptr..ptr + len)
+            .map(<[u8]>::to_vec)
+            .ok_or_else(|| {
+                Error::new(ToolError::new(
+                    ToolErrorKind::InvalidOutput,
+                    "Plugin returned bytes outside its memory",
+                ))
+            })
+    }
+
+    /// `host.log`: logs a message of the plugin, cut to `MAX_LOG_SIZE`
+    /// bytes. Messages outside the plugin's memory are ignored.
+    fn host_log(mut caller: Caller<'_, HostState>, level: i32, ptr: i32, len: i32) {
+        let memory = match caller.get_export("memory").and_then(|e| e.into_memory()) {
+            Some(memory) => memory,
+            None => return,
+        };
+        let start = ptr as u32 as usize;
+        let end = start + (len.max(0) as usize).min(MAX_LOG_SIZE);
+        let bytes = match memory.data(&caller).get(start..end) {
+            Some(bytes) => bytes,
+            None => return,
+        };
+        let message = String::from_utf8_lossy(bytes);
+        let plugin = &caller.data().plugin;
+        match level {
+            0 => error!("[plugin {}] {}", plugin, message),
+            1 => warn!("[plugin {}] {}", plugin, message),
+            2 => info!("[plugin {}] {}", plugin, message),
+            3 => debug!("[plugin {}] {}", plugin, message),
+            _ => trace!("[plugin {}] {}", plugin, message),
+        }
+    }
+}
+
+/// Stands in for wasmtime when built without the `plugins` feature; no
+/// plugin can be loaded
+#[cfg(not(feature = "plugins"))]
+mod wasm {
+    use super::{PluginLimits, PluginManifest};
+    use crate::mcp_server::Tool;
+    use crate::protocol::ToolSchema;
+    use anyhow::{Error, Result};
+    use std::path::Path;
+    use std::sync::Arc;
+
+    pub enum Plugin {}
+
+    impl Plugin {
+        pub fn load(_path: &Path, _limits: PluginLimits) -> Result<Self> {
+            Err(Error::msg(
+                "Loading plugins requires building with `--features plugins`",
+            ))
+        }
+
+        pub fn manifest(&self) -> &PluginManifest {
+            match *self {}
+        }
+    }
+
+    pub fn tool(plugin: &Arc<Plugin>, _schema: &ToolSchema) -> Tool {
+        match **plugin {}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_manifest_and_outcomes() {
+        let manifest: PluginManifest = serde_json::from_value(json!({
+            "name": "weather",
+            "version": "0.1.0",
+            "tools": [{
+                "name": "forecast",
+                "description": "Forecast for a city",
+                "input_schema": { "type": "object" }
+            }]
+        }))
+        .unwrap();
+        assert_eq!(manifest.tools[0].name, "forecast");
+        assert!(manifest.tools[0].output_schema.is_none());
+
+        let outcome: CallOutcome =
+            serde_json::from_value(json!({ "ok": { "temperature": 21 } })).unwrap();
+        assert_eq!(outcome.into_result().unwrap()["temperature"], 21);
+        let outcome: CallOutcome = serde_json::from_value(
+            json!({ "error": { "kind": "unavailable", "message": "Service down", "retryable": false } }),
+        )
+        .unwrap();
+        let error = ToolError::from_error(&outcome.into_result().unwrap_err());
+        assert_eq!(error.kind, ToolErrorKind::Unavailable);
+        assert!(!error.retryable);
+        let outcome: CallOutcome =
+            serde_json::from_value(json!({ "error": { "kind": "timeout", "message": "Slow" } }))
+                .unwrap();
+        assert!(ToolError::from_error(&outcome.into_result().unwrap_err()).retryable);
+
+        let limits: PluginLimits =
+            serde_json::from_value(json!({ "command": "plugins", "fuel": 1000 })).unwrap();
+        assert_eq!(limits.fuel, 1000);
+        assert_eq!(limits.max_memory_mb, 64);
+    }
+
+    /// A plugin with a `greet` tool that logs and answers, a `fail` tool
+    /// that returns an error, a `spin` tool that never returns and a `huge`
+    /// tool that logs and returns more than its memory holds
+    #[cfg(feature = "plugins")]
+    const TEST_PLUGIN: &str = r#"
+        (module
+          (import "host" "log" (func $log (param i32 i32 i32)))
+          (memory (export "memory") 1)
+          (global $next (mut i32) (i32.const 4096))
+          (data (i32.const 0) "{\"name\":\"test\",\"tools\":[{\"name\":\"greet\",\"description\":\"Greets\",\"input_schema\":{\"type\":\"object\"}},{\"name\":\"fail\",\"description\":\"Fails\",\"input_schema\":{\"type\":\"object\"}},{\"name\":\"spin\",\"description\":\"Spins\",\"input_schema\":{\"type\":\"object\"}},{\"name\":\"huge\",\"description\":\"Returns too much\",\"input_schema\":{\"type\":\"object\"}}]}")
+          (data (i32.const 1024) "{\"ok\":{\"greeting\":\"hello\"}}")
+          (data (i32.const 1100) "{\"error\":{\"kind\":\"not_found\",\"message\":\"nothing here\"}}")
+          (data (i32.const 1200) "greeting")
+          (func (export "alloc") (param $len i32) (result i32)
+            (local $ptr i32)
+            (local.set $ptr (global.get $next))
+            (global.set $next (i32.add (global.get $next) (local.get $len)))
+            (local.get $ptr))
+          (func (export "manifest") (result i64)
+            (i64.const 322))
+          (func (export "call") (param $name i32) (param $name_len i32) (param $args i32) (param $args_len i32) (result i64)
+            (if (i32.eq (i32.load8_u (local.get $name)) (i32.const 103))
+              (then
+                (call $log (i32.const 2) (i32.const 1200) (i32.const 8))
+                (return (i64.or (i64.shl (i64.const 1024) (i64.const 32)) (i64.const 27)))))
+            (if (i32.eq (i32.load8_u (local.get $name)) (i32.const 102))
+              (then
+                (return (i64.or (i64.shl (i64.const 1100) (i64.const 32)) (i64.const 55)))))
+            (if (i32.eq (i32.load8_u (local.get $name)) (i32.const 104))
+              (then
+                (call $log (i32.const 2) (i32.const 0) (i32.const 0x7fffffff))
+                (return (i64.const 0xffffffff))))
+            (loop $forever (br $forever))
+            (i64.const 0)))
+    "#;
+
+    #[cfg(feature = "plugins")]
+    #[tokio::test]
+    async fn test_plugin_tools() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        std::fs::write(temp_dir.path().join("test.wasm"), TEST_PLUGIN).unwrap();
+        std::fs::write(temp_dir.path().join("broken.wasm"), "not a module").unwrap();
+
+        let mut mcp_server = McpServer::new().named("plugins");
+        mcp_server.initialize().await.unwrap();
+        let limits = PluginLimits {
+            fuel: 1_000_000,
+            ..PluginLimits::default()
+        };
+        let registered = register_plugin_tools(&mut mcp_server, temp_dir.path(), limits)
+            .await
+            .unwrap();
+        assert_eq!(registered, 4);
+
+        let result = mcp_server.execute_tool("greet", &json!({})).await.unwrap();
+        assert_eq!(result, json!({ "greeting": "hello" }));
+
+        let error = mcp_server
+            .execute_tool("fail", &json!({}))
+            .await
+            .unwrap_err();
+        let error = ToolError::from_error(&error);
+        assert_eq!(error.kind, ToolErrorKind::NotFound);
+        assert_eq!(error.message, "nothing here");
+
+        // Endless loops end when the fuel runs out, without retries
+        let error = mcp_server
+            .execute_tool("spin", &json!({}))
+            .await
+            .unwrap_err();
+        let error = ToolError::from_error(&error);
+        assert_eq!(error.kind, ToolErrorKind::Timeout);
+        assert!(!error.retryable);
+
+        // Lengths beyond the memory fail without being allocated
+        let error = mcp_server
+            .execute_tool("huge", &json!({}))
+            .await
+            .unwrap_err();
+        let error = ToolError::from_error(&error);
+        assert_eq!(error.kind, ToolErrorKind::InvalidOutput);
+        assert!(!error.retryable);
+    }
+}
@@ -0,0 +1,220 @@
+//! Prompt Template Module: Variable substitution for system prompts
+//!
+//! This module provides:
+//! - Rendering of `{{variable}}` placeholders in prompts from `mcp_prompts.yaml`
+//! - Dotted paths for nested values (e.g. `{{user_profile.name}}`)
+//! - A standard context with the current date, server, model and tool list
+//!
+//! Key Components:
+//! - `PromptTemplate`: A parsed template that can be rendered many times
+//! - `build_context`: Builds the default rendering context
+//!
+//! Prompts use this plain `{{variable}}` syntax rather than the Jinja
+//! templates of `file_template`: there are no filters, conditions or loops,
+//! `{% ... %}` stays plain text, and an unknown variable renders as an empty
+//! string with a warning instead of failing, so a prompt always loads.
+//!
+//! Author: arkSong <arksong2018@gmail.com>
+//! Version: 1.0.0
+//! License: MIT
+
+use anyhow::{Error, Result};
+use log::warn;
+use serde_json::{json, Map, Value};
+use std::collections::HashMap;
+
+use crate::protocol::ToolSchema;
+
+#[derive(Debug, Clone, PartialEq)]
+enum Segment {
+    Text(String),
+    Variable(String),
+}
+
+#[derive(Debug, Clone)]
+pub struct PromptTemplate {
+    segments: Vec<Segment>,
+}
+
+impl PromptTemplate {
+    /// Parses `source`, failing on unclosed or empty placeholders
+    pub fn parse(source: &str) -> Result<Self> {
+        let mut segments = Vec::new();
+        let mut rest = source;
+
+        while let Some(start) = rest.find("{{") {
+            if start > 0 {
+                segments.push(Segment::Text(rest[..start].to_string()));
+            }
+            let after = &rest[start + 2..];
+            let end = after
+                .find("}}")
+                .ok_or_else(|| Error::msg("Unclosed '{{' in prompt template"))?;
+            let name = after[..end].trim();
+            if name.is_empty() {
+                return Err(Error::msg("Empty variable in prompt template"));
+            }
+            segments.push(Segment::Variable(name.to_string()));
+            rest = &after[end + 2..];
+        }
+        if !rest.is_empty() {
+            segments.push(Segment::Text(rest.to_string()));
+        }
+
+        Ok(Self { segments })
+    }
+
+    /// Names of all variables used by the template, in order of appearance
+    pub fn variables(&self) -> Vec<&str> {
+        self.segments
+            .iter()
+            .filter_map(|segment| match segment {
+                Segment::Variable(name) => Some(name.as_str()),
+                Segment::Text(_) => None,
+            })
+            .collect()
+    }
+
+    /// Substitutes every variable with its value in `context`.
+    /// Unknown variables render as an empty string.
+    pub fn render(&self, context: &Value) -> String {
+        let mut output = String::new();
+        for segment in &self.segments {
+            match segment {
+                Segment::Text(text) => output.push_str(text),
+                Segment::Variable(name) => match lookup(context, name) {
+                    Some(value) => output.push_str(&value_to_string(value)),
+                    None => warn!("Unknown prompt variable: {}", name),
+                },
+            }
+        }
+        output
+    }
+}
+
+/// Parses and renders `source` in one step. Templates that fail to parse are
+/// returned unchanged so a typo in the YAML file never breaks the chat.
+pub fn render(source: &str, context: &Value) -> String {
+    match PromptTemplate::parse(source) {
+        Ok(template) => template.render(context),
+        Err(e) => {
+            warn!("Invalid prompt template: {}", e);
+            source.to_string()
+        }
+    }
+}
+
+/// Context available to every system prompt:
+/// `date`, `time`, `server`, `model`, `tools`, `tool_names` and `user_profile.*`
+pub fn build_context(
+    server: &str,
+    model: &str,
+    tools: &[ToolSchema],
+    user_profile: &HashMap<String, String>,
+) -> Value {
+    let now = chrono::Local::now();
+    let tool_list = tools
+        .iter()
+        .map(|tool| tool.format_for_llm())
+        .collect::<Vec<_>>()
+        .join("\n");
+    let tool_names = tools
+        .iter()
+        .map(|tool| tool.name.as_str())
+        .collect::<Vec<_>>()
+        .join(", ");
+    let profile: Map<String, Value> = user_profile
+        .iter()
+        .map(|(k, v)| (k.clone(), Value::String(v.clone())))
+        .collect();
+
+    json!({
+        "date": now.format("%Y-%m-%d").to_string(),
+        "time": now.format("%H:%M").to_string(),
+        "server": server,
+        "model": model,
+        "tools": tool_list,
+        "tool_names": tool_names,
+        "user_profile": profile,
+    })
+}
+
+fn lookup<'a>(context: &'a Value, path: &str) -> Option<&'a Value> {
+    path.split('.')
+        .try_fold(context, |value, key| value.get(key))
+        .filter(|value| !value.is_null())
+}
+
+fn value_to_string(value: &Value) -> String {
+    match value {
+        Value::String(s) => s.clone(),
+        Value::Array(items) => items
+            .iter()
+            .map(value_to_string)
+            .collect::<Vec<_>>()
+            .join(", "),
+        other => other.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_render_variables() {
+        let template =
+            PromptTemplate::parse("Hi {{ user_profile.name }}, today is {{date}}.").unwrap();
+        assert_eq!(template.variables(), vec!["user_profile.name", "date"]);
+
+        let context = json!({"date": "2024-05-01", "user_profile": {"name": "Ada"}});
+        assert_eq!(template.render(&context), "Hi Ada, today is 2024-05-01.");
+    }
+
+    #[test]
+    fn test_render_missing_and_non_string_values() {
+        let context = json!({"count": 3, "tags": ["a", "b"]});
+        assert_eq!(
+            render("{{count}} {{tags}} [{{missing}}]", &context),
+            "3 a, b []"
+        );
+    }
+
+    #[test]
+    fn test_no_jinja_syntax() {
+        // Unlike file templates, tags and filters are not interpreted
+        let context = json!({"name": "ada"});
+        assert_eq!(
+            render(
+                "{% if name %}{{ name }}{% endif %} {{ name | upper }}.",
+                &context
+            ),
+            "{% if name %}ada{% endif %} ."
+        );
+    }
+
+    #[test]
+    fn test_invalid_templates() {
+        assert!(PromptTemplate::parse("Hello {{name").is_err());
+        assert!(PromptTemplate::parse("Hello {{ }}").is_err());
+        assert_eq!(render("Hello {{name", &json!({})), "Hello {{name");
+    }
+
+    #[test]
+    fn test_build_context() {
+        let tools = vec![ToolSchema {
+            name: "memory_get".to_string(),
+            description: "Get a value".to_string(),
+            input_schema: json!({}),
+            output_schema: None,
+        }];
+        let mut profile = HashMap::new();
+        profile.insert("language".to_string(), "English".to_string());
+
+        let context = build_context("ollama", "llama3.2:latest", &tools, &profile);
+        assert_eq!(context["server"], "ollama");
+        assert_eq!(context["tool_names"], "memory_get");
+        assert_eq!(context["user_profile"]["language"], "English");
+        assert!(context["tools"].as_str().unwrap().contains("memory_get"));
+    }
+}
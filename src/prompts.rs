@@ -0,0 +1,184 @@
+//! Prompts Module: System prompt definitions and loading
+//!
+//! This module provides:
+//! - The prompt structures read from `mcp_prompts.yaml`
+//! - Built-in defaults used when the YAML file is missing or invalid
+//!
+//! Key Components:
+//! - `SystemPrompts`: Per-server system prompts, common prompts and tool examples
+//! - `load_system_prompts`: Loads `mcp_prompts.yaml` with a fallback to the defaults
+//!
+//! Author: arkSong <arksong2018@gmail.com>
+//! Version: 1.0.0
+//! License: MIT
+
+use std::collections::HashMap;
+use std::fs;
+
+use log::{info, warn};
+use serde::Deserialize;
+
+/// Default system prompts used when YAML file is not available
+pub const DEFAULT_SYSTEM_PROMPT: &str = r#"You are an intelligent assistant that can perform various tasks. When you need to perform specific operations, you must use tools.
+
+System Rules:
+1. You must use tools when performing operations
+2. Tool usage format: {"tool": "tool_name", "arguments": {"parameter_name": "value"}}; independent tools may be called at once as a JSON array of such calls
+3. When using tools, only return the tool call format, do not add any explanatory text
+4. If unsure which tool to use, first use the /tools command to view all available tools
+5. For normal conversation, respond directly without using tools
+6. Keep your responses concise and clear
+7. If you don't understand something, ask for clarification
+8. If you need more information, ask for it
+9. If you can't perform a task, explain why
+
+Today is {{date}}. Available tools:
+{{tools}}"#;
+
+pub const DEFAULT_TOOL_RESPONSE_PROMPT: &str = "Tool results follow your tool calls as JSON objects, one per call in the order of the calls: \"content\" holds what the tool returned, or the error message when \"is_error\" is true, in which case \"error\" says what kind of failure it was and whether retrying may help. If another tool is needed, reply with only its JSON tool call. Otherwise answer the user's request in clear, natural language based on the results, without technical details or JSON formatting.";
+
+pub const DEFAULT_WELCOME_MESSAGE: &str =
+    "Welcome to MCP-ChatBot Playground!\nYour AI Assistant is ready to help.";
+
+/// Structure to hold all system prompts
+#[derive(Debug, Deserialize)]
+pub struct SystemPrompts {
+    pub server_prompts: HashMap<String, ServerPrompt>,
+    pub default_system_prompt: String,
+    pub common_prompts: CommonPrompts,
+    pub commands: HashMap<String, String>,
+    pub tool_examples: HashMap<String, Vec<ToolExample>>,
+    #[serde(default)]
+    pub user_profile: HashMap<String, String>, // Available as {{user_profile.*}}
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ServerPrompt {
+    pub system_prompt: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct CommonPrompts {
+    pub tool_response: String,
+    pub welcome: String,
+    pub error_prompts: ErrorPrompts,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ErrorPrompts {
+    pub ollama_not_found: String,
+    pub ollama_connection_failed: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ToolExample {
+    pub description: String,
+    pub example: String,
+}
+
+/// Loads system prompts from YAML file or returns default prompts
+pub fn load_system_prompts() -> SystemPrompts {
+    match fs::read_to_string("mcp_prompts.yaml") {
+        Ok(content) => match serde_yaml::from_str::<SystemPrompts>(&content) {
+            Ok(prompts) => {
+                info!("Successfully loaded prompts from mcp_prompts.yaml");
+                prompts
+            }
+            Err(e) => {
+                warn!(
+                    "Failed to parse mcp_prompts.yaml: {}. Using default prompts.",
+                    e
+                );
+                create_default_prompts()
+            }
+        },
+        Err(e) => {
+            warn!(
+                "Failed to read mcp_prompts.yaml: {}. Using default prompts.",
+                e
+            );
+            create_default_prompts()
+        }
+    }
+}
+
+/// Creates default system prompts
+pub fn create_default_prompts() -> SystemPrompts {
+    let mut commands = HashMap::new();
+    commands.insert(
+        "help".to_string(),
+        "Display help menu and available commands".to_string(),
+    );
+    commands.insert("clear".to_string(), "Clear the terminal screen".to_string());
+    commands.insert("usage".to_string(), "Display usage information".to_string());
+    commands.insert("exit".to_string(), "Exit the program".to_string());
+    commands.insert(
+        "servers".to_string(),
+        "List available MCP servers".to_string(),
+    );
+    commands.insert("tools".to_string(), "List available tools".to_string());
+    commands.insert(
+        "resources".to_string(),
+        "List available resources".to_string(),
+    );
+    commands.insert(
+        "debug".to_string(),
+        "Toggle debug logging level".to_string(),
+    );
+    commands.insert(
+        "ai".to_string(),
+        "Switch between AI providers (Ollama/OpenAI)".to_string(),
+    );
+
+    let mut tool_examples = HashMap::new();
+    tool_examples.insert(
+        "memory".to_string(),
+        vec![
+            ToolExample {
+                description: "Store a value in memory".to_string(),
+                example: r#"{"tool": "memory_set", "arguments": {"key": "name", "value": "John"}}"#
+                    .to_string(),
+            },
+            ToolExample {
+                description: "Retrieve a value from memory".to_string(),
+                example: r#"{"tool": "memory_get", "arguments": {"key": "name"}}"#.to_string(),
+            },
+        ],
+    );
+
+    SystemPrompts {
+        server_prompts: HashMap::new(),
+        default_system_prompt: DEFAULT_SYSTEM_PROMPT.to_string(),
+        common_prompts: CommonPrompts {
+            tool_response: DEFAULT_TOOL_RESPONSE_PROMPT.to_string(),
+            welcome: DEFAULT_WELCOME_MESSAGE.to_string(),
+            error_prompts: ErrorPrompts {
+                ollama_not_found: "Ollama model not found, please check:\n1. Make sure llama3.2:latest model is downloaded: ollama pull llama3.2:latest\n2. Switch to default Deepseek API? (y/n):".to_string(),
+                ollama_connection_failed: "Ollama server connection failed, please check:\n1. Make sure Ollama service is running\n2. Check if Ollama service is running at http://localhost:11434\n3. Make sure llama3.2:latest model is downloaded: ollama pull llama3.2:latest\n4. Switch to default Deepseek API? (y/n):".to_string(),
+            },
+        },
+        commands,
+        tool_examples,
+        user_profile: HashMap::new(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_default_prompts() {
+        let prompts = create_default_prompts();
+        assert_eq!(prompts.common_prompts.welcome, DEFAULT_WELCOME_MESSAGE);
+        assert!(prompts.commands.contains_key("help"));
+        assert_eq!(prompts.tool_examples["memory"].len(), 2);
+    }
+
+    #[test]
+    fn test_bundled_prompts_file_parses() {
+        let content = fs::read_to_string("mcp_prompts.yaml").unwrap();
+        let prompts: SystemPrompts = serde_yaml::from_str(&content).unwrap();
+        assert!(!prompts.default_system_prompt.is_empty());
+    }
+}
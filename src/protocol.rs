@@ -4,12 +4,20 @@
 //! including messages for initialization, tool execution, resource management,
 //! prompt handling, and lifecycle management.
 //!
+//! It also provides the JSON-RPC 2.0 framing of the Model Context Protocol:
+//! - `JsonRpcMessage`: requests, notifications and responses, one per line
+//! - `McpRequest`: the `initialize`, `ping`, `tools/*`, `resources/*` and
+//!   `prompts/*` methods with typed params
+//! - Result types converting to and from `ToolSchema`, `ResourceSchema`
+//!   and `PromptSchema`
+//!
 //! Author: arkSong <arksong2018@gmail.com>
 //! Version: 1.0.0
 //! License: MIT
 
+use serde::de::DeserializeOwned;
 use serde::{Deserialize, Serialize};
-use serde_json::Value;
+use serde_json::{json, Value};
 
 #[derive(Debug, Serialize, Deserialize)]
 pub enum Message {
@@ -79,7 +87,10 @@ pub enum Message {
     },
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+/// Method groups an MCP session may use. A server offers a set during
+/// `initialize`; the negotiated set then gates the methods both sides use.
+/// `notifications` covers the `notifications/*/list_changed` messages.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
 pub struct Capabilities {
     pub tools: bool,
     pub resources: bool,
@@ -87,6 +98,42 @@ pub struct Capabilities {
     pub notifications: bool,
 }
 
+impl Capabilities {
+    /// Whether `method` belongs to an enabled group. Methods outside the
+    /// groups, such as `initialize` and `ping`, are always allowed.
+    pub fn allows(&self, method: &str) -> bool {
+        if method.starts_with("notifications/") && method.ends_with("/list_changed") {
+            return self.notifications;
+        }
+        match method.split('/').next() {
+            Some("tools") => self.tools,
+            Some("resources") => self.resources,
+            Some("prompts") => self.prompts,
+            _ => true,
+        }
+    }
+
+    /// The groups enabled in both sets
+    pub fn intersect(&self, other: &Capabilities) -> Capabilities {
+        Capabilities {
+            tools: self.tools && other.tools,
+            resources: self.resources && other.resources,
+            prompts: self.prompts && other.prompts,
+            notifications: self.notifications && other.notifications,
+        }
+    }
+
+    /// The groups enabled in either set
+    pub fn union(&self, other: &Capabilities) -> Capabilities {
+        Capabilities {
+            tools: self.tools || other.tools,
+            resources: self.resources || other.resources,
+            prompts: self.prompts || other.prompts,
+            notifications: self.notifications || other.notifications,
+        }
+    }
+}
+
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct ToolSchema {
     pub name: String,
@@ -165,6 +212,793 @@ impl Message {
     }
 }
 
+// JSON-RPC 2.0 framing of the Model Context Protocol
+//
+// MCP peers such as Claude Desktop or the reference servers exchange
+// JSON-RPC 2.0 envelopes, one per line on stdio. `JsonRpcMessage` parses and
+// writes those envelopes; `McpRequest` and the result types below map the
+// MCP methods onto `ToolSchema`, `ResourceSchema` and `PromptSchema`.
+// `Message` above remains the crate's internal format.
+
+/// Version string every JSON-RPC envelope carries
+pub const JSONRPC_VERSION: &str = "2.0";
+
+/// MCP revision this crate speaks and offers during `initialize`
+pub const MCP_PROTOCOL_VERSION: &str = "2024-11-05";
+
+/// Protocol revisions this crate speaks, newest first
+pub const SUPPORTED_PROTOCOL_VERSIONS: [&str; 1] = [MCP_PROTOCOL_VERSION];
+
+/// The revision a server answers `initialize` with: the client's if this
+/// crate speaks it, otherwise the newest one, which the client may refuse
+pub fn negotiate_protocol_version(requested: &str) -> &'static str {
+    SUPPORTED_PROTOCOL_VERSIONS
+        .iter()
+        .find(|version| **version == requested)
+        .unwrap_or(&SUPPORTED_PROTOCOL_VERSIONS[0])
+}
+
+/// Notification a client sends once it has processed the `initialize` result
+pub const INITIALIZED_NOTIFICATION: &str = "notifications/initialized";
+
+/// Notification a server sends when its list of tools has changed
+pub const TOOLS_LIST_CHANGED_NOTIFICATION: &str = "notifications/tools/list_changed";
+
+/// Notification about a running request whose `_meta` carried a
+/// `progressToken`
+pub const PROGRESS_NOTIFICATION: &str = "notifications/progress";
+
+/// Id of a JSON-RPC request, echoed by its response
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum RequestId {
+    Number(i64),
+    String(String),
+}
+
+impl std::fmt::Display for RequestId {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            RequestId::Number(id) => write!(f, "{}", id),
+            RequestId::String(id) => write!(f, "{}", id),
+        }
+    }
+}
+
+/// A call that expects a response
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct JsonRpcRequest {
+    pub jsonrpc: String,
+    pub id: RequestId,
+    pub method: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub params: Option<Value>,
+}
+
+impl JsonRpcRequest {
+    pub fn new(id: RequestId, method: &str, params: Option<Value>) -> Self {
+        Self {
+            jsonrpc: JSONRPC_VERSION.to_string(),
+            id,
+            method: method.to_string(),
+            params,
+        }
+    }
+
+    /// Deserializes `params`, treating missing params as `{}`
+    pub fn params<T: DeserializeOwned>(&self) -> Result<T, JsonRpcError> {
+        let params = self.params.clone().unwrap_or_else(|| json!({}));
+        serde_json::from_value(params).map_err(|e| {
+            JsonRpcError::new(
+                JsonRpcError::INVALID_PARAMS,
+                format!("Invalid params for {}: {}", self.method, e),
+            )
+        })
+    }
+}
+
+/// A call without an id, which gets no response
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct JsonRpcNotification {
+    pub jsonrpc: String,
+    pub method: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub params: Option<Value>,
+}
+
+impl JsonRpcNotification {
+    pub fn new(method: &str, params: Option<Value>) -> Self {
+        Self {
+            jsonrpc: JSONRPC_VERSION.to_string(),
+            method: method.to_string(),
+            params,
+        }
+    }
+}
+
+/// The answer to a request, holding either `result` or `error`. The id is
+/// null when the request could not be read.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct JsonRpcResponse {
+    pub jsonrpc: String,
+    pub id: Option<RequestId>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub result: Option<Value>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<JsonRpcError>,
+}
+
+impl JsonRpcResponse {
+    pub fn success(id: RequestId, result: Value) -> Self {
+        Self {
+            jsonrpc: JSONRPC_VERSION.to_string(),
+            id: Some(id),
+            result: Some(result),
+            error: None,
+        }
+    }
+
+    pub fn failure(id: Option<RequestId>, error: JsonRpcError) -> Self {
+        Self {
+            jsonrpc: JSONRPC_VERSION.to_string(),
+            id,
+            result: None,
+            error: Some(error),
+        }
+    }
+
+    /// The result, or the error the peer reported
+    pub fn into_result(self) -> Result<Value, JsonRpcError> {
+        match (self.error, self.result) {
+            (Some(error), _) => Err(error),
+            (None, Some(result)) => Ok(result),
+            (None, None) => Err(JsonRpcError::new(
+                JsonRpcError::INVALID_REQUEST,
+                "Response has neither result nor error",
+            )),
+        }
+    }
+}
+
+/// Error object of a failed request
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct JsonRpcError {
+    pub code: i64,
+    pub message: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub data: Option<Value>,
+}
+
+impl JsonRpcError {
+    pub const PARSE_ERROR: i64 = -32700;
+    pub const INVALID_REQUEST: i64 = -32600;
+    pub const METHOD_NOT_FOUND: i64 = -32601;
+    pub const INVALID_PARAMS: i64 = -32602;
+    pub const INTERNAL_ERROR: i64 = -32603;
+
+    pub fn new(code: i64, message: impl Into<String>) -> Self {
+        Self {
+            code,
+            message: message.into(),
+            data: None,
+        }
+    }
+}
+
+impl std::fmt::Display for JsonRpcError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} (code {})", self.message, self.code)
+    }
+}
+
+impl std::error::Error for JsonRpcError {}
+
+/// Any JSON-RPC envelope. Batches are not supported.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(untagged)]
+pub enum JsonRpcMessage {
+    Request(JsonRpcRequest),
+    Notification(JsonRpcNotification),
+    Response(JsonRpcResponse),
+}
+
+impl JsonRpcMessage {
+    /// Parses one envelope, telling requests, notifications and responses
+    /// apart by their `method` and `id` members
+    pub fn parse(data: &str) -> Result<Self, JsonRpcError> {
+        let value: Value = serde_json::from_str(data)
+            .map_err(|e| JsonRpcError::new(JsonRpcError::PARSE_ERROR, e.to_string()))?;
+        let invalid = |message: String| JsonRpcError::new(JsonRpcError::INVALID_REQUEST, message);
+        let object = value
+            .as_object()
+            .ok_or_else(|| invalid("Expected a JSON-RPC object".to_string()))?;
+        if object.get("jsonrpc").and_then(Value::as_str) != Some(JSONRPC_VERSION) {
+            return Err(invalid("Expected \"jsonrpc\": \"2.0\"".to_string()));
+        }
+        let message = if object.contains_key("method") {
+            if object.contains_key("id") {
+                serde_json::from_value(value).map(JsonRpcMessage::Request)
+            } else {
+                serde_json::from_value(value).map(JsonRpcMessage::Notification)
+            }
+        } else if object.contains_key("id") {
+            serde_json::from_value(value).map(JsonRpcMessage::Response)
+        } else {
+            return Err(invalid("Expected a method or an id".to_string()));
+        };
+        message.map_err(|e| invalid(e.to_string()))
+    }
+
+    /// The envelope as a single line of JSON, without the trailing newline
+    pub fn to_line(&self) -> String {
+        serde_json::to_string(self).unwrap()
+    }
+}
+
+/// An MCP request, with its params mapped onto typed structs
+#[derive(Debug, Clone, PartialEq)]
+pub enum McpRequest {
+    Initialize(InitializeParams),
+    Ping,
+    ListTools,
+    CallTool(CallToolParams),
+    ListResources,
+    ListResourceTemplates,
+    ReadResource(ReadResourceParams),
+    ListPrompts,
+    GetPrompt(GetPromptParams),
+}
+
+impl McpRequest {
+    /// Reads an MCP method from `request`. Pagination cursors are ignored,
+    /// as every list fits into one page.
+    pub fn from_request(request: &JsonRpcRequest) -> Result<Self, JsonRpcError> {
+        Ok(match request.method.as_str() {
+            "initialize" => McpRequest::Initialize(request.params()?),
+            "ping" => McpRequest::Ping,
+            "tools/list" => McpRequest::ListTools,
+            "tools/call" => McpRequest::CallTool(request.params()?),
+            "resources/list" => McpRequest::ListResources,
+            "resources/templates/list" => McpRequest::ListResourceTemplates,
+            "resources/read" => McpRequest::ReadResource(request.params()?),
+            "prompts/list" => McpRequest::ListPrompts,
+            "prompts/get" => McpRequest::GetPrompt(request.params()?),
+            method => {
+                return Err(JsonRpcError::new(
+                    JsonRpcError::METHOD_NOT_FOUND,
+                    format!("Method not found: {}", method),
+                ))
+            }
+        })
+    }
+
+    pub fn method(&self) -> &'static str {
+        match self {
+            McpRequest::Initialize(_) => "initialize",
+            McpRequest::Ping => "ping",
+            McpRequest::ListTools => "tools/list",
+            McpRequest::CallTool(_) => "tools/call",
+            McpRequest::ListResources => "resources/list",
+            McpRequest::ListResourceTemplates => "resources/templates/list",
+            McpRequest::ReadResource(_) => "resources/read",
+            McpRequest::ListPrompts => "prompts/list",
+            McpRequest::GetPrompt(_) => "prompts/get",
+        }
+    }
+
+    /// Wraps the request into an envelope with the given id
+    pub fn into_request(self, id: RequestId) -> JsonRpcRequest {
+        let params = match &self {
+            McpRequest::Initialize(params) => serde_json::to_value(params).ok(),
+            McpRequest::CallTool(params) => serde_json::to_value(params).ok(),
+            McpRequest::ReadResource(params) => serde_json::to_value(params).ok(),
+            McpRequest::GetPrompt(params) => serde_json::to_value(params).ok(),
+            _ => None,
+        };
+        JsonRpcRequest::new(id, self.method(), params)
+    }
+}
+
+/// Name and version of an MCP client or server
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Implementation {
+    pub name: String,
+    pub version: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct InitializeParams {
+    pub protocol_version: String,
+    #[serde(default)]
+    pub capabilities: Value,
+    pub client_info: Implementation,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct InitializeResult {
+    pub protocol_version: String,
+    pub capabilities: ServerCapabilities,
+    pub server_info: Implementation,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub instructions: Option<String>,
+}
+
+/// What an MCP server offers; each present member enables a method group
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct ServerCapabilities {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tools: Option<Value>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub resources: Option<Value>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub prompts: Option<Value>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub logging: Option<Value>,
+}
+
+impl From<&Capabilities> for ServerCapabilities {
+    fn from(capabilities: &Capabilities) -> Self {
+        let enabled = |flag: bool| {
+            flag.then(|| match capabilities.notifications {
+                true => json!({ "listChanged": true }),
+                false => json!({}),
+            })
+        };
+        Self {
+            tools: enabled(capabilities.tools),
+            resources: enabled(capabilities.resources),
+            prompts: enabled(capabilities.prompts),
+            logging: None,
+        }
+    }
+}
+
+impl From<&ServerCapabilities> for Capabilities {
+    /// Notifications are enabled if any group announces `listChanged`
+    fn from(capabilities: &ServerCapabilities) -> Self {
+        let groups = [
+            &capabilities.tools,
+            &capabilities.resources,
+            &capabilities.prompts,
+        ];
+        Self {
+            tools: capabilities.tools.is_some(),
+            resources: capabilities.resources.is_some(),
+            prompts: capabilities.prompts.is_some(),
+            notifications: groups.iter().any(|group| {
+                group
+                    .as_ref()
+                    .is_some_and(|group| group["listChanged"] == json!(true))
+            }),
+        }
+    }
+}
+
+/// A tool as `tools/list` describes it
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct McpTool {
+    pub name: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    pub input_schema: Value,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub output_schema: Option<Value>,
+}
+
+impl From<ToolSchema> for McpTool {
+    fn from(schema: ToolSchema) -> Self {
+        Self {
+            name: schema.name,
+            description: Some(schema.description),
+            input_schema: schema.input_schema,
+            output_schema: schema.output_schema,
+        }
+    }
+}
+
+impl From<McpTool> for ToolSchema {
+    fn from(tool: McpTool) -> Self {
+        Self {
+            name: tool.name,
+            description: tool.description.unwrap_or_default(),
+            input_schema: tool.input_schema,
+            output_schema: tool.output_schema,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ListToolsResult {
+    pub tools: Vec<McpTool>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CallToolParams {
+    pub name: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub arguments: Option<Value>,
+    #[serde(rename = "_meta", default, skip_serializing_if = "Option::is_none")]
+    pub meta: Option<Value>,
+}
+
+impl CallToolParams {
+    /// Token to send progress notifications of the call with, if the
+    /// caller asked for them
+    pub fn progress_token(&self) -> Option<&Value> {
+        self.meta
+            .as_ref()?
+            .get("progressToken")
+            .filter(|token| token.is_string() || token.is_number())
+    }
+}
+
+/// Params of `notifications/progress`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ProgressParams {
+    pub progress_token: Value,
+    pub progress: f64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub total: Option<f64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub message: Option<String>,
+}
+
+/// A piece of content in a tool result or prompt message
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "lowercase")]
+pub enum Content {
+    Text {
+        text: String,
+    },
+    Image {
+        data: String,
+        #[serde(rename = "mimeType")]
+        mime_type: String,
+    },
+    Resource {
+        resource: ResourceContents,
+    },
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CallToolResult {
+    pub content: Vec<Content>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub structured_content: Option<Value>,
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub is_error: bool,
+}
+
+impl CallToolResult {
+    /// Wraps the JSON result of a tool as text, and as structured content
+    /// when it is an object
+    pub fn from_value(value: Value) -> Self {
+        Self {
+            content: vec![Content::Text {
+                text: value.to_string(),
+            }],
+            structured_content: value.is_object().then_some(value),
+            is_error: false,
+        }
+    }
+
+    /// A failed call, reported to the model rather than as a protocol error
+    pub fn error(message: impl Into<String>) -> Self {
+        Self {
+            content: vec![Content::Text {
+                text: message.into(),
+            }],
+            structured_content: None,
+            is_error: true,
+        }
+    }
+
+    /// The result as one JSON value: the structured content if present,
+    /// a single text parsed as JSON where possible, or else the list of
+    /// contents. Errors become their text.
+    pub fn into_value(self) -> Result<Value, String> {
+        if self.is_error {
+            return Err(self
+                .content
+                .iter()
+                .filter_map(|content| match content {
+                    Content::Text { text } => Some(text.as_str()),
+                    _ => None,
+                })
+                .collect::<Vec<_>>()
+                .join("\n"));
+        }
+        if let Some(value) = self.structured_content {
+            return Ok(value);
+        }
+        match self.content.as_slice() {
+            [Content::Text { text }] => {
+                Ok(serde_json::from_str(text).unwrap_or_else(|_| Value::String(text.clone())))
+            }
+            contents => Ok(serde_json::to_value(contents).unwrap_or_default()),
+        }
+    }
+}
+
+/// A resource as `resources/list` describes it. Built-in resources use
+/// their pattern as URI and take their arguments as query parameters, e.g.
+/// `filesystem?action=space`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct McpResource {
+    pub uri: String,
+    pub name: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub mime_type: Option<String>,
+}
+
+impl From<ResourceSchema> for McpResource {
+    fn from(schema: ResourceSchema) -> Self {
+        Self {
+            uri: schema.pattern.clone(),
+            name: schema.pattern,
+            description: Some(schema.description),
+            mime_type: Some("application/json".to_string()),
+        }
+    }
+}
+
+impl From<McpResource> for ResourceSchema {
+    fn from(resource: McpResource) -> Self {
+        Self {
+            pattern: resource.uri,
+            description: resource.description.unwrap_or(resource.name),
+            input_schema: None,
+            output_schema: None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ListResourcesResult {
+    pub resources: Vec<McpResource>,
+}
+
+/// A resource addressed by a URI template, as `resources/templates/list`
+/// describes it, e.g. `file:///{path}`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct McpResourceTemplate {
+    pub uri_template: String,
+    pub name: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub mime_type: Option<String>,
+}
+
+impl From<ResourceSchema> for McpResourceTemplate {
+    fn from(schema: ResourceSchema) -> Self {
+        Self {
+            uri_template: schema.pattern.clone(),
+            name: schema.pattern,
+            description: Some(schema.description),
+            mime_type: None,
+        }
+    }
+}
+
+impl From<McpResourceTemplate> for ResourceSchema {
+    fn from(template: McpResourceTemplate) -> Self {
+        Self {
+            pattern: template.uri_template,
+            description: template.description.unwrap_or(template.name),
+            input_schema: None,
+            output_schema: None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ListResourceTemplatesResult {
+    pub resource_templates: Vec<McpResourceTemplate>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ReadResourceParams {
+    pub uri: String,
+}
+
+impl ReadResourceParams {
+    /// Splits the URI into the resource pattern and its arguments, reading
+    /// query parameters as JSON where possible and as strings otherwise
+    pub fn pattern_and_arguments(&self) -> (String, Value) {
+        let (pattern, query) = match self.uri.split_once('?') {
+            Some((pattern, query)) => (pattern, query),
+            None => (self.uri.as_str(), ""),
+        };
+        let arguments = query
+            .split('&')
+            .filter(|pair| !pair.is_empty())
+            .map(|pair| {
+                let (key, value) = pair.split_once('=').unwrap_or((pair, ""));
+                let value = serde_json::from_str(value)
+                    .unwrap_or_else(|_| Value::String(value.to_string()));
+                (key.to_string(), value)
+            })
+            .collect::<serde_json::Map<_, _>>();
+        (pattern.to_string(), Value::Object(arguments))
+    }
+}
+
+/// Content of a resource, as text or base64 `blob`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ResourceContents {
+    pub uri: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub mime_type: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub text: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub blob: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ReadResourceResult {
+    pub contents: Vec<ResourceContents>,
+}
+
+impl ReadResourceResult {
+    /// Wraps the JSON a resource returned as `application/json` text
+    pub fn from_value(uri: &str, value: &Value) -> Self {
+        Self {
+            contents: vec![ResourceContents {
+                uri: uri.to_string(),
+                mime_type: Some("application/json".to_string()),
+                text: Some(value.to_string()),
+                blob: None,
+            }],
+        }
+    }
+
+    /// The first content as JSON: parsed text where possible, the text as
+    /// a string otherwise, or the content itself for blobs
+    pub fn into_value(self) -> Value {
+        match self.contents.into_iter().next() {
+            Some(ResourceContents {
+                text: Some(text), ..
+            }) => serde_json::from_str(&text).unwrap_or(Value::String(text)),
+            Some(contents) => serde_json::to_value(contents).unwrap_or_default(),
+            None => Value::Null,
+        }
+    }
+}
+
+/// A prompt as `prompts/list` describes it
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct McpPrompt {
+    pub name: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    #[serde(default)]
+    pub arguments: Vec<McpPromptArgument>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct McpPromptArgument {
+    pub name: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    #[serde(default)]
+    pub required: bool,
+}
+
+impl From<PromptSchema> for McpPrompt {
+    fn from(schema: PromptSchema) -> Self {
+        Self {
+            name: schema.name,
+            description: Some(schema.description),
+            arguments: schema
+                .arguments
+                .into_iter()
+                .map(|argument| McpPromptArgument {
+                    name: argument.name,
+                    description: Some(argument.description),
+                    required: argument.required,
+                })
+                .collect(),
+        }
+    }
+}
+
+impl From<McpPrompt> for PromptSchema {
+    fn from(prompt: McpPrompt) -> Self {
+        Self {
+            name: prompt.name,
+            description: prompt.description.unwrap_or_default(),
+            arguments: prompt
+                .arguments
+                .into_iter()
+                .map(|argument| PromptArgument {
+                    name: argument.name,
+                    description: argument.description.unwrap_or_default(),
+                    required: argument.required,
+                    // MCP prompt arguments are always strings
+                    schema: json!({ "type": "string" }),
+                })
+                .collect(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ListPromptsResult {
+    pub prompts: Vec<McpPrompt>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct GetPromptParams {
+    pub name: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub arguments: Option<Value>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PromptMessage {
+    pub role: String,
+    pub content: Content,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct GetPromptResult {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    pub messages: Vec<PromptMessage>,
+}
+
+impl GetPromptResult {
+    /// Wraps the text a prompt produced as a single user message. Other
+    /// JSON is sent as its text, except for prompt results, such as those
+    /// of external servers, which are kept as they are.
+    pub fn from_value(description: Option<String>, value: Value) -> Self {
+        if value.get("messages").is_some() {
+            if let Ok(mut result) = serde_json::from_value::<GetPromptResult>(value.clone()) {
+                result.description = result.description.or(description);
+                return result;
+            }
+        }
+        let text = match value {
+            Value::String(text) => text,
+            value => value.to_string(),
+        };
+        Self {
+            description,
+            messages: vec![PromptMessage {
+                role: "user".to_string(),
+                content: Content::Text { text },
+            }],
+        }
+    }
+
+    /// The text of all text messages, one per line
+    pub fn into_value(self) -> Value {
+        Value::String(
+            self.messages
+                .iter()
+                .filter_map(|message| match &message.content {
+                    Content::Text { text } => Some(text.as_str()),
+                    _ => None,
+                })
+                .collect::<Vec<_>>()
+                .join("\n"),
+        )
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -270,6 +1104,99 @@ mod tests {
         assert!(schema.output_schema.is_some());
     }
 
+    #[test]
+    fn test_resource_uri_arguments() {
+        let params = ReadResourceParams {
+            uri: "filesystem?action=space&depth=2&verbose=true".to_string(),
+        };
+        let (pattern, arguments) = params.pattern_and_arguments();
+        assert_eq!(pattern, "filesystem");
+        assert_eq!(
+            arguments,
+            json!({ "action": "space", "depth": 2, "verbose": true })
+        );
+
+        let params = ReadResourceParams {
+            uri: "sqlite_database".to_string(),
+        };
+        assert_eq!(
+            params.pattern_and_arguments(),
+            ("sqlite_database".to_string(), json!({}))
+        );
+    }
+
+    #[test]
+    fn test_resource_templates() {
+        let result: ListResourceTemplatesResult = serde_json::from_value(json!({
+            "resourceTemplates": [{
+                "uriTemplate": "file:///{path}",
+                "name": "Project Files",
+                "mimeType": "application/octet-stream"
+            }]
+        }))
+        .unwrap();
+        let schema = ResourceSchema::from(result.resource_templates[0].clone());
+        assert_eq!(schema.pattern, "file:///{path}");
+        assert_eq!(schema.description, "Project Files");
+
+        let template = serde_json::to_value(McpResourceTemplate::from(schema)).unwrap();
+        assert_eq!(template["uriTemplate"], "file:///{path}");
+        assert!(template.get("mimeType").is_none());
+    }
+
+    #[test]
+    fn test_server_capabilities() {
+        let capabilities = ServerCapabilities::from(&Capabilities {
+            tools: true,
+            resources: true,
+            prompts: false,
+            notifications: false,
+        });
+        assert_eq!(
+            serde_json::to_value(capabilities).unwrap(),
+            json!({ "tools": {}, "resources": {} })
+        );
+    }
+
+    #[test]
+    fn test_capability_negotiation() {
+        assert_eq!(
+            negotiate_protocol_version(MCP_PROTOCOL_VERSION),
+            MCP_PROTOCOL_VERSION
+        );
+        assert_eq!(
+            negotiate_protocol_version("1999-01-01"),
+            MCP_PROTOCOL_VERSION
+        );
+
+        let offered = Capabilities {
+            tools: true,
+            resources: true,
+            prompts: false,
+            notifications: true,
+        };
+        let announced = ServerCapabilities::from(&offered);
+        assert_eq!(
+            serde_json::to_value(&announced).unwrap(),
+            json!({ "tools": { "listChanged": true }, "resources": { "listChanged": true } })
+        );
+        assert_eq!(Capabilities::from(&announced), offered);
+
+        let tools_only = Capabilities {
+            tools: true,
+            ..Capabilities::default()
+        };
+        let negotiated = offered.intersect(&tools_only);
+        assert_eq!(negotiated, tools_only);
+        assert_eq!(offered.union(&tools_only), offered);
+        assert!(negotiated.allows("tools/call"));
+        assert!(!negotiated.allows("resources/read"));
+        assert!(!negotiated.allows("prompts/list"));
+        assert!(!negotiated.allows("notifications/tools/list_changed"));
+        assert!(negotiated.allows("ping"));
+        assert!(negotiated.allows("notifications/initialized"));
+    }
+
     #[test]
     fn test_prompt_schema() {
         let schema = PromptSchema {
@@ -1,44 +1,72 @@
-//! RAG Server Module: Retrieval Augmented Generation using Qdrant
+//! RAG Server Module: Retrieval Augmented Generation over a vector backend (Qdrant by default)
 //!
 //! This module provides functionality for:
 //! - Document embedding and storage
+//! - Streamed ingestion of text files too large to load at once
 //! - Semantic search using vector similarity
 //! - Context augmentation for LLM queries
+//! - `rag_search`, `rag_add` and `rag_documents` tools for MCP clients
 //!
 //! Author: arkSong <arksong2018@gmail.com>
 //! Version: 1.0.0
 //! License: MIT
 
 use anyhow::{Context, Error, Result};
-use log::{debug, info};
+use futures::stream::{self, StreamExt};
+use glob::Pattern;
+use log::{debug, info, warn};
 use lru::LruCache;
 use ndarray::{Array1, Array2};
 use ndarray_linalg::Norm;
 use ndarray_rand::{rand_distr::StandardNormal, RandomExt};
-use qdrant_client::qdrant::{
-    point_id, r#match::MatchValue, value::Kind, vectors, vectors_config::Config, CreateCollection,
-    Datatype, Distance, Filter, OptimizersConfigDiff, PointId, PointStruct, SearchPoints,
-    SearchPointsBuilder, UpsertPointsBuilder, Value, Vector, VectorParams, Vectors, VectorsConfig,
-    WalConfigDiff,
-};
-use qdrant_client::Qdrant;
 use regex::Regex;
-use rust_bert::pipelines::sentence_embeddings::{
-    SentenceEmbeddingsBuilder, SentenceEmbeddingsConfig, SentenceEmbeddingsModel,
-    SentenceEmbeddingsModelType,
-};
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
+use serde_json::{json, Value};
+use std::collections::{HashMap, HashSet};
 use std::num::NonZeroUsize;
-use std::sync::Arc;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, LazyLock};
 use tokenizers::tokenizer::TruncationDirection;
 use tokenizers::Tokenizer;
-use tokio::sync::RwLock as TokioRwLock;
+use tokio::io::AsyncReadExt;
+use tokio::sync::{Mutex as TokioMutex, OnceCell, RwLock as TokioRwLock};
 use unicode_normalization::UnicodeNormalization;
 use uuid;
+use walkdir::WalkDir;
+
+use crate::document_processor::{extract_file, file_hash, is_supported_file, CODE_EXTENSIONS};
+use crate::embedder::{create_embedder, Embedder, EmbedderConfig};
+use crate::llm_client::LLMClient;
+use crate::local_backend::LocalBackend;
+use crate::mcp_server::Tool;
+use crate::tool_error::invalid_arguments;
+use crate::vector_backend::{
+    BackendKind, Payload, PayloadFilter, QdrantBackend, QdrantConfig, RetryPolicy, ScoredPoint,
+    SearchQuery, VectorBackend, VectorPoint,
+};
 
-const EMBEDDING_SIZE: usize = 768;
 const MAX_CACHE_SIZE: usize = 10000;
+const SCROLL_PAGE_SIZE: usize = 256;
+/// Files extracted and embedded at the same time by `ingest_directory`
+const INGEST_CONCURRENCY: usize = 4;
+/// Candidates fetched per requested result when MMR re-ranking is enabled
+const MMR_CANDIDATE_FACTOR: u32 = 4;
+/// Named vector holding the embedding of a chunk's document title and headings
+const TITLE_VECTOR: &str = "title";
+/// Bytes of a streamed file chunked and embedded at a time by `add_large_file`
+const STREAM_SEGMENT_BYTES: usize = 256 * 1024;
+/// Characters of a document sent to the LLM for its summary
+const SUMMARY_INPUT_CHARS: usize = 12000;
+const SUMMARY_PROMPT: &str = "Summarize the document given by the user in 2-4 sentences and \
+extract up to 10 keywords, including synonyms a reader might search for. Reply with JSON only: \
+{\"summary\": \"...\", \"keywords\": [\"...\"]}";
+/// First line of a function, type or module definition, used by `chunk_code`
+static CODE_DEFINITION: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(
+        r"^\s*(?:(?:pub(?:\([^)]*\))?|export|default|async|unsafe|const|static|public|private|protected|extern(?:\s+\x22C\x22)?)\s+)*(?:fn|impl|trait|struct|enum|mod|def|class|function|func|interface)\b",
+    )
+    .unwrap()
+});
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Document {
@@ -56,81 +84,297 @@ pub struct DocumentChunk {
     pub metadata: Option<serde_json::Value>,
 }
 
+/// Connection and chunking settings, read from the `rag` section of `config.yaml`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RagConfig {
+    /// `qdrant`, or `local` or `lancedb`, which need no running Qdrant server
+    pub backend: BackendKind,
+    /// Qdrant gRPC endpoint, e.g. a Qdrant Cloud cluster URL
+    pub url: String,
+    pub api_key: Option<String>,
+    /// Directory holding the collections of the `local` and `lancedb` backends
+    pub local_path: String,
+    pub collection_name: String,
+    /// Maximum chunk length in words
+    pub chunk_size: usize,
+    /// Words shared by consecutive chunks of an over-long sentence
+    pub chunk_overlap: usize,
+    pub chunking: ChunkingMode,
+    /// Chunks at least this similar to a stored chunk are skipped on
+    /// ingestion. Chunks with identical text are always skipped.
+    pub dedup_threshold: Option<f32>,
+    /// Also index an LLM summary and keywords of each document as a separate
+    /// "summary" point, so broad queries match documents worded differently
+    pub summarize: bool,
+    /// Also store a "title" vector per chunk, embedding the document title
+    /// and heading path, so `title_weight` can rank by titles. Only used when
+    /// the collection is created.
+    pub title_vectors: bool,
+    /// Retries of Qdrant calls made while the server is unreachable
+    pub retry: RetryPolicy,
+    /// Default search options used by `search_similar`
+    #[serde(flatten)]
+    pub search: SearchOptions,
+    pub embedder: EmbedderConfig,
+}
+
+/// Relevance filtering and diversification for `search_similar_with_options`
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SearchOptions {
+    /// Drops chunks whose cosine similarity to the query is below this value
+    pub min_score: Option<f32>,
+    /// Enables maximal marginal relevance re-ranking: 1.0 ranks purely by
+    /// relevance, lower values favour chunks unlike those already selected
+    pub mmr_lambda: Option<f32>,
+    /// Share of the score taken from the "title" vector, between 0.0
+    /// (content only) and 1.0 (titles only). Needs `title_vectors`.
+    pub title_weight: Option<f32>,
+}
+
+/// How documents are split into chunks before embedding
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ChunkingMode {
+    /// Picks `Markdown` or `Code` from the file extension, `Text` otherwise
+    #[default]
+    Auto,
+    /// Paragraphs and sentences
+    Text,
+    /// Heading sections, keeping code fences intact
+    Markdown,
+    /// Function and type definitions
+    Code,
+}
+
+/// A piece of a document together with its position in the document outline
+#[derive(Debug, Clone, PartialEq)]
+pub struct Chunk {
+    pub text: String,
+    /// Enclosing Markdown headings, outermost first
+    pub heading_path: Vec<String>,
+    /// Character range of the chunk in the document text
+    pub start: usize,
+    pub end: usize,
+}
+
+impl Chunk {
+    fn plain(text: String) -> Self {
+        Self {
+            text,
+            heading_path: Vec::new(),
+            start: 0,
+            end: 0,
+        }
+    }
+}
+
+impl Default for RagConfig {
+    fn default() -> Self {
+        Self {
+            backend: BackendKind::Qdrant,
+            url: "http://localhost:6334".to_string(),
+            api_key: None,
+            local_path: "./vector_data".to_string(),
+            collection_name: "documents".to_string(),
+            chunk_size: 512,
+            chunk_overlap: 128,
+            chunking: ChunkingMode::Auto,
+            dedup_threshold: None,
+            summarize: false,
+            title_vectors: false,
+            retry: RetryPolicy::default(),
+            search: SearchOptions::default(),
+            embedder: EmbedderConfig::default(),
+        }
+    }
+}
+
+#[cfg(feature = "lancedb")]
+fn lancedb_backend(path: &str) -> Result<Arc<dyn VectorBackend>> {
+    Ok(Arc::new(crate::lancedb_backend::LanceDbBackend::new(
+        path,
+        crate::vector_backend::DistanceMetric::Cosine,
+    )))
+}
+
+#[cfg(not(feature = "lancedb"))]
+fn lancedb_backend(_path: &str) -> Result<Arc<dyn VectorBackend>> {
+    Err(Error::msg(
+        "The lancedb backend requires building with `--features lancedb`",
+    ))
+}
+
 pub struct RagServer {
-    client: Qdrant,
-    model: SentenceEmbeddingsModel,
+    backend: Arc<dyn VectorBackend>,
+    embedder: Box<dyn Embedder>,
     text_cleaner: Arc<Regex>,
     chunk_size: usize,
     chunk_overlap: usize,
+    chunking: ChunkingMode,
+    search_options: SearchOptions,
+    dedup_threshold: Option<f32>,
+    summarize: bool,
+    title_vectors: bool,
+    summarizer: Option<Arc<TokioMutex<LLMClient>>>,
+    dimension: OnceCell<usize>,
     embedding_cache: Arc<TokioRwLock<LruCache<String, Vec<f32>>>>,
     collection_name: String,
 }
 
 impl RagServer {
     pub fn new() -> Result<Self> {
-        let client = Qdrant::from_url("http://localhost:6334").build()?;
-        let model = SentenceEmbeddingsBuilder::remote(SentenceEmbeddingsModelType::AllMiniLmL6V2)
-            .with_device(tch::Device::Cpu)
-            .create_model()?;
+        Self::with_config(RagConfig::default())
+    }
+
+    pub fn with_config(config: RagConfig) -> Result<Self> {
+        let embedder = create_embedder(&config.embedder)?;
+        Self::with_embedder(config, embedder)
+    }
+
+    /// Creates a server that embeds text with a custom backend
+    pub fn with_embedder(config: RagConfig, embedder: Box<dyn Embedder>) -> Result<Self> {
+        let backend: Arc<dyn VectorBackend> = match config.backend {
+            BackendKind::Qdrant => Arc::new(QdrantBackend::with_retry(QdrantConfig {
+                url: config.url.clone(),
+                api_key: config.api_key.clone(),
+                on_disk: true,
+                on_disk_payload: true,
+                indexing_threshold: Some(20000),
+                memmap_threshold: Some(50000),
+                wal_capacity_mb: Some(32),
+                wal_segments_ahead: Some(64),
+                retry: config.retry,
+                ..QdrantConfig::default()
+            })?),
+            BackendKind::Local => Arc::new(LocalBackend::open(
+                shellexpand::tilde(&config.local_path).as_ref(),
+            )?),
+            BackendKind::LanceDb => lancedb_backend(&config.local_path)?,
+        };
+        info!("Storing RAG chunks in {}", backend.name());
+        Self::with_backend(config, embedder, backend)
+    }
+
+    /// Creates a server that stores chunks in a custom vector backend
+    pub fn with_backend(
+        config: RagConfig,
+        embedder: Box<dyn Embedder>,
+        backend: Arc<dyn VectorBackend>,
+    ) -> Result<Self> {
+        if config.chunk_overlap >= config.chunk_size {
+            return Err(Error::msg(format!(
+                "chunk_overlap ({}) must be smaller than chunk_size ({})",
+                config.chunk_overlap, config.chunk_size
+            )));
+        }
+
+        for (name, value) in [
+            ("mmr_lambda", config.search.mmr_lambda),
+            ("dedup_threshold", config.dedup_threshold),
+            ("title_weight", config.search.title_weight),
+        ] {
+            if let Some(value) = value {
+                if !(0.0..=1.0).contains(&value) {
+                    return Err(Error::msg(format!(
+                        "{} ({}) must be between 0.0 and 1.0",
+                        name, value
+                    )));
+                }
+            }
+        }
+
+        if config.search.title_weight.is_some() && !config.title_vectors {
+            return Err(Error::msg("title_weight needs title_vectors to be enabled"));
+        }
+
         let text_cleaner = Regex::new(r"[^\p{L}\p{N}\s]")?;
         let cache = LruCache::new(NonZeroUsize::new(MAX_CACHE_SIZE).unwrap());
         Ok(Self {
-            client,
-            model,
+            backend,
+            embedder,
             text_cleaner: Arc::new(text_cleaner),
-            chunk_size: 512,
-            chunk_overlap: 128,
+            chunk_size: config.chunk_size,
+            chunk_overlap: config.chunk_overlap,
+            chunking: config.chunking,
+            search_options: config.search,
+            dedup_threshold: config.dedup_threshold,
+            summarize: config.summarize,
+            title_vectors: config.title_vectors,
+            summarizer: None,
+            dimension: OnceCell::new(),
             embedding_cache: Arc::new(TokioRwLock::new(cache)),
-            collection_name: "documents".to_string(),
+            collection_name: config.collection_name,
         })
     }
 
-    pub async fn init(&self) -> Result<()> {
-        let collections = self.client.list_collections().await?;
-        let collection_exists = collections
-            .collections
-            .iter()
-            .any(|c| c.name == self.collection_name);
+    /// Sets the LLM used to summarize documents when `summarize` is enabled
+    pub fn set_summarizer(&mut self, llm_client: Arc<TokioMutex<LLMClient>>) {
+        self.summarizer = Some(llm_client);
+    }
 
-        if !collection_exists {
-            info!("Creating collection: {}", self.collection_name);
-            self.create_collection().await?;
-        } else {
-            info!("Collection {} already exists", self.collection_name);
+    /// Creates the collection sized for the embedder's vectors, or checks that
+    /// an existing collection was created with the same vector size.
+    pub async fn init(&self) -> Result<()> {
+        let dimension = self.embedding_dimension().await?;
+        match self.backend.collection_info(&self.collection_name).await? {
+            None => {
+                info!(
+                    "Creating collection {} for {}-dimensional vectors",
+                    self.collection_name, dimension
+                );
+                let names = if self.title_vectors {
+                    vec![TITLE_VECTOR.to_string()]
+                } else {
+                    Vec::new()
+                };
+                self.backend
+                    .create_collection_with_vectors(&self.collection_name, dimension, &names)
+                    .await
+            }
+            Some(info) if info.dimension != dimension => Err(Error::msg(format!(
+                "Collection '{}' stores {}-dimensional vectors but the {} embedder produces {}. \
+                 Set rag.collection_name to a new collection, or delete '{}', and re-ingest \
+                 your documents.",
+                self.collection_name,
+                info.dimension,
+                self.embedder.name(),
+                dimension,
+                self.collection_name
+            ))),
+            Some(info)
+                if self.title_vectors && !info.vector_names.iter().any(|n| n == TITLE_VECTOR) =>
+            {
+                Err(Error::msg(format!(
+                    "Collection '{}' was created without title vectors. Set \
+                     rag.collection_name to a new collection to use rag.title_vectors, \
+                     and re-ingest your documents.",
+                    self.collection_name
+                )))
+            }
+            Some(_) => {
+                info!("Collection {} already exists", self.collection_name);
+                Ok(())
+            }
         }
-
-        Ok(())
     }
 
-    async fn create_collection(&self) -> Result<()> {
-        let vector_config = VectorParams {
-            size: EMBEDDING_SIZE as u64,
-            distance: Distance::Cosine.into(),
-            on_disk: Some(true),
-            ..Default::default()
-        };
-
-        self.client
-            .create_collection(
-                qdrant_client::qdrant::CreateCollectionBuilder::new(&self.collection_name)
-                    .vectors_config(VectorsConfig {
-                        config: Some(Config::Params(vector_config)),
-                    })
-                    .optimizers_config(OptimizersConfigDiff {
-                        indexing_threshold: Some(20000),
-                        memmap_threshold: Some(50000),
-                        ..Default::default()
-                    })
-                    .wal_config(WalConfigDiff {
-                        wal_capacity_mb: Some(32),
-                        wal_segments_ahead: Some(64),
-                        ..Default::default()
-                    })
-                    .on_disk_payload(true),
-            )
-            .await?;
-
-        Ok(())
+    /// Length of the vectors produced by the configured embedder, read from
+    /// the model on first use
+    pub async fn embedding_dimension(&self) -> Result<usize> {
+        self.dimension
+            .get_or_try_init(|| async {
+                let dimension = self.embedder.dimension().await?;
+                debug!(
+                    "{} produces {}-dimensional vectors",
+                    self.embedder.name(),
+                    dimension
+                );
+                Ok::<_, Error>(dimension)
+            })
+            .await
+            .copied()
     }
 
     pub fn preprocess_text(&self, text: &str) -> String {
@@ -176,6 +420,9 @@ impl RagServer {
                         while start < sentence_words.len() {
                             let end = std::cmp::min(start + self.chunk_size, sentence_words.len());
                             chunks.push(sentence_words[start..end].join(" "));
+                            if end == sentence_words.len() {
+                                break;
+                            }
                             start = end.saturating_sub(self.chunk_overlap);
                         }
                     } else {
@@ -199,6 +446,38 @@ impl RagServer {
         chunks
     }
 
+    /// Splits a document with the configured chunking mode. In `Auto` mode the
+    /// `extension` recorded by `add_file` selects the Markdown or code chunker.
+    pub fn chunk_document(&self, doc: &Document) -> Vec<Chunk> {
+        let mode = match self.chunking {
+            ChunkingMode::Auto => {
+                let extension = doc
+                    .metadata
+                    .as_ref()
+                    .and_then(|metadata| metadata["extension"].as_str())
+                    .map(|e| e.to_lowercase());
+                match extension.as_deref() {
+                    Some("md") | Some("markdown") => ChunkingMode::Markdown,
+                    Some(e) if CODE_EXTENSIONS.contains(&e) => ChunkingMode::Code,
+                    _ => ChunkingMode::Text,
+                }
+            }
+            mode => mode,
+        };
+
+        let mut chunks = match mode {
+            ChunkingMode::Markdown => chunk_markdown(&doc.text, self.chunk_size),
+            ChunkingMode::Code => chunk_code(&doc.text, self.chunk_size),
+            _ => self
+                .split_into_chunks(&doc.text)
+                .into_iter()
+                .map(Chunk::plain)
+                .collect(),
+        };
+        locate_chunks(&doc.text, &mut chunks);
+        chunks
+    }
+
     pub async fn generate_embedding(&self, text: &str) -> Result<Vec<f32>> {
         // Check cache first
         let cached_embedding = {
@@ -210,9 +489,12 @@ impl RagServer {
             return Ok(embedding);
         }
 
-        // Generate embedding using rust-bert
-        let embeddings = self.model.encode(&[text])?;
-        let embedding = embeddings[0].clone();
+        let embedding = self
+            .embedder
+            .embed(&[text])
+            .await?
+            .pop()
+            .ok_or_else(|| Error::msg("Embedder returned no vector"))?;
 
         // Cache the result
         {
@@ -223,179 +505,1158 @@ impl RagServer {
         Ok(embedding)
     }
 
+    /// Generates embeddings for several texts at once. Cached texts are reused
+    /// and the remaining ones are encoded in a single model call.
+    pub async fn generate_embeddings(&self, texts: &[&str]) -> Result<Vec<Vec<f32>>> {
+        let mut embeddings: Vec<Option<Vec<f32>>> = {
+            let cache = self.embedding_cache.read().await;
+            texts
+                .iter()
+                .map(|text| cache.peek(*text).cloned())
+                .collect()
+        };
+
+        let missing: Vec<&str> = texts
+            .iter()
+            .zip(&embeddings)
+            .filter(|(_, embedding)| embedding.is_none())
+            .map(|(text, _)| *text)
+            .collect();
+
+        if !missing.is_empty() {
+            debug!(
+                "Encoding {} of {} texts in one batch with {}",
+                missing.len(),
+                texts.len(),
+                self.embedder.name()
+            );
+            let mut encoded = self.embedder.embed(&missing).await?.into_iter();
+
+            let mut cache = self.embedding_cache.write().await;
+            for (text, slot) in texts.iter().zip(embeddings.iter_mut()) {
+                if slot.is_none() {
+                    let embedding = encoded
+                        .next()
+                        .ok_or_else(|| Error::msg("Embedding model returned too few vectors"))?;
+                    cache.put(text.to_string(), embedding.clone());
+                    *slot = Some(embedding);
+                }
+            }
+        }
+
+        Ok(embeddings.into_iter().flatten().collect())
+    }
+
     pub async fn add_document(&mut self, doc: Document) -> Result<()> {
-        let chunks = self.split_into_chunks(&doc.text);
-
-        for (i, chunk_text) in chunks.iter().enumerate() {
-            let embedding = self.generate_embedding(chunk_text).await?;
-
-            let point = PointStruct {
-                id: Some(PointId::from(uuid::Uuid::new_v4().to_string())),
-                vectors: Some(Vectors::from(embedding)),
-                payload: {
-                    let mut payload = HashMap::new();
-                    payload.insert("text".to_string(), Value::from(chunk_text.as_str()));
-                    payload.insert("document_id".to_string(), Value::from(doc.id.as_str()));
+        self.index_document(doc).await
+    }
+
+    /// Shared by `add_document` and the concurrent directory ingestion
+    async fn index_document(&self, doc: Document) -> Result<()> {
+        self.index_part(&doc, 0, 0, self.summarize).await?;
+        Ok(())
+    }
+
+    /// Chunks, embeds and stores `doc`, whose text starts `first_chunk` chunks
+    /// and `char_offset` characters into the whole document, and returns the
+    /// number of chunks it was split into
+    async fn index_part(
+        &self,
+        doc: &Document,
+        first_chunk: usize,
+        char_offset: usize,
+        summarize: bool,
+    ) -> Result<usize> {
+        let chunks = self.chunk_document(doc);
+        if chunks.is_empty() {
+            debug!("Document {} has no text to index", doc.id);
+            return Ok(0);
+        }
+
+        let texts: Vec<&str> = chunks.iter().map(|chunk| chunk.text.as_str()).collect();
+        let embeddings = self.generate_embeddings(&texts).await?;
+
+        let metadata = match &doc.metadata {
+            Some(metadata) => Some(serde_json::to_string(metadata)?),
+            None => None,
+        };
+
+        let hashes: Vec<String> = chunks
+            .iter()
+            .map(|chunk| format!("{:x}", md5::compute(chunk.text.as_bytes())))
+            .collect();
+        let mut seen_hashes = self.stored_chunk_hashes(&hashes).await?;
+        let mut kept_embeddings: Vec<Vec<f32>> = Vec::new();
+        let mut new_chunks = Vec::new();
+
+        for (i, ((chunk, hash), embedding)) in chunks.iter().zip(hashes).zip(embeddings).enumerate()
+        {
+            if !seen_hashes.insert(hash.clone()) {
+                debug!("Skipping chunk {} of {}: identical chunk exists", i, doc.id);
+                continue;
+            }
+            if let Some(threshold) = self.dedup_threshold {
+                let duplicate_in_document = kept_embeddings
+                    .iter()
+                    .any(|kept| cosine_similarity(kept, &embedding) >= threshold);
+                if duplicate_in_document || self.has_similar_chunk(&embedding, threshold).await? {
+                    debug!("Skipping chunk {} of {}: near-duplicate exists", i, doc.id);
+                    continue;
+                }
+                kept_embeddings.push(embedding.clone());
+            }
+            new_chunks.push((i, chunk, hash, embedding));
+        }
+
+        if new_chunks.len() < chunks.len() {
+            info!(
+                "Skipped {} duplicate chunks of document {}",
+                chunks.len() - new_chunks.len(),
+                doc.id
+            );
+        }
+        if new_chunks.is_empty() {
+            return Ok(chunks.len());
+        }
+
+        let mut title_embeddings = if self.title_vectors {
+            let titles: Vec<String> = new_chunks
+                .iter()
+                .map(|(_, chunk, _, _)| chunk_title(doc, chunk))
+                .collect();
+            let titles: Vec<&str> = titles.iter().map(|title| title.as_str()).collect();
+            self.generate_embeddings(&titles).await?
+        } else {
+            Vec::new()
+        }
+        .into_iter();
+
+        let mut points: Vec<VectorPoint> = new_chunks
+            .into_iter()
+            .map(|(i, chunk, hash, embedding)| {
+                let mut payload = Payload::new();
+                payload.insert("text".to_string(), Value::from(chunk.text.as_str()));
+                payload.insert("chunk_hash".to_string(), Value::from(hash.as_str()));
+                payload.insert("document_id".to_string(), Value::from(doc.id.as_str()));
+                payload.insert(
+                    "chunk_index".to_string(),
+                    Value::from((first_chunk + i).to_string().as_str()),
+                );
+                payload.insert(
+                    "char_start".to_string(),
+                    Value::from((char_offset + chunk.start).to_string().as_str()),
+                );
+                payload.insert(
+                    "char_end".to_string(),
+                    Value::from((char_offset + chunk.end).to_string().as_str()),
+                );
+                if !chunk.heading_path.is_empty() {
                     payload.insert(
-                        "chunk_index".to_string(),
-                        Value::from(i.to_string().as_str()),
+                        "heading_path".to_string(),
+                        Value::from(chunk.heading_path.join(" > ").as_str()),
                     );
+                }
+                if let Some(metadata) = &metadata {
+                    payload.insert("metadata".to_string(), Value::from(metadata.as_str()));
+                }
+
+                let point = VectorPoint::new(uuid::Uuid::new_v4().to_string(), embedding, payload);
+                match title_embeddings.next() {
+                    Some(title_embedding) => point.with_named_vector(TITLE_VECTOR, title_embedding),
+                    None => point,
+                }
+            })
+            .collect();
+
+        if summarize {
+            match self.summary_point(doc, chunks.len(), &metadata).await {
+                Ok(Some(point)) => points.push(point),
+                Ok(None) => {}
+                Err(e) => warn!("Failed to summarize document {}: {:#}", doc.id, e),
+            }
+        }
+
+        info!("Upserting {} chunks of document {}", points.len(), doc.id);
+        self.backend.upsert(&self.collection_name, points).await?;
+
+        Ok(chunks.len())
+    }
+
+    /// Builds the "summary" point of a document: an LLM summary and keywords,
+    /// stored with `kind: summary` and the document's id and metadata. It is
+    /// indexed after the document's chunks and cited with the `Summary` heading.
+    async fn summary_point(
+        &self,
+        doc: &Document,
+        chunk_index: usize,
+        metadata: &Option<String>,
+    ) -> Result<Option<VectorPoint>> {
+        let llm_client = match &self.summarizer {
+            Some(llm_client) => llm_client,
+            None => {
+                debug!("Summaries are enabled but no LLM client is set");
+                return Ok(None);
+            }
+        };
+
+        let input: String = doc.text.chars().take(SUMMARY_INPUT_CHARS).collect();
+        let messages = vec![
+            json!({"role": "system", "content": SUMMARY_PROMPT}),
+            json!({"role": "user", "content": input}),
+        ];
+        let response = llm_client
+            .lock()
+            .await
+            .get_response(&messages, None)
+            .await?;
+        let (summary, keywords) = parse_summary(&response)?;
+
+        let text = format!("Summary: {}\nKeywords: {}", summary, keywords.join(", "));
+        let embedding = self.generate_embedding(&text).await?;
+
+        let mut payload = Payload::new();
+        payload.insert("text".to_string(), Value::from(text.as_str()));
+        payload.insert("kind".to_string(), Value::from("summary"));
+        payload.insert("document_id".to_string(), Value::from(doc.id.as_str()));
+        payload.insert(
+            "chunk_index".to_string(),
+            Value::from(chunk_index.to_string().as_str()),
+        );
+        payload.insert("heading_path".to_string(), Value::from("Summary"));
+        payload.insert(
+            "keywords".to_string(),
+            Value::from(keywords.join(", ").as_str()),
+        );
+        if let Some(metadata) = metadata {
+            payload.insert("metadata".to_string(), Value::from(metadata.as_str()));
+        }
+
+        debug!("Summarized document {}: {}", doc.id, summary);
+        Ok(Some(VectorPoint::new(
+            uuid::Uuid::new_v4().to_string(),
+            embedding,
+            payload,
+        )))
+    }
+
+    /// Which of `hashes` already belong to stored chunks
+    async fn stored_chunk_hashes(&self, hashes: &[String]) -> Result<HashSet<String>> {
+        let filter = PayloadFilter::new().matches_any("chunk_hash", hashes.to_vec());
+        let mut stored = HashSet::new();
+        let mut page_offset = None;
+
+        loop {
+            let (points, next) = self
+                .backend
+                .scroll(
+                    &self.collection_name,
+                    Some(filter.clone()),
+                    page_offset,
+                    SCROLL_PAGE_SIZE,
+                )
+                .await?;
+            stored.extend(
+                points
+                    .iter()
+                    .filter_map(|point| payload_string(&point.payload, "chunk_hash")),
+            );
+
+            match next {
+                Some(next) => page_offset = Some(next),
+                None => break,
+            }
+        }
+
+        Ok(stored)
+    }
+
+    /// True when a stored chunk has at least `threshold` cosine similarity
+    async fn has_similar_chunk(&self, embedding: &[f32], threshold: f32) -> Result<bool> {
+        let results = self
+            .backend
+            .search(
+                &self.collection_name,
+                SearchQuery::new(embedding.to_vec(), 1).score_threshold(threshold),
+            )
+            .await?;
+        Ok(!results.is_empty())
+    }
+
+    /// Extracts the text of a .md/.txt/.pdf/.html file and ingests it with its
+    /// path, modification time and content hash as metadata.
+    /// Returns the id of the new document.
+    pub async fn add_file(&mut self, path: &Path) -> Result<String> {
+        self.index_file(path).await
+    }
+
+    async fn index_file(&self, path: &Path) -> Result<String> {
+        let file = extract_file(path)
+            .await
+            .with_context(|| format!("Failed to read {}", path.display()))?;
+        if file.text.trim().is_empty() {
+            return Err(invalid_arguments(format!(
+                "No text found in {}",
+                path.display()
+            )));
+        }
+
+        let id = uuid::Uuid::new_v4().to_string();
+        info!("Ingesting {} as document {}", path.display(), id);
+        self.index_document(Document {
+            id: id.clone(),
+            metadata: Some(file.metadata()),
+            text: file.text,
+        })
+        .await?;
+
+        Ok(id)
+    }
 
-                    // Fix metadata serialization
-                    if let Some(metadata) = &doc.metadata {
-                        payload.insert(
-                            "metadata".to_string(),
-                            Value::from(serde_json::to_string(metadata)?.as_str()),
-                        );
+    /// Ingests a UTF-8 text file of any size as one document without loading
+    /// it whole. The file is read in segments of about `STREAM_SEGMENT_BYTES`
+    /// that end at a line break, and each segment is chunked, embedded and
+    /// stored before the next is read. No summary is generated. `progress`
+    /// is called after each segment with the bytes indexed so far and the
+    /// size of the file. If a segment fails, e.g. as it is not UTF-8, the
+    /// chunks stored for the earlier segments are deleted again.
+    pub async fn add_large_file<F>(
+        &mut self,
+        path: &Path,
+        mut progress: F,
+    ) -> Result<StreamedDocument>
+    where
+        F: FnMut(u64, u64),
+    {
+        let mut file = tokio::fs::File::open(path)
+            .await
+            .with_context(|| format!("Failed to read {}", path.display()))?;
+        let file_metadata = file.metadata().await?;
+        let modified = file_metadata
+            .modified()
+            .ok()
+            .map(|modified| chrono::DateTime::<chrono::Utc>::from(modified).to_rfc3339());
+        let doc_metadata = json!({
+            "source": "file",
+            "path": path.to_string_lossy(),
+            "file_name": path.file_name().map(|n| n.to_string_lossy()),
+            "extension": path.extension().map(|e| e.to_string_lossy()),
+            "modified_at": modified,
+            "size": file_metadata.len(),
+            "added_at": chrono::Utc::now().to_rfc3339(),
+        });
+
+        let mut report = StreamedDocument {
+            document_id: uuid::Uuid::new_v4().to_string(),
+            segments: 0,
+            chunks: 0,
+            bytes: 0,
+        };
+        info!(
+            "Streaming {} into document {}",
+            path.display(),
+            report.document_id
+        );
+        let mut buffer = Vec::with_capacity(STREAM_SEGMENT_BYTES);
+        let mut block = vec![0; STREAM_SEGMENT_BYTES];
+        let mut char_offset = 0;
+        let mut eof = false;
+
+        let streamed = async {
+            while !(eof && buffer.is_empty()) {
+                if !eof && buffer.len() < STREAM_SEGMENT_BYTES {
+                    let read = file
+                        .read(&mut block[..STREAM_SEGMENT_BYTES - buffer.len()])
+                        .await?;
+                    if read == 0 {
+                        eof = true;
+                    } else {
+                        buffer.extend_from_slice(&block[..read]);
                     }
+                    continue;
+                }
 
-                    payload
-                },
-            };
+                let end = if eof {
+                    buffer.len()
+                } else {
+                    segment_end(&buffer)
+                };
+                let text = String::from_utf8(buffer.drain(..end).collect())
+                    .ok()
+                    .filter(|text| !text.contains('\0'))
+                    .ok_or_else(|| {
+                        invalid_arguments(format!("{} is not a UTF-8 text file", path.display()))
+                    })?;
+                report.bytes += end as u64;
 
-            self.client
-                .upsert_points(
-                    UpsertPointsBuilder::new(&self.collection_name, vec![point])
-                        .wait(true)
-                        .build(),
+                let doc = Document {
+                    id: report.document_id.clone(),
+                    text,
+                    metadata: Some(doc_metadata.clone()),
+                };
+                report.chunks += self
+                    .index_part(&doc, report.chunks, char_offset, false)
+                    .await?;
+                report.segments += 1;
+                char_offset += doc.text.chars().count();
+                progress(report.bytes, file_metadata.len());
+            }
+            Ok::<_, Error>(())
+        }
+        .await;
+        if let Err(e) = streamed {
+            // A partial document would answer queries with half a file
+            if let Err(cleanup) = self
+                .delete_documents(std::slice::from_ref(&report.document_id))
+                .await
+            {
+                warn!(
+                    "Failed to delete the partial document {}: {:#}",
+                    report.document_id, cleanup
+                );
+            }
+            return Err(e);
+        }
+
+        if report.chunks == 0 {
+            return Err(invalid_arguments(format!(
+                "No text found in {}",
+                path.display()
+            )));
+        }
+        info!(
+            "Streamed {} bytes of {} as {} chunks",
+            report.bytes,
+            path.display(),
+            report.chunks
+        );
+        Ok(report)
+    }
+
+    /// Ingests every supported file under `dir` whose path relative to `dir`
+    /// matches `glob` (e.g. `"**/*.md"`). Files whose content hash is already
+    /// in the collection are skipped, and the chunks of a file ingested before
+    /// with different content are deleted before it is ingested again. Up to `INGEST_CONCURRENCY` files are
+    /// processed at once and `progress` is called after each file.
+    pub async fn ingest_directory<F>(
+        &mut self,
+        dir: &Path,
+        glob: Option<&str>,
+        recursive: bool,
+        mut progress: F,
+    ) -> Result<IngestReport>
+    where
+        F: FnMut(&IngestProgress),
+    {
+        let pattern = glob.map(Pattern::new).transpose()?;
+        let mut walker = WalkDir::new(dir);
+        if !recursive {
+            walker = walker.max_depth(1);
+        }
+
+        let files: Vec<PathBuf> = walker
+            .into_iter()
+            .filter_map(|entry| entry.ok())
+            .filter(|entry| entry.file_type().is_file() && is_supported_file(entry.path()))
+            .filter(|entry| match &pattern {
+                Some(pattern) => entry
+                    .path()
+                    .strip_prefix(dir)
+                    .map(|relative| pattern.matches_path(relative))
+                    .unwrap_or(false),
+                None => true,
+            })
+            .map(|entry| entry.into_path())
+            .collect();
+        info!("Found {} files to ingest in {}", files.len(), dir.display());
+
+        let documents = self.list_documents(0, usize::MAX).await?;
+        let known_hashes: HashSet<&str> = documents
+            .iter()
+            .filter_map(|doc| doc.metadata.as_ref()?["content_hash"].as_str())
+            .collect();
+        let mut documents_by_path: HashMap<&str, Vec<String>> = HashMap::new();
+        for doc in &documents {
+            if let Some(path) = doc.metadata.as_ref().and_then(|m| m["path"].as_str()) {
+                documents_by_path
+                    .entry(path)
+                    .or_default()
+                    .push(doc.document_id.clone());
+            }
+        }
+        let total = files.len();
+        let mut report = IngestReport::default();
+
+        let server = &*self;
+        let mut results = stream::iter(files)
+            .map(|path| {
+                let known_hashes = &known_hashes;
+                let documents_by_path = &documents_by_path;
+                async move {
+                    let status = match file_hash(&path).await {
+                        Ok(hash) if known_hashes.contains(hash.as_str()) => IngestStatus::Skipped,
+                        Ok(_) => {
+                            let stale = documents_by_path
+                                .get(path.to_string_lossy().as_ref())
+                                .map(Vec::as_slice)
+                                .unwrap_or_default();
+                            match server.reindex_file(&path, stale).await {
+                                Ok(_) => IngestStatus::Added,
+                                Err(e) => IngestStatus::Failed(format!("{:#}", e)),
+                            }
+                        }
+                        Err(e) => IngestStatus::Failed(e.to_string()),
+                    };
+                    (path, status)
+                }
+            })
+            .buffer_unordered(INGEST_CONCURRENCY);
+
+        while let Some((path, status)) = results.next().await {
+            match &status {
+                IngestStatus::Added => report.added += 1,
+                IngestStatus::Skipped => report.skipped += 1,
+                IngestStatus::Failed(e) => {
+                    warn!("Failed to ingest {}: {}", path.display(), e);
+                    report.failed.push((path.clone(), e.clone()));
+                }
+            }
+            progress(&IngestProgress {
+                done: report.added + report.skipped + report.failed.len(),
+                total,
+                path,
+                status,
+            });
+        }
+
+        Ok(report)
+    }
+
+    /// Deletes the `stale` documents of an earlier version of `path`, so
+    /// unchanged chunks are not skipped as duplicates of them, and ingests it
+    async fn reindex_file(&self, path: &Path, stale: &[String]) -> Result<String> {
+        if !stale.is_empty() {
+            let deleted = self.delete_documents(stale).await?;
+            info!(
+                "Deleted {} chunks of the previous version of {}",
+                deleted,
+                path.display()
+            );
+        }
+        self.index_file(path).await
+    }
+
+    /// Deletes every chunk of the given documents and returns how many
+    /// chunks were deleted
+    async fn delete_documents(&self, document_ids: &[String]) -> Result<usize> {
+        let filter = PayloadFilter::new().matches_any("document_id", document_ids.to_vec());
+        let mut ids = Vec::new();
+        let mut page_offset = None;
+        loop {
+            let (points, next) = self
+                .backend
+                .scroll(
+                    &self.collection_name,
+                    Some(filter.clone()),
+                    page_offset,
+                    SCROLL_PAGE_SIZE,
                 )
                 .await?;
+            ids.extend(points.into_iter().map(|point| point.id));
+            match next {
+                Some(next) => page_offset = Some(next),
+                None => break,
+            }
         }
 
-        Ok(())
+        if !ids.is_empty() {
+            self.backend.delete(&self.collection_name, &ids).await?;
+        }
+        Ok(ids.len())
     }
 
+    /// Searches with the `min_score` and `mmr_lambda` set in the RAG config.
+    /// `filter` keeps chunks whose payload fields equal the given values.
     pub async fn search_similar(
         &self,
         query: &str,
         limit: u32,
         filter: Option<HashMap<String, Value>>,
     ) -> Result<Vec<SearchResult>> {
-        let query_embedding = self.generate_embedding(query).await?;
+        self.search_similar_with_options(query, limit, filter, self.search_options)
+            .await
+    }
 
-        let mut search_builder = SearchPointsBuilder::new(
-            &self.collection_name,
-            query_embedding.to_vec(),
-            limit as u64,
-        )
-        .with_payload(true);
+    pub async fn search_similar_with_options(
+        &self,
+        query: &str,
+        limit: u32,
+        filter: Option<HashMap<String, Value>>,
+        options: SearchOptions,
+    ) -> Result<Vec<SearchResult>> {
+        let query_embedding = self.generate_embedding(query).await?;
 
+        // MMR picks from a larger pool of candidates, which needs their vectors
+        let candidates = match options.mmr_lambda {
+            Some(_) => limit.saturating_mul(MMR_CANDIDATE_FACTOR),
+            None => limit,
+        };
+        let mut search = SearchQuery::new(query_embedding, candidates as usize)
+            .with_vectors(options.mmr_lambda.is_some());
+        if let Some(min_score) = options.min_score {
+            search = search.score_threshold(min_score);
+        }
         if let Some(filter_map) = filter {
-            let conditions: Vec<qdrant_client::qdrant::Condition> = filter_map
-                .into_iter()
-                .map(|(k, v)| {
-                    let field_condition = qdrant_client::qdrant::FieldCondition {
-                        key: k,
-                        r#match: Some(qdrant_client::qdrant::Match {
-                            match_value: Some(MatchValue::Keyword(
-                                v.as_str().map_or("".to_string(), |s| s.to_string()),
-                            )),
-                        }),
-                        range: None,
-                        geo_radius: None,
-                        values_count: None,
-                        geo_bounding_box: None,
-                        geo_polygon: None,
-                        datetime_range: None,
-                        is_empty: None,
-                        is_null: None,
-                    };
-                    qdrant_client::qdrant::Condition {
-                        condition_one_of: Some(
-                            qdrant_client::qdrant::condition::ConditionOneOf::Field(
-                                field_condition,
-                            ),
-                        ),
-                    }
-                })
-                .collect();
-
-            let filter = Filter {
-                should: Vec::new(),
-                must: conditions,
-                must_not: Vec::new(),
-                min_should: None,
-            };
-            search_builder = search_builder.filter(filter);
+            let filter = payload_filter(filter_map);
+            search = search.filter(filter);
         }
 
-        let search_result = self.client.search_points(search_builder.build()).await?;
+        let points = match options.title_weight {
+            Some(weight) if self.title_vectors => self.search_blended(search, weight).await?,
+            _ => self.backend.search(&self.collection_name, search).await?,
+        };
 
-        let results = search_result
-            .result
+        let results = points
             .into_iter()
-            .filter_map(|scored_point| {
-                let score = scored_point.score;
-                let payload = scored_point.payload;
-
-                let text = payload.get("text").and_then(|v| match v {
-                    Value {
-                        kind: Some(Kind::StringValue(s)),
-                    } => Some(s.clone()),
-                    _ => None,
-                })?;
-
-                let document_id = payload.get("document_id").and_then(|v| match v {
-                    Value {
-                        kind: Some(Kind::StringValue(s)),
-                    } => Some(s.clone()),
-                    _ => None,
-                })?;
-
-                let chunk_index = payload.get("chunk_index").and_then(|v| match v {
-                    Value {
-                        kind: Some(Kind::StringValue(s)),
-                    } => s.parse::<usize>().ok(),
-                    _ => None,
-                })?;
-
-                let metadata = payload.get("metadata").and_then(|v| match v {
-                    Value {
-                        kind: Some(Kind::StringValue(s)),
-                    } => serde_json::from_str(s).ok(),
-                    _ => None,
-                });
+            .filter_map(|point| {
+                let payload = &point.payload;
+                let text = payload_string(payload, "text")?;
+                let document_id = payload_string(payload, "document_id")?;
+                let chunk_index = payload_string(payload, "chunk_index")?.parse().ok()?;
 
-                Some(SearchResult {
-                    text,
-                    document_id,
-                    chunk_index,
-                    score,
-                    metadata,
-                })
+                let metadata: Option<serde_json::Value> =
+                    payload_string(payload, "metadata").and_then(|s| serde_json::from_str(&s).ok());
+                let source_path = metadata
+                    .as_ref()
+                    .and_then(|metadata| metadata["path"].as_str())
+                    .map(|path| path.to_string());
+
+                Some((
+                    SearchResult {
+                        text,
+                        document_id,
+                        chunk_index,
+                        score: point.score,
+                        source_path,
+                        heading: payload_string(payload, "heading_path"),
+                        char_start: payload_string(payload, "char_start")
+                            .and_then(|s| s.parse().ok()),
+                        char_end: payload_string(payload, "char_end").and_then(|s| s.parse().ok()),
+                        metadata,
+                    },
+                    point.vector,
+                ))
             })
-            .collect();
+            .collect::<Vec<_>>();
+
+        let results = match options.mmr_lambda {
+            Some(lambda) => {
+                let candidates = results
+                    .into_iter()
+                    .map(|(result, vector)| (result, vector.unwrap_or_default()))
+                    .collect();
+                mmr_select(candidates, limit as usize, lambda)
+            }
+            None => results.into_iter().map(|(result, _)| result).collect(),
+        };
 
         Ok(results)
     }
 
-    pub async fn get_collection_info(&self) -> Result<HashMap<String, String>> {
-        let info = self.client.collection_info(&self.collection_name).await?;
+    /// Searches the "content" and "title" vectors and blends the scores,
+    /// taking `weight` of each from the title similarity. A chunk found by
+    /// only one of the searches scores 0 for the other.
+    async fn search_blended(&self, query: SearchQuery, weight: f32) -> Result<Vec<ScoredPoint>> {
+        let limit = query.limit;
+        let min_score = query.score_threshold;
+        let mut query = query;
+        query.score_threshold = None;
 
+        let content = self
+            .backend
+            .search(&self.collection_name, query.clone())
+            .await?;
+        let titles = self
+            .backend
+            .search(&self.collection_name, query.using(TITLE_VECTOR))
+            .await?;
+
+        let mut blended: HashMap<String, ScoredPoint> = HashMap::new();
+        for (mut point, share) in content
+            .into_iter()
+            .map(|point| (point, 1.0 - weight))
+            .chain(titles.into_iter().map(|point| (point, weight)))
+        {
+            let score = point.score * share;
+            match blended.get_mut(&point.id) {
+                Some(existing) => existing.score += score,
+                None => {
+                    point.score = score;
+                    blended.insert(point.id.clone(), point);
+                }
+            }
+        }
+
+        let mut points: Vec<ScoredPoint> = blended
+            .into_values()
+            .filter(|point| !matches!(min_score, Some(min_score) if point.score < min_score))
+            .collect();
+        points.sort_by(|a, b| {
+            b.score
+                .partial_cmp(&a.score)
+                .unwrap_or(std::cmp::Ordering::Equal)
+        });
+        points.truncate(limit);
+        Ok(points)
+    }
+
+    /// Lists ingested documents sorted by id, rebuilt from their chunks.
+    /// `offset` and `limit` count documents, not chunks.
+    pub async fn list_documents(&self, offset: usize, limit: usize) -> Result<Vec<DocumentInfo>> {
+        let mut documents: HashMap<String, DocumentInfo> = HashMap::new();
+        let mut page_offset = None;
+
+        loop {
+            let (points, next) = self
+                .backend
+                .scroll(&self.collection_name, None, page_offset, SCROLL_PAGE_SIZE)
+                .await?;
+            for point in points {
+                let document_id = match payload_string(&point.payload, "document_id") {
+                    Some(id) => id,
+                    None => continue,
+                };
+                let document = documents
+                    .entry(document_id)
+                    .or_insert_with_key(|id| DocumentInfo {
+                        document_id: id.clone(),
+                        chunk_count: 0,
+                        metadata: None,
+                    });
+                if payload_string(&point.payload, "kind").as_deref() != Some("summary") {
+                    document.chunk_count += 1;
+                }
+                if document.metadata.is_none() {
+                    document.metadata = payload_string(&point.payload, "metadata")
+                        .and_then(|s| serde_json::from_str(&s).ok());
+                }
+            }
+
+            match next {
+                Some(next) => page_offset = Some(next),
+                None => break,
+            }
+        }
+
+        let mut documents: Vec<DocumentInfo> = documents.into_values().collect();
+        documents.sort_by(|a, b| a.document_id.cmp(&b.document_id));
+        debug!("Collection holds {} documents", documents.len());
+
+        Ok(documents.into_iter().skip(offset).take(limit).collect())
+    }
+
+    /// Backend name and location, e.g. `local (./vector_data)`
+    pub fn backend_name(&self) -> String {
+        self.backend.name()
+    }
+
+    /// The vector backend, for other collections stored next to the index
+    pub(crate) fn backend(&self) -> Arc<dyn VectorBackend> {
+        Arc::clone(&self.backend)
+    }
+
+    /// Checks that the vector backend is reachable and returns its status
+    pub async fn health(&self) -> Result<String> {
+        self.backend.health().await
+    }
+
+    /// Number of stored chunks whose payload fields equal the given values
+    pub async fn count(&self, filter: Option<HashMap<String, Value>>) -> Result<u64> {
+        self.backend
+            .count(&self.collection_name, filter.map(payload_filter))
+            .await
+    }
+
+    /// Sets payload fields on every chunk of a document without re-embedding
+    /// it, e.g. tags or a read flag. Returns the number of chunks updated.
+    pub async fn update_document_payload(
+        &self,
+        document_id: &str,
+        patch: Payload,
+    ) -> Result<usize> {
+        let filter = PayloadFilter::new().matches("document_id", document_id);
+        let mut ids = Vec::new();
+        let mut page_offset = None;
+        loop {
+            let (points, next) = self
+                .backend
+                .scroll(
+                    &self.collection_name,
+                    Some(filter.clone()),
+                    page_offset,
+                    SCROLL_PAGE_SIZE,
+                )
+                .await?;
+            ids.extend(points.into_iter().map(|point| point.id));
+            match next {
+                Some(next) => page_offset = Some(next),
+                None => break,
+            }
+        }
+
+        for id in &ids {
+            self.backend
+                .update_payload(&self.collection_name, id, patch.clone())
+                .await?;
+        }
+        Ok(ids.len())
+    }
+
+    /// Backs up the RAG collection into `dir`, returning the snapshot file
+    pub async fn create_snapshot(&self, dir: &Path) -> Result<PathBuf> {
+        self.backend
+            .create_snapshot(&self.collection_name, dir)
+            .await
+    }
+
+    /// Replaces the RAG collection with a snapshot from `create_snapshot`
+    pub async fn restore_snapshot(&self, path: &Path) -> Result<()> {
+        self.backend
+            .restore_snapshot(&self.collection_name, path)
+            .await
+    }
+
+    pub async fn get_collection_info(&self) -> Result<HashMap<String, String>> {
         let mut result = HashMap::new();
-        if let Some(result_info) = info.result {
+        if let Some(info) = self.backend.collection_info(&self.collection_name).await? {
             result.insert("name".to_string(), self.collection_name.clone());
-            if let Some(config) = result_info.config {
-                if let Some(params) = config.params {
-                    if let Some(vector_config) = params.vectors_config {
-                        if let Some(vector_params) = vector_config.config {
-                            if let Config::Params(params) = vector_params {
-                                result.insert("vector_size".to_string(), params.size.to_string());
-                                result.insert(
-                                    "distance".to_string(),
-                                    format!("{:?}", params.distance),
-                                );
-                            }
-                        }
-                    }
+            result.insert("vector_size".to_string(), info.dimension.to_string());
+            result.insert("distance".to_string(), format!("{:?}", info.distance));
+            result.insert("points_count".to_string(), info.points_count.to_string());
+        }
+
+        Ok(result)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum IngestStatus {
+    Added,
+    /// Unchanged since it was last ingested
+    Skipped,
+    Failed(String),
+}
+
+/// Reported to the `ingest_directory` callback after each file
+#[derive(Debug, Clone)]
+pub struct IngestProgress {
+    pub done: usize,
+    pub total: usize,
+    pub path: PathBuf,
+    pub status: IngestStatus,
+}
+
+/// A file ingested by `RagServer::add_large_file`
+#[derive(Debug, Clone, Serialize)]
+pub struct StreamedDocument {
+    pub document_id: String,
+    /// Segments the file was read in
+    pub segments: usize,
+    pub chunks: usize,
+    pub bytes: u64,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct IngestReport {
+    pub added: usize,
+    pub skipped: usize,
+    pub failed: Vec<(PathBuf, String)>,
+}
+
+/// Where the next streamed segment of `buffer` ends: after its last line
+/// break, or at the last character boundary when it holds a single long line
+fn segment_end(buffer: &[u8]) -> usize {
+    match buffer.iter().rposition(|&byte| byte == b'\n') {
+        Some(newline) => newline + 1,
+        None => (1..buffer.len())
+            .rev()
+            .find(|&i| buffer[i] & 0xC0 != 0x80)
+            .unwrap_or(buffer.len()),
+    }
+}
+
+/// An ingested document as listed by `RagServer::list_documents`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DocumentInfo {
+    pub document_id: String,
+    pub chunk_count: usize,
+    pub metadata: Option<serde_json::Value>,
+}
+
+/// Text embedded as a chunk's "title" vector: the document title, taken from
+/// the `title` metadata, the file name or the id, followed by the heading path
+fn chunk_title(doc: &Document, chunk: &Chunk) -> String {
+    let metadata = doc.metadata.as_ref();
+    let title = metadata
+        .and_then(|metadata| metadata["title"].as_str())
+        .map(|title| title.to_string())
+        .or_else(|| {
+            metadata
+                .and_then(|metadata| metadata["path"].as_str())
+                .and_then(|path| Path::new(path).file_stem())
+                .map(|stem| stem.to_string_lossy().into_owned())
+        })
+        .unwrap_or_else(|| doc.id.clone());
+
+    std::iter::once(title)
+        .chain(chunk.heading_path.iter().cloned())
+        .collect::<Vec<_>>()
+        .join(" > ")
+}
+
+/// Requires each payload field to equal the given value
+fn payload_filter(fields: HashMap<String, Value>) -> PayloadFilter {
+    fields
+        .into_iter()
+        .fold(PayloadFilter::new(), |filter, (key, value)| {
+            let value = match value {
+                Value::String(s) => s,
+                other => other.to_string(),
+            };
+            filter.matches(&key, &value)
+        })
+}
+
+fn payload_string(payload: &Payload, key: &str) -> Option<String> {
+    payload.get(key)?.as_str().map(|s| s.to_string())
+}
+
+/// Splits Markdown into heading sections. Each chunk records its heading
+/// path, and fenced code blocks are never split across chunks unless a
+/// single block is longer than `max_words`.
+pub fn chunk_markdown(text: &str, max_words: usize) -> Vec<Chunk> {
+    let mut chunks = Vec::new();
+    let mut headings: Vec<(usize, String)> = Vec::new();
+    let mut blocks: Vec<String> = Vec::new();
+    let mut current = String::new();
+    let mut fence: Option<&str> = None;
+
+    let flush_block = |current: &mut String, blocks: &mut Vec<String>| {
+        if !current.trim().is_empty() {
+            blocks.push(current.trim_end().to_string());
+        }
+        current.clear();
+    };
+
+    for line in text.lines() {
+        let trimmed = line.trim_start();
+
+        if let Some(marker) = fence {
+            current.push_str(line);
+            current.push('\n');
+            if trimmed.starts_with(marker) {
+                fence = None;
+                flush_block(&mut current, &mut blocks);
+            }
+            continue;
+        }
+
+        if trimmed.starts_with("```") || trimmed.starts_with("~~~") {
+            flush_block(&mut current, &mut blocks);
+            fence = Some(&trimmed[..3]);
+            current.push_str(line);
+            current.push('\n');
+            continue;
+        }
+
+        if let Some((level, title)) = parse_heading(trimmed) {
+            flush_block(&mut current, &mut blocks);
+            let path: Vec<String> = headings.iter().map(|(_, h)| h.clone()).collect();
+            chunks.extend(pack_blocks(&blocks, max_words, "\n\n", &path));
+            blocks.clear();
+
+            headings.retain(|(l, _)| *l < level);
+            headings.push((level, title.to_string()));
+            current.push_str(line);
+            current.push('\n');
+            flush_block(&mut current, &mut blocks);
+            continue;
+        }
+
+        if trimmed.is_empty() {
+            flush_block(&mut current, &mut blocks);
+        } else {
+            current.push_str(line);
+            current.push('\n');
+        }
+    }
+
+    flush_block(&mut current, &mut blocks);
+    let path: Vec<String> = headings.iter().map(|(_, h)| h.clone()).collect();
+    chunks.extend(pack_blocks(&blocks, max_words, "\n\n", &path));
+    chunks
+}
+
+/// Splits source code before each function, method or type definition, so a
+/// definition stays in one chunk together with its doc comments and
+/// attributes. Small neighbouring definitions share a chunk.
+pub fn chunk_code(text: &str, max_words: usize) -> Vec<Chunk> {
+    let mut segments: Vec<Vec<&str>> = vec![Vec::new()];
+    for line in text.lines() {
+        if CODE_DEFINITION.is_match(line) {
+            let current = segments.last_mut().unwrap();
+            // Doc comments, attributes and decorators belong to the definition
+            let mut split = current.len();
+            while split > 0 && is_code_preamble(current[split - 1]) {
+                split -= 1;
+            }
+            let preamble = current.split_off(split);
+            segments.push(preamble);
+        }
+        segments.last_mut().unwrap().push(line);
+    }
+
+    let blocks: Vec<String> = segments
+        .iter()
+        .map(|lines| lines.join("\n").trim_matches('\n').to_string())
+        .filter(|block| !block.trim().is_empty())
+        .collect();
+    pack_blocks(&blocks, max_words, "\n\n", &[])
+}
+
+fn parse_heading(line: &str) -> Option<(usize, &str)> {
+    let level = line.chars().take_while(|c| *c == '#').count();
+    if level == 0 || level > 6 {
+        return None;
+    }
+    let rest = &line[level..];
+    if !rest.is_empty() && !rest.starts_with(char::is_whitespace) {
+        return None;
+    }
+    Some((level, rest.trim().trim_end_matches('#').trim()))
+}
+
+fn is_code_preamble(line: &str) -> bool {
+    let trimmed = line.trim_start();
+    trimmed.starts_with("//")
+        || trimmed.starts_with("/*")
+        || trimmed.starts_with('*')
+        || trimmed.starts_with("#[")
+        || trimmed.starts_with('@')
+}
+
+/// Joins consecutive blocks into chunks of at most `max_words` words. Blocks
+/// that are too long on their own are split on line boundaries.
+fn pack_blocks(
+    blocks: &[String],
+    max_words: usize,
+    separator: &str,
+    heading_path: &[String],
+) -> Vec<Chunk> {
+    let mut chunks = Vec::new();
+    let mut current = String::new();
+    let mut current_words = 0;
+
+    let mut push = |text: &mut String| {
+        if !text.trim().is_empty() {
+            chunks.push(Chunk {
+                heading_path: heading_path.to_vec(),
+                ..Chunk::plain(std::mem::take(text))
+            });
+        }
+        text.clear();
+    };
+
+    for block in blocks {
+        let words = block.split_whitespace().count();
+        if current_words + words > max_words && !current.is_empty() {
+            push(&mut current);
+            current_words = 0;
+        }
+
+        if words > max_words {
+            for line in block.lines() {
+                let line_words = line.split_whitespace().count();
+                if current_words + line_words > max_words && !current.is_empty() {
+                    push(&mut current);
+                    current_words = 0;
+                }
+                if !current.is_empty() {
+                    current.push('\n');
                 }
+                current.push_str(line);
+                current_words += line_words;
             }
-            if let Some(points_count) = result_info.points_count {
-                result.insert("points_count".to_string(), points_count.to_string());
+            continue;
+        }
+
+        if !current.is_empty() {
+            current.push_str(separator);
+        }
+        current.push_str(block);
+        current_words += words;
+    }
+    push(&mut current);
+
+    chunks
+}
+
+/// Reads the `{"summary": ..., "keywords": [...]}` reply to `SUMMARY_PROMPT`,
+/// tolerating text or code fences around the JSON object
+fn parse_summary(response: &str) -> Result<(String, Vec<String>)> {
+    let json = match (response.find('{'), response.rfind('}')) {
+        (Some(start), Some(end)) if start < end => &response[start..=end],
+        _ => return Err(Error::msg("Summary response contains no JSON object")),
+    };
+    let value: serde_json::Value =
+        serde_json::from_str(json).context("Invalid summary response")?;
+
+    let summary = value["summary"]
+        .as_str()
+        .map(str::trim)
+        .filter(|summary| !summary.is_empty())
+        .ok_or_else(|| Error::msg("Summary response has no summary"))?
+        .to_string();
+    let keywords = value["keywords"]
+        .as_array()
+        .map(|keywords| {
+            keywords
+                .iter()
+                .filter_map(|k| k.as_str())
+                .map(|k| k.trim().to_string())
+                .filter(|k| !k.is_empty())
+                .collect()
+        })
+        .unwrap_or_default();
+
+    Ok((summary, keywords))
+}
+
+/// Sets the character offsets of chunks produced from `text`. Chunkers may
+/// drop punctuation or whitespace, so each chunk is located by walking its
+/// words through the text in order.
+fn locate_chunks(text: &str, chunks: &mut [Chunk]) {
+    let mut cursor = 0;
+    for chunk in chunks.iter_mut() {
+        let mut words = chunk.text.split_whitespace();
+        let start = match words.next().and_then(|word| {
+            text[cursor..]
+                .find(word)
+                .map(|i| (cursor + i, cursor + i + word.len()))
+        }) {
+            Some(found) => found,
+            None => continue,
+        };
+        let mut end = start.1;
+        for word in words {
+            match text[end..].find(word) {
+                Some(i) => end += i + word.len(),
+                None => break,
             }
         }
 
-        Ok(result)
+        chunk.start = text[..start.0].chars().count();
+        chunk.end = chunk.start + text[start.0..end].chars().count();
+        cursor = start.0;
     }
 }
 
@@ -405,5 +1666,533 @@ pub struct SearchResult {
     pub document_id: String,
     pub chunk_index: usize,
     pub score: f32,
+    /// Path of the ingested file, for documents added with `add_file`
+    #[serde(default)]
+    pub source_path: Option<String>,
+    /// Heading path of the chunk, e.g. `Design > Architecture`
+    #[serde(default)]
+    pub heading: Option<String>,
+    /// Character range of the chunk in the document text
+    #[serde(default)]
+    pub char_start: Option<usize>,
+    #[serde(default)]
+    pub char_end: Option<usize>,
     pub metadata: Option<serde_json::Value>,
 }
+
+impl SearchResult {
+    /// Short source label, e.g. `notes/design.md §Architecture`. Paths under
+    /// the current directory are shown relative to it.
+    pub fn citation(&self) -> String {
+        let mut label = match &self.source_path {
+            Some(path) => {
+                let path = Path::new(path);
+                let relative = std::env::current_dir()
+                    .ok()
+                    .and_then(|cwd| path.strip_prefix(cwd).ok().map(Path::to_path_buf));
+                relative.as_deref().unwrap_or(path).display().to_string()
+            }
+            None => self.document_id.clone(),
+        };
+        if let Some(section) = self
+            .heading
+            .as_deref()
+            .and_then(|heading| heading.rsplit(" > ").next())
+        {
+            label.push_str(" §");
+            label.push_str(section);
+        }
+        label
+    }
+}
+
+/// Re-ranks search results with maximal marginal relevance: each step picks
+/// the candidate maximising `lambda * relevance - (1 - lambda) * similarity`
+/// to the closest already selected candidate. Candidates must be sorted by
+/// score, as returned by Qdrant.
+pub fn mmr_select(
+    mut candidates: Vec<(SearchResult, Vec<f32>)>,
+    limit: usize,
+    lambda: f32,
+) -> Vec<SearchResult> {
+    let mut selected: Vec<(SearchResult, Vec<f32>)> = Vec::new();
+
+    while selected.len() < limit && !candidates.is_empty() {
+        let mut best = 0;
+        let mut best_value = f32::NEG_INFINITY;
+        for (i, (candidate, vector)) in candidates.iter().enumerate() {
+            let redundancy = selected
+                .iter()
+                .map(|(_, chosen)| cosine_similarity(vector, chosen))
+                .fold(0.0, f32::max);
+            let value = lambda * candidate.score - (1.0 - lambda) * redundancy;
+            if value > best_value {
+                best = i;
+                best_value = value;
+            }
+        }
+        selected.push(candidates.remove(best));
+    }
+
+    selected.into_iter().map(|(result, _)| result).collect()
+}
+
+pub(crate) fn cosine_similarity(a: &[f32], b: &[f32]) -> f32 {
+    let dot: f32 = a.iter().zip(b).map(|(x, y)| x * y).sum();
+    let norm_a = a.iter().map(|x| x * x).sum::<f32>().sqrt();
+    let norm_b = b.iter().map(|x| x * x).sum::<f32>().sqrt();
+    if norm_a == 0.0 || norm_b == 0.0 {
+        0.0
+    } else {
+        dot / (norm_a * norm_b)
+    }
+}
+
+/// Formats retrieved chunks as a context block for the system prompt,
+/// each prefixed with the `[n]` marker used by `format_citations`
+pub fn build_context_prompt(results: &[SearchResult]) -> String {
+    let mut prompt = String::from(
+        "Answer using the following context when it is relevant. \
+         Cite the sources you use with their [n] markers.\n",
+    );
+    for (i, result) in results.iter().enumerate() {
+        prompt.push_str(&format!("\n[{}] {}\n", i + 1, result.text.trim()));
+    }
+    prompt
+}
+
+/// Lists the sources behind the `[n]` markers of `build_context_prompt`
+pub fn format_citations(results: &[SearchResult]) -> String {
+    let mut citations = String::from("Sources:");
+    for (i, result) in results.iter().enumerate() {
+        citations.push_str(&format!("\n[{}] {}", i + 1, result.citation()));
+    }
+    citations
+}
+
+/// Builds the `rag_search`, `rag_add` and `rag_documents` tools, which let
+/// MCP clients query and extend the index. RAG futures are not Sync, so each
+/// call runs on its own task and the handler only awaits its handle.
+pub(crate) fn rag_tools(rag_server: Arc<TokioMutex<RagServer>>) -> Vec<Tool> {
+    let search_server = Arc::clone(&rag_server);
+    let search_tool = Tool::new(
+        "rag_search".to_string(),
+        "Search the RAG knowledge base for the chunks most similar to a query".to_string(),
+        json!({
+            "type": "object",
+            "properties": {
+                "query": {
+                    "type": "string",
+                    "description": "Text to search for"
+                },
+                "limit": {
+                    "type": "integer",
+                    "description": "Maximum number of chunks (default: 5)"
+                }
+            },
+            "required": ["query"]
+        }),
+        Some(json!({
+            "type": "object",
+            "properties": {
+                "results": {
+                    "type": "array",
+                    "description": "Chunks by descending score, with text, score and source"
+                }
+            }
+        })),
+        Box::new(move |args| {
+            let rag_server = Arc::clone(&search_server);
+            Box::pin(async move {
+                let query = args["query"]
+                    .as_str()
+                    .ok_or_else(|| invalid_arguments("Invalid argument: query"))?
+                    .to_string();
+                let limit = args["limit"].as_u64().unwrap_or(5) as u32;
+                let results = tokio::spawn(async move {
+                    rag_server
+                        .lock()
+                        .await
+                        .search_similar(&query, limit, None)
+                        .await
+                })
+                .await??;
+                let results: Vec<Value> = results
+                    .iter()
+                    .map(|result| {
+                        json!({
+                            "text": result.text,
+                            "score": result.score,
+                            "document_id": result.document_id,
+                            "chunk_index": result.chunk_index,
+                            "source": result.citation(),
+                            "metadata": result.metadata,
+                        })
+                    })
+                    .collect();
+                Ok(json!({ "results": results }))
+            })
+        }),
+    );
+
+    let add_server = Arc::clone(&rag_server);
+    let add_tool = Tool::new(
+        "rag_add".to_string(),
+        "Add a text document to the RAG knowledge base".to_string(),
+        json!({
+            "type": "object",
+            "properties": {
+                "text": {
+                    "type": "string",
+                    "description": "Text of the document"
+                },
+                "title": {
+                    "type": "string",
+                    "description": "Title of the document (optional)"
+                }
+            },
+            "required": ["text"]
+        }),
+        Some(json!({
+            "type": "object",
+            "properties": {
+                "success": {
+                    "type": "boolean",
+                    "description": "Whether the document was added"
+                },
+                "document_id": {
+                    "type": "string",
+                    "description": "Id of the new document"
+                }
+            }
+        })),
+        Box::new(move |args| {
+            let rag_server = Arc::clone(&add_server);
+            Box::pin(async move {
+                let text = args["text"]
+                    .as_str()
+                    .ok_or_else(|| invalid_arguments("Invalid argument: text"))?;
+                let mut metadata = json!({
+                    "added_at": chrono::Utc::now().to_rfc3339(),
+                    "source": "mcp"
+                });
+                if let Some(title) = args["title"].as_str() {
+                    metadata["title"] = json!(title);
+                }
+                let doc = Document {
+                    id: uuid::Uuid::new_v4().to_string(),
+                    text: text.to_string(),
+                    metadata: Some(metadata),
+                };
+                let document_id = doc.id.clone();
+                tokio::spawn(async move { rag_server.lock().await.add_document(doc).await })
+                    .await??;
+                Ok(json!({ "success": true, "document_id": document_id }))
+            })
+        }),
+    );
+
+    let documents_tool = Tool::new(
+        "rag_documents".to_string(),
+        "List the documents in the RAG knowledge base".to_string(),
+        json!({
+            "type": "object",
+            "properties": {
+                "offset": {
+                    "type": "integer",
+                    "description": "Documents to skip (default: 0)"
+                },
+                "limit": {
+                    "type": "integer",
+                    "description": "Maximum number of documents (default: 20)"
+                }
+            }
+        }),
+        Some(json!({
+            "type": "object",
+            "properties": {
+                "documents": {
+                    "type": "array",
+                    "description": "Documents with their id, chunk count and metadata"
+                }
+            }
+        })),
+        Box::new(move |args| {
+            let rag_server = Arc::clone(&rag_server);
+            Box::pin(async move {
+                let offset = args["offset"].as_u64().unwrap_or(0) as usize;
+                let limit = args["limit"].as_u64().unwrap_or(20) as usize;
+                let documents = tokio::spawn(async move {
+                    rag_server.lock().await.list_documents(offset, limit).await
+                })
+                .await??;
+                Ok(json!({ "documents": documents }))
+            })
+        }),
+    );
+
+    vec![search_tool, add_tool, documents_tool]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Embeds texts by which of a few words they mention
+    struct WordEmbedder;
+
+    #[async_trait::async_trait]
+    impl Embedder for WordEmbedder {
+        fn name(&self) -> String {
+            "words".to_string()
+        }
+
+        async fn embed(&self, texts: &[&str]) -> Result<Vec<Vec<f32>>> {
+            Ok(texts
+                .iter()
+                .map(|text| {
+                    ["apple", "pear", "plum"]
+                        .iter()
+                        .map(|word| text.to_lowercase().matches(word).count() as f32 + 0.1)
+                        .collect()
+                })
+                .collect())
+        }
+    }
+
+    fn result(document_id: &str, text: &str) -> SearchResult {
+        SearchResult {
+            text: text.to_string(),
+            document_id: document_id.to_string(),
+            chunk_index: 0,
+            score: 0.9,
+            source_path: None,
+            heading: None,
+            char_start: None,
+            char_end: None,
+            metadata: None,
+        }
+    }
+
+    #[test]
+    fn test_context_prompt_and_citations() {
+        let results = vec![
+            SearchResult {
+                source_path: Some("notes/design.md".to_string()),
+                heading: Some("Design > Architecture".to_string()),
+                ..result("doc-a", "Rust is fast. ")
+            },
+            result("doc-b", "Qdrant stores vectors"),
+        ];
+
+        let prompt = build_context_prompt(&results);
+        assert!(prompt.contains("[1] Rust is fast.\n"));
+        assert!(prompt.contains("[2] Qdrant stores vectors"));
+
+        assert_eq!(
+            format_citations(&results),
+            "Sources:\n[1] notes/design.md §Architecture\n[2] doc-b"
+        );
+    }
+
+    #[test]
+    fn test_parse_summary() {
+        let response = "Here you go:\n```json\n{\"summary\": \" A design note. \", \"keywords\": [\"architecture\", \" \", \"rust\"]}\n```";
+        let (summary, keywords) = parse_summary(response).unwrap();
+        assert_eq!(summary, "A design note.");
+        assert_eq!(keywords, vec!["architecture", "rust"]);
+
+        assert!(parse_summary("no json here").is_err());
+        assert!(parse_summary("{\"keywords\": []}").is_err());
+    }
+
+    #[test]
+    fn test_chunk_title() {
+        let chunk = Chunk {
+            text: "Run cargo build".to_string(),
+            heading_path: vec!["Guide".to_string(), "Install".to_string()],
+            start: 0,
+            end: 15,
+        };
+        let doc = Document {
+            id: "doc-1".to_string(),
+            text: String::new(),
+            metadata: Some(json!({"path": "/notes/setup.md"})),
+        };
+        assert_eq!(chunk_title(&doc, &chunk), "setup > Guide > Install");
+
+        let doc = Document {
+            metadata: Some(json!({"title": "Setup guide", "path": "/notes/setup.md"})),
+            ..doc
+        };
+        assert_eq!(
+            chunk_title(&doc, &Chunk::plain("text".to_string())),
+            "Setup guide"
+        );
+    }
+
+    #[test]
+    fn test_locate_chunks() {
+        let text = "Héllo world. Second sentence here!\n\nNew paragraph.";
+        let mut chunks = vec![
+            Chunk::plain("Héllo world Second sentence here".to_string()),
+            Chunk::plain("New paragraph".to_string()),
+        ];
+        locate_chunks(text, &mut chunks);
+
+        let ranges: Vec<(usize, usize)> = chunks.iter().map(|c| (c.start, c.end)).collect();
+        assert_eq!(ranges, vec![(0, 33), (36, 49)]);
+        let chars: Vec<char> = text.chars().collect();
+        assert_eq!(chars[36..49].iter().collect::<String>(), "New paragraph");
+    }
+
+    #[test]
+    fn test_mmr_select_prefers_diverse_chunks() {
+        let scored = |id: &str, score: f32| SearchResult {
+            score,
+            ..result(id, id)
+        };
+        let candidates = vec![
+            (scored("a", 0.95), vec![1.0, 0.0]),
+            (scored("a-copy", 0.94), vec![0.99, 0.01]),
+            (scored("b", 0.80), vec![0.0, 1.0]),
+        ];
+
+        let ids = |results: Vec<SearchResult>| -> Vec<String> {
+            results.into_iter().map(|r| r.document_id).collect()
+        };
+        assert_eq!(ids(mmr_select(candidates.clone(), 2, 0.5)), vec!["a", "b"]);
+        assert_eq!(ids(mmr_select(candidates, 2, 1.0)), vec!["a", "a-copy"]);
+    }
+
+    #[test]
+    fn test_chunk_markdown_keeps_heading_path_and_fences() {
+        let text = "# Guide\n\nIntro text.\n\n## Install\n\nRun this:\n\n```bash\ncargo build\n\ncargo run\n```\n\n### Linux\n\nUse apt.\n\n## Usage\n\nStart the bot.\n";
+        let chunks = chunk_markdown(text, 100);
+
+        let paths: Vec<Vec<String>> = chunks.iter().map(|c| c.heading_path.clone()).collect();
+        assert_eq!(
+            paths,
+            vec![
+                vec!["Guide".to_string()],
+                vec!["Guide".to_string(), "Install".to_string()],
+                vec![
+                    "Guide".to_string(),
+                    "Install".to_string(),
+                    "Linux".to_string()
+                ],
+                vec!["Guide".to_string(), "Usage".to_string()],
+            ]
+        );
+        assert!(chunks[1]
+            .text
+            .contains("```bash\ncargo build\n\ncargo run\n```"));
+
+        // A heading inside a code fence is not a heading
+        let chunks = chunk_markdown("# A\n\n```\n# comment\n```\n", 100);
+        assert_eq!(chunks.len(), 1);
+        assert_eq!(chunks[0].heading_path, vec!["A".to_string()]);
+    }
+
+    #[test]
+    fn test_chunk_code_splits_on_definitions() {
+        let code = "use std::fmt;\n\n/// Adds numbers\n#[inline]\npub fn add(a: i32, b: i32) -> i32 {\n    a + b\n}\n\nasync fn fetch() {\n    todo!()\n}\n";
+        let chunks = chunk_code(code, 18);
+
+        assert_eq!(chunks.len(), 3);
+        assert_eq!(chunks[0].text, "use std::fmt;");
+        assert!(chunks[1]
+            .text
+            .starts_with("/// Adds numbers\n#[inline]\npub fn add"));
+        assert!(chunks[2].text.starts_with("async fn fetch()"));
+
+        // Small definitions are packed together
+        assert_eq!(chunk_code(code, 100).len(), 1);
+    }
+
+    #[test]
+    fn test_segment_end_keeps_lines_and_characters_whole() {
+        assert_eq!(segment_end(b"first line\nsecond li"), 11);
+        // A single long line is cut before a multi-byte character, never inside it
+        let line = "ab\u{e9}".as_bytes();
+        assert_eq!(segment_end(line), 2);
+        assert_eq!(segment_end(&line[..3]), 2);
+    }
+
+    #[tokio::test]
+    async fn test_ingest_directory_replaces_changed_files() {
+        let data_dir = tempfile::tempdir().unwrap();
+        let notes_dir = tempfile::tempdir().unwrap();
+        let mut rag_server = RagServer::with_backend(
+            RagConfig::default(),
+            Box::new(WordEmbedder),
+            Arc::new(LocalBackend::open(data_dir.path()).unwrap()),
+        )
+        .unwrap();
+        rag_server.init().await.unwrap();
+
+        let notes = notes_dir.path().join("fruit.txt");
+        std::fs::write(&notes, "Apples are red.\n\nPears are green.").unwrap();
+        let report = rag_server
+            .ingest_directory(notes_dir.path(), None, true, |_| {})
+            .await
+            .unwrap();
+        assert_eq!(report.added, 1);
+
+        // Unchanged files are skipped
+        let report = rag_server
+            .ingest_directory(notes_dir.path(), None, true, |_| {})
+            .await
+            .unwrap();
+        assert_eq!(report.skipped, 1);
+
+        std::fs::write(&notes, "Apples are red.\n\nPlums are purple.").unwrap();
+        let report = rag_server
+            .ingest_directory(notes_dir.path(), None, true, |_| {})
+            .await
+            .unwrap();
+        assert_eq!(report.added, 1);
+
+        let documents = rag_server.list_documents(0, usize::MAX).await.unwrap();
+        assert_eq!(documents.len(), 1);
+        let results = rag_server.search_similar("pear", 10, None).await.unwrap();
+        let texts: Vec<&str> = results.iter().map(|r| r.text.as_str()).collect();
+        assert!(texts.iter().all(|text| !text.contains("Pears")));
+        assert!(texts.iter().any(|text| text.contains("Apples")));
+        assert!(texts.iter().any(|text| text.contains("Plums")));
+    }
+
+    #[tokio::test]
+    async fn test_add_large_file_removes_partial_document() {
+        let data_dir = tempfile::tempdir().unwrap();
+        let notes_dir = tempfile::tempdir().unwrap();
+        let mut rag_server = RagServer::with_backend(
+            RagConfig::default(),
+            Box::new(WordEmbedder),
+            Arc::new(LocalBackend::open(data_dir.path()).unwrap()),
+        )
+        .unwrap();
+        rag_server.init().await.unwrap();
+
+        // The first segment is text, the second is not
+        let mut content = "Apples and pears.\n".repeat(STREAM_SEGMENT_BYTES / 16);
+        content.push_str("Plums.\n");
+        let mut bytes = content.into_bytes();
+        bytes.extend_from_slice(&[0xff, 0xfe, b'\n']);
+        let path = notes_dir.path().join("fruit.txt");
+        std::fs::write(&path, bytes).unwrap();
+
+        let mut segments = 0;
+        let error = rag_server
+            .add_large_file(&path, |_, _| segments += 1)
+            .await
+            .unwrap_err();
+        assert!(error.to_string().contains("is not a UTF-8 text file"));
+        assert_eq!(segments, 1);
+        assert!(rag_server
+            .list_documents(0, usize::MAX)
+            .await
+            .unwrap()
+            .is_empty());
+    }
+}
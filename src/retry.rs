@@ -0,0 +1,263 @@
+//! Retry Module: When and how often failed calls are tried again
+//!
+//! This module provides:
+//! - `RetryPolicy`: retries with exponential backoff and optional jitter,
+//!   read from the `retry` of a server entry in `servers_config.json` and
+//!   of the `rag` and `vector_db` sections of `config.yaml`
+//! - `RetryPolicy::run`: the one retry loop, given a predicate saying which
+//!   errors are worth another attempt
+//! - `permanent` and `is_transient`: errors a retry cannot fix, such as
+//!   unknown tools or invalid arguments, and the predicate of tool calls,
+//!   which follows the `retryable` of a `ToolError` and retries untyped
+//!   errors only if they come from a dropped connection or a timeout
+//!
+//! Tool calls are retried in one place only, `McpServer`, so a failing
+//! call is not multiplied by nested loops.
+//!
+//! Author: arkSong <arksong2018@gmail.com>
+//! Version: 1.0.0
+//! License: MIT
+
+use anyhow::{Error, Result};
+use log::warn;
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::future::Future;
+use std::io;
+use std::time::Duration;
+
+use crate::protocol::JsonRpcError;
+use crate::tool_error::ToolError;
+
+/// An error a retry cannot fix
+#[derive(Debug, Clone, PartialEq)]
+pub struct Permanent(pub String);
+
+impl fmt::Display for Permanent {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl std::error::Error for Permanent {}
+
+/// An error with `message` that is not retried
+pub fn permanent(message: impl Into<String>) -> Error {
+    Error::new(Permanent(message.into()))
+}
+
+/// Whether a failed tool call may succeed when made again: what a
+/// `ToolError` says, JSON-RPC errors other than those about the request
+/// itself, and I/O or HTTP errors of connections and timeouts. Any other
+/// error, such as one made with `Error::msg`, is not retried.
+pub fn is_transient(error: &Error) -> bool {
+    if let Some(tool_error) = error.downcast_ref::<ToolError>() {
+        return tool_error.retryable;
+    }
+    if error.downcast_ref::<Permanent>().is_some() {
+        return false;
+    }
+    match error.downcast_ref::<JsonRpcError>() {
+        Some(e) => !matches!(
+            e.code,
+            JsonRpcError::PARSE_ERROR
+                | JsonRpcError::INVALID_REQUEST
+                | JsonRpcError::METHOD_NOT_FOUND
+                | JsonRpcError::INVALID_PARAMS
+        ),
+        None => error.chain().any(is_connection_failure),
+    }
+}
+
+/// Whether `cause` is a connection that failed or an operation that timed
+/// out
+fn is_connection_failure(cause: &(dyn std::error::Error + 'static)) -> bool {
+    if let Some(e) = cause.downcast_ref::<io::Error>() {
+        return matches!(
+            e.kind(),
+            io::ErrorKind::TimedOut
+                | io::ErrorKind::ConnectionRefused
+                | io::ErrorKind::ConnectionReset
+                | io::ErrorKind::ConnectionAborted
+                | io::ErrorKind::BrokenPipe
+                | io::ErrorKind::Interrupted
+        );
+    }
+    if let Some(e) = cause.downcast_ref::<reqwest::Error>() {
+        return e.is_connect() || e.is_timeout();
+    }
+    cause.is::<tokio::time::error::Elapsed>()
+}
+
+/// Retries of failed calls, e.g.
+/// `{"max_retries": 2, "initial_backoff_ms": 500, "jitter": 0.2}`
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RetryPolicy {
+    /// Retries after the first attempt; 0 disables retrying
+    pub max_retries: u32,
+    /// Delay before the first retry, doubled for each further one
+    pub initial_backoff_ms: u64,
+    pub max_backoff_ms: u64,
+    /// Share of each delay added or taken off at random, from 0 to 1, so
+    /// that clients failing together do not retry together
+    pub jitter: f64,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_retries: 3,
+            initial_backoff_ms: 200,
+            max_backoff_ms: 5000,
+            jitter: 0.0,
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// `attempts` attempts in all with the same `delay` between them
+    pub fn fixed(attempts: u32, delay: Duration) -> Self {
+        let delay_ms = delay.as_millis() as u64;
+        Self {
+            max_retries: attempts.saturating_sub(1),
+            initial_backoff_ms: delay_ms,
+            max_backoff_ms: delay_ms,
+            jitter: 0.0,
+        }
+    }
+
+    /// A single attempt
+    pub fn none() -> Self {
+        Self::fixed(1, Duration::ZERO)
+    }
+
+    /// Delay before retry number `retry`, counting from 0
+    pub fn backoff(&self, retry: u32) -> Duration {
+        let delay = self
+            .initial_backoff_ms
+            .saturating_mul(1u64 << retry.min(20))
+            .min(self.max_backoff_ms);
+        let jitter = self.jitter.clamp(0.0, 1.0);
+        if jitter == 0.0 {
+            return Duration::from_millis(delay);
+        }
+        let factor = 1.0 + rand::thread_rng().gen_range(-jitter..=jitter);
+        Duration::from_millis((delay as f64 * factor).round() as u64)
+    }
+
+    /// Runs `call` until it succeeds, fails with an error `retryable`
+    /// rejects or has been retried `max_retries` times. `what` names the
+    /// call in the log.
+    pub async fn run<T, F, Fut, P>(&self, what: &str, retryable: P, mut call: F) -> Result<T>
+    where
+        F: FnMut() -> Fut,
+        Fut: Future<Output = Result<T>>,
+        P: Fn(&Error) -> bool,
+    {
+        let mut retry = 0;
+        loop {
+            match call().await {
+                Err(error) if retry < self.max_retries && retryable(&error) => {
+                    let delay = self.backoff(retry);
+                    warn!("{} failed: {:#}; retrying in {:?}", what, error, delay);
+                    tokio::time::sleep(delay).await;
+                    retry += 1;
+                }
+                result => return result,
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+    use std::sync::atomic::{AtomicU32, Ordering};
+
+    #[test]
+    fn test_backoff() {
+        let policy: RetryPolicy = serde_json::from_value(json!({
+            "max_retries": 4,
+            "initial_backoff_ms": 100,
+            "max_backoff_ms": 350
+        }))
+        .unwrap();
+        assert_eq!(policy.max_retries, 4);
+        assert_eq!(policy.backoff(0), Duration::from_millis(100));
+        assert_eq!(policy.backoff(1), Duration::from_millis(200));
+        assert_eq!(policy.backoff(2), Duration::from_millis(350));
+
+        // Jitter stays within its share of the delay
+        let policy = RetryPolicy {
+            jitter: 0.1,
+            ..RetryPolicy::default()
+        };
+        for _ in 0..20 {
+            let delay = policy.backoff(0).as_millis();
+            assert!((180..=220).contains(&delay), "{}", delay);
+        }
+        let policy = RetryPolicy::fixed(3, Duration::from_millis(5));
+        assert_eq!(policy.max_retries, 2);
+        assert_eq!(policy.backoff(9), Duration::from_millis(5));
+        assert_eq!(RetryPolicy::none().max_retries, 0);
+    }
+
+    #[test]
+    fn test_transient_errors() {
+        assert!(!is_transient(&Error::msg("Connection reset")));
+        let reset = io::Error::from(io::ErrorKind::ConnectionReset);
+        assert!(is_transient(&Error::new(reset).context("Fetching failed")));
+        let missing = io::Error::from(io::ErrorKind::NotFound);
+        assert!(!is_transient(&Error::new(missing)));
+        assert!(!is_transient(&permanent("Tool not found: x")));
+        let invalid = JsonRpcError::new(JsonRpcError::INVALID_PARAMS, "Unknown tool");
+        assert!(!is_transient(
+            &Error::new(invalid).context("MCP server x failed")
+        ));
+        let internal = JsonRpcError::new(JsonRpcError::INTERNAL_ERROR, "Database is locked");
+        assert!(is_transient(&Error::new(internal)));
+    }
+
+    #[tokio::test]
+    async fn test_run() {
+        let policy = RetryPolicy::fixed(3, Duration::ZERO);
+        let calls = AtomicU32::new(0);
+        let result = policy
+            .run("flaky", is_transient, || async {
+                match calls.fetch_add(1, Ordering::SeqCst) {
+                    0 | 1 => Err(io::Error::from(io::ErrorKind::TimedOut).into()),
+                    n => Ok(n),
+                }
+            })
+            .await;
+        assert_eq!(result.unwrap(), 2);
+
+        // Errors the predicate rejects end the call at once
+        calls.store(0, Ordering::SeqCst);
+        let result: Result<()> = policy
+            .run("missing", is_transient, || async {
+                calls.fetch_add(1, Ordering::SeqCst);
+                Err(permanent("Tool not found: missing"))
+            })
+            .await;
+        assert_eq!(result.unwrap_err().to_string(), "Tool not found: missing");
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+
+        // The last error is returned once the retries are used up
+        calls.store(0, Ordering::SeqCst);
+        let result: Result<()> = policy
+            .run(
+                "down",
+                |_| true,
+                || async {
+                    let n = calls.fetch_add(1, Ordering::SeqCst);
+                    Err(Error::msg(format!("Attempt {}", n + 1)))
+                },
+            )
+            .await;
+        assert_eq!(result.unwrap_err().to_string(), "Attempt 3");
+    }
+}
@@ -0,0 +1,878 @@
+//! Server Module: Built-in MCP servers and their tools
+//!
+//! This module provides:
+//! - Creation of the servers listed in `config/servers_config.json`
+//! - Registration of the built-in tools and resources (memory, SQLite, file, puppeteer)
+//! - Browser tools driving headless Chromium, configured by the server's
+//!   `BrowserConfig`
+//! - Tools of the WebAssembly plugins in the directory named in the
+//!   `plugins` server's `args`
+//! - SQLite tools that run against the database named in the server's `args`
+//! - File tools below the directory named in the server's `args`, limited by
+//!   its `FileServerPolicy`
+//! - External MCP servers, spawned from the configured `command` and used
+//!   through a `StdioClient`, with health checks and restarts for the
+//!   `ServerManager`
+//! - The `rag` server with the RAG tools, served by `serve --stdio`
+//! - Tool execution, retried as the server's `retry` policy says, and
+//!   server cleanup
+//! - Optional checking of tool results against their output schemas
+//!   (`validate_output` in the server's configuration)
+//! - The server's `tool_policy`: denied tools are neither listed nor run
+//! - Lookup of tools by bare or `server/tool` name across servers, and
+//!   diagnostics for tool names offered by several servers
+//!
+//! Key Components:
+//! - `Server`: A named MCP server instance with its registered tools
+//!
+//! Author: arkSong <arksong2018@gmail.com>
+//! Version: 1.0.0
+//! License: MIT
+
+use std::collections::HashMap;
+use std::path::Path;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use anyhow::{Error, Result};
+use log::{debug, info, warn};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use tokio::sync::Mutex;
+use tokio::task::JoinHandle;
+
+use crate::browser_server::{browser_resource, BrowserServer};
+use crate::file_policy::{FileServerPolicy, Sandbox};
+use crate::file_server::{file_resource, filesystem_resource, index_tool, FileServer};
+use crate::mcp_server::{McpServer, ProgressSink, Resource, Tool, ToolId, ToolResult};
+use crate::plugin_server::{register_plugin_tools, PluginLimits};
+use crate::protocol::{Capabilities, GetPromptResult, PromptSchema, ResourceSchema, ToolSchema};
+use crate::rag_server::{rag_tools, RagServer};
+use crate::retry::RetryPolicy;
+use crate::sqlite_server::{database_resource, spawn_expiry_sweeper, SqliteOptions, SqliteServer};
+use crate::stdio_server::{StdioClient, DEFAULT_REQUEST_TIMEOUT};
+use crate::tool_error::{ToolError, ToolErrorKind};
+use crate::tool_policy::{Permission, ToolPolicy};
+
+/// Commands that select a built-in server; any other command is spawned as
+/// an external MCP server
+const BUILT_IN_COMMANDS: [&str; 5] = ["memory", "sqlite", "file", "puppeteer", "plugins"];
+
+/// Method groups the built-in servers offer; none of them has prompts
+const BUILT_IN_CAPABILITIES: Capabilities = Capabilities {
+    tools: true,
+    resources: true,
+    prompts: false,
+    notifications: true,
+};
+
+/// Namespace of the memory server's pairs in an SQLite `kv_store`
+const MEMORY_NAMESPACE: &str = "memory";
+
+/// Arguments of `memory_set`
+#[derive(Debug, Deserialize, JsonSchema)]
+struct MemorySetArgs {
+    /// Key to store the value under
+    key: String,
+    /// Value to store
+    value: String,
+}
+
+/// Result of `memory_set`
+#[derive(Serialize, JsonSchema)]
+struct MemorySetResult {
+    /// Whether the operation was successful
+    success: bool,
+}
+
+/// Arguments of `memory_get`
+#[derive(Debug, Deserialize, JsonSchema)]
+struct MemoryGetArgs {
+    /// Key to retrieve the value for
+    key: String,
+}
+
+/// Result of `memory_get`
+#[derive(Serialize, JsonSchema)]
+struct MemoryGetResult {
+    /// The retrieved value
+    value: String,
+    /// Whether the key exists
+    exists: bool,
+}
+
+/// Storage behind the memory server's tools and resource
+#[derive(Clone)]
+enum MemoryStore {
+    /// Lost when the process exits
+    InProcess(Arc<Mutex<HashMap<String, String>>>),
+    /// `kv_store` of the SQLite database given as `database` in the memory
+    /// server's configuration, surviving restarts
+    Sqlite(Arc<SqliteServer>),
+}
+
+impl MemoryStore {
+    async fn set(&self, key: &str, value: &str) -> Result<()> {
+        match self {
+            Self::InProcess(store) => {
+                store
+                    .lock()
+                    .await
+                    .insert(key.to_string(), value.to_string());
+            }
+            Self::Sqlite(server) => {
+                server.set_with(MEMORY_NAMESPACE, key, value, None).await?;
+            }
+        }
+        Ok(())
+    }
+
+    async fn get(&self, key: &str) -> Result<Option<String>> {
+        match self {
+            Self::InProcess(store) => Ok(store.lock().await.get(key).cloned()),
+            Self::Sqlite(server) => {
+                let (value, found) = server.get_in(MEMORY_NAMESPACE, key).await?;
+                Ok(if found { Some(value) } else { None })
+            }
+        }
+    }
+
+    async fn keys(&self) -> Result<Vec<String>> {
+        match self {
+            Self::InProcess(store) => Ok(store.lock().await.keys().cloned().collect()),
+            Self::Sqlite(server) => server.list_keys(MEMORY_NAMESPACE, None).await,
+        }
+    }
+}
+
+/// Represents a server instance in the system
+/// Manages server configuration, tools, and resources
+pub struct Server {
+    pub name: String,                       // Server name/identifier
+    pub config: Value,                      // Server configuration
+    pub mcp_server: McpServer,              // MCP server instance
+    cleanup_lock: Arc<Mutex<()>>,           // Lock for cleanup operations
+    memory_store: Option<MemoryStore>,      // Storage of the memory server
+    sqlite_sweeper: Option<JoinHandle<()>>, // Deletes expired SQLite key-value pairs
+    file_sandbox: Option<Arc<Sandbox>>,     // Root and policy of the file tools
+    browser: Option<Arc<BrowserServer>>,    // Chromium page of the puppeteer tools
+    stdio_client: Option<Arc<StdioClient>>, // Connection to an external MCP server
+    tool_policy: ToolPolicy,                // Tools that need approval or are denied
+    retry_policy: RetryPolicy,              // How failed tool calls are retried
+}
+
+impl Server {
+    /// Creates a new server instance with the given name and configuration
+    /// Initializes the server and registers appropriate tools and resources
+    pub async fn new(name: String, config: Value) -> Result<Self> {
+        info!("Creating new server: {}", name);
+
+        // Get server configuration
+        let command = config["command"].as_str().ok_or_else(|| {
+            Error::msg(format!(
+                "Missing command in server configuration for {}",
+                name
+            ))
+        })?;
+
+        let args = config["args"].as_array().ok_or_else(|| {
+            Error::msg(format!("Missing args in server configuration for {}", name))
+        })?;
+
+        let env: HashMap<String, String> = config["env"]
+            .as_object()
+            .map(|env| {
+                env.iter()
+                    .map(|(k, v)| (k.clone(), v.as_str().unwrap_or("").to_string()))
+                    .collect()
+            })
+            .unwrap_or_default();
+
+        info!("Initializing server {} with command: {}", name, command);
+
+        // Results that do not match a tool's output schema fail the call
+        let validate_output = config["validate_output"].as_bool().unwrap_or(false);
+        let tool_policy: ToolPolicy = match config.get("tool_policy") {
+            Some(policy) => serde_json::from_value(policy.clone()).map_err(|e| {
+                Error::msg(format!("Invalid tool policy of server {}: {}", name, e))
+            })?,
+            None => ToolPolicy::default(),
+        };
+        let retry_policy: RetryPolicy = match config.get("retry") {
+            Some(policy) => serde_json::from_value(policy.clone()).map_err(|e| {
+                Error::msg(format!("Invalid retry policy of server {}: {}", name, e))
+            })?,
+            None => RetryPolicy::default(),
+        };
+
+        let mut mcp_server = McpServer::with_capabilities(BUILT_IN_CAPABILITIES).named(&name);
+        mcp_server.set_output_validation(validate_output);
+        enforce_tool_policy(&mut mcp_server, &tool_policy).await;
+        mcp_server.initialize().await?;
+
+        // Register tools and resources based on server type
+        let mut server = Self {
+            name: name.clone(),
+            config: config.clone(),
+            mcp_server,
+            cleanup_lock: Arc::new(Mutex::new(())),
+            memory_store: None,
+            sqlite_sweeper: None,
+            file_sandbox: None,
+            browser: None,
+            stdio_client: None,
+            tool_policy: tool_policy.clone(),
+            retry_policy,
+        };
+
+        if !BUILT_IN_COMMANDS.contains(&command) {
+            let args: Vec<String> = args
+                .iter()
+                .map(|arg| arg.as_str().unwrap_or_default().to_string())
+                .collect();
+            let timeout = config["timeout"]
+                .as_u64()
+                .map_or(DEFAULT_REQUEST_TIMEOUT, Duration::from_secs);
+            let client = StdioClient::spawn(&name, command, &args, &env, timeout).await?;
+            // Offer what the external server announced during its handshake
+            server.mcp_server = McpServer::with_capabilities(client.capabilities()).named(&name);
+            server.mcp_server.set_output_validation(validate_output);
+            enforce_tool_policy(&mut server.mcp_server, &tool_policy).await;
+            server.mcp_server.initialize().await?;
+            client.register_with(&mut server.mcp_server).await?;
+            server.stdio_client = Some(client);
+            info!("Server {} initialized successfully", name);
+            return Ok(server);
+        }
+
+        match name.as_str() {
+            "memory" => {
+                // Keep the memory in an SQLite database if one is configured
+                let memory_store = match config["database"].as_str() {
+                    Some(db_path) => {
+                        info!("Persisting memory in {}", db_path);
+                        MemoryStore::Sqlite(Arc::new(SqliteServer::new(db_path).await?))
+                    }
+                    None => MemoryStore::InProcess(Arc::new(Mutex::new(HashMap::new()))),
+                };
+
+                // Register memory tools
+                let memory_store_clone = memory_store.clone();
+                let set_tool = Tool::typed(
+                    "memory_set",
+                    "Set a value in memory",
+                    move |args: MemorySetArgs| {
+                        let memory_store_clone = memory_store_clone.clone();
+                        async move {
+                            info!("memory_set called with args: {:?}", args);
+                            memory_store_clone.set(&args.key, &args.value).await?;
+                            Ok(MemorySetResult { success: true })
+                        }
+                    },
+                );
+                server.mcp_server.register_tool(set_tool).await?;
+
+                let memory_store_clone = memory_store.clone();
+                let get_tool = Tool::typed(
+                    "memory_get",
+                    "Get a value from memory",
+                    move |args: MemoryGetArgs| {
+                        let memory_store_clone = memory_store_clone.clone();
+                        async move {
+                            info!("memory_get looking for key: {}", args.key);
+                            match memory_store_clone.get(&args.key).await? {
+                                Some(value) => {
+                                    info!("memory_get found value: {}", value);
+                                    Ok(MemoryGetResult {
+                                        value,
+                                        exists: true,
+                                    })
+                                }
+                                None => {
+                                    info!("memory_get key not found: {}", args.key);
+                                    Ok(MemoryGetResult {
+                                        value: String::new(),
+                                        exists: false,
+                                    })
+                                }
+                            }
+                        }
+                    },
+                );
+                server.mcp_server.register_tool(get_tool).await?;
+
+                // Register memory resources
+                let memory_store_clone = memory_store.clone();
+                let memory_resource = Resource::new(
+                    "memory_store".to_string(),
+                    "In-memory key-value store".to_string(),
+                    Some(json!({
+                        "type": "object",
+                        "properties": {
+                            "action": {
+                                "type": "string",
+                                "description": "Action to perform (get/set/list)",
+                                "enum": ["get", "set", "list"]
+                            },
+                            "key": {
+                                "type": "string",
+                                "description": "Key to access"
+                            },
+                            "value": {
+                                "type": "string",
+                                "description": "Value to set"
+                            }
+                        },
+                        "required": ["action"]
+                    })),
+                    Some(json!({
+                        "type": "object",
+                        "properties": {
+                            "size": {
+                                "type": "integer",
+                                "description": "Number of items in the store"
+                            },
+                            "keys": {
+                                "type": "array",
+                                "description": "List of all keys in the store",
+                                "items": {
+                                    "type": "string"
+                                }
+                            },
+                            "value": {
+                                "type": "string",
+                                "description": "Retrieved value"
+                            },
+                            "success": {
+                                "type": "boolean",
+                                "description": "Whether the operation was successful"
+                            }
+                        }
+                    })),
+                    Box::new(move |args| {
+                        let store = memory_store_clone.clone();
+                        Box::pin(async move {
+                            match args.get("action").and_then(|v| v.as_str()) {
+                                Some("list") => {
+                                    let keys = store.keys().await?;
+                                    Ok(json!({
+                                        "size": keys.len(),
+                                        "keys": keys,
+                                        "success": true
+                                    }))
+                                }
+                                Some("get") => {
+                                    let key = args
+                                        .get("key")
+                                        .and_then(|v| v.as_str())
+                                        .ok_or_else(|| Error::msg("Missing key argument"))?;
+                                    let value = store.get(key).await?;
+                                    Ok(json!({
+                                        "success": value.is_some(),
+                                        "value": value.unwrap_or_default()
+                                    }))
+                                }
+                                Some("set") => {
+                                    let key = args
+                                        .get("key")
+                                        .and_then(|v| v.as_str())
+                                        .ok_or_else(|| Error::msg("Missing key argument"))?;
+                                    let value = args
+                                        .get("value")
+                                        .and_then(|v| v.as_str())
+                                        .ok_or_else(|| Error::msg("Missing value argument"))?;
+                                    store.set(key, value).await?;
+                                    Ok(json!({ "success": true }))
+                                }
+                                _ => Err(Error::msg("Invalid action")),
+                            }
+                        })
+                    }),
+                );
+                server.mcp_server.register_resource(memory_resource).await?;
+
+                // Store the memory store in the server
+                server.memory_store = Some(memory_store);
+            }
+            "sqlite" => {
+                // The first argument is the database file
+                let db_path = config["args"][0].as_str().unwrap_or("test.db");
+                // read_only and the allowlists sit next to command and args
+                let options: SqliteOptions = serde_json::from_value(config.clone())
+                    .map_err(|e| Error::msg(format!("Invalid sqlite server options: {}", e)))?;
+                info!("Registering SQLite tools for {}", db_path);
+                let options = Arc::new(options);
+                let pool = SqliteServer::open(db_path, &options).await?;
+                SqliteServer::register_tools(
+                    &mut server.mcp_server,
+                    pool.clone(),
+                    Arc::clone(&options),
+                )
+                .await?;
+
+                if !options.read_only {
+                    server.sqlite_sweeper = Some(spawn_expiry_sweeper(pool.clone()));
+                }
+
+                // Register SQLite resources
+                let sqlite_resource =
+                    database_resource(pool.clone(), db_path, Arc::clone(&options));
+                server.mcp_server.register_resource(sqlite_resource).await?;
+
+                // Register file system resources
+                let filesystem_resource = Resource::new(
+                    "filesystem".to_string(),
+                    "Local file system access".to_string(),
+                    Some(json!({
+                        "type": "object",
+                        "properties": {
+                            "action": {
+                                "type": "string",
+                                "description": "Action to perform (cwd/space)",
+                                "enum": ["cwd", "space"]
+                            }
+                        },
+                        "required": ["action"]
+                    })),
+                    Some(json!({
+                        "type": "object",
+                        "properties": {
+                            "current_directory": {
+                                "type": "string",
+                                "description": "Current working directory"
+                            },
+                            "available_space": {
+                                "type": "integer",
+                                "description": "Available disk space in bytes"
+                            }
+                        }
+                    })),
+                    Box::new(|args| {
+                        Box::pin(async move {
+                            match args.get("action").and_then(|v| v.as_str()) {
+                                Some("cwd") => {
+                                    let cwd = std::env::current_dir()
+                                        .unwrap_or_default()
+                                        .to_string_lossy()
+                                        .to_string();
+                                    Ok(json!({
+                                        "current_directory": cwd
+                                    }))
+                                }
+                                Some("space") => Ok(json!({
+                                    "available_space": 0 // TODO: Implement actual disk space check
+                                })),
+                                _ => Err(Error::msg("Invalid action")),
+                            }
+                        })
+                    }),
+                );
+                server
+                    .mcp_server
+                    .register_resource(filesystem_resource)
+                    .await?;
+
+                // Register browser resources
+                let browser_resource = Resource::new(
+                    "browser".to_string(),
+                    "Browser automation resources".to_string(),
+                    Some(json!({
+                        "type": "object",
+                        "properties": {
+                            "action": {
+                                "type": "string",
+                                "description": "Action to perform (url/viewport)",
+                                "enum": ["url", "viewport"]
+                            }
+                        },
+                        "required": ["action"]
+                    })),
+                    Some(json!({
+                        "type": "object",
+                        "properties": {
+                            "current_url": {
+                                "type": "string",
+                                "description": "Current page URL"
+                            },
+                            "viewport": {
+                                "type": "object",
+                                "description": "Browser viewport dimensions",
+                                "properties": {
+                                    "width": {
+                                        "type": "integer",
+                                        "description": "Viewport width in pixels"
+                                    },
+                                    "height": {
+                                        "type": "integer",
+                                        "description": "Viewport height in pixels"
+                                    }
+                                }
+                            }
+                        }
+                    })),
+                    Box::new(|args| {
+                        Box::pin(async move {
+                            match args.get("action").and_then(|v| v.as_str()) {
+                                Some("url") => Ok(json!({
+                                    "current_url": "about:blank"
+                                })),
+                                Some("viewport") => Ok(json!({
+                                    "viewport": {
+                                        "width": 1024,
+                                        "height": 768
+                                    }
+                                })),
+                                _ => Err(Error::msg("Invalid action")),
+                            }
+                        })
+                    }),
+                );
+                server
+                    .mcp_server
+                    .register_resource(browser_resource)
+                    .await?;
+            }
+            "file" => {
+                // The first argument is the root directory of the file tools
+                let root_path = config["args"][0].as_str().unwrap_or(".");
+                // read_only, allow, deny and max_write_size sit next to command and args
+                let policy: FileServerPolicy = serde_json::from_value(config.clone())
+                    .map_err(|e| Error::msg(format!("Invalid file server policy: {}", e)))?;
+                info!("Registering file tools for {}", root_path);
+                let sandbox =
+                    FileServer::register_tools(&mut server.mcp_server, root_path.into(), policy)
+                        .await?;
+
+                // Register file system resources
+                server
+                    .mcp_server
+                    .register_resource(filesystem_resource(Arc::clone(&sandbox)))
+                    .await?;
+                server
+                    .mcp_server
+                    .register_resource(file_resource(Arc::clone(&sandbox)))
+                    .await?;
+                server.file_sandbox = Some(sandbox);
+            }
+            "puppeteer" => {
+                // headless, chrome_executable, screenshot_dir and the viewport
+                // sit next to command and args
+                info!("Registering puppeteer tools");
+                let browser =
+                    BrowserServer::register_tools(&mut server.mcp_server, &config).await?;
+                server
+                    .mcp_server
+                    .register_resource(browser_resource(Arc::clone(&browser)))
+                    .await?;
+                server.browser = Some(browser);
+            }
+            "plugins" => {
+                // The first argument is the plugin directory; fuel and
+                // max_memory_mb sit next to command and args
+                let dir = config["args"][0].as_str().unwrap_or("plugins");
+                let limits: PluginLimits = serde_json::from_value(config.clone())
+                    .map_err(|e| Error::msg(format!("Invalid plugin limits: {}", e)))?;
+                let count =
+                    register_plugin_tools(&mut server.mcp_server, Path::new(dir), limits).await?;
+                info!("Registered {} plugin tools from {}", count, dir);
+            }
+            _ => {}
+        }
+
+        info!("Server {} initialized successfully", name);
+        Ok(server)
+    }
+
+    /// Registers the tools that need the RAG server. The file server gains
+    /// `file_index`; other servers are left unchanged.
+    pub async fn attach_rag_server(&mut self, rag_server: Arc<Mutex<RagServer>>) -> Result<()> {
+        if let Some(sandbox) = &self.file_sandbox {
+            info!("Registering file_index on server {}", self.name);
+            self.mcp_server
+                .register_tool(index_tool(Arc::clone(sandbox), rag_server))
+                .await?;
+        }
+        Ok(())
+    }
+
+    /// Creates the `rag` server, whose tools search and extend the RAG index
+    /// of `rag_server`. It has no entry in `servers_config.json`; `serve
+    /// --stdio` adds it next to the configured servers.
+    pub async fn rag(rag_server: Arc<Mutex<RagServer>>) -> Result<Self> {
+        let mut mcp_server = McpServer::with_capabilities(Capabilities {
+            tools: true,
+            ..Capabilities::default()
+        })
+        .named("rag");
+        mcp_server.initialize().await?;
+        for tool in rag_tools(rag_server) {
+            mcp_server.register_tool(tool).await?;
+        }
+        Ok(Self {
+            name: "rag".to_string(),
+            config: json!({ "command": "rag", "args": [], "env": {} }),
+            mcp_server,
+            cleanup_lock: Arc::new(Mutex::new(())),
+            memory_store: None,
+            sqlite_sweeper: None,
+            file_sandbox: None,
+            browser: None,
+            stdio_client: None,
+            tool_policy: ToolPolicy::default(),
+            retry_policy: RetryPolicy::default(),
+        })
+    }
+
+    /// Whether the server runs the tool `name`, bare or qualified with the
+    /// server's name
+    pub async fn offers_tool(&self, name: &str) -> bool {
+        self.mcp_server.capabilities().tools && self.mcp_server.has_tool(name).await
+    }
+
+    /// Tools of the server, or none if it does not offer tools
+    pub async fn list_tools(&self) -> Result<Vec<ToolSchema>> {
+        debug!("Listing tools for server: {}", self.name);
+        if !self.mcp_server.capabilities().tools {
+            return Ok(Vec::new());
+        }
+        // Denied tools are not offered at all
+        let tools: Vec<ToolSchema> = self
+            .mcp_server
+            .list_tools()
+            .await?
+            .into_iter()
+            .filter(|tool| self.tool_policy.permission(&tool.name) != Permission::Deny)
+            .collect();
+        info!("Found {} tools for server {}", tools.len(), self.name);
+        Ok(tools)
+    }
+
+    /// Permission of the tool `name`, bare or qualified, under the
+    /// server's `tool_policy`
+    pub fn permission(&self, name: &str) -> Permission {
+        self.tool_policy
+            .permission(&self.mcp_server.tool_id(name).tool)
+    }
+
+    /// Resources of the server, or none if it does not offer resources
+    pub async fn list_resources(&self) -> Result<Vec<ResourceSchema>> {
+        debug!("Listing resources for server: {}", self.name);
+        if !self.mcp_server.capabilities().resources {
+            return Ok(Vec::new());
+        }
+        let resources = self.mcp_server.list_resources().await?;
+        info!(
+            "Found {} resources for server {}",
+            resources.len(),
+            self.name
+        );
+        Ok(resources)
+    }
+
+    /// Prompts of the server, or none if it does not offer prompts
+    pub async fn list_prompts(&self) -> Result<Vec<PromptSchema>> {
+        if !self.mcp_server.capabilities().prompts {
+            return Ok(Vec::new());
+        }
+        self.mcp_server.list_prompts().await
+    }
+
+    /// Renders the prompt `name` with `arguments` into the messages to send
+    pub async fn get_prompt(
+        &mut self,
+        name: &str,
+        arguments: Option<Value>,
+    ) -> Result<GetPromptResult> {
+        let description = self
+            .list_prompts()
+            .await?
+            .into_iter()
+            .find(|prompt| prompt.name == name)
+            .map(|prompt| prompt.description);
+        let value = self.mcp_server.get_prompt(name, arguments).await?;
+        Ok(GetPromptResult::from_value(description, value))
+    }
+
+    /// Executes a tool, retrying failed attempts as the server's `retry`
+    /// policy says
+    pub async fn execute_tool(&mut self, tool_name: &str, arguments: &Value) -> Result<Value> {
+        self.execute_tool_with_progress(tool_name, arguments, &ProgressSink::none())
+            .await
+    }
+
+    /// Executes a tool like `execute_tool`, passing its progress reports
+    /// to `progress`
+    pub async fn execute_tool_with_progress(
+        &self,
+        tool_name: &str,
+        arguments: &Value,
+        progress: &ProgressSink,
+    ) -> Result<Value> {
+        info!("Executing tool {} on server {}", tool_name, self.name);
+        debug!("Tool arguments: {}", arguments);
+        let result = self
+            .mcp_server
+            .execute_tool_with_policy(tool_name, arguments, &self.retry_policy, progress)
+            .await?;
+        info!("Tool {} executed successfully", tool_name);
+        debug!("Tool result: {}", result);
+        Ok(result)
+    }
+
+    /// Executes a tool like `execute_tool_with_progress`, reporting the
+    /// outcome and its duration as a `ToolResult`
+    pub async fn call_tool(
+        &self,
+        tool_name: &str,
+        arguments: &Value,
+        progress: &ProgressSink,
+    ) -> ToolResult {
+        let started = Instant::now();
+        let outcome = self
+            .execute_tool_with_progress(tool_name, arguments, progress)
+            .await;
+        let result = ToolResult::new(outcome, started);
+        info!(
+            "Tool {} on server {} {} in {} ms",
+            tool_name,
+            self.name,
+            if result.is_error {
+                "failed"
+            } else {
+                "succeeded"
+            },
+            result.duration_ms
+        );
+        result
+    }
+
+    /// Whether the server runs as an external process
+    pub fn is_external(&self) -> bool {
+        self.stdio_client.is_some()
+    }
+
+    /// Fails if the process of an external server has exited or does not
+    /// answer a `ping` within `timeout`. Built-in servers run in process
+    /// and are always healthy.
+    pub async fn health_check(&self, timeout: Duration) -> Result<()> {
+        let client = match &self.stdio_client {
+            Some(client) => client,
+            None => return Ok(()),
+        };
+        if !client.is_running().await {
+            return Err(Error::msg(format!(
+                "The process of server {} has exited",
+                self.name
+            )));
+        }
+        client.ping(timeout).await
+    }
+
+    /// Starts the process of an external server again and lists its tools
+    /// anew. Hooks and policies of the server stay in place.
+    pub async fn restart(&mut self) -> Result<()> {
+        let client = match &self.stdio_client {
+            Some(client) => Arc::clone(client),
+            None => {
+                return Err(Error::msg(format!(
+                    "Server {} is built in and cannot be restarted",
+                    self.name
+                )))
+            }
+        };
+        client.restart().await?;
+        client.refresh_tools(&mut self.mcp_server).await
+    }
+
+    pub async fn cleanup(&mut self) -> Result<()> {
+        info!("Cleaning up server: {}", self.name);
+        let _lock = self.cleanup_lock.lock().await;
+        if let Some(sweeper) = self.sqlite_sweeper.take() {
+            sweeper.abort();
+        }
+        if let Some(client) = self.stdio_client.take() {
+            client.shutdown().await?;
+        }
+        if let Some(browser) = self.browser.take() {
+            browser.close().await;
+        }
+        self.mcp_server.cleanup().await?;
+        info!("Server {} cleaned up successfully", self.name);
+        Ok(())
+    }
+}
+
+/// Refuses the tools `policy` denies, whoever calls them
+async fn enforce_tool_policy(mcp_server: &mut McpServer, policy: &ToolPolicy) {
+    if policy.default != Permission::Deny && !policy.tools.values().any(|p| *p == Permission::Deny)
+    {
+        return;
+    }
+    let policy = policy.clone();
+    mcp_server
+        .before_tool_call(move |id, _| match policy.permission(&id.tool) {
+            Permission::Deny => Err(ToolError::new(
+                ToolErrorKind::PermissionDenied,
+                format!(
+                    "Tool {} is denied by the tool policy of server {}",
+                    id.tool, id.server
+                ),
+            )
+            .into()),
+            _ => Ok(()),
+        })
+        .await;
+}
+
+/// Index of the server that runs the tool `name`. A qualified name such as
+/// `sqlite/sql_query` selects the server by name; a bare name selects the
+/// first server offering the tool.
+pub async fn find_tool_server(servers: &[Server], name: &str) -> Option<usize> {
+    if let (Some(server_name), _) = ToolId::parse(name) {
+        for (i, server) in servers.iter().enumerate() {
+            if server.name == server_name && server.offers_tool(name).await {
+                return Some(i);
+            }
+        }
+    }
+    for (i, server) in servers.iter().enumerate() {
+        if server.offers_tool(name).await {
+            return Some(i);
+        }
+    }
+    None
+}
+
+/// Tool names offered by more than one server, each with the ids that tell
+/// the tools apart. Bare names of these tools run on the first server.
+pub async fn tool_collisions(servers: &[Server]) -> Result<Vec<(String, Vec<ToolId>)>> {
+    let mut offered: HashMap<String, Vec<ToolId>> = HashMap::new();
+    for server in servers {
+        for tool in server.list_tools().await? {
+            let id = ToolId::new(&server.name, &tool.name);
+            offered.entry(tool.name).or_default().push(id);
+        }
+    }
+    let mut collisions: Vec<(String, Vec<ToolId>)> = offered
+        .into_iter()
+        .filter(|(_, ids)| ids.len() > 1)
+        .collect();
+    collisions.sort();
+    Ok(collisions)
+}
+
+/// Warns about each tool name offered by more than one server
+pub async fn warn_tool_collisions(servers: &[Server]) -> Result<()> {
+    for (name, ids) in tool_collisions(servers).await? {
+        let ids: Vec<String> = ids.iter().map(|id| id.to_string()).collect();
+        warn!(
+            "Tool {} is offered by several servers; {} runs {}, call the others as {}",
+            name,
+            name,
+            ids[0],
+            ids[1..].join(", ")
+        );
+    }
+    Ok(())
+}
@@ -0,0 +1,417 @@
+//! Server Manager Module: Supervision of the configured MCP servers
+//!
+//! This module provides:
+//! - `ServerManager`: owns the servers of `servers_config.json` and keeps
+//!   track of whether each one is up
+//! - Health checks: the process of an external server must be running and
+//!   answer a `ping`; built-in servers run in process and are always up
+//! - Restarts of external servers that crashed or hung, with exponential
+//!   backoff between attempts, until `restart.max_retries` attempts in a row
+//!   have failed
+//! - `SupervisorConfig`, read from the `supervisor` section of `config.yaml`
+//!
+//! The chat runs the checks between turns, once `health_check_secs` have
+//! passed since the last ones, and `/servers` runs them at once to show the
+//! current state. A restarted server keeps its hooks and tool policy, and
+//! its tools are listed anew.
+//!
+//! Author: arkSong <arksong2018@gmail.com>
+//! Version: 1.0.0
+//! License: MIT
+
+use anyhow::Result;
+use chrono::{DateTime, Local};
+use log::{info, warn};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::ops::{Deref, DerefMut};
+use std::time::{Duration, Instant};
+
+use crate::config::Configuration;
+use crate::retry::RetryPolicy;
+use crate::server::Server;
+
+/// Settings of the supervision, read from the `supervisor` section of
+/// `config.yaml`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SupervisorConfig {
+    /// Whether servers are checked and restarted between chat turns
+    pub enabled: bool,
+    /// Seconds between two rounds of health checks
+    pub health_check_secs: u64,
+    /// Seconds a server may take to answer a `ping`
+    pub ping_timeout_secs: u64,
+    /// Backoff between restarts of a server and how many failed restarts
+    /// in a row are tried before giving up
+    pub restart: RetryPolicy,
+}
+
+impl Default for SupervisorConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            health_check_secs: 30,
+            ping_timeout_secs: 5,
+            restart: RetryPolicy {
+                max_retries: 5,
+                initial_backoff_ms: 1000,
+                max_backoff_ms: 60_000,
+                jitter: 0.0,
+            },
+        }
+    }
+}
+
+impl SupervisorConfig {
+    /// The `supervisor` section of the configuration file `config_path`,
+    /// or the defaults if it cannot be read
+    pub fn from_config_file(config_path: &str) -> Self {
+        Configuration::new()
+            .load_supervisor_config(config_path)
+            .unwrap_or_else(|e| {
+                warn!(
+                    "Failed to load the supervisor configuration, using defaults: {}",
+                    e
+                );
+                Self::default()
+            })
+    }
+}
+
+/// Whether a server can be used
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ServerState {
+    /// Passed its last health check or was just restarted
+    Up,
+    /// Failed its last health check; it is restarted once its backoff ends
+    Down,
+    /// Failed to restart `restart.max_retries` times in a row and is no
+    /// longer restarted
+    Failed,
+}
+
+impl ServerState {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ServerState::Up => "up",
+            ServerState::Down => "down",
+            ServerState::Failed => "failed",
+        }
+    }
+}
+
+/// What the supervision knows about one server
+#[derive(Debug, Clone)]
+pub struct ServerStatus {
+    pub name: String,
+    /// Command line the server was started with
+    pub command: String,
+    /// Whether the server runs as an external process
+    pub external: bool,
+    pub state: ServerState,
+    /// Successful restarts since the chat started
+    pub restarts: u32,
+    /// Restart attempts since the server was last seen healthy
+    pub attempts: u32,
+    pub last_check: Option<DateTime<Local>>,
+    /// Why the server last failed its health check or a restart
+    pub last_error: Option<String>,
+    /// When the next restart may be attempted
+    next_restart: Option<Instant>,
+}
+
+impl ServerStatus {
+    fn new(server: &Server) -> Self {
+        let mut command = server.config["command"]
+            .as_str()
+            .unwrap_or_default()
+            .to_string();
+        for arg in server.config["args"].as_array().into_iter().flatten() {
+            command.push(' ');
+            command.push_str(arg.as_str().unwrap_or_default());
+        }
+        Self {
+            name: server.name.clone(),
+            command,
+            external: server.is_external(),
+            state: ServerState::Up,
+            restarts: 0,
+            attempts: 0,
+            last_check: None,
+            last_error: None,
+            next_restart: None,
+        }
+    }
+}
+
+/// Owns the configured servers and restarts those that crash. Derefs to
+/// the servers, in configuration order.
+pub struct ServerManager {
+    servers: Vec<Server>,
+    status: Vec<ServerStatus>,
+    config: SupervisorConfig,
+    last_check: Instant,
+}
+
+impl ServerManager {
+    pub fn new(servers: Vec<Server>, config: SupervisorConfig) -> Self {
+        let status = servers.iter().map(ServerStatus::new).collect();
+        Self {
+            servers,
+            status,
+            config,
+            last_check: Instant::now(),
+        }
+    }
+
+    /// Starts the servers of `servers_config`, the contents of
+    /// `servers_config.json`
+    pub async fn from_config(servers_config: &Value, config: SupervisorConfig) -> Result<Self> {
+        let mut servers = Vec::new();
+        if let Some(entries) = servers_config.get("mcpServers").and_then(Value::as_object) {
+            for (name, entry) in entries {
+                servers.push(Server::new(name.clone(), entry.clone()).await?);
+            }
+        }
+        Ok(Self::new(servers, config))
+    }
+
+    /// What is known about each server, in configuration order
+    pub fn status(&self) -> &[ServerStatus] {
+        &self.status
+    }
+
+    /// Runs the health checks if supervision is enabled and
+    /// `health_check_secs` have passed since the last ones
+    pub async fn supervise(&mut self) {
+        let interval = Duration::from_secs(self.config.health_check_secs);
+        if self.config.enabled && self.last_check.elapsed() >= interval {
+            self.check_health().await;
+        }
+    }
+
+    /// Checks every server now, restarting external servers that are down
+    /// and whose backoff has ended
+    pub async fn check_health(&mut self) {
+        self.last_check = Instant::now();
+        let timeout = Duration::from_secs(self.config.ping_timeout_secs);
+        for (server, status) in self.servers.iter_mut().zip(&mut self.status) {
+            status.last_check = Some(Local::now());
+            match server.health_check(timeout).await {
+                Ok(()) => {
+                    if status.state != ServerState::Up {
+                        info!("Server {} is up again", server.name);
+                    }
+                    status.state = ServerState::Up;
+                    status.attempts = 0;
+                    status.last_error = None;
+                    status.next_restart = None;
+                }
+                Err(e) => {
+                    if status.state == ServerState::Up {
+                        warn!("Server {} is down: {}", server.name, e);
+                    }
+                    status.last_error = Some(e.to_string());
+                    if status.state != ServerState::Failed {
+                        status.state = ServerState::Down;
+                        restart(server, status, &self.config.restart).await;
+                    }
+                }
+            }
+        }
+    }
+
+    /// Cleans up every server, logging failures
+    pub async fn cleanup(&mut self) {
+        for server in &mut self.servers {
+            if let Err(e) = server.cleanup().await {
+                warn!("Warning during server cleanup: {}", e);
+            }
+        }
+    }
+}
+
+/// Restarts `server` if it runs as an external process and its backoff
+/// has ended, giving up once `policy.max_retries` attempts have failed
+async fn restart(server: &mut Server, status: &mut ServerStatus, policy: &RetryPolicy) {
+    if !status.external
+        || status
+            .next_restart
+            .is_some_and(|next| Instant::now() < next)
+    {
+        return;
+    }
+    if status.attempts >= policy.max_retries {
+        warn!(
+            "Giving up on server {} after {} failed restarts",
+            server.name, status.attempts
+        );
+        status.state = ServerState::Failed;
+        return;
+    }
+    status.next_restart = Some(Instant::now() + policy.backoff(status.attempts));
+    status.attempts += 1;
+    match server.restart().await {
+        Ok(()) => {
+            info!("Server {} restarted", server.name);
+            status.state = ServerState::Up;
+            status.restarts += 1;
+        }
+        Err(e) => {
+            warn!("Failed to restart server {}: {}", server.name, e);
+            status.last_error = Some(e.to_string());
+        }
+    }
+}
+
+impl Deref for ServerManager {
+    type Target = [Server];
+
+    fn deref(&self) -> &[Server] {
+        &self.servers
+    }
+}
+
+impl DerefMut for ServerManager {
+    fn deref_mut(&mut self) -> &mut [Server] {
+        &mut self.servers
+    }
+}
+
+impl<'a> IntoIterator for &'a ServerManager {
+    type Item = &'a Server;
+    type IntoIter = std::slice::Iter<'a, Server>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.servers.iter()
+    }
+}
+
+impl<'a> IntoIterator for &'a mut ServerManager {
+    type Item = &'a mut Server;
+    type IntoIter = std::slice::IterMut<'a, Server>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.servers.iter_mut()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[tokio::test]
+    async fn test_built_in_servers_stay_up() {
+        let servers_config = json!({
+            "mcpServers": {
+                "memory": { "command": "memory", "args": [], "env": {} }
+            }
+        });
+        let config = SupervisorConfig {
+            health_check_secs: 0,
+            ..SupervisorConfig::default()
+        };
+        let mut manager = ServerManager::from_config(&servers_config, config)
+            .await
+            .unwrap();
+        assert_eq!(manager.len(), 1);
+        assert_eq!(manager[0].name, "memory");
+
+        manager.supervise().await;
+        let status = &manager.status()[0];
+        assert_eq!(status.state, ServerState::Up);
+        assert_eq!(status.command, "memory");
+        assert!(!status.external);
+        assert!(status.last_check.is_some());
+        manager.cleanup().await;
+    }
+
+    /// A server that stops answering is restarted with backoff and given
+    /// up on once its restarts keep failing
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_failing_server_is_given_up() {
+        // The first process answers the handshake but no ping; the ones
+        // started later exit on their first request
+        let script = r#"
+            if [ -e "$MARKER" ]; then read -r line; exit 1; fi
+            touch "$MARKER"
+            while read -r line; do
+                id=$(printf '%s' "$line" | sed -n 's/.*"id":\([0-9]*\).*/\1/p')
+                case "$line" in
+                    *'"initialize"'*)
+                        printf '{"jsonrpc":"2.0","id":%s,"result":{"protocolVersion":"2024-11-05","capabilities":{"tools":{}},"serverInfo":{"name":"hung","version":"0.1.0"}}}\n' "$id" ;;
+                    *'"tools/list"'*)
+                        printf '{"jsonrpc":"2.0","id":%s,"result":{"tools":[]}}\n' "$id" ;;
+                esac
+            done
+        "#;
+        let temp_dir = tempfile::tempdir().unwrap();
+        let servers_config = json!({
+            "mcpServers": {
+                "hung": {
+                    "command": "sh",
+                    "args": ["-c", script],
+                    "env": { "MARKER": temp_dir.path().join("started").to_str().unwrap() },
+                    "timeout": 2
+                }
+            }
+        });
+        let config = SupervisorConfig {
+            health_check_secs: 0,
+            ping_timeout_secs: 1,
+            restart: RetryPolicy {
+                max_retries: 2,
+                initial_backoff_ms: 500,
+                max_backoff_ms: 5000,
+                jitter: 0.0,
+            },
+            ..SupervisorConfig::default()
+        };
+        let mut manager = ServerManager::from_config(&servers_config, config)
+            .await
+            .unwrap();
+        assert_eq!(manager.status()[0].state, ServerState::Up);
+
+        // The unanswered ping fails the check, and the restart fails too
+        manager.check_health().await;
+        let status = &manager.status()[0];
+        assert_eq!(status.state, ServerState::Down);
+        assert_eq!(status.attempts, 1);
+        assert_eq!(status.restarts, 0);
+        assert!(status.last_error.is_some());
+
+        // No second restart before the backoff ends
+        manager.check_health().await;
+        assert_eq!(manager.status()[0].state, ServerState::Down);
+        assert_eq!(manager.status()[0].attempts, 1);
+
+        tokio::time::sleep(Duration::from_millis(600)).await;
+        manager.check_health().await;
+        assert_eq!(manager.status()[0].state, ServerState::Down);
+        assert_eq!(manager.status()[0].attempts, 2);
+
+        // Out of restarts once the next backoff ends
+        tokio::time::sleep(Duration::from_millis(1100)).await;
+        manager.check_health().await;
+        assert_eq!(manager.status()[0].state, ServerState::Failed);
+        manager.check_health().await;
+        let status = &manager.status()[0];
+        assert_eq!(status.state, ServerState::Failed);
+        assert_eq!(status.attempts, 2);
+        assert_eq!(status.restarts, 0);
+        manager.cleanup().await;
+    }
+
+    #[test]
+    fn test_config() {
+        let config: SupervisorConfig =
+            serde_yaml::from_str("health_check_secs: 10\nrestart:\n  max_retries: 2\n").unwrap();
+        assert!(config.enabled);
+        assert_eq!(config.health_check_secs, 10);
+        assert_eq!(config.restart.max_retries, 2);
+        assert_eq!(SupervisorConfig::default().restart.max_retries, 5);
+    }
+}
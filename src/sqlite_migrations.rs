@@ -0,0 +1,205 @@
+//! SQLite Migrations Module: Versioned schema changes for `SqliteServer`
+//!
+//! This module provides:
+//! - The list of migrations, embedded from the SQL files in `migrations/`
+//! - A runner that applies pending migrations in order when a database opens
+//! - The `schema_version` table recording which migrations were applied
+//!
+//! Each migration runs in its own transaction together with its
+//! `schema_version` row, so a failing migration leaves the database at the
+//! previous version. Databases created by a newer build are refused rather
+//! than modified.
+//!
+//! Adding a migration: create `migrations/NNNN_name.sql` with the next number
+//! and append it to `MIGRATIONS`. Applied migrations must never be edited.
+//!
+//! Author: arkSong <arksong2018@gmail.com>
+//! Version: 1.0.0
+//! License: MIT
+
+use anyhow::{Context, Error, Result};
+use log::info;
+use rusqlite::{params, Connection};
+
+/// One schema change
+#[derive(Debug, Clone, Copy)]
+pub struct Migration {
+    /// Consecutive version numbers, starting at 1
+    pub version: u32,
+    pub name: &'static str,
+    pub sql: &'static str,
+}
+
+/// All migrations, oldest first
+pub const MIGRATIONS: &[Migration] = &[
+    Migration {
+        version: 1,
+        name: "kv_store",
+        sql: include_str!("../migrations/0001_kv_store.sql"),
+    },
+    Migration {
+        version: 2,
+        name: "fts_indexes",
+        sql: include_str!("../migrations/0002_fts_indexes.sql"),
+    },
+    Migration {
+        version: 3,
+        name: "kv_namespaces",
+        sql: include_str!("../migrations/0003_kv_namespaces.sql"),
+    },
+];
+
+/// Version of the newest migration known to this build
+pub fn latest_version() -> u32 {
+    MIGRATIONS.last().map_or(0, |migration| migration.version)
+}
+
+/// Version the database schema is at; 0 before any migration
+pub fn current_version(conn: &Connection) -> Result<u32> {
+    create_version_table(conn)?;
+    let version: Option<u32> =
+        conn.query_row("SELECT MAX(version) FROM schema_version", [], |row| {
+            row.get(0)
+        })?;
+    Ok(version.unwrap_or(0))
+}
+
+/// Applies the pending `MIGRATIONS` and returns the resulting version
+pub fn migrate(conn: &mut Connection) -> Result<u32> {
+    run_migrations(conn, MIGRATIONS)
+}
+
+fn create_version_table(conn: &Connection) -> Result<()> {
+    conn.execute_batch(
+        "CREATE TABLE IF NOT EXISTS schema_version (
+            version INTEGER PRIMARY KEY,
+            name TEXT NOT NULL,
+            applied_at TEXT NOT NULL DEFAULT CURRENT_TIMESTAMP
+        );",
+    )?;
+    Ok(())
+}
+
+fn run_migrations(conn: &mut Connection, migrations: &[Migration]) -> Result<u32> {
+    let mut version = current_version(conn)?;
+    let latest = migrations.last().map_or(0, |migration| migration.version);
+    if version > latest {
+        return Err(Error::msg(format!(
+            "Database schema version {} is newer than the latest known version {}",
+            version, latest
+        )));
+    }
+
+    for migration in migrations.iter().filter(|m| m.version > version) {
+        if migration.version != version + 1 {
+            return Err(Error::msg(format!(
+                "Migration {} does not follow version {}",
+                migration.version, version
+            )));
+        }
+
+        info!(
+            "Applying database migration {} ({})",
+            migration.version, migration.name
+        );
+        let tx = conn.transaction()?;
+        tx.execute_batch(migration.sql).with_context(|| {
+            format!(
+                "Database migration {} ({}) failed",
+                migration.version, migration.name
+            )
+        })?;
+        tx.execute(
+            "INSERT INTO schema_version (version, name) VALUES (?1, ?2)",
+            params![migration.version, migration.name],
+        )?;
+        tx.commit()?;
+        version = migration.version;
+    }
+
+    Ok(version)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn table_exists(conn: &Connection, name: &str) -> bool {
+        conn.query_row(
+            "SELECT count(*) FROM sqlite_master WHERE type = 'table' AND name = ?1",
+            [name],
+            |row| row.get::<_, i64>(0),
+        )
+        .unwrap()
+            > 0
+    }
+
+    #[test]
+    fn test_migrate() {
+        let mut conn = Connection::open_in_memory().unwrap();
+        assert_eq!(current_version(&conn).unwrap(), 0);
+
+        assert_eq!(migrate(&mut conn).unwrap(), latest_version());
+        assert!(table_exists(&conn, "kv_store"));
+
+        // Running again applies nothing
+        assert_eq!(migrate(&mut conn).unwrap(), latest_version());
+        let applied: i64 = conn
+            .query_row("SELECT count(*) FROM schema_version", [], |row| row.get(0))
+            .unwrap();
+        assert_eq!(applied, MIGRATIONS.len() as i64);
+    }
+
+    #[test]
+    fn test_kv_pairs_survive_namespaces() {
+        let mut conn = Connection::open_in_memory().unwrap();
+        run_migrations(&mut conn, &MIGRATIONS[..2]).unwrap();
+        conn.execute("INSERT INTO kv_store (key, value) VALUES ('a', '1')", [])
+            .unwrap();
+
+        migrate(&mut conn).unwrap();
+        let row: (String, String, Option<i64>) = conn
+            .query_row(
+                "SELECT namespace, value, expires_at FROM kv_store WHERE key = 'a'",
+                [],
+                |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)),
+            )
+            .unwrap();
+        assert_eq!(row, ("default".to_string(), "1".to_string(), None));
+    }
+
+    #[test]
+    fn test_failed_migration_rolls_back() {
+        let mut conn = Connection::open_in_memory().unwrap();
+        let migrations = [
+            Migration {
+                version: 1,
+                name: "notes",
+                sql: "CREATE TABLE notes (id INTEGER PRIMARY KEY);",
+            },
+            Migration {
+                version: 2,
+                name: "broken",
+                sql: "CREATE TABLE tags (id INTEGER); INSERT INTO missing VALUES (1);",
+            },
+        ];
+
+        assert!(run_migrations(&mut conn, &migrations).is_err());
+        assert_eq!(current_version(&conn).unwrap(), 1);
+        assert!(table_exists(&conn, "notes"));
+        assert!(!table_exists(&conn, "tags"));
+    }
+
+    #[test]
+    fn test_newer_database_is_refused() {
+        let mut conn = Connection::open_in_memory().unwrap();
+        migrate(&mut conn).unwrap();
+        conn.execute(
+            "INSERT INTO schema_version (version, name) VALUES (?1, 'future')",
+            [latest_version() + 1],
+        )
+        .unwrap();
+
+        assert!(migrate(&mut conn).is_err());
+    }
+}
@@ -2,91 +2,1228 @@
 //!
 //! This module provides SQLite database functionality including:
 //! - Key-value store operations
+//! - SQL statements, queries and table management for the `sqlite_*` tools
+//! - Queries with bound parameters, so values never need quoting
+//! - FTS5 full-text indexes over table columns, kept in sync by triggers
+//! - Read-only mode and statement/table allowlists for the SQL tools
+//! - The `sqlite_database` resource describing tables, columns, indexes and size
+//! - Online backup and restore through SQLite's backup API, confined to a
+//!   backup directory
+//! - Multi-statement transactions that roll back on any failure
+//! - Row limits and busy/query timeouts, so runaway queries cannot stall the chat
+//! - Namespaces and expiry for key-value pairs, swept in the background
+//! - Optional SQLCipher encryption at rest (`sqlcipher` feature)
 //! - Database connection management
 //! - Tool registration for database operations
 //!
 //! The SQLite server supports:
 //! - Creating/opening databases with proper flags
-//! - Schema initialization
+//! - Schema initialization through versioned migrations (`sqlite_migrations`)
 //! - Thread-safe connection handling
 //! - Integration with the MCP server framework
 //!
 //! Key Components:
 //! - `SqliteServer`: Main server struct managing the database connection
-//! - Database tools for key-value operations
-//! - Connection pooling via Arc<Mutex>
+//! - Database tools for key-value operations and SQL
+//! - Query results as JSON objects keyed by column name
+//! - Connection pooling via r2d2, so concurrent tools do not wait on one connection
 //!
 //! Author: arkSong <arksong2018@gmail.com>
 //! Version: 1.0.0
 //! License: MIT
 
 use anyhow::{Error, Result};
-use log::info;
-use rusqlite::{Connection, OpenFlags};
-use serde_json::{json, Value};
-use std::path::Path;
-use std::sync::Arc;
-use std::sync::Mutex;
+use chrono::{DateTime, Utc};
+use log::{debug, info, warn};
+use r2d2::Pool;
+use r2d2_sqlite::SqliteConnectionManager;
+use rusqlite::backup::Progress;
+use rusqlite::hooks::{AuthAction, AuthContext, Authorization};
+use rusqlite::types::{Value as SqlValue, ValueRef};
+use rusqlite::{params, params_from_iter, Connection, DatabaseName, OpenFlags};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Map, Value};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::task::JoinHandle;
+
+use crate::file_policy::{FileServerPolicy, Sandbox};
+use crate::mcp_server::{McpServer, Resource, Tool};
+use crate::sqlite_migrations;
+use crate::tool_error::{invalid_arguments, ToolError, ToolErrorKind};
+
+/// Pooled connections to one database
+pub(crate) type SqlitePool = Pool<SqliteConnectionManager>;
+
+/// Connections per file database
+const POOL_SIZE: u32 = 8;
+
+/// Key-value tools
+const KV_TOOLS: [&str; 4] = [
+    "sqlite_set",
+    "sqlite_get",
+    "sqlite_delete",
+    "sqlite_list_keys",
+];
+
+/// Namespace of key-value pairs stored without one
+pub const DEFAULT_NAMESPACE: &str = "default";
+
+/// How often expired key-value pairs are deleted
+const EXPIRY_SWEEP_INTERVAL: Duration = Duration::from_secs(60);
+
+/// SQL tools, backed by the same database as the key-value store
+const SQL_TOOLS: [&str; 11] = [
+    "sqlite_execute",
+    "sqlite_query",
+    "sqlite_query_params",
+    "sqlite_create_table",
+    "sqlite_drop_table",
+    "sqlite_list_tables",
+    "sqlite_fts_index",
+    "sqlite_fts_search",
+    "sqlite_backup",
+    "sqlite_restore",
+    "sqlite_transaction",
+];
+
+/// Statement kinds that may be named in `SqliteOptions::allowed_statements`
+const STATEMENT_KINDS: [&str; 11] = [
+    "SELECT",
+    "INSERT",
+    "UPDATE",
+    "DELETE",
+    "CREATE",
+    "DROP",
+    "ALTER",
+    "PRAGMA",
+    "TRANSACTION",
+    "ATTACH",
+    "OTHER",
+];
+
+/// Rows returned by the query tools unless `max_rows` is configured
+const DEFAULT_MAX_ROWS: usize = 1000;
+
+/// How long a statement may wait for another connection's lock
+const DEFAULT_BUSY_TIMEOUT_MS: u64 = 5000;
+
+/// How long a statement of the SQL tools may run before it is interrupted
+const DEFAULT_QUERY_TIMEOUT_MS: u64 = 30_000;
+
+/// Virtual machine instructions between query timeout checks
+const PROGRESS_OPS: i32 = 10_000;
+
+/// Matches returned by `sqlite_fts_search` when no limit is given
+const DEFAULT_FTS_LIMIT: usize = 10;
+
+/// Directory of the backup tools unless `backup_dir` is configured
+const DEFAULT_BACKUP_DIR: &str = "backups";
+
+/// First bytes of every SQLite database file
+const SQLITE_HEADER: &[u8; 16] = b"SQLite format 3\0";
+
+/// Column of a table created by `sqlite_create_table`
+#[derive(Debug, Clone, Deserialize)]
+pub struct ColumnDef {
+    pub name: String,
+    /// INTEGER, TEXT, REAL, BLOB or NUMERIC
+    #[serde(rename = "type")]
+    pub column_type: String,
+    #[serde(default)]
+    pub primary_key: bool,
+    #[serde(default)]
+    pub not_null: bool,
+    #[serde(default)]
+    pub unique: bool,
+}
+
+/// Row of an indexed table matching a full-text search
+#[derive(Debug, Clone, Serialize)]
+pub struct FtsMatch {
+    pub table: String,
+    pub rowid: i64,
+    /// BM25 rank; lower is a better match
+    pub rank: f64,
+    /// Matching text with the terms in [brackets]
+    pub snippet: String,
+    /// Values of the indexed columns
+    pub row: Map<String, Value>,
+}
+
+/// Column of a table, from `PRAGMA table_info`
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ColumnInfo {
+    pub name: String,
+    #[serde(rename = "type")]
+    pub column_type: String,
+    pub not_null: bool,
+    /// Default value as SQL text
+    pub default: Option<String>,
+    pub primary_key: bool,
+}
+
+/// Index of a table, from `PRAGMA index_list` and `PRAGMA index_info`
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct IndexInfo {
+    pub name: String,
+    pub unique: bool,
+    pub columns: Vec<String>,
+}
+
+/// Table as described by the `sqlite_database` resource
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct TableInfo {
+    pub name: String,
+    pub rows: i64,
+    pub columns: Vec<ColumnInfo>,
+    pub indexes: Vec<IndexInfo>,
+}
+
+/// Statement of a `sqlite_transaction`: plain SQL, or SQL with `?1`, `?2`,
+/// ... bound to `params`
+#[derive(Debug, Clone, Deserialize)]
+#[serde(untagged)]
+pub enum TxStatement {
+    Sql(String),
+    WithParams {
+        query: String,
+        #[serde(default)]
+        params: Vec<Value>,
+    },
+}
+
+/// Where the SQLCipher key of an encrypted database comes from. Exactly one
+/// source must be set.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct EncryptionConfig {
+    /// Environment variable holding the key
+    pub key_env: Option<String>,
+    /// Service and user of the key in the system keyring
+    pub keyring_service: Option<String>,
+    pub keyring_user: Option<String>,
+}
+
+impl EncryptionConfig {
+    /// Reads the key from the environment or the keyring
+    pub fn key(&self) -> Result<String> {
+        match (&self.key_env, &self.keyring_service, &self.keyring_user) {
+            (Some(var), None, None) => std::env::var(var)
+                .map_err(|_| Error::msg(format!("Encryption key variable {} is not set", var))),
+            (None, Some(service), Some(user)) => keyring_password(service, user),
+            _ => Err(Error::msg(
+                "Set either key_env or keyring_service and keyring_user for encryption",
+            )),
+        }
+    }
+}
+
+#[cfg(feature = "sqlcipher")]
+fn keyring_password(service: &str, user: &str) -> Result<String> {
+    keyring::Entry::new(service, user)
+        .and_then(|entry| entry.get_password())
+        .map_err(|e| {
+            Error::msg(format!(
+                "Failed to read the encryption key from the keyring: {}",
+                e
+            ))
+        })
+}
+
+#[cfg(not(feature = "sqlcipher"))]
+fn keyring_password(_service: &str, _user: &str) -> Result<String> {
+    Err(Error::msg(
+        "Reading the key from the keyring requires the sqlcipher feature",
+    ))
+}
+
+/// Restrictions for exposing a database to the LLM, read from the `sqlite`
+/// entry of `servers_config.json`
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct SqliteOptions {
+    /// Open the database with `SQLITE_OPEN_READ_ONLY`; migrations are skipped
+    pub read_only: bool,
+    /// Statement kinds the SQL tools may run: SELECT, INSERT, UPDATE, DELETE,
+    /// CREATE, DROP, ALTER, PRAGMA, TRANSACTION, ATTACH or OTHER. Unset
+    /// allows all.
+    pub allowed_statements: Option<Vec<String>>,
+    /// Tables the SQL tools may read or change. Unset allows all.
+    pub allowed_tables: Option<Vec<String>>,
+    /// Rows returned by `sqlite_query` and `sqlite_query_params`; further
+    /// rows are dropped and the result is flagged `truncated`. `null`
+    /// returns every row.
+    pub max_rows: Option<usize>,
+    /// Milliseconds a statement waits for a locked database before failing
+    pub busy_timeout_ms: u64,
+    /// Milliseconds a statement of the SQL tools may run before it is
+    /// interrupted. `null` disables the timeout.
+    pub query_timeout_ms: Option<u64>,
+    /// Encrypt the database file with SQLCipher. Unset leaves it plain.
+    pub encryption: Option<EncryptionConfig>,
+    /// Directory `sqlite_backup` writes to and `sqlite_restore` reads from;
+    /// the paths of both tools are relative to it
+    pub backup_dir: PathBuf,
+}
+
+impl Default for SqliteOptions {
+    fn default() -> Self {
+        Self {
+            read_only: false,
+            allowed_statements: None,
+            allowed_tables: None,
+            max_rows: Some(DEFAULT_MAX_ROWS),
+            busy_timeout_ms: DEFAULT_BUSY_TIMEOUT_MS,
+            query_timeout_ms: Some(DEFAULT_QUERY_TIMEOUT_MS),
+            encryption: None,
+            backup_dir: PathBuf::from(DEFAULT_BACKUP_DIR),
+        }
+    }
+}
+
+impl SqliteOptions {
+    /// Fails unless a `kind` statement on `table` is allowed
+    pub fn check(&self, kind: &str, table: Option<&str>) -> Result<()> {
+        if let Some(allowed) = &self.allowed_statements {
+            if !allowed.iter().any(|a| a.eq_ignore_ascii_case(kind)) {
+                return Err(Error::new(ToolError::new(
+                    ToolErrorKind::PermissionDenied,
+                    format!("{} statements are not allowed", kind),
+                )));
+            }
+        }
+        self.check_table(table)
+    }
+
+    /// Fails unless `table` is allowed
+    fn check_table(&self, table: Option<&str>) -> Result<()> {
+        match table {
+            Some(table) if !self.allows_table(table) => Err(Error::new(ToolError::new(
+                ToolErrorKind::PermissionDenied,
+                format!("Table {} is not allowed", table),
+            ))),
+            _ => Ok(()),
+        }
+    }
+
+    /// Views and triggers run their statements whenever they are used, so
+    /// with any restriction creating them needs CREATE to be listed
+    fn check_definition(&self, what: &str) -> Result<()> {
+        let listed = self
+            .allowed_statements
+            .as_ref()
+            .is_some_and(|allowed| allowed.iter().any(|a| a.eq_ignore_ascii_case("CREATE")));
+        if !listed {
+            return Err(Error::new(ToolError::new(
+                ToolErrorKind::PermissionDenied,
+                format!("Creating {} needs CREATE in allowed_statements", what),
+            )));
+        }
+        Ok(())
+    }
+
+    fn allows_table(&self, table: &str) -> bool {
+        match &self.allowed_tables {
+            Some(allowed) => allowed.iter().any(|a| a.eq_ignore_ascii_case(table)),
+            None => true,
+        }
+    }
+
+    fn is_restricted(&self) -> bool {
+        self.allowed_statements.is_some() || self.allowed_tables.is_some()
+    }
+
+    /// The same restrictions, minus TRANSACTION statements, which would end
+    /// an enclosing transaction early
+    fn in_transaction(&self) -> SqliteOptions {
+        let allowed = match &self.allowed_statements {
+            Some(allowed) => allowed.clone(),
+            None => STATEMENT_KINDS
+                .iter()
+                .map(|kind| kind.to_string())
+                .collect(),
+        };
+        SqliteOptions {
+            allowed_statements: Some(
+                allowed
+                    .into_iter()
+                    .filter(|kind| !kind.eq_ignore_ascii_case("TRANSACTION"))
+                    .collect(),
+            ),
+            ..self.clone()
+        }
+    }
+
+    /// A backup contains every table, so hidden tables forbid it
+    fn check_backup(&self) -> Result<()> {
+        if self.allowed_tables.is_some() {
+            return Err(Error::new(ToolError::new(
+                ToolErrorKind::PermissionDenied,
+                "Backups are not allowed with allowed_tables",
+            )));
+        }
+        // SQLCipher cannot copy pages to an unkeyed database
+        if self.encryption.is_some() {
+            return Err(Error::msg(
+                "Backups of encrypted databases are not supported",
+            ));
+        }
+        Ok(())
+    }
 
-use crate::mcp_server::{McpServer, Tool};
+    /// Resolves `path` of a backup tool inside `backup_dir`, refusing
+    /// absolute paths and `..`
+    fn backup_path(&self, path: &str) -> Result<PathBuf> {
+        Sandbox::new(self.backup_dir.clone(), FileServerPolicy::default())?.readable(path)
+    }
+
+    /// A restore replaces the whole database, so it needs unrestricted access
+    fn check_restore(&self) -> Result<()> {
+        if self.read_only {
+            return Err(Error::new(ToolError::new(
+                ToolErrorKind::PermissionDenied,
+                "The database is read-only",
+            )));
+        }
+        if self.is_restricted() {
+            return Err(Error::new(ToolError::new(
+                ToolErrorKind::PermissionDenied,
+                "Restores are not allowed with allowlists",
+            )));
+        }
+        Ok(())
+    }
+}
 
 pub struct SqliteServer {
-    conn: Arc<Mutex<Connection>>,
+    pool: SqlitePool,
+    db_path: String,
+    options: Arc<SqliteOptions>,
     mcp_server: McpServer,
+    sweeper: Option<JoinHandle<()>>,
 }
 
 impl SqliteServer {
     pub async fn new(db_path: &str) -> Result<Self> {
+        Self::with_options(db_path, SqliteOptions::default()).await
+    }
+
+    /// Opens the database with read-only mode or allowlists for the SQL tools
+    pub async fn with_options(db_path: &str, options: SqliteOptions) -> Result<Self> {
+        let pool = Self::open(db_path, &options).await?;
+        let options = Arc::new(options);
+        let mut mcp_server = McpServer::new().named("sqlite");
+
+        // Register SQLite tools
+        Self::register_tools(&mut mcp_server, pool.clone(), Arc::clone(&options)).await?;
+        mcp_server
+            .register_resource(database_resource(
+                pool.clone(),
+                db_path,
+                Arc::clone(&options),
+            ))
+            .await?;
+
+        let sweeper = if options.read_only {
+            None
+        } else {
+            Some(spawn_expiry_sweeper(pool.clone()))
+        };
+
+        Ok(Self {
+            pool,
+            db_path: db_path.to_string(),
+            options,
+            mcp_server,
+            sweeper,
+        })
+    }
+
+    /// Opens a connection pool for the database and applies pending schema
+    /// migrations
+    pub(crate) async fn open(db_path: &str, options: &SqliteOptions) -> Result<SqlitePool> {
         // Create database directory if it doesn't exist
         if let Some(parent) = Path::new(db_path).parent() {
             tokio::fs::create_dir_all(parent).await?;
         }
 
-        let db_path = db_path.to_string();
-
-        // Open SQLite connection with proper flags
-        let conn = tokio::task::spawn_blocking(move || {
-            let mut conn = Connection::open_with_flags(
-                &db_path,
-                OpenFlags::SQLITE_OPEN_READ_WRITE
-                    | OpenFlags::SQLITE_OPEN_CREATE
-                    | OpenFlags::SQLITE_OPEN_URI,
-            )?;
-
-            // Initialize database schema
-            let tx = conn.transaction()?;
-            tx.execute_batch(
-                "PRAGMA foreign_keys = ON;
-                PRAGMA busy_timeout = 5000;
-                CREATE TABLE IF NOT EXISTS kv_store (
-                    key TEXT PRIMARY KEY,
-                    value TEXT NOT NULL
-                );",
-            )?;
-            tx.commit()?;
-
-            Ok::<_, Error>(conn)
-        })
-        .await??;
+        // Every connection of an in-memory database is a separate database,
+        // so those share a single connection
+        let max_size = if db_path.contains(":memory:") || db_path.contains("mode=memory") {
+            1
+        } else {
+            POOL_SIZE
+        };
+        let flags = if options.read_only {
+            OpenFlags::SQLITE_OPEN_READ_ONLY | OpenFlags::SQLITE_OPEN_URI
+        } else {
+            OpenFlags::SQLITE_OPEN_READ_WRITE
+                | OpenFlags::SQLITE_OPEN_CREATE
+                | OpenFlags::SQLITE_OPEN_URI
+        };
+        let read_only = options.read_only;
+        let busy_timeout = Duration::from_millis(options.busy_timeout_ms);
+        let key = match &options.encryption {
+            Some(encryption) => Some(encryption_key(encryption)?),
+            None => None,
+        };
+        let encrypted = key.is_some();
+        let manager = SqliteConnectionManager::file(db_path)
+            .with_flags(flags)
+            .with_init(move |conn| {
+                // The key must be set before anything reads the file
+                if let Some(key) = &key {
+                    conn.pragma_update(None, "key", key)?;
+                }
+                conn.busy_timeout(busy_timeout)?;
+                conn.execute_batch("PRAGMA foreign_keys = ON;")
+            });
+
+        // Open the pool with proper flags
+        let pool = tokio::task::spawn_blocking(move || {
+            let pool = Pool::builder().max_size(max_size).build(manager)?;
+            let mut conn = pool.get()?;
+            if encrypted {
+                // A wrong key only shows once the first page is read
+                conn.query_row("SELECT count(*) FROM sqlite_master", [], |_| Ok(()))
+                    .map_err(|e| {
+                        Error::msg(format!(
+                            "Failed to decrypt the database, is the key right? ({})",
+                            e
+                        ))
+                    })?;
+                info!("Opened encrypted SQLite database");
+            }
+            if read_only {
+                info!("Opened SQLite database read-only; skipping migrations");
+                return Ok(pool);
+            }
 
-        let conn = Arc::new(Mutex::new(conn));
-        let mut mcp_server = McpServer::new();
+            // Readers no longer wait for writers in WAL mode. In-memory
+            // databases ignore this.
+            conn.query_row("PRAGMA journal_mode = WAL", [], |_| Ok(()))?;
 
-        // Register SQLite tools
-        Self::register_tools(&mut mcp_server, Arc::clone(&conn)).await?;
+            // Bring the schema up to date
+            let version = sqlite_migrations::migrate(&mut conn)?;
+            debug!("Database schema is at version {}", version);
+
+            Ok::<_, Error>(pool)
+        })
+        .await??;
 
-        Ok(Self { conn, mcp_server })
+        Ok(pool)
     }
 
-    async fn register_tools(
+    /// Registers the key-value and SQL tools on `mcp_server`
+    pub(crate) async fn register_tools(
         mcp_server: &mut McpServer,
-        conn: Arc<Mutex<Connection>>,
+        pool: SqlitePool,
+        options: Arc<SqliteOptions>,
     ) -> Result<()> {
-        // Register set tool
-        let conn_set = Arc::clone(&conn);
-        let set_tool = Tool::new(
-            "sqlite_set".to_string(),
-            "Set a key-value pair in SQLite database".to_string(),
+        for name in KV_TOOLS {
+            mcp_server
+                .register_tool(kv_tool(name, pool.clone()))
+                .await?;
+        }
+
+        for name in SQL_TOOLS {
+            mcp_server
+                .register_tool(sql_tool(name, pool.clone(), Arc::clone(&options)))
+                .await?;
+        }
+
+        Ok(())
+    }
+
+    pub async fn initialize(&mut self) -> Result<()> {
+        info!("Initializing SQLite server");
+        self.mcp_server.initialize().await
+    }
+
+    pub async fn cleanup(&mut self) -> Result<()> {
+        info!("Cleaning up SQLite server");
+        if let Some(sweeper) = self.sweeper.take() {
+            sweeper.abort();
+        }
+        self.mcp_server.cleanup().await
+    }
+
+    pub async fn execute_tool(
+        &self,
+        tool_name: &str,
+        arguments: &Value,
+        _retry_count: u32,
+        _timeout: std::time::Duration,
+    ) -> Result<Value> {
+        match tool_name {
+            name if KV_TOOLS.contains(&name) => {
+                run_kv_tool(self.pool.clone(), name, arguments.clone()).await
+            }
+            name if SQL_TOOLS.contains(&name) => {
+                run_sql_tool(
+                    self.pool.clone(),
+                    Arc::clone(&self.options),
+                    name,
+                    arguments.clone(),
+                )
+                .await
+            }
+            _ => Err(Error::new(ToolError::new(
+                ToolErrorKind::NotFound,
+                format!("Unknown tool: {}", tool_name),
+            ))),
+        }
+    }
+
+    pub async fn list_tools(&self) -> Result<Vec<crate::protocol::ToolSchema>> {
+        self.mcp_server.list_tools().await
+    }
+
+    /// Reads a resource, i.e. `sqlite_database`
+    pub async fn read_resource(&mut self, pattern: &str, arguments: &Value) -> Result<Value> {
+        self.mcp_server.read_resource(pattern, arguments).await
+    }
+
+    /// Stores `value` under `key` in the default namespace, never expiring
+    pub async fn set(&self, key: &str, value: &str) -> Result<bool> {
+        self.set_with(DEFAULT_NAMESPACE, key, value, None).await
+    }
+
+    /// Stores `value` under `key` in `namespace`. After `expires_at` the
+    /// pair is no longer returned and is eventually swept.
+    pub async fn set_with(
+        &self,
+        namespace: &str,
+        key: &str,
+        value: &str,
+        expires_at: Option<DateTime<Utc>>,
+    ) -> Result<bool> {
+        let namespace = namespace.to_string();
+        let key = key.to_string();
+        let value = value.to_string();
+        let expires_at = expires_at.map(|time| time.timestamp());
+        with_connection(self.pool.clone(), move |conn| {
+            kv_set(conn, &namespace, &key, &value, expires_at)
+        })
+        .await
+    }
+
+    /// Value of `key` in the default namespace and whether it was found
+    pub async fn get(&self, key: &str) -> Result<(String, bool)> {
+        self.get_in(DEFAULT_NAMESPACE, key).await
+    }
+
+    /// Value of `key` in `namespace` and whether it was found; expired pairs
+    /// are not found
+    pub async fn get_in(&self, namespace: &str, key: &str) -> Result<(String, bool)> {
+        let namespace = namespace.to_string();
+        let key = key.to_string();
+        let value = with_connection(self.pool.clone(), move |conn| {
+            kv_get(conn, &namespace, &key)
+        })
+        .await?;
+        Ok(match value {
+            Some(value) => (value, true),
+            None => (String::new(), false),
+        })
+    }
+
+    pub async fn delete(&self, key: &str) -> Result<bool> {
+        self.delete_in(DEFAULT_NAMESPACE, key).await
+    }
+
+    pub async fn delete_in(&self, namespace: &str, key: &str) -> Result<bool> {
+        let namespace = namespace.to_string();
+        let key = key.to_string();
+        with_connection(self.pool.clone(), move |conn| {
+            kv_delete(conn, &namespace, &key)
+        })
+        .await
+    }
+
+    /// Unexpired keys of `namespace` starting with `prefix`, in order
+    pub async fn list_keys(&self, namespace: &str, prefix: Option<&str>) -> Result<Vec<String>> {
+        let namespace = namespace.to_string();
+        let prefix = prefix.unwrap_or_default().to_string();
+        with_connection(self.pool.clone(), move |conn| {
+            kv_list_keys(conn, &namespace, &prefix)
+        })
+        .await
+    }
+
+    /// Deletes the expired pairs now instead of waiting for the sweeper and
+    /// returns how many there were
+    pub async fn sweep_expired(&self) -> Result<usize> {
+        with_connection(self.pool.clone(), kv_sweep).await
+    }
+
+    /// Runs a single statement that returns no rows and returns the number of
+    /// rows it changed
+    pub async fn execute(&self, sql: &str) -> Result<usize> {
+        let sql = sql.to_string();
+        with_connection(self.pool.clone(), move |conn| Ok(conn.execute(&sql, [])?)).await
+    }
+
+    /// Runs a query and returns its rows as objects keyed by column name
+    pub async fn query(&self, sql: &str) -> Result<Vec<Map<String, Value>>> {
+        let sql = sql.to_string();
+        with_connection(self.pool.clone(), move |conn| {
+            query_rows(conn, &sql, Vec::new())
+        })
+        .await
+    }
+
+    /// Runs a statement with `?1`, `?2`, ... (or `?`) bound to `params` in
+    /// order and returns the rows it produced, if any
+    pub async fn query_with_params(
+        &self,
+        sql: &str,
+        params: Vec<Value>,
+    ) -> Result<Vec<Map<String, Value>>> {
+        let sql = sql.to_string();
+        let params = params.iter().map(to_sql).collect();
+        with_connection(self.pool.clone(), move |conn| {
+            query_rows(conn, &sql, params)
+        })
+        .await
+    }
+
+    pub async fn create_table(&self, name: &str, columns: &[ColumnDef]) -> Result<()> {
+        let sql = create_table_sql(name, columns)?;
+        with_connection(self.pool.clone(), move |conn| {
+            conn.execute(&sql, [])?;
+            Ok(())
+        })
+        .await
+    }
+
+    /// Drops the table together with its full-text index
+    pub async fn drop_table(&self, name: &str) -> Result<()> {
+        let name = name.to_string();
+        with_connection(self.pool.clone(), move |conn| drop_table(conn, &name)).await
+    }
+
+    /// Version of the database schema, see `sqlite_migrations`
+    pub async fn schema_version(&self) -> Result<u32> {
+        with_connection(self.pool.clone(), |conn| {
+            sqlite_migrations::current_version(conn)
+        })
+        .await
+    }
+
+    /// Names of the user tables, including `kv_store` and `schema_version`
+    pub async fn list_tables(&self) -> Result<Vec<String>> {
+        with_connection(self.pool.clone(), list_tables).await
+    }
+
+    /// Tables with their row counts, columns and indexes, leaving out
+    /// tables hidden by `SqliteOptions::allowed_tables`
+    pub async fn describe_tables(&self) -> Result<Vec<TableInfo>> {
+        let options = Arc::clone(&self.options);
+        with_connection(self.pool.clone(), move |conn| {
+            describe_tables(conn, &options)
+        })
+        .await
+    }
+
+    /// Size of the database file in bytes; 0 for in-memory databases
+    pub async fn file_size(&self) -> Result<u64> {
+        file_size(&self.db_path).await
+    }
+
+    /// Runs `statements` in one transaction and returns the result of each.
+    /// If any statement fails, none of them take effect. The configured
+    /// restrictions, `max_rows` and query timeout apply as for
+    /// `sqlite_transaction`.
+    pub async fn transaction(&self, statements: Vec<TxStatement>) -> Result<Vec<Value>> {
+        let options = Arc::clone(&self.options);
+        with_connection(self.pool.clone(), move |conn| {
+            run_transaction(conn, &statements, &options)
+        })
+        .await
+    }
+
+    /// Copies the live database to `path`, overwriting it. Other connections
+    /// keep reading and writing while the copy runs.
+    pub async fn backup(&self, path: &str) -> Result<()> {
+        let path = path.to_string();
+        with_connection(self.pool.clone(), move |conn| backup(conn, &path)).await
+    }
+
+    /// Replaces the database with the backup at `path` and brings its schema
+    /// up to date
+    pub async fn restore(&self, path: &str) -> Result<()> {
+        let path = path.to_string();
+        with_connection(self.pool.clone(), move |conn| restore(conn, &path)).await
+    }
+
+    /// Builds a full-text index over `columns` of `table`, replacing any
+    /// earlier index of the table. Later changes to the table are indexed
+    /// automatically.
+    pub async fn fts_index(&self, table: &str, columns: &[String]) -> Result<()> {
+        let table = table.to_string();
+        let columns = columns.to_vec();
+        with_connection(self.pool.clone(), move |conn| {
+            fts_index(conn, &table, &columns)
+        })
+        .await
+    }
+
+    /// Best matches of the FTS5 `query` in `table`, or in every indexed table
+    pub async fn fts_search(
+        &self,
+        query: &str,
+        table: Option<&str>,
+        limit: usize,
+    ) -> Result<Vec<FtsMatch>> {
+        let query = query.to_string();
+        let table = table.map(str::to_string);
+        let options = Arc::clone(&self.options);
+        with_connection(self.pool.clone(), move |conn| {
+            fts_search(conn, &query, table.as_deref(), limit, &options)
+        })
+        .await
+    }
+}
+
+impl Drop for SqliteServer {
+    fn drop(&mut self) {
+        if let Some(sweeper) = self.sweeper.take() {
+            sweeper.abort();
+        }
+    }
+}
+
+/// Deletes expired key-value pairs every `EXPIRY_SWEEP_INTERVAL` until the
+/// returned task is aborted
+pub(crate) fn spawn_expiry_sweeper(pool: SqlitePool) -> JoinHandle<()> {
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(EXPIRY_SWEEP_INTERVAL);
+        loop {
+            interval.tick().await;
+            match with_connection(pool.clone(), kv_sweep).await {
+                Ok(0) => {}
+                Ok(swept) => debug!("Swept {} expired key-value pairs", swept),
+                Err(e) => warn!("Failed to sweep expired key-value pairs: {}", e),
+            }
+        }
+    })
+}
+
+/// Key for an encrypted database. Without SQLCipher the key would be ignored
+/// and the file left in plain text, so that is an error.
+#[cfg(feature = "sqlcipher")]
+fn encryption_key(encryption: &EncryptionConfig) -> Result<String> {
+    encryption.key()
+}
+
+#[cfg(not(feature = "sqlcipher"))]
+fn encryption_key(_encryption: &EncryptionConfig) -> Result<String> {
+    Err(Error::msg(
+        "Database encryption requires building with the sqlcipher feature",
+    ))
+}
+
+/// Runs `f` on a pooled connection in a blocking task
+async fn with_connection<T, F>(pool: SqlitePool, f: F) -> Result<T>
+where
+    T: Send + 'static,
+    F: FnOnce(&mut Connection) -> Result<T> + Send + 'static,
+{
+    tokio::task::spawn_blocking(move || {
+        let mut conn = pool.get()?;
+        f(&mut conn)
+    })
+    .await?
+}
+
+/// The `sqlite_database` resource: `tables` describes every table, `size`
+/// returns the size of the database file
+pub(crate) fn database_resource(
+    pool: SqlitePool,
+    db_path: &str,
+    options: Arc<SqliteOptions>,
+) -> Resource {
+    let db_path = db_path.to_string();
+    Resource::new(
+        "sqlite_database".to_string(),
+        "SQLite database connection".to_string(),
+        Some(json!({
+            "type": "object",
+            "properties": {
+                "action": {
+                    "type": "string",
+                    "description": "Action to perform (tables/size)",
+                    "enum": ["tables", "size"]
+                }
+            },
+            "required": ["action"]
+        })),
+        Some(json!({
+            "type": "object",
+            "properties": {
+                "tables": {
+                    "type": "array",
+                    "description": "Tables with their row counts, columns and indexes",
+                    "items": {
+                        "type": "object",
+                        "properties": {
+                            "name": { "type": "string" },
+                            "rows": { "type": "integer" },
+                            "columns": {
+                                "type": "array",
+                                "description": "name, type, not_null, default and primary_key of each column",
+                                "items": { "type": "object" }
+                            },
+                            "indexes": {
+                                "type": "array",
+                                "description": "name, unique and columns of each index",
+                                "items": { "type": "object" }
+                            }
+                        }
+                    }
+                },
+                "size": {
+                    "type": "integer",
+                    "description": "Size of the database file in bytes"
+                }
+            }
+        })),
+        Box::new(move |args| {
+            let pool = pool.clone();
+            let db_path = db_path.clone();
+            let options = Arc::clone(&options);
+            Box::pin(async move {
+                match args.get("action").and_then(|v| v.as_str()) {
+                    Some("tables") => {
+                        let tables =
+                            with_connection(pool, move |conn| describe_tables(conn, &options))
+                                .await?;
+                        Ok(json!({ "tables": tables }))
+                    }
+                    Some("size") => Ok(json!({ "size": file_size(&db_path).await? })),
+                    _ => Err(invalid_arguments("Invalid action")),
+                }
+            })
+        }),
+    )
+}
+
+fn describe_tables(conn: &mut Connection, options: &SqliteOptions) -> Result<Vec<TableInfo>> {
+    let mut tables = Vec::new();
+    for name in list_tables(conn)? {
+        if !options.allows_table(&name) {
+            continue;
+        }
+        let table = quote_identifier(&name);
+
+        let rows = conn.query_row(&format!("SELECT count(*) FROM {}", table), [], |row| {
+            row.get(0)
+        })?;
+        let columns = conn
+            .prepare(&format!("PRAGMA table_info({})", table))?
+            .query_map([], |row| {
+                Ok(ColumnInfo {
+                    name: row.get(1)?,
+                    column_type: row.get(2)?,
+                    not_null: row.get(3)?,
+                    default: row.get(4)?,
+                    primary_key: row.get::<_, i64>(5)? > 0,
+                })
+            })?
+            .collect::<rusqlite::Result<_>>()?;
+
+        let index_names: Vec<(String, bool)> = conn
+            .prepare(&format!("PRAGMA index_list({})", table))?
+            .query_map([], |row| Ok((row.get(1)?, row.get(2)?)))?
+            .collect::<rusqlite::Result<_>>()?;
+        let mut indexes = Vec::with_capacity(index_names.len());
+        for (index, unique) in index_names {
+            let columns = conn
+                .prepare(&format!("PRAGMA index_info({})", quote_identifier(&index)))?
+                .query_map([], |row| row.get::<_, Option<String>>(2))?
+                .collect::<rusqlite::Result<Vec<_>>>()?;
+            indexes.push(IndexInfo {
+                name: index,
+                unique,
+                // Expression columns have no name
+                columns: columns
+                    .into_iter()
+                    .map(|column| column.unwrap_or_else(|| "<expression>".to_string()))
+                    .collect(),
+            });
+        }
+
+        tables.push(TableInfo {
+            name,
+            rows,
+            columns,
+            indexes,
+        });
+    }
+    Ok(tables)
+}
+
+async fn file_size(db_path: &str) -> Result<u64> {
+    match tokio::fs::metadata(db_path).await {
+        Ok(metadata) => Ok(metadata.len()),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(0),
+        Err(e) => Err(e.into()),
+    }
+}
+
+/// Runs `f`, interrupting its statements once `options.query_timeout_ms`
+/// have passed
+fn with_timeout<T>(
+    conn: &Connection,
+    options: &SqliteOptions,
+    f: impl FnOnce(&Connection) -> Result<T>,
+) -> Result<T> {
+    let timeout = match options.query_timeout_ms {
+        Some(timeout) => Duration::from_millis(timeout),
+        None => return f(conn),
+    };
+
+    let deadline = Instant::now() + timeout;
+    conn.progress_handler(PROGRESS_OPS, Some(move || Instant::now() >= deadline));
+    let result = f(conn);
+    conn.progress_handler(0, None::<fn() -> bool>);
+
+    match result {
+        // The same query would run out of time again
+        Err(_) if Instant::now() >= deadline => Err(Error::new(
+            ToolError::new(
+                ToolErrorKind::Timeout,
+                format!("Query timed out after {} ms", timeout.as_millis()),
+            )
+            .with_retryable(false),
+        )),
+        result => result,
+    }
+}
+
+/// Runs `f` with an authorizer that denies whatever `options` do not allow
+fn guarded<T>(
+    conn: &Connection,
+    options: &SqliteOptions,
+    f: impl FnOnce(&Connection) -> Result<T>,
+) -> Result<T> {
+    if !options.is_restricted() {
+        return f(conn);
+    }
+
+    let denied = Arc::new(Mutex::new(None));
+    let views = Arc::new(Mutex::new(Vec::new()));
+    let hook_denied = Arc::clone(&denied);
+    let hook_views = Arc::clone(&views);
+    let hook_options = options.clone();
+    conn.authorizer(Some(move |context: AuthContext<'_>| {
+        let check = match context.action {
+            // Statements run by views and triggers may use any statement
+            // kind, but only on allowed tables
+            _ if context.accessor.is_some() => hook_options.check_table(classify(context.action).1),
+            // Schema table changes are checked as the CREATE, DROP or ALTER
+            // statement that makes them
+            AuthAction::Insert { table_name }
+            | AuthAction::Update { table_name, .. }
+            | AuthAction::Delete { table_name }
+                if is_schema_table(table_name) =>
+            {
+                Ok(())
+            }
+            AuthAction::CreateView { view_name } | AuthAction::CreateTempView { view_name } => {
+                hook_views.lock().unwrap().push(view_name.to_string());
+                hook_options.check_definition("views")
+            }
+            AuthAction::CreateTrigger { table_name, .. }
+            | AuthAction::CreateTempTrigger { table_name, .. } => hook_options
+                .check_definition("triggers")
+                .and_then(|()| hook_options.check("CREATE", Some(table_name))),
+            action => {
+                let (kind, table) = classify(action);
+                hook_options.check(kind, table)
+            }
+        };
+        match check {
+            Ok(()) => Authorization::Allow,
+            Err(e) => {
+                *hook_denied.lock().unwrap() = Some(e.to_string());
+                Authorization::Deny
+            }
+        }
+    }));
+    let mut result = f(conn);
+    if result.is_ok() {
+        // The body of a view is only resolved when the view is read, so read
+        // new views now and drop those that use tables which are not allowed
+        for view in views.lock().unwrap().drain(..) {
+            let sql = format!("SELECT * FROM {}", quote_identifier(&view));
+            if conn.prepare(&sql).is_err() && denied.lock().unwrap().is_some() {
+                conn.authorizer(None::<fn(AuthContext<'_>) -> Authorization>);
+                if let Err(e) = conn.execute(&format!("DROP VIEW {}", quote_identifier(&view)), [])
+                {
+                    warn!("Failed to drop view {}: {}", view, e);
+                }
+                result = Err(Error::new(ToolError::new(
+                    ToolErrorKind::PermissionDenied,
+                    "View uses a table that is not allowed",
+                )));
+                break;
+            }
+        }
+    }
+    conn.authorizer(None::<fn(AuthContext<'_>) -> Authorization>);
+
+    let denied = denied.lock().unwrap().take();
+    match (result, denied) {
+        (Err(_), Some(reason)) => Err(Error::new(ToolError::new(
+            ToolErrorKind::PermissionDenied,
+            reason,
+        ))),
+        (result, _) => result,
+    }
+}
+
+/// Whether `table` is the table SQLite keeps the schema in
+fn is_schema_table(table: &str) -> bool {
+    [
+        "sqlite_master",
+        "sqlite_temp_master",
+        "sqlite_schema",
+        "sqlite_temp_schema",
+    ]
+    .iter()
+    .any(|name| name.eq_ignore_ascii_case(table))
+}
+
+/// Statement kind, as named in `SqliteOptions::allowed_statements`, and
+/// table of an authorizer action
+fn classify(action: AuthAction<'_>) -> (&'static str, Option<&str>) {
+    match action {
+        AuthAction::Select | AuthAction::Function { .. } | AuthAction::Recursive => {
+            ("SELECT", None)
+        }
+        AuthAction::Read { table_name, .. } => ("SELECT", Some(table_name)),
+        AuthAction::Insert { table_name } => ("INSERT", Some(table_name)),
+        AuthAction::Update { table_name, .. } => ("UPDATE", Some(table_name)),
+        AuthAction::Delete { table_name } => ("DELETE", Some(table_name)),
+        AuthAction::CreateTable { table_name }
+        | AuthAction::CreateTempTable { table_name }
+        | AuthAction::CreateIndex { table_name, .. }
+        | AuthAction::CreateTempIndex { table_name, .. }
+        | AuthAction::CreateTrigger { table_name, .. }
+        | AuthAction::CreateTempTrigger { table_name, .. }
+        | AuthAction::CreateVtable { table_name, .. } => ("CREATE", Some(table_name)),
+        AuthAction::CreateView { .. } | AuthAction::CreateTempView { .. } => ("CREATE", None),
+        AuthAction::DropTable { table_name }
+        | AuthAction::DropTempTable { table_name }
+        | AuthAction::DropIndex { table_name, .. }
+        | AuthAction::DropTempIndex { table_name, .. }
+        | AuthAction::DropTrigger { table_name, .. }
+        | AuthAction::DropTempTrigger { table_name, .. }
+        | AuthAction::DropVtable { table_name, .. } => ("DROP", Some(table_name)),
+        AuthAction::DropView { .. } | AuthAction::DropTempView { .. } => ("DROP", None),
+        AuthAction::AlterTable { table_name, .. } => ("ALTER", Some(table_name)),
+        AuthAction::Pragma { .. } => ("PRAGMA", None),
+        AuthAction::Transaction { .. } | AuthAction::Savepoint { .. } => ("TRANSACTION", None),
+        AuthAction::Attach { .. } | AuthAction::Detach { .. } => ("ATTACH", None),
+        _ => ("OTHER", None),
+    }
+}
+
+/// Executes the key-value tool `name` with the tool call's arguments
+async fn run_kv_tool(pool: SqlitePool, name: &str, args: Value) -> Result<Value> {
+    let string_arg = |key: &str| {
+        args[key]
+            .as_str()
+            .map(str::to_string)
+            .ok_or_else(|| invalid_arguments(format!("Invalid argument: {}", key)))
+    };
+    let namespace = args["namespace"]
+        .as_str()
+        .unwrap_or(DEFAULT_NAMESPACE)
+        .to_string();
+
+    match name {
+        "sqlite_set" => {
+            let key = string_arg("key")?;
+            let value = string_arg("value")?;
+            let expires_at = expiry(&args)?;
+            let success = with_connection(pool, move |conn| {
+                kv_set(conn, &namespace, &key, &value, expires_at)
+            })
+            .await?;
+            Ok(json!({ "success": success }))
+        }
+        "sqlite_get" => {
+            let key = string_arg("key")?;
+            let value = with_connection(pool, move |conn| kv_get(conn, &namespace, &key)).await?;
+            Ok(json!({
+                "found": value.is_some(),
+                "value": value.unwrap_or_default()
+            }))
+        }
+        "sqlite_delete" => {
+            let key = string_arg("key")?;
+            let deleted =
+                with_connection(pool, move |conn| kv_delete(conn, &namespace, &key)).await?;
+            Ok(json!({
+                "success": true,
+                "deleted": deleted
+            }))
+        }
+        "sqlite_list_keys" => {
+            let prefix = args["prefix"].as_str().unwrap_or_default().to_string();
+            let keys =
+                with_connection(pool, move |conn| kv_list_keys(conn, &namespace, &prefix)).await?;
+            Ok(json!({ "keys": keys }))
+        }
+        _ => Err(Error::new(ToolError::new(
+            ToolErrorKind::NotFound,
+            format!("Unknown tool: {}", name),
+        ))),
+    }
+}
+
+/// Unix time at which a pair set with `args` expires: `expires_at` as an
+/// RFC 3339 timestamp, or `ttl_seconds` from now. A `ttl_seconds` that is
+/// not positive, or too large to add to the current time, is refused.
+fn expiry(args: &Value) -> Result<Option<i64>> {
+    if let Some(expires_at) = args["expires_at"].as_str() {
+        let time = DateTime::parse_from_rfc3339(expires_at)
+            .map_err(|e| invalid_arguments(format!("Invalid argument: expires_at ({})", e)))?;
+        return Ok(Some(time.timestamp()));
+    }
+    match &args["ttl_seconds"] {
+        Value::Null => Ok(None),
+        ttl => match ttl.as_i64() {
+            Some(ttl) if ttl > 0 => match Utc::now().timestamp().checked_add(ttl) {
+                Some(expires_at) => Ok(Some(expires_at)),
+                None => Err(invalid_arguments(
+                    "Invalid argument: ttl_seconds is too large",
+                )),
+            },
+            Some(_) => Err(invalid_arguments(
+                "Invalid argument: ttl_seconds must be positive",
+            )),
+            None => Err(invalid_arguments(
+                "Invalid argument: ttl_seconds must be an integer",
+            )),
+        },
+    }
+}
+
+/// Builds the tool `name` from `KV_TOOLS` with its schemas
+fn kv_tool(name: &'static str, pool: SqlitePool) -> Tool {
+    let namespace = json!({
+        "type": "string",
+        "description": "Namespace of the key, e.g. a session id (default: \"default\")"
+    });
+    let (description, input_schema, output_schema) = match name {
+        "sqlite_set" => (
+            "Set a key-value pair in SQLite database",
             json!({
                 "type": "object",
                 "properties": {
@@ -97,11 +1234,21 @@ impl SqliteServer {
                     "value": {
                         "type": "string",
                         "description": "Value to set"
+                    },
+                    "namespace": namespace,
+                    "expires_at": {
+                        "type": "string",
+                        "description": "RFC 3339 time after which the pair is forgotten"
+                    },
+                    "ttl_seconds": {
+                        "type": "integer",
+                        "minimum": 1,
+                        "description": "Seconds after which the pair is forgotten"
                     }
                 },
                 "required": ["key", "value"]
             }),
-            Some(json!({
+            json!({
                 "type": "object",
                 "properties": {
                     "success": {
@@ -109,278 +1256,1079 @@ impl SqliteServer {
                         "description": "Whether the operation was successful"
                     }
                 }
-            })),
-            Box::new(move |args| {
-                let conn_set = Arc::clone(&conn_set);
-                let key = args["key"].as_str().unwrap_or_default().to_string();
-                let value = args["value"].as_str().unwrap_or_default().to_string();
-
-                Box::pin(async move {
-                    let result = tokio::task::spawn_blocking(move || {
-                        let mut conn = conn_set.lock().unwrap();
-                        let tx = conn.transaction()?;
-
-                        let result = tx.execute(
-                            "INSERT OR REPLACE INTO kv_store (key, value) VALUES (?1, ?2)",
-                            [&key, &value],
-                        )?;
-
-                        tx.commit()?;
-                        Ok::<_, Error>(result)
-                    })
-                    .await??;
+            }),
+        ),
+        "sqlite_get" => (
+            "Get a value from SQLite database by key",
+            json!({
+                "type": "object",
+                "properties": {
+                    "key": {
+                        "type": "string",
+                        "description": "Key to get"
+                    },
+                    "namespace": namespace
+                },
+                "required": ["key"]
+            }),
+            json!({
+                "type": "object",
+                "properties": {
+                    "value": {
+                        "type": "string",
+                        "description": "The value associated with the key"
+                    },
+                    "found": {
+                        "type": "boolean",
+                        "description": "Whether the key was found"
+                    }
+                }
+            }),
+        ),
+        "sqlite_delete" => (
+            "Delete a key-value pair from SQLite database",
+            json!({
+                "type": "object",
+                "properties": {
+                    "key": {
+                        "type": "string",
+                        "description": "Key to delete"
+                    },
+                    "namespace": namespace
+                },
+                "required": ["key"]
+            }),
+            json!({
+                "type": "object",
+                "properties": {
+                    "success": {
+                        "type": "boolean",
+                        "description": "Whether the operation was successful"
+                    },
+                    "deleted": {
+                        "type": "boolean",
+                        "description": "Whether the key was found and deleted"
+                    }
+                }
+            }),
+        ),
+        _ => (
+            "List the keys of a namespace in SQLite database",
+            json!({
+                "type": "object",
+                "properties": {
+                    "namespace": namespace,
+                    "prefix": {
+                        "type": "string",
+                        "description": "Only list keys starting with this prefix"
+                    }
+                }
+            }),
+            json!({
+                "type": "object",
+                "properties": {
+                    "keys": {
+                        "type": "array",
+                        "description": "Unexpired keys in order",
+                        "items": {
+                            "type": "string"
+                        }
+                    }
+                }
+            }),
+        ),
+    };
+
+    Tool::new(
+        name.to_string(),
+        description.to_string(),
+        input_schema,
+        Some(output_schema),
+        Box::new(move |args| {
+            let pool = pool.clone();
+            Box::pin(async move { run_kv_tool(pool, name, args).await })
+        }),
+    )
+}
 
-                    Ok(json!({ "success": result > 0 }))
+/// Executes the SQL tool `name` with the tool call's arguments
+async fn run_sql_tool(
+    pool: SqlitePool,
+    options: Arc<SqliteOptions>,
+    name: &str,
+    args: Value,
+) -> Result<Value> {
+    let string_arg = |key: &str| {
+        args[key]
+            .as_str()
+            .map(str::to_string)
+            .ok_or_else(|| invalid_arguments(format!("Invalid argument: {}", key)))
+    };
+
+    match name {
+        "sqlite_execute" => {
+            let sql = string_arg("query")?;
+            let rows_affected = with_connection(pool, move |conn| {
+                guarded(conn, &options, |conn| {
+                    with_timeout(conn, &options, |conn| Ok(conn.execute(&sql, [])?))
                 })
+            })
+            .await?;
+            Ok(json!({ "success": true, "rows_affected": rows_affected }))
+        }
+        "sqlite_query" => {
+            let sql = string_arg("query")?;
+            let (rows, truncated) = with_connection(pool, move |conn| {
+                guarded(conn, &options, |conn| {
+                    with_timeout(conn, &options, |conn| {
+                        query_rows_limited(conn, &sql, Vec::new(), options.max_rows)
+                    })
+                })
+            })
+            .await?;
+            Ok(json!({ "success": true, "rows": rows, "truncated": truncated }))
+        }
+        "sqlite_query_params" => {
+            let sql = string_arg("query")?;
+            let params = match &args["params"] {
+                Value::Array(params) => params.iter().map(to_sql).collect(),
+                Value::Null => Vec::new(),
+                _ => {
+                    return Err(invalid_arguments(
+                        "Invalid argument: params must be an array",
+                    ))
+                }
+            };
+            let (rows, truncated) = with_connection(pool, move |conn| {
+                guarded(conn, &options, |conn| {
+                    with_timeout(conn, &options, |conn| {
+                        query_rows_limited(conn, &sql, params, options.max_rows)
+                    })
+                })
+            })
+            .await?;
+            Ok(json!({ "success": true, "rows": rows, "truncated": truncated }))
+        }
+        "sqlite_create_table" => {
+            let columns: Vec<ColumnDef> = serde_json::from_value(args["columns"].clone())
+                .map_err(|e| invalid_arguments(format!("Invalid argument: columns ({})", e)))?;
+            let name = string_arg("name")?;
+            options.check("CREATE", Some(&name))?;
+            let sql = create_table_sql(&name, &columns)?;
+            with_connection(pool, move |conn| Ok(conn.execute(&sql, [])?)).await?;
+            Ok(json!({ "success": true }))
+        }
+        "sqlite_drop_table" => {
+            let name = string_arg("name")?;
+            options.check("DROP", Some(&name))?;
+            with_connection(pool, move |conn| drop_table(conn, &name)).await?;
+            Ok(json!({ "success": true }))
+        }
+        "sqlite_list_tables" => {
+            let tables: Vec<String> = with_connection(pool, list_tables)
+                .await?
+                .into_iter()
+                .filter(|table| options.allows_table(table))
+                .collect();
+            Ok(json!({ "tables": tables }))
+        }
+        "sqlite_fts_index" => {
+            let table = string_arg("table")?;
+            let columns: Vec<String> = serde_json::from_value(args["columns"].clone())
+                .map_err(|e| invalid_arguments(format!("Invalid argument: columns ({})", e)))?;
+            options.check("CREATE", Some(&table))?;
+            with_connection(pool, move |conn| fts_index(conn, &table, &columns)).await?;
+            Ok(json!({ "success": true }))
+        }
+        "sqlite_fts_search" => {
+            let query = string_arg("query")?;
+            let table = args["table"].as_str().map(str::to_string);
+            let limit = args["limit"]
+                .as_u64()
+                .map_or(DEFAULT_FTS_LIMIT, |limit| limit as usize);
+            let matches = with_connection(pool, move |conn| {
+                fts_search(conn, &query, table.as_deref(), limit, &options)
+            })
+            .await?;
+            Ok(json!({ "success": true, "matches": matches }))
+        }
+        "sqlite_backup" => {
+            let path = string_arg("path")?;
+            options.check_backup()?;
+            let full_path = options.backup_path(&path)?;
+            check_overwrite(&full_path)?;
+            if let Some(parent) = full_path.parent() {
+                tokio::fs::create_dir_all(parent).await?;
+            }
+            let full_path = full_path.to_string_lossy().to_string();
+            let size = {
+                let full_path = full_path.clone();
+                with_connection(pool, move |conn| backup(conn, &full_path)).await?;
+                file_size(&full_path).await?
+            };
+            Ok(json!({ "success": true, "path": full_path, "size": size }))
+        }
+        "sqlite_restore" => {
+            let path = string_arg("path")?;
+            options.check_restore()?;
+            let full_path = options.backup_path(&path)?.to_string_lossy().to_string();
+            with_connection(pool, move |conn| restore(conn, &full_path)).await?;
+            Ok(json!({ "success": true }))
+        }
+        "sqlite_transaction" => {
+            let statements: Vec<TxStatement> = serde_json::from_value(args["statements"].clone())
+                .map_err(|e| {
+                invalid_arguments(format!("Invalid argument: statements ({})", e))
+            })?;
+            let results = with_connection(pool, move |conn| {
+                run_transaction(conn, &statements, &options)
+            })
+            .await?;
+            Ok(json!({ "success": true, "results": results }))
+        }
+        _ => Err(Error::new(ToolError::new(
+            ToolErrorKind::NotFound,
+            format!("Unknown tool: {}", name),
+        ))),
+    }
+}
+
+/// Builds the tool `name` from `SQL_TOOLS` with its schemas
+fn sql_tool(name: &'static str, pool: SqlitePool, options: Arc<SqliteOptions>) -> Tool {
+    let (description, input_schema, output_schema) = match name {
+        "sqlite_execute" => (
+            "Execute a SQL statement that returns no rows, e.g. INSERT, UPDATE or DELETE",
+            json!({
+                "type": "object",
+                "properties": {
+                    "query": {
+                        "type": "string",
+                        "description": "SQL statement to execute"
+                    }
+                },
+                "required": ["query"]
+            }),
+            json!({
+                "type": "object",
+                "properties": {
+                    "success": {
+                        "type": "boolean",
+                        "description": "Whether the query executed successfully"
+                    },
+                    "rows_affected": {
+                        "type": "integer",
+                        "description": "Number of rows affected by the query"
+                    }
+                }
+            }),
+        ),
+        "sqlite_query" => (
+            "Execute a SQL query and return results",
+            json!({
+                "type": "object",
+                "properties": {
+                    "query": {
+                        "type": "string",
+                        "description": "SQL query to execute"
+                    }
+                },
+                "required": ["query"]
+            }),
+            json!({
+                "type": "object",
+                "properties": {
+                    "success": {
+                        "type": "boolean",
+                        "description": "Whether the query executed successfully"
+                    },
+                    "rows": {
+                        "type": "array",
+                        "description": "Query results as an array of objects",
+                        "items": {
+                            "type": "object",
+                            "additionalProperties": true
+                        }
+                    },
+                    "truncated": {
+                        "type": "boolean",
+                        "description": "Whether rows beyond max_rows were left out"
+                    }
+                }
+            }),
+        ),
+        "sqlite_query_params" => (
+            "Execute a SQL statement with ?1, ?2, ... placeholders bound to params and return \
+             its rows. Use this instead of putting values into the SQL text.",
+            json!({
+                "type": "object",
+                "properties": {
+                    "query": {
+                        "type": "string",
+                        "description": "SQL statement with ?1, ?2, ... placeholders"
+                    },
+                    "params": {
+                        "type": "array",
+                        "description": "Values bound to the placeholders in order",
+                        "items": {}
+                    }
+                },
+                "required": ["query", "params"]
+            }),
+            json!({
+                "type": "object",
+                "properties": {
+                    "success": {
+                        "type": "boolean",
+                        "description": "Whether the statement executed successfully"
+                    },
+                    "rows": {
+                        "type": "array",
+                        "description": "Result rows as an array of objects; empty for statements without results",
+                        "items": {
+                            "type": "object",
+                            "additionalProperties": true
+                        }
+                    },
+                    "truncated": {
+                        "type": "boolean",
+                        "description": "Whether rows beyond max_rows were left out"
+                    }
+                }
+            }),
+        ),
+        "sqlite_create_table" => (
+            "Create a new table",
+            json!({
+                "type": "object",
+                "properties": {
+                    "name": {
+                        "type": "string",
+                        "description": "Name of the table to create"
+                    },
+                    "columns": {
+                        "type": "array",
+                        "description": "Array of column definitions",
+                        "items": {
+                            "type": "object",
+                            "properties": {
+                                "name": {
+                                    "type": "string",
+                                    "description": "Column name"
+                                },
+                                "type": {
+                                    "type": "string",
+                                    "description": "Column type (INTEGER, TEXT, REAL, BLOB)"
+                                },
+                                "primary_key": {
+                                    "type": "boolean",
+                                    "description": "Whether this column is a primary key"
+                                },
+                                "not_null": {
+                                    "type": "boolean",
+                                    "description": "Whether this column cannot be null"
+                                },
+                                "unique": {
+                                    "type": "boolean",
+                                    "description": "Whether this column must be unique"
+                                }
+                            },
+                            "required": ["name", "type"]
+                        }
+                    }
+                },
+                "required": ["name", "columns"]
+            }),
+            json!({
+                "type": "object",
+                "properties": {
+                    "success": {
+                        "type": "boolean",
+                        "description": "Whether the table was created successfully"
+                    }
+                }
+            }),
+        ),
+        "sqlite_drop_table" => (
+            "Drop a table",
+            json!({
+                "type": "object",
+                "properties": {
+                    "name": {
+                        "type": "string",
+                        "description": "Name of the table to drop"
+                    }
+                },
+                "required": ["name"]
+            }),
+            json!({
+                "type": "object",
+                "properties": {
+                    "success": {
+                        "type": "boolean",
+                        "description": "Whether the table was dropped successfully"
+                    }
+                }
+            }),
+        ),
+        "sqlite_fts_index" => (
+            "Build a full-text search index over text columns of a table",
+            json!({
+                "type": "object",
+                "properties": {
+                    "table": {
+                        "type": "string",
+                        "description": "Table to index"
+                    },
+                    "columns": {
+                        "type": "array",
+                        "description": "Text columns to index",
+                        "items": {
+                            "type": "string"
+                        }
+                    }
+                },
+                "required": ["table", "columns"]
+            }),
+            json!({
+                "type": "object",
+                "properties": {
+                    "success": {
+                        "type": "boolean",
+                        "description": "Whether the index was built"
+                    }
+                }
+            }),
+        ),
+        "sqlite_fts_search" => (
+            "Keyword search over tables indexed with sqlite_fts_index, best matches first",
+            json!({
+                "type": "object",
+                "properties": {
+                    "query": {
+                        "type": "string",
+                        "description": "FTS5 query, e.g. rust AND async, \"exact phrase\" or prefix*"
+                    },
+                    "table": {
+                        "type": "string",
+                        "description": "Only search this table (default: all indexed tables)"
+                    },
+                    "limit": {
+                        "type": "integer",
+                        "description": "Maximum number of matches (default 10)"
+                    }
+                },
+                "required": ["query"]
+            }),
+            json!({
+                "type": "object",
+                "properties": {
+                    "success": {
+                        "type": "boolean",
+                        "description": "Whether the search succeeded"
+                    },
+                    "matches": {
+                        "type": "array",
+                        "description": "Matching rows with table, rowid, rank, snippet and row",
+                        "items": {
+                            "type": "object",
+                            "additionalProperties": true
+                        }
+                    }
+                }
+            }),
+        ),
+        "sqlite_backup" => (
+            "Save a snapshot of the whole database to a file while it stays in use",
+            json!({
+                "type": "object",
+                "properties": {
+                    "path": {
+                        "type": "string",
+                        "description": "File in the backup directory to write the backup to; an existing backup is overwritten"
+                    }
+                },
+                "required": ["path"]
+            }),
+            json!({
+                "type": "object",
+                "properties": {
+                    "success": {
+                        "type": "boolean",
+                        "description": "Whether the backup was written"
+                    },
+                    "path": {
+                        "type": "string",
+                        "description": "File the backup was written to"
+                    },
+                    "size": {
+                        "type": "integer",
+                        "description": "Size of the backup in bytes"
+                    }
+                }
             }),
-        );
-
-        // Register get tool
-        let conn_get = Arc::clone(&conn);
-        let get_tool = Tool::new(
-            "sqlite_get".to_string(),
-            "Get a value from SQLite database by key".to_string(),
+        ),
+        "sqlite_restore" => (
+            "Replace the whole database with a backup made by sqlite_backup",
             json!({
                 "type": "object",
                 "properties": {
-                    "key": {
+                    "path": {
                         "type": "string",
-                        "description": "Key to get"
+                        "description": "Backup file in the backup directory to restore"
                     }
                 },
-                "required": ["key"]
+                "required": ["path"]
             }),
-            Some(json!({
+            json!({
                 "type": "object",
                 "properties": {
-                    "value": {
-                        "type": "string",
-                        "description": "The value associated with the key"
-                    },
-                    "found": {
+                    "success": {
                         "type": "boolean",
-                        "description": "Whether the key was found"
+                        "description": "Whether the database was restored"
                     }
                 }
-            })),
-            Box::new(move |args| {
-                let conn_get = Arc::clone(&conn_get);
-                let key = args["key"].as_str().unwrap_or_default().to_string();
-
-                Box::pin(async move {
-                    let result = tokio::task::spawn_blocking(move || {
-                        let mut conn = conn_get.lock().unwrap();
-                        let tx = conn.transaction()?;
-                        let mut value = String::new();
-                        let mut found = false;
-
-                        {
-                            let mut stmt =
-                                tx.prepare("SELECT value FROM kv_store WHERE key = ?1")?;
-                            let mut rows = stmt.query([&key])?;
-                            if let Some(row) = rows.next()? {
-                                value = row.get(0)?;
-                                found = true;
-                            }
-                        }
-
-                        tx.commit()?;
-                        Ok::<_, Error>((value, found))
-                    })
-                    .await??;
-
-                    Ok(json!({
-                        "value": result.0,
-                        "found": result.1
-                    }))
-                })
             }),
-        );
-
-        // Register delete tool
-        let conn_delete = Arc::clone(&conn);
-        let delete_tool = Tool::new(
-            "sqlite_delete".to_string(),
-            "Delete a key-value pair from SQLite database".to_string(),
+        ),
+        "sqlite_transaction" => (
+            "Execute several SQL statements atomically: either all of them take effect or, \
+             if any fails, none do. Use this for changes that span multiple statements.",
             json!({
                 "type": "object",
                 "properties": {
-                    "key": {
-                        "type": "string",
-                        "description": "Key to delete"
+                    "statements": {
+                        "type": "array",
+                        "description": "Statements in order, each SQL text or {\"query\": ..., \"params\": [...]} with ?1, ?2, ... placeholders",
+                        "items": {
+                            "oneOf": [
+                                { "type": "string" },
+                                {
+                                    "type": "object",
+                                    "properties": {
+                                        "query": { "type": "string" },
+                                        "params": { "type": "array", "items": {} }
+                                    },
+                                    "required": ["query"]
+                                }
+                            ]
+                        }
                     }
                 },
-                "required": ["key"]
+                "required": ["statements"]
             }),
-            Some(json!({
+            json!({
                 "type": "object",
                 "properties": {
                     "success": {
                         "type": "boolean",
-                        "description": "Whether the operation was successful"
+                        "description": "Whether every statement succeeded and the transaction was committed"
                     },
-                    "deleted": {
-                        "type": "boolean",
-                        "description": "Whether the key was found and deleted"
+                    "results": {
+                        "type": "array",
+                        "description": "Per statement: rows and truncated for queries, rows_affected otherwise",
+                        "items": {
+                            "type": "object",
+                            "additionalProperties": true
+                        }
+                    }
+                }
+            }),
+        ),
+        _ => (
+            "List the tables in the database the SQL tools may use",
+            json!({
+                "type": "object",
+                "properties": {}
+            }),
+            json!({
+                "type": "object",
+                "properties": {
+                    "tables": {
+                        "type": "array",
+                        "description": "List of table names",
+                        "items": {
+                            "type": "string"
+                        }
                     }
                 }
-            })),
-            Box::new(move |args| {
-                let conn_delete = Arc::clone(&conn_delete);
-                let key = args["key"].as_str().unwrap_or_default().to_string();
+            }),
+        ),
+    };
+
+    Tool::new(
+        name.to_string(),
+        description.to_string(),
+        input_schema,
+        Some(output_schema),
+        Box::new(move |args| {
+            let pool = pool.clone();
+            let options = Arc::clone(&options);
+            Box::pin(async move { run_sql_tool(pool, options, name, args).await })
+        }),
+    )
+}
 
-                Box::pin(async move {
-                    let deleted = tokio::task::spawn_blocking(move || {
-                        let mut conn = conn_delete.lock().unwrap();
-                        let tx = conn.transaction()?;
+fn query_rows(
+    conn: &Connection,
+    sql: &str,
+    params: Vec<SqlValue>,
+) -> Result<Vec<Map<String, Value>>> {
+    Ok(query_rows_limited(conn, sql, params, None)?.0)
+}
 
-                        let deleted = tx.execute("DELETE FROM kv_store WHERE key = ?1", [&key])?;
+/// Rows of a query, at most `max_rows` of them, and whether more rows were
+/// left out
+fn query_rows_limited(
+    conn: &Connection,
+    sql: &str,
+    params: Vec<SqlValue>,
+    max_rows: Option<usize>,
+) -> Result<(Vec<Map<String, Value>>, bool)> {
+    let mut stmt = conn.prepare(sql)?;
+    let expected = stmt.parameter_count();
+    if params.len() != expected {
+        return Err(invalid_arguments(format!(
+            "Query expects {} parameters, got {}",
+            expected,
+            params.len()
+        )));
+    }
+    let columns: Vec<String> = stmt
+        .column_names()
+        .into_iter()
+        .map(str::to_string)
+        .collect();
+
+    let mut rows = stmt.query(params_from_iter(params))?;
+    let mut results = Vec::new();
+    while let Some(row) = rows.next()? {
+        if max_rows == Some(results.len()) {
+            return Ok((results, true));
+        }
+        let mut object = Map::new();
+        for (index, column) in columns.iter().enumerate() {
+            object.insert(column.clone(), to_json(row.get_ref(index)?));
+        }
+        results.push(object);
+    }
+    Ok((results, false))
+}
 
-                        tx.commit()?;
-                        Ok::<_, Error>(deleted)
-                    })
-                    .await??;
+/// Runs `statements` in a transaction that is rolled back unless all of
+/// them succeed
+fn run_transaction(
+    conn: &mut Connection,
+    statements: &[TxStatement],
+    options: &SqliteOptions,
+) -> Result<Vec<Value>> {
+    if statements.is_empty() {
+        return Err(invalid_arguments(
+            "Invalid argument: statements must not be empty",
+        ));
+    }
 
-                    Ok(json!({
-                        "success": true,
-                        "deleted": deleted > 0
-                    }))
+    let tx = conn.transaction()?;
+    let results = guarded(&tx, &options.in_transaction(), |conn| {
+        with_timeout(conn, options, |conn| {
+            statements
+                .iter()
+                .enumerate()
+                .map(|(index, statement)| {
+                    let (sql, params) = match statement {
+                        TxStatement::Sql(sql) => (sql, Vec::new()),
+                        TxStatement::WithParams { query, params } => {
+                            (query, params.iter().map(to_sql).collect())
+                        }
+                    };
+                    run_statement(conn, sql, params, options.max_rows)
+                        .map_err(|e| Error::msg(format!("Statement {} failed: {}", index + 1, e)))
                 })
-            }),
-        );
-
-        mcp_server.register_tool(set_tool).await?;
-        mcp_server.register_tool(get_tool).await?;
-        mcp_server.register_tool(delete_tool).await?;
+                .collect::<Result<Vec<_>>>()
+        })
+    })?;
+    // Dropping the transaction without committing rolls it back
+    tx.commit()?;
+    Ok(results)
+}
 
-        Ok(())
+/// Rows of a query, at most `max_rows` of them, or the number of rows
+/// changed by any other statement
+fn run_statement(
+    conn: &Connection,
+    sql: &str,
+    params: Vec<SqlValue>,
+    max_rows: Option<usize>,
+) -> Result<Value> {
+    let returns_rows = conn.prepare_cached(sql)?.column_count() > 0;
+    if returns_rows {
+        let (rows, truncated) = query_rows_limited(conn, sql, params, max_rows)?;
+        Ok(json!({ "rows": rows, "truncated": truncated }))
+    } else {
+        let rows_affected = conn.execute(sql, params_from_iter(params))?;
+        Ok(json!({ "rows_affected": rows_affected }))
     }
+}
 
-    pub async fn initialize(&mut self) -> Result<()> {
-        info!("Initializing SQLite server");
-        self.mcp_server.initialize().await
+/// SQLite value bound for a JSON parameter. Booleans become 0 or 1, arrays
+/// and objects are stored as JSON text.
+fn to_sql(value: &Value) -> SqlValue {
+    match value {
+        Value::Null => SqlValue::Null,
+        Value::Bool(b) => SqlValue::Integer(*b as i64),
+        Value::Number(n) => match n.as_i64() {
+            Some(i) => SqlValue::Integer(i),
+            None => SqlValue::Real(n.as_f64().unwrap_or_default()),
+        },
+        Value::String(s) => SqlValue::Text(s.clone()),
+        other => SqlValue::Text(other.to_string()),
     }
+}
 
-    pub async fn cleanup(&mut self) -> Result<()> {
-        info!("Cleaning up SQLite server");
-        self.mcp_server.cleanup().await
+/// JSON form of a column value; blobs become hex strings
+fn to_json(value: ValueRef) -> Value {
+    match value {
+        ValueRef::Null => Value::Null,
+        ValueRef::Integer(i) => json!(i),
+        ValueRef::Real(f) => json!(f),
+        ValueRef::Text(text) => json!(String::from_utf8_lossy(text)),
+        ValueRef::Blob(bytes) => json!(bytes
+            .iter()
+            .map(|b| format!("{:02x}", b))
+            .collect::<String>()),
     }
+}
 
-    pub async fn execute_tool(
-        &self,
-        tool_name: &str,
-        arguments: &Value,
-        _retry_count: u32,
-        _timeout: std::time::Duration,
-    ) -> Result<Value> {
-        match tool_name {
-            "sqlite_set" => {
-                let key = arguments["key"]
-                    .as_str()
-                    .ok_or_else(|| Error::msg("Invalid argument: key"))?;
-                let value = arguments["value"]
-                    .as_str()
-                    .ok_or_else(|| Error::msg("Invalid argument: value"))?;
-
-                let success = self.set(key, value).await?;
-                Ok(json!({ "success": success }))
-            }
-            "sqlite_get" => {
-                let key = arguments["key"]
-                    .as_str()
-                    .ok_or_else(|| Error::msg("Invalid argument: key"))?;
-
-                let (value, found) = self.get(key).await?;
-                Ok(json!({
-                    "value": value,
-                    "found": found
-                }))
-            }
-            "sqlite_delete" => {
-                let key = arguments["key"]
-                    .as_str()
-                    .ok_or_else(|| Error::msg("Invalid argument: key"))?;
-
-                let deleted = self.delete(key).await?;
-                Ok(json!({
-                    "success": true,
-                    "deleted": deleted
-                }))
-            }
-            _ => Err(Error::msg(format!("Unknown tool: {}", tool_name))),
-        }
-    }
+/// Stores a key-value pair, replacing any earlier value and expiry
+fn kv_set(
+    conn: &mut Connection,
+    namespace: &str,
+    key: &str,
+    value: &str,
+    expires_at: Option<i64>,
+) -> Result<bool> {
+    let changes = conn.execute(
+        "INSERT OR REPLACE INTO kv_store (namespace, key, value, expires_at)
+         VALUES (?1, ?2, ?3, ?4)",
+        params![namespace, key, value, expires_at],
+    )?;
+    Ok(changes > 0)
+}
 
-    pub async fn list_tools(&self) -> Result<Vec<crate::protocol::ToolSchema>> {
-        self.mcp_server.list_tools().await
+fn kv_get(conn: &mut Connection, namespace: &str, key: &str) -> Result<Option<String>> {
+    let mut stmt = conn.prepare_cached(
+        "SELECT value FROM kv_store
+         WHERE namespace = ?1 AND key = ?2 AND (expires_at IS NULL OR expires_at > ?3)",
+    )?;
+    let mut rows = stmt.query(params![namespace, key, Utc::now().timestamp()])?;
+    match rows.next()? {
+        Some(row) => Ok(Some(row.get(0)?)),
+        None => Ok(None),
     }
+}
 
-    pub async fn set(&self, key: &str, value: &str) -> Result<bool> {
-        let conn = self.conn.clone();
-        let key = key.to_string();
-        let value = value.to_string();
+fn kv_delete(conn: &mut Connection, namespace: &str, key: &str) -> Result<bool> {
+    let changes = conn.execute(
+        "DELETE FROM kv_store WHERE namespace = ?1 AND key = ?2",
+        params![namespace, key],
+    )?;
+    Ok(changes > 0)
+}
 
-        let result = tokio::task::spawn_blocking(move || {
-            let mut conn = conn.lock().unwrap();
-            let tx = conn.transaction()?;
+fn kv_list_keys(conn: &mut Connection, namespace: &str, prefix: &str) -> Result<Vec<String>> {
+    // substr avoids escaping % and _ in the prefix for LIKE
+    let mut stmt = conn.prepare_cached(
+        "SELECT key FROM kv_store
+         WHERE namespace = ?1 AND substr(key, 1, length(?2)) = ?2
+           AND (expires_at IS NULL OR expires_at > ?3)
+         ORDER BY key",
+    )?;
+    let keys = stmt
+        .query_map(params![namespace, prefix, Utc::now().timestamp()], |row| {
+            row.get(0)
+        })?
+        .collect::<rusqlite::Result<_>>()?;
+    Ok(keys)
+}
 
-            let result = tx.execute(
-                "INSERT OR REPLACE INTO kv_store (key, value) VALUES (?1, ?2)",
-                [&key, &value],
-            )?;
+/// Deletes the expired key-value pairs and returns how many there were
+fn kv_sweep(conn: &mut Connection) -> Result<usize> {
+    Ok(conn.execute(
+        "DELETE FROM kv_store WHERE expires_at <= ?1",
+        [Utc::now().timestamp()],
+    )?)
+}
 
-            tx.commit()?;
-            Ok::<_, Error>(result)
-        })
-        .await??;
+/// Fails if `path` exists and is not an SQLite database, so a backup only
+/// ever replaces an earlier backup
+fn check_overwrite(path: &Path) -> Result<()> {
+    if !path.exists() {
+        return Ok(());
+    }
+    let mut header = [0u8; 16];
+    let is_database = path.is_file()
+        && std::fs::File::open(path)
+            .and_then(|mut file| std::io::Read::read_exact(&mut file, &mut header))
+            .is_ok()
+        && &header == SQLITE_HEADER;
+    if !is_database {
+        return Err(invalid_arguments(format!(
+            "Refusing to overwrite {}, which is not an SQLite database",
+            path.display()
+        )));
+    }
+    Ok(())
+}
+
+/// Copies the main database of `conn` to `path` with the backup API
+fn backup(conn: &mut Connection, path: &str) -> Result<()> {
+    conn.backup(DatabaseName::Main, path, None)?;
+    info!("Backed up SQLite database to {}", path);
+    Ok(())
+}
 
-        Ok(result > 0)
+/// Overwrites the main database of `conn` with the backup at `path`. Every
+/// pooled connection sees the restored data, as they share the file.
+fn restore(conn: &mut Connection, path: &str) -> Result<()> {
+    // Opening a missing file would restore an empty database
+    if !Path::new(path).is_file() {
+        return Err(Error::new(ToolError::new(
+            ToolErrorKind::NotFound,
+            format!("Backup file not found: {}", path),
+        )));
     }
+    conn.restore(DatabaseName::Main, path, None::<fn(Progress)>)?;
+    // Backups from older versions need the newer migrations
+    let version = sqlite_migrations::migrate(conn)?;
+    info!(
+        "Restored SQLite database from {} (schema version {})",
+        path, version
+    );
+    Ok(())
+}
 
-    pub async fn get(&self, key: &str) -> Result<(String, bool)> {
-        let conn = self.conn.clone();
-        let key = key.to_string();
+fn list_tables(conn: &mut Connection) -> Result<Vec<String>> {
+    let mut stmt = conn.prepare(
+        "SELECT name FROM sqlite_master
+         WHERE type = 'table' AND name NOT LIKE 'sqlite_%'
+         ORDER BY name",
+    )?;
+    let tables = stmt
+        .query_map([], |row| row.get(0))?
+        .collect::<rusqlite::Result<Vec<String>>>()?;
+    Ok(tables)
+}
 
-        let result = tokio::task::spawn_blocking(move || {
-            let mut conn = conn.lock().unwrap();
-            let tx = conn.transaction()?;
-            let mut value = String::new();
-            let mut found = false;
+fn drop_table(conn: &mut Connection, name: &str) -> Result<()> {
+    let tx = conn.transaction()?;
+    tx.execute_batch(&format!(
+        "DROP TABLE {};
+        DROP TABLE IF EXISTS {};",
+        quote_identifier(name),
+        quote_identifier(&fts_table(name))
+    ))?;
+    tx.execute("DELETE FROM fts_indexes WHERE table_name = ?1", [name])?;
+    tx.commit()?;
+    Ok(())
+}
 
-            {
-                let mut stmt = tx.prepare("SELECT value FROM kv_store WHERE key = ?1")?;
-                let mut rows = stmt.query([&key])?;
-                if let Some(row) = rows.next()? {
-                    value = row.get(0)?;
-                    found = true;
-                }
-            }
+/// Name of the FTS5 table indexing `table`
+fn fts_table(table: &str) -> String {
+    format!("{}_fts", table)
+}
 
-            tx.commit()?;
-            Ok::<_, Error>((value, found))
-        })
-        .await??;
+fn fts_index(conn: &mut Connection, table: &str, columns: &[String]) -> Result<()> {
+    if columns.is_empty() {
+        return Err(invalid_arguments(
+            "Full-text index needs at least one column",
+        ));
+    }
 
-        Ok(result)
+    let existing: Vec<String> = conn
+        .prepare(&format!("PRAGMA table_info({})", quote_identifier(table)))?
+        .query_map([], |row| row.get(1))?
+        .collect::<rusqlite::Result<_>>()?;
+    if existing.is_empty() {
+        return Err(Error::new(ToolError::new(
+            ToolErrorKind::NotFound,
+            format!("No such table: {}", table),
+        )));
+    }
+    if let Some(missing) = columns.iter().find(|column| !existing.contains(column)) {
+        return Err(invalid_arguments(format!(
+            "Table {} has no column {}",
+            table, missing
+        )));
     }
 
-    pub async fn delete(&self, key: &str) -> Result<bool> {
-        let conn = self.conn.clone();
-        let key = key.to_string();
+    let fts = fts_table(table);
+    let quoted_table = quote_identifier(table);
+    let quoted_fts = quote_identifier(&fts);
+    let column_list = columns
+        .iter()
+        .map(|column| quote_identifier(column))
+        .collect::<Vec<_>>()
+        .join(", ");
+    let values = |prefix: &str| {
+        columns
+            .iter()
+            .map(|column| format!("{}.{}", prefix, quote_identifier(column)))
+            .collect::<Vec<_>>()
+            .join(", ")
+    };
+    let insert = format!(
+        "INSERT INTO {fts} (rowid, {cols}) VALUES (new.rowid, {new});",
+        fts = quoted_fts,
+        cols = column_list,
+        new = values("new")
+    );
+    let delete = format!(
+        "INSERT INTO {fts} ({fts}, rowid, {cols}) VALUES ('delete', old.rowid, {old});",
+        fts = quoted_fts,
+        cols = column_list,
+        old = values("old")
+    );
+    let trigger = |suffix: &str| quote_identifier(&format!("{}_{}", fts, suffix));
+
+    let tx = conn.transaction()?;
+    tx.execute_batch(&format!(
+        "DROP TRIGGER IF EXISTS {ai};
+        DROP TRIGGER IF EXISTS {ad};
+        DROP TRIGGER IF EXISTS {au};
+        DROP TABLE IF EXISTS {fts};
+        CREATE VIRTUAL TABLE {fts} USING fts5({cols}, content={content}, content_rowid='rowid');
+        INSERT INTO {fts} ({fts}) VALUES ('rebuild');
+        CREATE TRIGGER {ai} AFTER INSERT ON {table} BEGIN {insert} END;
+        CREATE TRIGGER {ad} AFTER DELETE ON {table} BEGIN {delete} END;
+        CREATE TRIGGER {au} AFTER UPDATE ON {table} BEGIN {delete} {insert} END;",
+        ai = trigger("ai"),
+        ad = trigger("ad"),
+        au = trigger("au"),
+        fts = quoted_fts,
+        cols = column_list,
+        content = quote_literal(table),
+        table = quoted_table,
+        insert = insert,
+        delete = delete,
+    ))?;
+    tx.execute(
+        "INSERT OR REPLACE INTO fts_indexes (table_name, columns) VALUES (?1, ?2)",
+        [table, serde_json::to_string(columns)?.as_str()],
+    )?;
+    tx.commit()?;
+
+    info!("Built full-text index {} over {}", fts, table);
+    Ok(())
+}
+
+fn fts_search(
+    conn: &mut Connection,
+    query: &str,
+    table: Option<&str>,
+    limit: usize,
+    options: &SqliteOptions,
+) -> Result<Vec<FtsMatch>> {
+    let mut indexes: Vec<(String, String)> = conn
+        .prepare("SELECT table_name, columns FROM fts_indexes ORDER BY table_name")?
+        .query_map([], |row| Ok((row.get(0)?, row.get(1)?)))?
+        .collect::<rusqlite::Result<_>>()?;
+    // Tables the allowlist hides are left out
+    indexes.retain(|(table, _)| options.check("SELECT", Some(table)).is_ok());
+    let indexes: Vec<(String, String)> = match table {
+        Some(table) => {
+            options.check("SELECT", Some(table))?;
+            let index: Vec<_> = indexes.into_iter().filter(|(t, _)| t == table).collect();
+            if index.is_empty() {
+                return Err(invalid_arguments(format!(
+                    "Table {} has no full-text index; create one with sqlite_fts_index",
+                    table
+                )));
+            }
+            index
+        }
+        None => indexes,
+    };
+
+    let mut matches = Vec::new();
+    for (table, columns) in indexes {
+        let columns: Vec<String> = serde_json::from_str(&columns)?;
+        let fts = quote_identifier(&fts_table(&table));
+        let column_list = columns
+            .iter()
+            .map(|column| quote_identifier(column))
+            .collect::<Vec<_>>()
+            .join(", ");
+        let mut stmt = conn.prepare(&format!(
+            "SELECT rowid, bm25({fts}), snippet({fts}, -1, '[', ']', '...', 12), {cols}
+             FROM {fts} WHERE {fts} MATCH ?1 ORDER BY bm25({fts}) LIMIT ?2",
+            fts = fts,
+            cols = column_list
+        ))?;
+        let mut rows = stmt.query(params![query, limit as i64])?;
+        while let Some(row) = rows.next()? {
+            let mut values = Map::new();
+            for (index, column) in columns.iter().enumerate() {
+                values.insert(column.clone(), to_json(row.get_ref(index + 3)?));
+            }
+            matches.push(FtsMatch {
+                table: table.clone(),
+                rowid: row.get(0)?,
+                rank: row.get(1)?,
+                snippet: row.get(2)?,
+                row: values,
+            });
+        }
+    }
+
+    matches.sort_by(|a, b| a.rank.total_cmp(&b.rank));
+    matches.truncate(limit);
+    Ok(matches)
+}
 
-        let changes = tokio::task::spawn_blocking(move || {
-            let mut conn = conn.lock().unwrap();
-            let tx = conn.transaction()?;
+/// Quotes a string as an SQL literal, for places that take no parameters
+fn quote_literal(value: &str) -> String {
+    format!("'{}'", value.replace('\'', "''"))
+}
 
-            let changes = tx.execute("DELETE FROM kv_store WHERE key = ?1", [&key])?;
+/// Quotes a table or column name so it cannot end the identifier early
+fn quote_identifier(name: &str) -> String {
+    format!("\"{}\"", name.replace('"', "\"\""))
+}
 
-            tx.commit()?;
-            Ok::<_, Error>(changes)
-        })
-        .await??;
+fn create_table_sql(name: &str, columns: &[ColumnDef]) -> Result<String> {
+    if columns.is_empty() {
+        return Err(invalid_arguments(format!(
+            "Table {} needs at least one column",
+            name
+        )));
+    }
+
+    let mut definitions = Vec::with_capacity(columns.len());
+    for column in columns {
+        let column_type = column.column_type.to_uppercase();
+        if !["INTEGER", "TEXT", "REAL", "BLOB", "NUMERIC"].contains(&column_type.as_str()) {
+            return Err(invalid_arguments(format!(
+                "Invalid type {} for column {}",
+                column.column_type, column.name
+            )));
+        }
 
-        Ok(changes > 0)
+        let mut definition = format!("{} {}", quote_identifier(&column.name), column_type);
+        if column.primary_key {
+            definition.push_str(" PRIMARY KEY");
+        }
+        if column.not_null {
+            definition.push_str(" NOT NULL");
+        }
+        if column.unique {
+            definition.push_str(" UNIQUE");
+        }
+        definitions.push(definition);
     }
+
+    Ok(format!(
+        "CREATE TABLE {} ({})",
+        quote_identifier(name),
+        definitions.join(", ")
+    ))
 }
 
 #[cfg(test)]
@@ -449,17 +2397,891 @@ mod tests {
         assert!(!result.unwrap());
     }
 
+    #[tokio::test]
+    async fn test_schema_version() {
+        let (server, temp_dir) = setup_test_db().await;
+        let latest = sqlite_migrations::latest_version();
+        assert_eq!(server.schema_version().await.unwrap(), latest);
+        drop(server);
+
+        // Reopening an up-to-date database applies nothing
+        let db_path = temp_dir.path().join("test.db");
+        let server = SqliteServer::new(db_path.to_str().unwrap()).await.unwrap();
+        assert_eq!(server.schema_version().await.unwrap(), latest);
+    }
+
     #[tokio::test]
     async fn test_cleanup() {
         let (mut server, _temp_dir) = setup_test_db().await;
         assert!(server.cleanup().await.is_ok());
     }
 
+    #[tokio::test]
+    async fn test_namespaces_and_expiry() {
+        let (server, _temp_dir) = setup_test_db().await;
+        server.set("topic", "rust").await.unwrap();
+        server
+            .set_with("session-1", "topic", "sqlite", None)
+            .await
+            .unwrap();
+        server
+            .set_with(
+                "session-1",
+                "token",
+                "secret",
+                Some(Utc::now() - chrono::Duration::seconds(1)),
+            )
+            .await
+            .unwrap();
+        assert_eq!(server.get("topic").await.unwrap().0, "rust");
+        assert_eq!(
+            server.get_in("session-1", "topic").await.unwrap().0,
+            "sqlite"
+        );
+        assert!(!server.get_in("session-1", "token").await.unwrap().1);
+
+        let result = server
+            .execute_tool(
+                "sqlite_set",
+                &json!({ "namespace": "session-1", "key": "tone", "value": "formal", "ttl_seconds": 3600 }),
+                1,
+                Duration::from_secs(1),
+            )
+            .await
+            .unwrap();
+        assert_eq!(result["success"], true);
+        let result = server
+            .execute_tool(
+                "sqlite_list_keys",
+                &json!({ "namespace": "session-1", "prefix": "to" }),
+                1,
+                Duration::from_secs(1),
+            )
+            .await
+            .unwrap();
+        assert_eq!(result["keys"], json!(["tone", "topic"]));
+
+        // The expired token is still stored until it is swept
+        assert_eq!(server.sweep_expired().await.unwrap(), 1);
+        assert_eq!(server.sweep_expired().await.unwrap(), 0);
+        assert!(server.delete_in("session-1", "topic").await.unwrap());
+        assert_eq!(
+            server.list_keys("session-1", None).await.unwrap(),
+            vec!["tone".to_string()]
+        );
+    }
+
+    #[test]
+    fn test_expiry() {
+        assert_eq!(expiry(&json!({})).unwrap(), None);
+        let expires_at = expiry(&json!({ "ttl_seconds": 60 })).unwrap().unwrap();
+        assert!(expires_at > Utc::now().timestamp());
+        assert_eq!(
+            expiry(&json!({ "expires_at": "2030-01-01T00:00:00Z" })).unwrap(),
+            Some(1_893_456_000)
+        );
+        for ttl in [
+            json!(0),
+            json!(-5),
+            json!(i64::MAX),
+            json!(u64::MAX),
+            json!("60"),
+        ] {
+            let error = expiry(&json!({ "ttl_seconds": ttl })).unwrap_err();
+            assert!(error
+                .to_string()
+                .starts_with("Invalid argument: ttl_seconds"));
+        }
+    }
+
+    #[test]
+    fn test_encryption_key() {
+        let options: SqliteOptions = serde_json::from_value(json!({
+            "command": "sqlite",
+            "args": ["notes.db"],
+            "encryption": { "key_env": "MCP_CHATBOT_TEST_DB_KEY" }
+        }))
+        .unwrap();
+        let encryption = options.encryption.unwrap();
+
+        std::env::set_var("MCP_CHATBOT_TEST_DB_KEY", "correct horse");
+        assert_eq!(encryption.key().unwrap(), "correct horse");
+        std::env::remove_var("MCP_CHATBOT_TEST_DB_KEY");
+        assert!(encryption.key().is_err());
+        assert!(EncryptionConfig::default().key().is_err());
+    }
+
+    #[cfg(not(feature = "sqlcipher"))]
+    #[tokio::test]
+    async fn test_encryption_requires_sqlcipher() {
+        let temp_dir = tempdir().unwrap();
+        let db_path = temp_dir.path().join("test.db");
+        let options = SqliteOptions {
+            encryption: Some(EncryptionConfig {
+                key_env: Some("MCP_CHATBOT_TEST_DB_KEY".to_string()),
+                ..EncryptionConfig::default()
+            }),
+            ..SqliteOptions::default()
+        };
+        // Opening must fail rather than silently write a plain file
+        assert!(
+            SqliteServer::with_options(db_path.to_str().unwrap(), options)
+                .await
+                .is_err()
+        );
+        assert!(!db_path.exists());
+    }
+
+    #[cfg(feature = "sqlcipher")]
+    #[tokio::test]
+    async fn test_encrypted_database() {
+        let temp_dir = tempdir().unwrap();
+        let db_path = temp_dir.path().join("test.db");
+        let db_path = db_path.to_str().unwrap();
+        let options = |var: &str| SqliteOptions {
+            encryption: Some(EncryptionConfig {
+                key_env: Some(var.to_string()),
+                ..EncryptionConfig::default()
+            }),
+            ..SqliteOptions::default()
+        };
+        std::env::set_var("MCP_CHATBOT_TEST_KEY_A", "first key");
+        std::env::set_var("MCP_CHATBOT_TEST_KEY_B", "second key");
+
+        let server = SqliteServer::with_options(db_path, options("MCP_CHATBOT_TEST_KEY_A"))
+            .await
+            .unwrap();
+        server.set("note", "private").await.unwrap();
+        drop(server);
+
+        assert!(SqliteServer::new(db_path).await.is_err());
+        assert!(
+            SqliteServer::with_options(db_path, options("MCP_CHATBOT_TEST_KEY_B"))
+                .await
+                .is_err()
+        );
+        let server = SqliteServer::with_options(db_path, options("MCP_CHATBOT_TEST_KEY_A"))
+            .await
+            .unwrap();
+        assert_eq!(server.get("note").await.unwrap().0, "private");
+    }
+
     #[tokio::test]
     async fn test_list_tools() {
         let (server, _temp_dir) = setup_test_db().await;
         let tools = server.list_tools().await.unwrap();
-        assert_eq!(tools.len(), 3); // sqlite_set, sqlite_get, sqlite_delete
+        assert_eq!(tools.len(), KV_TOOLS.len() + SQL_TOOLS.len());
+    }
+
+    #[tokio::test]
+    async fn test_sql_operations() {
+        let (server, _temp_dir) = setup_test_db().await;
+        let columns: Vec<ColumnDef> = serde_json::from_value(json!([
+            { "name": "id", "type": "integer", "primary_key": true },
+            { "name": "title", "type": "TEXT", "not_null": true },
+            { "name": "score", "type": "REAL" },
+            { "name": "data", "type": "BLOB" }
+        ]))
+        .unwrap();
+        server.create_table("notes", &columns).await.unwrap();
+
+        let inserted = server
+            .execute("INSERT INTO notes (title, score, data) VALUES ('a', 1.5, x'0aff'), ('b', NULL, NULL)")
+            .await
+            .unwrap();
+        assert_eq!(inserted, 2);
+
+        let rows = server
+            .query("SELECT id, title, score, data FROM notes ORDER BY id")
+            .await
+            .unwrap();
+        assert_eq!(
+            Value::Array(rows.into_iter().map(Value::Object).collect()),
+            json!([
+                { "id": 1, "title": "a", "score": 1.5, "data": "0aff" },
+                { "id": 2, "title": "b", "score": null, "data": null }
+            ])
+        );
+
+        assert_eq!(
+            server.list_tables().await.unwrap(),
+            vec!["fts_indexes", "kv_store", "notes", "schema_version"]
+        );
+        server.drop_table("notes").await.unwrap();
+        assert_eq!(
+            server.list_tables().await.unwrap(),
+            vec!["fts_indexes", "kv_store", "schema_version"]
+        );
+
+        // A failing statement surfaces the SQLite error
+        assert!(server.query("SELECT * FROM notes").await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_query_with_params() {
+        let (server, _temp_dir) = setup_test_db().await;
+        server
+            .execute("CREATE TABLE people (name TEXT, age INTEGER, active INTEGER)")
+            .await
+            .unwrap();
+
+        // Quotes in bound values need no escaping
+        let name = "O'Brien\"; DROP TABLE people; --";
+        server
+            .query_with_params(
+                "INSERT INTO people (name, age, active) VALUES (?1, ?2, ?3)",
+                vec![json!(name), json!(42), json!(true)],
+            )
+            .await
+            .unwrap();
+
+        let rows = server
+            .query_with_params(
+                "SELECT name, age, active FROM people WHERE name = ? AND age > ?",
+                vec![json!(name), json!(40.5)],
+            )
+            .await
+            .unwrap();
+        assert_eq!(rows.len(), 1);
+        assert_eq!(rows[0]["name"], json!(name));
+        assert_eq!(rows[0]["active"], json!(1));
+
+        let result = server
+            .execute_tool(
+                "sqlite_query_params",
+                &json!({
+                    "query": "SELECT count(*) AS n FROM people WHERE age = ?1",
+                    "params": [42]
+                }),
+                1,
+                Duration::from_secs(1),
+            )
+            .await
+            .unwrap();
+        assert_eq!(result["rows"][0]["n"], json!(1));
+
+        // The number of parameters must match the placeholders
+        assert!(server
+            .query_with_params("SELECT * FROM people WHERE age = ?1", Vec::new())
+            .await
+            .is_err());
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+    async fn test_parallel_reads() {
+        let (server, _temp_dir) = setup_test_db().await;
+        for i in 0..50 {
+            server
+                .set(&format!("key{}", i), &format!("value{}", i))
+                .await
+                .unwrap();
+        }
+
+        let server = std::sync::Arc::new(server);
+        let reads = (0..200).map(|i| {
+            let server = std::sync::Arc::clone(&server);
+            tokio::spawn(async move {
+                let key = format!("key{}", i % 50);
+                let (value, found) = server.get(&key).await.unwrap();
+                assert!(found);
+                assert_eq!(value, format!("value{}", i % 50));
+
+                let rows = server
+                    .query_with_params(
+                        "SELECT count(*) AS n FROM kv_store WHERE key LIKE ?1",
+                        vec![json!("key%")],
+                    )
+                    .await
+                    .unwrap();
+                assert_eq!(rows[0]["n"], json!(50));
+            })
+        });
+        for read in futures::future::join_all(reads).await {
+            read.unwrap();
+        }
+    }
+
+    #[tokio::test]
+    async fn test_full_text_search() {
+        let (server, _temp_dir) = setup_test_db().await;
+        server
+            .execute("CREATE TABLE notes (id INTEGER PRIMARY KEY, title TEXT, body TEXT)")
+            .await
+            .unwrap();
+        server
+            .execute(
+                "INSERT INTO notes (title, body) VALUES
+                 ('Rust async', 'Futures are polled by an executor such as tokio'),
+                 ('Groceries', 'Milk, eggs and bread')",
+            )
+            .await
+            .unwrap();
+
+        let columns = vec!["title".to_string(), "body".to_string()];
+        server.fts_index("notes", &columns).await.unwrap();
+
+        let matches = server.fts_search("tokio", None, 10).await.unwrap();
+        assert_eq!(matches.len(), 1);
+        assert_eq!(matches[0].table, "notes");
+        assert_eq!(matches[0].rowid, 1);
+        assert_eq!(matches[0].row["title"], json!("Rust async"));
+        assert!(matches[0].snippet.contains("[tokio]"));
+
+        // Rows written after indexing are found, deleted rows are not
+        server
+            .execute("INSERT INTO notes (title, body) VALUES ('Tokio tips', 'Use spawn_blocking')")
+            .await
+            .unwrap();
+        server
+            .execute("DELETE FROM notes WHERE id = 1")
+            .await
+            .unwrap();
+        server
+            .execute("UPDATE notes SET body = 'Milk and tokio-shaped cookies' WHERE id = 2")
+            .await
+            .unwrap();
+        let result = server
+            .execute_tool(
+                "sqlite_fts_search",
+                &json!({ "query": "tokio", "table": "notes" }),
+                1,
+                Duration::from_secs(1),
+            )
+            .await
+            .unwrap();
+        let mut rowids: Vec<i64> = result["matches"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|m| m["rowid"].as_i64().unwrap())
+            .collect();
+        rowids.sort();
+        assert_eq!(rowids, vec![2, 3]);
+
+        // Re-indexing replaces the index
+        server
+            .fts_index("notes", &["title".to_string()])
+            .await
+            .unwrap();
+        assert!(server
+            .fts_search("spawn_blocking", None, 10)
+            .await
+            .unwrap()
+            .is_empty());
+
+        assert!(server
+            .fts_index("notes", &["missing".to_string()])
+            .await
+            .is_err());
+
+        // Dropping the table removes its index
+        server.drop_table("notes").await.unwrap();
+        assert!(server
+            .fts_search("tokio", None, 10)
+            .await
+            .unwrap()
+            .is_empty());
+        assert!(server
+            .fts_search("milk", Some("kv_store"), 10)
+            .await
+            .is_err());
+    }
+
+    #[tokio::test]
+    async fn test_allowlists() {
+        let temp_dir = tempdir().unwrap();
+        let db_path = temp_dir.path().join("test.db");
+        let db_path = db_path.to_str().unwrap();
+        let server = SqliteServer::new(db_path).await.unwrap();
+        server
+            .execute("CREATE TABLE notes (id INTEGER PRIMARY KEY, body TEXT)")
+            .await
+            .unwrap();
+        server
+            .execute("CREATE TABLE secrets (value TEXT)")
+            .await
+            .unwrap();
+        drop(server);
+
+        let options = SqliteOptions {
+            read_only: false,
+            allowed_statements: Some(vec!["select".to_string(), "INSERT".to_string()]),
+            allowed_tables: Some(vec!["notes".to_string()]),
+            ..SqliteOptions::default()
+        };
+        let server = SqliteServer::with_options(db_path, options).await.unwrap();
+        let timeout = Duration::from_secs(1);
+        async fn run(server: &SqliteServer, tool: &str, query: &str) -> Result<Value> {
+            server
+                .execute_tool(tool, &json!({ "query": query }), 1, Duration::from_secs(1))
+                .await
+        }
+
+        assert!(run(
+            &server,
+            "sqlite_execute",
+            "INSERT INTO notes (body) VALUES ('hi')"
+        )
+        .await
+        .is_ok());
+        let error = run(&server, "sqlite_execute", "DELETE FROM notes")
+            .await
+            .unwrap_err();
+        assert_eq!(error.to_string(), "DELETE statements are not allowed");
+        let error = run(
+            &server,
+            "sqlite_execute",
+            "INSERT INTO secrets VALUES ('x')",
+        )
+        .await
+        .unwrap_err();
+        assert_eq!(error.to_string(), "Table secrets is not allowed");
+        assert!(run(&server, "sqlite_execute", "DROP TABLE notes")
+            .await
+            .is_err());
+
+        let result = run(&server, "sqlite_query", "SELECT body FROM notes")
+            .await
+            .unwrap();
+        assert_eq!(result["rows"][0]["body"], json!("hi"));
+        // Reads through a subquery are checked too
+        assert!(run(
+            &server,
+            "sqlite_query",
+            "SELECT (SELECT value FROM secrets) AS v FROM notes"
+        )
+        .await
+        .is_err());
+        assert!(server
+            .execute_tool("sqlite_drop_table", &json!({ "name": "notes" }), 1, timeout)
+            .await
+            .is_err());
+        // Hidden tables are not listed
+        let result = server
+            .execute_tool("sqlite_list_tables", &json!({}), 1, timeout)
+            .await
+            .unwrap();
+        assert_eq!(result["tables"], json!(["notes"]));
+        // Transactions of library callers are restricted as well
+        let error = server
+            .transaction(vec![TxStatement::Sql("DELETE FROM notes".to_string())])
+            .await
+            .unwrap_err();
+        assert!(error
+            .to_string()
+            .contains("DELETE statements are not allowed"));
+
+        // The connection is unrestricted again after a tool call
+        assert_eq!(
+            server.query("SELECT * FROM secrets").await.unwrap().len(),
+            0
+        );
+
+        // Views and triggers created outside the tools are limited to the
+        // allowed tables when the tools use them
+        server
+            .execute("CREATE VIEW secret_values AS SELECT value FROM secrets")
+            .await
+            .unwrap();
+        let error = run(&server, "sqlite_query", "SELECT * FROM secret_values")
+            .await
+            .unwrap_err();
+        assert_eq!(error.to_string(), "Table secrets is not allowed");
+        server
+            .execute(
+                "CREATE TRIGGER copy_notes AFTER INSERT ON notes \
+                 BEGIN INSERT INTO secrets VALUES (new.body); END",
+            )
+            .await
+            .unwrap();
+        let error = run(
+            &server,
+            "sqlite_execute",
+            "INSERT INTO notes (body) VALUES ('leak')",
+        )
+        .await
+        .unwrap_err();
+        assert_eq!(error.to_string(), "Table secrets is not allowed");
+        server.execute("DROP TRIGGER copy_notes").await.unwrap();
+
+        // Creating views and triggers needs CREATE to be listed
+        let error = run(
+            &server,
+            "sqlite_execute",
+            "CREATE VIEW note_bodies AS SELECT body FROM notes",
+        )
+        .await
+        .unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "Creating views needs CREATE in allowed_statements"
+        );
+        drop(server);
+
+        let options = SqliteOptions {
+            allowed_statements: Some(vec![
+                "SELECT".to_string(),
+                "INSERT".to_string(),
+                "CREATE".to_string(),
+            ]),
+            allowed_tables: Some(vec!["notes".to_string()]),
+            ..SqliteOptions::default()
+        };
+        let server = SqliteServer::with_options(db_path, options).await.unwrap();
+        let error = run(
+            &server,
+            "sqlite_execute",
+            "CREATE VIEW more_secrets AS SELECT value FROM secrets",
+        )
+        .await
+        .unwrap_err();
+        assert_eq!(error.to_string(), "Table secrets is not allowed");
+        assert!(server
+            .query("SELECT name FROM sqlite_master WHERE name = 'more_secrets'")
+            .await
+            .unwrap()
+            .is_empty());
+        assert!(run(
+            &server,
+            "sqlite_execute",
+            "CREATE TRIGGER on_secrets AFTER INSERT ON secrets BEGIN SELECT 1; END",
+        )
+        .await
+        .is_err());
+        run(
+            &server,
+            "sqlite_execute",
+            "CREATE VIEW note_bodies AS SELECT body FROM notes",
+        )
+        .await
+        .unwrap();
+        let result = run(&server, "sqlite_query", "SELECT * FROM note_bodies")
+            .await
+            .unwrap();
+        assert_eq!(result["rows"][0]["body"], json!("hi"));
+    }
+
+    #[tokio::test]
+    async fn test_read_only() {
+        let temp_dir = tempdir().unwrap();
+        let db_path = temp_dir.path().join("test.db");
+        let db_path = db_path.to_str().unwrap();
+        let server = SqliteServer::new(db_path).await.unwrap();
+        server.set("key", "value").await.unwrap();
+        drop(server);
+
+        let options = SqliteOptions {
+            read_only: true,
+            ..SqliteOptions::default()
+        };
+        let server = SqliteServer::with_options(db_path, options).await.unwrap();
+        assert_eq!(
+            server.get("key").await.unwrap(),
+            ("value".to_string(), true)
+        );
+        assert!(server.set("key", "other").await.is_err());
+        assert!(server
+            .execute_tool(
+                "sqlite_execute",
+                &json!({ "query": "DELETE FROM kv_store" }),
+                1,
+                Duration::from_secs(1),
+            )
+            .await
+            .is_err());
+    }
+
+    #[tokio::test]
+    async fn test_query_limits() {
+        let temp_dir = tempdir().unwrap();
+        let db_path = temp_dir.path().join("test.db");
+        let options = SqliteOptions {
+            max_rows: Some(2),
+            query_timeout_ms: Some(100),
+            ..SqliteOptions::default()
+        };
+        let server = SqliteServer::with_options(db_path.to_str().unwrap(), options)
+            .await
+            .unwrap();
+        for key in ["a", "b", "c"] {
+            server.set(key, "value").await.unwrap();
+        }
+
+        let result = server
+            .execute_tool(
+                "sqlite_query",
+                &json!({ "query": "SELECT * FROM kv_store" }),
+                1,
+                Duration::from_secs(1),
+            )
+            .await
+            .unwrap();
+        assert_eq!(result["rows"].as_array().unwrap().len(), 2);
+        assert_eq!(result["truncated"], true);
+
+        let result = server
+            .execute_tool(
+                "sqlite_query_params",
+                &json!({ "query": "SELECT * FROM kv_store WHERE key = ?1", "params": ["a"] }),
+                1,
+                Duration::from_secs(1),
+            )
+            .await
+            .unwrap();
+        assert_eq!(result["truncated"], false);
+
+        // Counting to a billion takes far longer than the timeout
+        // Queries inside a transaction are limited as well
+        let result = server
+            .execute_tool(
+                "sqlite_transaction",
+                &json!({ "statements": ["SELECT * FROM kv_store"] }),
+                1,
+                Duration::from_secs(1),
+            )
+            .await
+            .unwrap();
+        assert_eq!(result["results"][0]["rows"].as_array().unwrap().len(), 2);
+        assert_eq!(result["results"][0]["truncated"], true);
+
+        let error = server
+            .execute_tool(
+                "sqlite_query",
+                &json!({ "query": "WITH RECURSIVE n(i) AS (SELECT 1 UNION ALL SELECT i + 1 FROM n WHERE i < 1000000000) SELECT count(*) FROM n" }),
+                1,
+                Duration::from_secs(1),
+            )
+            .await
+            .unwrap_err();
+        assert!(error.to_string().contains("timed out"));
+    }
+
+    #[tokio::test]
+    async fn test_transaction() {
+        let (server, _temp_dir) = setup_test_db().await;
+        server
+            .execute("CREATE TABLE accounts (name TEXT PRIMARY KEY, balance INTEGER NOT NULL)")
+            .await
+            .unwrap();
+        server
+            .execute("INSERT INTO accounts VALUES ('a', 100), ('b', 0)")
+            .await
+            .unwrap();
+
+        let result = server
+            .execute_tool(
+                "sqlite_transaction",
+                &json!({ "statements": [
+                    "UPDATE accounts SET balance = balance - 30 WHERE name = 'a'",
+                    { "query": "UPDATE accounts SET balance = balance + ?1 WHERE name = ?2", "params": [30, "b"] },
+                    "SELECT balance FROM accounts WHERE name = 'b'"
+                ] }),
+                1,
+                Duration::from_secs(1),
+            )
+            .await
+            .unwrap();
+        assert_eq!(result["results"][0]["rows_affected"], 1);
+        assert_eq!(result["results"][2]["rows"][0]["balance"], 30);
+
+        // The failing insert undoes the update before it
+        let error = server
+            .execute_tool(
+                "sqlite_transaction",
+                &json!({ "statements": [
+                    "UPDATE accounts SET balance = 0 WHERE name = 'a'",
+                    "INSERT INTO accounts VALUES ('b', 1)"
+                ] }),
+                1,
+                Duration::from_secs(1),
+            )
+            .await
+            .unwrap_err();
+        assert!(error.to_string().starts_with("Statement 2 failed"));
+        let rows = server
+            .query("SELECT balance FROM accounts WHERE name = 'a'")
+            .await
+            .unwrap();
+        assert_eq!(rows[0]["balance"], 70);
+
+        // Statements cannot commit the transaction halfway
+        assert!(server
+            .transaction(vec![
+                TxStatement::Sql("DELETE FROM accounts".to_string()),
+                TxStatement::Sql("COMMIT".to_string()),
+                TxStatement::Sql("INSERT INTO missing VALUES (1)".to_string()),
+            ])
+            .await
+            .is_err());
+        assert_eq!(
+            server.query("SELECT * FROM accounts").await.unwrap().len(),
+            2
+        );
+    }
+
+    #[tokio::test]
+    async fn test_backup_restore() {
+        let temp_dir = tempdir().unwrap();
+        let db_path = temp_dir.path().join("test.db");
+        let options = SqliteOptions {
+            backup_dir: temp_dir.path().join("backups"),
+            ..SqliteOptions::default()
+        };
+        let server = SqliteServer::with_options(db_path.to_str().unwrap(), options)
+            .await
+            .unwrap();
+        let backup_path = "daily/backup.db";
+        server.set("key", "before").await.unwrap();
+
+        let result = server
+            .execute_tool(
+                "sqlite_backup",
+                &json!({ "path": backup_path }),
+                1,
+                Duration::from_secs(1),
+            )
+            .await
+            .unwrap();
+        assert!(result["size"].as_u64().unwrap() > 0);
+        assert!(temp_dir.path().join("backups/daily/backup.db").is_file());
+        // A backup replaces an earlier one
+        assert!(server
+            .execute_tool(
+                "sqlite_backup",
+                &json!({ "path": backup_path }),
+                1,
+                Duration::from_secs(1),
+            )
+            .await
+            .is_ok());
+
+        server.set("key", "after").await.unwrap();
+        server.set("other", "value").await.unwrap();
+        server
+            .execute_tool(
+                "sqlite_restore",
+                &json!({ "path": backup_path }),
+                1,
+                Duration::from_secs(1),
+            )
+            .await
+            .unwrap();
+        assert_eq!(
+            server.get("key").await.unwrap(),
+            ("before".to_string(), true)
+        );
+        assert!(!server.get("other").await.unwrap().1);
+
+        let missing = temp_dir.path().join("missing.db");
+        assert!(server.restore(missing.to_str().unwrap()).await.is_err());
+
+        // The tools stay inside the backup directory and never overwrite
+        // other files
+        std::fs::write(temp_dir.path().join("backups/notes.txt"), "keep me").unwrap();
+        for (tool, path) in [
+            ("sqlite_backup", "../outside.db"),
+            ("sqlite_backup", "/tmp/outside.db"),
+            ("sqlite_backup", "notes.txt"),
+            ("sqlite_restore", "../test.db"),
+        ] {
+            assert!(server
+                .execute_tool(tool, &json!({ "path": path }), 1, Duration::from_secs(1))
+                .await
+                .is_err());
+        }
+        assert!(!temp_dir.path().join("outside.db").exists());
+        assert_eq!(
+            std::fs::read_to_string(temp_dir.path().join("backups/notes.txt")).unwrap(),
+            "keep me"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_describe_tables() {
+        let (mut server, _temp_dir) = setup_test_db().await;
+        server
+            .execute(
+                "CREATE TABLE notes (
+                    id INTEGER PRIMARY KEY,
+                    title TEXT NOT NULL DEFAULT 'untitled',
+                    tag TEXT
+                )",
+            )
+            .await
+            .unwrap();
+        server
+            .execute("CREATE UNIQUE INDEX notes_title ON notes (title, tag)")
+            .await
+            .unwrap();
+        server
+            .execute("INSERT INTO notes (title) VALUES ('a'), ('b')")
+            .await
+            .unwrap();
+
+        let tables = server.describe_tables().await.unwrap();
+        let notes = tables.iter().find(|t| t.name == "notes").unwrap();
+        assert_eq!(notes.rows, 2);
+        assert_eq!(
+            notes.columns[1],
+            ColumnInfo {
+                name: "title".to_string(),
+                column_type: "TEXT".to_string(),
+                not_null: true,
+                default: Some("'untitled'".to_string()),
+                primary_key: false,
+            }
+        );
+        assert!(notes.columns[0].primary_key);
+        assert_eq!(
+            notes.indexes,
+            vec![IndexInfo {
+                name: "notes_title".to_string(),
+                unique: true,
+                columns: vec!["title".to_string(), "tag".to_string()],
+            }]
+        );
+
+        let result = server
+            .read_resource("sqlite_database", &json!({ "action": "tables" }))
+            .await
+            .unwrap();
+        assert!(result["tables"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .any(|t| t["name"] == "kv_store" && t["columns"][1]["name"] == "key"));
+
+        let size = server
+            .read_resource("sqlite_database", &json!({ "action": "size" }))
+            .await
+            .unwrap();
+        assert!(size["size"].as_u64().unwrap() > 0);
+        assert_eq!(
+            size["size"].as_u64().unwrap(),
+            server.file_size().await.unwrap()
+        );
+    }
+
+    #[test]
+    fn test_create_table_sql() {
+        let columns = vec![ColumnDef {
+            name: "na\"me".to_string(),
+            column_type: "text".to_string(),
+            primary_key: false,
+            not_null: true,
+            unique: true,
+        }];
+        assert_eq!(
+            create_table_sql("t", &columns).unwrap(),
+            "CREATE TABLE \"t\" (\"na\"\"me\" TEXT NOT NULL UNIQUE)"
+        );
+
+        let invalid = vec![ColumnDef {
+            column_type: "TEXT); DROP TABLE kv_store; --".to_string(),
+            ..columns[0].clone()
+        }];
+        assert!(create_table_sql("t", &invalid).is_err());
+        assert!(create_table_sql("t", &[]).is_err());
     }
 
     #[tokio::test]
@@ -7,9 +7,24 @@
 //! - Output formatting to stdout
 //! - Message history management
 //! - Integration with MCP server and LLM client
+//! - `StdioClient`: Client of external MCP servers spawned as child processes,
+//!   which can be pinged and restarted in place
+//! - `McpService`: The built-in servers offered to other MCP clients over
+//!   stdio, started by `mcp-chatbot serve --stdio`
+//! - Progress of tool calls in both directions: `notifications/progress`
+//!   for calls that carry a `progressToken`
+//! - `notifications/tools/list_changed` to clients when a served server's
+//!   tools change
+//! - Resources addressed by URI templates, listed by
+//!   `resources/templates/list` rather than `resources/list`
+//! - Failed tool calls carry their `ToolError` as structured content in
+//!   both directions; unanswered requests fail as `timeout` and requests to
+//!   exited servers as `unavailable`
 //!
 //! Key Components:
 //! - `StdioServer`: Main server struct managing I/O operations
+//! - `StdioClient`: Newline-delimited JSON-RPC over a child's stdin/stdout
+//! - `McpService`: Newline-delimited JSON-RPC over our own stdin/stdout
 //! - Input/output stream handling
 //! - Message buffering and history
 //! - Server state management
@@ -18,16 +33,41 @@
 //! Version: 1.0.0
 //! License: MIT
 
+use std::collections::HashMap;
 use std::io;
-use std::sync::Arc;
+use std::process::Stdio;
+use std::sync::atomic::{AtomicBool, AtomicI64, Ordering};
+use std::sync::{Arc, Mutex as StdMutex};
+use std::time::Duration;
 
 use anyhow::{Error, Result};
-use log::info;
-use serde_json::Value;
-use tokio::sync::Mutex;
+use log::{debug, error, info, warn};
+use serde::Serialize;
+use serde_json::{json, Value};
+use tokio::io::{AsyncBufReadExt, AsyncRead, AsyncWrite, AsyncWriteExt, BufReader};
+use tokio::process::{Child, ChildStderr, ChildStdin, ChildStdout, Command};
+use tokio::sync::{mpsc, oneshot, watch, Mutex};
+use tokio::task::JoinHandle;
 
+use crate::audit_log::AuditLog;
+use crate::config::Configuration;
 use crate::llm_client::LLMClient;
-use crate::mcp_server::McpServer;
+use crate::mcp_server::{McpServer, ProgressEvent, ProgressSink, Prompt, Resource, Session, Tool};
+use crate::metrics::Metrics;
+use crate::protocol::{
+    negotiate_protocol_version, CallToolParams, CallToolResult, Capabilities, GetPromptParams,
+    GetPromptResult, Implementation, InitializeParams, InitializeResult, JsonRpcError,
+    JsonRpcMessage, JsonRpcNotification, JsonRpcResponse, ListPromptsResult,
+    ListResourceTemplatesResult, ListResourcesResult, ListToolsResult, McpPrompt, McpRequest,
+    McpResource, McpResourceTemplate, McpTool, ProgressParams, PromptSchema, ReadResourceParams,
+    ReadResourceResult, RequestId, ResourceSchema, ServerCapabilities, ToolSchema,
+    INITIALIZED_NOTIFICATION, MCP_PROTOCOL_VERSION, PROGRESS_NOTIFICATION,
+    SUPPORTED_PROTOCOL_VERSIONS, TOOLS_LIST_CHANGED_NOTIFICATION,
+};
+use crate::rag_server::{RagConfig, RagServer};
+use crate::server::{find_tool_server, warn_tool_collisions, Server};
+use crate::tool_error::{ToolError, ToolErrorKind};
+use crate::uri_template::UriTemplate;
 use crate::utils::{print_menu, Color};
 
 /// Standard Input/Output server for MCP chatbot.
@@ -111,3 +151,1202 @@ impl StdioServer {
         Ok(())
     }
 }
+
+/// How long a spawned MCP server may take to answer a request, unless the
+/// server's configuration sets `timeout` in seconds
+pub const DEFAULT_REQUEST_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// Responses awaited by `StdioClient::request`, by request id
+type Pending = Arc<StdMutex<HashMap<RequestId, oneshot::Sender<JsonRpcResponse>>>>;
+
+/// Sinks of the tool calls waiting for progress notifications, by the
+/// JSON text of their progress token
+type ProgressListeners = Arc<StdMutex<HashMap<String, ProgressSink>>>;
+
+/// Client of an external MCP server that runs as a child process and speaks
+/// newline-delimited JSON-RPC on its stdin and stdout.
+///
+/// `spawn` starts the process and performs the `initialize` handshake;
+/// `register_with` then exposes the server's tools and resources through an
+/// `McpServer`, so they are listed and executed like built-in ones. The
+/// process is killed by `shutdown` or when the client is dropped, and
+/// `restart` replaces a crashed or hung process with a new one.
+pub struct StdioClient {
+    /// Name of the server in `servers_config.json`, used in logs and errors
+    name: String,
+    /// Command, arguments and environment the process is started with
+    command: String,
+    args: Vec<String>,
+    env: HashMap<String, String>,
+    /// The server process
+    child: Mutex<Child>,
+    /// Requests and notifications go here, one JSON object per line
+    stdin: Arc<Mutex<ChildStdin>>,
+    /// Senders of the requests still waiting for a response
+    pending: Pending,
+    /// Tool calls whose progress is reported
+    progress: ProgressListeners,
+    /// Marked changed when the server says its tools have changed
+    tools_changed: Arc<watch::Sender<()>>,
+    /// Id of the next request
+    next_id: AtomicI64,
+    /// How long a request may take
+    timeout: Duration,
+    /// What the server reported during `initialize`
+    info: InitializeResult,
+    /// Tasks reading stdout and stderr of the current process
+    readers: StdMutex<Vec<JoinHandle<()>>>,
+}
+
+impl StdioClient {
+    /// Spawns `command` with `args` and `env`, and initializes the MCP
+    /// session. Fails if the process cannot be started or does not answer
+    /// `initialize` within `timeout`.
+    pub async fn spawn(
+        name: &str,
+        command: &str,
+        args: &[String],
+        env: &HashMap<String, String>,
+        timeout: Duration,
+    ) -> Result<Arc<Self>> {
+        let (child, stdin, stdout, stderr) = start_process(name, command, args, env)?;
+        let mut client = Self {
+            name: name.to_string(),
+            command: command.to_string(),
+            args: args.to_vec(),
+            env: env.clone(),
+            child: Mutex::new(child),
+            stdin: Arc::new(Mutex::new(stdin)),
+            pending: Arc::new(StdMutex::new(HashMap::new())),
+            progress: Arc::new(StdMutex::new(HashMap::new())),
+            tools_changed: Arc::new(watch::channel(()).0),
+            next_id: AtomicI64::new(1),
+            timeout,
+            info: InitializeResult {
+                protocol_version: MCP_PROTOCOL_VERSION.to_string(),
+                capabilities: ServerCapabilities::default(),
+                server_info: Implementation {
+                    name: name.to_string(),
+                    version: String::new(),
+                },
+                instructions: None,
+            },
+            readers: StdMutex::new(Vec::new()),
+        };
+        client.start_readers(stdout, stderr);
+        client.info = client.handshake().await?;
+        Ok(Arc::new(client))
+    }
+
+    /// Reads the output of the current process
+    fn start_readers(&self, stdout: ChildStdout, stderr: ChildStderr) {
+        *self.readers.lock().unwrap() = vec![
+            tokio::spawn(read_messages(
+                self.name.clone(),
+                stdout,
+                Arc::clone(&self.stdin),
+                Arc::clone(&self.pending),
+                Arc::clone(&self.progress),
+                Arc::clone(&self.tools_changed),
+            )),
+            tokio::spawn(log_stderr(self.name.clone(), stderr)),
+        ];
+    }
+
+    /// Performs the `initialize` handshake, giving what the server reported
+    async fn handshake(&self) -> Result<InitializeResult> {
+        let result = self
+            .request(McpRequest::Initialize(InitializeParams {
+                protocol_version: MCP_PROTOCOL_VERSION.to_string(),
+                capabilities: json!({}),
+                client_info: Implementation {
+                    name: env!("CARGO_PKG_NAME").to_string(),
+                    version: env!("CARGO_PKG_VERSION").to_string(),
+                },
+            }))
+            .await?;
+        let info: InitializeResult = serde_json::from_value(result).map_err(|e| {
+            Error::msg(format!(
+                "Invalid initialize result from {}: {}",
+                self.name, e
+            ))
+        })?;
+        if !SUPPORTED_PROTOCOL_VERSIONS.contains(&info.protocol_version.as_str()) {
+            return Err(Error::msg(format!(
+                "MCP server {} speaks protocol {}, which is not supported (supported: {})",
+                self.name,
+                info.protocol_version,
+                SUPPORTED_PROTOCOL_VERSIONS.join(", ")
+            )));
+        }
+        self.notify(INITIALIZED_NOTIFICATION, None).await?;
+        info!(
+            "Connected to MCP server {} ({} {})",
+            self.name, info.server_info.name, info.server_info.version
+        );
+        Ok(info)
+    }
+
+    /// Whether the server process is still running
+    pub async fn is_running(&self) -> bool {
+        matches!(self.child.lock().await.try_wait(), Ok(None))
+    }
+
+    /// Fails unless the server answers a `ping` within `timeout`
+    pub async fn ping(&self, timeout: Duration) -> Result<()> {
+        self.request_within(McpRequest::Ping, timeout).await?;
+        Ok(())
+    }
+
+    /// Stops the server process and starts it again with the same command,
+    /// performing the handshake anew. Requests still waiting fail. The
+    /// proxies of `register_with` call through this client, so they keep
+    /// working.
+    pub async fn restart(&self) -> Result<()> {
+        info!("Restarting MCP server {}", self.name);
+        for reader in self.readers.lock().unwrap().drain(..) {
+            reader.abort();
+        }
+        {
+            let mut child = self.child.lock().await;
+            if child.try_wait()?.is_none() {
+                child.kill().await.ok();
+            }
+        }
+        // Dropping the senders fails the requests to the old process
+        self.pending.lock().unwrap().clear();
+
+        let (child, stdin, stdout, stderr) =
+            start_process(&self.name, &self.command, &self.args, &self.env)?;
+        *self.child.lock().await = child;
+        *self.stdin.lock().await = stdin;
+        self.start_readers(stdout, stderr);
+        let info = self.handshake().await?;
+        if Capabilities::from(&info.capabilities) != self.capabilities() {
+            warn!(
+                "MCP server {} announced other capabilities after restarting; \
+                 the ones of its first start are kept",
+                self.name
+            );
+        }
+        Ok(())
+    }
+
+    /// What the server reported about itself during `initialize`
+    pub fn info(&self) -> &InitializeResult {
+        &self.info
+    }
+
+    /// Method groups negotiated during `initialize`
+    pub fn capabilities(&self) -> Capabilities {
+        Capabilities::from(&self.info.capabilities)
+    }
+
+    /// Sends `request` and waits for its result. Requests of groups the
+    /// server did not announce fail without being sent.
+    pub async fn request(&self, request: McpRequest) -> Result<Value> {
+        self.request_within(request, self.timeout).await
+    }
+
+    /// Sends `request` like `request`, waiting at most `timeout`
+    async fn request_within(&self, request: McpRequest, timeout: Duration) -> Result<Value> {
+        let method = request.method();
+        if !self.capabilities().allows(method) {
+            return Err(Error::msg(format!(
+                "MCP server {} does not support {}",
+                self.name, method
+            )));
+        }
+        let id = RequestId::Number(self.next_id.fetch_add(1, Ordering::Relaxed));
+        let (sender, receiver) = oneshot::channel();
+        self.pending.lock().unwrap().insert(id.clone(), sender);
+
+        let message = JsonRpcMessage::Request(request.into_request(id.clone()));
+        if let Err(e) = write_message(&self.stdin, &message).await {
+            self.pending.lock().unwrap().remove(&id);
+            return Err(ToolError::new(
+                ToolErrorKind::Unavailable,
+                format!(
+                    "Failed to send {} to MCP server {}: {}",
+                    method, self.name, e
+                ),
+            )
+            .into());
+        }
+
+        let response = match tokio::time::timeout(timeout, receiver).await {
+            Ok(Ok(response)) => response,
+            Ok(Err(_)) => {
+                return Err(ToolError::new(
+                    ToolErrorKind::Unavailable,
+                    format!(
+                        "MCP server {} exited before answering {}",
+                        self.name, method
+                    ),
+                )
+                .into())
+            }
+            Err(_) => {
+                self.pending.lock().unwrap().remove(&id);
+                return Err(ToolError::new(
+                    ToolErrorKind::Timeout,
+                    format!(
+                        "MCP server {} did not answer {} within {} seconds",
+                        self.name,
+                        method,
+                        timeout.as_secs()
+                    ),
+                )
+                .into());
+            }
+        };
+        // The JSON-RPC error is kept, so that errors about the request
+        // itself are not retried
+        response.into_result().map_err(|e| {
+            let message = format!("MCP server {} failed {}: {}", self.name, method, e);
+            Error::new(e).context(message)
+        })
+    }
+
+    /// Sends a notification, which gets no response
+    pub async fn notify(&self, method: &str, params: Option<Value>) -> Result<()> {
+        let message = JsonRpcMessage::Notification(JsonRpcNotification::new(method, params));
+        write_message(&self.stdin, &message).await
+    }
+
+    /// Tools of the server, or none if it offers no tools
+    pub async fn list_tools(&self) -> Result<Vec<ToolSchema>> {
+        if !self.capabilities().tools {
+            return Ok(Vec::new());
+        }
+        let result: ListToolsResult =
+            serde_json::from_value(self.request(McpRequest::ListTools).await?)?;
+        Ok(result.tools.into_iter().map(ToolSchema::from).collect())
+    }
+
+    /// Calls the tool `name`. A result flagged `isError` becomes an error.
+    /// The server's progress notifications about the call go to `progress`.
+    pub async fn call_tool(
+        &self,
+        name: &str,
+        arguments: &Value,
+        progress: &ProgressSink,
+    ) -> Result<Value> {
+        let token = match progress.is_listening() {
+            true => {
+                let token = json!(format!(
+                    "{}-{}",
+                    name,
+                    self.next_id.fetch_add(1, Ordering::Relaxed)
+                ));
+                self.progress
+                    .lock()
+                    .unwrap()
+                    .insert(token.to_string(), progress.clone());
+                Some(token)
+            }
+            false => None,
+        };
+        let result = self
+            .request(McpRequest::CallTool(CallToolParams {
+                name: name.to_string(),
+                arguments: Some(arguments.clone()),
+                meta: token
+                    .as_ref()
+                    .map(|token| json!({ "progressToken": token })),
+            }))
+            .await;
+        if let Some(token) = token {
+            self.progress.lock().unwrap().remove(&token.to_string());
+        }
+        let result: CallToolResult = serde_json::from_value(result?)?;
+        // Servers that report errors as a `ToolError`, such as `serve
+        // --stdio`, keep their kind and retryability
+        if result.is_error {
+            let error = result
+                .structured_content
+                .clone()
+                .and_then(|content| serde_json::from_value::<ToolError>(content).ok());
+            if let Some(error) = error {
+                return Err(error.into());
+            }
+        }
+        result.into_value().map_err(Error::msg)
+    }
+
+    /// Resources of the server, or none if it offers no resources
+    pub async fn list_resources(&self) -> Result<Vec<ResourceSchema>> {
+        if !self.capabilities().resources {
+            return Ok(Vec::new());
+        }
+        let result: ListResourcesResult =
+            serde_json::from_value(self.request(McpRequest::ListResources).await?)?;
+        Ok(result
+            .resources
+            .into_iter()
+            .map(ResourceSchema::from)
+            .collect())
+    }
+
+    /// Resource templates of the server, or none if it offers no resources.
+    /// Servers that do not know the method are taken to have none.
+    pub async fn list_resource_templates(&self) -> Result<Vec<ResourceSchema>> {
+        if !self.capabilities().resources {
+            return Ok(Vec::new());
+        }
+        let result = match self.request(McpRequest::ListResourceTemplates).await {
+            Ok(result) => result,
+            Err(e) => {
+                debug!(
+                    "MCP server {} lists no resource templates: {}",
+                    self.name, e
+                );
+                return Ok(Vec::new());
+            }
+        };
+        let result: ListResourceTemplatesResult = serde_json::from_value(result)?;
+        Ok(result
+            .resource_templates
+            .into_iter()
+            .map(ResourceSchema::from)
+            .collect())
+    }
+
+    /// Reads the resource at `uri`
+    pub async fn read_resource(&self, uri: &str) -> Result<Value> {
+        let result = self
+            .request(McpRequest::ReadResource(ReadResourceParams {
+                uri: uri.to_string(),
+            }))
+            .await?;
+        let result: ReadResourceResult = serde_json::from_value(result)?;
+        Ok(result.into_value())
+    }
+
+    /// Prompts of the server, or none if it offers no prompts
+    pub async fn list_prompts(&self) -> Result<Vec<PromptSchema>> {
+        if !self.capabilities().prompts {
+            return Ok(Vec::new());
+        }
+        let result: ListPromptsResult =
+            serde_json::from_value(self.request(McpRequest::ListPrompts).await?)?;
+        Ok(result.prompts.into_iter().map(PromptSchema::from).collect())
+    }
+
+    /// Renders the prompt `name` with `arguments`, giving the server's
+    /// `GetPromptResult` as JSON
+    pub async fn get_prompt(&self, name: &str, arguments: Option<Value>) -> Result<Value> {
+        let result = self
+            .request(McpRequest::GetPrompt(GetPromptParams {
+                name: name.to_string(),
+                arguments,
+            }))
+            .await?;
+        let result: GetPromptResult = serde_json::from_value(result)?;
+        Ok(serde_json::to_value(result)?)
+    }
+
+    /// Registers a proxy for each of the server's tools, resources and
+    /// prompts with `mcp_server`. If the server announced `listChanged`, its tools are
+    /// registered again whenever it reports that they changed.
+    pub async fn register_with(self: &Arc<Self>, mcp_server: &mut McpServer) -> Result<()> {
+        let tools = self.list_tools().await?;
+        info!("MCP server {} offers {} tools", self.name, tools.len());
+        for schema in tools {
+            mcp_server.register_tool(self.proxy_tool(schema)).await?;
+        }
+        if self.capabilities().notifications {
+            self.follow_tool_changes(mcp_server.clone());
+        }
+
+        for schema in self.list_resources().await? {
+            let client = Arc::clone(self);
+            let uri = schema.pattern.clone();
+            mcp_server
+                .register_resource(Resource::new(
+                    schema.pattern,
+                    schema.description,
+                    None,
+                    None,
+                    Box::new(move |_args| {
+                        let client = Arc::clone(&client);
+                        let uri = uri.clone();
+                        Box::pin(async move {
+                            tokio::spawn(async move { client.read_resource(&uri).await }).await?
+                        })
+                    }),
+                ))
+                .await?;
+        }
+
+        // Templated resources are read at the URI their arguments expand to
+        for schema in self.list_resource_templates().await? {
+            let client = Arc::clone(self);
+            let template = match UriTemplate::parse(&schema.pattern) {
+                Ok(template) => template,
+                Err(e) => {
+                    warn!("Skipping a resource of {}: {}", self.name, e);
+                    continue;
+                }
+            };
+            mcp_server
+                .register_resource(Resource::new(
+                    schema.pattern,
+                    schema.description,
+                    None,
+                    None,
+                    Box::new(move |args| {
+                        let client = Arc::clone(&client);
+                        let uri = template.expand(&args);
+                        Box::pin(async move {
+                            let uri = uri?;
+                            tokio::spawn(async move { client.read_resource(&uri).await }).await?
+                        })
+                    }),
+                ))
+                .await?;
+        }
+
+        for schema in self.list_prompts().await? {
+            let client = Arc::clone(self);
+            let name = schema.name.clone();
+            mcp_server
+                .register_prompt(Prompt::with_async(
+                    schema.name,
+                    schema.description,
+                    schema.arguments,
+                    Box::new(move |args| {
+                        let client = Arc::clone(&client);
+                        let name = name.clone();
+                        Box::pin(async move {
+                            tokio::spawn(async move { client.get_prompt(&name, args).await })
+                                .await?
+                        })
+                    }),
+                ))
+                .await?;
+        }
+        Ok(())
+    }
+
+    /// A tool that runs the server's tool `schema` describes
+    fn proxy_tool(self: &Arc<Self>, schema: ToolSchema) -> Tool {
+        let client = Arc::clone(self);
+        let name = schema.name.clone();
+        Tool::with_progress(
+            schema.name,
+            schema.description,
+            schema.input_schema,
+            schema.output_schema,
+            Box::new(move |args, progress| {
+                let client = Arc::clone(&client);
+                let name = name.clone();
+                // Tool futures must be Sync, so the call runs on its
+                // own task and only its handle is awaited here
+                Box::pin(async move {
+                    tokio::spawn(async move { client.call_tool(&name, &args, &progress).await })
+                        .await?
+                })
+            }),
+        )
+    }
+
+    /// Keeps the tools of `mcp_server`, which shares its registry with the
+    /// server they were registered with, in step with the server's list
+    /// until the server exits
+    fn follow_tool_changes(self: &Arc<Self>, mut mcp_server: McpServer) {
+        let client = Arc::downgrade(self);
+        let mut changes = self.tools_changed.subscribe();
+        tokio::spawn(async move {
+            while changes.changed().await.is_ok() {
+                let client = match client.upgrade() {
+                    Some(client) => client,
+                    None => break,
+                };
+                if let Err(e) = client.refresh_tools(&mut mcp_server).await {
+                    warn!("Failed to refresh the tools of {}: {}", client.name, e);
+                }
+            }
+        });
+    }
+
+    /// Lists the server's tools again, replacing the proxies in
+    /// `mcp_server` and removing those of tools it no longer offers
+    pub(crate) async fn refresh_tools(self: &Arc<Self>, mcp_server: &mut McpServer) -> Result<()> {
+        let tools = self.list_tools().await?;
+        for old in mcp_server.list_tools().await? {
+            if !tools.iter().any(|tool| tool.name == old.name) {
+                mcp_server.unregister_tool(&old.name).await?;
+            }
+        }
+        info!("MCP server {} now offers {} tools", self.name, tools.len());
+        for schema in tools {
+            mcp_server.replace_tool(self.proxy_tool(schema)).await?;
+        }
+        Ok(())
+    }
+
+    /// Closes the server's stdin and kills the process
+    pub async fn shutdown(&self) -> Result<()> {
+        info!("Stopping MCP server {}", self.name);
+        self.stdin.lock().await.shutdown().await.ok();
+        let mut child = self.child.lock().await;
+        if child.try_wait()?.is_none() {
+            child.kill().await?;
+        }
+        for reader in self.readers.lock().unwrap().iter() {
+            reader.abort();
+        }
+        Ok(())
+    }
+}
+
+/// Writes `message` as one line to the server's stdin
+async fn write_message(stdin: &Mutex<ChildStdin>, message: &JsonRpcMessage) -> Result<()> {
+    let mut line = message.to_line();
+    debug!("MCP -> {}", line);
+    line.push('\n');
+    let mut stdin = stdin.lock().await;
+    stdin.write_all(line.as_bytes()).await?;
+    stdin.flush().await?;
+    Ok(())
+}
+
+/// Dispatches the server's messages until it closes stdout: responses go to
+/// the waiting requests, progress notifications to the sinks of their tool
+/// calls, changed tool lists to `tools_changed`, `ping` is answered and
+/// other requests are refused. Requests still waiting when the server exits
+/// fail.
+async fn read_messages(
+    name: String,
+    stdout: ChildStdout,
+    stdin: Arc<Mutex<ChildStdin>>,
+    pending: Pending,
+    progress: ProgressListeners,
+    tools_changed: Arc<watch::Sender<()>>,
+) {
+    let mut lines = BufReader::new(stdout).lines();
+    loop {
+        let line = match lines.next_line().await {
+            Ok(Some(line)) => line,
+            Ok(None) => break,
+            Err(e) => {
+                error!("Failed to read from MCP server {}: {}", name, e);
+                break;
+            }
+        };
+        if line.trim().is_empty() {
+            continue;
+        }
+        debug!("MCP <- {}", line);
+        match JsonRpcMessage::parse(&line) {
+            Ok(JsonRpcMessage::Response(response)) => {
+                let sender = response
+                    .id
+                    .as_ref()
+                    .and_then(|id| pending.lock().unwrap().remove(id));
+                match sender {
+                    Some(sender) => {
+                        let _ = sender.send(response);
+                    }
+                    None => warn!("Unexpected response from MCP server {}: {}", name, line),
+                }
+            }
+            Ok(JsonRpcMessage::Request(request)) => {
+                let response = match request.method.as_str() {
+                    "ping" => JsonRpcResponse::success(request.id, json!({})),
+                    method => JsonRpcResponse::failure(
+                        Some(request.id),
+                        JsonRpcError::new(
+                            JsonRpcError::METHOD_NOT_FOUND,
+                            format!("Method not supported by this client: {}", method),
+                        ),
+                    ),
+                };
+                if let Err(e) = write_message(&stdin, &JsonRpcMessage::Response(response)).await {
+                    warn!("Failed to answer MCP server {}: {}", name, e);
+                }
+            }
+            Ok(JsonRpcMessage::Notification(notification))
+                if notification.method == PROGRESS_NOTIFICATION =>
+            {
+                let params = notification
+                    .params
+                    .and_then(|params| serde_json::from_value::<ProgressParams>(params).ok());
+                let params = match params {
+                    Some(params) => params,
+                    None => {
+                        warn!("Invalid progress notification from MCP server {}", name);
+                        continue;
+                    }
+                };
+                // Late notifications of finished calls have no sink left
+                let sink = progress
+                    .lock()
+                    .unwrap()
+                    .get(&params.progress_token.to_string())
+                    .cloned();
+                if let Some(sink) = sink {
+                    sink.send(ProgressEvent {
+                        progress: params.progress,
+                        total: params.total,
+                        message: params.message,
+                    });
+                }
+            }
+            Ok(JsonRpcMessage::Notification(notification))
+                if notification.method == TOOLS_LIST_CHANGED_NOTIFICATION =>
+            {
+                info!("MCP server {} changed its tools", name);
+                tools_changed.send_replace(());
+            }
+            Ok(JsonRpcMessage::Notification(notification)) => {
+                debug!(
+                    "Notification from MCP server {}: {}",
+                    name, notification.method
+                )
+            }
+            Err(e) => warn!("Invalid message from MCP server {}: {}", name, e),
+        }
+    }
+    info!("MCP server {} closed its output", name);
+    // Dropping the senders fails the requests still waiting
+    pending.lock().unwrap().clear();
+}
+
+/// Starts `command` with its pipes
+fn start_process(
+    name: &str,
+    command: &str,
+    args: &[String],
+    env: &HashMap<String, String>,
+) -> Result<(Child, ChildStdin, ChildStdout, ChildStderr)> {
+    info!(
+        "Starting MCP server {}: {} {}",
+        name,
+        command,
+        args.join(" ")
+    );
+    let mut child = Command::new(command)
+        .args(args)
+        .envs(env)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .kill_on_drop(true)
+        .spawn()
+        .map_err(|e| {
+            Error::msg(format!(
+                "Failed to start MCP server {} ({}): {}",
+                name, command, e
+            ))
+        })?;
+    let stdin = child
+        .stdin
+        .take()
+        .ok_or_else(|| Error::msg(format!("No stdin for MCP server {}", name)))?;
+    let stdout = child
+        .stdout
+        .take()
+        .ok_or_else(|| Error::msg(format!("No stdout for MCP server {}", name)))?;
+    let stderr = child
+        .stderr
+        .take()
+        .ok_or_else(|| Error::msg(format!("No stderr for MCP server {}", name)))?;
+    Ok((child, stdin, stdout, stderr))
+}
+
+/// Logs what the server writes to stderr
+async fn log_stderr(name: String, stderr: ChildStderr) {
+    let mut lines = BufReader::new(stderr).lines();
+    while let Ok(Some(line)) = lines.next_line().await {
+        debug!("MCP server {} stderr: {}", name, line);
+    }
+}
+
+/// Built-in servers of `servers_config.json` whose tools `serve --stdio`
+/// exposes, next to the RAG tools
+pub const SERVED_COMMANDS: [&str; 2] = ["file", "sqlite"];
+
+/// MCP server speaking newline-delimited JSON-RPC on behalf of the built-in
+/// servers, so that other MCP clients can use their tools and resources.
+///
+/// Requests are answered one at a time, in the order they arrive. Only
+/// protocol messages are written to the output; logs go to stderr. Tool
+/// failures are reported as results flagged `isError`, unknown tools and
+/// resources as JSON-RPC errors. Until the client has sent `initialize`,
+/// only `ping` is answered; afterwards, methods of groups none of the
+/// servers offers are refused. Tool calls whose `_meta` carries a
+/// `progressToken` get `notifications/progress` while they run, and
+/// clients that negotiated notifications hear of changed tool lists.
+pub struct McpService {
+    /// Servers whose tools and resources are offered, searched in order
+    servers: Vec<Server>,
+    /// The negotiated session, once the client has initialized
+    session: Option<Session>,
+    /// Where notifications for the client go while `serve` runs
+    notifications: Option<mpsc::UnboundedSender<JsonRpcNotification>>,
+    /// Whether the session agreed on `notifications/tools/list_changed`
+    announce_tool_changes: Arc<AtomicBool>,
+}
+
+impl McpService {
+    /// Creates a service offering the tools and resources of `servers`
+    pub fn new(servers: Vec<Server>) -> Self {
+        Self {
+            servers,
+            session: None,
+            notifications: None,
+            announce_tool_changes: Arc::new(AtomicBool::new(false)),
+        }
+    }
+
+    /// The method groups of all servers
+    pub fn capabilities(&self) -> Capabilities {
+        self.servers
+            .iter()
+            .fold(Capabilities::default(), |capabilities, server| {
+                capabilities.union(&server.mcp_server.capabilities())
+            })
+    }
+
+    /// Creates the servers of `servers_config` listed in `SERVED_COMMANDS`
+    /// and the `rag` server with the RAG settings of `config`. If the RAG
+    /// index cannot be opened, the other tools are served without it.
+    pub async fn from_config(servers_config: &str, config: &str) -> Result<Self> {
+        let configuration = Configuration::new();
+        let server_config = configuration.load_config(servers_config)?;
+
+        let mut servers = Vec::new();
+        if let Some(servers_config) = server_config["mcpServers"].as_object() {
+            for (name, config) in servers_config {
+                match config["command"].as_str() {
+                    Some(command) if SERVED_COMMANDS.contains(&command) => {
+                        servers.push(Server::new(name.clone(), config.clone()).await?);
+                    }
+                    _ => debug!("Not serving server {}", name),
+                }
+            }
+        }
+
+        let rag_config = configuration.load_rag_config(config).unwrap_or_else(|e| {
+            warn!("Failed to load RAG configuration, using defaults: {}", e);
+            RagConfig::default()
+        });
+        match open_rag_server(rag_config).await {
+            Ok(rag_server) => {
+                for server in &mut servers {
+                    server.attach_rag_server(Arc::clone(&rag_server)).await?;
+                }
+                servers.push(Server::rag(rag_server).await?);
+            }
+            Err(e) => warn!("Serving without the RAG tools: {}", e),
+        }
+        warn_tool_collisions(&servers).await?;
+        if let Some(audit_log) = AuditLog::from_config_file(config) {
+            for server in &mut servers {
+                audit_log.attach(&mut server.mcp_server).await;
+            }
+        }
+        let metrics = Metrics::from_config_file(config).await;
+        for server in &mut servers {
+            metrics.attach(&mut server.mcp_server).await;
+        }
+        Ok(Self::new(servers))
+    }
+
+    /// Answers the requests read from `input` on `output` until `input`
+    /// is closed
+    pub async fn serve<R, W>(&mut self, input: R, mut output: W) -> Result<()>
+    where
+        R: AsyncRead + Unpin,
+        W: AsyncWrite + Unpin,
+    {
+        info!("Serving MCP over stdio");
+        let (notifications, mut outgoing) = mpsc::unbounded_channel();
+        let watchers = self.watch_tools(&notifications);
+        self.notifications = Some(notifications);
+        let mut lines = BufReader::new(input).lines();
+        loop {
+            // Notifications are also written between requests
+            let line = tokio::select! {
+                line = lines.next_line() => match line? {
+                    Some(line) => line,
+                    None => break,
+                },
+                Some(notification) = outgoing.recv() => {
+                    write_output(&mut output, JsonRpcMessage::Notification(notification)).await?;
+                    continue;
+                }
+            };
+            // Notifications are written while the request is handled
+            let response = {
+                let handled = self.handle_line(&line);
+                tokio::pin!(handled);
+                loop {
+                    tokio::select! {
+                        response = &mut handled => break response,
+                        Some(notification) = outgoing.recv() => {
+                            write_output(&mut output, JsonRpcMessage::Notification(notification))
+                                .await?;
+                        }
+                    }
+                }
+            };
+            while let Ok(notification) = outgoing.try_recv() {
+                write_output(&mut output, JsonRpcMessage::Notification(notification)).await?;
+            }
+            if let Some(response) = response {
+                write_output(&mut output, JsonRpcMessage::Response(response)).await?;
+            }
+        }
+        for watcher in watchers {
+            watcher.abort();
+        }
+        self.notifications = None;
+        info!("MCP client closed the connection");
+        Ok(())
+    }
+
+    /// Answers one line of input. Notifications and responses get no
+    /// answer; unreadable lines get an error with a null id.
+    pub async fn handle_line(&mut self, line: &str) -> Option<JsonRpcResponse> {
+        if line.trim().is_empty() {
+            return None;
+        }
+        debug!("MCP <- {}", line);
+        match JsonRpcMessage::parse(line) {
+            Ok(JsonRpcMessage::Request(request)) => {
+                let result = match McpRequest::from_request(&request) {
+                    Ok(mcp_request) => self.handle(mcp_request).await,
+                    Err(e) => Err(e),
+                };
+                Some(match result {
+                    Ok(result) => JsonRpcResponse::success(request.id, result),
+                    Err(e) => JsonRpcResponse::failure(Some(request.id), e),
+                })
+            }
+            Ok(JsonRpcMessage::Notification(notification)) => {
+                debug!("Notification from MCP client: {}", notification.method);
+                None
+            }
+            Ok(JsonRpcMessage::Response(_)) => {
+                warn!("Unexpected response from MCP client: {}", line);
+                None
+            }
+            Err(e) => Some(JsonRpcResponse::failure(None, e)),
+        }
+    }
+
+    /// Result of `request`
+    pub async fn handle(&mut self, request: McpRequest) -> Result<Value, JsonRpcError> {
+        let method = request.method();
+        match (&request, &self.session) {
+            (McpRequest::Initialize(_), _) | (McpRequest::Ping, _) => {}
+            (_, None) => {
+                return Err(JsonRpcError::new(
+                    JsonRpcError::INVALID_REQUEST,
+                    format!("Server not initialized: send initialize before {}", method),
+                ))
+            }
+            (_, Some(session)) if !session.capabilities.allows(method) => {
+                return Err(JsonRpcError::new(
+                    JsonRpcError::METHOD_NOT_FOUND,
+                    format!(
+                        "Method not supported: {} (capability not negotiated)",
+                        method
+                    ),
+                ))
+            }
+            _ => {}
+        }
+
+        match request {
+            McpRequest::Initialize(params) => {
+                let protocol_version = negotiate_protocol_version(&params.protocol_version);
+                info!(
+                    "MCP client {} {} connected (protocol {}, using {})",
+                    params.client_info.name,
+                    params.client_info.version,
+                    params.protocol_version,
+                    protocol_version
+                );
+                let capabilities = self.capabilities();
+                self.announce_tool_changes.store(
+                    capabilities.allows(TOOLS_LIST_CHANGED_NOTIFICATION),
+                    Ordering::Relaxed,
+                );
+                self.session = Some(Session {
+                    protocol_version: protocol_version.to_string(),
+                    capabilities,
+                    client_info: params.client_info,
+                });
+                to_result(InitializeResult {
+                    protocol_version: protocol_version.to_string(),
+                    capabilities: ServerCapabilities::from(&capabilities),
+                    server_info: Implementation {
+                        name: env!("CARGO_PKG_NAME").to_string(),
+                        version: env!("CARGO_PKG_VERSION").to_string(),
+                    },
+                    instructions: None,
+                })
+            }
+            McpRequest::Ping => Ok(json!({})),
+            McpRequest::ListTools => {
+                // A tool offered by several servers is listed once, for the
+                // server its bare name runs on
+                let mut tools: Vec<McpTool> = Vec::new();
+                for server in &self.servers {
+                    for schema in server.list_tools().await.map_err(internal)? {
+                        if !tools.iter().any(|tool| tool.name == schema.name) {
+                            tools.push(McpTool::from(schema));
+                        }
+                    }
+                }
+                to_result(ListToolsResult { tools })
+            }
+            McpRequest::CallTool(params) => {
+                let (progress, forwarder) = self.forward_progress(params.progress_token());
+                let arguments = params.arguments.unwrap_or_else(|| json!({}));
+                let server = self.server_with_tool(&params.name).await?;
+                // The client decides whether to retry, so each call runs once
+                let result = server
+                    .mcp_server
+                    .call_tool(&params.name, &arguments, 1, Duration::ZERO, &progress)
+                    .await;
+                // Every notification is queued before the response
+                drop(progress);
+                if let Some(forwarder) = forwarder {
+                    let _ = forwarder.await;
+                }
+                debug!("Tool {} took {} ms", params.name, result.duration_ms);
+                to_result(match result.into_result() {
+                    Ok(content) => CallToolResult::from_value(content),
+                    Err(e) => {
+                        warn!("Tool {} failed: {}", params.name, e);
+                        let error = ToolError::from_error(&e);
+                        CallToolResult {
+                            structured_content: serde_json::to_value(&error).ok(),
+                            ..CallToolResult::error(e.to_string())
+                        }
+                    }
+                })
+            }
+            McpRequest::ListResources => {
+                // Servers may share a resource pattern; the first one wins,
+                // as it does when the resource is read
+                let mut resources: Vec<McpResource> = Vec::new();
+                for server in &self.servers {
+                    for schema in server.list_resources().await.map_err(internal)? {
+                        if !UriTemplate::is_template(&schema.pattern)
+                            && !resources.iter().any(|r| r.uri == schema.pattern)
+                        {
+                            resources.push(McpResource::from(schema));
+                        }
+                    }
+                }
+                to_result(ListResourcesResult { resources })
+            }
+            McpRequest::ListResourceTemplates => {
+                let mut resource_templates: Vec<McpResourceTemplate> = Vec::new();
+                for server in &self.servers {
+                    for schema in server.list_resources().await.map_err(internal)? {
+                        if UriTemplate::is_template(&schema.pattern)
+                            && !resource_templates
+                                .iter()
+                                .any(|t| t.uri_template == schema.pattern)
+                        {
+                            resource_templates.push(McpResourceTemplate::from(schema));
+                        }
+                    }
+                }
+                to_result(ListResourceTemplatesResult { resource_templates })
+            }
+            McpRequest::ReadResource(params) => {
+                let (pattern, arguments) = params.pattern_and_arguments();
+                let server = self.server_with_resource(&pattern).await?;
+                let value = server
+                    .mcp_server
+                    .read_resource(&pattern, &arguments)
+                    .await
+                    .map_err(internal)?;
+                to_result(ReadResourceResult::from_value(&params.uri, &value))
+            }
+            McpRequest::ListPrompts => {
+                let mut prompts = Vec::new();
+                for server in self
+                    .servers
+                    .iter()
+                    .filter(|s| s.mcp_server.capabilities().prompts)
+                {
+                    prompts.extend(
+                        server
+                            .mcp_server
+                            .list_prompts()
+                            .await
+                            .map_err(internal)?
+                            .into_iter()
+                            .map(McpPrompt::from),
+                    );
+                }
+                to_result(ListPromptsResult { prompts })
+            }
+            McpRequest::GetPrompt(params) => {
+                for server in self
+                    .servers
+                    .iter_mut()
+                    .filter(|s| s.mcp_server.capabilities().prompts)
+                {
+                    let description = server
+                        .mcp_server
+                        .list_prompts()
+                        .await
+                        .map_err(internal)?
+                        .into_iter()
+                        .find(|prompt| prompt.name == params.name)
+                        .map(|prompt| prompt.description);
+                    if description.is_some() {
+                        let value = server
+                            .mcp_server
+                            .get_prompt(&params.name, params.arguments)
+                            .await
+                            .map_err(internal)?;
+                        return to_result(GetPromptResult::from_value(description, value));
+                    }
+                }
+                Err(JsonRpcError::new(
+                    JsonRpcError::INVALID_PARAMS,
+                    format!("Unknown prompt: {}", params.name),
+                ))
+            }
+        }
+    }
+
+    /// Cleans up the servers
+    pub async fn cleanup(&mut self) -> Result<()> {
+        for server in &mut self.servers {
+            if let Err(e) = server.cleanup().await {
+                warn!("Warning during server cleanup: {}", e);
+            }
+        }
+        Ok(())
+    }
+
+    /// Tasks sending `notifications/tools/list_changed` to `notifications`
+    /// whenever a server's tools change, once the session agreed on it
+    fn watch_tools(
+        &self,
+        notifications: &mpsc::UnboundedSender<JsonRpcNotification>,
+    ) -> Vec<JoinHandle<()>> {
+        self.servers
+            .iter()
+            .map(|server| {
+                let mut changes = server.mcp_server.subscribe_tools_changed();
+                let notifications = notifications.clone();
+                let announce = Arc::clone(&self.announce_tool_changes);
+                tokio::spawn(async move {
+                    while changes.changed().await.is_ok() {
+                        if !announce.load(Ordering::Relaxed) {
+                            continue;
+                        }
+                        let notification =
+                            JsonRpcNotification::new(TOOLS_LIST_CHANGED_NOTIFICATION, None);
+                        if notifications.send(notification).is_err() {
+                            break;
+                        }
+                    }
+                })
+            })
+            .collect()
+    }
+
+    /// A sink for a tool call and the task turning its events into progress
+    /// notifications, if the client gave a `token` and `serve` is running
+    fn forward_progress(&self, token: Option<&Value>) -> (ProgressSink, Option<JoinHandle<()>>) {
+        let (token, notifications) = match (token, &self.notifications) {
+            (Some(token), Some(notifications)) => (token.clone(), notifications.clone()),
+            _ => return (ProgressSink::none(), None),
+        };
+        let (progress, mut events) = ProgressSink::channel();
+        let forwarder = tokio::spawn(async move {
+            while let Some(event) = events.recv().await {
+                let params = ProgressParams {
+                    progress_token: token.clone(),
+                    progress: event.progress,
+                    total: event.total,
+                    message: event.message,
+                };
+                let notification = JsonRpcNotification::new(
+                    PROGRESS_NOTIFICATION,
+                    serde_json::to_value(params).ok(),
+                );
+                if notifications.send(notification).is_err() {
+                    break;
+                }
+            }
+        });
+        (progress, Some(forwarder))
+    }
+
+    /// The server running the tool `name`, bare or qualified
+    async fn server_with_tool(&mut self, name: &str) -> Result<&mut Server, JsonRpcError> {
+        match find_tool_server(&self.servers, name).await {
+            Some(i) => Ok(&mut self.servers[i]),
+            None => Err(JsonRpcError::new(
+                JsonRpcError::INVALID_PARAMS,
+                format!("Unknown tool: {}", name),
+            )),
+        }
+    }
+
+    /// The first server offering the resource `pattern`, by name or by a
+    /// matching URI template
+    async fn server_with_resource(&mut self, pattern: &str) -> Result<&mut Server, JsonRpcError> {
+        let mut found = None;
+        for (i, server) in self.servers.iter().enumerate() {
+            if server.mcp_server.capabilities().resources
+                && server.mcp_server.has_resource(pattern).await
+            {
+                found = Some(i);
+                break;
+            }
+        }
+        match found {
+            Some(i) => Ok(&mut self.servers[i]),
+            None => Err(JsonRpcError::new(
+                JsonRpcError::INVALID_PARAMS,
+                format!("Unknown resource: {}", pattern),
+            )),
+        }
+    }
+}
+
+/// Opens and initializes the RAG index described by `config`
+async fn open_rag_server(config: RagConfig) -> Result<Arc<Mutex<RagServer>>> {
+    let rag_server = RagServer::with_config(config)?;
+    rag_server.init().await?;
+    Ok(Arc::new(Mutex::new(rag_server)))
+}
+
+/// Serializes a result, failing with an internal error
+fn to_result<T: Serialize>(result: T) -> Result<Value, JsonRpcError> {
+    serde_json::to_value(result).map_err(internal)
+}
+
+/// Writes `message` as one line to the client
+async fn write_output<W: AsyncWrite + Unpin>(
+    output: &mut W,
+    message: JsonRpcMessage,
+) -> Result<()> {
+    let mut line = message.to_line();
+    debug!("MCP -> {}", line);
+    line.push('\n');
+    output.write_all(line.as_bytes()).await?;
+    output.flush().await?;
+    Ok(())
+}
+
+/// Maps a server failure onto an internal JSON-RPC error
+fn internal(e: impl std::fmt::Display) -> JsonRpcError {
+    JsonRpcError::new(JsonRpcError::INTERNAL_ERROR, e.to_string())
+}
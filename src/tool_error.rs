@@ -0,0 +1,186 @@
+//! Tool Error Module: Structured failures of tool calls
+//!
+//! This module provides:
+//! - `ToolError`: what kind of failure a tool call met, its message,
+//!   whether another attempt may succeed and optional details as `data`
+//! - `ToolErrorKind`: the kinds of failures, from invalid arguments to
+//!   timeouts
+//! - `ToolError::from_error`: the structured form of any error a call
+//!   returned, classifying JSON-RPC errors of external servers
+//! - `invalid_arguments`: the error of a call whose arguments are wrong
+//!
+//! Tools fail with a `ToolError` inside their `anyhow::Error`. Retries
+//! follow its `retryable`, `ToolResult` hands it to the LLM with the
+//! message, and `serve --stdio` sends it to MCP clients as the structured
+//! content of an `isError` result, which `StdioClient` reads back.
+//!
+//! Author: arkSong <arksong2018@gmail.com>
+//! Version: 1.0.0
+//! License: MIT
+
+use anyhow::Error;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::fmt;
+
+use crate::protocol::JsonRpcError;
+use crate::retry::is_transient;
+
+/// What kind of failure a tool call met
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ToolErrorKind {
+    /// The arguments do not fit the tool's input schema
+    InvalidArguments,
+    /// No server offers the tool, or what it works on does not exist
+    NotFound,
+    /// A policy or the user refused the call
+    PermissionDenied,
+    /// The tool did not finish in time
+    Timeout,
+    /// The server of the tool cannot be reached
+    Unavailable,
+    /// The result does not match the tool's output schema
+    InvalidOutput,
+    /// Any other failure, including kinds this version does not know
+    #[serde(other)]
+    Internal,
+}
+
+impl ToolErrorKind {
+    /// Whether calls failing this way are retried unless the tool says
+    /// otherwise
+    fn is_retryable(self) -> bool {
+        matches!(
+            self,
+            ToolErrorKind::Timeout | ToolErrorKind::Unavailable | ToolErrorKind::Internal
+        )
+    }
+}
+
+/// A failed tool call, e.g.
+/// `{"kind": "not_found", "message": "No such file: a.txt", "retryable": false}`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ToolError {
+    pub kind: ToolErrorKind,
+    pub message: String,
+    /// Whether the same call may succeed when made again
+    pub retryable: bool,
+    /// Details for the caller, such as the JSON-RPC error code
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub data: Option<Value>,
+}
+
+impl ToolError {
+    /// An error of `kind`, retryable if failures of its kind usually are
+    pub fn new(kind: ToolErrorKind, message: impl Into<String>) -> Self {
+        Self {
+            kind,
+            message: message.into(),
+            retryable: kind.is_retryable(),
+            data: None,
+        }
+    }
+
+    pub fn with_data(mut self, data: Value) -> Self {
+        self.data = Some(data);
+        self
+    }
+
+    pub fn with_retryable(mut self, retryable: bool) -> Self {
+        self.retryable = retryable;
+        self
+    }
+
+    /// The `ToolError` in `error`, or one made from its message, kind and
+    /// retryability for errors of other types
+    pub fn from_error(error: &Error) -> Self {
+        if let Some(tool_error) = error.downcast_ref::<ToolError>() {
+            return tool_error.clone();
+        }
+        let (kind, data) = match error.downcast_ref::<JsonRpcError>() {
+            Some(e) => {
+                let kind = match e.code {
+                    JsonRpcError::INVALID_PARAMS => ToolErrorKind::InvalidArguments,
+                    JsonRpcError::METHOD_NOT_FOUND => ToolErrorKind::NotFound,
+                    _ => ToolErrorKind::Internal,
+                };
+                (kind, Some(json!({ "code": e.code })))
+            }
+            None => (ToolErrorKind::Internal, None),
+        };
+        Self {
+            kind,
+            message: error.to_string(),
+            retryable: is_transient(error),
+            data,
+        }
+    }
+}
+
+/// A failure caused by the arguments of a call, which is not retried
+pub fn invalid_arguments(message: impl Into<String>) -> Error {
+    Error::new(ToolError::new(ToolErrorKind::InvalidArguments, message))
+}
+
+impl fmt::Display for ToolError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.message)
+    }
+}
+
+impl std::error::Error for ToolError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::retry::permanent;
+
+    #[test]
+    fn test_tool_errors() {
+        let error = ToolError::new(ToolErrorKind::NotFound, "No such file: a.txt");
+        assert!(!error.retryable);
+        assert_eq!(
+            serde_json::to_value(&error).unwrap(),
+            json!({ "kind": "not_found", "message": "No such file: a.txt", "retryable": false })
+        );
+        let timeout = ToolError::new(ToolErrorKind::Timeout, "Took too long")
+            .with_data(json!({ "seconds": 30 }));
+        assert!(timeout.retryable);
+        assert!(is_transient(&Error::new(timeout.clone())));
+        assert!(!is_transient(&Error::new(timeout.with_retryable(false))));
+
+        // Errors keep their kind through anyhow and context
+        let wrapped = Error::new(error.clone()).context("file_read failed");
+        assert_eq!(ToolError::from_error(&wrapped), error);
+
+        let invalid = JsonRpcError::new(JsonRpcError::INVALID_PARAMS, "Missing argument key");
+        let error = ToolError::from_error(&Error::new(invalid));
+        assert_eq!(error.kind, ToolErrorKind::InvalidArguments);
+        assert!(!error.retryable);
+        assert_eq!(error.data, Some(json!({ "code": -32602 })));
+
+        let error = ToolError::from_error(&permanent("Out of paper"));
+        assert_eq!(error.kind, ToolErrorKind::Internal);
+        assert!(!error.retryable);
+        let error = ToolError::from_error(&Error::msg("Disk full"));
+        assert_eq!(error.kind, ToolErrorKind::Internal);
+        assert!(!error.retryable);
+        let reset = std::io::Error::from(std::io::ErrorKind::ConnectionReset);
+        assert!(ToolError::from_error(&Error::new(reset)).retryable);
+
+        let error = invalid_arguments("Invalid argument: path");
+        assert_eq!(
+            ToolError::from_error(&error).kind,
+            ToolErrorKind::InvalidArguments
+        );
+        assert!(!is_transient(&error));
+
+        // Kinds of newer versions are read as internal errors
+        let error: ToolError = serde_json::from_value(
+            json!({ "kind": "quota_exceeded", "message": "Quota exceeded", "retryable": true }),
+        )
+        .unwrap();
+        assert_eq!(error.kind, ToolErrorKind::Internal);
+    }
+}
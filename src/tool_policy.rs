@@ -0,0 +1,110 @@
+//! Tool Policy Module: Which tools may run and which need approval
+//!
+//! This module provides:
+//! - `Permission`: `safe`, `confirm` or `deny`
+//! - `ToolPolicy`, read from the `tool_policy` of a server entry in
+//!   `servers_config.json`
+//! - The question the chat asks before a `confirm` tool runs
+//!
+//! Tools not named in a policy get its `default`, which is `safe`, so a
+//! server without a policy runs every tool as before. `deny` tools are
+//! neither offered to the LLM nor run, whoever calls them. `confirm` tools
+//! run in the chat only once the user has agreed to the call.
+//!
+//! Author: arkSong <arksong2018@gmail.com>
+//! Version: 1.0.0
+//! License: MIT
+
+use serde::Deserialize;
+use serde_json::Value;
+use std::collections::HashMap;
+
+/// Arguments naming what a tool works on, in the order they are looked for
+/// when asking for approval
+const TARGET_ARGUMENTS: [&str; 7] = ["path", "source", "table", "key", "name", "uri", "query"];
+
+/// What may happen when a tool is called
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Permission {
+    /// Runs without asking
+    #[default]
+    Safe,
+    /// Runs once the user has agreed to the call
+    Confirm,
+    /// Never runs
+    Deny,
+}
+
+/// Permissions of a server's tools, e.g.
+/// `{"default": "safe", "tools": {"file_delete": "confirm"}}`
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+#[serde(default)]
+pub struct ToolPolicy {
+    /// Permission of the tools not listed in `tools`
+    pub default: Permission,
+    /// Permissions by bare tool name
+    pub tools: HashMap<String, Permission>,
+}
+
+impl ToolPolicy {
+    /// Permission of the tool `name`, given without the server's name
+    pub fn permission(&self, name: &str) -> Permission {
+        self.tools.get(name).copied().unwrap_or(self.default)
+    }
+}
+
+/// The question asked before `tool` runs with `arguments`, e.g.
+/// `Allow file_delete on notes.txt? (y/n) `
+pub fn approval_question(tool: &str, arguments: &Value) -> String {
+    let target = TARGET_ARGUMENTS
+        .iter()
+        .find_map(|name| arguments.get(*name).and_then(Value::as_str));
+    match target {
+        Some(target) => format!("Allow {} on {}? (y/n) ", tool, target),
+        None => format!("Allow {}? (y/n) ", tool),
+    }
+}
+
+/// Whether `answer` to an approval question agrees to the call
+pub fn is_approval(answer: &str) -> bool {
+    matches!(answer.trim().to_lowercase().as_str(), "y" | "yes")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_permissions() {
+        let policy: ToolPolicy = serde_json::from_value(json!({
+            "tools": { "file_delete": "confirm", "sql_execute": "deny" }
+        }))
+        .unwrap();
+        assert_eq!(policy.permission("file_delete"), Permission::Confirm);
+        assert_eq!(policy.permission("sql_execute"), Permission::Deny);
+        assert_eq!(policy.permission("file_read"), Permission::Safe);
+
+        let policy: ToolPolicy = serde_json::from_value(json!({ "default": "confirm" })).unwrap();
+        assert_eq!(policy.permission("file_read"), Permission::Confirm);
+        assert_eq!(ToolPolicy::default().permission("x"), Permission::Safe);
+        assert!(serde_json::from_value::<ToolPolicy>(json!({ "default": "maybe" })).is_err());
+    }
+
+    #[test]
+    fn test_approval_question() {
+        assert_eq!(
+            approval_question("file_delete", &json!({ "path": "notes.txt" })),
+            "Allow file_delete on notes.txt? (y/n) "
+        );
+        assert_eq!(
+            approval_question("memory_clear", &json!({})),
+            "Allow memory_clear? (y/n) "
+        );
+        assert!(is_approval(" Y\n"));
+        assert!(is_approval("yes"));
+        assert!(!is_approval(""));
+        assert!(!is_approval("no"));
+    }
+}
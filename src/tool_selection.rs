@@ -0,0 +1,267 @@
+//! Tool Selection Module: Which tools are offered to the LLM in a turn
+//!
+//! This module provides:
+//! - `ToolSelectionConfig`, read from the `tool_selection` section of
+//!   `config.yaml`
+//! - `allow` and `deny` lists of tool names, with `*` and `?` wildcards;
+//!   the chat also refuses to call the tools they keep out
+//! - `ToolSelector`: the tools most similar to the user's input by
+//!   embedding similarity, at most `max_tools` of them
+//!
+//! When all tools the lists let through fit in `max_tools`, every one of
+//! them is offered and nothing is embedded. Otherwise the input and the
+//! name and description of each tool are embedded with the RAG embedder,
+//! whose cache keeps the tools' vectors from one turn to the next. The
+//! chosen tools keep their order, so the prompt only changes in which tools
+//! it lists.
+//!
+//! Author: arkSong <arksong2018@gmail.com>
+//! Version: 1.0.0
+//! License: MIT
+
+use glob::Pattern;
+use log::{debug, warn};
+use serde::{Deserialize, Serialize};
+
+use crate::config::Configuration;
+use crate::protocol::ToolSchema;
+use crate::rag_server::{cosine_similarity, RagServer};
+
+/// Settings of the tool selection, read from the `tool_selection` section
+/// of `config.yaml`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ToolSelectionConfig {
+    /// Whether only the most relevant tools are offered once there are
+    /// more than `max_tools`
+    pub enabled: bool,
+    /// Most tools offered in a turn
+    pub max_tools: usize,
+    /// Only tools matching one of these names are offered, unless empty
+    pub allow: Vec<String>,
+    /// Tools matching one of these names are never offered
+    pub deny: Vec<String>,
+}
+
+impl Default for ToolSelectionConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            max_tools: 20,
+            allow: Vec::new(),
+            deny: Vec::new(),
+        }
+    }
+}
+
+/// Picks the tools of a turn
+#[derive(Debug, Clone, Default)]
+pub struct ToolSelector {
+    config: ToolSelectionConfig,
+}
+
+impl ToolSelector {
+    pub fn new(config: ToolSelectionConfig) -> Self {
+        Self { config }
+    }
+
+    /// A selector configured by the `tool_selection` section of the
+    /// configuration file `config_path`, or by the defaults if it cannot
+    /// be read
+    pub fn from_config_file(config_path: &str) -> Self {
+        let config = Configuration::new()
+            .load_tool_selection_config(config_path)
+            .unwrap_or_else(|e| {
+                warn!(
+                    "Failed to load the tool selection configuration, using defaults: {}",
+                    e
+                );
+                ToolSelectionConfig::default()
+            });
+        Self::new(config)
+    }
+
+    pub fn config(&self) -> &ToolSelectionConfig {
+        &self.config
+    }
+
+    /// Whether the allow and deny lists let the tool `name` through
+    pub fn is_allowed(&self, name: &str) -> bool {
+        let matches = |patterns: &[String]| patterns.iter().any(|pattern| matches(pattern, name));
+        (self.config.allow.is_empty() || matches(&self.config.allow)) && !matches(&self.config.deny)
+    }
+
+    /// The tools to offer for `input`: those the lists let through, or
+    /// the `max_tools` most similar to `input` among them. If embedding
+    /// fails, the first `max_tools` are offered.
+    pub async fn select(
+        &self,
+        input: &str,
+        tools: &[ToolSchema],
+        rag_server: &RagServer,
+    ) -> Vec<ToolSchema> {
+        let tools: Vec<&ToolSchema> = tools
+            .iter()
+            .filter(|tool| self.is_allowed(&tool.name))
+            .collect();
+        let max_tools = self.config.max_tools;
+        if !self.config.enabled || tools.len() <= max_tools {
+            return tools.into_iter().cloned().collect();
+        }
+
+        let texts: Vec<String> = tools
+            .iter()
+            .map(|tool| format!("{}: {}", tool.name, tool.description))
+            .collect();
+        let mut inputs: Vec<&str> = vec![input];
+        inputs.extend(texts.iter().map(String::as_str));
+        let chosen = match rag_server.generate_embeddings(&inputs).await {
+            Ok(embeddings) if embeddings.len() == inputs.len() => {
+                let scores: Vec<f32> = embeddings[1..]
+                    .iter()
+                    .map(|embedding| cosine_similarity(&embeddings[0], embedding))
+                    .collect();
+                most_similar(&scores, max_tools)
+            }
+            Ok(_) => {
+                warn!("Embedder returned too few vectors, offering the first tools");
+                (0..max_tools).collect()
+            }
+            Err(e) => {
+                warn!("Failed to embed the tools, offering the first ones: {}", e);
+                (0..max_tools).collect()
+            }
+        };
+        let selected: Vec<ToolSchema> = chosen.into_iter().map(|i| tools[i].clone()).collect();
+        debug!(
+            "Offering {} of {} tools: {}",
+            selected.len(),
+            tools.len(),
+            selected
+                .iter()
+                .map(|tool| tool.name.as_str())
+                .collect::<Vec<_>>()
+                .join(", ")
+        );
+        selected
+    }
+}
+
+/// Whether the tool `name` matches `pattern`, a name with optional
+/// wildcards; patterns that are not valid globs only match themselves
+fn matches(pattern: &str, name: &str) -> bool {
+    match Pattern::new(pattern) {
+        Ok(glob) => glob.matches(name),
+        Err(_) => pattern == name,
+    }
+}
+
+/// Indices of the `count` highest `scores`, in ascending order
+fn most_similar(scores: &[f32], count: usize) -> Vec<usize> {
+    let mut ranked: Vec<usize> = (0..scores.len()).collect();
+    ranked.sort_by(|a, b| scores[*b].total_cmp(&scores[*a]));
+    ranked.truncate(count);
+    ranked.sort_unstable();
+    ranked
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::embedder::Embedder;
+    use crate::local_backend::LocalBackend;
+    use crate::rag_server::RagConfig;
+    use anyhow::Result;
+    use serde_json::json;
+    use std::sync::Arc;
+
+    /// Embeds texts by which of a few topics they mention
+    struct TopicEmbedder;
+
+    #[async_trait::async_trait]
+    impl Embedder for TopicEmbedder {
+        fn name(&self) -> String {
+            "topics".to_string()
+        }
+
+        async fn embed(&self, texts: &[&str]) -> Result<Vec<Vec<f32>>> {
+            Ok(texts
+                .iter()
+                .map(|text| {
+                    ["file", "sql", "memory", "browser"]
+                        .iter()
+                        .map(|topic| text.to_lowercase().matches(topic).count() as f32)
+                        .collect()
+                })
+                .collect())
+        }
+    }
+
+    fn tool(name: &str, description: &str) -> ToolSchema {
+        ToolSchema {
+            name: name.to_string(),
+            description: description.to_string(),
+            input_schema: json!({ "type": "object" }),
+            output_schema: None,
+        }
+    }
+
+    #[test]
+    fn test_allow_and_deny() {
+        let selector = ToolSelector::new(ToolSelectionConfig {
+            allow: vec!["file_*".to_string(), "memory_get".to_string()],
+            deny: vec!["file_delete".to_string()],
+            ..ToolSelectionConfig::default()
+        });
+        assert!(selector.is_allowed("file_read"));
+        assert!(selector.is_allowed("memory_get"));
+        assert!(!selector.is_allowed("file_delete"));
+        assert!(!selector.is_allowed("memory_set"));
+        assert!(ToolSelector::default().is_allowed("anything"));
+        assert_eq!(most_similar(&[0.1, 0.9, 0.5, 0.7], 2), vec![1, 3]);
+    }
+
+    #[tokio::test]
+    async fn test_select() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let rag_server = RagServer::with_backend(
+            RagConfig::default(),
+            Box::new(TopicEmbedder),
+            Arc::new(LocalBackend::open(temp_dir.path()).unwrap()),
+        )
+        .unwrap();
+        let tools = vec![
+            tool("file_read", "Reads a file"),
+            tool("sqlite_query", "Runs an SQL query"),
+            tool("memory_get", "Gets a value from memory"),
+            tool("puppeteer_navigate", "Opens a page in the browser"),
+            tool("file_write", "Writes a file"),
+        ];
+        let selector = ToolSelector::new(ToolSelectionConfig {
+            max_tools: 2,
+            ..ToolSelectionConfig::default()
+        });
+        let names = |tools: Vec<ToolSchema>| -> Vec<String> {
+            tools.into_iter().map(|tool| tool.name).collect()
+        };
+        assert_eq!(
+            names(
+                selector
+                    .select("Show me the file notes.txt", &tools, &rag_server)
+                    .await
+            ),
+            ["file_read", "file_write"]
+        );
+
+        // Few enough tools are all offered
+        let selector = ToolSelector::new(ToolSelectionConfig {
+            max_tools: 2,
+            deny: vec!["file_*".to_string(), "sqlite_*".to_string()],
+            ..ToolSelectionConfig::default()
+        });
+        assert_eq!(
+            names(selector.select("Anything", &tools, &rag_server).await),
+            ["memory_get", "puppeteer_navigate"]
+        );
+    }
+}
@@ -0,0 +1,238 @@
+//! Transcript Module: Chat sessions exported to files and imported back
+//!
+//! This module provides:
+//! - `TranscriptFormat`: Markdown or JSONL, chosen by the file extension
+//! - `to_markdown`: a readable transcript, tool calls and results in fenced
+//!   JSON blocks
+//! - `to_jsonl` and `from_jsonl`: one JSON line per message with its turn,
+//!   which `/import` reads back without loss
+//!
+//! Markdown is meant for reading and sharing; only JSONL is imported. A
+//! JSONL transcript starts with a line describing the session, which
+//! imports skip, so files can be concatenated or trimmed by hand.
+//!
+//! Author: arkSong <arksong2018@gmail.com>
+//! Version: 1.0.0
+//! License: MIT
+
+use anyhow::{Error, Result};
+use chrono::Utc;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::path::Path;
+
+use crate::agent::parse_tool_calls;
+
+/// How a transcript is written
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TranscriptFormat {
+    Markdown,
+    Jsonl,
+}
+
+impl TranscriptFormat {
+    /// The format of `path`: `.md` and `.markdown` are Markdown, `.jsonl`
+    /// is JSONL
+    pub fn from_path(path: &Path) -> Result<Self> {
+        match path.extension().and_then(|extension| extension.to_str()) {
+            Some("md") | Some("markdown") => Ok(Self::Markdown),
+            Some("jsonl") => Ok(Self::Jsonl),
+            _ => Err(Error::msg(format!(
+                "Unknown transcript format of {}, use .md or .jsonl",
+                path.display()
+            ))),
+        }
+    }
+}
+
+/// One message of a JSONL transcript
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+struct TranscriptLine {
+    /// Number of the turn, from 0
+    turn: usize,
+    role: String,
+    content: Value,
+}
+
+/// The text of a chat message
+fn content_of(message: &Value) -> String {
+    match message["content"].as_str() {
+        Some(content) => content.to_string(),
+        None => message["content"].to_string(),
+    }
+}
+
+/// `text` in a fenced code block of `language`, the fence longer than any
+/// run of backticks in `text`
+fn fenced(text: &str, language: &str) -> String {
+    let mut longest = 0;
+    let mut run = 0;
+    for c in text.chars() {
+        run = if c == '`' { run + 1 } else { 0 };
+        longest = longest.max(run);
+    }
+    let fence = "`".repeat((longest + 1).max(3));
+    format!("{}{}\n{}\n{}", fence, language, text.trim_end(), fence)
+}
+
+/// `text` pretty-printed if it is JSON
+fn pretty_json(text: &str) -> Option<String> {
+    let value: Value = serde_json::from_str(text.trim()).ok()?;
+    serde_json::to_string_pretty(&value).ok()
+}
+
+/// The `turns` of the session `session_id` as a Markdown document: each
+/// turn under its own heading, tool calls and results in JSON blocks
+pub fn to_markdown(session_id: &str, turns: &[Vec<Value>]) -> String {
+    let mut markdown = format!(
+        "# Chat session {}\n\nExported {}\n",
+        session_id,
+        Utc::now().format("%Y-%m-%d %H:%M UTC")
+    );
+    for (i, turn) in turns.iter().enumerate() {
+        markdown.push_str(&format!("\n## Turn {}\n", i + 1));
+        for message in turn {
+            let content = content_of(message);
+            let role = message["role"].as_str().unwrap_or("user");
+            let section = match role {
+                "assistant" if parse_tool_calls(&content).is_some() => {
+                    let json = pretty_json(&content).unwrap_or(content);
+                    format!("**Tool call:**\n\n{}", fenced(&json, "json"))
+                }
+                "tool" => match pretty_json(&content) {
+                    Some(json) => format!("**Tool result:**\n\n{}", fenced(&json, "json")),
+                    None => format!("**Tool result:**\n\n{}", fenced(&content, "")),
+                },
+                "assistant" => format!("**Assistant:**\n\n{}", content.trim()),
+                "system" => format!("**System:**\n\n{}", content.trim()),
+                _ => format!("**User:**\n\n{}", content.trim()),
+            };
+            markdown.push('\n');
+            markdown.push_str(&section);
+            markdown.push('\n');
+        }
+    }
+    markdown
+}
+
+/// The `turns` of the session `session_id` as JSONL: a line describing the
+/// session, then one line per message
+pub fn to_jsonl(session_id: &str, turns: &[Vec<Value>]) -> Result<String> {
+    let mut jsonl = serde_json::to_string(&json!({
+        "session_id": session_id,
+        "exported_at": Utc::now().to_rfc3339(),
+        "turns": turns.len()
+    }))?;
+    jsonl.push('\n');
+    for (turn, messages) in turns.iter().enumerate() {
+        for message in messages {
+            let line = TranscriptLine {
+                turn,
+                role: message["role"].as_str().unwrap_or("user").to_string(),
+                content: message["content"].clone(),
+            };
+            jsonl.push_str(&serde_json::to_string(&line)?);
+            jsonl.push('\n');
+        }
+    }
+    Ok(jsonl)
+}
+
+/// The turns of a JSONL transcript, in the order of its lines. Lines
+/// describing a session and blank lines are skipped; a message starts a
+/// new turn whenever its turn number changes or a session begins.
+pub fn from_jsonl(text: &str) -> Result<Vec<Vec<Value>>> {
+    let mut turns: Vec<Vec<Value>> = Vec::new();
+    let mut current = None;
+    for (number, line) in text.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() {
+            continue;
+        }
+        let value: Value = serde_json::from_str(line)
+            .map_err(|e| Error::msg(format!("Line {} is not JSON: {}", number + 1, e)))?;
+        if value.get("session_id").is_some() && value.get("role").is_none() {
+            current = None;
+            continue;
+        }
+        let line: TranscriptLine = serde_json::from_value(value)
+            .map_err(|e| Error::msg(format!("Line {} is not a message: {}", number + 1, e)))?;
+        if current != Some(line.turn) {
+            turns.push(Vec::new());
+            current = Some(line.turn);
+        }
+        if let Some(messages) = turns.last_mut() {
+            messages.push(json!({ "role": line.role, "content": line.content }));
+        }
+    }
+    Ok(turns)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn turns() -> Vec<Vec<Value>> {
+        vec![
+            vec![
+                json!({ "role": "user", "content": "What is in notes.txt?" }),
+                json!({
+                    "role": "assistant",
+                    "content": r#"{"tool": "file_read", "arguments": {"path": "notes.txt"}}"#
+                }),
+                json!({ "role": "tool", "content": r#"{"content":"Buy ```milk```"}"# }),
+                json!({ "role": "assistant", "content": "It says to buy milk." }),
+            ],
+            vec![
+                json!({ "role": "user", "content": "Thanks\n\nBye" }),
+                json!({ "role": "assistant", "content": "Bye!" }),
+            ],
+        ]
+    }
+
+    #[test]
+    fn test_format_from_path() {
+        assert_eq!(
+            TranscriptFormat::from_path(Path::new("chat.md")).unwrap(),
+            TranscriptFormat::Markdown
+        );
+        assert_eq!(
+            TranscriptFormat::from_path(Path::new("out/chat.jsonl")).unwrap(),
+            TranscriptFormat::Jsonl
+        );
+        assert!(TranscriptFormat::from_path(Path::new("chat.txt")).is_err());
+        assert!(TranscriptFormat::from_path(Path::new("chat")).is_err());
+    }
+
+    #[test]
+    fn test_markdown() {
+        let markdown = to_markdown("1234", &turns());
+        assert!(markdown.starts_with("# Chat session 1234\n"));
+        assert!(markdown.contains("## Turn 1\n\n**User:**\n\nWhat is in notes.txt?\n"));
+        assert!(markdown.contains("**Tool call:**\n\n```json\n{\n"));
+        assert!(markdown.contains("  \"tool\": \"file_read\""));
+        // Backticks in a result get a longer fence
+        assert!(markdown.contains("**Tool result:**\n\n````json\n"));
+        assert!(markdown.contains("**Assistant:**\n\nIt says to buy milk.\n"));
+        assert!(markdown.contains("## Turn 2\n\n**User:**\n\nThanks\n\nBye\n"));
+    }
+
+    #[test]
+    fn test_jsonl_round_trip() {
+        let jsonl = to_jsonl("1234", &turns()).unwrap();
+        assert_eq!(jsonl.lines().count(), 7);
+        assert!(jsonl
+            .lines()
+            .next()
+            .unwrap()
+            .contains("\"session_id\":\"1234\""));
+        assert_eq!(from_jsonl(&jsonl).unwrap(), turns());
+
+        // Transcripts can be concatenated
+        let twice = format!("{}\n{}", jsonl, jsonl);
+        assert_eq!(from_jsonl(&twice).unwrap().len(), 4);
+
+        assert!(from_jsonl("not json").is_err());
+        assert!(from_jsonl(r#"{"role": "user"}"#).is_err());
+    }
+}
@@ -0,0 +1,261 @@
+//! URI Template Module: Resources addressed by URIs with parameters
+//!
+//! This module provides:
+//! - `UriTemplate`: patterns such as `file:///{path}` or `db://tables/{name}`
+//! - Matching of a URI against a template, giving its parameters
+//! - Expansion of a template with parameters into a URI
+//!
+//! This is the simple `{name}` expansion of RFC 6570 that MCP resource
+//! templates use. An expression matches the shortest non-empty text up to
+//! the literal text after it, and the last expression of a template takes
+//! the rest of the URI, `/` included, so `file:///{path}` matches files in
+//! subdirectories. Matched values are percent-decoded; expanded values are
+//! percent-encoded, `/` included, so a value cannot add path segments.
+//!
+//! Author: arkSong <arksong2018@gmail.com>
+//! Version: 1.0.0
+//! License: MIT
+
+use anyhow::{Error, Result};
+use serde_json::{Map, Value};
+
+/// A piece of a template
+#[derive(Debug, Clone, PartialEq)]
+enum Part {
+    /// Text the URI must contain as is
+    Literal(String),
+    /// A `{name}` expression
+    Variable(String),
+}
+
+/// A parsed URI template
+#[derive(Debug, Clone, PartialEq)]
+pub struct UriTemplate {
+    template: String,
+    parts: Vec<Part>,
+}
+
+impl UriTemplate {
+    /// Parses `template`. Unclosed or empty expressions, and two expressions
+    /// with no text between them, are errors.
+    pub fn parse(template: &str) -> Result<Self> {
+        let mut parts = Vec::new();
+        let mut rest = template;
+        while let Some(start) = rest.find('{') {
+            if start > 0 {
+                parts.push(Part::Literal(rest[..start].to_string()));
+            } else if let Some(Part::Variable(_)) = parts.last() {
+                return Err(Error::msg(format!(
+                    "Invalid URI template {}: expressions must be separated by text",
+                    template
+                )));
+            }
+            let end = match rest[start..].find('}') {
+                Some(end) => start + end,
+                None => {
+                    return Err(Error::msg(format!(
+                        "Invalid URI template {}: unclosed expression",
+                        template
+                    )))
+                }
+            };
+            let name = rest[start + 1..end].trim();
+            if name.is_empty() || name.contains('{') {
+                return Err(Error::msg(format!(
+                    "Invalid URI template {}: invalid expression",
+                    template
+                )));
+            }
+            parts.push(Part::Variable(name.to_string()));
+            rest = &rest[end + 1..];
+        }
+        if !rest.is_empty() {
+            parts.push(Part::Literal(rest.to_string()));
+        }
+        Ok(Self {
+            template: template.to_string(),
+            parts,
+        })
+    }
+
+    /// Whether `pattern` is a template rather than a fixed URI
+    pub fn is_template(pattern: &str) -> bool {
+        pattern.contains('{')
+    }
+
+    /// The template as written
+    pub fn as_str(&self) -> &str {
+        &self.template
+    }
+
+    /// Names of the template's parameters, in order
+    pub fn variables(&self) -> Vec<&str> {
+        self.parts
+            .iter()
+            .filter_map(|part| match part {
+                Part::Variable(name) => Some(name.as_str()),
+                Part::Literal(_) => None,
+            })
+            .collect()
+    }
+
+    /// The parameters of `uri` by name, or `None` if it does not match
+    pub fn matches(&self, uri: &str) -> Option<Map<String, Value>> {
+        let mut params = Map::new();
+        let mut rest = uri;
+        for (i, part) in self.parts.iter().enumerate() {
+            match part {
+                Part::Literal(text) => rest = rest.strip_prefix(text.as_str())?,
+                Part::Variable(name) => {
+                    let end = match self.parts.get(i + 1) {
+                        Some(Part::Literal(text)) => rest
+                            .match_indices(text.as_str())
+                            .map(|(index, _)| index)
+                            .find(|index| *index > 0)?,
+                        _ => rest.len(),
+                    };
+                    if end == 0 {
+                        return None;
+                    }
+                    params.insert(name.clone(), Value::String(decode(&rest[..end])));
+                    rest = &rest[end..];
+                }
+            }
+        }
+        if rest.is_empty() {
+            Some(params)
+        } else {
+            None
+        }
+    }
+
+    /// The URI given by `params`, a JSON object with a value for every
+    /// parameter
+    pub fn expand(&self, params: &Value) -> Result<String> {
+        let mut uri = String::new();
+        for part in &self.parts {
+            match part {
+                Part::Literal(text) => uri.push_str(text),
+                Part::Variable(name) => match params.get(name) {
+                    Some(Value::String(value)) => uri.push_str(&encode(value)),
+                    Some(value) if !value.is_null() => uri.push_str(&encode(&value.to_string())),
+                    _ => {
+                        return Err(Error::msg(format!(
+                            "Missing argument {} for {}",
+                            name, self.template
+                        )))
+                    }
+                },
+            }
+        }
+        Ok(uri)
+    }
+}
+
+/// `value` with `%XX` escapes replaced by the bytes they stand for. Text
+/// that does not decode to UTF-8 is kept as it was.
+fn decode(value: &str) -> String {
+    let bytes = value.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let escaped = if bytes[i] == b'%' {
+            value
+                .get(i + 1..i + 3)
+                .filter(|hex| hex.bytes().all(|byte| byte.is_ascii_hexdigit()))
+                .and_then(|hex| u8::from_str_radix(hex, 16).ok())
+        } else {
+            None
+        };
+        match escaped {
+            Some(byte) => {
+                decoded.push(byte);
+                i += 3;
+            }
+            None => {
+                decoded.push(bytes[i]);
+                i += 1;
+            }
+        }
+    }
+    String::from_utf8(decoded).unwrap_or_else(|_| value.to_string())
+}
+
+/// `value` with every byte outside the unreserved characters
+/// percent-encoded
+fn encode(value: &str) -> String {
+    let mut encoded = String::with_capacity(value.len());
+    for byte in value.bytes() {
+        if byte.is_ascii_alphanumeric() || b"-._~".contains(&byte) {
+            encoded.push(byte as char);
+        } else {
+            encoded.push_str(&format!("%{:02X}", byte));
+        }
+    }
+    encoded
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_matches() {
+        let template = UriTemplate::parse("file:///{path}").unwrap();
+        assert_eq!(template.variables(), vec!["path"]);
+        let params = template.matches("file:///docs/my%20notes.txt").unwrap();
+        assert_eq!(params["path"], "docs/my notes.txt");
+        assert!(template.matches("file:///").is_none());
+        assert!(template.matches("db://tables/users").is_none());
+
+        let template = UriTemplate::parse("db://{db}/tables/{name}.json").unwrap();
+        let params = template.matches("db://main/tables/user.v2.json").unwrap();
+        assert_eq!(params["db"], "main");
+        assert_eq!(params["name"], "user.v2");
+        assert!(template.matches("db://main/tables/users").is_none());
+        assert!(template.matches("db://main/tables/users.json/x").is_none());
+    }
+
+    #[test]
+    fn test_expand() {
+        let template = UriTemplate::parse("db://tables/{name}").unwrap();
+        assert_eq!(
+            template.expand(&json!({ "name": "a b" })).unwrap(),
+            "db://tables/a%20b"
+        );
+        assert_eq!(
+            template.expand(&json!({ "name": 7 })).unwrap(),
+            "db://tables/7"
+        );
+        assert!(template.expand(&json!({})).is_err());
+
+        // Expanding and matching give back the parameters
+        let template = UriTemplate::parse("file:///{path}").unwrap();
+        let uri = template.expand(&json!({ "path": "src/100%.rs" })).unwrap();
+        assert_eq!(uri, "file:///src%2F100%25.rs");
+        assert_eq!(template.matches(&uri).unwrap()["path"], "src/100%.rs");
+
+        // A `/` in a value does not split it into segments
+        let template = UriTemplate::parse("db://{db}/tables/{name}").unwrap();
+        let params = json!({ "db": "a/b", "name": "users" });
+        let uri = template.expand(&params).unwrap();
+        assert_eq!(uri, "db://a%2Fb/tables/users");
+        assert_eq!(Value::Object(template.matches(&uri).unwrap()), params);
+    }
+
+    #[test]
+    fn test_invalid_templates() {
+        assert!(UriTemplate::is_template("file:///{path}"));
+        assert!(!UriTemplate::is_template("filesystem"));
+        assert!(UriTemplate::parse("file:///{path").is_err());
+        assert!(UriTemplate::parse("file:///{}").is_err());
+        assert!(UriTemplate::parse("db://{a}{b}").is_err());
+        assert_eq!(
+            UriTemplate::parse("filesystem")
+                .unwrap()
+                .matches("filesystem"),
+            Some(Map::new())
+        );
+    }
+}
@@ -0,0 +1,239 @@
+//! Usage Module: Token and cost accounting for LLM providers
+//!
+//! This module provides:
+//! - Parsing of token counts reported by provider responses
+//! - Per-session and per-provider usage totals
+//! - The usage of one turn, as the difference of two snapshots of a tracker
+//! - Cost estimation based on configurable $/1k-token pricing
+//!
+//! Key Components:
+//! - `TokenUsage`: Prompt/completion token counts for one or more requests
+//! - `ProviderPricing`: Price per 1k prompt and completion tokens
+//! - `UsageTracker`: Accumulates usage across a chat session
+//!
+//! Author: arkSong <arksong2018@gmail.com>
+//! Version: 1.0.0
+//! License: MIT
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::HashMap;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+pub struct TokenUsage {
+    pub prompt_tokens: u64,
+    pub completion_tokens: u64,
+}
+
+impl TokenUsage {
+    pub fn new(prompt_tokens: u64, completion_tokens: u64) -> Self {
+        Self {
+            prompt_tokens,
+            completion_tokens,
+        }
+    }
+
+    /// Parses the `usage` object of an OpenAI-compatible response
+    pub fn from_openai(response: &Value) -> Option<Self> {
+        let usage = response.get("usage")?;
+        Some(Self::new(
+            usage["prompt_tokens"].as_u64().unwrap_or(0),
+            usage["completion_tokens"].as_u64().unwrap_or(0),
+        ))
+    }
+
+    /// Parses the counters of the final (`done: true`) Ollama message
+    pub fn from_ollama(response: &Value) -> Option<Self> {
+        if response.get("prompt_eval_count").is_none() && response.get("eval_count").is_none() {
+            return None;
+        }
+        Some(Self::new(
+            response["prompt_eval_count"].as_u64().unwrap_or(0),
+            response["eval_count"].as_u64().unwrap_or(0),
+        ))
+    }
+
+    pub fn total(&self) -> u64 {
+        self.prompt_tokens + self.completion_tokens
+    }
+
+    pub fn add(&mut self, other: &TokenUsage) {
+        self.prompt_tokens += other.prompt_tokens;
+        self.completion_tokens += other.completion_tokens;
+    }
+
+    /// The tokens of `self` beyond those of `earlier`
+    pub fn since(&self, earlier: &TokenUsage) -> TokenUsage {
+        Self::new(
+            self.prompt_tokens.saturating_sub(earlier.prompt_tokens),
+            self.completion_tokens
+                .saturating_sub(earlier.completion_tokens),
+        )
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+pub struct ProviderPricing {
+    #[serde(default)]
+    pub prompt_per_1k: f64,
+    #[serde(default)]
+    pub completion_per_1k: f64,
+}
+
+impl ProviderPricing {
+    pub fn cost(&self, usage: &TokenUsage) -> f64 {
+        usage.prompt_tokens as f64 / 1000.0 * self.prompt_per_1k
+            + usage.completion_tokens as f64 / 1000.0 * self.completion_per_1k
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct ProviderUsage {
+    pub requests: u64,
+    pub usage: TokenUsage,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct UsageTracker {
+    session: TokenUsage,
+    requests: u64,
+    providers: HashMap<String, ProviderUsage>,
+}
+
+impl UsageTracker {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn record(&mut self, provider: &str, usage: &TokenUsage) {
+        self.record_requests(provider, 1, usage);
+    }
+
+    /// Records `requests` requests to `provider` that used `usage` together
+    pub fn record_requests(&mut self, provider: &str, requests: u64, usage: &TokenUsage) {
+        self.session.add(usage);
+        self.requests += requests;
+
+        let entry = self.providers.entry(provider.to_string()).or_default();
+        entry.requests += requests;
+        entry.usage.add(usage);
+    }
+
+    /// The usage recorded since the tracker was `earlier`, such as that of
+    /// one turn
+    pub fn since(&self, earlier: &UsageTracker) -> UsageTracker {
+        let mut usage = UsageTracker::new();
+        for (provider, entry) in self.providers() {
+            let (requests, tokens) = match earlier.providers.get(provider) {
+                Some(before) => (
+                    entry.requests.saturating_sub(before.requests),
+                    entry.usage.since(&before.usage),
+                ),
+                None => (entry.requests, entry.usage),
+            };
+            if requests > 0 {
+                usage.record_requests(provider, requests, &tokens);
+            }
+        }
+        usage
+    }
+
+    pub fn session(&self) -> &TokenUsage {
+        &self.session
+    }
+
+    pub fn requests(&self) -> u64 {
+        self.requests
+    }
+
+    /// Returns per-provider totals sorted by provider name
+    pub fn providers(&self) -> Vec<(&String, &ProviderUsage)> {
+        let mut providers: Vec<_> = self.providers.iter().collect();
+        providers.sort_by(|a, b| a.0.cmp(b.0));
+        providers
+    }
+
+    /// Estimated cost of a provider's usage; providers without pricing are free
+    pub fn provider_cost(&self, provider: &str, pricing: &HashMap<String, ProviderPricing>) -> f64 {
+        match (self.providers.get(provider), pricing.get(provider)) {
+            (Some(entry), Some(price)) => price.cost(&entry.usage),
+            _ => 0.0,
+        }
+    }
+
+    pub fn total_cost(&self, pricing: &HashMap<String, ProviderPricing>) -> f64 {
+        self.providers
+            .keys()
+            .map(|provider| self.provider_cost(provider, pricing))
+            .sum()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_parse_usage() {
+        let openai = json!({
+            "choices": [],
+            "usage": {"prompt_tokens": 12, "completion_tokens": 30, "total_tokens": 42}
+        });
+        assert_eq!(
+            TokenUsage::from_openai(&openai),
+            Some(TokenUsage::new(12, 30))
+        );
+
+        let ollama = json!({"done": true, "prompt_eval_count": 26, "eval_count": 298});
+        assert_eq!(
+            TokenUsage::from_ollama(&ollama),
+            Some(TokenUsage::new(26, 298))
+        );
+
+        assert_eq!(TokenUsage::from_ollama(&json!({"done": false})), None);
+    }
+
+    #[test]
+    fn test_usage_tracker_costs() {
+        let mut tracker = UsageTracker::new();
+        tracker.record("openai", &TokenUsage::new(1000, 500));
+        tracker.record("openai", &TokenUsage::new(1000, 500));
+        tracker.record("ollama", &TokenUsage::new(200, 100));
+
+        assert_eq!(tracker.requests(), 3);
+        assert_eq!(tracker.session().total(), 3300);
+
+        let mut pricing = HashMap::new();
+        pricing.insert(
+            "openai".to_string(),
+            ProviderPricing {
+                prompt_per_1k: 0.01,
+                completion_per_1k: 0.03,
+            },
+        );
+
+        let openai_cost = tracker.provider_cost("openai", &pricing);
+        assert!((openai_cost - 0.05).abs() < 1e-9);
+        assert_eq!(tracker.provider_cost("ollama", &pricing), 0.0);
+        assert!((tracker.total_cost(&pricing) - 0.05).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_usage_since() {
+        let mut tracker = UsageTracker::new();
+        tracker.record("openai", &TokenUsage::new(1000, 500));
+        let before = tracker.clone();
+        tracker.record("openai", &TokenUsage::new(300, 20));
+        tracker.record("ollama", &TokenUsage::new(200, 100));
+
+        let turn = tracker.since(&before);
+        assert_eq!(turn.requests(), 2);
+        assert_eq!(turn.session(), &TokenUsage::new(500, 120));
+        let providers = turn.providers();
+        assert_eq!(providers.len(), 2);
+        assert_eq!(providers[1].0, "openai");
+        assert_eq!(providers[1].1.usage, TokenUsage::new(300, 20));
+        assert_eq!(tracker.since(&tracker).requests(), 0);
+    }
+}
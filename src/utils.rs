@@ -1,28 +1,46 @@
 //! Utility Module: Common helper functions for the MCP-ChatBot framework
 //!
 //! This module provides various utility functions including:
-//! - Color formatting for terminal output
+//! - Color formatting for terminal output, in the colors of a theme
+//!   (default, light, dark or monochrome) chosen in the `display` section
+//!   of `config.yaml` or by `MCP_CHATBOT_THEME`
 //! - Loading animations and visual feedback
 //! - Printing helpers with color support
 //! - Status checking functions
 //!
 //! Key Components:
 //! - `Color`: Enum for ANSI terminal colors
+//! - `Theme` and `DisplayConfig`: The palette the colors are drawn from
 //! - Print functions for formatted output
 //! - Animation functions for visual feedback
 //!
+//! Output is monochrome when `NO_COLOR` is set or stdout is not a terminal,
+//! and animations and progress bars are then left out, so output piped to a
+//! file is clean text.
+//!
 //! Author: arkSong <arksong2018@gmail.com>
 //! Version: 1.0.0
 //! License: MIT
 
-
-use log::{debug, error, info};
+use crossterm::event::{self, Event, KeyCode, KeyModifiers};
+use crossterm::terminal;
+use log::{debug, error, warn};
+use serde::{Deserialize, Serialize};
 use serde_json::Value;
-use std::io::{self, Write};
+use std::collections::HashMap;
+use std::env;
+use std::io::{self, IsTerminal, Write};
+use std::str::FromStr;
+use std::sync::atomic::{AtomicU8, Ordering};
 use std::sync::Arc;
 use std::thread;
 use std::time::Duration;
 use tokio::sync::Mutex;
+use tokio_util::sync::CancellationToken;
+
+use crate::config::Configuration;
+use crate::llm_client::{LLMProvider, ProviderHealth};
+use crate::usage::{ProviderPricing, UsageTracker};
 
 #[derive(Debug, Clone, Copy)]
 pub enum Color {
@@ -33,25 +51,196 @@ pub enum Color {
     Magenta,
     Cyan,
     White,
+    /// Secondary text, such as hints
+    Gray,
 }
 
 impl Color {
+    /// The escape code of the color in the current theme, empty when
+    /// colors are off
     pub fn as_ansi_code(&self) -> &str {
-        match self {
-            Color::Red => "\x1b[31m",
-            Color::Green => "\x1b[32m",
-            Color::Yellow => "\x1b[33m",
-            Color::Blue => "\x1b[34m",
-            Color::Magenta => "\x1b[35m",
-            Color::Cyan => "\x1b[36m",
-            Color::White => "\x1b[37m",
+        self.code_in(current_theme())
+    }
+
+    /// The escape code of the color in `theme`
+    pub fn code_in(&self, theme: Theme) -> &'static str {
+        match theme {
+            Theme::Default => match self {
+                Color::Red => "\x1b[31m",
+                Color::Green => "\x1b[32m",
+                Color::Yellow => "\x1b[33m",
+                Color::Blue => "\x1b[34m",
+                Color::Magenta => "\x1b[35m",
+                Color::Cyan => "\x1b[36m",
+                Color::White => "\x1b[37m",
+                Color::Gray => "\x1b[90m",
+            },
+            Theme::Dark => match self {
+                Color::Red => "\x1b[91m",
+                Color::Green => "\x1b[92m",
+                Color::Yellow => "\x1b[93m",
+                Color::Blue => "\x1b[94m",
+                Color::Magenta => "\x1b[95m",
+                Color::Cyan => "\x1b[96m",
+                Color::White => "\x1b[97m",
+                Color::Gray => "\x1b[37m",
+            },
+            // White text would vanish on a light background, and the usual
+            // yellow and cyan are hard to read on it
+            Theme::Light => match self {
+                Color::Red => "\x1b[38;5;124m",
+                Color::Green => "\x1b[38;5;28m",
+                Color::Yellow => "\x1b[38;5;130m",
+                Color::Blue => "\x1b[38;5;25m",
+                Color::Magenta => "\x1b[38;5;90m",
+                Color::Cyan => "\x1b[38;5;30m",
+                Color::White => "\x1b[30m",
+                Color::Gray => "\x1b[38;5;244m",
+            },
+            Theme::Monochrome => "",
         }
     }
 }
 
-pub fn print_colored(text: &str, color: Color) {
+/// A palette the terminal output is colored with
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Theme {
+    /// The terminal's own colors
+    #[default]
+    Default,
+    /// Darker colors that stay readable on a light background
+    Light,
+    /// Bright colors for a dark background
+    Dark,
+    /// No colors
+    Monochrome,
+}
+
+impl FromStr for Theme {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> anyhow::Result<Self> {
+        match s.trim().to_lowercase().as_str() {
+            "default" => Ok(Theme::Default),
+            "light" => Ok(Theme::Light),
+            "dark" => Ok(Theme::Dark),
+            "monochrome" => Ok(Theme::Monochrome),
+            other => Err(anyhow::Error::msg(format!(
+                "Unknown theme {}, use default, light, dark or monochrome",
+                other
+            ))),
+        }
+    }
+}
+
+impl Theme {
+    const ALL: [Theme; 4] = [Theme::Default, Theme::Light, Theme::Dark, Theme::Monochrome];
+
+    /// The theme to use: `from_env`, the value of `MCP_CHATBOT_THEME`, if
+    /// it names one, else `configured`, and no colors at all when
+    /// `no_color` is set or the output is not a `terminal`
+    pub fn choose(
+        configured: Theme,
+        from_env: Option<&str>,
+        no_color: bool,
+        terminal: bool,
+    ) -> Theme {
+        if no_color || !terminal {
+            return Theme::Monochrome;
+        }
+        match from_env.map(str::parse::<Theme>) {
+            Some(Ok(theme)) => theme,
+            Some(Err(e)) => {
+                warn!("Ignoring MCP_CHATBOT_THEME: {}", e);
+                configured
+            }
+            None => configured,
+        }
+    }
+}
+
+/// Settings of the terminal output, read from the `display` section of
+/// `config.yaml`
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct DisplayConfig {
+    pub theme: Theme,
+}
+
+impl DisplayConfig {
+    /// The `display` section of the configuration file `config_path`, or
+    /// the defaults if it cannot be read
+    pub fn from_config_file(config_path: &str) -> Self {
+        Configuration::new()
+            .load_display_config(config_path)
+            .unwrap_or_else(|e| {
+                warn!(
+                    "Failed to load the display configuration, using defaults: {}",
+                    e
+                );
+                Self::default()
+            })
+    }
+}
+
+/// Index in `Theme::ALL` of the theme the output is colored with
+static THEME: AtomicU8 = AtomicU8::new(0);
+
+pub fn set_theme(theme: Theme) {
+    let index = Theme::ALL.iter().position(|t| *t == theme).unwrap_or(0);
+    THEME.store(index as u8, Ordering::Relaxed);
+}
+
+pub fn current_theme() -> Theme {
+    Theme::ALL[THEME.load(Ordering::Relaxed) as usize % Theme::ALL.len()]
+}
+
+/// Colors the output with the theme of `config`, `MCP_CHATBOT_THEME`,
+/// `NO_COLOR` and whether stdout is a terminal allow, and returns it
+pub fn init_theme(config: &DisplayConfig) -> Theme {
+    let from_env = env::var("MCP_CHATBOT_THEME").ok();
+    let no_color = matches!(env::var("NO_COLOR"), Ok(value) if !value.is_empty());
+    let theme = Theme::choose(
+        config.theme,
+        from_env.as_deref(),
+        no_color,
+        io::stdout().is_terminal(),
+    );
+    set_theme(theme);
+    theme
+}
+
+/// Whether the output is colored
+pub fn colors_enabled() -> bool {
+    current_theme() != Theme::Monochrome
+}
+
+/// Whether stdout is a terminal, where lines may be redrawn
+pub fn is_terminal() -> bool {
+    io::stdout().is_terminal()
+}
+
+/// `text` in `color`, or as it is when colors are off
+pub fn colorize(text: &str, color: Color) -> String {
     let color_code = color.as_ansi_code();
-    print!("{}{}\x1b[0m", color_code, text);
+    if color_code.is_empty() {
+        text.to_string()
+    } else {
+        format!("{}{}\x1b[0m", color_code, text)
+    }
+}
+
+/// Erases the current line of the terminal, for animations and progress
+pub fn clear_line() {
+    if is_terminal() {
+        print!("\r\x1b[K");
+        io::stdout().flush().unwrap();
+    }
+}
+
+pub fn print_colored(text: &str, color: Color) {
+    print!("{}", colorize(text, color));
     io::stdout().flush().unwrap();
 }
 
@@ -80,14 +269,65 @@ pub async fn print_menu(current_ai_server: &str) {
 
     print_colored_ln("Available Commands:", Color::Cyan);
     print_colored_ln("/help - Show this help menu", Color::White);
-    print_colored_ln("/clear - Clear the screen", Color::White);
-    print_colored_ln("/usage - Show usage information", Color::White);
+    print_colored_ln(
+        "/clear - Clear the screen and forget the conversation",
+        Color::White,
+    );
+    print_colored_ln(
+        "/usage - Show token usage and estimated cost, with the largest turns",
+        Color::White,
+    );
+    print_colored_ln("/about - Show information about MCP-ChatBot", Color::White);
     print_colored_ln("/exit - Exit the program", Color::White);
-    print_colored_ln("/servers - List available servers", Color::White);
+    print_colored_ln(
+        "/servers - List the MCP servers and whether they are up",
+        Color::White,
+    );
     print_colored_ln("/tools - List available tools", Color::White);
     print_colored_ln("/resources - List available resources", Color::White);
+    print_colored_ln(
+        "/prompt [name] [key=value ...] - List prompts or send one",
+        Color::White,
+    );
+    print_colored_ln(
+        "/audit [tool=..] [session=current] [status=error] [since=..] [limit=..] - Show recorded tool calls",
+        Color::White,
+    );
+    print_colored_ln(
+        "/trace - Show the tool calls and answer of the last message",
+        Color::White,
+    );
+    print_colored_ln(
+        "/system set <text> | show | reset - Change the system prompt of this session",
+        Color::White,
+    );
+    print_colored_ln("/undo - Take back the last exchange", Color::White);
+    print_colored_ln(
+        "/retry [model] - Ask the last message again, optionally of another model",
+        Color::White,
+    );
+    print_colored_ln("/sessions [number] - List saved sessions", Color::White);
+    print_colored_ln("/resume <id> - Continue a saved session", Color::White);
+    print_colored_ln(
+        "/history-search <query> - Search past conversations",
+        Color::White,
+    );
+    print_colored_ln(
+        "/export <path> - Save the session as Markdown (.md) or JSONL (.jsonl)",
+        Color::White,
+    );
+    print_colored_ln(
+        "/import <path> - Replay a JSONL transcript into a new session",
+        Color::White,
+    );
     print_colored_ln("/debug - Toggle debug mode", Color::White);
     print_colored_ln("/ai - Switch between AI servers", Color::White);
+    print_colored_ln("/models - List available models", Color::White);
+    print_colored_ln("/model <name> - Switch to another model", Color::White);
+    print_colored_ln(
+        "/compare <message> - Compare answers of all configured providers",
+        Color::White,
+    );
     print_colored_ln(
         "/voice - Start voice input (press Enter to stop recording)",
         Color::White,
@@ -95,104 +335,341 @@ pub async fn print_menu(current_ai_server: &str) {
 
     print_colored_ln("\nShortcuts:", Color::Cyan);
     print_colored_ln("CTRL+K - Stop current inference", Color::White);
+    print_colored_ln("ALT+Enter - Start a new line of the message", Color::White);
+    print_colored_ln(
+        "\"\"\" - Open and close a message of several lines",
+        Color::White,
+    );
     print_colored_ln("CTRL+C - Exit the program", Color::White);
     print_colored_ln("\nCurrent AI Server: ", Color::Cyan);
     print_colored_ln(current_ai_server, Color::White);
     print_colored_ln("\nType your message or command:", Color::Cyan);
 }
 
-pub async fn check_ollama_status() -> bool {
-    info!("Starting Ollama server status check...");
-    let client = reqwest::Client::builder()
-        .timeout(Duration::from_secs(5))
-        .no_proxy()
-        .build()
-        .unwrap();
-
-    debug!("Checking Ollama server status at http://localhost:11434/api/tags");
-    match client.get("http://localhost:11434/api/tags").send().await {
-        Ok(response) => {
-            info!(
-                "Received response from Ollama server with status: {}",
-                response.status()
+/// Prints the readiness line of the startup banner
+pub fn print_provider_health(health: &anyhow::Result<ProviderHealth>) {
+    match health {
+        Ok(health) if health.model_available => {
+            let context = health
+                .context_size
+                .map(|size| format!(", context {} tokens", size))
+                .unwrap_or_default();
+            print_colored_ln(
+                &format!(
+                    "Status: ready (latency {} ms{})",
+                    health.latency.as_millis(),
+                    context
+                ),
+                Color::Green,
             );
-            if response.status().is_success() {
-                match response.text().await {
-                    Ok(body) => {
-                        debug!("Ollama server response body: {}", body);
-                        match serde_json::from_str::<serde_json::Value>(&body) {
-                            Ok(json) => {
-                                debug!("Parsed JSON response: {:?}", json);
-                                if let Some(models) = json.get("models") {
-                                    if let Some(models_array) = models.as_array() {
-                                        let has_model = models_array.iter().any(|model| {
-                                            model
-                                                .get("name")
-                                                .and_then(|n| n.as_str())
-                                                .map(|s| s == "llama3.2:latest")
-                                                .unwrap_or(false)
-                                        });
-                                        if has_model {
-                                            info!(
-                                                "Found llama3.2:latest model in available models"
-                                            );
-                                            return true;
-                                        }
-                                    }
-                                }
-                                error!("llama3.2:latest model not found in available models. Response: {}", body);
-                                false
-                            }
-                            Err(e) => {
-                                error!("Failed to parse JSON response: {}", e);
-                                false
-                            }
-                        }
-                    }
-                    Err(e) => {
-                        error!("Failed to read Ollama response body: {}", e);
-                        false
-                    }
-                }
-            } else {
-                error!(
-                    "Ollama server returned error status: {} with headers: {:?}",
-                    response.status(),
-                    response.headers()
-                );
-                false
+        }
+        Ok(health) => {
+            print_colored_ln(
+                &format!(
+                    "Status: model {} not available on {}",
+                    health.model,
+                    health.provider.name()
+                ),
+                Color::Yellow,
+            );
+            if health.provider == LLMProvider::Ollama {
+                print_colored_ln(&format!("Run: ollama pull {}", health.model), Color::Yellow);
             }
         }
         Err(e) => {
-            error!("Failed to connect to Ollama server: {}", e);
-            if e.is_timeout() {
-                error!("Connection timed out after 5 seconds");
-            } else if e.is_connect() {
-                error!("Connection refused - is Ollama server running?");
-            }
-            false
+            print_colored_ln(&format!("Status: unreachable ({})", e), Color::Red);
         }
     }
 }
 
-pub fn get_server_status(current_ai_server: &str, ollama_status: &bool) -> String {
-    if current_ai_server == "ollama" {
-        if *ollama_status {
-            "Ollama (running with llama3.2:latest)".to_string()
-        } else {
-            "Ollama (not running or model not found)".to_string()
+/// Prints recorded tool calls, oldest first
+pub fn print_audit_entries(entries: &[crate::audit_log::AuditEntry]) {
+    if entries.is_empty() {
+        print_colored_ln("  No tool calls recorded", Color::White);
+        return;
+    }
+
+    for entry in entries {
+        let (status, color) = match entry.status {
+            crate::audit_log::AuditStatus::Success => ("success", Color::Green),
+            crate::audit_log::AuditStatus::Error => ("error", Color::Red),
+        };
+        print_colored(
+            &format!(
+                "\n{} ",
+                entry
+                    .timestamp
+                    .with_timezone(&chrono::Local)
+                    .format("%Y-%m-%d %H:%M:%S")
+            ),
+            Color::White,
+        );
+        print_colored(&format!("{} ", entry.tool), Color::Yellow);
+        print_colored(status, color);
+        print_colored_ln(
+            &format!(
+                " {} ms (session {})",
+                entry.duration_ms,
+                entry.session_id.get(..8).unwrap_or(&entry.session_id)
+            ),
+            Color::White,
+        );
+        print_colored_ln(&format!("  Arguments: {}", entry.arguments), Color::White);
+        if let Some(error) = &entry.error {
+            print_colored_ln(&format!("  Error: {}", error), Color::Red);
         }
-    } else if current_ai_server == "openai" {
-        "OpenAI (running)".to_string()
-    } else {
-        format!(
-            "Deepseek ({})",
-            if current_ai_server == "sk-878a5319c7b14bc48109e19315361" {
-                "default"
-            } else {
-                "custom"
+    }
+    println!();
+}
+
+/// Prints saved sessions, latest first, marking the session `current`
+pub fn print_sessions(sessions: &[crate::conversation::StoredSession], current: &str) {
+    if sessions.is_empty() {
+        print_colored_ln("  No sessions saved", Color::White);
+        return;
+    }
+
+    for session in sessions {
+        let marker = if session.id == current { "*" } else { " " };
+        print_colored(
+            &format!("{} {} ", marker, session.id.get(..8).unwrap_or(&session.id)),
+            Color::Yellow,
+        );
+        print_colored(
+            &format!(
+                "{} ",
+                session
+                    .updated_at
+                    .with_timezone(&chrono::Local)
+                    .format("%Y-%m-%d %H:%M")
+            ),
+            Color::White,
+        );
+        print_colored_ln(
+            &format!("({} turns, {})", session.turns, session.provider),
+            Color::Cyan,
+        );
+        print_colored_ln(&format!("    {}", session.title), Color::White);
+    }
+    println!();
+}
+
+/// Prints the saved messages matching a `/history-search` by keyword and
+/// the past exchanges similar to it, with the sessions they belong to
+pub fn print_history_matches(
+    matches: &[crate::conversation::MessageMatch],
+    similar: &[crate::long_term_memory::RecalledExchange],
+) {
+    let short = |id: &str| id.get(..8).unwrap_or(id).to_string();
+
+    print_colored_ln("\nMessages with these words:", Color::Cyan);
+    if matches.is_empty() {
+        print_colored_ln("  None found", Color::White);
+    }
+    for found in matches {
+        print_colored(&format!("  {} ", short(&found.session_id)), Color::Yellow);
+        print_colored(
+            &format!(
+                "{} turn {} {}: ",
+                found
+                    .created_at
+                    .with_timezone(&chrono::Local)
+                    .format("%Y-%m-%d %H:%M"),
+                found.turn + 1,
+                found.role
+            ),
+            Color::White,
+        );
+        print_colored_ln(&found.snippet, Color::Green);
+    }
+
+    print_colored_ln("\nSimilar exchanges:", Color::Cyan);
+    if similar.is_empty() {
+        print_colored_ln("  None found", Color::White);
+    }
+    for exchange in similar {
+        let date = chrono::DateTime::from_timestamp(exchange.timestamp, 0)
+            .map(|time| {
+                time.with_timezone(&chrono::Local)
+                    .format("%Y-%m-%d %H:%M")
+                    .to_string()
+            })
+            .unwrap_or_default();
+        print_colored(
+            &format!("  {} ", short(&exchange.session_id)),
+            Color::Yellow,
+        );
+        print_colored_ln(
+            &format!("{} (similarity {:.2})", date, exchange.score),
+            Color::White,
+        );
+        print_colored_ln(&format!("    User: {}", exchange.input), Color::White);
+        print_colored_ln(&format!("    Assistant: {}", exchange.answer), Color::Green);
+    }
+
+    if !matches.is_empty() || !similar.is_empty() {
+        print_colored_ln(
+            "\nUse /resume <session> to continue one of these sessions",
+            Color::Yellow,
+        );
+    }
+    println!();
+}
+
+/// Prints the steps of the last turn: each tool call with its outcome, why
+/// the calls ended early if they did, and the answer
+pub fn print_trace(trace: &crate::agent::AgentTrace) {
+    print_colored_ln(
+        &format!(
+            "\nTrace of \"{}\" ({}):",
+            trace.input,
+            trace.started.format("%Y-%m-%d %H:%M:%S")
+        ),
+        Color::Cyan,
+    );
+    let mut call = 0;
+    for step in &trace.steps {
+        match step {
+            crate::agent::TraceStep::ToolCall {
+                tool,
+                arguments,
+                result,
+            } => {
+                call += 1;
+                print_colored(&format!("\n{}. {} ", call, tool), Color::Yellow);
+                let (status, color) = match result.is_error {
+                    false => ("success", Color::Green),
+                    true => ("error", Color::Red),
+                };
+                print_colored(status, color);
+                print_colored_ln(&format!(" {} ms", result.duration_ms), Color::White);
+                print_colored_ln(&format!("  Arguments: {}", arguments), Color::White);
+                print_colored_ln(&format!("  Result: {}", result.content), color);
             }
-        )
+            crate::agent::TraceStep::Stopped { reason } => {
+                print_colored_ln(&format!("\nStopped: {}", reason), Color::Yellow);
+            }
+            crate::agent::TraceStep::Answer { text } => {
+                print_colored_ln(&format!("\nAnswer: {}", text), Color::White);
+            }
+        }
+    }
+    print_colored_ln(
+        &format!(
+            "\n{} LLM requests, {} tokens",
+            trace.llm_requests, trace.tokens
+        ),
+        Color::White,
+    );
+}
+
+pub fn print_usage(tracker: &UsageTracker, pricing: &HashMap<String, ProviderPricing>) {
+    print_colored_ln("\nToken Usage (since the chat started):", Color::Cyan);
+    if tracker.requests() == 0 {
+        print_colored_ln("No usage reported yet", Color::Yellow);
+        return;
+    }
+
+    let session = tracker.session();
+    print_colored_ln(
+        &format!("Requests:          {}", tracker.requests()),
+        Color::White,
+    );
+    print_colored_ln(
+        &format!("Prompt tokens:     {}", session.prompt_tokens),
+        Color::White,
+    );
+    print_colored_ln(
+        &format!("Completion tokens: {}", session.completion_tokens),
+        Color::White,
+    );
+    print_colored_ln(
+        &format!("Total tokens:      {}", session.total()),
+        Color::White,
+    );
+    print_provider_usage(tracker, pricing);
+}
+
+/// Prints the requests, tokens and estimated cost of each provider
+fn print_provider_usage(tracker: &UsageTracker, pricing: &HashMap<String, ProviderPricing>) {
+    print_colored_ln("\nBy provider:", Color::Cyan);
+    print_colored_ln(
+        &format!(
+            "{:<10} {:>8} {:>10} {:>12} {:>10}",
+            "Provider", "Requests", "Prompt", "Completion", "Cost ($)"
+        ),
+        Color::Yellow,
+    );
+    for (provider, entry) in tracker.providers() {
+        print_colored_ln(
+            &format!(
+                "{:<10} {:>8} {:>10} {:>12} {:>10.4}",
+                provider,
+                entry.requests,
+                entry.usage.prompt_tokens,
+                entry.usage.completion_tokens,
+                tracker.provider_cost(provider, pricing)
+            ),
+            Color::White,
+        );
+    }
+
+    print_colored_ln(
+        &format!(
+            "\nEstimated total cost: ${:.4}",
+            tracker.total_cost(pricing)
+        ),
+        Color::Green,
+    );
+}
+
+/// Prints the tokens the saved conversation used: totals, per provider and
+/// its `largest` costliest turns
+pub fn print_session_usage(
+    usage: &crate::conversation::SessionUsage,
+    pricing: &HashMap<String, ProviderPricing>,
+    largest: usize,
+) {
+    print_colored_ln("\nToken Usage (this conversation):", Color::Cyan);
+    if usage.turns.is_empty() {
+        print_colored_ln("No usage saved for this conversation yet", Color::Yellow);
+        return;
+    }
+
+    let totals = usage.totals.session();
+    print_colored_ln(
+        &format!(
+            "{} turns, {} requests, {} tokens ({} prompt, {} completion)",
+            usage.turns.len(),
+            usage.totals.requests(),
+            totals.total(),
+            totals.prompt_tokens,
+            totals.completion_tokens
+        ),
+        Color::White,
+    );
+    print_provider_usage(&usage.totals, pricing);
+
+    print_colored_ln("\nLargest turns:", Color::Cyan);
+    print_colored_ln(
+        &format!(
+            "{:<6} {:>8} {:>10} {:>12}  {}",
+            "Turn", "Tokens", "Prompt", "Completion", "Message"
+        ),
+        Color::Yellow,
+    );
+    for turn in usage.largest_turns(largest) {
+        print_colored_ln(
+            &format!(
+                "{:<6} {:>8} {:>10} {:>12}  {}",
+                turn.turn + 1,
+                turn.usage.total(),
+                turn.usage.prompt_tokens,
+                turn.usage.completion_tokens,
+                turn.input
+            ),
+            Color::White,
+        );
     }
 }
 
@@ -211,11 +688,21 @@ pub fn print_about() {
     print_colored_ln("- DeepSeek LLM integration", Color::White);
     print_colored_ln("\nAvailable Commands:", Color::Yellow);
     print_colored_ln("/help - Show help menu", Color::White);
-    print_colored_ln("/servers - List available servers", Color::White);
+    print_colored_ln(
+        "/servers - List the MCP servers and whether they are up",
+        Color::White,
+    );
     print_colored_ln("/tools - List available tools", Color::White);
     print_colored_ln("/resources - List available resources", Color::White);
-    print_colored_ln("/clear - Clear the screen", Color::White);
-    print_colored_ln("/usage - Show this information", Color::White);
+    print_colored_ln(
+        "/clear - Clear the screen and forget the conversation",
+        Color::White,
+    );
+    print_colored_ln(
+        "/usage - Show token usage and estimated cost, with the largest turns",
+        Color::White,
+    );
+    print_colored_ln("/about - Show this information", Color::White);
     print_colored_ln("/exit - Exit the program", Color::White);
     println!();
 
@@ -383,6 +870,36 @@ pub fn print_resources(resources: &[crate::protocol::ResourceSchema]) {
     println!();
 }
 
+pub fn print_prompts(prompts: &[crate::protocol::PromptSchema]) {
+    if prompts.is_empty() {
+        print_colored_ln("  No prompts available", Color::White);
+        return;
+    }
+
+    for prompt in prompts {
+        print_colored_ln(&format!("\nPrompt: {}", prompt.name), Color::Yellow);
+        print_colored_ln(
+            &format!("  Description: {}", prompt.description),
+            Color::White,
+        );
+        if !prompt.arguments.is_empty() {
+            print_colored_ln("  Arguments:", Color::White);
+            for argument in &prompt.arguments {
+                print_colored_ln(
+                    &format!(
+                        "    - {}: {}{}",
+                        argument.name,
+                        argument.description,
+                        if argument.required { " (required)" } else { "" }
+                    ),
+                    Color::White,
+                );
+            }
+        }
+    }
+    println!();
+}
+
 pub fn print_mcp_servers(config: &Value) {
     print_colored_ln("\nAvailable MCP Servers:", Color::Cyan);
     if let Some(servers) = config.get("mcpServers") {
@@ -407,6 +924,37 @@ pub fn print_mcp_servers(config: &Value) {
     println!();
 }
 
+pub fn print_server_status(status: &[crate::server_manager::ServerStatus]) {
+    print_colored_ln("\nMCP Servers:", Color::Cyan);
+    if status.is_empty() {
+        print_colored_ln("  No servers configured", Color::White);
+        return;
+    }
+
+    for server in status {
+        let color = match server.state {
+            crate::server_manager::ServerState::Up => Color::Green,
+            crate::server_manager::ServerState::Down => Color::Yellow,
+            crate::server_manager::ServerState::Failed => Color::Red,
+        };
+        print_colored("● ", color);
+        print_colored(&format!("{} ", server.name), Color::Yellow);
+        print_colored(&format!("{:<6} ", server.state.as_str()), color);
+        print_colored(&server.command, Color::White);
+        if server.restarts > 0 {
+            print_colored(
+                &format!(" (restarted {} times)", server.restarts),
+                Color::White,
+            );
+        }
+        println!();
+        if let Some(error) = &server.last_error {
+            print_colored_ln(&format!("  Last error: {}", error), Color::Red);
+        }
+    }
+    println!();
+}
+
 pub fn print_tools(tools: &[crate::protocol::ToolSchema]) {
     if tools.is_empty() {
         print_colored_ln("  No tools available", Color::White);
@@ -465,6 +1013,10 @@ pub fn print_tools(tools: &[crate::protocol::ToolSchema]) {
 }
 
 pub fn typing_animation(text: &str, delay_ms: u64) {
+    if !is_terminal() {
+        println!("{}", text);
+        return;
+    }
     for c in text.chars() {
         print!("{}", c);
         io::stdout().flush().unwrap();
@@ -473,6 +1025,82 @@ pub fn typing_animation(text: &str, delay_ms: u64) {
     println!();
 }
 
+/// Splits `text` into lines of at most `width` characters, breaking on
+/// whitespace where possible
+pub fn wrap_text(text: &str, width: usize) -> Vec<String> {
+    let width = width.max(1);
+    let mut lines = Vec::new();
+
+    for paragraph in text.lines() {
+        let mut line = String::new();
+        for word in paragraph.split_whitespace() {
+            let mut word: Vec<char> = word.chars().collect();
+            let line_len = line.chars().count();
+
+            if line_len > 0 && line_len + 1 + word.len() > width {
+                lines.push(std::mem::take(&mut line));
+            }
+            // Words longer than a full line are split hard
+            while word.len() > width {
+                let rest = word.split_off(width);
+                lines.push(word.into_iter().collect());
+                word = rest;
+            }
+            if !line.is_empty() {
+                line.push(' ');
+            }
+            line.extend(word);
+        }
+        lines.push(line);
+    }
+
+    lines
+}
+
+/// Prints each `(title, text)` pair as a column next to the others,
+/// sized to the terminal width
+pub fn print_side_by_side(columns: &[(String, String)]) {
+    if columns.is_empty() {
+        return;
+    }
+
+    let term_width = terminal::size().map(|(w, _)| w as usize).unwrap_or(120);
+    let separator = " │ ";
+    let col_width =
+        (term_width.saturating_sub(separator.len() * (columns.len() - 1)) / columns.len()).max(10);
+
+    let wrapped: Vec<Vec<String>> = columns
+        .iter()
+        .map(|(_, text)| wrap_text(text, col_width))
+        .collect();
+    let rows = wrapped.iter().map(|lines| lines.len()).max().unwrap_or(0);
+
+    let header: Vec<String> = columns
+        .iter()
+        .map(|(title, _)| {
+            let title: String = title.chars().take(col_width).collect();
+            format!("{:<width$}", title, width = col_width)
+        })
+        .collect();
+    print_colored_ln(&header.join(separator), Color::Cyan);
+    print_colored_ln(
+        &vec!["─".repeat(col_width); columns.len()].join("─┼─"),
+        Color::Cyan,
+    );
+
+    for row in 0..rows {
+        let cells: Vec<String> = wrapped
+            .iter()
+            .map(|lines| {
+                let cell = lines.get(row).map(|s| s.as_str()).unwrap_or("");
+                let padding = col_width.saturating_sub(cell.chars().count());
+                format!("{}{}", cell, " ".repeat(padding))
+            })
+            .collect();
+        println!("{}", cells.join(separator).trim_end());
+    }
+}
+
 pub fn print_download_animation(text: &str, duration: Duration) {
     let frames = ["⠋", "⠙", "⠹", "⠸", "⠼", "⠴", "⠦", "⠧", "⠇", "⠏"];
     let mut i = 0;
@@ -502,6 +1130,27 @@ pub fn print_download_progress(text: &str, current: usize, total: usize) {
     io::stdout().flush().unwrap();
 }
 
+/// Draws a single-line progress bar, e.g. `[=========>          ] 12/40 label`
+pub fn print_progress_bar(label: &str, current: usize, total: usize) {
+    const WIDTH: usize = 30;
+    if !is_terminal() {
+        return;
+    }
+    let filled = if total == 0 {
+        WIDTH
+    } else {
+        current * WIDTH / total
+    };
+    let bar = format!(
+        "{}{}{}",
+        "=".repeat(filled.saturating_sub(1)),
+        if filled > 0 { ">" } else { "" },
+        " ".repeat(WIDTH - filled)
+    );
+    print!("\r\x1b[K[{}] {}/{} {}", bar, current, total, label);
+    io::stdout().flush().unwrap();
+}
+
 pub fn print_waiting_animation(text: &str, duration: Duration) {
     let frames = ["⠋", "⠙", "⠹", "⠸", "⠼", "⠴", "⠦", "⠧", "⠇", "⠏"];
     let dots = ["", ".", "..", "..."];
@@ -537,16 +1186,19 @@ pub async fn print_bot_thinking_continuous(stop_signal: Arc<Mutex<bool>>) {
     let mut i = 0;
     let mut dot_index = 0;
 
+    let animate = is_terminal();
+
     loop {
         if *stop_signal.lock().await {
             // 清理当前行
-            print!("\r\x1b[K");
-            io::stdout().flush().unwrap();
+            clear_line();
             break;
         }
 
-        print!("\rBOT: Thinking{} {}", frames[i], dots[dot_index]);
-        io::stdout().flush().unwrap();
+        if animate {
+            print!("\rBOT: Thinking{} {}", frames[i], dots[dot_index]);
+            io::stdout().flush().unwrap();
+        }
 
         i = (i + 1) % frames.len();
         dot_index = (dot_index + 1) % dots.len();
@@ -555,6 +1207,40 @@ pub async fn print_bot_thinking_continuous(stop_signal: Arc<Mutex<bool>>) {
     }
 }
 
+/// Watches the terminal for CTRL+K while a request is in flight and cancels
+/// `cancel` when it is pressed. Stops listening once `done` is cancelled.
+pub fn spawn_cancel_key_listener(
+    cancel: CancellationToken,
+    done: CancellationToken,
+) -> tokio::task::JoinHandle<()> {
+    tokio::task::spawn_blocking(move || {
+        if let Err(e) = terminal::enable_raw_mode() {
+            debug!("Cannot enable raw mode, CTRL+K disabled: {}", e);
+            return;
+        }
+
+        while !done.is_cancelled() && !cancel.is_cancelled() {
+            match event::poll(Duration::from_millis(50)) {
+                Ok(true) => {
+                    if let Ok(Event::Key(key)) = event::read() {
+                        let ctrl = key.modifiers.contains(KeyModifiers::CONTROL);
+                        if ctrl && matches!(key.code, KeyCode::Char('k') | KeyCode::Char('c')) {
+                            cancel.cancel();
+                        }
+                    }
+                }
+                Ok(false) => {}
+                Err(e) => {
+                    error!("Failed to read terminal event: {}", e);
+                    break;
+                }
+            }
+        }
+
+        let _ = terminal::disable_raw_mode();
+    })
+}
+
 pub async fn print_recording_animation(stop_signal: Arc<Mutex<bool>>) {
     let frames = vec!["🎤", "🎙️", "🎧", "🎵"];
     let dots = vec!["", ".", "..", "..."];
@@ -563,8 +1249,7 @@ pub async fn print_recording_animation(stop_signal: Arc<Mutex<bool>>) {
 
     loop {
         if *stop_signal.lock().await {
-            print!("\r\x1b[K");
-            io::stdout().flush().unwrap();
+            clear_line();
             break;
         }
 
@@ -577,3 +1262,49 @@ pub async fn print_recording_animation(stop_signal: Arc<Mutex<bool>>) {
         tokio::time::sleep(Duration::from_millis(200)).await;
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_wrap_text() {
+        assert_eq!(
+            wrap_text("the quick brown fox", 10),
+            vec!["the quick", "brown fox"]
+        );
+        assert_eq!(wrap_text("abcdefghij", 4), vec!["abcd", "efgh", "ij"]);
+        assert_eq!(wrap_text("one\n\ntwo", 10), vec!["one", "", "two"]);
+    }
+
+    #[test]
+    fn test_theme() {
+        assert_eq!("Light".parse::<Theme>().unwrap(), Theme::Light);
+        assert!("sepia".parse::<Theme>().is_err());
+
+        assert_eq!(Theme::choose(Theme::Dark, None, false, true), Theme::Dark);
+        assert_eq!(
+            Theme::choose(Theme::Dark, Some("light"), false, true),
+            Theme::Light
+        );
+        assert_eq!(
+            Theme::choose(Theme::Dark, Some("sepia"), false, true),
+            Theme::Dark
+        );
+        // NO_COLOR and output to a file or pipe turn colors off
+        assert_eq!(
+            Theme::choose(Theme::Dark, Some("light"), true, true),
+            Theme::Monochrome
+        );
+        assert_eq!(
+            Theme::choose(Theme::Default, None, false, false),
+            Theme::Monochrome
+        );
+
+        assert_eq!(Color::White.code_in(Theme::Default), "\x1b[37m");
+        assert_eq!(Color::White.code_in(Theme::Light), "\x1b[30m");
+        assert_eq!(Color::Red.code_in(Theme::Monochrome), "");
+        let config: DisplayConfig = serde_yaml::from_str("theme: monochrome").unwrap();
+        assert_eq!(config.theme, Theme::Monochrome);
+    }
+}
@@ -0,0 +1,1522 @@
+//! Vector Backend Module: Storage abstraction shared by all vector consumers
+//!
+//! This module provides:
+//! - A common `VectorBackend` trait for collections of embeddings with JSON payloads
+//! - The Qdrant implementation used by `RagServer`, `VectorStore` and `VectorDBClient`
+//! - Conversion between JSON payloads and Qdrant values in one place
+//!
+//! Key Components:
+//! - `VectorBackend`: Collection management, upsert, search, get, delete and scroll
+//! - `QdrantBackend`: Qdrant client built from a `QdrantConfig`
+//! - `PayloadFilter`: Keyword filters on payload fields
+//! - `BackendKind`: Config switch between Qdrant, the in-process `LocalBackend`
+//!   and LanceDB
+//! - `RetryingBackend`: Retries calls with backoff while the backend is unreachable,
+//!   as its `RetryPolicy` says
+//!
+//! Author: arkSong <arksong2018@gmail.com>
+//! Version: 1.0.0
+//! License: MIT
+
+use anyhow::{Context, Error, Result};
+use async_trait::async_trait;
+use dashmap::DashMap;
+use log::info;
+use qdrant_client::qdrant::{
+    point_id::PointIdOptions, quantization_config, value::Kind, vectors::VectorsOptions,
+    vectors_config::Config, CompressionRatio, Condition, CountPointsBuilder,
+    CreateCollectionBuilder, DeletePointsBuilder, Distance, Filter, GetPointsBuilder,
+    HnswConfigDiff, ListValue, OptimizersConfigDiff, PointId, PointStruct, PointsIdsList,
+    ProductQuantization, QuantizationConfig, QuantizationType, Range, ScalarQuantization,
+    ScrollPointsBuilder, SearchPointsBuilder, SetPayloadPointsBuilder, Struct, UpsertPointsBuilder,
+    Value, VectorParams, VectorParamsMap, Vectors, VectorsConfig, WalConfigDiff,
+};
+use qdrant_client::Qdrant;
+use serde::{Deserialize, Serialize};
+use serde_json::Value as JsonValue;
+use std::collections::HashMap;
+use std::future::Future;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::time::Duration;
+
+pub use crate::retry::RetryPolicy;
+
+/// Payload stored next to each vector
+pub type Payload = serde_json::Map<String, JsonValue>;
+
+/// Payload key holding the caller's id when it is neither a UUID nor a number,
+/// which are the only point ids Qdrant accepts
+const ORIGINAL_ID_KEY: &str = "_id";
+
+#[derive(Debug, thiserror::Error)]
+pub enum VectorStoreError {
+    #[error("Collection error: {0}")]
+    CollectionError(String),
+    #[error("Storage error: {0}")]
+    StorageError(String),
+    #[error("Search error: {0}")]
+    SearchError(String),
+    #[error("Invalid dimension: expected {expected}, got {actual}")]
+    InvalidDimension { expected: u64, actual: u64 },
+}
+
+/// Name of the main vector in collections that also hold named vectors
+pub const CONTENT_VECTOR: &str = "content";
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct VectorPoint {
+    pub id: String,
+    /// Empty for points returned by `scroll`
+    pub vector: Vec<f32>,
+    /// Additional vectors, e.g. "title", for collections created with
+    /// `create_collection_with_vectors`
+    pub named_vectors: HashMap<String, Vec<f32>>,
+    pub payload: Payload,
+}
+
+impl VectorPoint {
+    pub fn new(id: String, vector: Vec<f32>, payload: Payload) -> Self {
+        Self {
+            id,
+            vector,
+            named_vectors: HashMap::new(),
+            payload,
+        }
+    }
+
+    pub fn with_named_vector(mut self, name: &str, vector: Vec<f32>) -> Self {
+        self.named_vectors.insert(name.to_string(), vector);
+        self
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ScoredPoint {
+    pub id: String,
+    pub score: f32,
+    pub payload: Payload,
+    /// Only set when the query asked for vectors
+    pub vector: Option<Vec<f32>>,
+}
+
+/// Payload conditions that must all hold. Each condition matches a field
+/// against any of a list of keywords, or bounds a numeric field.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct PayloadFilter {
+    pub must: Vec<(String, Vec<String>)>,
+    pub ranges: Vec<PayloadRange>,
+}
+
+/// Inclusive bounds on a numeric payload field
+#[derive(Debug, Clone, PartialEq)]
+pub struct PayloadRange {
+    pub key: String,
+    pub gte: Option<f64>,
+    pub lte: Option<f64>,
+}
+
+impl PayloadFilter {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Requires `key` to equal `value`
+    pub fn matches(self, key: &str, value: &str) -> Self {
+        self.matches_any(key, vec![value.to_string()])
+    }
+
+    /// Requires `key` to equal one of `values`
+    pub fn matches_any(mut self, key: &str, values: Vec<String>) -> Self {
+        self.must.push((key.to_string(), values));
+        self
+    }
+
+    /// Requires the numeric field `key` to lie within `gte..=lte`
+    pub fn range(mut self, key: &str, gte: Option<f64>, lte: Option<f64>) -> Self {
+        self.ranges.push(PayloadRange {
+            key: key.to_string(),
+            gte,
+            lte,
+        });
+        self
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.must.is_empty() && self.ranges.is_empty()
+    }
+
+    /// Evaluates the filter against a payload, for backends without native filtering
+    pub fn accepts(&self, payload: &Payload) -> bool {
+        let keywords_match = self
+            .must
+            .iter()
+            .all(|(key, values)| match payload.get(key) {
+                Some(JsonValue::String(s)) => values.contains(s),
+                Some(other) => values.contains(&other.to_string()),
+                None => false,
+            });
+        keywords_match
+            && self.ranges.iter().all(|range| {
+                match payload.get(&range.key).and_then(JsonValue::as_f64) {
+                    Some(value) => {
+                        !matches!(range.gte, Some(gte) if value < gte)
+                            && !matches!(range.lte, Some(lte) if value > lte)
+                    }
+                    None => false,
+                }
+            })
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct SearchQuery {
+    pub vector: Vec<f32>,
+    pub limit: usize,
+    pub filter: Option<PayloadFilter>,
+    /// Drops points scoring below this value
+    pub score_threshold: Option<f32>,
+    pub with_vectors: bool,
+    /// Named vector to compare against; `None` uses the main vector
+    pub using: Option<String>,
+}
+
+impl SearchQuery {
+    pub fn new(vector: Vec<f32>, limit: usize) -> Self {
+        Self {
+            vector,
+            limit,
+            filter: None,
+            score_threshold: None,
+            with_vectors: false,
+            using: None,
+        }
+    }
+
+    /// Searches the named vector `name` instead of the main vector
+    pub fn using(mut self, name: &str) -> Self {
+        self.using = Some(name.to_string());
+        self
+    }
+
+    pub fn filter(mut self, filter: PayloadFilter) -> Self {
+        if !filter.is_empty() {
+            self.filter = Some(filter);
+        }
+        self
+    }
+
+    pub fn score_threshold(mut self, threshold: f32) -> Self {
+        self.score_threshold = Some(threshold);
+        self
+    }
+
+    pub fn with_vectors(mut self, with_vectors: bool) -> Self {
+        self.with_vectors = with_vectors;
+        self
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum DistanceMetric {
+    #[default]
+    Cosine,
+    Euclid,
+    Dot,
+}
+
+impl FromStr for DistanceMetric {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.to_lowercase().as_str() {
+            "cosine" => Ok(DistanceMetric::Cosine),
+            "euclid" | "euclidean" => Ok(DistanceMetric::Euclid),
+            "dot" => Ok(DistanceMetric::Dot),
+            _ => Err(Error::msg(format!(
+                "Unknown distance metric: {} (expected Cosine, Euclid or Dot)",
+                s
+            ))),
+        }
+    }
+}
+
+/// Where vectors are stored
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum BackendKind {
+    /// A Qdrant server
+    #[default]
+    Qdrant,
+    /// In-process flat index saved to disk, see `LocalBackend`
+    Local,
+    /// Embedded LanceDB database, see `LanceDbBackend` (feature `lancedb`)
+    LanceDb,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct CollectionInfo {
+    pub dimension: usize,
+    pub distance: DistanceMetric,
+    pub points_count: u64,
+    /// Named vectors besides the main one, e.g. "title"
+    pub vector_names: Vec<String>,
+}
+
+#[async_trait]
+pub trait VectorBackend: Send + Sync {
+    /// Backend name and location, used in logs
+    fn name(&self) -> String;
+
+    /// Checks that the backend is reachable and returns a short status,
+    /// e.g. the server version
+    async fn health(&self) -> Result<String> {
+        Ok("ok".to_string())
+    }
+
+    /// Whether `error` means the backend was unreachable rather than that
+    /// the request was wrong, so the call is worth retrying
+    fn is_transient(&self, _error: &Error) -> bool {
+        false
+    }
+
+    /// Returns `None` when the collection does not exist
+    async fn collection_info(&self, collection: &str) -> Result<Option<CollectionInfo>>;
+
+    async fn create_collection(&self, collection: &str, dimension: usize) -> Result<()>;
+
+    /// Creates a collection whose points also carry the named vectors
+    /// `names`, all of `dimension`
+    async fn create_collection_with_vectors(
+        &self,
+        collection: &str,
+        dimension: usize,
+        names: &[String],
+    ) -> Result<()> {
+        if names.is_empty() {
+            return self.create_collection(collection, dimension).await;
+        }
+        Err(Error::msg(format!(
+            "{} does not support named vectors",
+            self.name()
+        )))
+    }
+
+    /// Inserts the points, replacing points with the same ids
+    async fn upsert(&self, collection: &str, points: Vec<VectorPoint>) -> Result<()>;
+
+    /// Nearest points to `query.vector`, best match first
+    async fn search(&self, collection: &str, query: SearchQuery) -> Result<Vec<ScoredPoint>>;
+
+    /// Points with the given ids; unknown ids are left out
+    async fn get(&self, collection: &str, ids: &[String]) -> Result<Vec<VectorPoint>>;
+
+    async fn delete(&self, collection: &str, ids: &[String]) -> Result<()>;
+
+    /// Merges `patch` into the payload of point `id`, keeping its vector.
+    /// The default reads the point and writes it back.
+    async fn update_payload(&self, collection: &str, id: &str, patch: Payload) -> Result<()> {
+        let mut point = self
+            .get(collection, &[id.to_string()])
+            .await?
+            .into_iter()
+            .next()
+            .ok_or_else(|| Error::msg(format!("No point {} in {}", id, collection)))?;
+        point.payload.extend(patch);
+        self.upsert(collection, vec![point]).await
+    }
+
+    /// Number of points matching `filter`. The default scrolls through them.
+    async fn count(&self, collection: &str, filter: Option<PayloadFilter>) -> Result<u64> {
+        let mut count = 0;
+        let mut offset = None;
+        loop {
+            let (points, next) = self.scroll(collection, filter.clone(), offset, 256).await?;
+            count += points.len() as u64;
+            match next {
+                Some(next) => offset = Some(next),
+                None => return Ok(count),
+            }
+        }
+    }
+
+    /// Saves a snapshot of the collection into `dir` and returns its path
+    async fn create_snapshot(&self, collection: &str, _dir: &Path) -> Result<PathBuf> {
+        Err(Error::msg(format!(
+            "{} does not support snapshots of {}",
+            self.name(),
+            collection
+        )))
+    }
+
+    /// Replaces the collection with the snapshot stored at `path`
+    async fn restore_snapshot(&self, collection: &str, _path: &Path) -> Result<()> {
+        Err(Error::msg(format!(
+            "{} does not support restoring {} from a snapshot",
+            self.name(),
+            collection
+        )))
+    }
+
+    /// One page of points matching `filter`, without vectors, and the offset
+    /// of the next page
+    async fn scroll(
+        &self,
+        collection: &str,
+        filter: Option<PayloadFilter>,
+        offset: Option<String>,
+        limit: usize,
+    ) -> Result<(Vec<VectorPoint>, Option<String>)>;
+
+    /// Creates the collection if needed, or checks that the existing one
+    /// holds vectors of `dimension`
+    async fn ensure_collection(&self, collection: &str, dimension: usize) -> Result<()> {
+        match self.collection_info(collection).await? {
+            None => {
+                info!(
+                    "Creating collection {} for {}-dimensional vectors",
+                    collection, dimension
+                );
+                self.create_collection(collection, dimension).await
+            }
+            Some(info) if info.dimension != dimension => {
+                Err(Error::new(VectorStoreError::InvalidDimension {
+                    expected: dimension as u64,
+                    actual: info.dimension as u64,
+                })
+                .context(format!(
+                    "Collection '{}' was created for {}-dimensional vectors",
+                    collection, info.dimension
+                )))
+            }
+            Some(_) => Ok(()),
+        }
+    }
+}
+
+/// Wraps a backend so that calls failing with transient errors, such as a
+/// restarted Qdrant container, are retried with exponential backoff
+pub struct RetryingBackend<B> {
+    inner: B,
+    policy: RetryPolicy,
+}
+
+impl<B: VectorBackend> RetryingBackend<B> {
+    pub fn new(inner: B, policy: RetryPolicy) -> Self {
+        Self { inner, policy }
+    }
+
+    pub fn inner(&self) -> &B {
+        &self.inner
+    }
+
+    async fn retry<T, F, Fut>(&self, operation: &str, call: F) -> Result<T>
+    where
+        F: FnMut() -> Fut + Send,
+        Fut: Future<Output = Result<T>> + Send,
+        T: Send,
+    {
+        let what = format!("{} on {}", operation, self.inner.name());
+        self.policy
+            .run(&what, |error| self.inner.is_transient(error), call)
+            .await
+    }
+}
+
+#[async_trait]
+impl<B: VectorBackend> VectorBackend for RetryingBackend<B> {
+    fn name(&self) -> String {
+        self.inner.name()
+    }
+
+    /// Not retried, so that an unreachable backend is reported right away
+    async fn health(&self) -> Result<String> {
+        self.inner.health().await
+    }
+
+    fn is_transient(&self, error: &Error) -> bool {
+        self.inner.is_transient(error)
+    }
+
+    async fn collection_info(&self, collection: &str) -> Result<Option<CollectionInfo>> {
+        self.retry("collection_info", || self.inner.collection_info(collection))
+            .await
+    }
+
+    async fn create_collection(&self, collection: &str, dimension: usize) -> Result<()> {
+        self.retry("create_collection", || {
+            self.inner.create_collection(collection, dimension)
+        })
+        .await
+    }
+
+    async fn create_collection_with_vectors(
+        &self,
+        collection: &str,
+        dimension: usize,
+        names: &[String],
+    ) -> Result<()> {
+        self.retry("create_collection", || {
+            self.inner
+                .create_collection_with_vectors(collection, dimension, names)
+        })
+        .await
+    }
+
+    async fn upsert(&self, collection: &str, points: Vec<VectorPoint>) -> Result<()> {
+        self.retry("upsert", || self.inner.upsert(collection, points.clone()))
+            .await
+    }
+
+    async fn search(&self, collection: &str, query: SearchQuery) -> Result<Vec<ScoredPoint>> {
+        self.retry("search", || self.inner.search(collection, query.clone()))
+            .await
+    }
+
+    async fn get(&self, collection: &str, ids: &[String]) -> Result<Vec<VectorPoint>> {
+        self.retry("get", || self.inner.get(collection, ids)).await
+    }
+
+    async fn delete(&self, collection: &str, ids: &[String]) -> Result<()> {
+        self.retry("delete", || self.inner.delete(collection, ids))
+            .await
+    }
+
+    async fn update_payload(&self, collection: &str, id: &str, patch: Payload) -> Result<()> {
+        self.retry("update_payload", || {
+            self.inner.update_payload(collection, id, patch.clone())
+        })
+        .await
+    }
+
+    async fn count(&self, collection: &str, filter: Option<PayloadFilter>) -> Result<u64> {
+        self.retry("count", || self.inner.count(collection, filter.clone()))
+            .await
+    }
+
+    async fn create_snapshot(&self, collection: &str, dir: &Path) -> Result<PathBuf> {
+        self.retry("create_snapshot", || {
+            self.inner.create_snapshot(collection, dir)
+        })
+        .await
+    }
+
+    async fn restore_snapshot(&self, collection: &str, path: &Path) -> Result<()> {
+        self.retry("restore_snapshot", || {
+            self.inner.restore_snapshot(collection, path)
+        })
+        .await
+    }
+
+    async fn scroll(
+        &self,
+        collection: &str,
+        filter: Option<PayloadFilter>,
+        offset: Option<String>,
+        limit: usize,
+    ) -> Result<(Vec<VectorPoint>, Option<String>)> {
+        self.retry("scroll", || {
+            self.inner
+                .scroll(collection, filter.clone(), offset.clone(), limit)
+        })
+        .await
+    }
+}
+
+/// HNSW index settings; unset fields keep Qdrant's defaults
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct HnswSettings {
+    /// Edges per node. Higher values improve recall and use more memory.
+    pub m: Option<u64>,
+    /// Neighbours considered while building. Higher values build a more
+    /// accurate index more slowly.
+    pub ef_construct: Option<u64>,
+    /// Segments smaller than this many kilobytes are searched without the index
+    pub full_scan_threshold: Option<u64>,
+    /// Keep the index on disk instead of in memory
+    pub on_disk: Option<bool>,
+}
+
+/// Compression of stored vectors, trading accuracy for memory
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "lowercase")]
+pub enum Quantization {
+    /// One byte per dimension, about 4x smaller
+    Scalar {
+        /// Fraction of values used to pick the int8 range, e.g. 0.99
+        #[serde(default)]
+        quantile: Option<f32>,
+        /// Keep the quantized vectors in memory even when vectors are on disk
+        #[serde(default)]
+        always_ram: bool,
+    },
+    /// Product codes, `compression` times smaller (4, 8, 16, 32 or 64)
+    Product {
+        compression: u32,
+        #[serde(default)]
+        always_ram: bool,
+    },
+}
+
+impl Quantization {
+    pub fn validate(&self) -> Result<()> {
+        match self {
+            Quantization::Scalar {
+                quantile: Some(quantile),
+                ..
+            } if !(0.5..=1.0).contains(quantile) => Err(Error::msg(format!(
+                "Scalar quantization quantile ({}) must be between 0.5 and 1.0",
+                quantile
+            ))),
+            Quantization::Product { compression, .. } => {
+                compression_ratio(*compression).map(|_| ())
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+fn compression_ratio(compression: u32) -> Result<CompressionRatio> {
+    match compression {
+        4 => Ok(CompressionRatio::X4),
+        8 => Ok(CompressionRatio::X8),
+        16 => Ok(CompressionRatio::X16),
+        32 => Ok(CompressionRatio::X32),
+        64 => Ok(CompressionRatio::X64),
+        _ => Err(Error::msg(format!(
+            "Product quantization compression must be 4, 8, 16, 32 or 64, got {}",
+            compression
+        ))),
+    }
+}
+
+fn to_qdrant_quantization(quantization: &Quantization) -> Result<QuantizationConfig> {
+    let quantization = match *quantization {
+        Quantization::Scalar {
+            quantile,
+            always_ram,
+        } => quantization_config::Quantization::Scalar(ScalarQuantization {
+            r#type: QuantizationType::Int8.into(),
+            quantile,
+            always_ram: Some(always_ram),
+        }),
+        Quantization::Product {
+            compression,
+            always_ram,
+        } => quantization_config::Quantization::Product(ProductQuantization {
+            compression: compression_ratio(compression)?.into(),
+            always_ram: Some(always_ram),
+        }),
+    };
+    Ok(QuantizationConfig {
+        quantization: Some(quantization),
+    })
+}
+
+/// Connection and collection settings for Qdrant
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct QdrantConfig {
+    /// gRPC endpoint, e.g. a Qdrant Cloud cluster URL
+    pub url: String,
+    /// REST endpoint, used to download and upload snapshots. Defaults to
+    /// `url` with port 6334 replaced by 6333.
+    pub rest_url: Option<String>,
+    /// Defaults to the `QDRANT_API_KEY` environment variable
+    pub api_key: Option<String>,
+    /// Connect over HTTPS even if `url` starts with `http://`
+    pub tls: bool,
+    /// Request timeout in seconds
+    pub timeout_secs: Option<u64>,
+    /// Connection timeout in seconds
+    pub connect_timeout_secs: Option<u64>,
+    pub distance: DistanceMetric,
+    /// Keep vectors on disk instead of in memory
+    pub on_disk: bool,
+    pub on_disk_payload: bool,
+    pub indexing_threshold: Option<u64>,
+    pub memmap_threshold: Option<u64>,
+    pub default_segment_number: Option<u64>,
+    pub wal_capacity_mb: Option<u64>,
+    pub wal_segments_ahead: Option<u64>,
+    pub hnsw: Option<HnswSettings>,
+    pub quantization: Option<Quantization>,
+    /// Retries while Qdrant is unreachable, used by `QdrantBackend::with_retry`
+    pub retry: RetryPolicy,
+}
+
+impl Default for QdrantConfig {
+    fn default() -> Self {
+        Self {
+            url: "http://localhost:6334".to_string(),
+            rest_url: None,
+            api_key: None,
+            tls: false,
+            timeout_secs: None,
+            connect_timeout_secs: None,
+            distance: DistanceMetric::Cosine,
+            on_disk: false,
+            on_disk_payload: false,
+            indexing_threshold: None,
+            memmap_threshold: None,
+            default_segment_number: None,
+            wal_capacity_mb: None,
+            wal_segments_ahead: None,
+            hnsw: None,
+            quantization: None,
+            retry: RetryPolicy::default(),
+        }
+    }
+}
+
+pub struct QdrantBackend {
+    client: Qdrant,
+    config: QdrantConfig,
+    rest_url: String,
+    http: reqwest::Client,
+    /// Whether each known collection stores named vectors
+    named: DashMap<String, bool>,
+}
+
+impl QdrantBackend {
+    pub fn new(config: QdrantConfig) -> Result<Self> {
+        let url = match config.url.strip_prefix("http://") {
+            Some(rest) if config.tls => format!("https://{}", rest),
+            _ => config.url.clone(),
+        };
+        let api_key = config
+            .api_key
+            .clone()
+            .or_else(|| std::env::var("QDRANT_API_KEY").ok());
+
+        info!("Connecting to Qdrant at {}", url);
+        let mut builder = Qdrant::from_url(&url).api_key(api_key.clone());
+        if let Some(secs) = config.timeout_secs {
+            builder = builder.timeout(Duration::from_secs(secs));
+        }
+        if let Some(secs) = config.connect_timeout_secs {
+            builder = builder.connect_timeout(Duration::from_secs(secs));
+        }
+        let client = builder
+            .build()
+            .with_context(|| format!("Failed to create Qdrant client for {}", url))?;
+        let rest_url = match &config.rest_url {
+            Some(rest_url) => rest_url.trim_end_matches('/').to_string(),
+            None => url.trim_end_matches('/').replace(":6334", ":6333"),
+        };
+        Ok(Self {
+            client,
+            config: QdrantConfig { api_key, ..config },
+            rest_url,
+            http: reqwest::Client::new(),
+            named: DashMap::new(),
+        })
+    }
+
+    /// Whether the collection was created with named vectors, which changes
+    /// how vectors are sent and searched
+    async fn is_named(&self, collection: &str) -> Result<bool> {
+        if let Some(named) = self.named.get(collection) {
+            return Ok(*named);
+        }
+        match self.collection_info(collection).await? {
+            Some(info) => {
+                let named = !info.vector_names.is_empty();
+                self.named.insert(collection.to_string(), named);
+                Ok(named)
+            }
+            None => Ok(false),
+        }
+    }
+
+    fn vector_params(&self, dimension: usize) -> VectorParams {
+        let distance = match self.config.distance {
+            DistanceMetric::Cosine => Distance::Cosine,
+            DistanceMetric::Euclid => Distance::Euclid,
+            DistanceMetric::Dot => Distance::Dot,
+        };
+        VectorParams {
+            size: dimension as u64,
+            distance: distance.into(),
+            on_disk: self.config.on_disk.then_some(true),
+            ..Default::default()
+        }
+    }
+
+    async fn create(&self, collection: &str, vectors: Config) -> Result<()> {
+        let mut request = CreateCollectionBuilder::new(collection)
+            .vectors_config(VectorsConfig {
+                config: Some(vectors),
+            })
+            .optimizers_config(OptimizersConfigDiff {
+                indexing_threshold: self.config.indexing_threshold,
+                memmap_threshold: self.config.memmap_threshold,
+                default_segment_number: self.config.default_segment_number,
+                ..Default::default()
+            })
+            .wal_config(WalConfigDiff {
+                wal_capacity_mb: self.config.wal_capacity_mb,
+                wal_segments_ahead: self.config.wal_segments_ahead,
+                ..Default::default()
+            })
+            .on_disk_payload(self.config.on_disk_payload);
+        if let Some(hnsw) = &self.config.hnsw {
+            request = request.hnsw_config(HnswConfigDiff {
+                m: hnsw.m,
+                ef_construct: hnsw.ef_construct,
+                full_scan_threshold: hnsw.full_scan_threshold,
+                on_disk: hnsw.on_disk,
+                ..Default::default()
+            });
+        }
+        if let Some(quantization) = &self.config.quantization {
+            request = request.quantization_config(to_qdrant_quantization(quantization)?);
+        }
+
+        self.client
+            .create_collection(request)
+            .await
+            .with_context(|| format!("Failed to create collection {}", collection))?;
+
+        Ok(())
+    }
+
+    fn rest_request(&self, request: reqwest::RequestBuilder) -> reqwest::RequestBuilder {
+        match &self.config.api_key {
+            Some(api_key) => request.header("api-key", api_key),
+            None => request,
+        }
+    }
+
+    /// Connects with default collection settings
+    pub fn connect(url: &str) -> Result<Self> {
+        Self::new(QdrantConfig {
+            url: url.to_string(),
+            ..QdrantConfig::default()
+        })
+    }
+
+    /// Creates a backend whose calls are retried following `config.retry`.
+    /// The client reconnects on the next call after a failed one, so a
+    /// restarted Qdrant is picked up again once it accepts connections.
+    pub fn with_retry(config: QdrantConfig) -> Result<RetryingBackend<Self>> {
+        let policy = config.retry;
+        Ok(RetryingBackend::new(Self::new(config)?, policy))
+    }
+}
+
+/// Whether an error from the Qdrant client or the REST API means the server
+/// could not be reached. The gRPC status is only available as text here.
+fn is_connection_error(error: &Error) -> bool {
+    if error.chain().any(|cause| {
+        matches!(cause.downcast_ref::<reqwest::Error>(), Some(e) if e.is_connect() || e.is_timeout())
+    }) {
+        return true;
+    }
+    let message = format!("{:#}", error).to_lowercase();
+    [
+        "transport error",
+        "connection refused",
+        "connection reset",
+        "broken pipe",
+        "unavailable",
+        "deadline",
+        "timed out",
+    ]
+    .iter()
+    .any(|pattern| message.contains(pattern))
+}
+
+#[async_trait]
+impl VectorBackend for QdrantBackend {
+    fn name(&self) -> String {
+        format!("qdrant ({})", self.config.url)
+    }
+
+    async fn health(&self) -> Result<String> {
+        let reply = self
+            .client
+            .health_check()
+            .await
+            .with_context(|| format!("Qdrant at {} is not reachable", self.config.url))?;
+        Ok(format!("{} {}", reply.title, reply.version))
+    }
+
+    fn is_transient(&self, error: &Error) -> bool {
+        is_connection_error(error)
+    }
+
+    async fn collection_info(&self, collection: &str) -> Result<Option<CollectionInfo>> {
+        if !self.client.collection_exists(collection).await? {
+            return Ok(None);
+        }
+
+        let info = self
+            .client
+            .collection_info(collection)
+            .await?
+            .result
+            .ok_or_else(|| Error::msg(format!("No information for collection {}", collection)))?;
+        let params = info
+            .config
+            .and_then(|config| config.params)
+            .and_then(|params| params.vectors_config)
+            .and_then(|vectors_config| vectors_config.config);
+        let (params, vector_names) = match params {
+            Some(Config::Params(params)) => (params, Vec::new()),
+            Some(Config::ParamsMap(mut params_map)) => {
+                let params = params_map.map.remove(CONTENT_VECTOR).ok_or_else(|| {
+                    Error::new(VectorStoreError::CollectionError(format!(
+                        "Collection {} has named vectors but none called '{}'",
+                        collection, CONTENT_VECTOR
+                    )))
+                })?;
+                let mut names: Vec<String> = params_map.map.into_keys().collect();
+                names.sort();
+                (params, names)
+            }
+            None => {
+                return Err(Error::new(VectorStoreError::CollectionError(format!(
+                    "Collection {} has no vector configuration",
+                    collection
+                ))))
+            }
+        };
+        let distance = match Distance::try_from(params.distance) {
+            Ok(Distance::Euclid) => DistanceMetric::Euclid,
+            Ok(Distance::Dot) => DistanceMetric::Dot,
+            _ => DistanceMetric::Cosine,
+        };
+
+        Ok(Some(CollectionInfo {
+            dimension: params.size as usize,
+            distance,
+            points_count: info.points_count.unwrap_or(0),
+            vector_names,
+        }))
+    }
+
+    async fn create_collection(&self, collection: &str, dimension: usize) -> Result<()> {
+        self.create(collection, Config::Params(self.vector_params(dimension)))
+            .await?;
+        self.named.insert(collection.to_string(), false);
+        Ok(())
+    }
+
+    /// Stores the main vector as "content" next to the named vectors
+    async fn create_collection_with_vectors(
+        &self,
+        collection: &str,
+        dimension: usize,
+        names: &[String],
+    ) -> Result<()> {
+        if names.is_empty() {
+            return self.create_collection(collection, dimension).await;
+        }
+
+        let map = std::iter::once(CONTENT_VECTOR.to_string())
+            .chain(names.iter().cloned())
+            .map(|name| (name, self.vector_params(dimension)))
+            .collect();
+        self.create(collection, Config::ParamsMap(VectorParamsMap { map }))
+            .await?;
+        self.named.insert(collection.to_string(), true);
+        Ok(())
+    }
+
+    async fn upsert(&self, collection: &str, points: Vec<VectorPoint>) -> Result<()> {
+        if points.is_empty() {
+            return Ok(());
+        }
+
+        let named = self.is_named(collection).await?;
+        let points: Vec<PointStruct> = points
+            .into_iter()
+            .map(|point| {
+                let mut payload = point.payload;
+                let id = to_point_id(&point.id);
+                if !is_native_id(&point.id) {
+                    payload.insert(ORIGINAL_ID_KEY.to_string(), JsonValue::from(point.id));
+                }
+                let vectors = if named {
+                    let mut vectors = point.named_vectors;
+                    vectors.insert(CONTENT_VECTOR.to_string(), point.vector);
+                    Vectors::from(vectors)
+                } else {
+                    Vectors::from(point.vector)
+                };
+                PointStruct {
+                    id: Some(id),
+                    vectors: Some(vectors),
+                    payload: to_qdrant_payload(payload),
+                }
+            })
+            .collect();
+
+        self.client
+            .upsert_points(UpsertPointsBuilder::new(collection, points).wait(true))
+            .await
+            .context("Failed to upsert points")?;
+        Ok(())
+    }
+
+    async fn search(&self, collection: &str, query: SearchQuery) -> Result<Vec<ScoredPoint>> {
+        let mut request = SearchPointsBuilder::new(collection, query.vector, query.limit as u64)
+            .with_payload(true)
+            .with_vectors(query.with_vectors);
+        if let Some(threshold) = query.score_threshold {
+            request = request.score_threshold(threshold);
+        }
+        if let Some(filter) = &query.filter {
+            request = request.filter(to_qdrant_filter(filter));
+        }
+        if self.is_named(collection).await? {
+            request = request.vector_name(query.using.as_deref().unwrap_or(CONTENT_VECTOR));
+        } else if let Some(name) = &query.using {
+            return Err(Error::new(VectorStoreError::SearchError(format!(
+                "Collection {} has no '{}' vector",
+                collection, name
+            ))));
+        }
+
+        let response = self
+            .client
+            .search_points(request)
+            .await
+            .context("Failed to search points")?;
+
+        Ok(response
+            .result
+            .into_iter()
+            .map(|point| {
+                let (id, payload) = from_qdrant_point(point.id, point.payload);
+                ScoredPoint {
+                    id,
+                    score: point.score,
+                    payload,
+                    vector: point.vectors.and_then(vector_data),
+                }
+            })
+            .collect())
+    }
+
+    async fn get(&self, collection: &str, ids: &[String]) -> Result<Vec<VectorPoint>> {
+        let ids: Vec<PointId> = ids.iter().map(|id| to_point_id(id)).collect();
+        let response = self
+            .client
+            .get_points(
+                GetPointsBuilder::new(collection, ids)
+                    .with_payload(true)
+                    .with_vectors(true),
+            )
+            .await
+            .context("Failed to get points")?;
+
+        Ok(response
+            .result
+            .into_iter()
+            .map(|point| {
+                let (id, payload) = from_qdrant_point(point.id, point.payload);
+                let (vector, named_vectors) = match point.vectors {
+                    Some(vectors) => split_vectors(vectors),
+                    None => (Vec::new(), HashMap::new()),
+                };
+                VectorPoint {
+                    id,
+                    vector,
+                    named_vectors,
+                    payload,
+                }
+            })
+            .collect())
+    }
+
+    async fn delete(&self, collection: &str, ids: &[String]) -> Result<()> {
+        if ids.is_empty() {
+            return Ok(());
+        }
+
+        self.client
+            .delete_points(
+                DeletePointsBuilder::new(collection)
+                    .points(PointsIdsList {
+                        ids: ids.iter().map(|id| to_point_id(id)).collect(),
+                    })
+                    .wait(true),
+            )
+            .await
+            .context("Failed to delete points")?;
+        Ok(())
+    }
+
+    async fn scroll(
+        &self,
+        collection: &str,
+        filter: Option<PayloadFilter>,
+        offset: Option<String>,
+        limit: usize,
+    ) -> Result<(Vec<VectorPoint>, Option<String>)> {
+        let mut request = ScrollPointsBuilder::new(collection)
+            .limit(limit as u32)
+            .with_payload(true)
+            .with_vectors(false);
+        if let Some(filter) = &filter {
+            request = request.filter(to_qdrant_filter(filter));
+        }
+        if let Some(offset) = offset {
+            request = request.offset(to_point_id(&offset));
+        }
+
+        let response = self
+            .client
+            .scroll(request)
+            .await
+            .context("Failed to scroll points")?;
+
+        let points = response
+            .result
+            .into_iter()
+            .map(|point| {
+                let (id, payload) = from_qdrant_point(point.id, point.payload);
+                VectorPoint::new(id, Vec::new(), payload)
+            })
+            .collect();
+        let next = response.next_page_offset.map(|id| point_id_string(&id));
+        Ok((points, next))
+    }
+
+    async fn update_payload(&self, collection: &str, id: &str, patch: Payload) -> Result<()> {
+        self.client
+            .set_payload(
+                SetPayloadPointsBuilder::new(collection, to_qdrant_payload(patch))
+                    .points_selector(PointsIdsList {
+                        ids: vec![to_point_id(id)],
+                    })
+                    .wait(true),
+            )
+            .await
+            .with_context(|| format!("Failed to update the payload of {}", id))?;
+        Ok(())
+    }
+
+    async fn count(&self, collection: &str, filter: Option<PayloadFilter>) -> Result<u64> {
+        let mut request = CountPointsBuilder::new(collection).exact(true);
+        if let Some(filter) = &filter {
+            request = request.filter(to_qdrant_filter(filter));
+        }
+
+        let response = self
+            .client
+            .count(request)
+            .await
+            .context("Failed to count points")?;
+        Ok(response.result.map(|result| result.count).unwrap_or(0))
+    }
+
+    /// Creates a snapshot on the server, then downloads it over REST
+    async fn create_snapshot(&self, collection: &str, dir: &Path) -> Result<PathBuf> {
+        let snapshot = self
+            .client
+            .create_snapshot(collection)
+            .await
+            .with_context(|| format!("Failed to create a snapshot of {}", collection))?
+            .snapshot_description
+            .ok_or_else(|| Error::msg("Qdrant did not describe the new snapshot"))?;
+
+        let url = format!(
+            "{}/collections/{}/snapshots/{}",
+            self.rest_url, collection, snapshot.name
+        );
+        let response = self
+            .rest_request(self.http.get(&url))
+            .send()
+            .await?
+            .error_for_status()
+            .with_context(|| format!("Failed to download snapshot {}", snapshot.name))?;
+        let bytes = response.bytes().await?;
+
+        tokio::fs::create_dir_all(dir).await?;
+        let path = dir.join(&snapshot.name);
+        tokio::fs::write(&path, &bytes)
+            .await
+            .with_context(|| format!("Failed to write {}", path.display()))?;
+        info!(
+            "Saved snapshot of {} ({} bytes) to {}",
+            collection,
+            bytes.len(),
+            path.display()
+        );
+        Ok(path)
+    }
+
+    /// Uploads the snapshot file, which replaces the collection's data
+    async fn restore_snapshot(&self, collection: &str, path: &Path) -> Result<()> {
+        let bytes = tokio::fs::read(path)
+            .await
+            .with_context(|| format!("Failed to read {}", path.display()))?;
+        let file_name = path
+            .file_name()
+            .map(|name| name.to_string_lossy().into_owned())
+            .unwrap_or_else(|| "snapshot".to_string());
+        let form = reqwest::multipart::Form::new().part(
+            "snapshot",
+            reqwest::multipart::Part::bytes(bytes).file_name(file_name),
+        );
+
+        let url = format!(
+            "{}/collections/{}/snapshots/upload?priority=snapshot&wait=true",
+            self.rest_url, collection
+        );
+        self.rest_request(self.http.post(&url).multipart(form))
+            .send()
+            .await?
+            .error_for_status()
+            .with_context(|| format!("Failed to restore {} from {}", collection, path.display()))?;
+        info!("Restored {} from {}", collection, path.display());
+        Ok(())
+    }
+}
+
+fn is_native_id(id: &str) -> bool {
+    uuid::Uuid::parse_str(id).is_ok() || id.parse::<u64>().is_ok()
+}
+
+/// UUIDs and numbers are used as is; other ids are mapped to a stable UUID
+fn to_point_id(id: &str) -> PointId {
+    if let Ok(num) = id.parse::<u64>() {
+        return PointId::from(num);
+    }
+    match uuid::Uuid::parse_str(id) {
+        Ok(uuid) => PointId::from(uuid.to_string()),
+        Err(_) => PointId::from(uuid::Uuid::from_bytes(md5::compute(id).0).to_string()),
+    }
+}
+
+fn point_id_string(id: &PointId) -> String {
+    match &id.point_id_options {
+        Some(PointIdOptions::Num(num)) => num.to_string(),
+        Some(PointIdOptions::Uuid(uuid)) => uuid.clone(),
+        None => String::new(),
+    }
+}
+
+/// Restores the caller's id saved by `upsert` and strips it from the payload
+fn from_qdrant_point(id: Option<PointId>, payload: HashMap<String, Value>) -> (String, Payload) {
+    let mut payload = from_qdrant_payload(payload);
+    let id = match payload.remove(ORIGINAL_ID_KEY) {
+        Some(JsonValue::String(original)) => original,
+        _ => id.as_ref().map(point_id_string).unwrap_or_default(),
+    };
+    (id, payload)
+}
+
+/// The main vector, which is called "content" in collections with named vectors
+fn vector_data(vectors: Vectors) -> Option<Vec<f32>> {
+    match vectors.vectors_options? {
+        VectorsOptions::Vector(vector) => Some(vector.data),
+        VectorsOptions::Vectors(mut named) => named
+            .vectors
+            .remove(CONTENT_VECTOR)
+            .map(|vector| vector.data),
+    }
+}
+
+/// The main vector and the remaining named vectors
+fn split_vectors(vectors: Vectors) -> (Vec<f32>, HashMap<String, Vec<f32>>) {
+    match vectors.vectors_options {
+        Some(VectorsOptions::Vectors(named)) => {
+            let mut named: HashMap<String, Vec<f32>> = named
+                .vectors
+                .into_iter()
+                .map(|(name, vector)| (name, vector.data))
+                .collect();
+            let vector = named.remove(CONTENT_VECTOR).unwrap_or_default();
+            (vector, named)
+        }
+        Some(VectorsOptions::Vector(vector)) => (vector.data, HashMap::new()),
+        None => (Vec::new(), HashMap::new()),
+    }
+}
+
+fn to_qdrant_filter(filter: &PayloadFilter) -> Filter {
+    let keywords = filter
+        .must
+        .iter()
+        .map(|(key, values)| match values.as_slice() {
+            [value] => Condition::matches(key.as_str(), value.clone()),
+            _ => Condition::matches(key.as_str(), values.clone()),
+        });
+    let ranges = filter.ranges.iter().map(|range| {
+        Condition::range(
+            range.key.as_str(),
+            Range {
+                gte: range.gte,
+                lte: range.lte,
+                ..Default::default()
+            },
+        )
+    });
+    Filter::must(keywords.chain(ranges))
+}
+
+pub fn to_qdrant_payload(payload: Payload) -> HashMap<String, Value> {
+    payload
+        .into_iter()
+        .map(|(key, value)| (key, to_qdrant_value(value)))
+        .collect()
+}
+
+pub fn from_qdrant_payload(payload: HashMap<String, Value>) -> Payload {
+    payload
+        .into_iter()
+        .map(|(key, value)| (key, from_qdrant_value(value)))
+        .collect()
+}
+
+fn to_qdrant_value(value: JsonValue) -> Value {
+    let kind = match value {
+        JsonValue::Null => Kind::NullValue(0),
+        JsonValue::Bool(b) => Kind::BoolValue(b),
+        JsonValue::Number(n) => match n.as_i64() {
+            Some(i) => Kind::IntegerValue(i),
+            None => Kind::DoubleValue(n.as_f64().unwrap_or_default()),
+        },
+        JsonValue::String(s) => Kind::StringValue(s),
+        JsonValue::Array(items) => Kind::ListValue(ListValue {
+            values: items.into_iter().map(to_qdrant_value).collect(),
+        }),
+        JsonValue::Object(fields) => Kind::StructValue(Struct {
+            fields: fields
+                .into_iter()
+                .map(|(key, value)| (key, to_qdrant_value(value)))
+                .collect(),
+        }),
+    };
+    Value { kind: Some(kind) }
+}
+
+fn from_qdrant_value(value: Value) -> JsonValue {
+    match value.kind {
+        None | Some(Kind::NullValue(_)) => JsonValue::Null,
+        Some(Kind::BoolValue(b)) => JsonValue::Bool(b),
+        Some(Kind::IntegerValue(i)) => JsonValue::from(i),
+        Some(Kind::DoubleValue(f)) => JsonValue::from(f),
+        Some(Kind::StringValue(s)) => JsonValue::String(s),
+        Some(Kind::ListValue(list)) => {
+            JsonValue::Array(list.values.into_iter().map(from_qdrant_value).collect())
+        }
+        Some(Kind::StructValue(object)) => JsonValue::Object(
+            object
+                .fields
+                .into_iter()
+                .map(|(key, value)| (key, from_qdrant_value(value)))
+                .collect(),
+        ),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_payload_round_trip() {
+        let payload = json!({
+            "text": "hello",
+            "count": 3,
+            "score": 0.5,
+            "tags": ["a", "b"],
+            "meta": {"nested": true, "missing": null}
+        });
+        let payload = payload.as_object().unwrap().clone();
+        assert_eq!(
+            from_qdrant_payload(to_qdrant_payload(payload.clone())),
+            payload
+        );
+    }
+
+    #[test]
+    fn test_point_ids() {
+        let uuid = "0b5c9a2e-6f43-4a8e-9d6c-1f2e3d4c5b6a";
+        assert!(is_native_id(uuid));
+        assert!(is_native_id("42"));
+        assert!(!is_native_id("test_id_1"));
+
+        assert_eq!(point_id_string(&to_point_id(uuid)), uuid);
+        assert_eq!(point_id_string(&to_point_id("42")), "42");
+        // Other ids map to the same UUID every time
+        assert_eq!(to_point_id("test_id_1"), to_point_id("test_id_1"));
+        assert!(uuid::Uuid::parse_str(&point_id_string(&to_point_id("test_id_1"))).is_ok());
+    }
+
+    #[test]
+    fn test_payload_filter() {
+        let payload = json!({"role": "user", "turn": 3});
+        let payload = payload.as_object().unwrap();
+
+        assert!(PayloadFilter::new().accepts(payload));
+        assert!(PayloadFilter::new()
+            .matches("role", "user")
+            .accepts(payload));
+        assert!(PayloadFilter::new()
+            .matches_any("turn", vec!["2".to_string(), "3".to_string()])
+            .accepts(payload));
+        assert!(!PayloadFilter::new()
+            .matches("role", "user")
+            .matches("missing", "x")
+            .accepts(payload));
+        assert!(PayloadFilter::new()
+            .range("turn", Some(3.0), None)
+            .accepts(payload));
+        assert!(!PayloadFilter::new()
+            .range("turn", Some(1.0), Some(2.0))
+            .accepts(payload));
+        assert!(!PayloadFilter::new()
+            .range("role", Some(0.0), None)
+            .accepts(payload));
+    }
+
+    #[test]
+    fn test_quantization() {
+        let scalar: Quantization =
+            serde_yaml::from_str("type: scalar\nquantile: 0.99\nalways_ram: true").unwrap();
+        assert_eq!(
+            scalar,
+            Quantization::Scalar {
+                quantile: Some(0.99),
+                always_ram: true
+            }
+        );
+        assert!(scalar.validate().is_ok());
+        assert!(to_qdrant_quantization(&scalar).is_ok());
+
+        let product: Quantization = serde_yaml::from_str("type: product\ncompression: 16").unwrap();
+        assert!(product.validate().is_ok());
+        assert!(Quantization::Product {
+            compression: 10,
+            always_ram: false
+        }
+        .validate()
+        .is_err());
+        assert!(Quantization::Scalar {
+            quantile: Some(0.1),
+            always_ram: false
+        }
+        .validate()
+        .is_err());
+    }
+
+    #[test]
+    fn test_distance_metric() {
+        assert_eq!(
+            "cosine".parse::<DistanceMetric>().unwrap(),
+            DistanceMetric::Cosine
+        );
+        assert_eq!(
+            "Euclid".parse::<DistanceMetric>().unwrap(),
+            DistanceMetric::Euclid
+        );
+        assert!("Invalid".parse::<DistanceMetric>().is_err());
+    }
+
+    #[test]
+    fn test_retry_backoff() {
+        let policy = RetryPolicy::default();
+        assert_eq!(policy.backoff(0), Duration::from_millis(200));
+        assert_eq!(policy.backoff(2), Duration::from_millis(800));
+        assert_eq!(policy.backoff(10), Duration::from_millis(5000));
+        assert_eq!(policy.backoff(u32::MAX), Duration::from_millis(5000));
+    }
+
+    #[test]
+    fn test_connection_errors() {
+        assert!(is_connection_error(
+            &Error::msg("status: Unavailable, message: \"error trying to connect\"")
+                .context("Failed to search")
+        ));
+        assert!(is_connection_error(&Error::msg("transport error")));
+        assert!(!is_connection_error(&Error::msg(
+            "Wrong input: Vector dimension error"
+        )));
+    }
+
+    /// Fails `collection_info` with a transport error until `failures` runs out
+    struct FlakyBackend {
+        failures: std::sync::atomic::AtomicU32,
+    }
+
+    #[async_trait]
+    impl VectorBackend for FlakyBackend {
+        fn name(&self) -> String {
+            "flaky".to_string()
+        }
+
+        fn is_transient(&self, error: &Error) -> bool {
+            is_connection_error(error)
+        }
+
+        async fn collection_info(&self, _collection: &str) -> Result<Option<CollectionInfo>> {
+            use std::sync::atomic::Ordering;
+            if self.failures.load(Ordering::SeqCst) > 0 {
+                self.failures.fetch_sub(1, Ordering::SeqCst);
+                return Err(Error::msg("transport error"));
+            }
+            Ok(None)
+        }
+
+        async fn create_collection(&self, _collection: &str, _dimension: usize) -> Result<()> {
+            Err(Error::msg("collection already exists"))
+        }
+
+        async fn upsert(&self, _collection: &str, _points: Vec<VectorPoint>) -> Result<()> {
+            Ok(())
+        }
+
+        async fn search(&self, _collection: &str, _query: SearchQuery) -> Result<Vec<ScoredPoint>> {
+            Ok(Vec::new())
+        }
+
+        async fn get(&self, _collection: &str, _ids: &[String]) -> Result<Vec<VectorPoint>> {
+            Ok(Vec::new())
+        }
+
+        async fn delete(&self, _collection: &str, _ids: &[String]) -> Result<()> {
+            Ok(())
+        }
+
+        async fn scroll(
+            &self,
+            _collection: &str,
+            _filter: Option<PayloadFilter>,
+            _offset: Option<String>,
+            _limit: usize,
+        ) -> Result<(Vec<VectorPoint>, Option<String>)> {
+            Ok((Vec::new(), None))
+        }
+    }
+
+    #[tokio::test]
+    async fn test_retrying_backend() {
+        let policy = RetryPolicy {
+            max_retries: 2,
+            initial_backoff_ms: 1,
+            max_backoff_ms: 1,
+            jitter: 0.0,
+        };
+        let flaky = |failures| FlakyBackend {
+            failures: std::sync::atomic::AtomicU32::new(failures),
+        };
+
+        let backend = RetryingBackend::new(flaky(2), policy);
+        assert!(backend.collection_info("test").await.unwrap().is_none());
+
+        let backend = RetryingBackend::new(flaky(3), policy);
+        assert!(backend.collection_info("test").await.is_err());
+
+        // Errors that are not transient are returned without retrying
+        let backend = RetryingBackend::new(flaky(0), policy);
+        assert!(backend.create_collection("test", 4).await.is_err());
+    }
+}
@@ -1,15 +1,13 @@
-use anyhow::Result;
-use log::info;
-use qdrant_client::{
-    qdrant::{
-        point_id, value, vectors, vectors_config::Config, CreateCollection, Distance,
-        OptimizersConfigDiff, PointId, PointStruct, SearchPoints, SearchPointsBuilder,
-        UpsertPointsBuilder, Value, Vector, VectorParams, Vectors, VectorsConfig, WalConfigDiff,
-    },
-    Qdrant,
+use anyhow::{Error, Result};
+use serde::Deserialize;
+use std::sync::Arc;
+
+use crate::vector_backend::{
+    DistanceMetric, HnswSettings, Payload, QdrantBackend, QdrantConfig, Quantization, RetryPolicy,
+    SearchQuery, VectorBackend, VectorPoint,
 };
-use serde::{Deserialize, Serialize};
 
+/// The `vector_db` section of `config.yaml`
 #[derive(Debug, Deserialize)]
 pub struct VectorDBConfig {
     pub provider: String,
@@ -19,6 +17,27 @@ pub struct VectorDBConfig {
     pub collection_name: String,
     pub vector_size: u64,
     pub distance: String,
+    /// Qdrant Cloud API key; defaults to the `QDRANT_API_KEY` environment variable
+    #[serde(default)]
+    pub api_key: Option<String>,
+    /// Connect over HTTPS, required by Qdrant Cloud
+    #[serde(default)]
+    pub tls: bool,
+    /// Request timeout in seconds
+    #[serde(default)]
+    pub timeout_secs: Option<u64>,
+    /// HNSW index settings applied when the collection is created
+    #[serde(default)]
+    pub hnsw_config: Option<HnswSettings>,
+    /// Scalar or product quantization applied when the collection is created
+    #[serde(default)]
+    pub quantization: Option<Quantization>,
+    /// Database directory for the `lancedb` provider
+    #[serde(default)]
+    pub path: Option<String>,
+    /// Retries of calls made while Qdrant is unreachable, e.g. restarting
+    #[serde(default)]
+    pub retry: RetryPolicy,
     pub on_disk_payload: bool,
     pub optimizers_config: OptimizersConfig,
     pub wal_config: WalConfig,
@@ -43,126 +62,118 @@ pub struct PerformanceConfig {
     pub max_optimization_threads: u32,
 }
 
-pub struct VectorDBClient {
-    client: Qdrant,
-    config: VectorDBConfig,
-}
+impl VectorDBConfig {
+    /// Backend settings for this section, failing on an unknown provider or
+    /// distance metric
+    pub fn qdrant_config(&self) -> Result<QdrantConfig> {
+        if !self.provider.eq_ignore_ascii_case("qdrant") {
+            return Err(Error::msg(format!(
+                "Unsupported vector database provider: {}",
+                self.provider
+            )));
+        }
 
-impl VectorDBClient {
-    pub fn new(config: VectorDBConfig) -> Result<Self> {
-        let client =
-            Qdrant::from_url(&format!("http://{}:{}", config.host, config.http_port)).build()?;
+        if let Some(quantization) = &self.quantization {
+            quantization.validate()?;
+        }
 
-        Ok(Self { client, config })
+        Ok(QdrantConfig {
+            url: format!(
+                "{}://{}:{}",
+                if self.tls { "https" } else { "http" },
+                self.host,
+                self.http_port
+            ),
+            rest_url: Some(format!(
+                "{}://{}:{}",
+                if self.tls { "https" } else { "http" },
+                self.host,
+                self.port
+            )),
+            api_key: self.api_key.clone(),
+            tls: self.tls,
+            timeout_secs: self.timeout_secs,
+            hnsw: self.hnsw_config,
+            quantization: self.quantization,
+            retry: self.retry,
+            distance: self.distance.parse::<DistanceMetric>()?,
+            on_disk_payload: self.on_disk_payload,
+            memmap_threshold: Some(self.optimizers_config.memmap_threshold as u64),
+            default_segment_number: Some(self.optimizers_config.default_segment_number as u64),
+            wal_capacity_mb: Some(self.wal_config.wal_capacity_mb as u64),
+            wal_segments_ahead: Some(self.wal_config.wal_segments_capacity_mb as u64),
+            ..QdrantConfig::default()
+        })
     }
+}
 
-    pub async fn init(&self) -> Result<()> {
-        // 检查集合是否存在
-        let collections = self.client.list_collections().await?;
-        let collection_exists = collections
-            .collections
-            .iter()
-            .any(|c| c.name == self.config.collection_name);
-
-        if !collection_exists {
-            info!("Creating collection: {}", self.config.collection_name);
-            self.create_collection().await?;
-        } else {
-            info!("Collection {} already exists", self.config.collection_name);
-        }
+#[cfg(feature = "lancedb")]
+fn lancedb_backend(config: &VectorDBConfig) -> Result<Arc<dyn VectorBackend>> {
+    let path = config.path.as_deref().unwrap_or("./lancedb");
+    let distance = config.distance.parse::<DistanceMetric>()?;
+    Ok(Arc::new(crate::lancedb_backend::LanceDbBackend::new(
+        path, distance,
+    )))
+}
 
-        Ok(())
-    }
+#[cfg(not(feature = "lancedb"))]
+fn lancedb_backend(_config: &VectorDBConfig) -> Result<Arc<dyn VectorBackend>> {
+    Err(Error::msg(
+        "The lancedb provider requires building with `--features lancedb`",
+    ))
+}
 
-    async fn create_collection(&self) -> Result<()> {
-        let distance = match self.config.distance.as_str() {
-            "Cosine" => Distance::Cosine,
-            "Euclid" => Distance::Euclid,
-            "Dot" => Distance::Dot,
-            _ => Distance::Cosine,
-        };
+pub struct VectorDBClient {
+    backend: Arc<dyn VectorBackend>,
+    config: VectorDBConfig,
+}
 
-        let vectors_config = VectorsConfig {
-            config: Some(Config::Params(VectorParams {
-                size: self.config.vector_size,
-                distance: distance.into(),
-                ..Default::default()
-            })),
+impl VectorDBClient {
+    pub fn new(config: VectorDBConfig) -> Result<Self> {
+        let backend: Arc<dyn VectorBackend> = if config.provider.eq_ignore_ascii_case("lancedb") {
+            lancedb_backend(&config)?
+        } else {
+            Arc::new(QdrantBackend::with_retry(config.qdrant_config()?)?)
         };
+        Ok(Self::with_backend(config, backend))
+    }
 
-        let optimizers_config = OptimizersConfigDiff {
-            default_segment_number: Some(
-                self.config.optimizers_config.default_segment_number as u64,
-            ),
-            memmap_threshold: Some(self.config.optimizers_config.memmap_threshold as u64),
-            ..Default::default()
-        };
+    pub fn with_backend(config: VectorDBConfig, backend: Arc<dyn VectorBackend>) -> Self {
+        Self { backend, config }
+    }
 
-        let wal_config = WalConfigDiff {
-            wal_capacity_mb: Some(self.config.wal_config.wal_capacity_mb as u64),
-            wal_segments_ahead: Some(self.config.wal_config.wal_segments_capacity_mb as u64),
-            ..Default::default()
-        };
+    /// Checks that the backend is reachable and returns its status
+    pub async fn health(&self) -> Result<String> {
+        self.backend.health().await
+    }
 
-        self.client
-            .create_collection(
-                qdrant_client::qdrant::CreateCollectionBuilder::new(&self.config.collection_name)
-                    .vectors_config(vectors_config)
-                    .optimizers_config(optimizers_config)
-                    .wal_config(wal_config)
-                    .on_disk_payload(self.config.on_disk_payload),
+    pub async fn init(&self) -> Result<()> {
+        self.backend
+            .ensure_collection(
+                &self.config.collection_name,
+                self.config.vector_size as usize,
             )
-            .await?;
-
-        Ok(())
+            .await
     }
 
     pub async fn upsert_vectors(
         &self,
         points: Vec<(String, Vec<f32>, serde_json::Value)>,
     ) -> Result<()> {
-        let points: Vec<PointStruct> = points
+        let points = points
             .into_iter()
-            .map(|(id, vector, payload)| PointStruct {
-                id: Some(PointId {
-                    point_id_options: Some(point_id::PointIdOptions::Uuid(id)),
-                }),
-                vectors: Some(Vectors {
-                    vectors_options: Some(vectors::VectorsOptions::Vector(Vector {
-                        data: vector,
-                        indices: None,
-                        vector: None,
-                        vectors_count: None,
-                    })),
-                }),
-                payload: serde_json::to_value(payload)
-                    .unwrap_or_default()
-                    .as_object()
-                    .map(|m| {
-                        m.iter()
-                            .map(|(k, v)| {
-                                (
-                                    k.clone(),
-                                    Value {
-                                        kind: Some(value::Kind::StringValue(v.to_string())),
-                                    },
-                                )
-                            })
-                            .collect()
-                    })
-                    .unwrap_or_default(),
+            .map(|(id, vector, payload)| {
+                let payload = match payload {
+                    serde_json::Value::Object(payload) => payload,
+                    _ => Payload::new(),
+                };
+                VectorPoint::new(id, vector, payload)
             })
             .collect();
 
-        self.client
-            .upsert_points(
-                UpsertPointsBuilder::new(&self.config.collection_name, points)
-                    .wait(true)
-                    .build(),
-            )
-            .await?;
-
-        Ok(())
+        self.backend
+            .upsert(&self.config.collection_name, points)
+            .await
     }
 
     pub async fn search_vectors(
@@ -170,41 +181,24 @@ impl VectorDBClient {
         vector: Vec<f32>,
         limit: u32,
     ) -> Result<Vec<(String, f32, serde_json::Value)>> {
-        let search_result = self
-            .client
-            .search_points(
-                SearchPointsBuilder::new(&self.config.collection_name, vector, limit as u64)
-                    .with_payload(true),
+        let results = self
+            .backend
+            .search(
+                &self.config.collection_name,
+                SearchQuery::new(vector, limit as usize),
             )
             .await?;
 
-        let results = search_result
-            .result
+        Ok(results
             .into_iter()
-            .map(|scored_point| {
-                let id = match scored_point.id.unwrap().point_id_options {
-                    Some(point_id::PointIdOptions::Uuid(id)) => id,
-                    _ => "".to_string(),
-                };
-
-                let score = scored_point.score;
-                let payload = scored_point
-                    .payload
-                    .iter()
-                    .map(|(k, v)| {
-                        let value = match &v.kind {
-                            Some(value::Kind::StringValue(s)) => s.clone(),
-                            _ => "".to_string(),
-                        };
-                        (k.clone(), serde_json::Value::String(value))
-                    })
-                    .collect::<serde_json::Map<String, serde_json::Value>>();
-
-                (id, score, serde_json::Value::Object(payload))
+            .map(|point| {
+                (
+                    point.id,
+                    point.score,
+                    serde_json::Value::Object(point.payload),
+                )
             })
-            .collect();
-
-        Ok(results)
+            .collect())
     }
 }
 
@@ -222,6 +216,13 @@ mod tests {
             collection_name: "test_collection".to_string(),
             vector_size: 4,
             distance: "Cosine".to_string(),
+            api_key: None,
+            tls: false,
+            timeout_secs: None,
+            hnsw_config: None,
+            quantization: None,
+            path: None,
+            retry: RetryPolicy::default(),
             on_disk_payload: true,
             optimizers_config: OptimizersConfig {
                 default_segment_number: 2,
@@ -238,6 +239,32 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_qdrant_config() {
+        let qdrant = create_test_config().qdrant_config().unwrap();
+        assert_eq!(qdrant.url, "http://localhost:6334");
+        assert_eq!(qdrant.distance, DistanceMetric::Cosine);
+        assert_eq!(qdrant.wal_segments_ahead, Some(64));
+
+        let mut config = create_test_config();
+        config.host = "xyz.cloud.qdrant.io".to_string();
+        config.api_key = Some("secret".to_string());
+        config.tls = true;
+        config.timeout_secs = Some(30);
+        let qdrant = config.qdrant_config().unwrap();
+        assert_eq!(qdrant.url, "https://xyz.cloud.qdrant.io:6334");
+        assert_eq!(qdrant.api_key.as_deref(), Some("secret"));
+        assert_eq!(qdrant.timeout_secs, Some(30));
+
+        let mut config = create_test_config();
+        config.distance = "Invalid".to_string();
+        assert!(config.qdrant_config().is_err());
+
+        let mut config = create_test_config();
+        config.provider = "pinecone".to_string();
+        assert!(config.qdrant_config().is_err());
+    }
+
     #[tokio::test]
     async fn test_vector_db_operations() {
         let config = create_test_config();
@@ -1,29 +1,27 @@
 use anyhow::{Context, Result};
-use qdrant_client::{
-    qdrant::{
-        points_selector::PointsSelectorOneOf, CreateCollection, DeletePointsBuilder, Distance,
-        GetPointsBuilder, PointStruct, PointsIdsList, PointsSelector, SearchPoints,
-        SearchPointsBuilder, UpsertPointsBuilder, Value, VectorParams, Vectors, VectorsConfig,
-        WithPayloadSelector, WithVectorsSelector, WriteOrdering,
-    },
-    Qdrant,
-};
+use futures::{StreamExt, TryStreamExt};
 use serde::{Deserialize, Serialize};
+use serde_json::Value as JsonValue;
 use std::collections::HashMap;
+use std::path::{Path, PathBuf};
 use std::sync::Arc;
-use thiserror::Error;
-
-#[derive(Debug, thiserror::Error)]
-pub enum VectorStoreError {
-    #[error("Collection error: {0}")]
-    CollectionError(String),
-    #[error("Storage error: {0}")]
-    StorageError(String),
-    #[error("Search error: {0}")]
-    SearchError(String),
-    #[error("Invalid dimension: expected {expected}, got {actual}")]
-    InvalidDimension { expected: u64, actual: u64 },
-}
+
+use crate::local_backend::LocalBackend;
+use crate::vector_backend::{
+    Payload, PayloadFilter, QdrantBackend, QdrantConfig, SearchQuery, VectorBackend, VectorPoint,
+};
+
+pub use crate::vector_backend::VectorStoreError;
+
+/// Payload key recording when a message was stored, in nanoseconds, which
+/// gives `scroll` its insertion order
+const INSERTED_AT_KEY: &str = "inserted_at";
+
+/// Points fetched per backend request while scrolling
+const SCROLL_PAGE_SIZE: usize = 256;
+
+/// Searches of one `search_similar_batch` call that run at the same time
+const BATCH_SEARCH_CONCURRENCY: usize = 8;
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Message {
@@ -34,142 +32,232 @@ pub struct Message {
     pub metadata: HashMap<String, String>,
 }
 
+impl Message {
+    fn to_payload(&self) -> Payload {
+        let mut payload: Payload = self
+            .metadata
+            .iter()
+            .map(|(k, v)| (k.clone(), JsonValue::from(v.as_str())))
+            .collect();
+        payload.insert("role".to_string(), JsonValue::from(self.role.as_str()));
+        payload.insert(
+            "content".to_string(),
+            JsonValue::from(self.content.as_str()),
+        );
+        // Stored as a number so that `Filter::since`/`until` can range over it
+        payload.insert("timestamp".to_string(), JsonValue::from(self.timestamp));
+        payload
+    }
+
+    /// Insertion time recorded by `store_messages_batch`, falling back to the
+    /// message timestamp for messages stored without one
+    fn inserted_at(payload: &Payload) -> i64 {
+        match payload.get(INSERTED_AT_KEY).and_then(JsonValue::as_i64) {
+            Some(inserted_at) => inserted_at,
+            None => payload_strings(payload)
+                .get("timestamp")
+                .and_then(|t| t.parse::<i64>().ok())
+                .unwrap_or_default()
+                .saturating_mul(1_000_000_000),
+        }
+    }
+
+    fn from_payload(id: String, payload: &Payload) -> Self {
+        let fields = payload_strings(payload);
+        let field = |key: &str| fields.get(key).cloned().unwrap_or_default();
+
+        Self {
+            id,
+            role: field("role"),
+            content: field("content"),
+            timestamp: field("timestamp").parse().unwrap_or_default(),
+            metadata: fields
+                .iter()
+                .filter(|(k, _)| {
+                    !matches!(
+                        k.as_str(),
+                        "role" | "content" | "timestamp" | INSERTED_AT_KEY
+                    )
+                })
+                .map(|(k, v)| (k.clone(), v.clone()))
+                .collect(),
+        }
+    }
+}
+
+/// Payload values as plain strings, without JSON quoting
+fn payload_strings(payload: &Payload) -> HashMap<String, String> {
+    payload
+        .iter()
+        .map(|(k, v)| {
+            let value = match v {
+                JsonValue::String(s) => s.clone(),
+                other => other.to_string(),
+            };
+            (k.clone(), value)
+        })
+        .collect()
+}
+
+/// Restricts a message search to a role, a time window and metadata values
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Filter {
+    pub role: Option<String>,
+    /// Inclusive bounds on the message timestamp, in Unix seconds
+    pub since: Option<i64>,
+    pub until: Option<i64>,
+    /// Metadata entries that must all be present with these values
+    pub metadata: Vec<(String, String)>,
+}
+
+impl Filter {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn role(mut self, role: &str) -> Self {
+        self.role = Some(role.to_string());
+        self
+    }
+
+    pub fn since(mut self, timestamp: i64) -> Self {
+        self.since = Some(timestamp);
+        self
+    }
+
+    pub fn until(mut self, timestamp: i64) -> Self {
+        self.until = Some(timestamp);
+        self
+    }
+
+    pub fn metadata(mut self, key: &str, value: &str) -> Self {
+        self.metadata.push((key.to_string(), value.to_string()));
+        self
+    }
+
+    fn to_payload_filter(&self) -> PayloadFilter {
+        let mut filter = PayloadFilter::new();
+        if let Some(role) = &self.role {
+            filter = filter.matches("role", role);
+        }
+        if self.since.is_some() || self.until.is_some() {
+            filter = filter.range(
+                "timestamp",
+                self.since.map(|t| t as f64),
+                self.until.map(|t| t as f64),
+            );
+        }
+        for (key, value) in &self.metadata {
+            filter = filter.matches(key, value);
+        }
+        filter
+    }
+}
+
 pub struct VectorStore {
-    client: Arc<Qdrant>,
+    backend: Arc<dyn VectorBackend>,
     collection_name: String,
     dimension: u64,
 }
 
 impl VectorStore {
+    /// Stores messages in a Qdrant collection at `url`
     pub async fn new(url: &str, collection_name: &str, dimension: u64) -> Result<Self> {
-        let client = Arc::new(Qdrant::from_url(url).build()?);
+        let backend = Arc::new(QdrantBackend::with_retry(QdrantConfig {
+            url: url.to_string(),
+            ..QdrantConfig::default()
+        })?);
+        Self::with_backend(backend, collection_name, dimension).await
+    }
 
-        // Check if collection exists, create if not
-        let collections = client.list_collections().await?;
-        if !collections
-            .collections
-            .iter()
-            .any(|c| c.name == collection_name)
-        {
-            client
-                .create_collection(
-                    qdrant_client::qdrant::CreateCollectionBuilder::new(collection_name)
-                        .vectors_config(VectorsConfig {
-                            config: Some(qdrant_client::qdrant::vectors_config::Config::Params(
-                                VectorParams {
-                                    size: dimension as u64,
-                                    distance: Distance::Cosine.into(),
-                                    ..Default::default()
-                                },
-                            )),
-                        }),
-                )
-                .await
-                .context("Failed to create collection")?;
-        }
+    /// Stores messages in Qdrant with full connection settings, e.g. an API
+    /// key and TLS for Qdrant Cloud
+    pub async fn connect(
+        config: QdrantConfig,
+        collection_name: &str,
+        dimension: u64,
+    ) -> Result<Self> {
+        let backend = Arc::new(QdrantBackend::with_retry(config)?);
+        Self::with_backend(backend, collection_name, dimension).await
+    }
+
+    /// Stores messages in the in-process backend under `path`, without a
+    /// Qdrant server
+    pub async fn open_local(path: &str, collection_name: &str, dimension: u64) -> Result<Self> {
+        let backend = Arc::new(LocalBackend::open(path)?);
+        Self::with_backend(backend, collection_name, dimension).await
+    }
+
+    /// Stores messages in any vector backend, creating the collection if needed
+    pub async fn with_backend(
+        backend: Arc<dyn VectorBackend>,
+        collection_name: &str,
+        dimension: u64,
+    ) -> Result<Self> {
+        backend
+            .ensure_collection(collection_name, dimension as usize)
+            .await
+            .context("Failed to create collection")?;
 
         Ok(Self {
-            client,
+            backend,
             collection_name: collection_name.to_string(),
             dimension,
         })
     }
 
-    pub async fn store_message(&self, message: Message, embedding: Vec<f32>) -> Result<()> {
+    fn check_dimension(&self, embedding: &[f32]) -> Result<()> {
         if embedding.len() as u64 != self.dimension {
             return Err(anyhow::anyhow!(VectorStoreError::InvalidDimension {
                 expected: self.dimension,
                 actual: embedding.len() as u64,
             }));
         }
+        Ok(())
+    }
 
-        let mut payload = message.metadata.clone();
-        payload.insert("role".to_string(), message.role.clone());
-        payload.insert("content".to_string(), message.content.clone());
-        payload.insert("timestamp".to_string(), message.timestamp.to_string());
-
-        let point = PointStruct {
-            id: Some(message.id.clone().into()),
-            vectors: Some(Vectors::from(embedding)),
-            payload: payload
-                .into_iter()
-                .map(|(k, v)| (k, Value::from(v)))
-                .collect(),
-        };
-
-        self.client
-            .upsert_points(UpsertPointsBuilder::new(&self.collection_name, vec![point]).wait(true))
+    pub async fn store_message(&self, message: Message, embedding: Vec<f32>) -> Result<()> {
+        self.store_messages_batch(vec![(message, embedding)])
             .await
-            .context("Failed to store message")?;
-
-        Ok(())
+            .context("Failed to store message")
     }
 
     pub async fn store_messages_batch(&self, messages: Vec<(Message, Vec<f32>)>) -> Result<()> {
-        let points: Vec<PointStruct> = messages
-            .into_iter()
-            .map(|(message, embedding)| {
-                let mut payload = message.metadata.clone();
-                payload.insert("role".to_string(), message.role.clone());
-                payload.insert("content".to_string(), message.content.clone());
-                payload.insert("timestamp".to_string(), message.timestamp.to_string());
-
-                PointStruct {
-                    id: Some(message.id.clone().into()),
-                    vectors: Some(Vectors::from(embedding)),
-                    payload: payload
-                        .into_iter()
-                        .map(|(k, v)| (k, Value::from(v)))
-                        .collect(),
-                }
-            })
-            .collect();
+        let now = chrono::Utc::now().timestamp_nanos_opt().unwrap_or_default();
+        let mut points = Vec::with_capacity(messages.len());
+        for (i, (message, embedding)) in messages.into_iter().enumerate() {
+            self.check_dimension(&embedding)?;
+            let mut payload = message.to_payload();
+            // Offset by the batch position so a batch keeps its order
+            payload.insert(INSERTED_AT_KEY.to_string(), JsonValue::from(now + i as i64));
+            points.push(VectorPoint::new(message.id.clone(), embedding, payload));
+        }
 
-        self.client
-            .upsert_points(UpsertPointsBuilder::new(&self.collection_name, points).wait(true))
+        self.backend
+            .upsert(&self.collection_name, points)
             .await
-            .context("Failed to store messages batch")?;
-
-        Ok(())
+            .context("Failed to store messages batch")
     }
 
+    /// Most similar messages, optionally limited to those matching `filter`
     pub async fn search_similar(
         &self,
         embedding: Vec<f32>,
         limit: u64,
+        filter: Option<&Filter>,
     ) -> Result<Vec<(String, f32, HashMap<String, String>)>> {
-        if embedding.len() as u64 != self.dimension {
-            return Err(anyhow::anyhow!(VectorStoreError::InvalidDimension {
-                expected: self.dimension,
-                actual: embedding.len() as u64,
-            }));
-        }
+        self.check_dimension(&embedding)?;
 
-        let search_result = self
-            .client
-            .search_points(
-                SearchPointsBuilder::new(&self.collection_name, embedding, limit)
-                    .with_payload(true),
-            )
-            .await
-            .context("Failed to search points")?;
+        let mut query = SearchQuery::new(embedding, limit as usize);
+        if let Some(filter) = filter {
+            query = query.filter(filter.to_payload_filter());
+        }
+        let results = self.backend.search(&self.collection_name, query).await?;
 
-        let results = search_result
-            .result
+        Ok(results
             .into_iter()
-            .map(|point| {
-                let id = point.id.map(|id| format!("{:?}", id)).unwrap_or_default();
-                let score = point.score;
-                let payload = point
-                    .payload
-                    .into_iter()
-                    .map(|(k, v)| (k, v.to_string()))
-                    .collect();
-                (id, score, payload)
-            })
-            .collect();
-
-        Ok(results)
+            .map(|point| (point.id, point.score, payload_strings(&point.payload)))
+            .collect())
     }
 
     pub async fn search_similar_batch(
@@ -179,124 +267,155 @@ impl VectorStore {
     ) -> Result<Vec<Vec<(String, f32, HashMap<String, String>)>>> {
         // Validate dimensions
         for embedding in &embeddings {
-            if embedding.len() as u64 != self.dimension {
-                return Err(anyhow::anyhow!(VectorStoreError::InvalidDimension {
-                    expected: self.dimension,
-                    actual: embedding.len() as u64,
-                }));
-            }
+            self.check_dimension(embedding)?;
         }
 
-        let mut results = Vec::with_capacity(embeddings.len());
-        for embedding in embeddings {
-            let search_result = self
-                .client
-                .search_points(
-                    SearchPointsBuilder::new(&self.collection_name, embedding, limit)
-                        .with_payload(true),
+        // `buffered` keeps the results in the order of `embeddings`
+        futures::stream::iter(embeddings)
+            .map(|embedding| self.search_similar(embedding, limit, None))
+            .buffered(BATCH_SEARCH_CONCURRENCY)
+            .try_collect()
+            .await
+    }
+
+    /// Stored messages in insertion order, without similarity search, for
+    /// exporting or auditing the collection. Pass the returned token as
+    /// `offset` to fetch the next page; `None` means there are no more.
+    ///
+    /// Every call reads all messages matching `filter`, so prefer large pages.
+    pub async fn scroll(
+        &self,
+        offset: Option<&str>,
+        limit: usize,
+        filter: Option<&Filter>,
+    ) -> Result<(Vec<Message>, Option<String>)> {
+        // The token is the insertion time and id of the last message of the
+        // previous page, as messages can share an insertion time
+        let after = offset
+            .map(|token| {
+                token
+                    .split_once(':')
+                    .and_then(|(inserted_at, id)| Some((inserted_at.parse::<i64>().ok()?, id)))
+                    .ok_or_else(|| anyhow::anyhow!("Invalid scroll token: {}", token))
+            })
+            .transpose()?;
+        let payload_filter = filter.map(Filter::to_payload_filter);
+
+        let mut points = Vec::new();
+        let mut page_offset = None;
+        loop {
+            let (page, next) = self
+                .backend
+                .scroll(
+                    &self.collection_name,
+                    payload_filter.clone(),
+                    page_offset,
+                    SCROLL_PAGE_SIZE,
                 )
                 .await
-                .context("Failed to search points")?;
-
-            let batch_results = search_result
-                .result
-                .into_iter()
-                .map(|point| {
-                    let id = point.id.map(|id| format!("{:?}", id)).unwrap_or_default();
-                    let score = point.score;
-                    let payload = point
-                        .payload
-                        .into_iter()
-                        .map(|(k, v)| (k, v.to_string()))
-                        .collect();
-                    (id, score, payload)
-                })
-                .collect();
-
-            results.push(batch_results);
+                .context("Failed to scroll messages")?;
+            points.extend(
+                page.into_iter()
+                    .map(|point| (Message::inserted_at(&point.payload), point))
+                    .filter(|(inserted_at, point)| match after {
+                        Some(after) => (*inserted_at, point.id.as_str()) > after,
+                        None => true,
+                    }),
+            );
+            match next {
+                Some(next) => page_offset = Some(next),
+                None => break,
+            }
         }
 
-        Ok(results)
+        points.sort_by(|(a, point_a), (b, point_b)| a.cmp(b).then(point_a.id.cmp(&point_b.id)));
+        let next = match limit.checked_sub(1).and_then(|last| points.get(last)) {
+            Some((inserted_at, point)) if points.len() > limit => {
+                Some(format!("{}:{}", inserted_at, point.id))
+            }
+            _ => None,
+        };
+        let messages = points
+            .into_iter()
+            .take(limit)
+            .map(|(_, point)| Message::from_payload(point.id, &point.payload))
+            .collect();
+        Ok((messages, next))
     }
 
-    pub async fn delete_message(&self, message_id: &str) -> Result<()> {
-        self.client
-            .delete_points(
-                DeletePointsBuilder::new(&self.collection_name)
-                    .points(PointsIdsList {
-                        ids: vec![message_id.into()],
-                    })
-                    .wait(true),
-            )
+    /// Sets metadata entries of a stored message without re-embedding it,
+    /// e.g. tags or a read flag
+    pub async fn update_metadata(
+        &self,
+        message_id: &str,
+        patch: HashMap<String, String>,
+    ) -> Result<()> {
+        let patch: Payload = patch
+            .into_iter()
+            .map(|(k, v)| (k, JsonValue::from(v)))
+            .collect();
+        self.backend
+            .update_payload(&self.collection_name, message_id, patch)
             .await
-            .context("Failed to delete message")?;
+            .context("Failed to update message metadata")
+    }
 
-        Ok(())
+    /// Backend name and location, e.g. `qdrant (http://localhost:6334)`
+    pub fn backend_name(&self) -> String {
+        self.backend.name()
     }
 
-    pub async fn delete_messages_batch(&self, message_ids: Vec<String>) -> Result<()> {
-        self.client
-            .delete_points(
-                DeletePointsBuilder::new(&self.collection_name)
-                    .points(PointsIdsList {
-                        ids: message_ids.into_iter().map(|id| id.into()).collect(),
-                    })
-                    .wait(true),
-            )
+    /// Checks that the backend is reachable and returns its status
+    pub async fn health(&self) -> Result<String> {
+        self.backend.health().await
+    }
+
+    /// Number of stored messages, optionally only those matching `filter`
+    pub async fn count(&self, filter: Option<&Filter>) -> Result<u64> {
+        self.backend
+            .count(&self.collection_name, filter.map(Filter::to_payload_filter))
             .await
-            .context("Failed to delete messages batch")?;
+    }
 
-        Ok(())
+    /// Backs up the stored messages into `dir`, returning the snapshot file
+    pub async fn create_snapshot(&self, dir: &Path) -> Result<PathBuf> {
+        self.backend
+            .create_snapshot(&self.collection_name, dir)
+            .await
     }
 
-    pub async fn get_message(&self, message_id: &str) -> Result<Option<Message>> {
-        let result = self
-            .client
-            .get_points(
-                GetPointsBuilder::new(&self.collection_name, vec![message_id.into()])
-                    .with_payload(true),
-            )
+    /// Replaces the stored messages with a snapshot from `create_snapshot`
+    pub async fn restore_snapshot(&self, path: &Path) -> Result<()> {
+        self.backend
+            .restore_snapshot(&self.collection_name, path)
             .await
-            .context("Failed to get message")?;
+    }
 
-        if result.result.is_empty() {
-            return Ok(None);
-        }
+    pub async fn delete_message(&self, message_id: &str) -> Result<()> {
+        self.backend
+            .delete(&self.collection_name, &[message_id.to_string()])
+            .await
+            .context("Failed to delete message")
+    }
 
-        let point = &result.result[0];
-        let payload = &point.payload;
-
-        let role = payload
-            .get("role")
-            .map(|v| v.to_string())
-            .unwrap_or_default();
-        let content = payload
-            .get("content")
-            .map(|v| v.to_string())
-            .unwrap_or_default();
-        let timestamp = payload
-            .get("timestamp")
-            .and_then(|v| v.to_string().parse().ok())
-            .unwrap_or_default();
-
-        let mut metadata = HashMap::new();
-        for (k, v) in payload {
-            if k != "role" && k != "content" && k != "timestamp" {
-                metadata.insert(k.clone(), v.to_string());
-            }
-        }
+    pub async fn delete_messages_batch(&self, message_ids: Vec<String>) -> Result<()> {
+        self.backend
+            .delete(&self.collection_name, &message_ids)
+            .await
+            .context("Failed to delete messages batch")
+    }
+
+    pub async fn get_message(&self, message_id: &str) -> Result<Option<Message>> {
+        let points = self
+            .backend
+            .get(&self.collection_name, &[message_id.to_string()])
+            .await
+            .context("Failed to get message")?;
 
-        Ok(Some(Message {
-            id: point
-                .id
-                .as_ref()
-                .map(|id| format!("{:?}", id))
-                .unwrap_or_default(),
-            role,
-            content,
-            timestamp,
-            metadata,
-        }))
+        Ok(points
+            .into_iter()
+            .next()
+            .map(|point| Message::from_payload(point.id, &point.payload)))
     }
 }
 
@@ -348,12 +467,119 @@ mod tests {
         let embedding = vec![0.1; 384];
         store.store_message(message, embedding.clone()).await?;
 
-        let results = store.search_similar(embedding, 1).await?;
+        let results = store.search_similar(embedding, 1, None).await?;
         assert!(!results.is_empty());
 
         Ok(())
     }
 
+    #[tokio::test]
+    async fn test_scroll_in_insertion_order() -> Result<()> {
+        let dir = tempfile::TempDir::new()?;
+        let backend = Arc::new(LocalBackend::open(dir.path())?);
+        let store = VectorStore::with_backend(backend, "messages", 2).await?;
+
+        let messages: Vec<(Message, Vec<f32>)> = ["first", "second", "third"]
+            .iter()
+            .map(|content| {
+                (
+                    Message {
+                        id: uuid::Uuid::new_v4().to_string(),
+                        role: "user".to_string(),
+                        content: content.to_string(),
+                        timestamp: Utc::now().timestamp(),
+                        metadata: HashMap::new(),
+                    },
+                    vec![0.1, 0.2],
+                )
+            })
+            .collect();
+        store.store_messages_batch(messages).await?;
+
+        let (page, next) = store.scroll(None, 2, None).await?;
+        let contents: Vec<&str> = page.iter().map(|m| m.content.as_str()).collect();
+        assert_eq!(contents, vec!["first", "second"]);
+        assert!(page[0].metadata.is_empty());
+
+        let (page, next) = store.scroll(next.as_deref(), 2, None).await?;
+        assert_eq!(page.len(), 1);
+        assert_eq!(page[0].content, "third");
+        assert!(next.is_none());
+
+        let (page, _) = store
+            .scroll(None, 10, Some(&Filter::new().role("assistant")))
+            .await?;
+        assert!(page.is_empty());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_scroll_with_equal_insertion_times() -> Result<()> {
+        let dir = tempfile::TempDir::new()?;
+        let backend = Arc::new(LocalBackend::open(dir.path())?);
+        let store = VectorStore::with_backend(backend.clone(), "messages", 2).await?;
+
+        // Messages stored without an insertion time fall back to their
+        // timestamp, which several messages can share
+        let points = (0..5)
+            .map(|i| {
+                let message = Message {
+                    id: format!("m{}", i),
+                    role: "user".to_string(),
+                    content: format!("message {}", i),
+                    timestamp: 1_700_000_000,
+                    metadata: HashMap::new(),
+                };
+                VectorPoint::new(message.id.clone(), vec![0.1, 0.2], message.to_payload())
+            })
+            .collect();
+        backend.upsert("messages", points).await?;
+
+        let mut ids = Vec::new();
+        let mut offset = None;
+        for _ in 0..5 {
+            let (page, next) = store.scroll(offset.as_deref(), 2, None).await?;
+            ids.extend(page.into_iter().map(|m| m.id));
+            offset = match next {
+                Some(next) => Some(next),
+                None => break,
+            };
+        }
+        assert_eq!(ids, vec!["m0", "m1", "m2", "m3", "m4"]);
+        assert!(store.scroll(Some("12"), 2, None).await.is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_filter_to_payload_filter() {
+        let message = Message {
+            id: "1".to_string(),
+            role: "user".to_string(),
+            content: "Hello".to_string(),
+            timestamp: 1_700_000_000,
+            metadata: HashMap::from([("session".to_string(), "abc".to_string())]),
+        };
+        let payload = message.to_payload();
+
+        let filter = Filter::new()
+            .role("user")
+            .since(1_699_999_000)
+            .metadata("session", "abc");
+        assert!(filter.to_payload_filter().accepts(&payload));
+        assert!(!Filter::new()
+            .role("assistant")
+            .to_payload_filter()
+            .accepts(&payload));
+        assert!(!Filter::new()
+            .until(1_699_999_000)
+            .to_payload_filter()
+            .accepts(&payload));
+        assert!(!Filter::new()
+            .metadata("session", "xyz")
+            .to_payload_filter()
+            .accepts(&payload));
+    }
+
     #[tokio::test]
     async fn test_store_messages_batch() -> Result<()> {
         let store = create_test_store().await?;
@@ -477,6 +703,96 @@ mod tests {
         Ok(())
     }
 
+    /// Backend that answers every search after a fixed delay, like a remote
+    /// server would
+    struct SlowBackend {
+        inner: LocalBackend,
+        delay: std::time::Duration,
+    }
+
+    #[async_trait::async_trait]
+    impl VectorBackend for SlowBackend {
+        fn name(&self) -> String {
+            "slow".to_string()
+        }
+
+        async fn collection_info(
+            &self,
+            collection: &str,
+        ) -> Result<Option<crate::vector_backend::CollectionInfo>> {
+            self.inner.collection_info(collection).await
+        }
+
+        async fn create_collection(&self, collection: &str, dimension: usize) -> Result<()> {
+            self.inner.create_collection(collection, dimension).await
+        }
+
+        async fn upsert(&self, collection: &str, points: Vec<VectorPoint>) -> Result<()> {
+            self.inner.upsert(collection, points).await
+        }
+
+        async fn search(
+            &self,
+            collection: &str,
+            query: SearchQuery,
+        ) -> Result<Vec<crate::vector_backend::ScoredPoint>> {
+            tokio::time::sleep(self.delay).await;
+            self.inner.search(collection, query).await
+        }
+
+        async fn get(&self, collection: &str, ids: &[String]) -> Result<Vec<VectorPoint>> {
+            self.inner.get(collection, ids).await
+        }
+
+        async fn delete(&self, collection: &str, ids: &[String]) -> Result<()> {
+            self.inner.delete(collection, ids).await
+        }
+
+        async fn scroll(
+            &self,
+            collection: &str,
+            filter: Option<PayloadFilter>,
+            offset: Option<String>,
+            limit: usize,
+        ) -> Result<(Vec<VectorPoint>, Option<String>)> {
+            self.inner.scroll(collection, filter, offset, limit).await
+        }
+    }
+
+    #[tokio::test]
+    async fn test_search_similar_batch_runs_concurrently() -> Result<()> {
+        let dir = tempfile::TempDir::new()?;
+        let delay = std::time::Duration::from_millis(50);
+        let backend = Arc::new(SlowBackend {
+            inner: LocalBackend::open(dir.path())?,
+            delay,
+        });
+        let store = VectorStore::with_backend(backend, "messages", 2).await?;
+
+        let embeddings: Vec<Vec<f32>> = (0..8).map(|i| vec![1.0, i as f32]).collect();
+
+        let started = std::time::Instant::now();
+        for embedding in embeddings.clone() {
+            store.search_similar(embedding, 1, None).await?;
+        }
+        let sequential = started.elapsed();
+
+        let started = std::time::Instant::now();
+        let results = store.search_similar_batch(embeddings, 1).await?;
+        let batched = started.elapsed();
+
+        assert_eq!(results.len(), 8);
+        assert!(sequential >= delay * 8);
+        // All eight searches fit in one round of BATCH_SEARCH_CONCURRENCY
+        assert!(
+            batched < sequential / 2,
+            "batch search took {:?}, sequential {:?}",
+            batched,
+            sequential
+        );
+        Ok(())
+    }
+
     #[tokio::test]
     async fn test_invalid_dimension() -> Result<()> {
         let store = create_test_store().await?;
@@ -500,4 +816,3 @@ mod tests {
         Ok(())
     }
 }
- 
\ No newline at end of file
@@ -3,9 +3,14 @@ mod tests {
     use anyhow::Result;
     use mcp_chatbot::{
         llm_client::{LLMClient, LLMProvider},
-        load_system_prompts, ChatSession, Configuration, Message, Server, Tool,
+        load_system_prompts,
+        server::{find_tool_server, tool_collisions},
+        server_manager::{ServerManager, ServerState, SupervisorConfig},
+        stdio_server::McpService,
+        tool_policy::Permission,
+        ChatSession, Configuration, Message, Server, Tool,
     };
-    use serde_json::json;
+    use serde_json::{json, Value};
     use std::sync::Arc;
     use tokio::sync::Mutex;
 
@@ -166,15 +171,689 @@ mod tests {
             .ok_or_else(|| anyhow::anyhow!("Memory server not found"))?;
 
         let result = memory_server
+            .execute_tool("memory_set", &json!({"key": "test", "value": "value"}))
+            .await?;
+
+        assert!(result["success"].as_bool().unwrap_or(false));
+        Ok(())
+    }
+
+    /// Independent tool calls of one response run at the same time and
+    /// their results keep the order of the calls
+    #[tokio::test]
+    async fn test_parallel_tool_calls() -> Result<()> {
+        let config = Configuration::new()
+            .load_llm_config("config/config.yaml")
+            .unwrap();
+        let llm_client = Arc::new(Mutex::new(LLMClient::new(config, LLMProvider::Ollama)));
+        let mut session = ChatSession::new(llm_client).await?;
+
+        let slow_config = json!({ "command": "memory", "args": [], "env": {} });
+        let mut slow_server = Server::new("slow".to_string(), slow_config).await?;
+        let echo_tool = Tool::new(
+            "slow_echo".to_string(),
+            "Echoes its text after a while".to_string(),
+            json!({
+                "type": "object",
+                "properties": { "text": { "type": "string" } },
+                "required": ["text"]
+            }),
+            None,
+            Box::new(|args| {
+                Box::pin(async move {
+                    tokio::time::sleep(std::time::Duration::from_millis(300)).await;
+                    Ok(json!({ "text": args["text"] }))
+                })
+            }),
+        );
+        slow_server.mcp_server.register_tool(echo_tool).await?;
+        session.servers.push(slow_server);
+
+        let calls = vec![
+            ("slow_echo".to_string(), json!({ "text": "first" })),
+            ("missing_tool".to_string(), json!({})),
+            ("slow_echo".to_string(), json!({ "text": "second" })),
+            ("slow_echo".to_string(), json!({ "text": "third" })),
+        ];
+        let started = std::time::Instant::now();
+        let results = session.call_tools(&calls).await;
+        assert!(started.elapsed() < std::time::Duration::from_millis(800));
+
+        assert_eq!(results.len(), 4);
+        assert_eq!(results[0].content["text"], "first");
+        assert!(results[1].is_error);
+        assert_eq!(results[2].content["text"], "second");
+        assert_eq!(results[3].content["text"], "third");
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_persistent_memory_server() -> Result<()> {
+        let temp_dir = tempfile::tempdir()?;
+        let memory_config = json!({
+            "command": "memory",
+            "args": [],
+            "env": {},
+            "database": temp_dir.path().join("memory.db").to_str().unwrap()
+        });
+        let mut server = Server::new("memory".to_string(), memory_config.clone()).await?;
+        server
+            .execute_tool("memory_set", &json!({"key": "name", "value": "Ada"}))
+            .await?;
+        server.cleanup().await?;
+        drop(server);
+
+        // A new server over the same database remembers the value
+        let mut server = Server::new("memory".to_string(), memory_config).await?;
+        let result = server
+            .execute_tool("memory_get", &json!({"key": "name"}))
+            .await?;
+        assert_eq!(result["value"], "Ada");
+        assert_eq!(result["exists"], true);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_file_server_policy() -> Result<()> {
+        let temp_dir = tempfile::tempdir()?;
+        std::fs::create_dir_all(temp_dir.path().join("docs"))?;
+        std::fs::write(temp_dir.path().join("docs/guide.md"), "# Guide")?;
+        std::fs::write(temp_dir.path().join("docs/.env"), "TOKEN=secret")?;
+        let file_config = json!({
+            "command": "file",
+            "args": [temp_dir.path().to_str().unwrap()],
+            "env": {},
+            "read_only": true,
+            "deny": ["**/.env"]
+        });
+        let mut server = Server::new("file".to_string(), file_config).await?;
+        let result = server
+            .execute_tool("file_read", &json!({"path": "docs/guide.md"}))
+            .await?;
+        assert_eq!(result["content"], "# Guide");
+
+        let result = server
+            .execute_tool("list_directory", &json!({"path": "docs"}))
+            .await?;
+        assert_eq!(result["entries"].as_array().unwrap().len(), 1);
+
+        assert!(server
+            .execute_tool("file_read", &json!({"path": "docs/.env"}))
+            .await
+            .is_err());
+        assert!(server
             .execute_tool(
-                "memory_set",
-                &json!({"key": "test", "value": "value"}),
-                3,
-                std::time::Duration::from_secs(1),
+                "file_write",
+                &json!({"path": "docs/new.md", "content": "x"})
             )
+            .await
+            .is_err());
+        assert!(!temp_dir.path().join("docs/new.md").exists());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_tool_policy() -> Result<()> {
+        let temp_dir = tempfile::tempdir()?;
+        std::fs::write(temp_dir.path().join("notes.txt"), "hello")?;
+        let file_config = json!({
+            "command": "file",
+            "args": [temp_dir.path().to_str().unwrap()],
+            "env": {},
+            "tool_policy": {
+                "tools": { "file_delete": "deny", "file_write": "confirm" }
+            }
+        });
+        let mut server = Server::new("file".to_string(), file_config).await?;
+        assert_eq!(server.permission("file_write"), Permission::Confirm);
+        assert_eq!(server.permission("file/file_delete"), Permission::Deny);
+        assert_eq!(server.permission("file_read"), Permission::Safe);
+
+        // Denied tools are hidden and refused
+        let tools = server.list_tools().await?;
+        assert!(!tools.iter().any(|tool| tool.name == "file_delete"));
+        assert!(tools.iter().any(|tool| tool.name == "file_write"));
+        let error = server
+            .execute_tool("file_delete", &json!({"path": "notes.txt"}))
+            .await
+            .unwrap_err();
+        assert!(error.to_string().contains("denied"));
+        assert!(temp_dir.path().join("notes.txt").exists());
+
+        let config = json!({
+            "command": "file",
+            "args": [],
+            "env": {},
+            "tool_policy": { "default": "sometimes" }
+        });
+        assert!(Server::new("file".to_string(), config).await.is_err());
+        Ok(())
+    }
+
+    /// A request the external server rejects is not sent again
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_retry_policy() -> Result<()> {
+        let script = r#"
+            read -r line
+            echo '{"jsonrpc":"2.0","id":1,"result":{"protocolVersion":"2024-11-05","capabilities":{"tools":{}},"serverInfo":{"name":"strict","version":"0.1.0"}}}'
+            read -r line
+            read -r line
+            echo '{"jsonrpc":"2.0","id":2,"result":{"tools":[{"name":"lookup","inputSchema":{"type":"object"}}]}}'
+            read -r line
+            echo '{"jsonrpc":"2.0","id":3,"error":{"code":-32602,"message":"Missing argument key"}}'
+            read -r line
+        "#;
+        let config = json!({
+            "command": "sh",
+            "args": ["-c", script],
+            "env": {},
+            "timeout": 5,
+            "retry": { "max_retries": 2, "initial_backoff_ms": 10 }
+        });
+        let mut server = Server::new("strict".to_string(), config).await?;
+        let error = server.execute_tool("lookup", &json!({})).await.unwrap_err();
+        assert!(
+            error.to_string().contains("Missing argument key"),
+            "{}",
+            error
+        );
+        server.cleanup().await?;
+
+        let config = json!({
+            "command": "memory",
+            "args": [],
+            "env": {},
+            "retry": { "max_retries": "often" }
+        });
+        assert!(Server::new("memory".to_string(), config).await.is_err());
+        Ok(())
+    }
+
+    /// A server whose process exits is restarted by the next health check
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_server_supervision() -> Result<()> {
+        // The first process exits once it has listed its tools
+        let script = r#"
+            while read -r line; do
+                id=$(printf '%s' "$line" | sed -n 's/.*"id":\([0-9]*\).*/\1/p')
+                case "$line" in
+                    *'"initialize"'*)
+                        printf '{"jsonrpc":"2.0","id":%s,"result":{"protocolVersion":"2024-11-05","capabilities":{"tools":{}},"serverInfo":{"name":"flaky","version":"0.1.0"}}}\n' "$id" ;;
+                    *'"tools/list"'*)
+                        printf '{"jsonrpc":"2.0","id":%s,"result":{"tools":[{"name":"status","inputSchema":{"type":"object"}}]}}\n' "$id"
+                        if [ ! -e "$MARKER" ]; then touch "$MARKER"; exit 0; fi ;;
+                    *'"ping"'*)
+                        printf '{"jsonrpc":"2.0","id":%s,"result":{}}\n' "$id" ;;
+                esac
+            done
+        "#;
+        let temp_dir = tempfile::tempdir()?;
+        let config = json!({
+            "command": "sh",
+            "args": ["-c", script],
+            "env": { "MARKER": temp_dir.path().join("started").to_str().unwrap() },
+            "timeout": 5
+        });
+        let server = Server::new("flaky".to_string(), config).await?;
+        let supervisor = SupervisorConfig {
+            health_check_secs: 0,
+            ..SupervisorConfig::default()
+        };
+        let mut servers = ServerManager::new(vec![server], supervisor);
+        assert_eq!(servers.status()[0].state, ServerState::Up);
+        assert!(servers.status()[0].external);
+
+        servers.supervise().await;
+        let status = &servers.status()[0];
+        assert_eq!(status.state, ServerState::Up, "{:?}", status.last_error);
+        assert_eq!(status.restarts, 1);
+        assert!(status.last_error.is_some());
+
+        // The new process stays up and offers the same tools
+        servers.check_health().await;
+        let status = &servers.status()[0];
+        assert_eq!(status.state, ServerState::Up);
+        assert_eq!(status.restarts, 1);
+        assert!(status.last_error.is_none());
+        assert_eq!(servers[0].list_tools().await?[0].name, "status");
+        servers.cleanup().await;
+        Ok(())
+    }
+
+    /// Spawns a scripted MCP server that answers each request in turn
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_external_stdio_server() -> Result<()> {
+        let script = r#"
+            read -r line
+            echo '{"jsonrpc":"2.0","id":1,"result":{"protocolVersion":"2024-11-05","capabilities":{"tools":{}},"serverInfo":{"name":"echo","version":"0.1.0"}}}'
+            read -r line
+            read -r line
+            echo '{"jsonrpc":"2.0","method":"notifications/message","params":{"level":"info","data":"ready"}}'
+            echo '{"jsonrpc":"2.0","id":2,"result":{"tools":[{"name":"echo","description":"Echo the text back","inputSchema":{"type":"object","properties":{"text":{"type":"string"}},"required":["text"]}}]}}'
+            read -r line
+            printf '%s\n' '{"jsonrpc":"2.0","id":3,"result":{"content":[{"type":"text","text":"{\"text\":\"hello\"}"}]}}'
+            read -r line
+        "#;
+        let config = json!({
+            "command": "sh",
+            "args": ["-c", script],
+            "env": {},
+            "timeout": 5
+        });
+        let mut server = Server::new("echo".to_string(), config).await?;
+        let tools = server.list_tools().await?;
+        assert_eq!(tools.len(), 1);
+        assert_eq!(tools[0].name, "echo");
+        assert_eq!(tools[0].input_schema["required"], json!(["text"]));
+
+        let result = server
+            .execute_tool("echo", &json!({"text": "hello"}))
             .await?;
+        assert_eq!(result, json!({"text": "hello"}));
+        server.cleanup().await?;
 
-        assert!(result["success"].as_bool().unwrap_or(false));
+        let config = json!({
+            "command": "mcp-chatbot-no-such-command",
+            "args": [],
+            "env": {}
+        });
+        assert!(Server::new("missing".to_string(), config).await.is_err());
+        Ok(())
+    }
+
+    /// Lists and renders the prompts of an external server
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_external_prompts() -> Result<()> {
+        let script = r#"
+            read -r line
+            echo '{"jsonrpc":"2.0","id":1,"result":{"protocolVersion":"2024-11-05","capabilities":{"prompts":{}},"serverInfo":{"name":"prompts","version":"0.1.0"}}}'
+            read -r line
+            read -r line
+            echo '{"jsonrpc":"2.0","id":2,"result":{"prompts":[{"name":"code_review","description":"Asks for a review","arguments":[{"name":"code","required":true}]}]}}'
+            read -r line
+            case "$line" in
+                *'"code":"fn main() {}"'*) echo '{"jsonrpc":"2.0","id":3,"result":{"messages":[{"role":"assistant","content":{"type":"text","text":"I review code."}},{"role":"user","content":{"type":"text","text":"Review fn main() {}"}}]}}' ;;
+            esac
+            read -r line
+        "#;
+        let config = json!({
+            "command": "sh",
+            "args": ["-c", script],
+            "env": {},
+            "timeout": 5
+        });
+        let mut server = Server::new("prompts".to_string(), config).await?;
+        assert!(server.list_tools().await?.is_empty());
+        let prompts = server.list_prompts().await?;
+        assert_eq!(prompts.len(), 1);
+        assert_eq!(prompts[0].name, "code_review");
+        assert!(prompts[0].arguments[0].required);
+
+        let prompt = server
+            .get_prompt("code_review", Some(json!({ "code": "fn main() {}" })))
+            .await?;
+        assert_eq!(prompt.description.as_deref(), Some("Asks for a review"));
+        assert_eq!(prompt.messages.len(), 2);
+        assert_eq!(prompt.messages[1].role, "user");
+        assert!(server.get_prompt("missing", None).await.is_err());
+        server.cleanup().await?;
+        Ok(())
+    }
+
+    /// Tells apart tools of the same name on different servers
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_tool_namespacing() -> Result<()> {
+        let temp_dir = tempfile::tempdir()?;
+        std::fs::write(temp_dir.path().join("notes.txt"), "hello")?;
+        let file_config = json!({
+            "command": "file",
+            "args": [temp_dir.path().to_str().unwrap()],
+            "env": {}
+        });
+        let script = r#"
+            read -r line
+            echo '{"jsonrpc":"2.0","id":1,"result":{"protocolVersion":"2024-11-05","capabilities":{"tools":{}},"serverInfo":{"name":"remote","version":"0.1.0"}}}'
+            read -r line
+            read -r line
+            echo '{"jsonrpc":"2.0","id":2,"result":{"tools":[{"name":"file_read","description":"Read a remote file","inputSchema":{"type":"object"}}]}}'
+            read -r line
+            echo '{"jsonrpc":"2.0","id":3,"result":{"content":[{"type":"text","text":"remote"}]}}'
+            read -r line
+        "#;
+        let remote_config = json!({
+            "command": "sh",
+            "args": ["-c", script],
+            "env": {},
+            "timeout": 5
+        });
+        let mut servers = vec![
+            Server::new("file".to_string(), file_config).await?,
+            Server::new("remote".to_string(), remote_config).await?,
+        ];
+
+        let collisions = tool_collisions(&servers).await?;
+        assert_eq!(collisions.len(), 1);
+        assert_eq!(collisions[0].0, "file_read");
+        let ids: Vec<String> = collisions[0].1.iter().map(|id| id.to_string()).collect();
+        assert_eq!(ids, vec!["file/file_read", "remote/file_read"]);
+
+        assert_eq!(find_tool_server(&servers, "file_read").await, Some(0));
+        assert_eq!(find_tool_server(&servers, "file/file_read").await, Some(0));
+        assert_eq!(
+            find_tool_server(&servers, "remote/file_read").await,
+            Some(1)
+        );
+        assert_eq!(find_tool_server(&servers, "remote/file_write").await, None);
+        assert_eq!(find_tool_server(&servers, "missing").await, None);
+
+        let result = servers[0]
+            .execute_tool("file/file_read", &json!({"path": "notes.txt"}))
+            .await?;
+        assert_eq!(result["content"], "hello");
+        let result = servers[1]
+            .execute_tool("remote/file_read", &json!({"path": "notes.txt"}))
+            .await?;
+        assert_eq!(result, "remote");
+
+        for server in &mut servers {
+            server.cleanup().await?;
+        }
+        Ok(())
+    }
+
+    /// Serves a file server over an in-memory stream
+    #[tokio::test]
+    async fn test_mcp_service() -> Result<()> {
+        let temp_dir = tempfile::tempdir()?;
+        std::fs::write(temp_dir.path().join("notes.txt"), "hello")?;
+        let file_config = json!({
+            "command": "file",
+            "args": [temp_dir.path().to_str().unwrap()],
+            "env": {}
+        });
+        let server = Server::new("file".to_string(), file_config).await?;
+        let mut service = McpService::new(vec![server]);
+
+        let input = [
+            r#"{"jsonrpc":"2.0","id":1,"method":"initialize","params":{"protocolVersion":"2024-11-05","capabilities":{},"clientInfo":{"name":"test","version":"1.0"}}}"#,
+            r#"{"jsonrpc":"2.0","method":"notifications/initialized"}"#,
+            r#"{"jsonrpc":"2.0","id":2,"method":"tools/list"}"#,
+            r#"{"jsonrpc":"2.0","id":3,"method":"tools/call","params":{"name":"file_read","arguments":{"path":"notes.txt"}}}"#,
+            r#"{"jsonrpc":"2.0","id":4,"method":"tools/call","params":{"name":"file_read","arguments":{"path":"missing.txt"}}}"#,
+            r#"{"jsonrpc":"2.0","id":5,"method":"tools/call","params":{"name":"no_such_tool"}}"#,
+            r#"{"jsonrpc":"2.0","id":"six","method":"resources/read","params":{"uri":"filesystem?action=cwd"}}"#,
+            "not json",
+            r#"{"jsonrpc":"2.0","id":7,"method":"ping"}"#,
+            r#"{"jsonrpc":"2.0","id":8,"method":"prompts/list"}"#,
+        ]
+        .join("\n");
+        let mut output = Vec::new();
+        service.serve(input.as_bytes(), &mut output).await?;
+
+        let responses: Vec<Value> = String::from_utf8(output)?
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        // The notification gets no response
+        assert_eq!(responses.len(), 9);
+        assert_eq!(responses[0]["result"]["protocolVersion"], "2024-11-05");
+        assert_eq!(responses[0]["result"]["serverInfo"]["name"], "mcp-chatbot");
+        assert!(responses[0]["result"]["capabilities"]["tools"].is_object());
+        assert!(responses[0]["result"]["capabilities"]["prompts"].is_null());
+
+        let tools = responses[1]["result"]["tools"].as_array().unwrap();
+        let file_read = tools
+            .iter()
+            .find(|tool| tool["name"] == "file_read")
+            .unwrap();
+        assert!(file_read["inputSchema"]["properties"]["path"].is_object());
+
+        let call = &responses[2]["result"];
+        assert!(call.get("isError").is_none());
+        assert_eq!(call["structuredContent"]["content"], "hello");
+        let content: Value = serde_json::from_str(call["content"][0]["text"].as_str().unwrap())?;
+        assert_eq!(content["content"], "hello");
+
+        // Tool failures are results, unknown tools are errors
+        assert_eq!(responses[3]["result"]["isError"], true);
+        let error = &responses[3]["result"]["structuredContent"];
+        assert_eq!(error["kind"], "not_found");
+        assert_eq!(error["message"], "File not found: missing.txt");
+        assert_eq!(error["retryable"], false);
+        assert_eq!(responses[4]["id"], 5);
+        assert_eq!(responses[4]["error"]["code"], -32602);
+
+        assert_eq!(responses[5]["id"], "six");
+        assert_eq!(
+            responses[5]["result"]["contents"][0]["uri"],
+            "filesystem?action=cwd"
+        );
+        assert_eq!(responses[6]["id"], Value::Null);
+        assert_eq!(responses[6]["error"]["code"], -32700);
+        assert_eq!(
+            responses[7],
+            json!({"jsonrpc": "2.0", "id": 7, "result": {}})
+        );
+        // No server offers prompts
+        assert_eq!(responses[8]["error"]["code"], -32601);
+        service.cleanup().await?;
+
+        // Only ping is answered before initialize
+        let mut service = McpService::new(Vec::new());
+        let response = service
+            .handle_line(r#"{"jsonrpc":"2.0","id":1,"method":"tools/list"}"#)
+            .await
+            .unwrap();
+        assert_eq!(response.into_result().unwrap_err().code, -32600);
+        let response = service
+            .handle_line(r#"{"jsonrpc":"2.0","id":2,"method":"ping"}"#)
+            .await
+            .unwrap();
+        assert!(response.into_result().is_ok());
+        Ok(())
+    }
+
+    /// The result of the request `line` to `service`
+    async fn request(
+        service: &mut McpService,
+        line: &str,
+    ) -> std::result::Result<Value, mcp_chatbot::protocol::JsonRpcError> {
+        service.handle_line(line).await.unwrap().into_result()
+    }
+
+    /// Reads files through the `file:///{path}` resource template
+    #[tokio::test]
+    async fn test_mcp_resource_templates() -> Result<()> {
+        let temp_dir = tempfile::tempdir()?;
+        std::fs::create_dir(temp_dir.path().join("docs"))?;
+        std::fs::write(temp_dir.path().join("docs/my notes.txt"), "hello")?;
+        let file_config = json!({
+            "command": "file",
+            "args": [temp_dir.path().to_str().unwrap()],
+            "env": {}
+        });
+        let server = Server::new("file".to_string(), file_config).await?;
+        let mut service = McpService::new(vec![server]);
+        request(
+            &mut service,
+            r#"{"jsonrpc":"2.0","id":1,"method":"initialize","params":{"protocolVersion":"2024-11-05","capabilities":{},"clientInfo":{"name":"test","version":"1.0"}}}"#,
+        )
+        .await
+        .unwrap();
+
+        // Templates are listed apart from the fixed resources
+        let resources = request(
+            &mut service,
+            r#"{"jsonrpc":"2.0","id":2,"method":"resources/list"}"#,
+        )
+        .await
+        .unwrap();
+        let uris: Vec<&str> = resources["resources"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .filter_map(|resource| resource["uri"].as_str())
+            .collect();
+        assert_eq!(uris, vec!["filesystem"]);
+        let templates = request(
+            &mut service,
+            r#"{"jsonrpc":"2.0","id":3,"method":"resources/templates/list"}"#,
+        )
+        .await
+        .unwrap();
+        assert_eq!(
+            templates["resourceTemplates"][0]["uriTemplate"],
+            "file:///{path}"
+        );
+
+        let result = request(
+            &mut service,
+            r#"{"jsonrpc":"2.0","id":4,"method":"resources/read","params":{"uri":"file:///docs/my%20notes.txt"}}"#,
+        )
+        .await
+        .unwrap();
+        let content: Value = serde_json::from_str(result["contents"][0]["text"].as_str().unwrap())?;
+        assert_eq!(content["path"], "docs/my notes.txt");
+        assert_eq!(content["content"], "hello");
+
+        // Paths outside the root are refused like they are for file_read
+        let error = request(
+            &mut service,
+            r#"{"jsonrpc":"2.0","id":5,"method":"resources/read","params":{"uri":"file:///../secret.txt"}}"#,
+        )
+        .await
+        .unwrap_err();
+        assert_eq!(error.code, -32603);
+        let error = request(
+            &mut service,
+            r#"{"jsonrpc":"2.0","id":6,"method":"resources/read","params":{"uri":"db://tables/users"}}"#,
+        )
+        .await
+        .unwrap_err();
+        assert_eq!(error.code, -32602);
+        service.cleanup().await?;
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_mcp_progress_notifications() -> Result<()> {
+        let temp_dir = tempfile::tempdir()?;
+        let file_config = json!({
+            "command": "file",
+            "args": [temp_dir.path().to_str().unwrap()],
+            "env": {}
+        });
+        let mut server = Server::new("file".to_string(), file_config).await?;
+        server
+            .mcp_server
+            .register_tool(Tool::with_progress(
+                "count".to_string(),
+                "Counts to two".to_string(),
+                json!({ "type": "object" }),
+                None,
+                Box::new(|_, progress| {
+                    Box::pin(async move {
+                        progress.report(1.0, Some(2.0), Some("one"));
+                        progress.report(2.0, Some(2.0), Some("two"));
+                        Ok(json!({ "counted": 2 }))
+                    })
+                }),
+            ))
+            .await?;
+        let mut service = McpService::new(vec![server]);
+
+        let input = [
+            r#"{"jsonrpc":"2.0","id":1,"method":"initialize","params":{"protocolVersion":"2024-11-05","capabilities":{},"clientInfo":{"name":"test","version":"1.0"}}}"#,
+            r#"{"jsonrpc":"2.0","id":2,"method":"tools/call","params":{"name":"count","_meta":{"progressToken":"count-1"}}}"#,
+            r#"{"jsonrpc":"2.0","id":3,"method":"tools/call","params":{"name":"count"}}"#,
+        ]
+        .join("\n");
+        let mut output = Vec::new();
+        service.serve(input.as_bytes(), &mut output).await?;
+
+        let messages: Vec<Value> = String::from_utf8(output)?
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        // Notifications come before the response of their call, and only
+        // when a token was given
+        assert_eq!(messages.len(), 5);
+        assert_eq!(messages[1]["method"], "notifications/progress");
+        assert_eq!(
+            messages[1]["params"],
+            json!({ "progressToken": "count-1", "progress": 1.0, "total": 2.0, "message": "one" })
+        );
+        assert_eq!(messages[2]["params"]["message"], "two");
+        assert_eq!(messages[3]["id"], 2);
+        assert_eq!(messages[3]["result"]["structuredContent"]["counted"], 2);
+        assert_eq!(messages[4]["id"], 3);
+        service.cleanup().await?;
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_mcp_tools_list_changed() -> Result<()> {
+        use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+
+        let temp_dir = tempfile::tempdir()?;
+        let file_config = json!({
+            "command": "file",
+            "args": [temp_dir.path().to_str().unwrap()],
+            "env": {}
+        });
+        let server = Server::new("file".to_string(), file_config).await?;
+        // Shares the tool registry with the served server
+        let mut registry = server.mcp_server.clone();
+        let mut service = McpService::new(vec![server]);
+
+        let (mut to_service, input) = tokio::io::duplex(64 * 1024);
+        let (output, from_service) = tokio::io::duplex(64 * 1024);
+        let client = async move {
+            let mut lines = BufReader::new(from_service).lines();
+            to_service
+                .write_all(
+                    concat!(
+                        r#"{"jsonrpc":"2.0","id":1,"method":"initialize","params":{"protocolVersion":"2024-11-05","capabilities":{},"clientInfo":{"name":"test","version":"1.0"}}}"#,
+                        "\n"
+                    )
+                    .as_bytes(),
+                )
+                .await?;
+            let initialized: Value = serde_json::from_str(&lines.next_line().await?.unwrap())?;
+
+            registry.unregister_tool("file_delete").await?;
+            let notification: Value = serde_json::from_str(&lines.next_line().await?.unwrap())?;
+            drop(to_service);
+            Ok::<_, anyhow::Error>((initialized, notification))
+        };
+        let (served, exchanged) = tokio::join!(service.serve(input, output), client);
+        served?;
+        let (initialized, notification) = exchanged?;
+
+        assert_eq!(
+            initialized["result"]["capabilities"]["tools"]["listChanged"],
+            true
+        );
+        assert_eq!(
+            notification,
+            json!({ "jsonrpc": "2.0", "method": "notifications/tools/list_changed" })
+        );
+        let response = service
+            .handle_line(r#"{"jsonrpc":"2.0","id":2,"method":"tools/list"}"#)
+            .await
+            .unwrap();
+        let tools = response.into_result().unwrap();
+        assert!(!tools["tools"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .any(|tool| tool["name"] == "file_delete"));
+        service.cleanup().await?;
         Ok(())
     }
 }
@@ -0,0 +1,320 @@
+//! Compatibility tests of the JSON-RPC framing against messages taken from
+//! the Model Context Protocol specification (revision 2024-11-05)
+
+use mcp_chatbot::protocol::{
+    CallToolParams, CallToolResult, Content, GetPromptResult, InitializeResult, JsonRpcError,
+    JsonRpcMessage, JsonRpcNotification, JsonRpcResponse, ListPromptsResult, ListResourcesResult,
+    ListToolsResult, McpRequest, McpTool, ProgressParams, PromptSchema, ReadResourceResult,
+    RequestId, ResourceSchema, ToolSchema, INITIALIZED_NOTIFICATION, MCP_PROTOCOL_VERSION,
+    PROGRESS_NOTIFICATION,
+};
+use serde_json::{json, Value};
+
+/// Every fixture by name, as the raw JSON text
+const FIXTURES: &[(&str, &str)] = &[
+    (
+        "initialize_request",
+        include_str!("fixtures/mcp/initialize_request.json"),
+    ),
+    (
+        "initialize_response",
+        include_str!("fixtures/mcp/initialize_response.json"),
+    ),
+    (
+        "initialized_notification",
+        include_str!("fixtures/mcp/initialized_notification.json"),
+    ),
+    (
+        "ping_request",
+        include_str!("fixtures/mcp/ping_request.json"),
+    ),
+    (
+        "tools_list_response",
+        include_str!("fixtures/mcp/tools_list_response.json"),
+    ),
+    (
+        "tools_call_request",
+        include_str!("fixtures/mcp/tools_call_request.json"),
+    ),
+    (
+        "tools_call_response",
+        include_str!("fixtures/mcp/tools_call_response.json"),
+    ),
+    (
+        "resources_list_response",
+        include_str!("fixtures/mcp/resources_list_response.json"),
+    ),
+    (
+        "resources_read_request",
+        include_str!("fixtures/mcp/resources_read_request.json"),
+    ),
+    (
+        "resources_read_response",
+        include_str!("fixtures/mcp/resources_read_response.json"),
+    ),
+    (
+        "prompts_list_response",
+        include_str!("fixtures/mcp/prompts_list_response.json"),
+    ),
+    (
+        "prompts_get_request",
+        include_str!("fixtures/mcp/prompts_get_request.json"),
+    ),
+    (
+        "prompts_get_response",
+        include_str!("fixtures/mcp/prompts_get_response.json"),
+    ),
+    (
+        "error_response",
+        include_str!("fixtures/mcp/error_response.json"),
+    ),
+];
+
+fn fixture(name: &str) -> &'static str {
+    FIXTURES
+        .iter()
+        .find(|(fixture, _)| *fixture == name)
+        .map(|(_, text)| *text)
+        .unwrap()
+}
+
+/// The `result` of a response fixture
+fn result(name: &str) -> Value {
+    match JsonRpcMessage::parse(fixture(name)).unwrap() {
+        JsonRpcMessage::Response(response) => response.into_result().unwrap(),
+        other => panic!("{} is not a response: {:?}", name, other),
+    }
+}
+
+#[test]
+fn test_envelopes_round_trip() {
+    for (name, text) in FIXTURES {
+        let message = JsonRpcMessage::parse(text).unwrap();
+        let expected = if name.ends_with("_request") {
+            "request"
+        } else if name.ends_with("_notification") {
+            "notification"
+        } else {
+            "response"
+        };
+        let kind = match &message {
+            JsonRpcMessage::Request(request) => {
+                // Every request of the spec is an MCP method we know
+                let mcp = McpRequest::from_request(request).unwrap();
+                assert_eq!(mcp.method(), request.method);
+                assert_eq!(mcp.into_request(request.id.clone()), *request);
+                "request"
+            }
+            JsonRpcMessage::Notification(_) => "notification",
+            JsonRpcMessage::Response(_) => "response",
+        };
+        assert_eq!(kind, expected, "{}", name);
+
+        // Serializing gives back the same JSON on a single line
+        let line = message.to_line();
+        assert!(!line.contains('\n'));
+        assert_eq!(
+            serde_json::from_str::<Value>(&line).unwrap(),
+            serde_json::from_str::<Value>(text).unwrap(),
+            "{}",
+            name
+        );
+    }
+}
+
+#[test]
+fn test_initialize() {
+    let message = JsonRpcMessage::parse(fixture("initialize_request")).unwrap();
+    let request = match message {
+        JsonRpcMessage::Request(request) => request,
+        other => panic!("Expected a request: {:?}", other),
+    };
+    assert_eq!(request.id, RequestId::Number(1));
+    match McpRequest::from_request(&request).unwrap() {
+        McpRequest::Initialize(params) => {
+            assert_eq!(params.protocol_version, MCP_PROTOCOL_VERSION);
+            assert_eq!(params.client_info.name, "ExampleClient");
+            assert_eq!(params.capabilities["roots"]["listChanged"], true);
+        }
+        other => panic!("Expected initialize: {:?}", other),
+    }
+
+    let value = result("initialize_response");
+    let initialize: InitializeResult = serde_json::from_value(value.clone()).unwrap();
+    assert_eq!(initialize.server_info.name, "ExampleServer");
+    assert!(initialize.capabilities.tools.is_some());
+    assert!(initialize.capabilities.logging.is_some());
+    assert_eq!(serde_json::to_value(&initialize).unwrap(), value);
+
+    match JsonRpcMessage::parse(fixture("initialized_notification")).unwrap() {
+        JsonRpcMessage::Notification(notification) => {
+            assert_eq!(notification.method, INITIALIZED_NOTIFICATION)
+        }
+        other => panic!("Expected a notification: {:?}", other),
+    }
+}
+
+#[test]
+fn test_tools() {
+    let tools: ListToolsResult = serde_json::from_value(result("tools_list_response")).unwrap();
+    let schema = ToolSchema::from(tools.tools[0].clone());
+    assert_eq!(schema.name, "get_weather");
+    assert_eq!(schema.input_schema["required"], json!(["location"]));
+    assert!(schema.output_schema.is_none());
+
+    // Built-in tools are listed with camelCase schemas
+    let tool = serde_json::to_value(McpTool::from(schema)).unwrap();
+    assert_eq!(tool, result("tools_list_response")["tools"][0]);
+
+    let call: CallToolResult = serde_json::from_value(result("tools_call_response")).unwrap();
+    assert!(!call.is_error);
+    assert_eq!(
+        call.into_value().unwrap(),
+        "Current weather in New York:\nTemperature: 72°F\nConditions: Partly cloudy"
+    );
+
+    // JSON results survive the trip through a tool result
+    let value = json!({ "success": true, "size": 12 });
+    let call = CallToolResult::from_value(value.clone());
+    let call: CallToolResult = serde_json::from_value(serde_json::to_value(call).unwrap()).unwrap();
+    assert_eq!(call.into_value().unwrap(), value);
+    let call = CallToolResult {
+        content: vec![Content::Text {
+            text: "[1, 2]".to_string(),
+        }],
+        structured_content: None,
+        is_error: false,
+    };
+    assert_eq!(call.into_value().unwrap(), json!([1, 2]));
+    assert_eq!(
+        CallToolResult::error("File not found").into_value(),
+        Err("File not found".to_string())
+    );
+
+    // Progress is asked for with a token in `_meta`
+    let call: CallToolParams = serde_json::from_value(json!({
+        "name": "file_index",
+        "arguments": { "path": "big.log" },
+        "_meta": { "progressToken": "index-1" }
+    }))
+    .unwrap();
+    assert_eq!(call.progress_token(), Some(&json!("index-1")));
+    let notification = JsonRpcNotification::new(
+        PROGRESS_NOTIFICATION,
+        Some(
+            serde_json::to_value(ProgressParams {
+                progress_token: json!("index-1"),
+                progress: 50.0,
+                total: Some(100.0),
+                message: None,
+            })
+            .unwrap(),
+        ),
+    );
+    assert_eq!(
+        JsonRpcMessage::Notification(notification).to_line(),
+        r#"{"jsonrpc":"2.0","method":"notifications/progress","params":{"progressToken":"index-1","progress":50.0,"total":100.0}}"#
+    );
+}
+
+#[test]
+fn test_resources() {
+    let resources: ListResourcesResult =
+        serde_json::from_value(result("resources_list_response")).unwrap();
+    let schema = ResourceSchema::from(resources.resources[0].clone());
+    assert_eq!(schema.pattern, "file:///project/src/main.rs");
+    assert_eq!(schema.description, "Primary application entry point");
+
+    let contents: ReadResourceResult =
+        serde_json::from_value(result("resources_read_response")).unwrap();
+    assert_eq!(
+        contents.into_value(),
+        "fn main() {\n    println!(\"Hello world!\");\n}"
+    );
+
+    let contents = ReadResourceResult::from_value("filesystem?action=cwd", &json!({ "a": 1 }));
+    assert_eq!(
+        contents.contents[0].mime_type.as_deref(),
+        Some("application/json")
+    );
+    assert_eq!(contents.into_value(), json!({ "a": 1 }));
+}
+
+#[test]
+fn test_prompts() {
+    let prompts: ListPromptsResult =
+        serde_json::from_value(result("prompts_list_response")).unwrap();
+    let schema = PromptSchema::from(prompts.prompts[0].clone());
+    assert_eq!(schema.name, "code_review");
+    assert!(schema.arguments[0].required);
+    assert_eq!(schema.arguments[0].schema, json!({ "type": "string" }));
+
+    let prompt: GetPromptResult = serde_json::from_value(result("prompts_get_response")).unwrap();
+    assert_eq!(prompt.description.as_deref(), Some("Code review prompt"));
+    assert_eq!(
+        prompt.into_value(),
+        "Please review this Python code:\ndef hello():\n    print('world')"
+    );
+
+    // Results of external servers pass through as they are
+    let value = result("prompts_get_response");
+    let prompt = GetPromptResult::from_value(None, value.clone());
+    assert_eq!(serde_json::to_value(prompt).unwrap(), value);
+    let prompt = GetPromptResult::from_value(Some("Greeting".to_string()), json!("Hello"));
+    assert_eq!(prompt.messages[0].role, "user");
+    assert_eq!(prompt.description.as_deref(), Some("Greeting"));
+}
+
+#[test]
+fn test_errors() {
+    let response = match JsonRpcMessage::parse(fixture("error_response")).unwrap() {
+        JsonRpcMessage::Response(response) => response,
+        other => panic!("Expected a response: {:?}", other),
+    };
+    let error = response.into_result().unwrap_err();
+    assert_eq!(error.code, JsonRpcError::INVALID_PARAMS);
+    assert_eq!(error.message, "Unknown tool: invalid_tool_name");
+
+    assert_eq!(
+        JsonRpcMessage::parse("{\"jsonrpc\": ").unwrap_err().code,
+        JsonRpcError::PARSE_ERROR
+    );
+    for invalid in [
+        r#"{"jsonrpc": "1.0", "id": 1, "method": "ping"}"#,
+        r#"{"jsonrpc": "2.0"}"#,
+        r#"[{"jsonrpc": "2.0", "id": 1, "method": "ping"}]"#,
+        r#"{"jsonrpc": "2.0", "id": null, "method": "ping"}"#,
+    ] {
+        assert_eq!(
+            JsonRpcMessage::parse(invalid).unwrap_err().code,
+            JsonRpcError::INVALID_REQUEST,
+            "{}",
+            invalid
+        );
+    }
+
+    let request = |line: &str| match JsonRpcMessage::parse(line).unwrap() {
+        JsonRpcMessage::Request(request) => McpRequest::from_request(&request),
+        other => panic!("Expected a request: {:?}", other),
+    };
+    assert_eq!(
+        request(r#"{"jsonrpc": "2.0", "id": 4, "method": "sampling/createMessage"}"#)
+            .unwrap_err()
+            .code,
+        JsonRpcError::METHOD_NOT_FOUND
+    );
+    assert_eq!(
+        request(r#"{"jsonrpc": "2.0", "id": 5, "method": "tools/call", "params": {}}"#)
+            .unwrap_err()
+            .code,
+        JsonRpcError::INVALID_PARAMS
+    );
+
+    // Errors to unreadable requests carry a null id
+    let response =
+        JsonRpcResponse::failure(None, JsonRpcError::new(JsonRpcError::PARSE_ERROR, "x"));
+    assert_eq!(
+        JsonRpcMessage::Response(response).to_line(),
+        r#"{"jsonrpc":"2.0","id":null,"error":{"code":-32700,"message":"x"}}"#
+    );
+}
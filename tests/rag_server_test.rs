@@ -1,7 +1,6 @@
 use anyhow::Result;
 use log::{debug, info};
 use mcp_chatbot::rag_server::{Document, RagServer};
-use qdrant_client::qdrant::Value;
 use std::collections::HashMap;
 
 #[tokio::test]
@@ -89,11 +88,27 @@ async fn test_embedding_generation() -> Result<()> {
     debug!("First few embedding values: {:?}", &embedding[..5]);
 
     info!("Step 4/4: Verifying results...");
-    assert_eq!(embedding.len(), 768); // EMBEDDING_SIZE
+    assert_eq!(embedding.len(), server.embedding_dimension().await?);
+    assert_eq!(embedding.len(), 384); // all-MiniLM-L6-v2
     info!("=== Embedding generation test completed successfully ===\n");
     Ok(())
 }
 
+#[tokio::test]
+async fn test_batch_embedding_generation() -> Result<()> {
+    let server = RagServer::new()?;
+    let texts = ["First text to embed", "Second text to embed"];
+
+    let single = server.generate_embedding(texts[1]).await?;
+    let batch = server.generate_embeddings(&texts).await?;
+
+    assert_eq!(batch.len(), 2);
+    assert_eq!(batch[1], single); // Served from the cache
+    assert_eq!(batch[0].len(), single.len());
+    assert!(server.generate_embeddings(&[]).await?.is_empty());
+    Ok(())
+}
+
 #[tokio::test]
 async fn test_collection_info() -> Result<()> {
     info!("=== Starting collection info test ===");
@@ -146,7 +161,10 @@ async fn test_document_operations() -> Result<()> {
     info!("Document added successfully");
 
     info!("Step 4/5: Searching with metadata filter...");
-    let filter = Some(HashMap::from([("source".to_string(), Value::from("test"))]));
+    let filter = Some(HashMap::from([(
+        "source".to_string(),
+        serde_json::Value::String("test".to_string()),
+    )]));
     let results = server.search_similar("test document", 5, filter).await?;
     info!("Search completed, found {} results", results.len());
     debug!("Search results: {:#?}", results);
@@ -1,4 +1,5 @@
 use anyhow::Result;
+use mcp_chatbot::vector_backend::RetryPolicy;
 use mcp_chatbot::vector_db::{VectorDBClient, VectorDBConfig};
 use qdrant_client::qdrant::{value, Value};
 use serde_json::json;
@@ -143,6 +144,13 @@ async fn test_vector_db_basic_operations() -> Result<()> {
         collection_name: "test_vectors".to_string(),
         vector_size: 4,
         distance: "Cosine".to_string(),
+        api_key: None,
+        tls: false,
+        timeout_secs: None,
+        hnsw_config: None,
+        quantization: None,
+        path: None,
+        retry: RetryPolicy::default(),
         on_disk_payload: true,
         optimizers_config: mcp_chatbot::vector_db::OptimizersConfig {
             default_segment_number: 2,
@@ -215,6 +223,13 @@ async fn test_vector_db_error_handling() -> Result<()> {
         collection_name: "test_vectors_error".to_string(),
         vector_size: 4,
         distance: "Invalid".to_string(),
+        api_key: None,
+        tls: false,
+        timeout_secs: None,
+        hnsw_config: None,
+        quantization: None,
+        path: None,
+        retry: RetryPolicy::default(),
         on_disk_payload: true,
         optimizers_config: mcp_chatbot::vector_db::OptimizersConfig {
             default_segment_number: 2,
@@ -253,6 +268,13 @@ async fn test_vector_db_empty_search() -> Result<()> {
         collection_name: "test_vectors_empty".to_string(),
         vector_size: 4,
         distance: "Cosine".to_string(),
+        api_key: None,
+        tls: false,
+        timeout_secs: None,
+        hnsw_config: None,
+        quantization: None,
+        path: None,
+        retry: RetryPolicy::default(),
         on_disk_payload: true,
         optimizers_config: mcp_chatbot::vector_db::OptimizersConfig {
             default_segment_number: 2,